@@ -0,0 +1,127 @@
+//! Address lists for `memlink etmem swap`
+//!
+//! Addresses can be given on the command line or, for lists too long for
+//! argv, read from a file or stdin (`-`), typically scan output that an
+//! external tool post-processed. A list has one entry per line: a hex
+//! address, standing for the base page at it, or a `START-END` range as in
+//! `/proc/<pid>/maps`, end exclusive. `0x` prefixes are optional, blank
+//! lines are skipped and `#` starts a comment.
+//!
+//! ```text
+//! # cold heap pages
+//! 0x55d4c3a00000-0x55d4c3c00000
+//! 7f3a1c2e5000   # single page
+//! ```
+//!
+//! Every entry must be page aligned. Entries are coalesced before
+//! submission, so overlapping and repeated entries are swapped once.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+
+use anyhow::Context;
+use etmem_rs::{AddressRange, BASE_PAGE_SIZE, RangeSet};
+
+/// Read an address list from `path`, or from stdin if it is `-`
+pub(crate) fn read_list(path: &Path) -> anyhow::Result<RangeSet> {
+    if path == Path::new("-") {
+        return parse_list(io::stdin().lock(), "<stdin>");
+    }
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    parse_list(BufReader::new(file), &path.display().to_string())
+}
+
+/// Parse an address list, naming `source` in errors
+pub(crate) fn parse_list<R: BufRead>(reader: R, source: &str) -> anyhow::Result<RangeSet> {
+    let mut ranges = RangeSet::default();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {source}"))?;
+        let entry = line.split('#').next().unwrap_or_default().trim();
+        if entry.is_empty() {
+            continue;
+        }
+        let range =
+            parse_entry(entry).map_err(|e| anyhow::anyhow!("{source}:{}: {e}", index + 1))?;
+        ranges.insert(range);
+    }
+    Ok(ranges)
+}
+
+/// Parse one entry: an address or a `START-END` range
+pub(crate) fn parse_entry(entry: &str) -> Result<AddressRange, String> {
+    let range = match entry.split_once('-') {
+        Some((start, end)) => AddressRange::new(parse_hex(start)?, parse_hex(end)?),
+        None => {
+            let addr = parse_hex(entry)?;
+            let end = addr
+                .checked_add(BASE_PAGE_SIZE)
+                .ok_or_else(|| format!("address out of range: {entry}"))?;
+            AddressRange::new(addr, end)
+        }
+    };
+    if range.start >= range.end {
+        return Err(format!("empty range: {entry}"));
+    }
+    if !range.start.is_multiple_of(BASE_PAGE_SIZE) || !range.end.is_multiple_of(BASE_PAGE_SIZE) {
+        return Err(format!("not page aligned: {entry}"));
+    }
+    Ok(range)
+}
+
+/// Parse a hex number, with or without a `0x` prefix
+fn parse_hex(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    u64::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| format!("invalid address: {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry() {
+        assert_eq!(
+            parse_entry("0x7f0000001000"),
+            Ok(AddressRange::new(0x7f00_0000_1000, 0x7f00_0000_2000))
+        );
+        assert_eq!(
+            parse_entry("600000-0x800000"),
+            Ok(AddressRange::new(0x600000, 0x800000))
+        );
+        assert!(
+            parse_entry("0x1234")
+                .unwrap_err()
+                .contains("not page aligned")
+        );
+        assert!(
+            parse_entry("2000-1000")
+                .unwrap_err()
+                .contains("empty range")
+        );
+        assert!(parse_entry("xyz").unwrap_err().contains("invalid address"));
+        assert!(parse_entry("fffffffffffff000").is_err());
+    }
+
+    #[test]
+    fn test_parse_list() {
+        let list = "# cold pages\n\
+                    \n\
+                    0x1000-0x3000\n\
+                    2000   # overlaps the range above\n\
+                    0x3000\n\
+                    0x10000\n";
+        let ranges = parse_list(list.as_bytes(), "addrs.txt").unwrap();
+        assert_eq!(
+            ranges.ranges(),
+            [
+                AddressRange::new(0x1000, 0x4000),
+                AddressRange::new(0x10000, 0x11000)
+            ]
+        );
+
+        let err = parse_list("0x1000\n0x1001\n".as_bytes(), "addrs.txt").unwrap_err();
+        assert_eq!(err.to_string(), "addrs.txt:2: not page aligned: 0x1001");
+    }
+}
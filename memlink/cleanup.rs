@@ -0,0 +1,264 @@
+//! Stale state detection for `memlink cleanup`
+//!
+//! The registry and its descriptor files outlive the memory they describe
+//! when a node reboots, the OBMM module is reloaded or an exporter goes
+//! away. Cleanup compares them with the regions that currently have a
+//! shared memory device (`/dev/obmm_shmdev<memid>`) and classifies every
+//! mismatch as an [`Orphan`]. A region with a device is live whatever kind
+//! the kernel reports for it, and nothing is classified while the kernel
+//! lists no regions at all but memlink has state: an unloaded module or an
+//! unreadable listing must not be mistaken for every region being gone.
+//!
+//! Registry entries and descriptor files are removed. Kernel exports that
+//! memlink has no record of are only reported: unexporting them could pull
+//! memory from under another tool.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use obmm_rs::query::RegionInfo;
+use obmm_rs::{EntryKind, MemId, Registry};
+
+/// A piece of state that no longer matches the kernel
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Orphan {
+    /// Export registered or described on disk but no longer exported
+    DeadExport {
+        mem_id: MemId,
+        /// Whether the registry index lists it
+        registered: bool,
+        /// Descriptor file, if present
+        desc: Option<PathBuf>,
+    },
+    /// Kernel export without a registry entry or descriptor file
+    UnknownExport { mem_id: MemId, size: u64 },
+    /// Registered import the kernel dropped, typically because its
+    /// exporter went away
+    DeadImport { mem_id: MemId },
+}
+
+impl Orphan {
+    /// Memory ID the orphan refers to
+    pub(crate) fn mem_id(&self) -> MemId {
+        match *self {
+            Orphan::DeadExport { mem_id, .. }
+            | Orphan::UnknownExport { mem_id, .. }
+            | Orphan::DeadImport { mem_id } => mem_id,
+        }
+    }
+
+    /// Whether cleanup removes the orphan (rather than only reporting it)
+    pub(crate) fn is_removable(&self) -> bool {
+        !matches!(self, Orphan::UnknownExport { .. })
+    }
+
+    /// One-line description for the report
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Orphan::DeadExport {
+                registered,
+                desc: Some(path),
+                ..
+            } => format!(
+                "export no longer live; {}descriptor {}",
+                if *registered {
+                    "registry entry and "
+                } else {
+                    ""
+                },
+                path.display()
+            ),
+            Orphan::DeadExport { desc: None, .. } => {
+                "export no longer live; registry entry without descriptor".to_string()
+            }
+            Orphan::UnknownExport { mem_id, size } => format!(
+                "live export of {} without descriptor (not removed; \
+                 `memlink unexport --memid {mem_id}` if unused)",
+                etmem_rs::format_bytes(*size)
+            ),
+            Orphan::DeadImport { .. } => "import no longer live in the kernel".to_string(),
+        }
+    }
+
+    /// Remove the orphan's state from the registry
+    pub(crate) fn remove(&self, registry: &Registry) -> anyhow::Result<()> {
+        match self {
+            Orphan::DeadExport {
+                mem_id,
+                registered: true,
+                ..
+            } => {
+                registry
+                    .remove(EntryKind::Export, *mem_id)
+                    .with_context(|| format!("Failed to unregister export {mem_id}"))?;
+            }
+            Orphan::DeadExport {
+                desc: Some(path), ..
+            } => {
+                fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove {}", path.display()))?;
+            }
+            Orphan::DeadImport { mem_id } => {
+                registry
+                    .remove(EntryKind::Import, *mem_id)
+                    .with_context(|| format!("Failed to unregister import {mem_id}"))?;
+            }
+            Orphan::DeadExport { .. } | Orphan::UnknownExport { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+/// Find the orphans of `registry` given the regions the kernel lists
+///
+/// # Returns
+/// Orphans sorted by memory ID
+pub(crate) fn find_orphans(
+    registry: &Registry,
+    regions: &[RegionInfo],
+) -> anyhow::Result<Vec<Orphan>> {
+    let live: BTreeSet<MemId> = regions.iter().map(|r| r.mem_id).collect();
+
+    let entries = registry
+        .entries()
+        .with_context(|| "Failed to read memlink registry")?;
+    let registered = |kind: EntryKind| -> BTreeSet<MemId> {
+        entries
+            .iter()
+            .filter(|e| e.kind == kind)
+            .map(|e| e.mem_id)
+            .collect()
+    };
+    let (exports, imports) = (registered(EntryKind::Export), registered(EntryKind::Import));
+    let described = descriptor_ids(registry.dir())?;
+    if live.is_empty() && !(entries.is_empty() && described.is_empty()) {
+        anyhow::bail!(
+            "The kernel lists no OBMM regions but memlink has {} registry entries and {} \
+             descriptors; refusing to treat them all as stale (is the OBMM module loaded?)",
+            entries.len(),
+            described.len()
+        );
+    }
+
+    let mut orphans = Vec::new();
+    for &mem_id in exports.union(&described) {
+        if !live.contains(&mem_id) {
+            orphans.push(Orphan::DeadExport {
+                mem_id,
+                registered: exports.contains(&mem_id),
+                desc: described
+                    .contains(&mem_id)
+                    .then(|| registry.desc_path(mem_id)),
+            });
+        }
+    }
+    for region in regions.iter().filter(|r| r.kind == Some(EntryKind::Export)) {
+        if !exports.contains(&region.mem_id) && !described.contains(&region.mem_id) {
+            orphans.push(Orphan::UnknownExport {
+                mem_id: region.mem_id,
+                size: region.size.unwrap_or(0),
+            });
+        }
+    }
+    for &mem_id in imports.difference(&live) {
+        orphans.push(Orphan::DeadImport { mem_id });
+    }
+
+    orphans.sort_by_key(Orphan::mem_id);
+    Ok(orphans)
+}
+
+/// Memory IDs of the `memdesc_<memid>.json` files in a state directory
+fn descriptor_ids(dir: &Path) -> anyhow::Result<BTreeSet<MemId>> {
+    let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))?;
+    Ok(entries
+        .flatten()
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("memdesc_")?
+                .strip_suffix(".json")?
+                .parse()
+                .ok()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obmm_rs::{ObmmExportFlags, ObmmMemDesc, UbPrivData};
+
+    /// Write a device node stand-in and its sysfs-style attributes
+    fn region(root: &Path, mem_id: MemId, kind: &str) {
+        fs::create_dir_all(root.join("dev")).unwrap();
+        fs::write(root.join(format!("dev/obmm_shmdev{mem_id}")), "").unwrap();
+        let dir = root.join(format!("obmm_shmdev{mem_id}"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("type"), kind).unwrap();
+        fs::write(dir.join("size"), "4096").unwrap();
+    }
+
+    #[test]
+    fn test_find_and_remove_orphans() {
+        let root = std::env::temp_dir().join(format!("memlink-cleanup-{}", std::process::id()));
+        let registry = Registry::open(root.join("state")).unwrap();
+        let sysfs = root.join("sysfs");
+        fs::create_dir_all(&sysfs).unwrap();
+
+        let desc = ObmmMemDesc::<UbPrivData>::default();
+        let flags = ObmmExportFlags::ALLOWMMAP;
+        registry.record_export(1, flags, &desc).unwrap();
+        registry.record_export(2, flags, &desc).unwrap();
+        desc.to_json_path(registry.desc_path(3)).unwrap();
+        registry.record_import(4, 4096, flags, 0).unwrap();
+        registry.record_import(5, 4096, flags, 0).unwrap();
+        region(&sysfs, 1, "export");
+        region(&sysfs, 5, "import");
+        region(&sysfs, 6, "export");
+        // A live export whose kind the kernel does not report
+        registry.record_export(7, flags, &desc).unwrap();
+        fs::write(sysfs.join("dev/obmm_shmdev7"), "").unwrap();
+
+        // Nothing is stale while the kernel lists no regions
+        assert!(find_orphans(&registry, &[]).is_err());
+
+        let regions = obmm_rs::query::list_regions_in(&sysfs.join("dev"), &sysfs).unwrap();
+        let orphans = find_orphans(&registry, &regions).unwrap();
+        assert_eq!(
+            orphans,
+            vec![
+                Orphan::DeadExport {
+                    mem_id: 2,
+                    registered: true,
+                    desc: Some(registry.desc_path(2)),
+                },
+                Orphan::DeadExport {
+                    mem_id: 3,
+                    registered: false,
+                    desc: Some(registry.desc_path(3)),
+                },
+                Orphan::DeadImport { mem_id: 4 },
+                Orphan::UnknownExport {
+                    mem_id: 6,
+                    size: 4096
+                },
+            ]
+        );
+        assert!(!orphans[3].is_removable());
+
+        for orphan in &orphans {
+            orphan.remove(&registry).unwrap();
+        }
+        let orphans = find_orphans(&registry, &regions).unwrap();
+        assert_eq!(orphans.iter().map(Orphan::mem_id).collect::<Vec<_>>(), [6]);
+        assert!(registry.desc_path(1).exists());
+        assert!(!registry.desc_path(3).exists());
+        assert_eq!(registry.entries().unwrap().len(), 3);
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
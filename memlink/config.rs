@@ -0,0 +1,416 @@
+//! Desired-state etmem configuration for `memlink etmem config --apply`
+//!
+//! A desired-state file is a JSON object naming the settings to enforce;
+//! settings left out are not touched:
+//!
+//! ```json
+//! {
+//!   "kernel_swap_enable": true,
+//!   "watermark": { "low_percent": 30, "high_percent": 70 },
+//!   "proactive_reclaim": true
+//! }
+//! ```
+//!
+//! Applying a file compares it with the kernel, records the current state
+//! under the memlink state directory and writes the differing settings one
+//! by one. The kernel has no transaction covering several knobs, so if a
+//! write fails the settings already changed are restored to their previous
+//! values before the error is reported. `--rollback` applies the recorded
+//! state the same way and records the state it replaces, so a second
+//! rollback returns to the applied configuration.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use etmem_rs::{EtmemError, SwapcacheConfig, SwapcacheController, WatermarkConfig};
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+/// File under the state directory holding the state before the last apply
+const RECORD_FILE: &str = "etmem-config.json";
+
+/// Settings a desired-state file may enforce
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DesiredState {
+    /// `/sys/kernel/mm/etmem/kernel_swap_enable`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) kernel_swap_enable: Option<bool>,
+    /// Proactive swapcache reclaim watermarks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) watermark: Option<WatermarkConfig>,
+    /// Whether the proactive reclaim kernel thread runs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) proactive_reclaim: Option<bool>,
+}
+
+impl DesiredState {
+    /// Parse and validate a desired-state document
+    pub(crate) fn parse(text: &str) -> anyhow::Result<Self> {
+        let state: Self = serde_json::from_str(text)?;
+        if let Some(watermark) = state.watermark {
+            watermark.validate()?;
+        }
+        Ok(state)
+    }
+
+    /// Read a desired-state file
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid desired state in {}", path.display()))
+    }
+}
+
+/// Settings as currently set in the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CurrentState {
+    pub(crate) kernel_swap_enable: bool,
+    /// `None` if the kernel cannot report its watermarks or reading them
+    /// failed
+    pub(crate) watermark: Option<WatermarkConfig>,
+    pub(crate) proactive_reclaim: bool,
+}
+
+impl CurrentState {
+    /// Desired state that restores this one
+    pub(crate) fn to_desired(self) -> DesiredState {
+        DesiredState {
+            kernel_swap_enable: Some(self.kernel_swap_enable),
+            watermark: self.watermark,
+            proactive_reclaim: Some(self.proactive_reclaim),
+        }
+    }
+}
+
+/// Value of a single setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Setting {
+    KernelSwap(bool),
+    Watermark(WatermarkConfig),
+    ProactiveReclaim(bool),
+}
+
+impl Setting {
+    /// Name of the setting as used in desired-state files
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Setting::KernelSwap(_) => "kernel_swap_enable",
+            Setting::Watermark(_) => "watermark",
+            Setting::ProactiveReclaim(_) => "proactive_reclaim",
+        }
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Setting::KernelSwap(enabled) | Setting::ProactiveReclaim(enabled) => {
+                f.write_str(if *enabled { "enabled" } else { "disabled" })
+            }
+            Setting::Watermark(wm) => write!(f, "{}%/{}%", wm.low_percent, wm.high_percent),
+        }
+    }
+}
+
+/// A setting that differs from the desired state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Change {
+    /// Current value, `None` if unknown
+    pub(crate) from: Option<Setting>,
+    /// Desired value
+    pub(crate) to: Setting,
+}
+
+/// List the settings of `desired` that differ from `current`
+///
+/// Changes are ordered the way they are applied: kernel swap first, then
+/// the watermarks, so that reclaim never starts with stale ones.
+pub(crate) fn diff(current: &CurrentState, desired: &DesiredState) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if let Some(enable) = desired.kernel_swap_enable
+        && enable != current.kernel_swap_enable
+    {
+        changes.push(Change {
+            from: Some(Setting::KernelSwap(current.kernel_swap_enable)),
+            to: Setting::KernelSwap(enable),
+        });
+    }
+    if let Some(watermark) = desired.watermark
+        && current.watermark != Some(watermark)
+    {
+        changes.push(Change {
+            from: current.watermark.map(Setting::Watermark),
+            to: Setting::Watermark(watermark),
+        });
+    }
+    if let Some(enable) = desired.proactive_reclaim
+        && enable != current.proactive_reclaim
+    {
+        changes.push(Change {
+            from: Some(Setting::ProactiveReclaim(current.proactive_reclaim)),
+            to: Setting::ProactiveReclaim(enable),
+        });
+    }
+    changes
+}
+
+/// Access to the etmem settings
+pub(crate) trait Knobs {
+    /// Read the current settings
+    fn current(&mut self) -> anyhow::Result<CurrentState>;
+    /// Write one setting
+    fn set(&mut self, setting: Setting) -> anyhow::Result<()>;
+}
+
+/// The system-wide etmem settings of this host
+pub(crate) struct KernelKnobs {
+    controller: SwapcacheController,
+}
+
+impl KernelKnobs {
+    /// Open the swapcache reclaim interface
+    pub(crate) fn open() -> anyhow::Result<Self> {
+        let controller =
+            SwapcacheController::open().with_context(|| "Failed to open swapcache controller")?;
+        Ok(Self { controller })
+    }
+}
+
+impl Knobs for KernelKnobs {
+    fn current(&mut self) -> anyhow::Result<CurrentState> {
+        let kernel_swap_enable =
+            SwapcacheConfig::is_enabled().with_context(|| "Failed to check kernel swap status")?;
+        // An unreadable watermark is reported as unknown, which leaves a
+        // watermark change unrevertible rather than failing the whole read
+        let watermark = match self.controller.get_watermark() {
+            Ok(watermark) => Some(watermark),
+            Err(EtmemError::NotSupported) => None,
+            Err(e) => {
+                warn!("Failed to read watermarks: {e}");
+                None
+            }
+        };
+        Ok(CurrentState {
+            kernel_swap_enable,
+            watermark,
+            proactive_reclaim: etmem_rs::sys::kernel_thread_running(
+                etmem_rs::sys::SWAPCACHE_RECLAIM_THREAD,
+            ),
+        })
+    }
+
+    fn set(&mut self, setting: Setting) -> anyhow::Result<()> {
+        match setting {
+            Setting::KernelSwap(enable) => SwapcacheConfig::set_enabled(enable),
+            Setting::Watermark(watermark) => self.controller.set_watermark(watermark),
+            Setting::ProactiveReclaim(true) => self.controller.enable(),
+            Setting::ProactiveReclaim(false) => self.controller.disable(),
+        }
+        .with_context(|| format!("Failed to set {} to {setting}", setting.name()))
+    }
+}
+
+/// Apply changes in order, restoring the applied ones if one fails
+///
+/// # Errors
+/// Returns the error of the failed change, noting any setting that could
+/// not be restored
+pub(crate) fn apply(knobs: &mut dyn Knobs, changes: &[Change]) -> anyhow::Result<()> {
+    for (applied, change) in changes.iter().enumerate() {
+        if let Err(err) = knobs.set(change.to) {
+            let stuck = revert(knobs, &changes[..applied]);
+            if stuck.is_empty() {
+                return Err(err.context("Configuration left unchanged"));
+            }
+            return Err(err.context(format!(
+                "Configuration partially applied, could not restore {}",
+                stuck.join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Restore the previous values of applied changes, last first
+///
+/// Returns the names of the settings that could not be restored.
+fn revert(knobs: &mut dyn Knobs, applied: &[Change]) -> Vec<&'static str> {
+    let mut stuck = Vec::new();
+    for change in applied.iter().rev() {
+        let restored = match change.from {
+            Some(previous) => knobs.set(previous),
+            None => Err(anyhow::anyhow!("previous value unknown")),
+        };
+        if let Err(err) = restored {
+            warn!("Failed to restore {}: {err:#}", change.to.name());
+            stuck.push(change.to.name());
+        }
+    }
+    stuck
+}
+
+/// Path of the recorded state under a state directory
+pub(crate) fn record_path(state_dir: &Path) -> PathBuf {
+    state_dir.join(RECORD_FILE)
+}
+
+/// Record the state to roll back to
+pub(crate) fn record(state_dir: &Path, state: &DesiredState) -> anyhow::Result<()> {
+    let path = record_path(state_dir);
+    let body = serde_json::to_vec_pretty(state)?;
+    let tmp = path.with_extension("tmp");
+    std::fs::create_dir_all(state_dir)
+        .and_then(|()| std::fs::write(&tmp, body))
+        .and_then(|()| std::fs::rename(&tmp, &path))
+        .with_context(|| format!("Failed to record etmem configuration to {}", path.display()))
+}
+
+/// Read the state recorded by the last apply or rollback
+pub(crate) fn recorded(state_dir: &Path) -> anyhow::Result<DesiredState> {
+    let path = record_path(state_dir);
+    if !path.exists() {
+        anyhow::bail!(
+            "No recorded etmem configuration in {}; nothing to roll back",
+            state_dir.display()
+        );
+    }
+    DesiredState::load(&path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// In-memory settings failing on one value
+    struct FakeKnobs {
+        state: CurrentState,
+        fail_on: Option<Setting>,
+        writes: Vec<Setting>,
+    }
+
+    impl Knobs for FakeKnobs {
+        fn current(&mut self) -> anyhow::Result<CurrentState> {
+            Ok(self.state)
+        }
+
+        fn set(&mut self, setting: Setting) -> anyhow::Result<()> {
+            if self.fail_on == Some(setting) {
+                anyhow::bail!("injected failure");
+            }
+            self.writes.push(setting);
+            match setting {
+                Setting::KernelSwap(enable) => self.state.kernel_swap_enable = enable,
+                Setting::Watermark(wm) => self.state.watermark = Some(wm),
+                Setting::ProactiveReclaim(enable) => self.state.proactive_reclaim = enable,
+            }
+            Ok(())
+        }
+    }
+
+    const INITIAL: CurrentState = CurrentState {
+        kernel_swap_enable: false,
+        watermark: Some(WatermarkConfig::new(30, 70)),
+        proactive_reclaim: false,
+    };
+
+    #[test]
+    fn test_parse_desired_state() {
+        let state = DesiredState::parse(
+            r#"{"kernel_swap_enable": true, "watermark": {"low_percent": 20, "high_percent": 60}}"#,
+        )
+        .unwrap();
+        assert_eq!(state.kernel_swap_enable, Some(true));
+        assert_eq!(state.watermark, Some(WatermarkConfig::new(20, 60)));
+        assert_eq!(state.proactive_reclaim, None);
+
+        assert!(DesiredState::parse(r#"{"kernel_swap": true}"#).is_err());
+        assert!(
+            DesiredState::parse(r#"{"watermark": {"low_percent": 70, "high_percent": 30}}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let desired = DesiredState {
+            kernel_swap_enable: Some(true),
+            watermark: Some(WatermarkConfig::new(30, 70)),
+            proactive_reclaim: Some(true),
+        };
+        let changes = diff(&INITIAL, &desired);
+        assert_eq!(
+            changes,
+            vec![
+                Change {
+                    from: Some(Setting::KernelSwap(false)),
+                    to: Setting::KernelSwap(true),
+                },
+                Change {
+                    from: Some(Setting::ProactiveReclaim(false)),
+                    to: Setting::ProactiveReclaim(true),
+                },
+            ]
+        );
+        assert!(diff(&INITIAL, &INITIAL.to_desired()).is_empty());
+        assert!(diff(&INITIAL, &DesiredState::default()).is_empty());
+    }
+
+    #[test]
+    fn test_apply_restores_on_failure() {
+        let desired = DesiredState {
+            kernel_swap_enable: Some(true),
+            watermark: Some(WatermarkConfig::new(10, 50)),
+            proactive_reclaim: Some(true),
+        };
+        let mut knobs = FakeKnobs {
+            state: INITIAL,
+            fail_on: Some(Setting::ProactiveReclaim(true)),
+            writes: Vec::new(),
+        };
+        let changes = diff(&knobs.current().unwrap(), &desired);
+        assert!(apply(&mut knobs, &changes).is_err());
+        assert_eq!(knobs.state, INITIAL);
+        assert_eq!(
+            knobs.writes,
+            vec![
+                Setting::KernelSwap(true),
+                Setting::Watermark(WatermarkConfig::new(10, 50)),
+                Setting::Watermark(WatermarkConfig::new(30, 70)),
+                Setting::KernelSwap(false),
+            ]
+        );
+
+        knobs.fail_on = None;
+        apply(&mut knobs, &changes).unwrap();
+        assert!(diff(&knobs.state, &desired).is_empty());
+    }
+
+    #[test]
+    fn test_record_and_rollback() {
+        let dir = std::env::temp_dir().join(format!("memlink-config-{}", std::process::id()));
+        assert!(recorded(&dir).is_err());
+
+        let mut knobs = FakeKnobs {
+            state: INITIAL,
+            fail_on: None,
+            writes: Vec::new(),
+        };
+        let desired = DesiredState {
+            kernel_swap_enable: Some(true),
+            ..DesiredState::default()
+        };
+        record(&dir, &knobs.state.to_desired()).unwrap();
+        let changes = diff(&knobs.state, &desired);
+        apply(&mut knobs, &changes).unwrap();
+        assert!(knobs.state.kernel_swap_enable);
+
+        let previous = recorded(&dir).unwrap();
+        assert_eq!(previous, INITIAL.to_desired());
+        let changes = diff(&knobs.state, &previous);
+        apply(&mut knobs, &changes).unwrap();
+        assert_eq!(knobs.state, INITIAL);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,153 @@
+//! Export leases for `memlink export --lease`
+//!
+//! A leased export stays exported only while its importer keeps renewing
+//! the lease through the exporter's `memlink serve` (`memlink renew`). The
+//! server reaps exports whose lease expired more than the grace period ago:
+//! they are force-unexported and unregistered, so memory granted to a
+//! crashed or partitioned importer is returned to the donor.
+
+use std::time::Duration;
+
+use anyhow::Context;
+use log::{info, warn};
+use obmm_rs::{EntryKind, MemId, ObmmUnexportFlags, Registry, mem_unexport};
+
+/// Time between two scans of the registry for expired leases
+const REAP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Renew a lease at this fraction of its TTL
+const RENEW_FRACTION: u32 = 3;
+
+/// Unexport and unregister the exports whose lease is past its grace period
+///
+/// Exports that fail to unexport stay registered and are retried on the
+/// next call.
+///
+/// # Returns
+/// Memory IDs of the reclaimed exports
+pub(crate) fn reap_expired<F>(registry: &Registry, mut unexport: F) -> anyhow::Result<Vec<MemId>>
+where
+    F: FnMut(MemId) -> anyhow::Result<()>,
+{
+    let expired = registry
+        .expired_leases()
+        .with_context(|| "Failed to read memlink registry")?;
+
+    let mut reclaimed = Vec::new();
+    for entry in expired {
+        let mem_id = entry.mem_id;
+        if let Err(e) = unexport(mem_id) {
+            warn!("Failed to reclaim memid {mem_id} after its lease expired: {e:#}");
+            continue;
+        }
+        registry
+            .remove(EntryKind::Export, mem_id)
+            .with_context(|| format!("Failed to unregister export {mem_id}"))?;
+        info!("Reclaimed memid {mem_id}: lease expired");
+        reclaimed.push(mem_id);
+    }
+    Ok(reclaimed)
+}
+
+/// Reap expired leases of `registry` on a background thread
+pub(crate) fn spawn_reaper(registry: Registry) -> anyhow::Result<()> {
+    std::thread::Builder::new()
+        .name("lease-reaper".to_string())
+        .spawn(move || {
+            loop {
+                let unexport = |mem_id| {
+                    // The importer is presumed gone; do not wait for it
+                    mem_unexport(mem_id, ObmmUnexportFlags::FORCE)
+                        .with_context(|| format!("Failed to unexport {mem_id}"))
+                };
+                if let Err(e) = reap_expired(&registry, unexport) {
+                    warn!("Lease reaping failed: {e:#}");
+                }
+                std::thread::sleep(REAP_INTERVAL);
+            }
+        })
+        .with_context(|| "Failed to start lease reaper")?;
+    Ok(())
+}
+
+/// Renew the lease of an export on a remote `memlink serve`
+///
+/// Requests are authenticated with `key`, which a server given a key
+/// requires. With `keep`, renew at a third of the lease TTL until a
+/// renewal fails.
+pub(crate) fn renew(
+    host: &str,
+    mem_id: MemId,
+    keep: bool,
+    key: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let mut client = crate::net::Client::connect(host)?;
+    loop {
+        let lease = client
+            .renew(mem_id, key)
+            .with_context(|| format!("Failed to renew the lease of memid {mem_id} on {host}"))?;
+        println!(
+            "Lease of memid {mem_id} renewed for {}",
+            crate::format_duration(lease.remaining())
+        );
+        if !keep {
+            return Ok(());
+        }
+        std::thread::sleep(renew_interval(lease.ttl));
+    }
+}
+
+/// Time between renewals of a lease with the given TTL in seconds
+fn renew_interval(ttl: u64) -> Duration {
+    (Duration::from_secs(ttl) / RENEW_FRACTION).max(Duration::from_secs(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obmm_rs::{Lease, ObmmExportFlags, ObmmMemDesc, UbPrivData};
+
+    #[test]
+    fn test_reap_expired() {
+        let dir = std::env::temp_dir().join(format!("memlink-lease-{}", std::process::id()));
+        let registry = Registry::open(&dir).unwrap();
+        let desc = ObmmMemDesc::<UbPrivData>::default();
+        for mem_id in 1..=4 {
+            registry
+                .record_export(mem_id, ObmmExportFlags::ALLOWMMAP, &desc)
+                .unwrap();
+        }
+        let expired = Lease::new(Duration::ZERO, Duration::ZERO);
+        registry.set_lease(1, Some(expired)).unwrap();
+        registry.set_lease(2, Some(expired)).unwrap();
+        let live = Lease::new(Duration::from_secs(60), Duration::ZERO);
+        registry.set_lease(3, Some(live)).unwrap();
+
+        // memid 2 fails to unexport and is kept for the next round
+        let mut attempted = Vec::new();
+        let reclaimed = reap_expired(&registry, |mem_id| {
+            attempted.push(mem_id);
+            anyhow::ensure!(mem_id != 2, "busy");
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(attempted, [1, 2]);
+        assert_eq!(reclaimed, [1]);
+        assert!(!registry.desc_path(1).exists());
+        let left: Vec<MemId> = registry
+            .exports()
+            .unwrap()
+            .iter()
+            .map(|e| e.mem_id)
+            .collect();
+        assert_eq!(left, [2, 3, 4]);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_renew_interval() {
+        assert_eq!(renew_interval(60), Duration::from_secs(20));
+        assert_eq!(renew_interval(0), Duration::from_secs(1));
+    }
+}
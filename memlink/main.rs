@@ -4,10 +4,37 @@
 //! in a distributed system, enabling efficient memory sharing and management.
 #![allow(clippy::print_stdout, clippy::print_stderr)]
 
+mod addrs;
+mod cleanup;
+mod config;
+mod lease;
+mod net;
+mod output;
+mod probe;
+mod push;
+mod shell;
+mod target;
+mod trace;
+mod watch;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
 use anyhow::Context;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+use etmem_rs::{
+    AddressRebaser, DamonReport, FileCacheReport, IdlePageInfo, IdlePageStats, RegionReport, VmaMap,
+};
 use log::info;
-use obmm_rs::{MAX_NUMA_NODES, ObmmExportFlags, UbPrivData, mem_export};
+use obmm_rs::{
+    ByteSize, EntryKind, ExportRequest, HonoredPolicy, ImportOptions, Lease, MemId, NumaPolicy,
+    ObmmExportFlags, ObmmMemDesc, ObmmUnexportFlags, Registry, UbPrivData, UnexportOutcome,
+    mem_unexport, mem_unimport, query_importers, unexport_graceful,
+};
+use output::{ColorChoice, Column, Table, Tone, Verbosity, paint};
+use serde::Serialize;
 
 /// Memlink CLI arguments
 #[derive(Parser, Debug)]
@@ -18,6 +45,15 @@ struct Cli {
     /// Subcommand to execute
     #[command(subcommand)]
     command: Commands,
+    /// When to color the output
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Print only results, warnings and errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print debug logs (twice for trace logs)
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
 }
 
 /// Available subcommands
@@ -31,6 +67,114 @@ enum Commands {
         /// Size of memory to export in MB
         #[arg(short, long, default_value = "128")]
         size: usize,
+        /// Also write the memory descriptor to FILE (it is always stored in the registry)
+        #[arg(short, long, value_name = "FILE")]
+        out: Option<PathBuf>,
+        /// Unexport automatically unless the importer renews the export
+        /// within DURATION (e.g. 5m) through `memlink renew`
+        #[arg(short, long, value_name = "DURATION", value_parser = parse_duration)]
+        lease: Option<Duration>,
+        /// Extra time after the lease expires before the export is reclaimed
+        #[arg(short, long, value_name = "DURATION", value_parser = parse_duration, default_value = "60s", requires = "lease")]
+        grace: Duration,
+    },
+    /// Import remote memory from a descriptor file
+    Import {
+        /// Memory descriptor file produced by `memlink export`
+        #[arg(short, long, value_name = "FILE")]
+        desc: PathBuf,
+        /// NUMA node to place the imported memory on (default: kernel choice)
+        #[arg(short, long)]
+        numa: Option<i32>,
+        /// Fail instead of falling back when the NUMA node is unavailable
+        #[arg(long, requires = "numa")]
+        strict: bool,
+        /// Base distance hint for NUMA placement
+        #[arg(short, long, default_value = "0")]
+        base_dist: i32,
+    },
+    /// Inspect memory descriptor files
+    Desc {
+        #[command(subcommand)]
+        action: DescCommands,
+    },
+    /// Publish exported memory descriptors to remote nodes over TCP
+    Serve {
+        /// Address to listen on; non-loopback addresses require --key-file
+        #[arg(short, long, default_value = "127.0.0.1:7878")]
+        listen: String,
+        /// Directory containing descriptor files (default: the registry state directory)
+        #[arg(short, long)]
+        dir: Option<PathBuf>,
+        /// Number of connection worker threads
+        #[arg(short, long, default_value = "4")]
+        workers: usize,
+        /// Sign descriptors with the shared key in FILE
+        #[arg(short, long, value_name = "FILE")]
+        key_file: Option<PathBuf>,
+    },
+    /// List or import descriptors published by a remote `memlink serve`
+    Fetch {
+        /// Remote node (`host` or `host:port`)
+        host: String,
+        /// Memory ID to fetch (lists all descriptors if omitted)
+        #[arg(short, long)]
+        memid: Option<MemId>,
+        /// Import the fetched memory
+        #[arg(short, long, requires = "memid")]
+        import: bool,
+        /// NUMA node to place imported memory on (default: kernel choice)
+        #[arg(short, long, requires = "import")]
+        numa: Option<i32>,
+        /// Base distance hint for NUMA placement
+        #[arg(short, long, default_value = "0", requires = "import")]
+        base_dist: i32,
+        /// Save the fetched descriptor to FILE
+        #[arg(short, long, value_name = "FILE", requires = "memid")]
+        save: Option<PathBuf>,
+        /// Require descriptors signed with the shared key in FILE
+        #[arg(short, long, value_name = "FILE")]
+        key_file: Option<PathBuf>,
+    },
+    /// Renew the lease of memory exported by a remote `memlink serve`
+    Renew {
+        /// Remote node (`host` or `host:port`)
+        host: String,
+        /// Memory ID of the leased export
+        #[arg(short, long)]
+        memid: MemId,
+        /// Keep renewing until interrupted or a renewal fails
+        #[arg(short, long)]
+        keep: bool,
+        /// Authenticate renewals with the shared key in FILE
+        #[arg(long, value_name = "FILE")]
+        key_file: Option<PathBuf>,
+    },
+    /// Unexport previously exported memory
+    Unexport {
+        /// Memory ID returned by `memlink export`
+        #[arg(short, long)]
+        memid: MemId,
+        /// Force unexport even if the memory is still imported
+        #[arg(short, long)]
+        force: bool,
+        /// With --force, first wait up to DURATION for importers to detach
+        /// (e.g. 30s, 2m)
+        #[arg(short, long, value_name = "DURATION", value_parser = parse_duration, requires = "force")]
+        wait: Option<Duration>,
+    },
+    /// Unimport previously imported memory
+    Unimport {
+        /// Memory ID returned by `memlink import`
+        #[arg(short, long)]
+        memid: MemId,
+    },
+    /// Find and remove registry entries and descriptors of memory that is
+    /// no longer exported or imported
+    Cleanup {
+        /// Only report what would be removed
+        #[arg(short = 'n', long)]
+        dry_run: bool,
     },
     /// Measure bandwidth and latency using mar_perf
     MarPerf {
@@ -43,15 +187,125 @@ enum Commands {
         /// Port ID to measure
         #[arg(short, long, default_value = "0")]
         port: u32,
-        /// Measurement time in milliseconds (1-3600)
+        /// Measure every port pair of the die at once
+        #[arg(short, long, conflicts_with = "port")]
+        all: bool,
+        /// Measurement time in milliseconds (1-3600); the sampling interval
+        /// with --watch
         #[arg(short, long, default_value = "1000")]
         time: u32,
+        /// Sample the port continuously until interrupted
+        #[arg(short, long, conflicts_with = "all")]
+        watch: bool,
+        /// Stop watching after N samples
+        #[arg(short = 'n', long, value_name = "N", requires = "watch")]
+        count: Option<usize>,
+        /// Also write the samples to FILE as CSV
+        #[arg(long, value_name = "FILE", requires = "watch")]
+        csv: Option<PathBuf>,
+        /// Print the results as a JSON array
+        #[arg(long, conflicts_with = "watch")]
+        json: bool,
+        /// Measure ports whose link is down (they read zero traffic)
+        #[arg(long)]
+        force: bool,
+    },
+    /// List UB fwctl devices and their ports
+    Ls {
+        /// Print the device list as JSON
+        #[arg(long)]
+        json: bool,
+        /// Report which backend (ioctl or sysfs) serves each device and
+        /// which commands it supports
+        #[arg(long, conflicts_with = "json")]
+        caps: bool,
+        /// Query every device instead of reusing results cached by a
+        /// listing in the last few seconds
+        #[arg(long, conflicts_with = "caps")]
+        refresh: bool,
     },
     /// ETMEM: Enhanced Tiered Memory management
     Etmem {
         #[command(subcommand)]
         action: EtmemCommands,
     },
+    /// Report kernel support, devices and permissions for ETMEM, OBMM and
+    /// UB fwctl
+    Probe {
+        /// Print the capability document as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Interactive prompt to attach, scan, filter and swap without rescanning
+    Shell {
+        /// Process ID to attach to at startup
+        #[arg(short, long)]
+        pid: Option<u32>,
+    },
+}
+
+/// Descriptor subcommands
+#[derive(Subcommand, Debug)]
+enum DescCommands {
+    /// Print the size, endpoints, flags and attributes of a descriptor and
+    /// whether it is valid, without importing it
+    Show {
+        /// Memory descriptor file
+        file: PathBuf,
+        /// Print the breakdown as JSON
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// Output format for scan results
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// Human-readable table
+    #[default]
+    Table,
+    /// JSON document with pages and summary
+    Json,
+    /// CSV with one row per page entry
+    Csv,
+    /// DAMON-style monitoring regions, laid out like `damo report raw`
+    Damon,
+    /// DAMON-style heatmap: `<time> <address> <heat>` per address slice
+    DamonHeats,
+}
+
+/// How addresses are written in scan results
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum AddressFormat {
+    /// Absolute virtual addresses
+    #[default]
+    Absolute,
+    /// Offsets into the containing mapping (e.g. `[heap]+0x1000`), stable
+    /// across ASLR layouts
+    Relative,
+}
+
+/// Order in which autoswap evicts cold pages
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum EvictionStrategy {
+    /// Pages idle for the most scans first
+    #[default]
+    Oldest,
+    /// Pages of the largest contiguous cold runs first
+    LargestRun,
+    /// Lowest addresses first
+    Address,
+}
+
+impl EvictionStrategy {
+    /// Configure an ager to select pages in this order
+    fn apply(self, ager: etmem_rs::PageAger) -> etmem_rs::PageAger {
+        match self {
+            EvictionStrategy::Oldest => ager.with_eviction_order(etmem_rs::OldestFirst),
+            EvictionStrategy::LargestRun => ager.with_eviction_order(etmem_rs::LargestRunFirst),
+            EvictionStrategy::Address => ager.with_eviction_order(etmem_rs::AddressOrder),
+        }
+    }
 }
 
 /// ETMEM subcommands for tiered memory management
@@ -62,6 +316,10 @@ enum EtmemCommands {
         /// Process ID to scan (default: current process)
         #[arg(short, long)]
         pid: Option<u32>,
+        /// Take --pid as a PID inside the container that this host PID
+        /// belongs to
+        #[arg(long, value_name = "PID", requires = "pid")]
+        container: Option<u32>,
         /// Only scan huge pages (2MB/1GB)
         #[arg(long)]
         huge_only: bool,
@@ -71,101 +329,730 @@ enum EtmemCommands {
         /// Only show idle (cold) pages
         #[arg(long)]
         idle_only: bool,
+        /// Split idle memory into anonymous and file-backed, with per-file
+        /// page cache idleness
+        #[arg(long)]
+        files: bool,
+        /// Stop after this long and report where to resume (e.g. 200ms, 5s)
+        #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+        max_duration: Option<Duration>,
+        /// Continue a time-limited scan from this address (hex)
+        #[arg(long, value_name = "ADDR", value_parser = parse_address)]
+        resume: Option<u64>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+        /// How addresses are written; relative addresses let reports from
+        /// different runs of the same binary be compared
+        #[arg(long, value_enum, default_value_t = AddressFormat::Absolute)]
+        addresses: AddressFormat,
+        /// Write results to FILE instead of stdout
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<PathBuf>,
     },
     /// Swap out cold pages to free memory
     Swap {
         /// Process ID to swap pages from
         #[arg(short, long)]
         pid: Option<u32>,
-        /// Virtual addresses to swap (hex, comma-separated)
+        /// Take --pid as a PID inside the container that this host PID
+        /// belongs to
+        #[arg(long, value_name = "PID", requires = "pid")]
+        container: Option<u32>,
+        /// Virtual addresses or START-END ranges to swap (hex, comma-separated)
         #[arg(short, long, value_delimiter = ',')]
         addrs: Vec<String>,
+        /// Read addresses from FILE, one address or range per line; `-` reads stdin
+        #[arg(long, value_name = "FILE")]
+        from_file: Option<PathBuf>,
+        /// Do not ask before swapping a process of another user or cgroup
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Detect pages that stay idle across several scans and swap them out
+    Autoswap {
+        /// Process ID to reclaim memory from
+        #[arg(short, long)]
+        pid: u32,
+        /// Time between scans (e.g. 500ms, 30s, 2m)
+        #[arg(short, long, default_value = "30s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Number of scans a page must stay idle before it is swapped
+        #[arg(short, long, default_value = "3")]
+        cycles: u32,
+        /// Maximum amount of memory to swap in MB
+        #[arg(short, long)]
+        max_mb: Option<u64>,
+        /// Order in which cold pages are selected and swapped
+        #[arg(long, value_enum, default_value_t = EvictionStrategy::Oldest)]
+        order: EvictionStrategy,
+        /// Only report cold pages, do not swap them
+        #[arg(long)]
+        dry_run: bool,
+        /// Write a Chrome trace-event timeline of the run to FILE (open in Perfetto)
+        #[arg(long, value_name = "FILE")]
+        trace: Option<PathBuf>,
+        /// Maximum scan CPU time per minute in ms; slower scanning beyond it
+        #[arg(long, value_name = "MS")]
+        cpu_budget: Option<u64>,
+        /// Maximum bytes read by scans per minute in MB; slower scanning beyond it
+        #[arg(long, value_name = "MB")]
+        read_budget: Option<u64>,
+        /// Anomalies (overlapping runs, invalid page types, ...) a scan may
+        /// have before reclaim based on it is refused
+        #[arg(long, value_name = "N", default_value = "0")]
+        max_anomalies: u64,
+        /// Do not ask before swapping a process of another user or cgroup
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Live view of hot/cold memory per region
+    Watch {
+        /// Process ID to watch
+        #[arg(short, long)]
+        pid: u32,
+        /// Refresh interval (e.g. 500ms, 2s)
+        #[arg(short, long, default_value = "2s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Number of regions to display
+        #[arg(short, long, default_value = "20")]
+        top: usize,
+        /// Exit after this many refreshes
+        #[arg(short = 'n', long)]
+        iterations: Option<u32>,
+    },
+    /// Summarize cold memory of processes as JSON, optionally pushing it to a collector
+    Report {
+        /// Processes to include (comma-separated)
+        #[arg(short, long, value_delimiter = ',', required = true)]
+        pid: Vec<u32>,
+        /// Post reports to a collector at URL (http://host[:port]/path)
+        #[arg(long, value_name = "URL")]
+        push: Option<String>,
+        /// Report every DURATION until interrupted (e.g. 30s, 5m)
+        #[arg(short, long, value_name = "DURATION", value_parser = parse_duration)]
+        interval: Option<Duration>,
+        /// Exit after this many reports
+        #[arg(short = 'n', long, requires = "interval")]
+        iterations: Option<u32>,
+        /// Sign pushed reports with the shared key in FILE
+        #[arg(short, long, value_name = "FILE", requires = "push")]
+        key_file: Option<PathBuf>,
+        /// Keep unsent reports in DIR (default: the state directory's spool)
+        #[arg(long, value_name = "DIR", requires = "push")]
+        spool: Option<PathBuf>,
+        /// Most unsent reports to keep; the oldest are dropped beyond it
+        #[arg(long, value_name = "N", default_value_t = push::MAX_SPOOLED, requires = "push")]
+        max_spooled: usize,
+        /// Attempts per report before leaving it for the next round
+        #[arg(long, value_name = "N", default_value = "3", requires = "push")]
+        attempts: u32,
+    },
+    /// Cross-check a scan against DAMON's monitoring results
+    DamonCheck {
+        /// Process ID monitored by DAMON
+        #[arg(short, long)]
+        pid: u32,
+        /// Fraction of the compared bytes both must agree on
+        #[arg(long, default_value = "0.9")]
+        min_agreement: f64,
+    },
+    /// Configure swapcache watermarks and proactive reclaim
+    Watermark {
+        /// Process ID whose swap handle issues the requests (default: memlink itself)
+        #[arg(short, long)]
+        pid: Option<u32>,
+        /// Low watermark in percent of RAM (reclaim starts above it)
+        #[arg(long, requires = "high", value_parser = clap::value_parser!(u8).range(0..=100))]
+        low: Option<u8>,
+        /// High watermark in percent of RAM (reclaim stops below it)
+        #[arg(long, requires = "low", value_parser = clap::value_parser!(u8).range(0..=100))]
+        high: Option<u8>,
+        /// Start the proactive reclaim kernel thread
+        #[arg(long, conflicts_with = "disable_reclaim")]
+        enable_reclaim: bool,
+        /// Stop the proactive reclaim kernel thread
+        #[arg(long)]
+        disable_reclaim: bool,
     },
     /// Configure kernel swap settings
     Config {
         /// Enable kernel swap
-        #[arg(long)]
+        #[arg(long, conflicts_with_all = ["disable", "apply", "rollback"])]
         enable: bool,
         /// Disable kernel swap
-        #[arg(long)]
+        #[arg(long, conflicts_with_all = ["apply", "rollback"])]
         disable: bool,
         /// Show current status
         #[arg(long)]
         status: bool,
+        /// Apply a desired-state JSON file (kernel_swap_enable, watermark,
+        /// proactive_reclaim), recording the current state for --rollback
+        #[arg(long, value_name = "FILE", conflicts_with = "rollback")]
+        apply: Option<PathBuf>,
+        /// Restore the state recorded by the last --apply or --rollback
+        #[arg(long)]
+        rollback: bool,
+        /// Only show the differences with --apply or --rollback
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
 fn main() -> anyhow::Result<()> {
-    env_logger::init_from_env(env_logger::Env::default().default_filter_or("info"));
-
     let cli = Cli::parse();
+    output::init(cli.color, Verbosity::from_flags(cli.quiet, cli.verbose));
+    #[cfg(feature = "failpoints")]
+    etmem_rs::failpoints::configure_from_env()?;
 
     match cli.command {
-        Commands::Export { node, size } => {
+        Commands::Export {
+            node,
+            size,
+            out,
+            lease,
+            grace,
+        } => {
             info!("Exporting memory from NUMA node {node}, size: {size} MB");
-            export_memory(node, size)?;
+            let lease = lease.map(|ttl| Lease::new(ttl, grace));
+            export_memory(node, size, out, lease)?;
+        }
+        Commands::Import {
+            desc,
+            numa,
+            strict,
+            base_dist,
+        } => {
+            let policy = match numa {
+                Some(node) if strict => NumaPolicy::Strict(node),
+                Some(node) => NumaPolicy::Preferred(node),
+                None => NumaPolicy::Any,
+            };
+            let desc = ObmmMemDesc::<UbPrivData>::from_json_path(&desc)
+                .with_context(|| format!("Failed to read descriptor {}", desc.display()))?;
+            import_memory(&desc, policy, base_dist)?;
+        }
+        Commands::Desc {
+            action: DescCommands::Show { file, json },
+        } => {
+            let desc = ObmmMemDesc::<UbPrivData>::from_json_path(&file)
+                .with_context(|| format!("Failed to read descriptor {}", file.display()))?;
+            let description = desc.describe();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&description)?);
+            } else {
+                println!("{description}");
+            }
+            if let Some(problem) = description.problem {
+                anyhow::bail!("Descriptor {} is invalid: {problem}", file.display());
+            }
+        }
+        Commands::Serve {
+            listen,
+            dir,
+            workers,
+            key_file,
+        } => {
+            let registry = open_registry()?;
+            let dir = dir.unwrap_or_else(|| registry.dir().to_path_buf());
+            let key = key_file.as_deref().map(net::read_key).transpose()?;
+            net::serve(&listen, dir, registry, workers, key)?;
+        }
+        Commands::Fetch {
+            host,
+            memid,
+            import,
+            numa,
+            base_dist,
+            save,
+            key_file,
+        } => {
+            let key = key_file.as_deref().map(net::read_key).transpose()?;
+            fetch_descriptors(&host, memid, import, numa, base_dist, save, key.as_deref())?;
+        }
+        Commands::Renew {
+            host,
+            memid,
+            keep,
+            key_file,
+        } => {
+            let key = key_file.as_deref().map(net::read_key).transpose()?;
+            lease::renew(&host, memid, keep, key.as_deref())?;
+        }
+        Commands::Unexport { memid, force, wait } => {
+            unexport_memory(memid, force, wait)?;
+
+            // The descriptor no longer refers to exported memory
+            open_registry()?
+                .remove(EntryKind::Export, memid)
+                .with_context(|| format!("Failed to unregister export {memid}"))?;
+            info!("Unexported memory with MemID: {memid}");
+        }
+        Commands::Unimport { memid } => {
+            mem_unimport(memid, ObmmExportFlags::empty())
+                .with_context(|| format!("Failed to unimport {memid}"))?;
+            open_registry()?
+                .remove(EntryKind::Import, memid)
+                .with_context(|| format!("Failed to unregister import {memid}"))?;
+            info!("Unimported memory with MemID: {memid}");
+        }
+        Commands::Cleanup { dry_run } => {
+            cleanup_state(dry_run)?;
         }
         Commands::MarPerf {
             chip_id,
             die_id,
             port,
+            all,
             time,
+            watch,
+            count,
+            csv,
+            json,
+            force,
+        } => {
+            let command = ubfwctl::MarPerfCommand::new().force(force);
+            if watch {
+                info!(
+                    "Watching mar_perf on chip {chip_id}, die {die_id}, port {port}, interval: {time}ms"
+                );
+                let mut monitor = ubfwctl::MarPerfMonitor::new(chip_id, die_id, port)
+                    .interval_ms(time)
+                    .force(force);
+                if let Some(count) = count {
+                    monitor = monitor.count(count);
+                }
+                watch_mar_perf(monitor, csv)?;
+            } else if all {
+                info!(
+                    "Running mar_perf measurement on chip {chip_id}, die {die_id}, all ports, time: {time}ms"
+                );
+                let results = command
+                    .execute_all(chip_id, die_id, time)
+                    .with_context(|| "mar_perf measurement failed")?;
+                print_mar_perf(&results, json)?;
+            } else {
+                info!(
+                    "Running mar_perf measurement on chip {chip_id}, die {die_id}, port {port}, time: {time}ms"
+                );
+                let result = command
+                    .execute(chip_id, die_id, port, time)
+                    .with_context(|| "mar_perf measurement failed")?;
+                print_mar_perf(&[result], json)?;
+            }
+        }
+        Commands::Ls { caps: true, .. } => {
+            let reports =
+                ubfwctl::probe_capabilities().with_context(|| "Failed to probe fwctl devices")?;
+            if reports.is_empty() {
+                println!("No devices found.");
+                return Ok(());
+            }
+            let mut table = Table::new([
+                Column::left("Device"),
+                Column::left("List"),
+                Column::left("Ports/mar_perf"),
+            ]);
+            for report in &reports {
+                let backend = match report.backend() {
+                    Some(ubfwctl::Backend::Ioctl) => paint("ioctl", Tone::Good),
+                    Some(ubfwctl::Backend::Sysfs) => paint("sysfs", Tone::Warn),
+                    None => paint("unavailable", Tone::Bad),
+                };
+                let rpc = if report.supports_rpc_commands() {
+                    paint("available", Tone::Good)
+                } else {
+                    paint("unavailable", Tone::Bad)
+                };
+                table.row([report.device.clone(), backend.to_string(), rpc.to_string()]);
+            }
+            table.write(&mut io::stdout().lock())?;
+        }
+        Commands::Ls {
+            json,
+            caps: false,
+            refresh,
         } => {
-            info!(
-                "Running mar_perf measurement on chip {chip_id}, die {die_id}, port {port}, time: {time}ms"
+            let options = ubfwctl::ScanOptions::new().cache(
+                ubfwctl::cache::DEVICE_CACHE_PATH,
+                ubfwctl::cache::DEFAULT_CACHE_TTL,
             );
-            run_mar_perf(chip_id, die_id, port, time)?;
+            let options = if refresh { options.refresh() } else { options };
+            let devices = ubfwctl::scan_devices_with(&options)
+                .with_context(|| "Failed to list fwctl devices")?;
+            let output = if json {
+                ubfwctl::format_device_list_json(&devices)?
+            } else {
+                ubfwctl::format_device_list(&devices)
+            };
+            println!("{}", output.trim_end());
         }
         Commands::Etmem { action } => {
             handle_etmem_command(action)?;
         }
+        Commands::Probe { json } => {
+            let report = probe::ProbeReport::collect();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                report.write(&mut io::stdout().lock())?;
+            }
+        }
+        Commands::Shell { pid } => {
+            shell::run(pid)?;
+        }
     }
 
     Ok(())
 }
 
-/// Export memory from a NUMA node
-fn export_memory(node: usize, size_mb: usize) -> anyhow::Result<()> {
-    let export_id = node;
-    let size_bytes = size_mb * 1024 * 1024;
-
-    let mut lens = vec![0; MAX_NUMA_NODES];
-    lens.get_mut(export_id)
-        .map(|v| *v = size_bytes)
-        .with_context(|| format!("Failed to set length for NUMA node {export_id}"))?;
+/// Open the export/import registry (`MEMLINK_STATE_DIR` overrides its location)
+fn open_registry() -> anyhow::Result<Registry> {
+    Registry::open_default().with_context(|| "Failed to open memlink registry")
+}
 
-    let (mem_id, desc) = mem_export::<UbPrivData>(&lens, ObmmExportFlags::ALLOWMMAP)
-        .with_context(|| "Failed to export memory")?;
+/// Export memory from a NUMA node, optionally under a lease
+fn export_memory(
+    node: usize,
+    size_mb: usize,
+    out: Option<PathBuf>,
+    lease: Option<Lease>,
+) -> anyhow::Result<()> {
+    let flags = ObmmExportFlags::ALLOWMMAP;
+    let request = ExportRequest::new().numa(node, size_mb.mib()).flags(flags);
+    let context = || format!("Failed to export {size_mb} MB from NUMA node {node}");
+    // Explain layouts the kernel would reject with a bare EINVAL
+    request
+        .lengths()
+        .and_then(|lengths| obmm_rs::validate_export_layout(&lengths))
+        .with_context(context)?;
+    let (mem_id, desc) = request.export::<UbPrivData>().with_context(context)?;
 
     info!("Exported memory with MemID: {mem_id}");
     info!("Memory Descriptor: {desc:?}");
 
+    let registry = open_registry()?;
+    let mut path = registry
+        .record_export(mem_id, flags, &desc)
+        .with_context(|| format!("Failed to register export {mem_id}"))?;
+    if lease.is_some() {
+        registry
+            .set_lease(mem_id, lease)
+            .with_context(|| format!("Failed to set the lease of export {mem_id}"))?;
+    }
+    if let Some(out) = out {
+        desc.to_json_path(&out)
+            .with_context(|| format!("Failed to write descriptor to {}", out.display()))?;
+        path = out;
+    }
+    println!("{mem_id}\t{}", path.display());
+
     Ok(())
 }
 
-/// Run mar_perf measurement and display results
-fn run_mar_perf(chip_id: u32, die_id: u32, port: u32, time: u32) -> anyhow::Result<()> {
-    let result = ubfwctl::mar_perf_measure(chip_id, die_id, port, time)
-        .with_context(|| "mar_perf measurement failed")?;
+/// Unexport memory, refusing or warning if other nodes still import it
+fn unexport_memory(memid: MemId, force: bool, wait: Option<Duration>) -> anyhow::Result<()> {
+    if let Some(timeout) = wait {
+        let outcome = unexport_graceful(memid, timeout)
+            .with_context(|| format!("Failed to unexport {memid}"))?;
+        match outcome {
+            UnexportOutcome::Forced {
+                importers: Some(importers),
+            } => log::warn!(
+                "Forced unexport of {memid}: {importers} importer(s) did not detach within {}",
+                format_duration(timeout)
+            ),
+            UnexportOutcome::Forced { importers: None } => log::warn!(
+                "Forced unexport of {memid}: it was still in use after {}",
+                format_duration(timeout)
+            ),
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    match query_importers(memid) {
+        Ok(0) => {}
+        Ok(importers) if force => log::warn!(
+            "Forcing unexport of {memid} while {importers} node(s) still import it; \
+             their accesses will fault"
+        ),
+        Ok(importers) => anyhow::bail!(
+            "{memid} is still imported by {importers} node(s); unimport it there first \
+             or pass --force (with --wait to give importers time to detach)"
+        ),
+        Err(err) => log::warn!("Could not query importers of {memid}: {err}"),
+    }
+
+    let flags = if force {
+        ObmmUnexportFlags::FORCE
+    } else {
+        ObmmUnexportFlags::empty()
+    };
+    mem_unexport(memid, flags).with_context(|| format!("Failed to unexport {memid}"))
+}
+
+/// Remove registry state that no longer matches the kernel's regions
+fn cleanup_state(dry_run: bool) -> anyhow::Result<()> {
+    let registry = open_registry()?;
+    // Without the listing every entry would look stale
+    let regions = obmm_rs::query::list_regions()
+        .with_context(|| "Failed to list OBMM regions (is the OBMM kernel module loaded?)")?;
+    let orphans = cleanup::find_orphans(&registry, &regions)?;
 
-    println!("{result}");
+    let mut removed = 0;
+    let mut table = Table::new([
+        Column::right("MemID").width(10),
+        Column::left("Action").width(12),
+        Column::left("Entry"),
+    ]);
+    for orphan in &orphans {
+        let action = match (orphan.is_removable(), dry_run) {
+            (false, _) => paint("keep", Tone::Dim),
+            (true, true) => paint("would remove", Tone::Warn),
+            (true, false) => {
+                orphan.remove(&registry)?;
+                removed += 1;
+                paint("removed", Tone::Good)
+            }
+        };
+        table.row([
+            orphan.mem_id().to_string(),
+            action.to_string(),
+            orphan.describe(),
+        ]);
+    }
+    if !table.is_empty() {
+        table.write(&mut io::stdout().lock())?;
+    }
 
+    let removable = orphans.iter().filter(|o| o.is_removable()).count();
+    if dry_run {
+        output::status(format!(
+            "{} orphan(s), {removable} would be removed (dry run)",
+            orphans.len()
+        ));
+    } else {
+        output::status(format!("{} orphan(s), {removed} removed", orphans.len()));
+    }
     Ok(())
 }
 
+/// Import memory described by a descriptor
+fn import_memory(
+    desc: &ObmmMemDesc<UbPrivData>,
+    policy: NumaPolicy,
+    base_dist: i32,
+) -> anyhow::Result<()> {
+    info!("Importing {} bytes at {:#x}", desc.length, desc.addr);
+
+    let flags = ObmmExportFlags::ALLOWMMAP;
+    let outcome = ImportOptions::new()
+        .base_dist(base_dist)
+        .policy(policy)
+        .import(desc)
+        .with_context(|| "Failed to import memory")?;
+    let result = outcome.result;
+    if outcome.honored == HonoredPolicy::Fallback {
+        log::warn!(
+            "Requested NUMA node unavailable, placed on node {}",
+            result.numa_node
+        );
+    }
+    open_registry()?
+        .record_import(result.mem_id, desc.length, flags, result.numa_node)
+        .with_context(|| format!("Failed to register import {}", result.mem_id))?;
+
+    info!(
+        "Imported memory with MemID: {} on NUMA node {}",
+        result.mem_id, result.numa_node
+    );
+    println!("{}\t{}", result.mem_id, result.numa_node);
+
+    Ok(())
+}
+
+/// List, save or import descriptors from a remote node
+fn fetch_descriptors(
+    host: &str,
+    memid: Option<MemId>,
+    import: bool,
+    numa: Option<i32>,
+    base_dist: i32,
+    save: Option<PathBuf>,
+    key: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let mut client = net::Client::connect(host)?;
+
+    let Some(memid) = memid else {
+        let entries = client.list()?;
+        let mut table = Table::new([
+            Column::right("MemID").width(10),
+            Column::right("Address").width(18),
+            Column::right("Length").width(12),
+            Column::right("Signed"),
+        ]);
+        for entry in &entries {
+            table.row([
+                entry.mem_id.to_string(),
+                format!("{:#x}", entry.desc().addr),
+                etmem_rs::format_bytes(entry.desc().length),
+                if entry.is_sealed() { "yes" } else { "no" }.to_string(),
+            ]);
+        }
+        table.write(&mut io::stdout().lock())?;
+        output::status(format!("{} descriptors on {host}", entries.len()));
+        return Ok(());
+    };
+
+    let desc = client.get(memid)?.open(key)?;
+    if let Some(path) = &save {
+        desc.to_json_path(path)
+            .with_context(|| format!("Failed to write descriptor to {}", path.display()))?;
+        info!("Saved descriptor of {memid} to {}", path.display());
+    }
+
+    if import {
+        import_memory(
+            &desc,
+            numa.map_or(NumaPolicy::Any, NumaPolicy::Preferred),
+            base_dist,
+        )?;
+    } else if save.is_none() {
+        println!("{}", desc.to_json()?);
+    }
+
+    Ok(())
+}
+
+/// Print mar_perf results as text or as a JSON array
+fn print_mar_perf(results: &[ubfwctl::MarPerfResult], json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", ubfwctl::format_mar_perf_json(results)?);
+    } else if let [result] = results {
+        // Same layout as the C ubctl tool
+        println!("{result}");
+    } else {
+        let mut table = Table::new([
+            Column::right("Ports"),
+            Column::right("Write B/s"),
+            Column::right("Read B/s"),
+            Column::right("Total B/s"),
+            Column::right("Wr lat ns"),
+            Column::right("Rd lat ns"),
+        ]);
+        for result in results {
+            table.row([
+                format!("{}-{}", result.first_port_id, result.second_port_id),
+                result.wr_traffic.to_string(),
+                result.rd_traffic.to_string(),
+                result.sum_traffic.to_string(),
+                result.wr_delayed.to_string(),
+                result.rd_delayed.to_string(),
+            ]);
+        }
+        table.write(&mut io::stdout().lock())?;
+    }
+
+    Ok(())
+}
+
+/// Sample mar_perf continuously, printing one line per sample
+fn watch_mar_perf(monitor: ubfwctl::MarPerfMonitor, csv: Option<PathBuf>) -> anyhow::Result<()> {
+    let mut csv = csv
+        .map(|path| {
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            ubfwctl::MarPerfCsvWriter::new(BufWriter::new(file))
+                .with_context(|| format!("Failed to write {}", path.display()))
+        })
+        .transpose()?;
+
+    let table = Table::new([
+        Column::right("Elapsed").width(8),
+        Column::right("Write B/s").width(12),
+        Column::right("Read B/s").width(12),
+        Column::right("Total B/s").width(12),
+        Column::right("Wr lat ns").width(10),
+        Column::right("Rd lat ns").width(10),
+    ]);
+    let mut stdout = io::stdout();
+    table.write_header(&mut stdout)?;
+    let start = Instant::now();
+    let samples = monitor
+        .start()
+        .with_context(|| "mar_perf monitoring failed")?;
+    for sample in samples {
+        let sample = sample.with_context(|| "mar_perf sample failed")?;
+        let result = &sample.result;
+        table.write_row(
+            &mut stdout,
+            [
+                format_duration(start.elapsed()),
+                result.wr_traffic.to_string(),
+                result.rd_traffic.to_string(),
+                result.sum_traffic.to_string(),
+                result.wr_delayed.to_string(),
+                result.rd_delayed.to_string(),
+            ],
+        )?;
+        if let Some(csv) = csv.as_mut() {
+            csv.write_sample(&sample)
+                .with_context(|| "Failed to write CSV sample")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the host PID of a target, defaulting to memlink itself
+///
+/// With `container`, `pid` is a PID inside the container that host PID
+/// belongs to.
+fn resolve_target(pid: Option<u32>, container: Option<u32>) -> anyhow::Result<u32> {
+    match (pid, container) {
+        (Some(pid), Some(container)) => {
+            let host_pid = etmem_rs::container::resolve_pid(container, pid).with_context(|| {
+                format!("Failed to find process {pid} in the container of process {container}")
+            })?;
+            info!("Process {pid} of the container is host process {host_pid}");
+            Ok(host_pid)
+        }
+        (pid, _) => Ok(pid.unwrap_or_else(std::process::id)),
+    }
+}
+
 /// Handle ETMEM subcommands
 fn handle_etmem_command(action: EtmemCommands) -> anyhow::Result<()> {
-    use etmem_rs::{IdlePageScanner, PageSwapper, ScanConfig, ScanFlags, SwapcacheConfig};
+    use etmem_rs::{
+        BASE_PAGE_SIZE, IdlePageScanner, RangeSet, ScanConfig, ScanFlags, SwapConfig, SwapSession,
+        SwapcacheConfig, WatermarkConfig,
+    };
 
     match action {
         EtmemCommands::Scan {
             pid,
+            container,
             huge_only,
             dirty,
             idle_only,
+            files,
+            max_duration,
+            resume,
+            format,
+            addresses,
+            output,
         } => {
-            let pid = pid.unwrap_or_else(std::process::id);
-            println!("Scanning process {pid} for memory pages...");
+            let pid = resolve_target(pid, container)?;
+            info!("Scanning process {pid} for memory pages...");
+
+            if files && !matches!(format, OutputFormat::Table | OutputFormat::Json) {
+                anyhow::bail!("--files is only supported with table or JSON output");
+            }
+            let relative = addresses == AddressFormat::Relative;
+            if relative && matches!(format, OutputFormat::Damon | OutputFormat::DamonHeats) {
+                anyhow::bail!("--addresses relative is not supported with DAMON output");
+            }
 
             // Check if ETMEM is available
             if !etmem_rs::is_available() {
@@ -182,11 +1069,42 @@ fn handle_etmem_command(action: EtmemCommands) -> anyhow::Result<()> {
             if dirty {
                 flags |= ScanFlags::SCAN_DIRTY_PAGE;
             }
-            let config = ScanConfig::default().with_flags(flags);
+            let mut config = ScanConfig::default().with_flags(flags);
+            if let Some(limit) = max_duration {
+                config = config.with_max_duration(limit);
+            }
 
             // Scan the process
-            let pages = IdlePageScanner::scan_process(pid, config)
+            let spinner = output::spinner(format!("Scanning process {pid}"));
+            let scan = IdlePageScanner::scan_process_from(pid, config, resume.unwrap_or(0))
                 .with_context(|| format!("Failed to scan process {pid}"))?;
+            spinner.finish_and_clear();
+            if let Some(addr) = scan.resume_at {
+                log::warn!("Scan stopped at its time limit; continue with --resume {addr:#x}");
+            }
+            let pages = scan.pages;
+
+            // Read the mappings right after the scan, so they match the
+            // addresses it reported
+            let vma_map = if files || relative {
+                Some(
+                    VmaMap::for_process(pid)
+                        .with_context(|| format!("Failed to read mappings of process {pid}"))?,
+                )
+            } else {
+                None
+            };
+
+            // Join with mappings before filtering, so hot file pages are
+            // not mistaken for unreferenced ones
+            let page_cache = vma_map
+                .as_ref()
+                .filter(|_| files)
+                .map(|vma_map| PageCacheSummary::build(vma_map, &pages));
+            let rebaser = vma_map
+                .as_ref()
+                .filter(|_| relative)
+                .map(AddressRebaser::new);
 
             // Filter and display results
             let filtered_pages: Vec<_> = if idle_only {
@@ -195,94 +1113,853 @@ fn handle_etmem_command(action: EtmemCommands) -> anyhow::Result<()> {
                 pages
             };
 
-            println!("\nFound {} memory regions:", filtered_pages.len());
-            println!("{:-^60}", "");
-            println!(
-                "{:>16}  {:<15}  {:<10}  {:<12}",
-                "Address", "Type", "Count", "Size"
-            );
-            println!("{:-^60}", "");
-
-            let mut total_bytes = 0u64;
-            for page in &filtered_pages {
-                let size = page.total_size();
-                total_bytes += size;
-                println!(
-                    "{:>16x}  {:<15?}  {:<10}  {:<12}",
-                    page.address,
-                    page.page_type,
-                    page.count,
-                    etmem_rs::format_bytes(size)
+            let mut out: Box<dyn Write> = match &output {
+                Some(path) => Box::new(BufWriter::new(File::create(path).with_context(|| {
+                    format!("Failed to create output file {}", path.display())
+                })?)),
+                None => Box::new(io::stdout().lock()),
+            };
+            let styled = output.is_none();
+            write_scan_results(
+                &mut out,
+                pid,
+                &filtered_pages,
+                page_cache.as_ref(),
+                rebaser.as_ref(),
+                format,
+                styled,
+            )
+            .with_context(|| "Failed to write scan results")?;
+            out.flush()?;
+        }
+        EtmemCommands::Swap {
+            pid,
+            container,
+            addrs,
+            from_file,
+            yes,
+        } => {
+            if addrs.is_empty() && from_file.is_none() {
+                anyhow::bail!(
+                    "No addresses provided. Use --addrs or --from-file to specify addresses to swap."
                 );
             }
 
-            println!("{:-^60}", "");
-            println!(
-                "Total: {} bytes ({})",
-                total_bytes,
-                etmem_rs::format_bytes(total_bytes)
-            );
-
-            // Show statistics
-            let stats = etmem_rs::IdlePageStats::from_pages(&filtered_pages);
-            println!("\nStatistics:");
-            println!(
-                "  Idle pages:     {} ({})",
-                stats.idle_pages,
-                etmem_rs::format_bytes(stats.idle_bytes)
-            );
-            println!(
-                "  Accessed pages: {} ({})",
-                stats.accessed_pages,
-                etmem_rs::format_bytes(stats.accessed_bytes)
-            );
-            println!("  Huge pages:     {}", stats.huge_pages);
-            println!("  Idle ratio:     {:.1}%", stats.idle_ratio() * 100.0);
-        }
-        EtmemCommands::Swap { pid, addrs } => {
-            if addrs.is_empty() {
-                anyhow::bail!("No addresses provided. Use --addrs to specify addresses to swap.");
+            // Validate and coalesce every address before touching the target
+            let mut ranges = match &from_file {
+                Some(path) => addrs::read_list(path)?,
+                None => RangeSet::default(),
+            };
+            for entry in &addrs {
+                ranges.insert(addrs::parse_entry(entry).map_err(anyhow::Error::msg)?);
             }
-
-            let pid = pid.unwrap_or_else(std::process::id);
-            println!("Swapping pages in process {pid}...");
-
-            // Parse addresses
-            let mut parsed_addrs = Vec::new();
-            for addr_str in &addrs {
-                let addr = u64::from_str_radix(addr_str.trim_start_matches("0x"), 16)
-                    .with_context(|| format!("Invalid address: {addr_str}"))?;
-                parsed_addrs.push(addr);
+            if ranges.is_empty() {
+                anyhow::bail!("The address list is empty.");
             }
 
+            let pid = resolve_target(pid, container)?;
+            target::confirm_swap_target(pid, "Swap", yes)?;
+            output::status(format!(
+                "Swapping {} in {} ranges in process {pid}...",
+                etmem_rs::format_bytes(ranges.iter().map(|r| r.size()).sum()),
+                ranges.ranges().len()
+            ));
+
             // Swap the pages
-            let swapped = PageSwapper::swap_pages(pid, &parsed_addrs)
+            let spinner = output::spinner("Swapping pages");
+            let mut session = SwapSession::new(pid, SwapConfig::default())
+                .with_context(|| format!("Failed to open swap session for process {pid}"))?;
+            for &range in ranges.iter() {
+                session
+                    .add_range(range, BASE_PAGE_SIZE)
+                    .with_context(|| format!("Failed to queue {range}"))?;
+            }
+            session
+                .flush()
                 .with_context(|| format!("Failed to swap pages in process {pid}"))?;
+            spinner.finish_and_clear();
 
-            println!("Successfully swapped {swapped} pages");
+            // Batches flushed while queueing count as well as the last one
+            println!(
+                "{} {} pages",
+                paint("Swapped", Tone::Good),
+                session.submitted()
+            );
+        }
+        EtmemCommands::Autoswap {
+            pid,
+            interval,
+            cycles,
+            max_mb,
+            order,
+            dry_run,
+            trace,
+            cpu_budget,
+            read_budget,
+            max_anomalies,
+            yes,
+        } => {
+            if !etmem_rs::is_available() {
+                anyhow::bail!(
+                    "ETMEM is not available. Check kernel configuration (CONFIG_ETMEM=y)."
+                );
+            }
+            if !dry_run {
+                target::confirm_swap_target(pid, "Autoswap", yes)?;
+            }
+            let budget = etmem_rs::ScanBudget::new()
+                .with_cpu_per_minute(cpu_budget.map(Duration::from_millis))
+                .with_read_bytes_per_minute(read_budget.map(|mb| mb * 1024 * 1024));
+            let mut policy = etmem_rs::AgingPolicy::new()
+                .with_min_idle_scans(cycles)
+                .with_max_anomalies(max_anomalies);
+            if let Some(mb) = max_mb {
+                policy = policy.with_max_swap_bytes(mb * 1024 * 1024);
+            }
+            // Cold pages are only handed out while swap has room to take them
+            let ager = order
+                .apply(etmem_rs::PageAger::new(policy))
+                .with_guard(etmem_rs::SwapGuard::default());
+            run_autoswap(pid, interval, ager, dry_run, trace, budget)?;
+        }
+        EtmemCommands::Watch {
+            pid,
+            interval,
+            top,
+            iterations,
+        } => {
+            if !etmem_rs::is_available() {
+                anyhow::bail!(
+                    "ETMEM is not available. Check kernel configuration (CONFIG_ETMEM=y)."
+                );
+            }
+            watch::run(&watch::WatchOptions {
+                pid,
+                interval,
+                top,
+                iterations,
+            })?;
+        }
+        EtmemCommands::Report {
+            pid,
+            push,
+            interval,
+            iterations,
+            key_file,
+            spool,
+            max_spooled,
+            attempts,
+        } => {
+            if !etmem_rs::is_available() {
+                anyhow::bail!(
+                    "ETMEM is not available. Check kernel configuration (CONFIG_ETMEM=y)."
+                );
+            }
+            let pusher = match push {
+                Some(url) => {
+                    let endpoint = push::Endpoint::parse(&url)?;
+                    let key = key_file.as_deref().map(net::read_key).transpose()?;
+                    let spool = push::Spool::open(spool.unwrap_or_else(push::Spool::default_dir))?
+                        .with_limit(max_spooled);
+                    Some(
+                        push::Pusher::new(endpoint, spool, key)
+                            .with_retry(attempts, Duration::from_secs(1)),
+                    )
+                }
+                None => None,
+            };
+            push::run(&push::ReportOptions {
+                pids: pid,
+                pusher,
+                interval,
+                iterations,
+            })?;
+        }
+        EtmemCommands::DamonCheck { pid, min_agreement } => {
+            if !etmem_rs::is_available() {
+                anyhow::bail!(
+                    "ETMEM is not available. Check kernel configuration (CONFIG_ETMEM=y)."
+                );
+            }
+            if !etmem_rs::damon::is_available() {
+                anyhow::bail!(
+                    "DAMON is not available (no {})",
+                    etmem_rs::damon::DAMON_SYSFS_ROOT
+                );
+            }
+            run_damon_check(pid, min_agreement)?;
+        }
+        EtmemCommands::Watermark {
+            pid,
+            low,
+            high,
+            enable_reclaim,
+            disable_reclaim,
+        } => {
+            let watermark = low
+                .zip(high)
+                .map(|(low, high)| WatermarkConfig::new(low, high));
+            let reclaim = if enable_reclaim {
+                Some(true)
+            } else if disable_reclaim {
+                Some(false)
+            } else {
+                None
+            };
+            run_watermark(pid, watermark, reclaim)?;
         }
         EtmemCommands::Config {
             enable,
             disable,
             status,
+            apply,
+            rollback,
+            dry_run,
         } => {
-            if enable {
+            let state_dir = obmm_rs::registry::default_state_dir();
+            if let Some(path) = apply {
+                let desired = config::DesiredState::load(&path)?;
+                run_config_apply(&desired, &state_dir, dry_run)?;
+            } else if rollback {
+                let desired = config::recorded(&state_dir)?;
+                run_config_apply(&desired, &state_dir, dry_run)?;
+            } else if enable {
                 SwapcacheConfig::enable().with_context(|| "Failed to enable kernel swap")?;
-                println!("Kernel swap enabled");
+                println!("Kernel swap {}", paint("enabled", Tone::Good));
             } else if disable {
                 SwapcacheConfig::disable().with_context(|| "Failed to disable kernel swap")?;
-                println!("Kernel swap disabled");
+                println!("Kernel swap {}", paint("disabled", Tone::Warn));
             } else if status || (!enable && !disable) {
                 let enabled = SwapcacheConfig::is_enabled()
                     .with_context(|| "Failed to check kernel swap status")?;
                 println!(
                     "Kernel swap status: {}",
-                    if enabled { "enabled" } else { "disabled" }
+                    enabled_str(enabled, "enabled", "disabled")
                 );
 
                 // Also check ETMEM availability
-                println!("ETMEM available: {}", etmem_rs::is_available());
-                println!("Root privileges: {}", etmem_rs::has_permission());
+                println!(
+                    "ETMEM available: {}",
+                    enabled_str(etmem_rs::is_available(), "true", "false")
+                );
+                println!(
+                    "Root privileges: {}",
+                    enabled_str(etmem_rs::has_permission(), "true", "false")
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Show how the kernel differs from a desired state and apply it
+///
+/// The current state is recorded under `state_dir` before anything is
+/// written, so that `--rollback` can restore it.
+fn run_config_apply(
+    desired: &config::DesiredState,
+    state_dir: &std::path::Path,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let mut knobs = config::KernelKnobs::open()?;
+    let current = config::Knobs::current(&mut knobs)?;
+    let changes = config::diff(&current, desired);
+    if changes.is_empty() {
+        println!(
+            "etmem configuration already {}",
+            paint("up to date", Tone::Good)
+        );
+        return Ok(());
+    }
+
+    let mut table = Table::new([
+        Column::left("Setting").width(20),
+        Column::left("Current").width(12),
+        Column::left("Desired"),
+    ]);
+    for change in &changes {
+        table.row([
+            change.to.name().to_string(),
+            change
+                .from
+                .map_or_else(|| "unknown".to_string(), |from| from.to_string()),
+            change.to.to_string(),
+        ]);
+    }
+    table.write(&mut io::stdout().lock())?;
+    if dry_run {
+        return Ok(());
+    }
+
+    config::record(state_dir, &current.to_desired())?;
+    config::apply(&mut knobs, &changes)?;
+    println!(
+        "Applied {} change(s); previous state recorded in {}",
+        changes.len(),
+        config::record_path(state_dir).display()
+    );
+    Ok(())
+}
+
+/// Scan a process repeatedly, age its pages and swap the cold ones
+///
+/// The process is scanned as many times as the ager's policy requires a
+/// page to stay idle.
+fn run_autoswap(
+    pid: u32,
+    interval: Duration,
+    mut ager: etmem_rs::PageAger,
+    dry_run: bool,
+    trace: Option<PathBuf>,
+    budget: etmem_rs::ScanBudget,
+) -> anyhow::Result<()> {
+    use etmem_rs::{
+        CostLimiter, FreezeDetector, IdlePageScanner, PolicyEvent, ScanConfig, SwapConfig,
+        SwapSession,
+    };
+    use serde_json::json;
+
+    let cycles = ager.policy().min_idle_scans;
+    let mut limiter = CostLimiter::new(budget);
+    let mut recorder = trace.as_ref().map(|_| trace::TraceRecorder::new(pid));
+    let start = Instant::now();
+
+    output::status(format!(
+        "Autoswap for process {pid}: {cycles} scans, {} apart, {} eviction",
+        format_duration(interval),
+        ager.eviction_order().name()
+    ));
+
+    let progress = output::progress(u64::from(cycles), "Scanning");
+    let mut last_stats = IdlePageStats::default();
+    for cycle in 1..=cycles {
+        if cycle > 1 {
+            std::thread::sleep(interval);
+        }
+
+        let throttle_start = Instant::now();
+        let delay = limiter.throttle();
+        if !delay.is_zero() {
+            output::progress_line(
+                &progress,
+                format!(
+                    "  [{cycle}/{cycles}] scan budget exceeded, waited {}",
+                    format_duration(delay)
+                ),
+            );
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.span(
+                    "throttle",
+                    throttle_start,
+                    Instant::now(),
+                    json!({ "cycle": cycle }),
+                );
+            }
+        }
+
+        let scan_start = Instant::now();
+        let (pages, anomalies) = limiter
+            .measure(|| IdlePageScanner::scan_process_checked(pid, ScanConfig::default()))
+            .with_context(|| format!("Failed to scan process {pid}"))?;
+        // The process may have exited; ages then stay as they are
+        let vma_map = VmaMap::for_process(pid).ok();
+        if let Some(vma_map) = &vma_map {
+            ager.sync_maps(vma_map);
+        }
+        let idle = match ager.observe_checked(&pages, &anomalies) {
+            Some(idle) => idle,
+            None => ager.tracked(),
+        };
+        last_stats = IdlePageStats::from_pages(&pages);
+        let scan_end = Instant::now();
+
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.span(
+                "scan",
+                scan_start,
+                scan_end,
+                json!({
+                    "cycle": cycle,
+                    "scanned_bytes": last_stats.total_bytes,
+                    "idle_bytes": last_stats.idle_bytes,
+                    "idle_pages": idle,
+                }),
+            );
+            recorder.counter(
+                "memory",
+                scan_end,
+                json!({
+                    "idle_bytes": last_stats.idle_bytes,
+                    "cold_bytes": ager.cold_bytes(),
+                }),
+            );
+            // The process may have exited; the timeline then stops at the scan
+            if let Some(vma_map) = &vma_map {
+                recorder.regions(scan_end, &RegionReport::build(vma_map, &pages));
+            }
+        }
+
+        for event in ager.take_events() {
+            match &event {
+                PolicyEvent::MapsChanged {
+                    change,
+                    invalidated,
+                } => {
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.instant(
+                            "maps_changed",
+                            scan_end,
+                            json!({
+                                "cycle": cycle,
+                                "generation": change.generation,
+                                "changed_bytes": change.changed_bytes(),
+                                "invalidated_pages": invalidated,
+                            }),
+                        );
+                    }
+                }
+                PolicyEvent::ScanRejected {
+                    report,
+                    max_anomalies,
+                } => {
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.instant(
+                            "scan_rejected",
+                            scan_end,
+                            json!({
+                                "cycle": cycle,
+                                "anomalies": report.count,
+                                "max_anomalies": max_anomalies,
+                            }),
+                        );
+                    }
+                }
+                // Raised only when selecting pages for reclaim, after the scans
+                PolicyEvent::ReclaimRefused { .. } => {}
+            }
+            output::progress_line(&progress, format!("  [{cycle}/{cycles}] {event}"));
+        }
+
+        output::progress_line(
+            &progress,
+            format!(
+                "  [{cycle}/{cycles}] scanned {}, idle {} ({:.1}%), {idle} pages idle so far, {} cold",
+                etmem_rs::format_bytes(last_stats.total_bytes),
+                etmem_rs::format_bytes(last_stats.idle_bytes),
+                last_stats.idle_ratio() * 100.0,
+                etmem_rs::format_bytes(ager.cold_bytes())
+            ),
+        );
+        progress.inc(1);
+    }
+    progress.finish_and_clear();
+
+    let cold = if dry_run {
+        ager.cold_pages()
+    } else {
+        ager.reclaim_pages()
+            .with_context(|| "Failed to check swap pressure")?
+    };
+    for event in ager.take_events() {
+        log::warn!("{event}; skipping reclaim");
+    }
+    let cold_bytes: u64 = cold.iter().map(|p| p.total_size()).sum();
+
+    let swap_start = Instant::now();
+    // A stopped or frozen process is most likely being checkpointed, and
+    // reclaim would only slow down the dump
+    let freeze = FreezeDetector::new();
+    let state = if dry_run || cold.is_empty() {
+        None
+    } else {
+        Some(
+            freeze
+                .state(pid)
+                .with_context(|| format!("Failed to read the state of process {pid}"))?,
+        )
+    };
+    let swapped = match state {
+        None => 0,
+        Some(state) if state.is_frozen() => {
+            log::warn!("Process {pid} is {state}; deferring reclaim");
+            0
+        }
+        Some(_) => {
+            // Cold pages come in eviction order; keep it
+            let config = SwapConfig::default().with_exact_order(true);
+            let mut session = SwapSession::new(pid, config)
+                .with_context(|| format!("Failed to open swap session for process {pid}"))?
+                .with_freeze_detector(freeze);
+            session
+                .add_pages(&cold, ager.policy().huge_pages)
+                .with_context(|| "Failed to queue cold pages")?;
+            session
+                .flush()
+                .with_context(|| format!("Failed to swap pages in process {pid}"))?;
+            ager.forget(&cold);
+            cold.len()
+        }
+    };
+
+    if let (Some(mut recorder), Some(path)) = (recorder, trace) {
+        let end = Instant::now();
+        recorder.span(
+            "swap",
+            swap_start,
+            end,
+            json!({
+                "cold_pages": cold.len(),
+                "cold_bytes": cold_bytes,
+                "swapped_pages": swapped,
+                "dry_run": dry_run,
+            }),
+        );
+        let file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        recorder
+            .finish(end, BufWriter::new(file))
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("Wrote trace to {}", path.display());
+    }
+
+    println!("\n{}", output::heading("Autoswap report:"));
+    println!("  Duration:       {}", format_duration(start.elapsed()));
+    println!(
+        "  Last scan:      {} scanned, {} idle",
+        etmem_rs::format_bytes(last_stats.total_bytes),
+        etmem_rs::format_bytes(last_stats.idle_bytes)
+    );
+    println!("  Scan cost:      {}", limiter.stats());
+    println!(
+        "  Cold pages:     {} ({})",
+        cold.len(),
+        etmem_rs::format_bytes(cold_bytes)
+    );
+    if dry_run {
+        println!("  Swapped:        {}", paint("none (dry run)", Tone::Dim));
+    } else {
+        println!(
+            "  Swapped:        {}",
+            paint(
+                format!(
+                    "{swapped} pages ({})",
+                    etmem_rs::format_bytes(if swapped > 0 { cold_bytes } else { 0 })
+                ),
+                Tone::Good
+            )
+        );
+    }
+
+    Ok(())
+}
+
+/// Apply watermark and reclaim settings, then print the current status
+///
+/// The swapcache IOCTLs are system-wide; `pid` only selects whose
+/// `swap_pages` file is used to issue them.
+fn run_watermark(
+    pid: Option<u32>,
+    watermark: Option<etmem_rs::WatermarkConfig>,
+    reclaim: Option<bool>,
+) -> anyhow::Result<()> {
+    use etmem_rs::{SwapConfig, SwapSession, SwapcacheController};
+
+    let status = match pid {
+        Some(pid) => {
+            let mut session = SwapSession::new(pid, SwapConfig::default())
+                .with_context(|| format!("Failed to open swap session for process {pid}"))?;
+            if let Some(watermark) = watermark {
+                session
+                    .set_watermark(watermark)
+                    .with_context(|| "Failed to set watermarks")?;
+            }
+            match reclaim {
+                Some(true) => session
+                    .enable_proactive_reclaim()
+                    .with_context(|| "Failed to enable proactive reclaim")?,
+                Some(false) => session
+                    .disable_proactive_reclaim()
+                    .with_context(|| "Failed to disable proactive reclaim")?,
+                None => {}
+            }
+            session.watermark_status()
+        }
+        None => {
+            let mut controller = SwapcacheController::open()
+                .with_context(|| "Failed to open swapcache controller")?;
+            if let Some(watermark) = watermark {
+                controller
+                    .set_watermark(watermark)
+                    .with_context(|| "Failed to set watermarks")?;
+            }
+            match reclaim {
+                Some(true) => controller
+                    .enable()
+                    .with_context(|| "Failed to enable proactive reclaim")?,
+                Some(false) => controller
+                    .disable()
+                    .with_context(|| "Failed to disable proactive reclaim")?,
+                None => {}
+            }
+            controller.status()
+        }
+    }
+    .with_context(|| "Failed to read watermark status")?;
+
+    if let Some(watermark) = watermark {
+        println!(
+            "Watermarks set: low {}%, high {}%",
+            watermark.low_percent, watermark.high_percent
+        );
+    }
+    match reclaim {
+        Some(true) => println!("Proactive reclaim {}", paint("enabled", Tone::Good)),
+        Some(false) => println!("Proactive reclaim {}", paint("disabled", Tone::Warn)),
+        None => {}
+    }
+
+    println!("{}", output::heading("Swapcache watermark status:"));
+    println!(
+        "  Low watermark:   {}%{}",
+        status.watermark.low_percent,
+        if status.kernel_confirmed {
+            String::new()
+        } else {
+            paint(" (not confirmed by kernel)", Tone::Warn).to_string()
+        }
+    );
+    println!("  High watermark:  {}%", status.watermark.high_percent);
+    println!(
+        "  Swapcache:       {} ({:.1}% of RAM)",
+        etmem_rs::format_bytes(status.swapcache_kb * 1024),
+        status.occupancy_percent()
+    );
+    println!(
+        "  Reclaim thread:  {}",
+        enabled_str(status.reclaim_active, "running", "stopped")
+    );
+    if status.exceeds_high() {
+        println!(
+            "  {}",
+            paint("Swapcache is above the high watermark", Tone::Bad)
+        );
+    } else if status.exceeds_low() {
+        println!(
+            "  {}",
+            paint("Swapcache is above the low watermark", Tone::Warn)
+        );
+    }
+
+    Ok(())
+}
+
+/// Parse a duration such as `500ms`, `30s`, `2m` or `1h` (bare numbers are seconds)
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration: {value}"))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 3600)),
+        _ => Err(format!(
+            "invalid duration unit '{unit}' (use ms, s, m or h)"
+        )),
+    }
+}
+
+/// Parse a virtual address, with or without a `0x` prefix
+fn parse_address(value: &str) -> Result<u64, String> {
+    u64::from_str_radix(value.trim().trim_start_matches("0x"), 16)
+        .map_err(|_| format!("invalid address: {value}"))
+}
+
+/// Style an on/off state: `on` as healthy, `off` as needing attention
+fn enabled_str(value: bool, on: &'static str, off: &'static str) -> String {
+    if value {
+        paint(on, Tone::Good).to_string()
+    } else {
+        paint(off, Tone::Warn).to_string()
+    }
+}
+
+/// Format a duration for progress output
+fn format_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format!("{:.1}s", duration.as_secs_f64())
+    }
+}
+
+/// A single scan entry with stable field names for JSON/CSV output
+#[derive(Serialize, Debug)]
+struct ScanRecord {
+    /// Start address as a `0x`-prefixed hex string, or relative to its
+    /// mapping (e.g. `[heap]+0x1000`)
+    address: String,
+    /// Page type name (e.g. `pte_idle`)
+    page_type: &'static str,
+    /// Number of consecutive pages
+    count: u8,
+    /// Total size in bytes
+    size_bytes: u64,
+    /// Whether the pages are idle
+    idle: bool,
+    /// Whether the pages are huge pages
+    huge: bool,
+}
+
+impl ScanRecord {
+    /// Build a record, writing the address relative to its mapping if a
+    /// rebaser is given
+    fn new(page: &IdlePageInfo, rebaser: Option<&AddressRebaser>) -> Self {
+        Self {
+            address: format_address(page.address, rebaser),
+            page_type: page.page_type.as_str(),
+            count: page.count,
+            size_bytes: page.total_size(),
+            idle: page.is_idle(),
+            huge: page.page_type.is_huge(),
+        }
+    }
+}
+
+/// Format an address as `0x`-prefixed hex, or relative to its mapping if a
+/// rebaser is given
+fn format_address(address: u64, rebaser: Option<&AddressRebaser>) -> String {
+    match rebaser {
+        Some(rebaser) => rebaser.display(address),
+        None => format!("{address:#x}"),
+    }
+}
+
+/// Aggregate scan statistics for JSON output
+#[derive(Serialize, Debug)]
+struct ScanSummary {
+    total_pages: usize,
+    idle_pages: usize,
+    accessed_pages: usize,
+    huge_pages: usize,
+    total_bytes: u64,
+    idle_bytes: u64,
+    accessed_bytes: u64,
+    idle_ratio: f64,
+}
+
+impl From<&IdlePageStats> for ScanSummary {
+    fn from(stats: &IdlePageStats) -> Self {
+        Self {
+            total_pages: stats.total_pages,
+            idle_pages: stats.idle_pages,
+            accessed_pages: stats.accessed_pages,
+            huge_pages: stats.huge_pages,
+            total_bytes: stats.total_bytes,
+            idle_bytes: stats.idle_bytes,
+            accessed_bytes: stats.accessed_bytes,
+            idle_ratio: stats.idle_ratio(),
+        }
+    }
+}
+
+/// Complete scan result document for JSON output
+#[derive(Serialize, Debug)]
+struct ScanReport {
+    pid: u32,
+    pages: Vec<ScanRecord>,
+    summary: ScanSummary,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page_cache: Option<PageCacheSummary>,
+}
+
+/// Anonymous versus file-backed idleness of a scanned process
+#[derive(Serialize, Debug, Clone)]
+struct PageCacheSummary {
+    idle_anonymous_bytes: u64,
+    idle_file_bytes: u64,
+    idle_dax_bytes: u64,
+    #[serde(flatten)]
+    report: FileCacheReport,
+}
+
+impl PageCacheSummary {
+    /// Join unfiltered scan output with mappings and page cache residency
+    fn build(vma_map: &VmaMap, pages: &[IdlePageInfo]) -> Self {
+        let regions = RegionReport::build(vma_map, pages);
+        let (idle_anonymous_bytes, idle_file_bytes) = regions.idle_split();
+        Self {
+            idle_anonymous_bytes,
+            idle_file_bytes,
+            idle_dax_bytes: regions.idle_dax_bytes(),
+            report: FileCacheReport::build(vma_map, pages),
+        }
+    }
+}
+
+/// Address slices of a DAMON-style heatmap
+const DAMON_HEAT_BINS: usize = 64;
+
+/// Write scan results in the requested format
+fn write_scan_results(
+    out: &mut dyn Write,
+    pid: u32,
+    pages: &[IdlePageInfo],
+    page_cache: Option<&PageCacheSummary>,
+    rebaser: Option<&AddressRebaser>,
+    format: OutputFormat,
+    styled: bool,
+) -> anyhow::Result<()> {
+    let stats = IdlePageStats::from_pages(pages);
+
+    match format {
+        OutputFormat::Table => {
+            write_scan_table(out, pid, pages, &stats, rebaser, styled)?;
+            if let Some(page_cache) = page_cache {
+                write_page_cache_table(out, page_cache, styled)?;
+            }
+        }
+        OutputFormat::Json => {
+            let report = ScanReport {
+                pid,
+                pages: pages
+                    .iter()
+                    .map(|page| ScanRecord::new(page, rebaser))
+                    .collect(),
+                summary: ScanSummary::from(&stats),
+                page_cache: page_cache.cloned(),
+            };
+            serde_json::to_writer_pretty(&mut *out, &report)?;
+            writeln!(out)?;
+        }
+        OutputFormat::Csv => {
+            writeln!(out, "address,page_type,count,size_bytes,idle,huge")?;
+            for record in pages.iter().map(|page| ScanRecord::new(page, rebaser)) {
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{}",
+                    record.address,
+                    record.page_type,
+                    record.count,
+                    record.size_bytes,
+                    record.idle,
+                    record.huge
+                )?;
+            }
+        }
+        OutputFormat::Damon => {
+            write!(out, "{}", DamonReport::from_pages(pid, pages))?;
+        }
+        OutputFormat::DamonHeats => {
+            for (address, heat) in DamonReport::from_pages(pid, pages).heats(DAMON_HEAT_BINS) {
+                writeln!(out, "0 {address} {heat:.3}")?;
             }
         }
     }
@@ -290,8 +1967,774 @@ fn handle_etmem_command(action: EtmemCommands) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Scan a process and compare the result with DAMON's regions for it
+fn run_damon_check(pid: u32, min_agreement: f64) -> anyhow::Result<()> {
+    use etmem_rs::{DamonComparison, IdlePageScanner, ScanConfig};
+
+    let damon = DamonReport::from_sysfs(pid)
+        .context("Failed to read DAMON results")?
+        .with_context(|| {
+            format!(
+                "No DAMON results for process {pid}: it needs a context monitoring it alone \
+                 and a scheme with tried regions (write update_schemes_tried_regions to the \
+                 kdamond state file)"
+            )
+        })?;
+
+    let spinner = output::spinner(format!("Scanning process {pid}"));
+    let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+        .with_context(|| format!("Failed to scan process {pid}"))?;
+    spinner.finish_and_clear();
+
+    let etmem = DamonReport::from_pages(pid, &pages);
+    let comparison = DamonComparison::between(&etmem, &damon);
+    println!(
+        "etmem: {} in {} regions, {} accessed",
+        etmem_rs::format_bytes(etmem.total_bytes()),
+        etmem.regions.len(),
+        etmem_rs::format_bytes(etmem.accessed_bytes())
+    );
+    println!(
+        "DAMON: {} in {} regions, {} accessed",
+        etmem_rs::format_bytes(damon.total_bytes()),
+        damon.regions.len(),
+        etmem_rs::format_bytes(damon.accessed_bytes())
+    );
+    println!("{comparison}");
+
+    if !comparison.is_equivalent(min_agreement) {
+        anyhow::bail!(
+            "etmem and DAMON agree on {:.1}% of process {pid}, below {:.1}%",
+            comparison.agreement() * 100.0,
+            min_agreement * 100.0
+        );
+    }
+    Ok(())
+}
+
+/// Write scan results as a human-readable table, styled for a terminal
+fn write_scan_table(
+    out: &mut dyn Write,
+    pid: u32,
+    pages: &[IdlePageInfo],
+    stats: &IdlePageStats,
+    rebaser: Option<&AddressRebaser>,
+    styled: bool,
+) -> io::Result<()> {
+    writeln!(
+        out,
+        "\nFound {} memory regions in process {pid}:",
+        pages.len()
+    )?;
+    let mut table = Table::new([
+        Column::right("Address").width(16),
+        Column::left("Type").width(15),
+        Column::left("Count").width(10),
+        Column::left("Size"),
+    ])
+    .styled(styled);
+    for page in pages {
+        let tone = if page.is_idle() {
+            Tone::Warn
+        } else if page.page_type.is_accessed() {
+            Tone::Good
+        } else {
+            Tone::Dim
+        };
+        let page_type = if styled {
+            paint(page.page_type.as_str(), tone).to_string()
+        } else {
+            page.page_type.as_str().to_string()
+        };
+        let address = match rebaser {
+            Some(rebaser) => rebaser.display(page.address),
+            None => format!("{:x}", page.address),
+        };
+        table.row([
+            address,
+            page_type,
+            page.count.to_string(),
+            etmem_rs::format_bytes(page.total_size()),
+        ]);
+    }
+    table.write(out)?;
+
+    writeln!(
+        out,
+        "Total: {} bytes ({})",
+        stats.total_bytes,
+        etmem_rs::format_bytes(stats.total_bytes)
+    )?;
+
+    if styled {
+        writeln!(out, "\n{}", output::heading("Statistics:"))?;
+    } else {
+        writeln!(out, "\nStatistics:")?;
+    }
+    writeln!(
+        out,
+        "  Idle pages:     {} ({})",
+        stats.idle_pages,
+        etmem_rs::format_bytes(stats.idle_bytes)
+    )?;
+    writeln!(
+        out,
+        "  Accessed pages: {} ({})",
+        stats.accessed_pages,
+        etmem_rs::format_bytes(stats.accessed_bytes)
+    )?;
+    writeln!(out, "  Huge pages:     {}", stats.huge_pages)?;
+    writeln!(out, "  Idle ratio:     {:.1}%", stats.idle_ratio() * 100.0)?;
+
+    Ok(())
+}
+
+/// Write the anonymous/file-backed split and per-file page cache idleness
+fn write_page_cache_table(
+    out: &mut dyn Write,
+    page_cache: &PageCacheSummary,
+    styled: bool,
+) -> io::Result<()> {
+    if styled {
+        writeln!(out, "\n{}", output::heading("Page cache:"))?;
+    } else {
+        writeln!(out, "\nPage cache:")?;
+    }
+    writeln!(
+        out,
+        "  Idle anonymous:   {}",
+        etmem_rs::format_bytes(page_cache.idle_anonymous_bytes)
+    )?;
+    writeln!(
+        out,
+        "  Idle file-backed: {}",
+        etmem_rs::format_bytes(page_cache.idle_file_bytes)
+    )?;
+    if page_cache.idle_dax_bytes > 0 {
+        writeln!(
+            out,
+            "  Idle DAX:         {} (never swapped)",
+            etmem_rs::format_bytes(page_cache.idle_dax_bytes)
+        )?;
+    }
+
+    let report = &page_cache.report;
+    if report.files.is_empty() && report.skipped.is_empty() {
+        return Ok(());
+    }
+    writeln!(out)?;
+    let mut table = Table::new([
+        Column::left("File"),
+        Column::right("Cached"),
+        Column::right("Idle"),
+        Column::right("Unreferenced"),
+        Column::right("Recl%"),
+    ])
+    .styled(styled);
+    for file in &report.files {
+        table.row([
+            file.path.clone(),
+            etmem_rs::format_bytes(file.resident_bytes),
+            etmem_rs::format_bytes(file.idle_bytes),
+            etmem_rs::format_bytes(file.unreferenced_bytes),
+            format!("{:.1}%", file.reclaimable_ratio() * 100.0),
+        ]);
+    }
+    table.write(out)?;
+    for path in &report.skipped {
+        writeln!(out, "  {path}: residency unavailable")?;
+    }
+    writeln!(
+        out,
+        "Reclaimable page cache: {} of {}",
+        etmem_rs::format_bytes(report.total_reclaimable_bytes()),
+        etmem_rs::format_bytes(report.total_resident_bytes())
+    )?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use etmem_rs::ProcIdlePageType;
+
+    fn sample_pages() -> Vec<IdlePageInfo> {
+        vec![
+            IdlePageInfo::new(0x7f0000000000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x7f0000200000, ProcIdlePageType::PmdAccessed, 1),
+        ]
+    }
+
+    #[test]
+    fn test_cli_definition() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_probe_args() {
+        let cli = Cli::try_parse_from(["memlink", "probe", "--json"]).unwrap();
+        assert!(matches!(cli.command, Commands::Probe { json: true }));
+        let cli = Cli::try_parse_from(["memlink", "probe"]).unwrap();
+        assert!(matches!(cli.command, Commands::Probe { json: false }));
+    }
+
+    #[test]
+    fn test_desc_show_args() {
+        let cli = Cli::try_parse_from(["memlink", "desc", "show", "desc.json", "--json"]).unwrap();
+        match cli.command {
+            Commands::Desc {
+                action: DescCommands::Show { file, json },
+            } => {
+                assert_eq!(file, PathBuf::from("desc.json"));
+                assert!(json);
+            }
+            other => panic!("unexpected command: {other:?}"),
+        }
+        assert!(Cli::try_parse_from(["memlink", "desc", "show"]).is_err());
+    }
+
+    #[test]
+    fn test_container_args() {
+        let cli = Cli::try_parse_from([
+            "memlink",
+            "etmem",
+            "scan",
+            "--pid",
+            "1",
+            "--container",
+            "4242",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Etmem {
+                action: EtmemCommands::Scan {
+                    pid: Some(1),
+                    container: Some(4242),
+                    ..
+                },
+            }
+        ));
+        assert!(Cli::try_parse_from(["memlink", "etmem", "swap", "--container", "4242"]).is_err());
+
+        let own = std::process::id();
+        assert_eq!(resolve_target(None, None).unwrap(), own);
+        assert_eq!(resolve_target(Some(42), None).unwrap(), 42);
+        // Unless memlink runs in a nested PID namespace, its PID there is the
+        // one procfs shows
+        if !std::fs::read_to_string("/proc/self/status")
+            .unwrap()
+            .lines()
+            .any(|line| line.starts_with("NSpid:") && line.split_whitespace().count() > 2)
+        {
+            assert_eq!(resolve_target(Some(own), Some(own)).unwrap(), own);
+        }
+    }
+
+    #[test]
+    fn test_swap_from_file_args() {
+        let cli = Cli::try_parse_from([
+            "memlink",
+            "etmem",
+            "swap",
+            "--pid",
+            "42",
+            "--from-file",
+            "-",
+        ]);
+        assert!(matches!(
+            cli.unwrap().command,
+            Commands::Etmem {
+                action: EtmemCommands::Swap {
+                    pid: Some(42),
+                    ref addrs,
+                    from_file: Some(ref path),
+                    ..
+                }
+            } if addrs.is_empty() && path.as_os_str() == "-"
+        ));
+    }
+
+    #[test]
+    fn test_shell_args() {
+        let cli = Cli::try_parse_from(["memlink", "shell", "--pid", "42"]).unwrap();
+        assert!(matches!(cli.command, Commands::Shell { pid: Some(42) }));
+        let cli = Cli::try_parse_from(["memlink", "shell"]).unwrap();
+        assert!(matches!(cli.command, Commands::Shell { pid: None }));
+    }
+
+    #[test]
+    fn test_watermark_args() {
+        let cli = Cli::try_parse_from([
+            "memlink",
+            "etmem",
+            "watermark",
+            "--low",
+            "30",
+            "--high",
+            "70",
+        ]);
+        assert!(matches!(
+            cli.unwrap().command,
+            Commands::Etmem {
+                action: EtmemCommands::Watermark {
+                    low: Some(30),
+                    high: Some(70),
+                    ..
+                }
+            }
+        ));
+        assert!(Cli::try_parse_from(["memlink", "etmem", "watermark", "--low", "30"]).is_err());
+        assert!(
+            Cli::try_parse_from([
+                "memlink",
+                "etmem",
+                "watermark",
+                "--low",
+                "30",
+                "--high",
+                "170"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_config_args() {
+        let cli = Cli::try_parse_from([
+            "memlink",
+            "etmem",
+            "config",
+            "--apply",
+            "etmem.json",
+            "--dry-run",
+        ]);
+        assert!(matches!(
+            cli.unwrap().command,
+            Commands::Etmem {
+                action: EtmemCommands::Config {
+                    apply: Some(_),
+                    rollback: false,
+                    dry_run: true,
+                    ..
+                }
+            }
+        ));
+        assert!(
+            Cli::try_parse_from([
+                "memlink",
+                "etmem",
+                "config",
+                "--apply",
+                "etmem.json",
+                "--rollback"
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from(["memlink", "etmem", "config", "--enable", "--rollback"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_autoswap_order_arg() {
+        let parse = |args: &[&str]| {
+            let base = ["memlink", "etmem", "autoswap", "--pid", "1"];
+            match Cli::try_parse_from(base.iter().chain(args)).map(|cli| cli.command) {
+                Ok(Commands::Etmem {
+                    action: EtmemCommands::Autoswap { order, .. },
+                }) => Some(order),
+                _ => None,
+            }
+        };
+        assert_eq!(parse(&[]), Some(EvictionStrategy::Oldest));
+        assert_eq!(
+            parse(&["--order", "largest-run"]),
+            Some(EvictionStrategy::LargestRun)
+        );
+        assert_eq!(parse(&["--order", "random"]), None);
+
+        let ager = EvictionStrategy::Address.apply(etmem_rs::PageAger::default());
+        assert_eq!(ager.eviction_order().name(), "address-order");
+    }
+
+    #[test]
+    fn test_autoswap_max_anomalies_arg() {
+        let parse = |args: &[&str]| {
+            let base = ["memlink", "etmem", "autoswap", "--pid", "1"];
+            match Cli::try_parse_from(base.iter().chain(args)).map(|cli| cli.command) {
+                Ok(Commands::Etmem {
+                    action: EtmemCommands::Autoswap { max_anomalies, .. },
+                }) => Some(max_anomalies),
+                _ => None,
+            }
+        };
+        assert_eq!(parse(&[]), Some(0));
+        assert_eq!(parse(&["--max-anomalies", "5"]), Some(5));
+        assert_eq!(parse(&["--max-anomalies", "-1"]), None);
+    }
+
+    #[test]
+    fn test_scan_time_limit_args() {
+        let cli = Cli::try_parse_from([
+            "memlink",
+            "etmem",
+            "scan",
+            "--max-duration",
+            "200ms",
+            "--resume",
+            "0x7f0000013000",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Etmem {
+                action:
+                    EtmemCommands::Scan {
+                        max_duration,
+                        resume,
+                        ..
+                    },
+            } => {
+                assert_eq!(max_duration, Some(Duration::from_millis(200)));
+                assert_eq!(resume, Some(0x7f0000013000));
+            }
+            _ => panic!("expected etmem scan"),
+        }
+        assert!(Cli::try_parse_from(["memlink", "etmem", "scan", "--resume", "xyz"]).is_err());
+
+        let cli =
+            Cli::try_parse_from(["memlink", "etmem", "scan", "--addresses", "relative"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Etmem {
+                action: EtmemCommands::Scan {
+                    addresses: AddressFormat::Relative,
+                    ..
+                },
+            }
+        ));
+    }
+
+    #[test]
+    fn test_report_args() {
+        let cli = Cli::try_parse_from([
+            "memlink",
+            "etmem",
+            "report",
+            "--pid",
+            "42,43",
+            "--push",
+            "http://collector:9000/ingest",
+            "--interval",
+            "5m",
+        ])
+        .unwrap();
+        match cli.command {
+            Commands::Etmem {
+                action:
+                    EtmemCommands::Report {
+                        pid,
+                        push,
+                        interval,
+                        ..
+                    },
+            } => {
+                assert_eq!(pid, vec![42, 43]);
+                assert_eq!(push.as_deref(), Some("http://collector:9000/ingest"));
+                assert_eq!(interval, Some(Duration::from_secs(300)));
+            }
+            _ => panic!("expected etmem report"),
+        }
+        assert!(Cli::try_parse_from(["memlink", "etmem", "report"]).is_err());
+        assert!(
+            Cli::try_parse_from(["memlink", "etmem", "report", "-p", "1", "--key-file", "k"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cleanup_args() {
+        let cli = Cli::try_parse_from(["memlink", "cleanup", "--dry-run"]).unwrap();
+        assert!(matches!(cli.command, Commands::Cleanup { dry_run: true }));
+        let cli = Cli::try_parse_from(["memlink", "cleanup"]).unwrap();
+        assert!(matches!(cli.command, Commands::Cleanup { dry_run: false }));
+    }
+
+    #[test]
+    fn test_output_args() {
+        let cli = Cli::try_parse_from(["memlink", "cleanup", "-vv", "--color", "never"]).unwrap();
+        assert_eq!(cli.color, ColorChoice::Never);
+        assert_eq!(
+            Verbosity::from_flags(cli.quiet, cli.verbose),
+            Verbosity::Trace
+        );
+        let cli = Cli::try_parse_from(["memlink", "-q", "ls"]).unwrap();
+        assert_eq!(cli.color, ColorChoice::Auto);
+        assert_eq!(
+            Verbosity::from_flags(cli.quiet, cli.verbose),
+            Verbosity::Quiet
+        );
+        assert!(Cli::try_parse_from(["memlink", "ls", "-q", "-v"]).is_err());
+        assert!(Cli::try_parse_from(["memlink", "ls", "--color", "sometimes"]).is_err());
+    }
+
+    #[test]
+    fn test_ls_args() {
+        let cli = Cli::try_parse_from(["memlink", "ls", "--json", "--refresh"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Ls {
+                json: true,
+                caps: false,
+                refresh: true,
+            }
+        ));
+        assert!(Cli::try_parse_from(["memlink", "ls", "--caps", "--refresh"]).is_err());
+    }
+
+    #[test]
+    fn test_mar_perf_args() {
+        let cli = Cli::try_parse_from(["memlink", "mar-perf", "--all", "--force"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::MarPerf {
+                all: true,
+                force: true,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_lease_args() {
+        let cli = Cli::try_parse_from(["memlink", "export", "--lease", "5m"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Export {
+                lease: Some(ttl),
+                grace,
+                ..
+            } if ttl == Duration::from_secs(300) && grace == Duration::from_secs(60)
+        ));
+        assert!(Cli::try_parse_from(["memlink", "export", "--grace", "10s"]).is_err());
+
+        let cli =
+            Cli::try_parse_from(["memlink", "renew", "node1", "--memid", "7", "--keep"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Renew {
+                memid: 7,
+                keep: true,
+                ..
+            }
+        ));
+        let cli = Cli::try_parse_from([
+            "memlink",
+            "renew",
+            "node1",
+            "--memid",
+            "7",
+            "--key-file",
+            "/etc/memlink.key",
+        ])
+        .unwrap();
+        assert!(matches!(
+            cli.command,
+            Commands::Renew {
+                key_file: Some(_),
+                keep: false,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("5").unwrap(), Duration::from_secs(5));
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("10d").is_err());
+    }
+
+    #[test]
+    fn test_scan_output_csv() {
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            None,
+            None,
+            OutputFormat::Csv,
+            false,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "address,page_type,count,size_bytes,idle,huge");
+        assert_eq!(lines[1], "0x7f0000000000,pte_idle,2,8192,true,false");
+        assert_eq!(lines[2], "0x7f0000200000,pmd_accessed,1,2097152,false,true");
+    }
+
+    #[test]
+    fn test_scan_output_damon() {
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            None,
+            None,
+            OutputFormat::Damon,
+            false,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[0], "target_id: 42");
+        assert_eq!(lines[1], "nr_regions: 2");
+        assert!(lines[2].starts_with("7f0000000000-7f0000002000("));
+        assert!(lines[3].ends_with(":   1   1"));
+
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            None,
+            None,
+            OutputFormat::DamonHeats,
+            false,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(text.lines().count(), DAMON_HEAT_BINS);
+        assert!(text.starts_with("0 139637976727552 0.000\n"));
+    }
+
+    #[test]
+    fn test_scan_output_json() {
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            None,
+            None,
+            OutputFormat::Json,
+            false,
+        )
+        .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value["pid"], 42);
+        assert_eq!(value["pages"][0]["address"], "0x7f0000000000");
+        assert_eq!(value["pages"][0]["page_type"], "pte_idle");
+        assert_eq!(value["summary"]["idle_bytes"], 8192);
+        assert!(value.get("page_cache").is_none());
+    }
+
+    #[test]
+    fn test_scan_output_relative() {
+        let path = std::env::temp_dir().join(format!("memlink-maps-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "7f0000000000-7f0000100000 rw-p 00000000 00:00 0 [heap]\n\
+             7f0000100000-7f0000300000 r-xp 00000000 08:01 200 /usr/lib/libfoo.so\n",
+        )
+        .unwrap();
+        let rebaser = AddressRebaser::new(&VmaMap::from_file(&path, 42).unwrap());
+        std::fs::remove_file(&path).unwrap();
+
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            None,
+            Some(&rebaser),
+            OutputFormat::Csv,
+            false,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines[1], "[heap]+0x0,pte_idle,2,8192,true,false");
+        assert_eq!(
+            lines[2],
+            "/usr/lib/libfoo.so+0x100000,pmd_accessed,1,2097152,false,true"
+        );
+
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            None,
+            Some(&rebaser),
+            OutputFormat::Json,
+            false,
+        )
+        .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value["pages"][0]["address"], "[heap]+0x0");
+    }
+
+    #[test]
+    fn test_scan_output_page_cache() {
+        let page_cache = PageCacheSummary {
+            idle_anonymous_bytes: 8192,
+            idle_file_bytes: 4096,
+            idle_dax_bytes: 0,
+            report: FileCacheReport {
+                pid: 42,
+                files: vec![etmem_rs::FileCacheStats {
+                    path: "/usr/lib/libfoo.so".to_string(),
+                    device: "08:01".to_string(),
+                    inode: 200,
+                    mapped_bytes: 16384,
+                    resident_bytes: 12288,
+                    idle_bytes: 4096,
+                    unreferenced_bytes: 4096,
+                }],
+                skipped: Vec::new(),
+            },
+        };
+
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            Some(&page_cache),
+            None,
+            OutputFormat::Table,
+            false,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(text.contains("Idle file-backed: 4.00 KB"));
+        assert!(!text.contains("Idle DAX"));
+        assert!(text.contains("/usr/lib/libfoo.so"));
+
+        let mut buf = Vec::new();
+        write_scan_results(
+            &mut buf,
+            42,
+            &sample_pages(),
+            Some(&page_cache),
+            None,
+            OutputFormat::Json,
+            false,
+        )
+        .unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(value["page_cache"]["idle_file_bytes"], 4096);
+        assert_eq!(value["page_cache"]["files"][0]["inode"], 200);
+    }
+
     #[test]
     fn test_etmem_availability() {
         // Just check that the function works
@@ -0,0 +1,807 @@
+//! Descriptor exchange over TCP for `memlink serve` / `memlink fetch`
+//!
+//! A node running `memlink serve` publishes the memory descriptors of its
+//! exports (the `memdesc_<memid>.json` files written by `memlink export`).
+//! A remote node uses `memlink fetch <host>` to list them and import one.
+//!
+//! The protocol is line-based: each request is a single text line
+//! (`LIST`, `GET <memid>` or `RENEW <memid>`) and each response is a single
+//! JSON line. `RENEW` extends the lease of an export (see
+//! [`lease`](crate::lease)); importers send it periodically to keep leased
+//! memory exported.
+//!
+//! Handing out a descriptor that names a destination (`deid`) charges the
+//! export to that peer in the registry; `GET` is answered with an error
+//! instead when the peer's quota would be exceeded.
+//!
+//! When both sides are given a shared key (`--key-file`), descriptors are
+//! sent as sealed envelopes signed with HMAC-SHA256 and the client rejects
+//! any descriptor whose signature does not verify. A keyed server also
+//! requires `RENEW` to carry its issue time and the HMAC of
+//! `RENEW <memid> <issued_at>` under the key (`RENEW <memid> <issued_at>
+//! <mac>`), so only key holders can keep an export alive. Renewals issued
+//! more than [`SEAL_MAX_AGE`] away from the server clock, or not after the
+//! last renewal accepted for the export, are refused as replays.
+//! Without a key the server only listens on loopback addresses.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use log::{debug, info, warn};
+use obmm_rs::sign::{SEAL_MAX_AGE, Signature};
+use obmm_rs::{Lease, MemId, ObmmMemDesc, Registry, SealedDesc, UbPrivData};
+use serde::{Deserialize, Serialize};
+
+/// Default TCP port for descriptor exchange
+pub(crate) const DEFAULT_PORT: u16 = 7878;
+
+/// Idle timeout for client connections
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest request line accepted, including the newline
+const MAX_REQUEST_LINE: usize = 256;
+
+/// An exported descriptor published by a server
+#[derive(Serialize, Deserialize, Debug)]
+pub(crate) struct DescEntry {
+    /// Memory ID on the exporting node
+    pub mem_id: MemId,
+    /// Memory descriptor, sealed if the server has a key
+    #[serde(flatten)]
+    body: DescBody,
+}
+
+/// Descriptor as sent on the wire
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+enum DescBody {
+    /// Descriptor signed with the server key
+    Sealed { sealed: SealedDesc },
+    /// Unsigned descriptor
+    Plain {
+        #[serde(with = "obmm_rs::types::versioned")]
+        desc: ObmmMemDesc<UbPrivData>,
+    },
+}
+
+impl DescEntry {
+    /// Create an entry, sealing the descriptor if a key is given
+    pub(crate) fn new(mem_id: MemId, desc: ObmmMemDesc<UbPrivData>, key: Option<&[u8]>) -> Self {
+        let body = match key {
+            Some(key) => DescBody::Sealed {
+                sealed: desc.seal(key, mem_id),
+            },
+            None => DescBody::Plain { desc },
+        };
+        Self { mem_id, body }
+    }
+
+    /// Get the descriptor without verifying it
+    pub(crate) fn desc(&self) -> &ObmmMemDesc<UbPrivData> {
+        match &self.body {
+            DescBody::Sealed { sealed } => &sealed.desc,
+            DescBody::Plain { desc } => desc,
+        }
+    }
+
+    /// Check whether the descriptor is signed
+    pub(crate) fn is_sealed(&self) -> bool {
+        matches!(self.body, DescBody::Sealed { .. })
+    }
+
+    /// Take the descriptor, verifying its signature if a key is given
+    ///
+    /// With a key, unsigned descriptors, bad signatures, descriptors sealed
+    /// for another memory ID and descriptors sealed more than
+    /// [`SEAL_MAX_AGE`] ago are rejected. Without one, a signed descriptor
+    /// is accepted unverified.
+    pub(crate) fn open(self, key: Option<&[u8]>) -> anyhow::Result<ObmmMemDesc<UbPrivData>> {
+        match (self.body, key) {
+            (DescBody::Sealed { sealed }, Some(key)) => sealed
+                .open(key, self.mem_id, SEAL_MAX_AGE)
+                .with_context(|| {
+                    format!("Descriptor of memid {} failed verification", self.mem_id)
+                }),
+            (DescBody::Sealed { sealed }, None) => {
+                warn!(
+                    "Descriptor of memid {} is signed but no key was given",
+                    self.mem_id
+                );
+                Ok(sealed.desc)
+            }
+            (DescBody::Plain { .. }, Some(_)) => {
+                anyhow::bail!("Descriptor of memid {} is not signed", self.mem_id)
+            }
+            (DescBody::Plain { desc }, None) => Ok(desc),
+        }
+    }
+}
+
+/// Read a shared signing key from a file
+///
+/// Trailing whitespace is ignored so that keys can be written with `echo`.
+pub(crate) fn read_key(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut key = std::fs::read(path)
+        .with_context(|| format!("Failed to read key file {}", path.display()))?;
+    while key.last().is_some_and(u8::is_ascii_whitespace) {
+        key.pop();
+    }
+    if key.is_empty() {
+        anyhow::bail!("Key file {} is empty", path.display());
+    }
+    Ok(key)
+}
+
+/// Client request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Request {
+    /// List all published descriptors
+    List,
+    /// Get the descriptor of one memory ID
+    Get(MemId),
+    /// Renew the lease of an export, authenticated if the client has a key
+    Renew(MemId, Option<RenewAuth>),
+}
+
+/// Authentication of a `RENEW` request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct RenewAuth {
+    /// Issue time in seconds since the Unix epoch
+    issued_at: u64,
+    /// MAC of [`renew_message`] for the memory ID and `issued_at`
+    mac: Signature,
+}
+
+impl RenewAuth {
+    /// Authenticate a renewal of `mem_id` issued now
+    fn new(key: &[u8], mem_id: MemId) -> Self {
+        let issued_at = unix_now();
+        Self {
+            issued_at,
+            mac: Signature::compute(key, renew_message(mem_id, issued_at).as_bytes()),
+        }
+    }
+}
+
+impl Request {
+    /// Parse a request line
+    fn parse(line: &str) -> Result<Self, String> {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(cmd), None, None) if cmd.eq_ignore_ascii_case("LIST") => Ok(Self::List),
+            (Some(cmd), Some(id), None) if cmd.eq_ignore_ascii_case("GET") => id
+                .parse()
+                .map(Self::Get)
+                .map_err(|_| format!("invalid memid: {id}")),
+            (Some(cmd), Some(id), issued_at) if cmd.eq_ignore_ascii_case("RENEW") => {
+                let mem_id = id.parse().map_err(|_| format!("invalid memid: {id}"))?;
+                let auth = match (issued_at, parts.next(), parts.next()) {
+                    (None, None, None) => None,
+                    (Some(issued_at), Some(mac), None) => Some(RenewAuth {
+                        issued_at: issued_at
+                            .parse()
+                            .map_err(|_| format!("invalid issue time: {issued_at}"))?,
+                        mac: Signature::from_hex(mac).map_err(|e| e.to_string())?,
+                    }),
+                    _ => return Err(format!("unknown request: {}", line.trim())),
+                };
+                Ok(Self::Renew(mem_id, auth))
+            }
+            _ => Err(format!("unknown request: {}", line.trim())),
+        }
+    }
+
+    /// Encode the request as a line
+    fn to_line(self) -> String {
+        match self {
+            Self::List => "LIST\n".to_string(),
+            Self::Get(mem_id) => format!("GET {mem_id}\n"),
+            Self::Renew(mem_id, None) => format!("RENEW {mem_id}\n"),
+            Self::Renew(mem_id, Some(auth)) => {
+                format!("RENEW {mem_id} {} {}\n", auth.issued_at, auth.mac.to_hex())
+            }
+        }
+    }
+}
+
+/// Server response
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum Response {
+    /// All published descriptors
+    List { entries: Vec<DescEntry> },
+    /// A single descriptor
+    Desc { entry: DescEntry },
+    /// A renewed lease
+    Lease { mem_id: MemId, lease: Lease },
+    /// Request failed
+    Error { message: String },
+}
+
+/// Append the default port if `host` has none
+pub(crate) fn with_default_port(host: &str) -> String {
+    if host.parse::<SocketAddr>().is_ok() {
+        return host.to_string();
+    }
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return SocketAddr::new(ip, DEFAULT_PORT).to_string();
+    }
+    match host.rsplit_once(':') {
+        Some((_, port)) if port.parse::<u16>().is_ok() => host.to_string(),
+        _ => format!("{host}:{DEFAULT_PORT}"),
+    }
+}
+
+/// Load all descriptor files from a directory
+///
+/// Files that are not named `memdesc_<memid>.json` or fail to parse are
+/// skipped. Descriptors are sealed with `key` if given. Entries are sorted
+/// by memory ID.
+pub(crate) fn load_descriptors(dir: &Path, key: Option<&[u8]>) -> anyhow::Result<Vec<DescEntry>> {
+    let mut entries = Vec::new();
+
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(entries),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+
+    for file in read_dir.flatten() {
+        let name = file.file_name();
+        let Some(mem_id) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix("memdesc_"))
+            .and_then(|n| n.strip_suffix(".json"))
+            .and_then(|id| id.parse::<MemId>().ok())
+        else {
+            continue;
+        };
+
+        match ObmmMemDesc::<UbPrivData>::from_json_path(file.path()) {
+            Ok(desc) => entries.push(DescEntry::new(mem_id, desc, key)),
+            Err(e) => warn!("Skipping {}: {e}", file.path().display()),
+        }
+    }
+
+    entries.sort_by_key(|e| e.mem_id);
+    Ok(entries)
+}
+
+/// Serve descriptors from `dir` on `listen` until the process exits
+///
+/// Descriptors are sealed with `key` if given. Leases are renewed in
+/// `registry`, whose expired exports are reclaimed in the background.
+pub(crate) fn serve(
+    listen: &str,
+    dir: PathBuf,
+    registry: Registry,
+    workers: usize,
+    key: Option<Vec<u8>>,
+) -> anyhow::Result<()> {
+    check_listen(listen, key.is_some())?;
+    let listener = TcpListener::bind(with_default_port(listen))
+        .with_context(|| format!("Failed to listen on {listen}"))?;
+    info!(
+        "Serving descriptors from {} on {}",
+        dir.display(),
+        listener.local_addr()?
+    );
+    crate::lease::spawn_reaper(registry.clone())?;
+    serve_listener(listener, dir, registry, workers, key.map(Arc::from))
+}
+
+/// Refuse to publish unsigned descriptors beyond the local node
+///
+/// Without a key, every address `listen` resolves to must be a loopback
+/// address.
+fn check_listen(listen: &str, keyed: bool) -> anyhow::Result<()> {
+    if keyed {
+        return Ok(());
+    }
+    let addr = with_default_port(listen);
+    let exposed = addr
+        .to_socket_addrs()
+        .with_context(|| format!("Failed to resolve {addr}"))?
+        .find(|socket| !socket.ip().is_loopback());
+    if let Some(socket) = exposed {
+        anyhow::bail!(
+            "Refusing to serve unsigned descriptors on {socket}; \
+             pass --key-file or listen on a loopback address"
+        );
+    }
+    Ok(())
+}
+
+/// Accept connections on a bound listener, handling each on a worker thread
+fn serve_listener(
+    listener: TcpListener,
+    dir: PathBuf,
+    registry: Registry,
+    workers: usize,
+    key: Option<Arc<[u8]>>,
+) -> anyhow::Result<()> {
+    let pool = threadpool::ThreadPool::new(workers.max(1))?;
+    let renewals = Arc::new(Renewals::default());
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept connection: {e}");
+                continue;
+            }
+        };
+
+        let dir = dir.clone();
+        let registry = registry.clone();
+        let key = key.clone();
+        let renewals = Arc::clone(&renewals);
+        pool.execute(move || {
+            let peer = stream
+                .peer_addr()
+                .map(|a| a.to_string())
+                .unwrap_or_default();
+            if let Err(e) = handle_client(stream, &dir, &registry, key.as_deref(), &renewals) {
+                debug!("Connection from {peer} ended: {e}");
+            }
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Answer requests on one connection until the client disconnects
+fn handle_client(
+    stream: TcpStream,
+    dir: &Path,
+    registry: &Registry,
+    key: Option<&[u8]>,
+    renewals: &Renewals,
+) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = (&mut reader)
+            .take(MAX_REQUEST_LINE as u64)
+            .read_line(&mut line)?;
+        if read == 0 {
+            break;
+        }
+        // A request that does not fit is rejected and the connection closed,
+        // as the rest of the line cannot be told apart from the next request
+        if !line.ends_with('\n') && read == MAX_REQUEST_LINE {
+            let message = format!("request longer than {MAX_REQUEST_LINE} bytes");
+            send(&mut writer, &Response::Error { message })?;
+            anyhow::bail!("Request line too long");
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match Request::parse(&line) {
+            Ok(request) => respond(request, dir, registry, key, renewals),
+            Err(message) => Response::Error { message },
+        };
+        send(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+/// Write a response line
+fn send(writer: &mut TcpStream, response: &Response) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *writer, response)?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Build the response to a request
+fn respond(
+    request: Request,
+    dir: &Path,
+    registry: &Registry,
+    key: Option<&[u8]>,
+    renewals: &Renewals,
+) -> Response {
+    let mem_id = match request {
+        Request::Renew(mem_id, auth) => return renew(registry, mem_id, auth, key, renewals),
+        Request::Get(mem_id) => Some(mem_id),
+        Request::List => None,
+    };
+
+    let entries = match load_descriptors(dir, key) {
+        Ok(entries) => entries,
+        Err(e) => {
+            return Response::Error {
+                message: e.to_string(),
+            };
+        }
+    };
+
+    match mem_id {
+        None => Response::List { entries },
+        Some(mem_id) => match entries.into_iter().find(|e| e.mem_id == mem_id) {
+            Some(entry) => match charge_peer(registry, &entry) {
+                Ok(()) => Response::Desc { entry },
+                Err(e) => Response::Error {
+                    message: e.to_string(),
+                },
+            },
+            None => Response::Error {
+                message: format!("memid {mem_id} is not exported"),
+            },
+        },
+    }
+}
+
+/// Charge a descriptor handed out to the peer it names
+///
+/// Descriptors importable by any node are not charged.
+fn charge_peer(registry: &Registry, entry: &DescEntry) -> obmm_rs::Result<()> {
+    let desc = entry.desc();
+    if desc.deid == [0; 16] {
+        return Ok(());
+    }
+    registry.grant_to_peer(entry.mem_id, desc.deid, desc.length)
+}
+
+/// Bytes authenticated by the MAC of a `RENEW` request
+fn renew_message(mem_id: MemId, issued_at: u64) -> String {
+    format!("RENEW {mem_id} {issued_at}")
+}
+
+/// Issue times of the last authenticated renewal of each export
+///
+/// Shared by all connections of a server, so a renewal seen on the wire
+/// cannot be sent again on another connection.
+#[derive(Debug, Default)]
+struct Renewals(Mutex<HashMap<MemId, u64>>);
+
+impl Renewals {
+    /// Accept a renewal issued at `issued_at` unless it is stale or replayed
+    fn accept(&self, mem_id: MemId, issued_at: u64) -> Result<(), String> {
+        let age_secs = unix_now().abs_diff(issued_at);
+        if age_secs > SEAL_MAX_AGE.as_secs() {
+            return Err(format!(
+                "RENEW of memid {mem_id} was issued {age_secs}s away from the server clock"
+            ));
+        }
+        let mut last = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        match last.get(&mem_id) {
+            Some(&previous) if issued_at <= previous => Err(format!(
+                "RENEW of memid {mem_id} is not newer than the last one accepted"
+            )),
+            _ => {
+                last.insert(mem_id, issued_at);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Renew the lease of an export
+///
+/// With a key, the request must carry a MAC of [`renew_message`] that
+/// verifies under it, issued within [`SEAL_MAX_AGE`] of now and after the
+/// last renewal accepted for the export.
+fn renew(
+    registry: &Registry,
+    mem_id: MemId,
+    auth: Option<RenewAuth>,
+    key: Option<&[u8]>,
+    renewals: &Renewals,
+) -> Response {
+    if let Some(key) = key {
+        let checked = match auth {
+            Some(auth) => auth
+                .mac
+                .verify(key, renew_message(mem_id, auth.issued_at).as_bytes())
+                .map_err(|_| format!("RENEW of memid {mem_id} failed authentication"))
+                .and_then(|()| renewals.accept(mem_id, auth.issued_at)),
+            None => Err(format!("RENEW of memid {mem_id} requires a MAC")),
+        };
+        if let Err(message) = checked {
+            return Response::Error { message };
+        }
+    }
+
+    match registry.renew_lease(mem_id) {
+        Ok(lease) => {
+            debug!("Renewed lease of memid {mem_id} until {}", lease.expires_at);
+            Response::Lease { mem_id, lease }
+        }
+        Err(e) => Response::Error {
+            message: e.to_string(),
+        },
+    }
+}
+
+/// Client connection to a descriptor server
+pub(crate) struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    /// Connect to a server (`host` or `host:port`)
+    pub(crate) fn connect(host: &str) -> anyhow::Result<Self> {
+        let addr = with_default_port(host);
+        let socket = addr
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {addr}"))?
+            .next()
+            .with_context(|| format!("No address for {addr}"))?;
+        let stream = TcpStream::connect_timeout(&socket, IO_TIMEOUT)
+            .with_context(|| format!("Failed to connect to {addr}"))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+
+        Ok(Self {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        })
+    }
+
+    /// List the descriptors published by the server
+    pub(crate) fn list(&mut self) -> anyhow::Result<Vec<DescEntry>> {
+        match self.request(Request::List)? {
+            Response::List { entries } => Ok(entries),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Fetch the descriptor of one memory ID
+    pub(crate) fn get(&mut self, mem_id: MemId) -> anyhow::Result<DescEntry> {
+        match self.request(Request::Get(mem_id))? {
+            Response::Desc { entry } if entry.mem_id == mem_id => Ok(entry),
+            Response::Desc { entry } => anyhow::bail!(
+                "Server answered memid {} with the descriptor of memid {}",
+                mem_id,
+                entry.mem_id
+            ),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Renew the lease of an export, authenticating with `key` if given
+    pub(crate) fn renew(&mut self, mem_id: MemId, key: Option<&[u8]>) -> anyhow::Result<Lease> {
+        let auth = key.map(|key| RenewAuth::new(key, mem_id));
+        match self.request(Request::Renew(mem_id, auth))? {
+            Response::Lease { lease, .. } => Ok(lease),
+            other => Err(unexpected(other)),
+        }
+    }
+
+    /// Send a request and read its response line
+    fn request(&mut self, request: Request) -> anyhow::Result<Response> {
+        self.writer.write_all(request.to_line().as_bytes())?;
+        self.writer.flush()?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            anyhow::bail!("Server closed the connection");
+        }
+        serde_json::from_str(&line).with_context(|| "Malformed response from server")
+    }
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Turn an unexpected response into an error
+fn unexpected(response: Response) -> anyhow::Error {
+    match response {
+        Response::Error { message } => anyhow::anyhow!("Server error: {message}"),
+        other => anyhow::anyhow!("Unexpected response from server: {other:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_parse() {
+        assert_eq!(Request::parse("LIST"), Ok(Request::List));
+        assert_eq!(Request::parse("get 42\n"), Ok(Request::Get(42)));
+        assert!(Request::parse("GET abc").is_err());
+        assert!(Request::parse("DELETE 1").is_err());
+        assert_eq!(Request::parse("RENEW 5"), Ok(Request::Renew(5, None)));
+        let auth = RenewAuth::new(b"key", 5);
+        assert_eq!(
+            Request::parse(Request::Renew(5, Some(auth)).to_line().as_str()),
+            Ok(Request::Renew(5, Some(auth)))
+        );
+        assert!(Request::parse("RENEW 5 00").is_err());
+        assert!(Request::parse("RENEW 5 1 zz").is_err());
+        assert!(Request::parse("RENEW 5 x 00").is_err());
+        assert!(Request::parse("RENEW 5 1 00 00").is_err());
+        assert_eq!(
+            Request::parse(Request::Get(7).to_line().as_str()),
+            Ok(Request::Get(7))
+        );
+    }
+
+    #[test]
+    fn test_with_default_port() {
+        assert_eq!(with_default_port("node1"), "node1:7878");
+        assert_eq!(with_default_port("node1:9000"), "node1:9000");
+        assert_eq!(with_default_port("10.0.0.1"), "10.0.0.1:7878");
+        assert_eq!(with_default_port("[::1]:9000"), "[::1]:9000");
+        assert_eq!(with_default_port("::1"), "[::1]:7878");
+    }
+
+    #[test]
+    fn test_check_listen() {
+        assert!(check_listen("127.0.0.1:7878", false).is_ok());
+        assert!(check_listen("[::1]", false).is_ok());
+        assert!(check_listen("0.0.0.0:7878", false).is_err());
+        assert!(check_listen("0.0.0.0:7878", true).is_ok());
+    }
+
+    #[test]
+    fn test_request_line_limit() {
+        let dir = std::env::temp_dir().join(format!("memlink-net-limit-{}", std::process::id()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let registry = Registry::open(&dir).unwrap();
+        let server_dir = dir.clone();
+        std::thread::spawn(move || serve_listener(listener, server_dir, registry, 1, None));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(IO_TIMEOUT)).unwrap();
+        stream.write_all(&[b'A'; MAX_REQUEST_LINE]).unwrap();
+        let mut response = String::new();
+        BufReader::new(&stream).read_line(&mut response).unwrap();
+        assert!(response.contains("longer than"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_serve_and_fetch() {
+        let dir = std::env::temp_dir().join(format!("memlink-net-test-{}", std::process::id()));
+        let mut desc = ObmmMemDesc::<UbPrivData>::new();
+        desc.addr = 0xffff_fc00_0000;
+        desc.length = 64 * 1024 * 1024;
+        desc.tokenid = 9;
+        desc.to_json_path(dir.join("memdesc_3.json")).unwrap();
+        std::fs::write(dir.join("unrelated.txt"), "ignored").unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server_dir = dir.clone();
+        let registry = Registry::open(&dir).unwrap();
+        let server_registry = registry.clone();
+        std::thread::spawn(move || serve_listener(listener, server_dir, server_registry, 2, None));
+
+        let mut client = Client::connect(&addr).unwrap();
+        let entries = client.list().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].mem_id, 3);
+
+        let entry = client.get(3).unwrap();
+        assert!(!entry.is_sealed());
+        assert_eq!(entry.desc().addr, desc.addr);
+        assert_eq!(entry.desc().tokenid, 9);
+        assert!(client.get(4).is_err());
+        assert!(entry.open(Some(b"key")).is_err());
+
+        // Leases are renewed in the registry
+        assert!(client.renew(3, None).is_err());
+        registry
+            .record_export(3, obmm_rs::ObmmExportFlags::ALLOWMMAP, &desc)
+            .unwrap();
+        let lease = Lease::new(Duration::from_secs(60), Duration::ZERO);
+        registry.set_lease(3, Some(lease)).unwrap();
+        assert!(client.renew(3, None).unwrap().expires_at >= lease.expires_at);
+
+        let signed = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = signed.local_addr().unwrap().to_string();
+        let server_dir = dir.clone();
+        let key: Arc<[u8]> = Arc::from(&b"key"[..]);
+        std::thread::spawn(move || serve_listener(signed, server_dir, registry, 2, Some(key)));
+
+        let mut client = Client::connect(&addr).unwrap();
+        let entry = client.get(3).unwrap();
+        assert!(entry.is_sealed());
+        assert!(client.get(3).unwrap().open(Some(b"wrong")).is_err());
+        assert_eq!(entry.open(Some(b"key")).unwrap().tokenid, 9);
+
+        // A keyed server only renews leases for holders of the key
+        let err = client.renew(3, None).unwrap_err();
+        assert!(err.to_string().contains("requires a MAC"), "{err}");
+        let err = client.renew(3, Some(b"wrong")).unwrap_err();
+        assert!(err.to_string().contains("failed authentication"), "{err}");
+        assert!(client.renew(3, Some(b"key")).is_ok());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_renew_replay() {
+        let dir = std::env::temp_dir().join(format!("memlink-net-replay-{}", std::process::id()));
+        let registry = Registry::open(&dir).unwrap();
+        registry
+            .record_export(
+                3,
+                obmm_rs::ObmmExportFlags::ALLOWMMAP,
+                &ObmmMemDesc::<UbPrivData>::new(),
+            )
+            .unwrap();
+        let lease = Lease::new(Duration::from_secs(60), Duration::ZERO);
+        registry.set_lease(3, Some(lease)).unwrap();
+        let renewals = Renewals::default();
+        let key = Some(&b"key"[..]);
+        let renew_at = |issued_at: u64| {
+            let auth = RenewAuth {
+                issued_at,
+                mac: Signature::compute(b"key", renew_message(3, issued_at).as_bytes()),
+            };
+            renew(&registry, 3, Some(auth), key, &renewals)
+        };
+        let refused = |response: Response, reason: &str| match response {
+            Response::Error { message } => assert!(message.contains(reason), "{message}"),
+            other => panic!("renewal accepted: {other:?}"),
+        };
+
+        let now = unix_now();
+        assert!(matches!(renew_at(now - 1), Response::Lease { .. }));
+
+        // The same renewal sent again, or an older one, is a replay
+        refused(renew_at(now - 1), "not newer");
+        refused(renew_at(now - 2), "not newer");
+        assert!(matches!(renew_at(now), Response::Lease { .. }));
+
+        // Renewals far from the server clock are stale whatever their order
+        refused(renew_at(now - SEAL_MAX_AGE.as_secs() - 60), "away from");
+        refused(renew_at(now + SEAL_MAX_AGE.as_secs() + 60), "away from");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_serve_peer_quota() {
+        let dir = std::env::temp_dir().join(format!("memlink-net-quota-{}", std::process::id()));
+        let deid = [7; 16];
+        let mut desc = ObmmMemDesc::<UbPrivData>::new();
+        desc.length = 64 * 1024 * 1024;
+        desc.deid = deid;
+        desc.to_json_path(dir.join("memdesc_5.json")).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server_dir = dir.clone();
+        let registry = Registry::open(&dir).unwrap();
+        registry
+            .set_peer_quota(deid, Some(desc.length - 1))
+            .unwrap();
+        let server_registry = registry.clone();
+        std::thread::spawn(move || serve_listener(listener, server_dir, server_registry, 1, None));
+
+        let mut client = Client::connect(&addr).unwrap();
+        let err = client.get(5).unwrap_err();
+        assert!(err.to_string().contains("Peer quota exceeded"), "{err}");
+        assert_eq!(registry.peer_usage(&deid).unwrap(), 0);
+
+        registry.set_peer_quota(deid, Some(desc.length)).unwrap();
+        assert_eq!(client.get(5).unwrap().desc().length, desc.length);
+        assert_eq!(registry.peer_usage(&deid).unwrap(), desc.length);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
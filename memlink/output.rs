@@ -0,0 +1,405 @@
+//! Human-readable output shared by the subcommands
+//!
+//! Results go to stdout; progress bars and logs go to stderr. `--color`
+//! selects whether styling is used (`auto` follows the terminal and
+//! `NO_COLOR`), `--quiet` drops status lines, progress bars and info logs,
+//! and each `--verbose` lowers the log level by one step. `RUST_LOG` still
+//! overrides the log level.
+//!
+//! Tables are aligned on their visible width, so styled cells line up with
+//! plain ones.
+
+use std::fmt::Display;
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use console::{Alignment, Style, StyledObject};
+use indicatif::{ProgressBar, ProgressStyle};
+
+/// Spacing between table columns
+const COLUMN_GAP: &str = "  ";
+
+/// Redraw interval of spinners
+const TICK_INTERVAL: Duration = Duration::from_millis(120);
+
+/// When to style output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub(crate) enum ColorChoice {
+    /// Style output written to a terminal
+    #[default]
+    Auto,
+    /// Always style output
+    Always,
+    /// Never style output
+    Never,
+}
+
+/// How much the CLI prints
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Verbosity {
+    /// Results, warnings and errors only
+    Quiet,
+    /// Results, status lines, progress and info logs
+    #[default]
+    Normal,
+    /// Debug logs as well
+    Verbose,
+    /// Trace logs as well
+    Trace,
+}
+
+impl Verbosity {
+    /// Build from the `--quiet` and repeated `--verbose` flags
+    pub(crate) fn from_flags(quiet: bool, verbose: u8) -> Self {
+        match (quiet, verbose) {
+            (true, _) => Self::Quiet,
+            (false, 0) => Self::Normal,
+            (false, 1) => Self::Verbose,
+            (false, _) => Self::Trace,
+        }
+    }
+
+    /// Default log level at this verbosity
+    pub(crate) fn log_level(self) -> log::LevelFilter {
+        match self {
+            Self::Quiet => log::LevelFilter::Warn,
+            Self::Normal => log::LevelFilter::Info,
+            Self::Verbose => log::LevelFilter::Debug,
+            Self::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// Verbosity selected at startup
+static VERBOSITY: OnceLock<Verbosity> = OnceLock::new();
+
+/// Apply the output options and set up logging
+///
+/// Call once, before anything is printed.
+pub(crate) fn init(color: ColorChoice, verbosity: Verbosity) {
+    match color {
+        ColorChoice::Auto => {}
+        ColorChoice::Always | ColorChoice::Never => {
+            let enabled = color == ColorChoice::Always;
+            console::set_colors_enabled(enabled);
+            console::set_colors_enabled_stderr(enabled);
+        }
+    }
+    let _ = VERBOSITY.set(verbosity);
+
+    let level = verbosity.log_level().to_string().to_lowercase();
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level))
+        .write_style(match color {
+            ColorChoice::Auto => env_logger::WriteStyle::Auto,
+            ColorChoice::Always => env_logger::WriteStyle::Always,
+            ColorChoice::Never => env_logger::WriteStyle::Never,
+        })
+        .init();
+}
+
+/// Get the verbosity selected at startup
+pub(crate) fn verbosity() -> Verbosity {
+    VERBOSITY.get().copied().unwrap_or_default()
+}
+
+/// Print a status line to stdout unless `--quiet`
+pub(crate) fn status(message: impl Display) {
+    if verbosity() > Verbosity::Quiet {
+        println!("{message}");
+    }
+}
+
+/// Meaning of a piece of output, which selects its style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Tone {
+    /// Table headers and section titles
+    Header,
+    /// Healthy state or completed action
+    Good,
+    /// State that needs attention
+    Warn,
+    /// Failure or error state
+    Bad,
+    /// Secondary detail
+    Dim,
+}
+
+impl Tone {
+    fn style(self) -> Style {
+        match self {
+            Self::Header => Style::new().bold(),
+            Self::Good => Style::new().green(),
+            Self::Warn => Style::new().yellow(),
+            Self::Bad => Style::new().red().bold(),
+            Self::Dim => Style::new().dim(),
+        }
+    }
+}
+
+/// Style a value for stdout
+pub(crate) fn paint<D>(value: D, tone: Tone) -> StyledObject<D> {
+    tone.style().apply_to(value)
+}
+
+/// Style a section title
+pub(crate) fn heading(title: impl Display) -> String {
+    paint(title, Tone::Header).to_string()
+}
+
+/// Horizontal alignment of a table column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Align {
+    /// Pad on the right
+    Left,
+    /// Pad on the left
+    Right,
+}
+
+/// Column of a [`Table`]
+#[derive(Debug, Clone)]
+pub(crate) struct Column {
+    /// Header text
+    title: &'static str,
+    /// Cell alignment
+    align: Align,
+    /// Minimum width, the only width when streaming rows
+    width: usize,
+}
+
+impl Column {
+    /// Left-aligned column
+    pub(crate) fn left(title: &'static str) -> Self {
+        Self {
+            title,
+            align: Align::Left,
+            width: console::measure_text_width(title),
+        }
+    }
+
+    /// Right-aligned column
+    pub(crate) fn right(title: &'static str) -> Self {
+        Self {
+            align: Align::Right,
+            ..Self::left(title)
+        }
+    }
+
+    /// Set the minimum width
+    pub(crate) fn width(mut self, width: usize) -> Self {
+        self.width = self.width.max(width);
+        self
+    }
+
+    fn pad(&self, text: &str, width: usize) -> String {
+        let align = match self.align {
+            Align::Left => Alignment::Left,
+            Align::Right => Alignment::Right,
+        };
+        console::pad_str(text, width, align, None).into_owned()
+    }
+}
+
+/// Column-aligned table
+///
+/// Either collect the rows and [`write`](Table::write) them, which sizes
+/// each column to its widest cell, or stream them with
+/// [`write_header`](Table::write_header) and
+/// [`write_row`](Table::write_row), which use the minimum widths.
+#[derive(Debug, Clone)]
+pub(crate) struct Table {
+    /// Columns, left to right
+    columns: Vec<Column>,
+    /// Collected rows
+    rows: Vec<Vec<String>>,
+    /// Whether the header is styled
+    styled: bool,
+}
+
+impl Table {
+    /// Create a table with the given columns
+    pub(crate) fn new(columns: impl IntoIterator<Item = Column>) -> Self {
+        Self {
+            columns: columns.into_iter().collect(),
+            rows: Vec::new(),
+            styled: true,
+        }
+    }
+
+    /// Set whether the header is styled (off for output written to files)
+    pub(crate) fn styled(mut self, styled: bool) -> Self {
+        self.styled = styled;
+        self
+    }
+
+    /// Add a row; missing cells are left empty
+    pub(crate) fn row<I>(&mut self, cells: I)
+    where
+        I: IntoIterator,
+        I::Item: Display,
+    {
+        self.rows
+            .push(cells.into_iter().map(|cell| cell.to_string()).collect());
+    }
+
+    /// Check whether no row was added
+    pub(crate) fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Write the header and the collected rows
+    pub(crate) fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        let widths: Vec<usize> = self
+            .columns
+            .iter()
+            .enumerate()
+            .map(|(i, column)| {
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .map(|cell| console::measure_text_width(cell))
+                    .fold(column.width, usize::max)
+            })
+            .collect();
+        self.write_header_with(out, &widths)?;
+        for row in &self.rows {
+            writeln!(out, "{}", self.format_line(&widths, row))?;
+        }
+        Ok(())
+    }
+
+    /// Write the header for streamed rows
+    pub(crate) fn write_header(&self, out: &mut dyn Write) -> io::Result<()> {
+        self.write_header_with(out, &self.min_widths())
+    }
+
+    /// Write one streamed row
+    pub(crate) fn write_row<I>(&self, out: &mut dyn Write, cells: I) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: Display,
+    {
+        let cells: Vec<String> = cells.into_iter().map(|cell| cell.to_string()).collect();
+        writeln!(out, "{}", self.format_line(&self.min_widths(), &cells))
+    }
+
+    fn min_widths(&self) -> Vec<usize> {
+        self.columns.iter().map(|column| column.width).collect()
+    }
+
+    fn write_header_with(&self, out: &mut dyn Write, widths: &[usize]) -> io::Result<()> {
+        let titles: Vec<String> = self.columns.iter().map(|c| c.title.to_string()).collect();
+        let line = self.format_line(widths, &titles);
+        if self.styled {
+            writeln!(out, "{}", paint(line, Tone::Header))
+        } else {
+            writeln!(out, "{line}")
+        }
+    }
+
+    /// Pad the cells to `widths` and join them, without trailing blanks
+    fn format_line(&self, widths: &[usize], cells: &[String]) -> String {
+        let mut line = String::new();
+        for (i, (column, width)) in self.columns.iter().zip(widths).enumerate() {
+            if i > 0 {
+                line.push_str(COLUMN_GAP);
+            }
+            let cell = cells.get(i).map_or("", String::as_str);
+            line.push_str(&column.pad(cell, *width));
+        }
+        line.trim_end().to_string()
+    }
+}
+
+/// Progress bar over `len` steps on stderr
+///
+/// Hidden with `--quiet` or when stderr is not a terminal.
+pub(crate) fn progress(len: u64, message: impl Into<String>) -> ProgressBar {
+    if verbosity() == Verbosity::Quiet {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len).with_message(message.into());
+    if let Ok(style) = ProgressStyle::with_template("{msg} [{bar:30}] {pos}/{len} {elapsed}") {
+        bar.set_style(style.progress_chars("=> "));
+    }
+    bar
+}
+
+/// Print a status line without tearing a progress bar
+///
+/// Falls back to [`status`] when the bar is hidden.
+pub(crate) fn progress_line(bar: &ProgressBar, message: impl Display) {
+    if bar.is_hidden() {
+        status(message);
+    } else {
+        bar.println(message.to_string());
+    }
+}
+
+/// Spinner for work of unknown length on stderr
+///
+/// Hidden with `--quiet` or when stderr is not a terminal.
+pub(crate) fn spinner(message: impl Into<String>) -> ProgressBar {
+    if verbosity() == Verbosity::Quiet {
+        return ProgressBar::hidden();
+    }
+    let spinner = ProgressBar::new_spinner().with_message(message.into());
+    spinner.enable_steady_tick(TICK_INTERVAL);
+    spinner
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verbosity_from_flags() {
+        assert_eq!(Verbosity::from_flags(false, 0), Verbosity::Normal);
+        assert_eq!(Verbosity::from_flags(false, 1), Verbosity::Verbose);
+        assert_eq!(Verbosity::from_flags(false, 5), Verbosity::Trace);
+        assert_eq!(Verbosity::from_flags(true, 0), Verbosity::Quiet);
+        assert_eq!(Verbosity::Quiet.log_level(), log::LevelFilter::Warn);
+        assert_eq!(Verbosity::Verbose.log_level(), log::LevelFilter::Debug);
+    }
+
+    #[test]
+    fn test_table() {
+        let mut table = Table::new([
+            Column::right("MemID"),
+            Column::left("Action"),
+            Column::left("Detail"),
+        ]);
+        table.row(["7", "keep", "still exported"]);
+        table.row(["12345", "removed", ""]);
+        let mut out = Vec::new();
+        table.write(&mut out).unwrap();
+        let text = console::strip_ansi_codes(std::str::from_utf8(&out).unwrap()).into_owned();
+        assert_eq!(
+            text,
+            "MemID  Action   Detail\n    7  keep     still exported\n12345  removed\n"
+        );
+
+        // Styled cells are aligned on their visible width
+        let mut table = Table::new([Column::left("State"), Column::right("N")]);
+        table.row([
+            paint("up", Tone::Good).force_styling(true).to_string(),
+            "1".to_string(),
+        ]);
+        table.row(["down".to_string(), "22".to_string()]);
+        let mut out = Vec::new();
+        table.write(&mut out).unwrap();
+        let text = console::strip_ansi_codes(std::str::from_utf8(&out).unwrap()).into_owned();
+        assert_eq!(text, "State   N\nup      1\ndown   22\n");
+    }
+
+    #[test]
+    fn test_streamed_table() {
+        let table = Table::new([Column::right("Elapsed").width(8), Column::right("B/s")]);
+        let mut out = Vec::new();
+        table.write_header(&mut out).unwrap();
+        table.write_row(&mut out, ["1.0s", "12345"]).unwrap();
+        let text = console::strip_ansi_codes(std::str::from_utf8(&out).unwrap()).into_owned();
+        assert_eq!(text, " Elapsed  B/s\n    1.0s  12345\n");
+    }
+}
@@ -0,0 +1,167 @@
+//! Host capability document for `memlink probe`
+//!
+//! Combines the ETMEM and OBMM probes with UB fwctl device discovery so
+//! that deployment tooling can check, in one call, which features a host
+//! supports before enabling them. Probing never fails: a subsystem that is
+//! missing or unreadable is reported as such in the document.
+
+use std::io::{self, Write};
+
+use etmem_rs::EtmemProbe;
+use obmm_rs::ObmmProbe;
+use serde::Serialize;
+use ubfwctl::CapabilityReport;
+
+use crate::output::{Column, Table, Tone, heading, paint};
+
+/// Capabilities of UB fwctl devices
+#[derive(Debug, Serialize)]
+pub(crate) struct FwctlProbe {
+    /// Devices found and what each supports
+    pub(crate) devices: Vec<CapabilityReport>,
+    /// Why device discovery failed, if it did
+    pub(crate) error: Option<String>,
+}
+
+/// Capability document of the host
+#[derive(Debug, Serialize)]
+pub(crate) struct ProbeReport {
+    /// Version of memlink that produced the document
+    pub(crate) version: &'static str,
+    /// ETMEM support
+    pub(crate) etmem: EtmemProbe,
+    /// OBMM support
+    pub(crate) obmm: ObmmProbe,
+    /// UB fwctl devices
+    pub(crate) fwctl: FwctlProbe,
+}
+
+impl ProbeReport {
+    /// Probe every subsystem of this host
+    pub(crate) fn collect() -> Self {
+        let fwctl = match ubfwctl::probe_capabilities() {
+            Ok(devices) => FwctlProbe {
+                devices,
+                error: None,
+            },
+            Err(e) => FwctlProbe {
+                devices: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        };
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            etmem: etmem_rs::probe(),
+            obmm: obmm_rs::probe::probe(),
+            fwctl,
+        }
+    }
+
+    /// Print the document as tables
+    pub(crate) fn write(&self, out: &mut dyn Write) -> io::Result<()> {
+        let etmem = &self.etmem;
+        writeln!(out, "{}", heading("ETMEM"))?;
+        let mut table = Table::new([Column::left("Check"), Column::left("Result")]);
+        table.row([
+            "Kernel".to_string(),
+            etmem
+                .kernel_release
+                .clone()
+                .unwrap_or_else(|| paint("unknown", Tone::Warn).to_string()),
+        ]);
+        for module in &etmem.modules {
+            table.row([
+                format!("Module {}", module.name),
+                verdict(module.loaded, "loaded", "not loaded"),
+            ]);
+        }
+        table.row([
+            "idle_pages".to_string(),
+            verdict(etmem.idle_pages, "present", "missing"),
+        ]);
+        table.row([
+            "swap_pages".to_string(),
+            verdict(etmem.swap_pages, "present", "missing"),
+        ]);
+        table.row([
+            "Kernel swap".to_string(),
+            match etmem.kernel_swap {
+                Some(enabled) => verdict(enabled, "enabled", "disabled"),
+                None => paint("unsupported", Tone::Dim).to_string(),
+            },
+        ]);
+        table.row([
+            "CAP_SYS_ADMIN".to_string(),
+            verdict(etmem.cap_sys_admin, "held", "missing"),
+        ]);
+        table.write(out)?;
+
+        let obmm = &self.obmm;
+        writeln!(out, "\n{}", heading("OBMM"))?;
+        let mut table = Table::new([Column::left("Check"), Column::left("Result")]);
+        table.row([
+            "Module obmm".to_string(),
+            verdict(obmm.module, "loaded", "not loaded"),
+        ]);
+        table.row([
+            "/dev/obmm".to_string(),
+            verdict(obmm.device, "present", "missing"),
+        ]);
+        table.row([
+            "Device access".to_string(),
+            verdict(obmm.accessible, "read-write", "denied"),
+        ]);
+        table.row([
+            "Regions".to_string(),
+            if obmm.listing {
+                format!(
+                    "{} live, {} exported, {} imported",
+                    obmm.regions, obmm.exports, obmm.imports
+                )
+            } else {
+                paint("missing", Tone::Bad).to_string()
+            },
+        ]);
+        table.row([
+            "NUMA nodes".to_string(),
+            obmm.numa_nodes
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        ]);
+        table.write(out)?;
+
+        writeln!(out, "\n{}", heading("UB fwctl"))?;
+        if let Some(error) = &self.fwctl.error {
+            writeln!(out, "{}", paint(error, Tone::Bad))?;
+            return Ok(());
+        }
+        if self.fwctl.devices.is_empty() {
+            writeln!(out, "No devices found.")?;
+            return Ok(());
+        }
+        let mut table = Table::new([
+            Column::left("Device"),
+            Column::left("RPC"),
+            Column::left("Sysfs"),
+        ]);
+        for device in &self.fwctl.devices {
+            table.row([
+                device.device.clone(),
+                verdict(device.rpc, "permitted", "denied"),
+                verdict(device.sysfs, "present", "missing"),
+            ]);
+        }
+        table.write(out)
+    }
+}
+
+/// Paint the outcome of a check
+fn verdict(ok: bool, good: &str, bad: &str) -> String {
+    if ok {
+        paint(good, Tone::Good).to_string()
+    } else {
+        paint(bad, Tone::Bad).to_string()
+    }
+}
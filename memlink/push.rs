@@ -0,0 +1,647 @@
+//! Per-host cold memory reports for `memlink etmem report`
+//!
+//! A report summarizes the idle, hot and swapped memory of a set of
+//! processes together with the system reclaim counters. It is printed as
+//! JSON or, with `--push URL`, posted to a collection endpoint so that
+//! fleet-wide dashboards can be built without a separate agent.
+//!
+//! Pushed reports go through a spool directory: each report is written to
+//! the spool first and removed once the collector accepted it, oldest
+//! first. Reports survive collector outages and restarts up to
+//! [`MAX_SPOOLED`] files, beyond which the oldest are dropped.
+//!
+//! Only plain `http://` endpoints are supported; terminate TLS at a local
+//! proxy. With a shared key (`--key-file`), each request carries an
+//! `X-Memlink-Signature: hmac-sha256=<hex>` header computed over
+//! `memlink-report-v1` followed by the exact request body. The prefix keeps
+//! a report signature from being valid for any other payload signed with
+//! the same key, such as a descriptor.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use log::{debug, info, warn};
+use obmm_rs::Signature;
+use serde::Serialize;
+
+use crate::watch::{self, SwapCounters};
+
+/// Version of the report document
+const REPORT_VERSION: u32 = 1;
+
+/// Most reports kept in the spool while the collector is unreachable
+pub(crate) const MAX_SPOOLED: usize = 1000;
+
+/// Connect and I/O timeout of a push
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header carrying the body signature
+const SIGNATURE_HEADER: &str = "X-Memlink-Signature";
+
+/// Domain separation prefix of the signed bytes
+const SIGN_DOMAIN: &[u8] = b"memlink-report-v1";
+
+/// Idle, hot and swapped memory of one process
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct ProcessSummary {
+    pub pid: u32,
+    /// Command name from `/proc/[pid]/comm`
+    pub comm: String,
+    pub idle_bytes: u64,
+    pub hot_bytes: u64,
+    pub swapped_bytes: u64,
+    pub idle_anonymous_bytes: u64,
+    pub idle_file_bytes: u64,
+    /// Why the process could not be scanned
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ProcessSummary {
+    /// Scan a process, recording failures instead of returning them
+    fn capture(pid: u32) -> Self {
+        let comm = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+            .map(|c| c.trim().to_string())
+            .unwrap_or_default();
+        match watch::capture(pid) {
+            Ok(report) => {
+                let (idle_anonymous_bytes, idle_file_bytes) = report.idle_split();
+                Self {
+                    pid,
+                    comm,
+                    idle_bytes: report.total_idle_bytes(),
+                    hot_bytes: report.total_hot_bytes(),
+                    swapped_bytes: report.total_swapped_bytes(),
+                    idle_anonymous_bytes,
+                    idle_file_bytes,
+                    error: None,
+                }
+            }
+            Err(e) => Self {
+                pid,
+                comm,
+                error: Some(format!("{e:#}")),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+/// Scan and reclaim summary of one host
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct HostReport {
+    pub version: u32,
+    pub host: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub idle_bytes: u64,
+    pub hot_bytes: u64,
+    pub swapped_bytes: u64,
+    /// System-wide swap counters from `/proc/vmstat`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reclaim: Option<SwapCounters>,
+    pub processes: Vec<ProcessSummary>,
+}
+
+impl HostReport {
+    /// Assemble a report from per-process summaries
+    pub(crate) fn new(
+        host: String,
+        timestamp: u64,
+        reclaim: Option<SwapCounters>,
+        processes: Vec<ProcessSummary>,
+    ) -> Self {
+        Self {
+            version: REPORT_VERSION,
+            host,
+            timestamp,
+            idle_bytes: processes.iter().map(|p| p.idle_bytes).sum(),
+            hot_bytes: processes.iter().map(|p| p.hot_bytes).sum(),
+            swapped_bytes: processes.iter().map(|p| p.swapped_bytes).sum(),
+            reclaim,
+            processes,
+        }
+    }
+
+    /// Scan the given processes of this host
+    pub(crate) fn capture(pids: &[u32]) -> Self {
+        let processes = pids
+            .iter()
+            .map(|&pid| ProcessSummary::capture(pid))
+            .collect();
+        Self::new(hostname(), unix_now(), SwapCounters::read().ok(), processes)
+    }
+}
+
+/// Name of this host
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|h| h.trim().to_string())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Sign a report body, prefixed with [`SIGN_DOMAIN`]
+fn sign(key: &[u8], body: &[u8]) -> Signature {
+    let mut data = Vec::with_capacity(SIGN_DOMAIN.len() + body.len());
+    data.extend_from_slice(SIGN_DOMAIN);
+    data.extend_from_slice(body);
+    Signature::compute(key, &data)
+}
+
+/// Collection endpoint, `http://host[:port][/path]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Endpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Endpoint {
+    /// Parse an `http://` URL
+    pub(crate) fn parse(url: &str) -> anyhow::Result<Self> {
+        if url.starts_with("https://") {
+            anyhow::bail!("https is not supported; push through a local TLS proxy instead");
+        }
+        let rest = url
+            .strip_prefix("http://")
+            .with_context(|| format!("Push URL must start with http://: {url}"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.strip_prefix('[') {
+            // IPv6 literal, `[addr]` or `[addr]:port`
+            Some(bracketed) => {
+                let (host, port) = bracketed
+                    .split_once(']')
+                    .with_context(|| format!("Invalid host in push URL: {url}"))?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .with_context(|| format!("Invalid port in push URL: {url}"))?,
+            None => 80,
+        };
+        if host.is_empty() {
+            anyhow::bail!("Missing host in push URL: {url}");
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Value of the `Host` header
+    fn host_header(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Build an HTTP/1.1 POST request for a JSON body
+    fn request(&self, body: &[u8], key: Option<&[u8]>) -> Vec<u8> {
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: memlink/{}\r\n\
+             Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            self.path,
+            self.host_header(),
+            env!("CARGO_PKG_VERSION"),
+            body.len()
+        );
+        if let Some(key) = key {
+            request.push_str(&format!(
+                "{SIGNATURE_HEADER}: hmac-sha256={}\r\n",
+                sign(key, body).to_hex()
+            ));
+        }
+        request.push_str("\r\n");
+        let mut request = request.into_bytes();
+        request.extend_from_slice(body);
+        request
+    }
+
+    /// Post a JSON body, succeeding on any 2xx status
+    fn post(&self, body: &[u8], key: Option<&[u8]>) -> anyhow::Result<()> {
+        let socket = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .with_context(|| format!("Failed to resolve {}", self.host))?
+            .next()
+            .with_context(|| format!("No address for {}", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&socket, IO_TIMEOUT)
+            .with_context(|| format!("Failed to connect to {socket}"))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        stream.write_all(&self.request(body, key))?;
+
+        let mut status_line = String::new();
+        BufReader::new(&stream).read_line(&mut status_line)?;
+        let status: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .with_context(|| format!("Invalid response from collector: {status_line:?}"))?;
+        if !(200..300).contains(&status) {
+            anyhow::bail!("Collector rejected report: {}", status_line.trim());
+        }
+        Ok(())
+    }
+}
+
+/// Directory of reports waiting to be pushed
+#[derive(Debug, Clone)]
+pub(crate) struct Spool {
+    dir: PathBuf,
+    limit: usize,
+}
+
+impl Spool {
+    /// Open a spool directory, creating it if needed
+    pub(crate) fn open(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create spool directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            limit: MAX_SPOOLED,
+        })
+    }
+
+    /// Default spool directory under the memlink state directory
+    pub(crate) fn default_dir() -> PathBuf {
+        std::env::var_os(obmm_rs::registry::STATE_DIR_ENV)
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(obmm_rs::registry::DEFAULT_STATE_DIR))
+            .join("spool")
+    }
+
+    /// Keep at most `limit` reports
+    pub(crate) fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit.max(1);
+        self
+    }
+
+    /// Add a report, dropping the oldest ones beyond the limit
+    pub(crate) fn push(&self, report: &HostReport) -> anyhow::Result<PathBuf> {
+        let body = serde_json::to_vec(report)?;
+        let pending = self.pending()?;
+        let seq = pending
+            .last()
+            .and_then(|p| spool_seq(p))
+            .map_or(0, |seq| seq + 1);
+        let path = self.dir.join(format!("report-{seq:012}.json"));
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, body)
+            .and_then(|()| std::fs::rename(&tmp, &path))
+            .with_context(|| format!("Failed to spool report to {}", path.display()))?;
+
+        let excess = (pending.len() + 1).saturating_sub(self.limit);
+        for old in pending.iter().take(excess) {
+            warn!("Spool full, dropping {}", old.display());
+            let _ = std::fs::remove_file(old);
+        }
+        Ok(path)
+    }
+
+    /// Spooled reports, oldest first
+    pub(crate) fn pending(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let mut pending: Vec<PathBuf> = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read spool directory {}", self.dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| spool_seq(path).is_some())
+            .collect();
+        pending.sort();
+        Ok(pending)
+    }
+}
+
+/// Sequence number of a spooled report file
+fn spool_seq(path: &Path) -> Option<u64> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix("report-")?
+        .strip_suffix(".json")?
+        .parse()
+        .ok()
+}
+
+/// Sends spooled reports to a collector
+#[derive(Debug)]
+pub(crate) struct Pusher {
+    endpoint: Endpoint,
+    spool: Spool,
+    key: Option<Vec<u8>>,
+    /// Attempts per report before giving up until the next round
+    attempts: u32,
+    /// Delay before the first retry, doubled after each failure
+    backoff: Duration,
+}
+
+impl Pusher {
+    pub(crate) fn new(endpoint: Endpoint, spool: Spool, key: Option<Vec<u8>>) -> Self {
+        Self {
+            endpoint,
+            spool,
+            key,
+            attempts: 3,
+            backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Set the attempts per report and the initial retry delay
+    pub(crate) fn with_retry(mut self, attempts: u32, backoff: Duration) -> Self {
+        self.attempts = attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Spool a report and send everything pending, oldest first
+    ///
+    /// Returns the number of reports delivered. Stops at the first report
+    /// that still fails after all attempts, leaving it and later ones
+    /// spooled for the next round.
+    pub(crate) fn push(&self, report: &HostReport) -> anyhow::Result<usize> {
+        self.spool.push(report)?;
+        self.flush()
+    }
+
+    /// Send spooled reports, oldest first
+    pub(crate) fn flush(&self) -> anyhow::Result<usize> {
+        let mut delivered = 0;
+        for path in self.spool.pending()? {
+            let body = std::fs::read(&path)
+                .with_context(|| format!("Failed to read spooled report {}", path.display()))?;
+            if let Err(e) = self.post_retrying(&body) {
+                warn!(
+                    "Push failed, {} report(s) remain spooled: {e:#}",
+                    self.spool.pending()?.len()
+                );
+                break;
+            }
+            std::fs::remove_file(&path)?;
+            delivered += 1;
+        }
+        Ok(delivered)
+    }
+
+    fn post_retrying(&self, body: &[u8]) -> anyhow::Result<()> {
+        let mut delay = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match self.endpoint.post(body, self.key.as_deref()) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= self.attempts => return Err(e),
+                Err(e) => {
+                    debug!("Push attempt {attempt} failed: {e:#}, retrying in {delay:?}");
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Options of `memlink etmem report`
+#[derive(Debug)]
+pub(crate) struct ReportOptions {
+    pub pids: Vec<u32>,
+    /// Collector to post to (prints the report if `None`)
+    pub pusher: Option<Pusher>,
+    /// Time between reports (one report if `None`)
+    pub interval: Option<Duration>,
+    /// Stop after this many reports
+    pub iterations: Option<u32>,
+}
+
+/// Produce reports once or on a schedule
+pub(crate) fn run(options: &ReportOptions) -> anyhow::Result<()> {
+    let mut rounds = 0;
+    loop {
+        let started = std::time::Instant::now();
+        let report = HostReport::capture(&options.pids);
+        match &options.pusher {
+            Some(pusher) => {
+                let delivered = pusher.push(&report)?;
+                info!("Pushed {delivered} report(s)");
+            }
+            None => {
+                let mut out = std::io::stdout().lock();
+                serde_json::to_writer_pretty(&mut out, &report)?;
+                writeln!(out)?;
+            }
+        }
+
+        rounds += 1;
+        let Some(interval) = options.interval else {
+            return Ok(());
+        };
+        if options.iterations.is_some_and(|limit| rounds >= limit) {
+            return Ok(());
+        }
+        std::thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    fn sample_report(timestamp: u64) -> HostReport {
+        HostReport::new(
+            "node1".to_string(),
+            timestamp,
+            Some(SwapCounters {
+                pswpin: 10,
+                pswpout: 20,
+            }),
+            vec![
+                ProcessSummary {
+                    pid: 42,
+                    comm: "app".to_string(),
+                    idle_bytes: 4096,
+                    hot_bytes: 8192,
+                    ..ProcessSummary::default()
+                },
+                ProcessSummary {
+                    pid: 43,
+                    error: Some("exited".to_string()),
+                    ..ProcessSummary::default()
+                },
+            ],
+        )
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("memlink-push-test-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Accept `count` requests, answer with `status` and return the requests
+    fn collector(
+        status: &'static str,
+        count: usize,
+    ) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/ingest", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut head = String::new();
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.strip_prefix("Content-Length: ") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    head.push_str(&line);
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                stream
+                    .write_all(format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\n\r\n").as_bytes())
+                    .unwrap();
+                requests.push(head + &String::from_utf8(body).unwrap());
+            }
+            requests
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_host_report() {
+        let report = sample_report(1_700_000_000);
+        assert_eq!(report.idle_bytes, 4096);
+        assert_eq!(report.hot_bytes, 8192);
+
+        let value = serde_json::to_value(&report).unwrap();
+        assert_eq!(value["version"], REPORT_VERSION);
+        assert_eq!(value["reclaim"]["pswpout"], 20);
+        assert_eq!(value["processes"][0]["comm"], "app");
+        assert!(value["processes"][0].get("error").is_none());
+        assert_eq!(value["processes"][1]["error"], "exited");
+    }
+
+    #[test]
+    fn test_endpoint_parse() {
+        let endpoint = Endpoint::parse("http://collector:9000/v1/reports").unwrap();
+        assert_eq!(endpoint.host, "collector");
+        assert_eq!(endpoint.port, 9000);
+        assert_eq!(endpoint.path, "/v1/reports");
+
+        let endpoint = Endpoint::parse("http://10.0.0.1").unwrap();
+        assert_eq!(endpoint.port, 80);
+        assert_eq!(endpoint.path, "/");
+
+        let endpoint = Endpoint::parse("http://[::1]:8080/x").unwrap();
+        assert_eq!(endpoint.host, "::1");
+        assert_eq!(endpoint.host_header(), "[::1]:8080");
+
+        assert!(Endpoint::parse("https://collector/").is_err());
+        assert!(Endpoint::parse("collector:9000").is_err());
+        assert!(Endpoint::parse("http://:9000/").is_err());
+    }
+
+    #[test]
+    fn test_signed_request() {
+        let endpoint = Endpoint::parse("http://collector/ingest").unwrap();
+        let request = String::from_utf8(endpoint.request(b"{}", Some(b"key"))).unwrap();
+        assert!(request.starts_with("POST /ingest HTTP/1.1\r\nHost: collector:80\r\n"));
+        assert!(request.contains("Content-Length: 2\r\n"));
+        let signature = Signature::compute(b"key", b"memlink-report-v1{}").to_hex();
+        assert_eq!(sign(b"key", b"{}").to_hex(), signature);
+        assert_ne!(Signature::compute(b"key", b"{}").to_hex(), signature);
+        assert!(request.contains(&format!("{SIGNATURE_HEADER}: hmac-sha256={signature}\r\n")));
+        assert!(request.ends_with("\r\n\r\n{}"));
+
+        let unsigned = String::from_utf8(endpoint.request(b"{}", None)).unwrap();
+        assert!(!unsigned.contains(SIGNATURE_HEADER));
+    }
+
+    #[test]
+    fn test_spool_limit() {
+        let dir = temp_dir("limit");
+        let spool = Spool::open(&dir).unwrap().with_limit(2);
+        for timestamp in 0..3 {
+            spool.push(&sample_report(timestamp)).unwrap();
+        }
+        let pending = spool.pending().unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(spool_seq(&pending[0]), Some(1));
+        assert_eq!(spool_seq(&pending[1]), Some(2));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_push_delivers_spooled_reports() {
+        let dir = temp_dir("deliver");
+        let spool = Spool::open(&dir).unwrap();
+
+        // Collector down: the report stays spooled
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let down = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let pusher = Pusher::new(Endpoint::parse(&down).unwrap(), spool.clone(), None)
+            .with_retry(2, Duration::from_millis(1));
+        assert_eq!(pusher.push(&sample_report(1)).unwrap(), 0);
+        assert_eq!(spool.pending().unwrap().len(), 1);
+
+        // Collector back: both reports are sent, oldest first
+        let (url, collector) = collector("200 OK", 2);
+        let pusher = Pusher::new(
+            Endpoint::parse(&url).unwrap(),
+            spool.clone(),
+            Some(b"k".to_vec()),
+        );
+        assert_eq!(pusher.push(&sample_report(2)).unwrap(), 2);
+        assert!(spool.pending().unwrap().is_empty());
+
+        let requests = collector.join().unwrap();
+        assert!(requests[0].contains("\"timestamp\":1"));
+        assert!(requests[1].contains("\"timestamp\":2"));
+        assert!(requests[1].contains(SIGNATURE_HEADER));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_push_rejected() {
+        let dir = temp_dir("rejected");
+        let spool = Spool::open(&dir).unwrap();
+        let (url, collector) = collector("403 Forbidden", 1);
+        let pusher = Pusher::new(Endpoint::parse(&url).unwrap(), spool.clone(), None)
+            .with_retry(1, Duration::ZERO);
+        assert_eq!(pusher.push(&sample_report(1)).unwrap(), 0);
+        assert_eq!(spool.pending().unwrap().len(), 1);
+        collector.join().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,793 @@
+//! Interactive prompt for `memlink shell`
+//!
+//! Investigating a process usually takes several looks at the same scan:
+//! which mappings hold the cold memory, the idle pages of one of them, a
+//! trial swap of part of it. The shell keeps the attached process, its last
+//! scan and the current selection between commands, so each look reuses the
+//! scan instead of walking the address space again.
+//!
+//! On a terminal, input is read key by key with history and tab completion
+//! of commands, their arguments and mapping names. Piped input is read line
+//! by line, so an investigation can be replayed from a file.
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use console::{Key, Term};
+use etmem_rs::report::RegionReport;
+use etmem_rs::{
+    AddressRange, HugePagePolicy, IdlePageInfo, IdlePageScanner, IdlePageStats, ScanConfig,
+    ScanFlags, SwapConfig, SwapSession, UnsupportedFlagPolicy, VmaMap, format_bytes,
+};
+
+use crate::output::{self, Column, Table, Tone, paint};
+
+/// Prompt shown before each command
+const PROMPT: &str = "memlink> ";
+
+/// Commands with their arguments and description, for `help` and completion
+const COMMANDS: [(&str, &str, &str); 11] = [
+    (
+        "attach",
+        "PID",
+        "Attach to a process, discarding the previous scan",
+    ),
+    ("detach", "", "Detach from the process"),
+    ("scan", "[huge] [dirty]", "Scan the attached process"),
+    (
+        "filter",
+        "all|idle|hot",
+        "Restrict show, regions and stats to these pages",
+    ),
+    (
+        "show",
+        "[N]",
+        "List the first N filtered pages (default 20)",
+    ),
+    (
+        "regions",
+        "[N]",
+        "Top N mappings by idle bytes (default 10)",
+    ),
+    (
+        "select",
+        "[all|NAME|START-END]",
+        "Select idle pages, of a mapping or range",
+    ),
+    ("swap", "[--yes]", "Swap out the selected pages"),
+    ("stats", "", "Page statistics of the filtered scan"),
+    ("help", "", "Show this help"),
+    ("quit", "", "Leave the shell"),
+];
+
+/// Pages `show` lists by default
+const DEFAULT_SHOW: usize = 20;
+
+/// Mappings `regions` lists by default
+const DEFAULT_REGIONS: usize = 10;
+
+/// Pages of the last scan that `show`, `regions` and `stats` look at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum PageFilter {
+    /// Every page
+    #[default]
+    All,
+    /// Idle (cold) pages
+    Idle,
+    /// Accessed (hot) pages
+    Hot,
+}
+
+impl PageFilter {
+    /// Names accepted by `filter`
+    const NAMES: [&str; 3] = ["all", "idle", "hot"];
+
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "all" => Some(Self::All),
+            "idle" => Some(Self::Idle),
+            "hot" => Some(Self::Hot),
+            _ => None,
+        }
+    }
+
+    fn matches(self, page: &IdlePageInfo) -> bool {
+        match self {
+            Self::All => true,
+            Self::Idle => page.is_idle(),
+            Self::Hot => page.is_accessed(),
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+}
+
+/// Idle pages `select` picks from the last scan
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Selection {
+    /// Every idle page
+    All,
+    /// Idle pages of the mappings with this name
+    Mapping(String),
+    /// Idle pages starting within this range
+    Range(AddressRange),
+}
+
+/// A parsed shell command
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ShellCommand {
+    Attach(u32),
+    Detach,
+    Scan { huge_only: bool, dirty: bool },
+    Filter(PageFilter),
+    Show(usize),
+    Regions(usize),
+    Select(Selection),
+    Swap { yes: bool },
+    Stats,
+    Help,
+    Quit,
+}
+
+/// Parse a command line, `None` for a blank line
+fn parse_command(line: &str) -> Result<Option<ShellCommand>, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let Some((&name, args)) = words.split_first() else {
+        return Ok(None);
+    };
+
+    let count = |default: usize| match args {
+        [] => Ok(default),
+        [n] => n.parse().map_err(|_| format!("Invalid count: {n}")),
+        _ => Err(format!("Usage: {name} [N]")),
+    };
+    let command = match (name, args) {
+        ("attach", [pid]) => {
+            ShellCommand::Attach(pid.parse().map_err(|_| format!("Invalid PID: {pid}"))?)
+        }
+        ("attach", _) => return Err("Usage: attach PID".to_string()),
+        ("detach", []) => ShellCommand::Detach,
+        ("scan", _) => {
+            let mut huge_only = false;
+            let mut dirty = false;
+            for &arg in args {
+                match arg {
+                    "huge" => huge_only = true,
+                    "dirty" => dirty = true,
+                    _ => return Err(format!("Unknown scan option: {arg}")),
+                }
+            }
+            ShellCommand::Scan { huge_only, dirty }
+        }
+        ("filter", [filter]) => ShellCommand::Filter(
+            PageFilter::parse(filter).ok_or_else(|| format!("Unknown filter: {filter}"))?,
+        ),
+        ("filter", _) => return Err("Usage: filter all|idle|hot".to_string()),
+        ("show", _) => ShellCommand::Show(count(DEFAULT_SHOW)?),
+        ("regions", _) => ShellCommand::Regions(count(DEFAULT_REGIONS)?),
+        ("select", [] | ["all"]) => ShellCommand::Select(Selection::All),
+        ("select", [target]) => ShellCommand::Select(match target.split_once('-') {
+            Some((start, end)) if start.starts_with("0x") || end.starts_with("0x") => {
+                let range =
+                    AddressRange::new(crate::parse_address(start)?, crate::parse_address(end)?);
+                if !range.is_valid() {
+                    return Err(format!("Invalid range: {target}"));
+                }
+                Selection::Range(range)
+            }
+            _ => Selection::Mapping(target.to_string()),
+        }),
+        ("swap", []) => ShellCommand::Swap { yes: false },
+        ("swap", ["--yes"]) => ShellCommand::Swap { yes: true },
+        ("stats", []) => ShellCommand::Stats,
+        ("help", _) => ShellCommand::Help,
+        ("quit" | "exit", []) => ShellCommand::Quit,
+        _ if COMMANDS.iter().any(|(command, ..)| *command == name) => {
+            return Err(format!("Invalid arguments to {name}; see help"));
+        }
+        _ => return Err(format!("Unknown command: {name}; see help")),
+    };
+    Ok(Some(command))
+}
+
+/// Complete the last word of `line`
+///
+/// Returns the byte offset where the word starts and the sorted candidates
+/// that extend it. `mappings` are the names `select` can complete.
+fn complete(line: &str, mappings: &[String]) -> (usize, Vec<String>) {
+    let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+    let word = &line[start..];
+    let previous: Vec<&str> = line[..start].split_whitespace().collect();
+
+    let options: Vec<&str> = match previous.as_slice() {
+        [] => COMMANDS.iter().map(|(command, ..)| *command).collect(),
+        ["filter"] => PageFilter::NAMES.to_vec(),
+        ["scan", ..] => vec!["huge", "dirty"],
+        ["select"] => std::iter::once("all")
+            .chain(mappings.iter().map(String::as_str))
+            .collect(),
+        ["swap"] => vec!["--yes"],
+        _ => Vec::new(),
+    };
+    let candidates: BTreeSet<String> = options
+        .into_iter()
+        .filter(|option| option.starts_with(word))
+        .map(str::to_string)
+        .collect();
+    (start, candidates.into_iter().collect())
+}
+
+/// Longest prefix shared by all candidates
+fn common_prefix(candidates: &[String]) -> &str {
+    let Some((first, rest)) = candidates.split_first() else {
+        return "";
+    };
+    let mut prefix = first.as_str();
+    for candidate in rest {
+        while !candidate.starts_with(prefix) {
+            let mut chars = prefix.chars();
+            chars.next_back();
+            prefix = chars.as_str();
+        }
+    }
+    prefix
+}
+
+/// Result of the last scan
+#[derive(Debug)]
+struct LastScan {
+    /// Scanned pages
+    pages: Vec<IdlePageInfo>,
+    /// Mappings of the process at scan time
+    vma_map: VmaMap,
+    /// When the scan finished
+    at: Instant,
+    /// How long the scan took
+    elapsed: Duration,
+}
+
+/// State kept between commands
+#[derive(Debug, Default)]
+struct ShellState {
+    /// Attached process
+    pid: Option<u32>,
+    /// Last scan of the attached process
+    scan: Option<LastScan>,
+    /// Pages `show`, `regions` and `stats` look at
+    filter: PageFilter,
+    /// Idle pages `swap` submits
+    selection: Vec<IdlePageInfo>,
+}
+
+impl ShellState {
+    fn pid(&self) -> anyhow::Result<u32> {
+        self.pid
+            .with_context(|| "No process attached; use attach PID")
+    }
+
+    fn last_scan(&self) -> anyhow::Result<&LastScan> {
+        self.scan.as_ref().with_context(|| "No scan yet; use scan")
+    }
+
+    /// Pages of the last scan that pass the filter
+    fn view(&self) -> anyhow::Result<Vec<IdlePageInfo>> {
+        Ok(self
+            .last_scan()?
+            .pages
+            .iter()
+            .filter(|page| self.filter.matches(page))
+            .cloned()
+            .collect())
+    }
+
+    /// Names of the mapped regions, for completion
+    fn mapping_names(&self) -> Vec<String> {
+        let Some(scan) = &self.scan else {
+            return Vec::new();
+        };
+        let names: BTreeSet<&str> = scan.vma_map.regions().iter().map(|r| r.name()).collect();
+        names.into_iter().map(str::to_string).collect()
+    }
+
+    /// Run a command, returning `false` once the shell should exit
+    fn execute(&mut self, command: ShellCommand, out: &mut dyn Write) -> anyhow::Result<bool> {
+        match command {
+            ShellCommand::Attach(pid) => {
+                let comm = std::fs::read_to_string(format!("/proc/{pid}/comm"))
+                    .with_context(|| format!("Process {pid} not found"))?;
+                *self = Self {
+                    pid: Some(pid),
+                    ..Self::default()
+                };
+                writeln!(out, "Attached to process {pid} ({})", comm.trim())?;
+            }
+            ShellCommand::Detach => {
+                let pid = self.pid()?;
+                *self = Self::default();
+                writeln!(out, "Detached from process {pid}")?;
+            }
+            ShellCommand::Scan { huge_only, dirty } => self.scan(huge_only, dirty, out)?,
+            ShellCommand::Filter(filter) => {
+                self.filter = filter;
+                writeln!(out, "Filter: {}", filter.as_str())?;
+            }
+            ShellCommand::Show(limit) => {
+                let pid = self.pid()?;
+                let view = self.view()?;
+                let shown = &view[..limit.min(view.len())];
+                crate::write_scan_table(
+                    out,
+                    pid,
+                    shown,
+                    &IdlePageStats::from_pages(&view),
+                    None,
+                    true,
+                )?;
+                if shown.len() < view.len() {
+                    writeln!(out, "({} of {} entries shown)", shown.len(), view.len())?;
+                }
+            }
+            ShellCommand::Regions(top) => self.write_regions(top, out)?,
+            ShellCommand::Select(selection) => {
+                let count = self.select(&selection)?;
+                let bytes: u64 = self.selection.iter().map(IdlePageInfo::total_size).sum();
+                writeln!(
+                    out,
+                    "Selected {count} idle entries ({})",
+                    format_bytes(bytes)
+                )?;
+            }
+            ShellCommand::Swap { yes } => self.swap(yes, out)?,
+            ShellCommand::Stats => self.write_stats(out)?,
+            ShellCommand::Help => write_help(out)?,
+            ShellCommand::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Scan the attached process, replacing the last scan and selection
+    fn scan(&mut self, huge_only: bool, dirty: bool, out: &mut dyn Write) -> anyhow::Result<()> {
+        let pid = self.pid()?;
+        if !etmem_rs::is_available() {
+            anyhow::bail!("ETMEM is not available. Check kernel configuration (CONFIG_ETMEM=y).");
+        }
+
+        let mut flags = ScanFlags::empty();
+        if huge_only {
+            flags |= ScanFlags::SCAN_HUGE_PAGE;
+        }
+        if dirty {
+            flags |= ScanFlags::SCAN_DIRTY_PAGE;
+        }
+        let config = ScanConfig::default()
+            .with_flags(flags)
+            .with_unsupported_flags(UnsupportedFlagPolicy::Drop);
+
+        let started = Instant::now();
+        let spinner = output::spinner(format!("Scanning process {pid}"));
+        let pages = IdlePageScanner::scan_process(pid, config)
+            .with_context(|| format!("Failed to scan process {pid}"));
+        spinner.finish_and_clear();
+        let pages = pages?;
+        let vma_map = VmaMap::for_process(pid)
+            .with_context(|| format!("Failed to read mappings of process {pid}"))?;
+
+        let stats = IdlePageStats::from_pages(&pages);
+        self.scan = Some(LastScan {
+            pages,
+            vma_map,
+            at: Instant::now(),
+            elapsed: started.elapsed(),
+        });
+        self.selection.clear();
+        writeln!(
+            out,
+            "Scanned {} in {}: {} idle, {} accessed",
+            format_bytes(stats.total_bytes),
+            crate::format_duration(started.elapsed()),
+            paint(format_bytes(stats.idle_bytes), Tone::Warn),
+            paint(format_bytes(stats.accessed_bytes), Tone::Good)
+        )?;
+        Ok(())
+    }
+
+    /// Replace the selection with idle pages of the last scan
+    ///
+    /// Returns the number of selected entries.
+    fn select(&mut self, selection: &Selection) -> anyhow::Result<usize> {
+        let scan = self.last_scan()?;
+        let idle = scan.pages.iter().filter(|page| page.is_idle());
+        let selected: Vec<IdlePageInfo> = match selection {
+            Selection::All => idle.cloned().collect(),
+            Selection::Range(range) => idle
+                .filter(|page| range.contains(page.address))
+                .cloned()
+                .collect(),
+            Selection::Mapping(name) => {
+                if !scan.vma_map.regions().iter().any(|r| r.name() == name) {
+                    anyhow::bail!("No mapping named {name}");
+                }
+                idle.filter(|page| {
+                    scan.vma_map
+                        .find_region(page.address)
+                        .is_some_and(|r| r.name() == name)
+                })
+                .cloned()
+                .collect()
+            }
+        };
+        self.selection = selected;
+        Ok(self.selection.len())
+    }
+
+    /// Swap out the selection
+    fn swap(&mut self, yes: bool, out: &mut dyn Write) -> anyhow::Result<()> {
+        let pid = self.pid()?;
+        if self.selection.is_empty() {
+            anyhow::bail!("Nothing selected; use select");
+        }
+        crate::target::confirm_swap_target(pid, "Swap", yes)?;
+
+        let mut session = SwapSession::new(pid, SwapConfig::default())
+            .with_context(|| format!("Failed to open swap session for process {pid}"))?;
+        let queued = session
+            .add_pages(&self.selection, HugePagePolicy::default())
+            .and_then(|queued| session.flush().map(|_| queued))
+            .with_context(|| format!("Failed to swap pages in process {pid}"))?;
+        let bytes: u64 = self.selection.iter().map(IdlePageInfo::total_size).sum();
+        self.selection.clear();
+        writeln!(
+            out,
+            "{} {queued} pages ({}); scan again to see the effect",
+            paint("Swapped", Tone::Good),
+            format_bytes(bytes)
+        )?;
+        Ok(())
+    }
+
+    /// Write the mappings holding the most idle memory
+    fn write_regions(&self, top: usize, out: &mut dyn Write) -> anyhow::Result<()> {
+        let scan = self.last_scan()?;
+        let report = RegionReport::build(&scan.vma_map, &self.view()?);
+        let mut table = Table::new([
+            Column::left("Mapping"),
+            Column::right("Size"),
+            Column::right("Idle"),
+            Column::right("Hot"),
+            Column::right("Idle%"),
+        ]);
+        for region in report
+            .by_name()
+            .into_iter()
+            .filter(|r| r.present_bytes() > 0)
+            .take(top)
+        {
+            table.row([
+                region.name.clone(),
+                format_bytes(region.size_bytes),
+                format_bytes(region.idle_bytes),
+                format_bytes(region.hot_bytes),
+                format!("{:.1}%", region.idle_ratio() * 100.0),
+            ]);
+        }
+        table.write(out)?;
+        Ok(())
+    }
+
+    /// Write statistics of the filtered scan and the selection
+    fn write_stats(&self, out: &mut dyn Write) -> anyhow::Result<()> {
+        let pid = self.pid()?;
+        let scan = self.last_scan()?;
+        let stats = IdlePageStats::from_pages(&self.view()?);
+        writeln!(
+            out,
+            "Process {pid}, scanned {} ago in {}, filter {}",
+            crate::format_duration(scan.at.elapsed()),
+            crate::format_duration(scan.elapsed),
+            self.filter.as_str()
+        )?;
+        writeln!(
+            out,
+            "  Idle pages:     {} ({})",
+            stats.idle_pages,
+            format_bytes(stats.idle_bytes)
+        )?;
+        writeln!(
+            out,
+            "  Accessed pages: {} ({})",
+            stats.accessed_pages,
+            format_bytes(stats.accessed_bytes)
+        )?;
+        writeln!(out, "  Huge pages:     {}", stats.huge_pages)?;
+        writeln!(out, "  Idle ratio:     {:.1}%", stats.idle_ratio() * 100.0)?;
+        let selected: u64 = self.selection.iter().map(IdlePageInfo::total_size).sum();
+        writeln!(
+            out,
+            "  Selected:       {} entries ({})",
+            self.selection.len(),
+            format_bytes(selected)
+        )?;
+        Ok(())
+    }
+}
+
+/// Write the command list
+fn write_help(out: &mut dyn Write) -> io::Result<()> {
+    let mut table = Table::new([Column::left("Command"), Column::left("Description")]);
+    for (command, args, description) in COMMANDS {
+        table.row([format!("{command} {args}"), description.to_string()]);
+    }
+    table.write(out)?;
+    writeln!(out, "Tab completes commands, options and mapping names.")
+}
+
+/// Reads commands key by key on a terminal and line by line otherwise
+struct LineEditor {
+    term: Term,
+    interactive: bool,
+    history: Vec<String>,
+}
+
+impl LineEditor {
+    fn new() -> Self {
+        let term = Term::stdout();
+        let interactive = term.is_term() && io::stdin().is_terminal();
+        Self {
+            term,
+            interactive,
+            history: Vec::new(),
+        }
+    }
+
+    /// Read the next command line, `None` at end of input
+    fn read_line(&mut self, mappings: &[String]) -> io::Result<Option<String>> {
+        if !self.interactive {
+            let mut line = String::new();
+            return Ok((io::stdin().lock().read_line(&mut line)? > 0).then_some(line));
+        }
+
+        let mut line = String::new();
+        let mut recall = self.history.len();
+        self.term.write_str(PROMPT)?;
+        loop {
+            match self.term.read_key_raw()? {
+                Key::Enter => break,
+                Key::Char('\u{4}') if line.is_empty() => {
+                    self.term.write_line("")?;
+                    return Ok(None);
+                }
+                Key::Char(c) if !c.is_control() => {
+                    line.push(c);
+                    self.term.write_str(c.encode_utf8(&mut [0; 4]))?;
+                }
+                Key::Backspace if line.pop().is_some() => self.term.clear_chars(1)?,
+                Key::CtrlC => {
+                    line.clear();
+                    self.term.write_line("^C")?;
+                    self.term.write_str(PROMPT)?;
+                }
+                Key::ArrowUp if recall > 0 => {
+                    recall -= 1;
+                    line.clone_from(&self.history[recall]);
+                    self.redraw(&line)?;
+                }
+                Key::ArrowDown if recall < self.history.len() => {
+                    recall += 1;
+                    line = self.history.get(recall).cloned().unwrap_or_default();
+                    self.redraw(&line)?;
+                }
+                Key::Tab => self.complete(&mut line, mappings)?,
+                _ => {}
+            }
+        }
+        self.term.write_line("")?;
+
+        if !line.trim().is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+        }
+        Ok(Some(line))
+    }
+
+    /// Complete the last word, or list the candidates if it is ambiguous
+    fn complete(&self, line: &mut String, mappings: &[String]) -> io::Result<()> {
+        let (start, candidates) = complete(line, mappings);
+        match candidates.as_slice() {
+            [] => return Ok(()),
+            [only] => {
+                line.truncate(start);
+                line.push_str(only);
+                line.push(' ');
+            }
+            _ => {
+                let prefix = common_prefix(&candidates);
+                if prefix.len() > line.len() - start {
+                    line.truncate(start);
+                    line.push_str(prefix);
+                } else {
+                    self.term.write_line("")?;
+                    self.term.write_line(&candidates.join("  "))?;
+                }
+            }
+        }
+        self.redraw(line)
+    }
+
+    fn redraw(&self, line: &str) -> io::Result<()> {
+        self.term.clear_line()?;
+        self.term.write_str(PROMPT)?;
+        self.term.write_str(line)
+    }
+}
+
+/// Run the shell until `quit` or end of input
+///
+/// Commands that fail print their error and leave the state unchanged.
+pub(crate) fn run(pid: Option<u32>) -> anyhow::Result<()> {
+    let mut state = ShellState::default();
+    let mut editor = LineEditor::new();
+    let mut stdout = io::stdout();
+    if editor.interactive {
+        output::status("memlink shell: type help for commands, tab to complete");
+    }
+    if let Some(pid) = pid {
+        state.execute(ShellCommand::Attach(pid), &mut stdout)?;
+    }
+
+    while let Some(line) = editor.read_line(&state.mapping_names())? {
+        let result = match parse_command(&line) {
+            Ok(Some(command)) => state.execute(command, &mut stdout),
+            Ok(None) => Ok(true),
+            Err(message) => Err(anyhow::anyhow!(message)),
+        };
+        match result {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("{} {e:#}", paint("Error:", Tone::Bad)),
+        }
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etmem_rs::ProcIdlePageType;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("  "), Ok(None));
+        assert_eq!(
+            parse_command("attach 42"),
+            Ok(Some(ShellCommand::Attach(42)))
+        );
+        assert_eq!(
+            parse_command("scan dirty"),
+            Ok(Some(ShellCommand::Scan {
+                huge_only: false,
+                dirty: true
+            }))
+        );
+        assert_eq!(
+            parse_command("filter idle"),
+            Ok(Some(ShellCommand::Filter(PageFilter::Idle)))
+        );
+        assert_eq!(parse_command("show"), Ok(Some(ShellCommand::Show(20))));
+        assert_eq!(
+            parse_command("regions 3"),
+            Ok(Some(ShellCommand::Regions(3)))
+        );
+        assert_eq!(
+            parse_command("select 0x1000-0x3000"),
+            Ok(Some(ShellCommand::Select(Selection::Range(
+                AddressRange::new(0x1000, 0x3000)
+            ))))
+        );
+        assert_eq!(
+            parse_command("select /usr/lib/libc-2.31.so"),
+            Ok(Some(ShellCommand::Select(Selection::Mapping(
+                "/usr/lib/libc-2.31.so".to_string()
+            ))))
+        );
+        assert_eq!(
+            parse_command("swap --yes"),
+            Ok(Some(ShellCommand::Swap { yes: true }))
+        );
+        assert_eq!(parse_command("exit"), Ok(Some(ShellCommand::Quit)));
+
+        assert!(parse_command("attach abc").is_err());
+        assert!(parse_command("filter warm").is_err());
+        assert!(parse_command("select 0x3000-0x1000").is_err());
+        assert!(parse_command("swap now").is_err());
+        assert!(parse_command("frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_complete() {
+        let mappings = vec!["/usr/lib/libc.so.6".to_string(), "[heap]".to_string()];
+        assert_eq!(
+            complete("s", &mappings),
+            (
+                0,
+                vec![
+                    "scan".to_string(),
+                    "select".to_string(),
+                    "show".to_string(),
+                    "stats".to_string(),
+                    "swap".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            complete("filter i", &mappings),
+            (7, vec!["idle".to_string()])
+        );
+        assert_eq!(
+            complete("select [h", &mappings),
+            (7, vec!["[heap]".to_string()])
+        );
+        assert_eq!(
+            complete("scan huge d", &mappings),
+            (10, vec!["dirty".to_string()])
+        );
+        assert!(complete("attach ", &mappings).1.is_empty());
+
+        let candidates = vec!["select".to_string(), "selection".to_string()];
+        assert_eq!(common_prefix(&candidates), "select");
+        assert_eq!(common_prefix(&complete("s", &mappings).1), "s");
+    }
+
+    #[test]
+    fn test_filter_and_select() {
+        let pages = vec![
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x3000, ProcIdlePageType::PteAccessed, 1),
+            IdlePageInfo::new(0x8000, ProcIdlePageType::PteIdle, 4),
+        ];
+        let mut state = ShellState {
+            pid: Some(std::process::id()),
+            scan: Some(LastScan {
+                pages,
+                vma_map: VmaMap::from_file("/dev/null", std::process::id()).unwrap(),
+                at: Instant::now(),
+                elapsed: Duration::from_millis(5),
+            }),
+            ..ShellState::default()
+        };
+
+        let mut out = Vec::new();
+        assert!(
+            state
+                .execute(ShellCommand::Filter(PageFilter::Hot), &mut out)
+                .unwrap()
+        );
+        assert_eq!(state.view().unwrap().len(), 1);
+
+        // Selection ignores the filter and only takes idle pages
+        assert_eq!(state.select(&Selection::All).unwrap(), 2);
+        let range = Selection::Range(AddressRange::new(0x2000, 0x9000));
+        assert_eq!(state.select(&range).unwrap(), 1);
+        assert_eq!(state.selection[0].address, 0x8000);
+        assert!(
+            state
+                .select(&Selection::Mapping("[heap]".to_string()))
+                .is_err()
+        );
+
+        state.execute(ShellCommand::Stats, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("filter hot"));
+        assert!(text.contains("Selected:       1 entries (16.00 KB)"));
+
+        assert!(!state.execute(ShellCommand::Quit, &mut Vec::new()).unwrap());
+        state
+            .execute(ShellCommand::Detach, &mut Vec::new())
+            .unwrap();
+        assert!(state.scan.is_none());
+        assert!(state.pid().is_err());
+    }
+}
@@ -0,0 +1,217 @@
+//! Ownership check before swapping another process's memory
+//!
+//! `memlink etmem swap` and `autoswap` act on any pid the caller can reach,
+//! which as root is every service on the host. A target that runs under a
+//! different uid or in a different cgroup than memlink itself is treated as
+//! foreign: its owner is shown and the operation needs confirmation, either
+//! interactively or with `--yes` for automation.
+//!
+//! Uids are compared as the host sees them. For targets in a user
+//! namespace, such as rootless containers, the uid inside the namespace is
+//! shown as well, since root in a container is an unprivileged host uid.
+
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::Context;
+use etmem_rs::container::UidMap;
+
+/// Owner of a process as seen in procfs
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ProcessOwner {
+    /// Command name
+    comm: String,
+    /// Effective user ID
+    uid: u32,
+    /// Effective user ID inside the process's user namespace, if it is not
+    /// the initial one
+    namespace_uid: Option<u32>,
+    /// Cgroup v2 path (or the first hierarchy on v1)
+    cgroup: String,
+}
+
+impl ProcessOwner {
+    /// Read the owner of `pid` (`"self"` for memlink itself)
+    fn read(pid: &str) -> anyhow::Result<Self> {
+        let status = fs::read_to_string(format!("/proc/{pid}/status"))
+            .with_context(|| format!("Failed to read status of process {pid}"))?;
+        let cgroup = fs::read_to_string(format!("/proc/{pid}/cgroup"))
+            .with_context(|| format!("Failed to read cgroup of process {pid}"))?;
+        let comm = fs::read_to_string(format!("/proc/{pid}/comm")).unwrap_or_default();
+        let uid = parse_effective_uid(&status)
+            .with_context(|| format!("No Uid line in status of process {pid}"))?;
+        let namespace_uid = fs::read_to_string(format!("/proc/{pid}/uid_map"))
+            .ok()
+            .and_then(|map| UidMap::parse(&map).ok())
+            .filter(|map| !map.is_identity())
+            .and_then(|map| map.to_namespace(uid));
+
+        Ok(Self {
+            comm: comm.trim().to_string(),
+            uid,
+            namespace_uid,
+            cgroup: parse_cgroup(&cgroup),
+        })
+    }
+
+    /// Describe the uid, with the uid inside the user namespace if any
+    fn describe_uid(&self) -> String {
+        match self.namespace_uid {
+            Some(inner) => format!("uid {} ({inner} in its user namespace)", self.uid),
+            None => format!("uid {}", self.uid),
+        }
+    }
+
+    /// Check if `other` belongs to a different user or service
+    fn is_foreign(&self, other: &Self) -> bool {
+        self.uid != other.uid || self.cgroup != other.cgroup
+    }
+}
+
+/// Parse the effective uid from `/proc/<pid>/status`
+fn parse_effective_uid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|ids| ids.split_whitespace().nth(1))
+        .and_then(|uid| uid.parse().ok())
+}
+
+/// Parse the cgroup path from `/proc/<pid>/cgroup`
+///
+/// Prefers the unified (v2) hierarchy `0::<path>`.
+fn parse_cgroup(content: &str) -> String {
+    let path_of = |line: &str| line.splitn(3, ':').nth(2).map(str::to_string);
+    content
+        .lines()
+        .find(|line| line.starts_with("0::"))
+        .and_then(path_of)
+        .or_else(|| content.lines().next().and_then(path_of))
+        .unwrap_or_default()
+}
+
+/// Ask a yes/no question; anything but `y`/`yes` is no
+fn confirm(question: &str, input: &mut impl BufRead, out: &mut impl Write) -> io::Result<bool> {
+    write!(out, "{question} [y/N] ")?;
+    out.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Make sure the caller means to swap memory of `pid`
+///
+/// Targets owned by the same uid in the same cgroup as memlink pass
+/// silently. Foreign targets pass with `yes`, after an interactive
+/// confirmation on a terminal, and fail otherwise.
+pub(crate) fn confirm_swap_target(pid: u32, action: &str, yes: bool) -> anyhow::Result<()> {
+    if pid == std::process::id() {
+        return Ok(());
+    }
+    let own = ProcessOwner::read("self")?;
+    let target = ProcessOwner::read(&pid.to_string())?;
+    if !own.is_foreign(&target) {
+        return Ok(());
+    }
+
+    let description = format!(
+        "Process {pid} ({}) runs as {} in cgroup {}, memlink as {} in {}",
+        target.comm,
+        target.describe_uid(),
+        target.cgroup,
+        own.describe_uid(),
+        own.cgroup
+    );
+    if yes {
+        log::warn!("{description}; continuing because of --yes");
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("{description}. Pass --yes to {action} it anyway.");
+    }
+
+    eprintln!("{description}.");
+    let confirmed = confirm(
+        &format!("{action} process {pid} anyway?"),
+        &mut io::stdin().lock(),
+        &mut io::stderr(),
+    )
+    .with_context(|| "Failed to read confirmation")?;
+    if !confirmed {
+        anyhow::bail!("Aborted: process {pid} was not confirmed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner(uid: u32, cgroup: &str) -> ProcessOwner {
+        ProcessOwner {
+            comm: "test".to_string(),
+            uid,
+            namespace_uid: None,
+            cgroup: cgroup.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_effective_uid() {
+        let status = "Name:\tnginx\nUid:\t1000\t33\t33\t33\nGid:\t33\t33\t33\t33\n";
+        assert_eq!(parse_effective_uid(status), Some(33));
+        assert_eq!(parse_effective_uid("Name:\tx\n"), None);
+    }
+
+    #[test]
+    fn test_parse_cgroup() {
+        assert_eq!(
+            parse_cgroup("0::/system.slice/nginx.service\n"),
+            "/system.slice/nginx.service"
+        );
+        // Hybrid hierarchy: the unified line wins
+        let hybrid = "12:memory:/user.slice\n0::/user.slice/session-3.scope\n";
+        assert_eq!(parse_cgroup(hybrid), "/user.slice/session-3.scope");
+        assert_eq!(parse_cgroup("4:cpu,cpuacct:/db\n"), "/db");
+        assert_eq!(parse_cgroup(""), "");
+    }
+
+    #[test]
+    fn test_is_foreign() {
+        let own = owner(0, "/user.slice/session-3.scope");
+        assert!(!own.is_foreign(&owner(0, "/user.slice/session-3.scope")));
+        assert!(own.is_foreign(&owner(33, "/user.slice/session-3.scope")));
+        assert!(own.is_foreign(&owner(0, "/system.slice/db.service")));
+    }
+
+    #[test]
+    fn test_describe_uid() {
+        let mut target = owner(100000, "/system.slice/docker-abc.scope");
+        assert_eq!(target.describe_uid(), "uid 100000");
+        target.namespace_uid = Some(0);
+        assert_eq!(
+            target.describe_uid(),
+            "uid 100000 (0 in its user namespace)"
+        );
+    }
+
+    #[test]
+    fn test_confirm() {
+        let mut out = Vec::new();
+        assert!(confirm("Swap?", &mut "y\n".as_bytes(), &mut out).unwrap());
+        assert!(confirm("Swap?", &mut "YES\n".as_bytes(), &mut out).unwrap());
+        assert!(!confirm("Swap?", &mut "\n".as_bytes(), &mut out).unwrap());
+        assert!(!confirm("Swap?", &mut "".as_bytes(), &mut out).unwrap());
+        assert!(String::from_utf8(out).unwrap().starts_with("Swap? [y/N] "));
+    }
+
+    #[test]
+    fn test_self_is_not_foreign() {
+        let own = ProcessOwner::read("self").unwrap();
+        assert!(!own.is_foreign(&own.clone()));
+        assert!(confirm_swap_target(std::process::id(), "Swap", false).is_ok());
+    }
+}
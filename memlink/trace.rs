@@ -0,0 +1,362 @@
+//! Chrome trace-event export of reclaim runs for `memlink etmem autoswap --trace`
+//!
+//! The recorder collects policy-cycle spans (scan, swap), counters and
+//! per-region state transitions and writes them in the Chrome trace-event
+//! JSON format, which Perfetto (ui.perfetto.dev) and `chrome://tracing`
+//! open directly. Timestamps are microseconds since the recorder was
+//! created. Each region gets its own track, named after the mapping, on
+//! which consecutive cycles in the same state form one span.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Instant;
+
+use anyhow::Context;
+use etmem_rs::report::RegionReport;
+use serde::Serialize;
+use serde_json::{Value, json};
+
+/// Track id of the policy-cycle events
+const POLICY_TID: u64 = 0;
+
+/// Idle ratio at or above which a region is cold
+const COLD_RATIO: f64 = 0.75;
+
+/// Idle ratio below which a region is hot
+const HOT_RATIO: f64 = 0.25;
+
+/// Access state of a region in one scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RegionState {
+    /// Mostly accessed since the previous scan
+    Hot,
+    /// Partly idle
+    Warm,
+    /// Mostly idle
+    Cold,
+}
+
+impl RegionState {
+    fn from_idle_ratio(ratio: f64) -> Self {
+        if ratio >= COLD_RATIO {
+            Self::Cold
+        } else if ratio < HOT_RATIO {
+            Self::Hot
+        } else {
+            Self::Warm
+        }
+    }
+
+    const fn as_str(self) -> &'static str {
+        match self {
+            Self::Hot => "hot",
+            Self::Warm => "warm",
+            Self::Cold => "cold",
+        }
+    }
+}
+
+/// One entry of the `traceEvents` array
+#[derive(Debug, Clone, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    ts: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dur: Option<u64>,
+    pid: u32,
+    tid: u64,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    args: Value,
+}
+
+/// Open state span of a region track
+#[derive(Debug, Clone, Copy)]
+struct RegionTrack {
+    tid: u64,
+    state: RegionState,
+    since: u64,
+    idle_ratio: f64,
+}
+
+/// Collects trace events of one reclaim run
+#[derive(Debug)]
+pub(crate) struct TraceRecorder {
+    start: Instant,
+    pid: u32,
+    events: Vec<TraceEvent>,
+    regions: HashMap<String, RegionTrack>,
+    next_tid: u64,
+}
+
+impl TraceRecorder {
+    /// Start recording a run against process `pid`
+    pub(crate) fn new(pid: u32) -> Self {
+        let mut recorder = Self {
+            start: Instant::now(),
+            pid,
+            events: Vec::new(),
+            regions: HashMap::new(),
+            next_tid: POLICY_TID + 1,
+        };
+        recorder.name_track(POLICY_TID, "policy");
+        recorder
+    }
+
+    fn micros(&self, at: Instant) -> u64 {
+        u64::try_from(at.saturating_duration_since(self.start).as_micros()).unwrap_or(u64::MAX)
+    }
+
+    fn name_track(&mut self, tid: u64, name: &str) {
+        self.events.push(TraceEvent {
+            name: "thread_name".to_string(),
+            cat: "__metadata",
+            ph: "M",
+            ts: 0,
+            dur: None,
+            pid: self.pid,
+            tid,
+            args: json!({ "name": name }),
+        });
+    }
+
+    /// Record a policy step (scan, swap, ...) that ran from `start` to `end`
+    pub(crate) fn span(&mut self, name: &str, start: Instant, end: Instant, args: Value) {
+        let ts = self.micros(start);
+        self.events.push(TraceEvent {
+            name: name.to_string(),
+            cat: "policy",
+            ph: "X",
+            ts,
+            dur: Some(self.micros(end).saturating_sub(ts)),
+            pid: self.pid,
+            tid: POLICY_TID,
+            args,
+        });
+    }
+
+    /// Record counter values (e.g. idle and cold bytes) at `at`
+    pub(crate) fn counter(&mut self, name: &str, at: Instant, values: Value) {
+        self.events.push(TraceEvent {
+            name: name.to_string(),
+            cat: "policy",
+            ph: "C",
+            ts: self.micros(at),
+            dur: None,
+            pid: self.pid,
+            tid: POLICY_TID,
+            args: values,
+        });
+    }
+
+    /// Record a policy event (e.g. an address-space change) at `at`
+    pub(crate) fn instant(&mut self, name: &str, at: Instant, args: Value) {
+        self.events.push(TraceEvent {
+            name: name.to_string(),
+            cat: "policy",
+            ph: "i",
+            ts: self.micros(at),
+            dur: None,
+            pid: self.pid,
+            tid: POLICY_TID,
+            args,
+        });
+    }
+
+    /// Update region states from the scan that completed at `at`
+    ///
+    /// A region whose state changed closes its previous span. Regions that
+    /// disappeared from the mappings are closed as well.
+    pub(crate) fn regions(&mut self, at: Instant, report: &RegionReport) {
+        let ts = self.micros(at);
+        let mut seen = Vec::new();
+        for region in report.by_name() {
+            let ratio = region.idle_ratio();
+            let state = RegionState::from_idle_ratio(ratio);
+            seen.push(region.name.clone());
+            match self.regions.get(&region.name).copied() {
+                Some(track) if track.state == state => {}
+                Some(track) => {
+                    self.close_region(&region.name, track, ts);
+                    self.open_region(region.name, track.tid, state, ts, ratio);
+                }
+                None => {
+                    let tid = self.next_tid;
+                    self.next_tid += 1;
+                    self.name_track(tid, &region.name);
+                    self.open_region(region.name, tid, state, ts, ratio);
+                }
+            }
+        }
+
+        let gone: Vec<(String, RegionTrack)> = self
+            .regions
+            .iter()
+            .filter(|(name, _)| !seen.contains(name))
+            .map(|(name, track)| (name.clone(), *track))
+            .collect();
+        for (name, track) in gone {
+            self.close_region(&name, track, ts);
+            self.regions.remove(&name);
+        }
+    }
+
+    fn open_region(&mut self, name: String, tid: u64, state: RegionState, ts: u64, ratio: f64) {
+        self.regions.insert(
+            name,
+            RegionTrack {
+                tid,
+                state,
+                since: ts,
+                idle_ratio: ratio,
+            },
+        );
+    }
+
+    fn close_region(&mut self, name: &str, track: RegionTrack, ts: u64) {
+        self.events.push(TraceEvent {
+            name: track.state.as_str().to_string(),
+            cat: "region",
+            ph: "X",
+            ts: track.since,
+            dur: Some(ts.saturating_sub(track.since)),
+            pid: self.pid,
+            tid: track.tid,
+            args: json!({ "region": name, "idle_ratio": track.idle_ratio }),
+        });
+    }
+
+    /// Close all open region spans at `at` and write the trace
+    pub(crate) fn finish<W: Write>(mut self, at: Instant, mut out: W) -> anyhow::Result<()> {
+        let ts = self.micros(at);
+        let mut open: Vec<(String, RegionTrack)> = self.regions.drain().collect();
+        open.sort_by_key(|(_, track)| track.tid);
+        for (name, track) in open {
+            self.close_region(&name, track, ts);
+        }
+
+        serde_json::to_writer(
+            &mut out,
+            &json!({
+                "traceEvents": self.events,
+                "displayTimeUnit": "ms",
+            }),
+        )
+        .with_context(|| "Failed to write trace")?;
+        out.flush().with_context(|| "Failed to write trace")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use etmem_rs::report::RegionStats;
+    use etmem_rs::vma::PathnameType;
+
+    fn report(regions: &[(&str, u64)]) -> RegionReport {
+        RegionReport {
+            pid: 1,
+            regions: regions
+                .iter()
+                .map(|&(name, idle_bytes)| RegionStats {
+                    name: name.to_string(),
+                    pathname_type: PathnameType::Anonymous,
+                    file_backed: false,
+                    dax: None,
+                    ranges: Vec::new(),
+                    size_bytes: 100,
+                    idle_bytes,
+                    hot_bytes: 100 - idle_bytes,
+                    dirty_bytes: 0,
+                    hole_bytes: 0,
+                    swapped_bytes: 0,
+                })
+                .collect(),
+            unmapped_bytes: 0,
+        }
+    }
+
+    fn events_of(recorder: TraceRecorder, at: Instant) -> Vec<Value> {
+        let mut buf = Vec::new();
+        recorder.finish(at, &mut buf).unwrap();
+        let doc: Value = serde_json::from_slice(&buf).unwrap();
+        doc["traceEvents"].as_array().unwrap().clone()
+    }
+
+    #[test]
+    fn test_region_state() {
+        assert_eq!(RegionState::from_idle_ratio(0.0), RegionState::Hot);
+        assert_eq!(RegionState::from_idle_ratio(0.5), RegionState::Warm);
+        assert_eq!(RegionState::from_idle_ratio(0.75), RegionState::Cold);
+    }
+
+    #[test]
+    fn test_policy_span() {
+        let mut recorder = TraceRecorder::new(42);
+        let start = recorder.start + Duration::from_millis(2);
+        recorder.span(
+            "scan",
+            start,
+            start + Duration::from_millis(3),
+            json!({ "cycle": 1 }),
+        );
+        recorder.instant(
+            "maps_changed",
+            start + Duration::from_millis(3),
+            json!({ "generation": 1 }),
+        );
+
+        let events = events_of(recorder, start);
+        assert_eq!(events[0]["ph"], "M");
+        assert_eq!(events[0]["args"]["name"], "policy");
+        assert_eq!(events[1]["name"], "scan");
+        assert_eq!(events[1]["ph"], "X");
+        assert_eq!(events[1]["ts"], 2000);
+        assert_eq!(events[1]["dur"], 3000);
+        assert_eq!(events[1]["pid"], 42);
+        assert_eq!(events[1]["args"]["cycle"], 1);
+        assert_eq!(events[2]["name"], "maps_changed");
+        assert_eq!(events[2]["ph"], "i");
+        assert_eq!(events[2]["ts"], 5000);
+        assert!(events[2].get("dur").is_none());
+    }
+
+    #[test]
+    fn test_region_transitions() {
+        let mut recorder = TraceRecorder::new(1);
+        let t0 = recorder.start;
+        let ms = Duration::from_millis(1);
+        recorder.regions(t0, &report(&[("[heap]", 10), ("libc.so", 90)]));
+        // Same states: no span is closed
+        recorder.regions(t0 + ms, &report(&[("[heap]", 20), ("libc.so", 90)]));
+        // Heap turns cold, libc is unmapped
+        recorder.regions(t0 + 2 * ms, &report(&[("[heap]", 80)]));
+
+        let spans: Vec<Value> = events_of(recorder, t0 + 3 * ms)
+            .into_iter()
+            .filter(|e| e["cat"] == "region")
+            .collect();
+        let summary: Vec<(String, String, u64, u64)> = spans
+            .iter()
+            .map(|e| {
+                (
+                    e["args"]["region"].as_str().unwrap().to_string(),
+                    e["name"].as_str().unwrap().to_string(),
+                    e["ts"].as_u64().unwrap(),
+                    e["dur"].as_u64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("[heap]".to_string(), "hot".to_string(), 0, 2000),
+                ("libc.so".to_string(), "cold".to_string(), 0, 2000),
+                ("[heap]".to_string(), "cold".to_string(), 2000, 1000),
+            ]
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Live terminal view of hot/cold memory for `memlink etmem watch`
+//!
+//! Each refresh scans the target process, joins the results with its
+//! mappings and redraws a top(1)-style screen with per-region idle, hot
+//! and swapped bytes, idle ratio sparklines and system reclaim counters.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use etmem_rs::report::{RegionReport, RegionStats};
+use etmem_rs::{IdlePageScanner, ScanConfig, VmaMap, format_bytes};
+use serde::Serialize;
+
+/// Number of samples kept for each sparkline
+const HISTORY_LEN: usize = 30;
+
+/// Characters used to draw sparklines, from lowest to highest
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// ANSI sequence clearing the screen and moving the cursor home
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Options for the watch loop
+#[derive(Debug, Clone)]
+pub(crate) struct WatchOptions {
+    /// Process to watch
+    pub pid: u32,
+    /// Time between refreshes
+    pub interval: Duration,
+    /// Number of regions to display
+    pub top: usize,
+    /// Stop after this many refreshes (None = run until interrupted)
+    pub iterations: Option<u32>,
+}
+
+/// System-wide swap counters from `/proc/vmstat`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct SwapCounters {
+    pub pswpin: u64,
+    pub pswpout: u64,
+}
+
+impl SwapCounters {
+    pub(crate) fn read() -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(etmem_rs::guard::PROC_VMSTAT)
+            .with_context(|| "Failed to read /proc/vmstat")?;
+        Ok(Self {
+            pswpin: etmem_rs::guard::parse_vmstat_field(&content, "pswpin")?,
+            pswpout: etmem_rs::guard::parse_vmstat_field(&content, "pswpout")?,
+        })
+    }
+}
+
+/// State carried between refreshes
+#[derive(Debug, Default)]
+struct WatchState {
+    /// Overall idle ratio history
+    total_history: VecDeque<f64>,
+    /// Idle ratio history by region name
+    region_history: HashMap<String, VecDeque<f64>>,
+    /// Counters at startup
+    baseline: SwapCounters,
+    /// Most recent counters
+    counters: SwapCounters,
+    /// Number of refreshes so far
+    refreshes: u32,
+}
+
+impl WatchState {
+    /// Record a new report in the trend histories
+    fn record(&mut self, report: &RegionReport) {
+        let hot = report.total_hot_bytes();
+        let idle = report.total_idle_bytes();
+        push_sample(&mut self.total_history, ratio(idle, idle + hot));
+
+        for region in report.by_name() {
+            push_sample(
+                self.region_history.entry(region.name.clone()).or_default(),
+                region.idle_ratio(),
+            );
+        }
+        self.refreshes += 1;
+    }
+}
+
+/// Run the watch loop until interrupted or the iteration limit is reached
+pub(crate) fn run(options: &WatchOptions) -> anyhow::Result<()> {
+    let baseline = SwapCounters::read().unwrap_or_default();
+    let mut state = WatchState {
+        baseline,
+        counters: baseline,
+        ..WatchState::default()
+    };
+
+    loop {
+        let started = Instant::now();
+        let report = capture(options.pid)?;
+        state.counters = SwapCounters::read().unwrap_or(state.counters);
+        state.record(&report);
+
+        let mut out = io::stdout().lock();
+        write!(out, "{CLEAR_SCREEN}")?;
+        render(&mut out, options, &state, &report)?;
+        out.flush()?;
+
+        if options
+            .iterations
+            .is_some_and(|limit| state.refreshes >= limit)
+        {
+            return Ok(());
+        }
+        std::thread::sleep(options.interval.saturating_sub(started.elapsed()));
+    }
+}
+
+/// Scan the process and build a region report
+pub(crate) fn capture(pid: u32) -> anyhow::Result<RegionReport> {
+    let vma_map =
+        VmaMap::for_process(pid).with_context(|| format!("Failed to read maps of {pid}"))?;
+    let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+        .with_context(|| format!("Failed to scan process {pid}"))?;
+
+    let mut report = RegionReport::build(&vma_map, &pages);
+    if let Err(e) = report.attach_swap() {
+        log::debug!("Swap usage unavailable for {pid}: {e}");
+    }
+    Ok(report)
+}
+
+/// Draw one frame
+fn render(
+    out: &mut dyn Write,
+    options: &WatchOptions,
+    state: &WatchState,
+    report: &RegionReport,
+) -> io::Result<()> {
+    let idle = report.total_idle_bytes();
+    let hot = report.total_hot_bytes();
+    let swapped = report.total_swapped_bytes();
+
+    writeln!(
+        out,
+        "memlink etmem watch - pid {}  refresh {}  every {:.1}s",
+        options.pid,
+        state.refreshes,
+        options.interval.as_secs_f64()
+    )?;
+    writeln!(
+        out,
+        "Idle {:>10}  Hot {:>10}  Swapped {:>10}  Idle% {:>5.1}  {}",
+        format_bytes(idle),
+        format_bytes(hot),
+        format_bytes(swapped),
+        ratio(idle, idle + hot) * 100.0,
+        sparkline(&state.total_history)
+    )?;
+    writeln!(
+        out,
+        "Reclaim: {} pages out, {} pages in since start",
+        state
+            .counters
+            .pswpout
+            .saturating_sub(state.baseline.pswpout),
+        state.counters.pswpin.saturating_sub(state.baseline.pswpin)
+    )?;
+    writeln!(out)?;
+    writeln!(
+        out,
+        "{:<36} {:>10} {:>10} {:>10} {:>6}  {:<width$}",
+        "REGION",
+        "IDLE",
+        "HOT",
+        "SWAPPED",
+        "IDLE%",
+        "TREND",
+        width = HISTORY_LEN
+    )?;
+
+    let regions: Vec<RegionStats> = report
+        .by_name()
+        .into_iter()
+        .filter(|r| r.present_bytes() > 0 || r.swapped_bytes > 0)
+        .take(options.top)
+        .collect();
+
+    for region in &regions {
+        let trend = state
+            .region_history
+            .get(&region.name)
+            .map(sparkline)
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "{:<36} {:>10} {:>10} {:>10} {:>5.1}%  {}",
+            truncate_name(&region.name, 36),
+            format_bytes(region.idle_bytes),
+            format_bytes(region.hot_bytes),
+            format_bytes(region.swapped_bytes),
+            region.idle_ratio() * 100.0,
+            trend
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Append a sample, dropping the oldest beyond `HISTORY_LEN`
+fn push_sample(history: &mut VecDeque<f64>, value: f64) {
+    if history.len() == HISTORY_LEN {
+        history.pop_front();
+    }
+    history.push_back(value);
+}
+
+/// Render ratios in 0.0 - 1.0 as a sparkline
+fn sparkline(values: &VecDeque<f64>) -> String {
+    values
+        .iter()
+        .map(|v| {
+            let idx = (v.clamp(0.0, 1.0) * (SPARK_CHARS.len() - 1) as f64).round() as usize;
+            SPARK_CHARS[idx]
+        })
+        .collect()
+}
+
+/// Compute `part / whole`, returning 0 for an empty whole
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+/// Shorten long names, keeping the end (usually the file name)
+fn truncate_name(name: &str, width: usize) -> String {
+    let len = name.chars().count();
+    if len <= width {
+        return name.to_string();
+    }
+    let tail: String = name.chars().skip(len - (width - 1)).collect();
+    format!("…{tail}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparkline() {
+        let values: VecDeque<f64> = [0.0, 0.5, 1.0, 2.0].into_iter().collect();
+        assert_eq!(sparkline(&values), "▁▅██");
+    }
+
+    #[test]
+    fn test_push_sample_bounded() {
+        let mut history = VecDeque::new();
+        for i in 0..HISTORY_LEN + 5 {
+            push_sample(&mut history, i as f64);
+        }
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history[0], 5.0);
+    }
+
+    #[test]
+    fn test_truncate_name() {
+        assert_eq!(truncate_name("[heap]", 36), "[heap]");
+        assert_eq!(truncate_name("/usr/lib/libfoo.so", 10), "…libfoo.so");
+    }
+}
@@ -29,7 +29,6 @@
 
 use etmem_rs::{AddressRange, IdlePageInfo, ScanConfig, ScanSession};
 use std::env;
-use std::process;
 
 // Memory allocation size: 10 MB
 const ALLOC_SIZE: usize = 10 * 1024 * 1024;
@@ -103,7 +102,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Configure scan - use default flags to scan all pages
     let config = ScanConfig::default();
-    let mut session = ScanSession::new(process::id(), config)?;
+    let mut session = ScanSession::for_self(config)?;
 
     let range = AddressRange {
         start: ptr as u64,
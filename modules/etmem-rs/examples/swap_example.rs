@@ -12,9 +12,12 @@
 //! sudo cargo run --example swap_example --package etmem-rs
 //! ```
 
-use etmem_rs::{AddressRange, ScanConfig, ScanSession, SwapConfig, SwapSession, SwapcacheConfig};
+use etmem_rs::{
+    AddressRange, IdlePageScanner, ScanConfig, SwapConfig, SwapSession, SwapcacheConfig,
+};
 use std::env;
 use std::process;
+use std::time::Duration;
 
 // Memory allocation size: 10 MB
 const ALLOC_SIZE: usize = 10 * 1024 * 1024;
@@ -99,27 +102,20 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("\nBaseline swap: {} KB", baseline / 1024);
 
     // Step 1: Scan pages to mark as idle (required before swap)
-    // In ETMEM, pages become idle when they haven't been accessed since the last scan.
-    // We scan once (marks current state), wait, then scan again to detect idle pages.
+    // Pages not accessed between the mark and verify passes are idle.
     println!("\nScanning pages to identify idle pages...");
-
-    let scan_config = ScanConfig::default();
-    let mut scan_session = ScanSession::new(process::id(), scan_config)?;
+    println!("Waiting 2 seconds between scans for pages to become idle...");
 
     let range = AddressRange {
         start: start_addr,
         end: end_addr,
     };
-
-    // First scan - establishes baseline
-    let _ = scan_session.read_range(range)?;
-
-    // Wait for pages to become idle (not accessed)
-    println!("Waiting 2 seconds for pages to become idle...");
-    std::thread::sleep(std::time::Duration::from_secs(2));
-
-    // Second scan - pages not accessed since first scan show as idle
-    let pages = scan_session.read_range(range)?;
+    let pages = IdlePageScanner::two_pass_range(
+        process::id(),
+        range,
+        ScanConfig::default(),
+        Duration::from_secs(2),
+    )?;
 
     // Expand idle page regions into individual 4KB page addresses
     // The scan may return consolidated regions (e.g., 64KB or 2MB chunks)
@@ -0,0 +1,392 @@
+//! Cooperative reclaim for applications scanning themselves
+//!
+//! Instead of having its cold pages swapped out by an external daemon, an
+//! application can link this crate, scan its own address space and be told
+//! which regions went cold, so that it can release them itself: drop a
+//! cache, shrink a pool, or let a managed runtime compact its heap. A
+//! [`SelfAdvisor`] ages pages with the same [`PageAger`] as the reclaim
+//! policy and calls the registered handlers once per cold region.
+//!
+//! Handlers answer with an [`Advice`]. Regions the application released
+//! are forgotten until they go cold again; regions it kept are advised
+//! again in later rounds while they stay cold.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use etmem_rs::advisory::{Advice, AdvisoryConfig, SelfAdvisor};
+//!
+//! let advisor = SelfAdvisor::new(AdvisoryConfig::new().with_interval(Duration::from_secs(10)))
+//!     .on_cold(|region| {
+//!         println!("{} is cold for {} scans", region.range, region.idle_scans);
+//!         Advice::Kept
+//!     });
+//! let handle = advisor.spawn();
+//! // ... run the application ...
+//! handle.stop();
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::error::Result;
+use crate::policy::{AgingPolicy, PageAger};
+use crate::scan::ScanSession;
+use crate::types::{AddressRange, IdlePageInfo, RangeSet, ScanConfig};
+use crate::vma::VmaMap;
+
+/// Settings of a [`SelfAdvisor`]
+#[derive(Debug, Clone)]
+pub struct AdvisoryConfig {
+    /// Configuration of each scan
+    pub scan: ScanConfig,
+    /// Policy deciding when pages are cold
+    pub aging: AgingPolicy,
+    /// Smallest region worth advising, in bytes
+    pub min_region_bytes: u64,
+    /// Time between rounds of a spawned advisor
+    pub interval: Duration,
+}
+
+impl AdvisoryConfig {
+    /// Create the default configuration
+    ///
+    /// Defaults: default scan, three consecutive idle scans, 2MB regions,
+    /// 30s between rounds.
+    pub fn new() -> Self {
+        Self {
+            scan: ScanConfig::default(),
+            aging: AgingPolicy::new().with_min_idle_scans(3),
+            min_region_bytes: crate::types::HUGE_PAGE_SIZE,
+            interval: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the configuration of each scan
+    pub fn with_scan(mut self, scan: ScanConfig) -> Self {
+        self.scan = scan;
+        self
+    }
+
+    /// Set the policy deciding when pages are cold
+    pub fn with_aging(mut self, aging: AgingPolicy) -> Self {
+        self.aging = aging;
+        self
+    }
+
+    /// Set the smallest region worth advising
+    pub fn with_min_region_bytes(mut self, bytes: u64) -> Self {
+        self.min_region_bytes = bytes;
+        self
+    }
+
+    /// Set the time between rounds of a spawned advisor
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+}
+
+impl Default for AdvisoryConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Region of the application that went cold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColdRegion {
+    /// Address range of the region
+    pub range: AddressRange,
+    /// Consecutive scans the least idle page of the region was idle
+    pub idle_scans: u32,
+    /// Name of the mapping containing the region start, if known
+    pub mapping: Option<String>,
+}
+
+/// Answer of a handler to a cold region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// The application released the region; stop advising it until it
+    /// goes cold again
+    Released,
+    /// The application keeps the region; advise it again while it stays
+    /// cold
+    Kept,
+}
+
+/// Outcome of one advisory round
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AdvisoryRound {
+    /// Scan entries observed
+    pub pages_scanned: usize,
+    /// Cold regions advised
+    pub regions_advised: usize,
+    /// Bytes in regions a handler released
+    pub bytes_released: u64,
+}
+
+/// Handler called for each cold region
+type ColdHandler = Box<dyn FnMut(&ColdRegion) -> Advice + Send>;
+
+/// In-process advisor telling an application which of its regions are cold
+pub struct SelfAdvisor {
+    /// Advisor settings
+    config: AdvisoryConfig,
+    /// Idle ages of the application's pages
+    ager: PageAger,
+    /// Handlers, called in registration order
+    handlers: Vec<ColdHandler>,
+}
+
+impl fmt::Debug for SelfAdvisor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SelfAdvisor")
+            .field("config", &self.config)
+            .field("ager", &self.ager)
+            .field("handlers", &self.handlers.len())
+            .finish()
+    }
+}
+
+impl SelfAdvisor {
+    /// Create an advisor without handlers
+    pub fn new(config: AdvisoryConfig) -> Self {
+        Self {
+            ager: PageAger::new(config.aging),
+            config,
+            handlers: Vec::new(),
+        }
+    }
+
+    /// Register a handler called for each cold region
+    ///
+    /// A region counts as released if any handler released it; later
+    /// handlers are still called.
+    pub fn on_cold<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&ColdRegion) -> Advice + Send + 'static,
+    {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    /// Get the advisor settings
+    pub fn config(&self) -> &AdvisoryConfig {
+        &self.config
+    }
+
+    /// Get the page ager
+    pub fn ager(&self) -> &PageAger {
+        &self.ager
+    }
+
+    /// Scan the calling process and advise its cold regions
+    ///
+    /// Scans through [`ScanSession::for_self`], so the scanner's buffers
+    /// and the calling thread's stack are left out.
+    ///
+    /// # Errors
+    /// Returns error if the scan session cannot be created or the scan
+    /// fails.
+    pub fn poll(&mut self) -> Result<AdvisoryRound> {
+        let mut session = ScanSession::for_self(self.config.scan.clone())?;
+        let pages = session.read_range(AddressRange::new(0, u64::MAX))?;
+        let vma_map = VmaMap::for_process(std::process::id())
+            .inspect_err(|e| log::debug!("Cannot name cold regions: {}", e))
+            .ok();
+        Ok(self.advise(&pages, vma_map.as_ref()))
+    }
+
+    /// Age the pages of one scan and advise the regions that went cold
+    ///
+    /// Regions are address-contiguous runs of cold pages of at least
+    /// `min_region_bytes`, advised in address order. Ages of ranges the
+    /// application remapped since the previous round are dropped first.
+    pub fn advise(&mut self, pages: &[IdlePageInfo], vma_map: Option<&VmaMap>) -> AdvisoryRound {
+        let mut round = AdvisoryRound {
+            pages_scanned: pages.len(),
+            ..AdvisoryRound::default()
+        };
+        if let Some(map) = vma_map {
+            // The ager logs the change; nobody else listens for it here
+            self.ager.sync_maps(map);
+            self.ager.take_events();
+        }
+        self.ager.observe(pages);
+
+        let cold = self.ager.cold_pages();
+        let regions = RangeSet::from_pages(&cold);
+        for &range in regions
+            .ranges()
+            .iter()
+            .filter(|r| r.size() >= self.config.min_region_bytes)
+        {
+            let in_region: Vec<IdlePageInfo> = cold
+                .iter()
+                .filter(|p| range.contains(p.address))
+                .copied()
+                .collect();
+            let region = ColdRegion {
+                range,
+                idle_scans: in_region
+                    .iter()
+                    .map(|p| self.ager.age_of(p.address))
+                    .min()
+                    .unwrap_or(0),
+                mapping: vma_map
+                    .and_then(|map| map.find_region(range.start))
+                    .map(|vma| vma.name().to_string()),
+            };
+
+            round.regions_advised += 1;
+            let mut released = false;
+            for handler in &mut self.handlers {
+                released |= handler(&region) == Advice::Released;
+            }
+            if released {
+                self.ager.forget(&in_region);
+                round.bytes_released += range.size();
+            }
+        }
+        round
+    }
+
+    /// Run rounds on a background thread until the handle is stopped
+    ///
+    /// Failed rounds are logged and retried after the interval.
+    pub fn spawn(mut self) -> AdvisorHandle {
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let signal = Arc::clone(&stop);
+        let thread = std::thread::spawn(move || {
+            let (stopped, wake) = &*signal;
+            loop {
+                if let Err(e) = self.poll() {
+                    log::warn!("Self-advisory round failed: {}", e);
+                }
+                let guard = stopped.lock().unwrap_or_else(PoisonError::into_inner);
+                let (guard, _) = wake
+                    .wait_timeout_while(guard, self.config.interval, |stopped| !*stopped)
+                    .unwrap_or_else(PoisonError::into_inner);
+                if *guard {
+                    break;
+                }
+            }
+        });
+        AdvisorHandle {
+            stop,
+            thread: Some(thread),
+        }
+    }
+}
+
+/// Handle of an advisor spawned with [`SelfAdvisor::spawn`]
+///
+/// Dropping the handle stops the advisor.
+#[derive(Debug)]
+pub struct AdvisorHandle {
+    /// Stop flag and its wakeup
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    /// Advisor thread
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AdvisorHandle {
+    /// Stop the advisor and wait for its current round to finish
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    /// Signal the thread and join it
+    fn shutdown(&mut self) {
+        let (stopped, wake) = &*self.stop;
+        *stopped.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        wake.notify_all();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            log::warn!("Self-advisory thread panicked");
+        }
+    }
+}
+
+impl Drop for AdvisorHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProcIdlePageType;
+
+    fn advisor() -> SelfAdvisor {
+        SelfAdvisor::new(
+            AdvisoryConfig::new()
+                .with_aging(AgingPolicy::new().with_min_idle_scans(2))
+                .with_min_region_bytes(0x2000),
+        )
+    }
+
+    #[test]
+    fn test_advise_cold_regions() {
+        let advised = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&advised);
+        let mut advisor = advisor().on_cold(move |region| {
+            seen.lock().unwrap().push(region.clone());
+            Advice::Kept
+        });
+
+        // Two idle runs, of three pages and of a single page
+        let pages = [
+            IdlePageInfo::new(0x10000, ProcIdlePageType::PteIdle, 3),
+            IdlePageInfo::new(0x20000, ProcIdlePageType::PteIdle, 1),
+            IdlePageInfo::new(0x30000, ProcIdlePageType::PteAccessed, 4),
+        ];
+        let round = advisor.advise(&pages, None);
+        assert_eq!(round.pages_scanned, 3);
+        assert_eq!(round.regions_advised, 0);
+
+        // Cold after the second scan; the single page is below the minimum
+        let round = advisor.advise(&pages, None);
+        assert_eq!(round.regions_advised, 1);
+        assert_eq!(round.bytes_released, 0);
+        assert_eq!(
+            advised.lock().unwrap().as_slice(),
+            [ColdRegion {
+                range: AddressRange::new(0x10000, 0x13000),
+                idle_scans: 2,
+                mapping: None,
+            }]
+        );
+
+        // Kept regions are advised again
+        assert_eq!(advisor.advise(&pages, None).regions_advised, 1);
+        assert_eq!(advised.lock().unwrap()[1].idle_scans, 3);
+    }
+
+    #[test]
+    fn test_released_regions_are_forgotten() {
+        let calls = Arc::new(Mutex::new(0));
+        let counted = Arc::clone(&calls);
+        let mut advisor = advisor().on_cold(|_| Advice::Kept).on_cold(move |_| {
+            *counted.lock().unwrap() += 1;
+            Advice::Released
+        });
+
+        let pages = [IdlePageInfo::new(0x10000, ProcIdlePageType::PteIdle, 2)];
+        advisor.advise(&pages, None);
+        let round = advisor.advise(&pages, None);
+        assert_eq!(round.bytes_released, 0x2000);
+        assert_eq!(advisor.ager().age_of(0x10000), 0);
+
+        // Released pages must age again before they are advised
+        assert_eq!(advisor.advise(&pages, None).regions_advised, 0);
+        assert_eq!(advisor.advise(&pages, None).regions_advised, 1);
+        assert_eq!(*calls.lock().unwrap(), 2);
+    }
+}
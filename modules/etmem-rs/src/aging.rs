@@ -0,0 +1,539 @@
+//! Multi-generation idle tracking at 2MB block granularity
+//!
+//! [`PageAger`](crate::policy::PageAger) keeps one hash map entry per idle
+//! page, which grows to hundreds of millions of entries on terabyte
+//! processes. [`AgingMap`] instead keeps, per tracked region, the last K
+//! scan results of every 2MB block as a packed bitset, i.e. K bits per
+//! 2MB. A block counts as idle in a scan if the scan reported idle pages
+//! in it and no accessed ones. It can be used on its own, as below, or as
+//! the backend of a `PageAger` with
+//! [`PageAger::with_block_aging`](crate::policy::PageAger::with_block_aging).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::aging::AgingMap;
+//! use etmem_rs::policy::AgingPolicy;
+//! use etmem_rs::{IdlePageScanner, ScanConfig, VmaMap};
+//!
+//! let pid = std::process::id() as u32;
+//! let mut map = AgingMap::new(8).with_memory_limit(16 * 1024 * 1024);
+//! map.sync_vmas(&VmaMap::for_process(pid).expect("Failed to parse VMAs"));
+//!
+//! for _ in 0..3 {
+//!     let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+//!         .expect("Failed to scan");
+//!     map.observe(&pages);
+//! }
+//!
+//! let cold = map.cold_ranges(&AgingPolicy::new().with_min_idle_scans(3));
+//! println!("{} cold ranges, {} bytes of history", cold.len(), map.memory_usage());
+//! ```
+
+use std::collections::BTreeMap;
+
+use crate::policy::AgingPolicy;
+use crate::types::{AddressRange, HUGE_PAGE_SIZE, IdlePageInfo, RangeSet};
+use crate::util::huge_page_align_down;
+use crate::vma::VmaMap;
+
+/// Maximum number of scans kept per block
+pub const MAX_HISTORY: u32 = 64;
+
+/// Scan result of a block while a scan is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockSeen {
+    /// Not reported, or only holes
+    None,
+    /// Only idle pages reported
+    Idle,
+    /// At least one accessed page reported
+    Accessed,
+}
+
+/// Packed histories of the blocks of one region
+#[derive(Debug, Clone)]
+struct RegionAging {
+    /// End address of the region (exclusive)
+    end: u64,
+    /// First block address (2MB aligned)
+    base: u64,
+    /// Number of blocks
+    blocks: usize,
+    /// `blocks * history` bits, block `i` at bits `i * history..`
+    bits: Vec<u64>,
+}
+
+impl RegionAging {
+    fn new(range: AddressRange, history: u32) -> Self {
+        let base = huge_page_align_down(range.start);
+        let blocks = (range.end - base).div_ceil(HUGE_PAGE_SIZE) as usize;
+        Self {
+            end: range.end,
+            base,
+            blocks,
+            bits: vec![0; Self::words(blocks, history)],
+        }
+    }
+
+    fn words(blocks: usize, history: u32) -> usize {
+        (blocks * history as usize).div_ceil(64)
+    }
+
+    fn block_of(&self, addr: u64) -> usize {
+        ((huge_page_align_down(addr) - self.base) / HUGE_PAGE_SIZE) as usize
+    }
+
+    /// Address range of a block, clipped to the region
+    fn block_range(&self, block: usize, start: u64) -> AddressRange {
+        let block_start = self.base + block as u64 * HUGE_PAGE_SIZE;
+        AddressRange::new(
+            block_start.max(start),
+            (block_start + HUGE_PAGE_SIZE).min(self.end),
+        )
+    }
+
+    fn get(&self, block: usize, history: u32) -> u64 {
+        let bit = block * history as usize;
+        let (word, shift) = (bit / 64, bit % 64);
+        let mut value = self.bits[word] >> shift;
+        if shift + history as usize > 64 {
+            value |= self.bits[word + 1] << (64 - shift);
+        }
+        value & mask(history)
+    }
+
+    fn set(&mut self, block: usize, history: u32, value: u64) {
+        let bit = block * history as usize;
+        let (word, shift) = (bit / 64, bit % 64);
+        let m = mask(history);
+        self.bits[word] = (self.bits[word] & !(m << shift)) | ((value & m) << shift);
+        if shift + history as usize > 64 {
+            let rest = 64 - shift;
+            self.bits[word + 1] = (self.bits[word + 1] & !(m >> rest)) | ((value & m) >> rest);
+        }
+    }
+}
+
+const fn mask(history: u32) -> u64 {
+    if history >= 64 {
+        u64::MAX
+    } else {
+        (1 << history) - 1
+    }
+}
+
+/// Per-region idle history of 2MB blocks over the last K scans
+///
+/// Regions must be registered with [`track`](Self::track) or
+/// [`sync_vmas`](Self::sync_vmas); pages outside them are ignored. Bit 0
+/// of a block history is the most recent scan.
+#[derive(Debug, Clone)]
+pub struct AgingMap {
+    /// Scans kept per block (K)
+    history: u32,
+    /// Upper bound on the history storage in bytes
+    max_bytes: Option<usize>,
+    /// Tracked regions by start address
+    regions: BTreeMap<u64, RegionAging>,
+    /// Number of scans observed
+    scans: u32,
+}
+
+impl AgingMap {
+    /// Create a map keeping the last `history` scans per block (1-64)
+    pub fn new(history: u32) -> Self {
+        Self {
+            history: history.clamp(1, MAX_HISTORY),
+            max_bytes: None,
+            regions: BTreeMap::new(),
+            scans: 0,
+        }
+    }
+
+    /// Limit the history storage to `bytes`
+    ///
+    /// Regions that would exceed the limit are not tracked.
+    pub fn with_memory_limit(mut self, bytes: usize) -> Self {
+        self.max_bytes = Some(bytes);
+        self
+    }
+
+    /// Get the number of scans kept per block
+    pub fn history(&self) -> u32 {
+        self.history
+    }
+
+    /// Get the number of scans observed
+    pub fn scans(&self) -> u32 {
+        self.scans
+    }
+
+    /// Get the number of tracked regions
+    pub fn regions(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Get the number of tracked 2MB blocks
+    pub fn blocks(&self) -> usize {
+        self.regions.values().map(|r| r.blocks).sum()
+    }
+
+    /// Bytes used by the block histories
+    pub fn memory_usage(&self) -> usize {
+        self.regions
+            .values()
+            .map(|r| r.bits.len() * size_of::<u64>())
+            .sum()
+    }
+
+    /// Start tracking a region
+    ///
+    /// A region with the same start address keeps its history if its end
+    /// is unchanged and is replaced otherwise.
+    ///
+    /// Returns `false` if the range is invalid or tracking it would exceed
+    /// the memory limit.
+    pub fn track(&mut self, range: AddressRange) -> bool {
+        if !range.is_valid() || range.size() == 0 {
+            return false;
+        }
+        if self
+            .regions
+            .get(&range.start)
+            .is_some_and(|r| r.end == range.end)
+        {
+            return true;
+        }
+
+        let region = RegionAging::new(range, self.history);
+        if let Some(max) = self.max_bytes {
+            let replaced = self
+                .regions
+                .get(&range.start)
+                .map_or(0, |r| r.bits.len() * size_of::<u64>());
+            let needed = region.bits.len() * size_of::<u64>();
+            if self.memory_usage() - replaced + needed > max {
+                log::debug!(
+                    "Not tracking {:#x}-{:#x}: aging map limit of {} bytes reached",
+                    range.start,
+                    range.end,
+                    max
+                );
+                return false;
+            }
+        }
+        self.regions.insert(range.start, region);
+        true
+    }
+
+    /// Stop tracking the region starting at `start`
+    pub fn untrack(&mut self, start: u64) {
+        self.regions.remove(&start);
+    }
+
+    /// Track exactly the swappable regions of a process
+    ///
+    /// Regions that are unchanged keep their history. Returns the number
+    /// of regions that could not be tracked because of the memory limit.
+    pub fn sync_vmas(&mut self, vma_map: &VmaMap) -> usize {
+        let ranges: Vec<AddressRange> = vma_map
+            .swappable()
+            .iter()
+            .map(|vma| AddressRange::new(vma.start, vma.end))
+            .collect();
+        self.regions.retain(|&start, region| {
+            ranges
+                .iter()
+                .any(|r| r.start == start && r.end == region.end)
+        });
+        ranges.into_iter().filter(|&r| !self.track(r)).count()
+    }
+
+    /// Clear the histories of the blocks overlapping `ranges`
+    ///
+    /// Used when the address space changed under tracked regions, e.g.
+    /// with a [`MapsChange`](crate::maps::MapsChange). Returns the number
+    /// of blocks cleared.
+    pub fn invalidate(&mut self, ranges: &RangeSet) -> usize {
+        let history = self.history;
+        let mut cleared = 0;
+        for (&start, region) in &mut self.regions {
+            for range in ranges.iter() {
+                let (from, to) = (range.start.max(start), range.end.min(region.end));
+                if from >= to {
+                    continue;
+                }
+                for block in region.block_of(from)..=region.block_of(to - 1) {
+                    region.set(block, history, 0);
+                    cleared += 1;
+                }
+            }
+        }
+        cleared
+    }
+
+    fn region_of(&self, addr: u64) -> Option<(u64, &RegionAging)> {
+        self.regions
+            .range(..=addr)
+            .next_back()
+            .filter(|(_, r)| addr < r.end)
+            .map(|(&start, r)| (start, r))
+    }
+
+    /// Record the results of one scan
+    ///
+    /// Every tracked block ages by one scan; blocks not reported count as
+    /// not idle. Returns the number of blocks idle in this scan.
+    pub fn observe(&mut self, pages: &[IdlePageInfo]) -> usize {
+        let mut seen: BTreeMap<u64, Vec<BlockSeen>> = BTreeMap::new();
+
+        for page in pages.iter().filter(|p| p.is_idle() || p.is_accessed()) {
+            let end = page.end_address();
+            let mut addr = page.address;
+            while addr < end {
+                let Some((start, region)) = self.region_of(addr) else {
+                    // Skip to the next tracked region, if any
+                    match self.regions.range(addr..end).next() {
+                        Some((&next, _)) => {
+                            addr = next;
+                            continue;
+                        }
+                        None => break,
+                    }
+                };
+                let state = seen
+                    .entry(start)
+                    .or_insert_with(|| vec![BlockSeen::None; region.blocks]);
+                let stop = end.min(region.end);
+                let blocks = region.block_of(addr)..=region.block_of(stop - 1);
+                for block in &mut state[blocks] {
+                    *block = match (*block, page.is_accessed()) {
+                        (_, true) | (BlockSeen::Accessed, _) => BlockSeen::Accessed,
+                        _ => BlockSeen::Idle,
+                    };
+                }
+                addr = stop;
+            }
+        }
+
+        let history = self.history;
+        let mut idle = 0;
+        for (start, region) in self.regions.iter_mut() {
+            let state = seen.get(start);
+            for block in 0..region.blocks {
+                let bit = state.is_some_and(|s| s[block] == BlockSeen::Idle);
+                idle += usize::from(bit);
+                let value = (region.get(block, history) << 1) | u64::from(bit);
+                region.set(block, history, value);
+            }
+        }
+
+        self.scans += 1;
+        idle
+    }
+
+    /// Get the history of the block containing `addr` (bit 0 = last scan)
+    pub fn history_of(&self, addr: u64) -> u64 {
+        self.region_of(addr)
+            .map(|(_, r)| r.get(r.block_of(addr), self.history))
+            .unwrap_or(0)
+    }
+
+    /// Get the number of consecutive idle scans of the block containing `addr`
+    ///
+    /// Saturates at the history length.
+    pub fn age_of(&self, addr: u64) -> u32 {
+        self.history_of(addr).trailing_ones().min(self.history)
+    }
+
+    /// Select cold blocks according to the policy
+    ///
+    /// A block is cold once it was idle for `min_idle_scans` consecutive
+    /// scans (at most the history length). The oldest blocks are selected
+    /// first up to `max_swap_bytes`; the result is sorted by address with
+    /// adjacent blocks merged.
+    pub fn cold_ranges(&self, policy: &AgingPolicy) -> Vec<AddressRange> {
+        let min_scans = policy.min_idle_scans.min(self.history);
+        let mut candidates: Vec<(u32, AddressRange)> = Vec::new();
+        for (&start, region) in &self.regions {
+            for block in 0..region.blocks {
+                let age = region.get(block, self.history).trailing_ones();
+                if age >= min_scans {
+                    candidates.push((age, region.block_range(block, start)));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.start.cmp(&b.1.start)));
+
+        let mut selected = Vec::new();
+        let mut bytes = 0u64;
+        for (_, range) in candidates {
+            if let Some(max) = policy.max_swap_bytes
+                && bytes + range.size() > max
+            {
+                continue;
+            }
+            bytes += range.size();
+            selected.push(range);
+        }
+
+        selected.sort_by_key(|r| r.start);
+        let mut merged: Vec<AddressRange> = Vec::with_capacity(selected.len());
+        for range in selected {
+            match merged.last_mut() {
+                Some(last) if last.end == range.start => last.end = range.end,
+                _ => merged.push(range),
+            }
+        }
+        merged
+    }
+
+    /// Clear all histories, keeping the tracked regions
+    pub fn reset(&mut self) {
+        for region in self.regions.values_mut() {
+            region.bits.fill(0);
+        }
+        self.scans = 0;
+    }
+}
+
+impl Default for AgingMap {
+    fn default() -> Self {
+        Self::new(8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProcIdlePageType;
+
+    const MB: u64 = 1024 * 1024;
+
+    #[test]
+    fn test_packed_histories() {
+        // 5-bit histories straddle word boundaries
+        let mut region = RegionAging::new(AddressRange::new(0, 40 * MB), 5);
+        assert_eq!(region.blocks, 20);
+        assert_eq!(region.bits.len(), 2);
+        for block in 0..20 {
+            region.set(block, 5, block as u64);
+        }
+        for block in 0..20 {
+            assert_eq!(region.get(block, 5), block as u64 & 0x1f);
+        }
+
+        let mut wide = RegionAging::new(AddressRange::new(0, 4 * MB), 64);
+        wide.set(1, 64, u64::MAX);
+        assert_eq!(wide.get(0, 64), 0);
+        assert_eq!(wide.get(1, 64), u64::MAX);
+    }
+
+    #[test]
+    fn test_observe_blocks() {
+        let mut map = AgingMap::new(4);
+        assert!(map.track(AddressRange::new(2 * MB, 8 * MB)));
+        assert_eq!(map.blocks(), 3);
+
+        let scan = [
+            // Idle base pages in the first block
+            IdlePageInfo::new(2 * MB, ProcIdlePageType::PteIdle, 4),
+            // Second block: idle and accessed pages, accessed wins
+            IdlePageInfo::new(4 * MB, ProcIdlePageType::PteIdle, 1),
+            IdlePageInfo::new(4 * MB + 4096, ProcIdlePageType::PteAccessed, 1),
+            // Third block: idle huge page
+            IdlePageInfo::new(6 * MB, ProcIdlePageType::PmdIdle, 1),
+            // Untracked
+            IdlePageInfo::new(64 * MB, ProcIdlePageType::PmdIdle, 1),
+        ];
+        assert_eq!(map.observe(&scan), 2);
+        assert_eq!(map.observe(&scan[..1]), 1);
+
+        assert_eq!(map.history_of(2 * MB), 0b11);
+        assert_eq!(map.age_of(2 * MB), 2);
+        assert_eq!(map.age_of(4 * MB), 0);
+        assert_eq!(map.history_of(6 * MB), 0b10);
+        assert_eq!(map.age_of(64 * MB), 0);
+
+        // Ages saturate at the history length
+        for _ in 0..10 {
+            map.observe(&scan[..1]);
+        }
+        assert_eq!(map.age_of(2 * MB), 4);
+        assert_eq!(map.scans(), 12);
+    }
+
+    #[test]
+    fn test_cold_ranges() {
+        let mut map = AgingMap::new(4);
+        map.track(AddressRange::new(MB, 9 * MB));
+        map.observe(&[IdlePageInfo::new(MB, ProcIdlePageType::PteIdle, 255)]);
+        map.observe(&[IdlePageInfo::new(2 * MB, ProcIdlePageType::PmdIdle, 3)]);
+
+        // First block clipped to the region start, then two more blocks
+        let policy = AgingPolicy::new().with_min_idle_scans(1);
+        assert_eq!(
+            map.cold_ranges(&policy),
+            vec![AddressRange::new(2 * MB, 8 * MB)]
+        );
+        assert!(
+            map.cold_ranges(&AgingPolicy::new().with_min_idle_scans(2))
+                .is_empty()
+        );
+
+        let limited = policy.with_max_swap_bytes(4 * MB);
+        assert_eq!(
+            map.cold_ranges(&limited),
+            vec![AddressRange::new(2 * MB, 6 * MB)]
+        );
+    }
+
+    #[test]
+    fn test_invalidate_blocks() {
+        let mut map = AgingMap::new(4);
+        map.track(AddressRange::new(0, 8 * MB));
+        map.observe(&[IdlePageInfo::new(0, ProcIdlePageType::PmdIdle, 4)]);
+        assert_eq!(map.history_of(2 * MB), 1);
+
+        // A remapped page clears the whole block containing it
+        let changed = RangeSet::from_ranges([AddressRange::new(2 * MB + 4096, 2 * MB + 8192)]);
+        assert_eq!(map.invalidate(&changed), 1);
+        assert_eq!(map.history_of(0), 1);
+        assert_eq!(map.history_of(2 * MB), 0);
+        assert_eq!(map.history_of(4 * MB), 1);
+        assert_eq!(
+            map.invalidate(&RangeSet::from_ranges([AddressRange::new(
+                16 * MB,
+                32 * MB
+            )])),
+            0
+        );
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let mut map = AgingMap::new(64).with_memory_limit(16);
+        assert!(map.track(AddressRange::new(0, 4 * MB)));
+        assert_eq!(map.memory_usage(), 16);
+        assert!(!map.track(AddressRange::new(8 * MB, 10 * MB)));
+        assert!(!map.track(AddressRange::new(5, 5)));
+
+        // Re-tracking an unchanged region keeps its history
+        map.observe(&[IdlePageInfo::new(0, ProcIdlePageType::PmdIdle, 1)]);
+        assert!(map.track(AddressRange::new(0, 4 * MB)));
+        assert_eq!(map.age_of(0), 1);
+
+        map.untrack(0);
+        assert_eq!(map.regions(), 0);
+        assert!(map.track(AddressRange::new(8 * MB, 10 * MB)));
+    }
+
+    #[test]
+    fn test_terabyte_footprint() {
+        let mut map = AgingMap::new(4);
+        assert!(map.track(AddressRange::new(0, 1 << 40)));
+        // 512Ki blocks at 4 bits each
+        assert_eq!(map.memory_usage(), 256 * 1024);
+    }
+}
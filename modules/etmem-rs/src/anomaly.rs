@@ -0,0 +1,430 @@
+//! Sanity checks of scan results
+//!
+//! The decoder repairs addresses it cannot use (see [`DecodeWarning`]), but
+//! a stream can be well-formed byte by byte and still describe an address
+//! space no page table walk produces: entries covering the same pages
+//! twice, a walk going back on itself, page types the protocol does not
+//! define or holes larger than any mapping. Such streams point at a
+//! corrupted page table walk or a module speaking another protocol version,
+//! and idle bits read from them cannot be trusted for reclaim.
+//!
+//! An [`AnomalyDetector`] checks every read of a scan and aggregates what it
+//! finds into an [`AnomalyReport`]. [`ScanSession`](crate::ScanSession) runs
+//! one per scan cycle, and [`PageAger::observe_checked`] refuses to reclaim
+//! based on a scan whose report exceeds
+//! [`AgingPolicy::max_anomalies`](crate::policy::AgingPolicy::max_anomalies).
+//!
+//! [`DecodeWarning`]: crate::types::DecodeWarning
+//! [`PageAger::observe_checked`]: crate::policy::PageAger::observe_checked
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::{IdlePageScanner, ScanConfig};
+//!
+//! let (pages, report) = IdlePageScanner::scan_process_checked(1234, ScanConfig::default())
+//!     .expect("Failed to scan");
+//! if !report.is_clean() {
+//!     eprintln!("Not reclaiming from {} entries: {}", pages.len(), report);
+//! }
+//! ```
+
+use std::fmt;
+
+use crate::types::{AddressRange, BASE_PAGE_SIZE, PipEncoding, ProcIdlePageType};
+
+/// Default size above which a run of hole entries is an anomaly (1TB)
+///
+/// Larger than the untouched reservations of real workloads, such as
+/// managed-runtime heaps; sanitizer shadow mappings can exceed it.
+pub const DEFAULT_MAX_HOLE_BYTES: u64 = 1 << 40;
+
+/// Number of anomalies kept in a report, the rest are only counted
+const MAX_RECORDED: usize = 32;
+
+/// Inconsistency found in scan results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// `SET_HVA` moved the walk back over pages already reported
+    OverlappingRun {
+        /// Address the walk was moved to
+        address: u64,
+        /// End of the pages reported so far
+        covered_to: u64,
+    },
+    /// A read started below the pages already reported, without `SET_HVA`
+    AddressRegression {
+        /// Address the read started at
+        address: u64,
+        /// End of the pages reported so far
+        covered_to: u64,
+    },
+    /// Entry with a page type at or above [`ProcIdlePageType::Max`]
+    InvalidPageType {
+        /// Address of the entry, `None` if the walk lost track of it
+        address: Option<u64>,
+        /// Raw page type
+        raw: u8,
+    },
+    /// Run of consecutive hole entries larger than the configured maximum
+    AbsurdHole {
+        /// Start of the run
+        start: u64,
+        /// Size of the run in bytes
+        bytes: u64,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OverlappingRun {
+                address,
+                covered_to,
+            } => write!(
+                f,
+                "SET_HVA to {:#x} overlaps pages reported up to {:#x}",
+                address, covered_to
+            ),
+            Self::AddressRegression {
+                address,
+                covered_to,
+            } => write!(
+                f,
+                "Read at {:#x} went back below {:#x} without SET_HVA",
+                address, covered_to
+            ),
+            Self::InvalidPageType {
+                address: Some(address),
+                raw,
+            } => write!(f, "Page type {} out of range at {:#x}", raw, address),
+            Self::InvalidPageType { address: None, raw } => {
+                write!(f, "Page type {} out of range", raw)
+            }
+            Self::AbsurdHole { start, bytes } => write!(
+                f,
+                "Hole of {} at {:#x}",
+                crate::util::format_bytes(*bytes),
+                start
+            ),
+        }
+    }
+}
+
+/// Anomalies found across the reads of a scan
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnomalyReport {
+    /// First anomalies found, in stream order
+    pub anomalies: Vec<Anomaly>,
+    /// Number of anomalies found, including those not kept
+    pub count: u64,
+    /// Number of page entries checked
+    pub entries: u64,
+}
+
+impl AnomalyReport {
+    /// Check whether no anomaly was found
+    pub fn is_clean(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Check whether more than `threshold` anomalies were found
+    pub fn exceeds(&self, threshold: u64) -> bool {
+        self.count > threshold
+    }
+
+    /// Record an anomaly
+    fn push(&mut self, anomaly: Anomaly) {
+        self.count += 1;
+        if self.anomalies.len() < MAX_RECORDED {
+            self.anomalies.push(anomaly);
+        }
+    }
+}
+
+impl fmt::Display for AnomalyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} anomalies in {} entries", self.count, self.entries)?;
+        if let Some(first) = self.anomalies.first() {
+            write!(f, " (first: {})", first)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the raw idle page data of the reads of a scan
+///
+/// Reads are expected in walk order: each one continuing where the walk
+/// stopped, moving forward only through `SET_HVA`. Call
+/// [`reset`](Self::reset) before every scan, and
+/// [`start_walk`](Self::start_walk) before each walk of a scan made of
+/// several, such as the windows of a sampled scan.
+#[derive(Debug, Clone)]
+pub struct AnomalyDetector {
+    /// Size above which a run of holes is an anomaly
+    max_hole_bytes: u64,
+    /// Anomalies found so far
+    report: AnomalyReport,
+    /// End of the pages reported so far in the walk
+    covered_to: Option<u64>,
+    /// Run of hole entries being measured
+    hole: Option<AddressRange>,
+}
+
+impl AnomalyDetector {
+    /// Create a detector flagging runs of holes larger than `max_hole_bytes`
+    pub fn new(max_hole_bytes: u64) -> Self {
+        Self {
+            max_hole_bytes,
+            report: AnomalyReport::default(),
+            covered_to: None,
+            hole: None,
+        }
+    }
+
+    /// Forget the previous scan and its anomalies
+    pub fn reset(&mut self) {
+        self.report = AnomalyReport::default();
+        self.covered_to = None;
+        self.hole = None;
+    }
+
+    /// Start a walk from an arbitrary address, keeping the anomalies found
+    /// so far
+    pub fn start_walk(&mut self) {
+        self.end_hole();
+        self.covered_to = None;
+    }
+
+    /// Check the data returned by one read
+    ///
+    /// `base_addr` is the address the read started at, as passed to
+    /// [`decode_pip`](etmem_types::decode_pip).
+    pub fn observe_read(&mut self, data: &[u8], base_addr: u64) {
+        let mut cursor = Some(base_addr);
+        // Whether the cursor was moved and not yet checked against the walk
+        let mut moved = true;
+        let mut by_set_hva = false;
+        let mut i = 0;
+
+        while i < data.len() {
+            let byte = data[i];
+            let raw = PipEncoding::extract_type(byte);
+            let count = u64::from(PipEncoding::extract_size(byte)) + 1;
+            i += 1;
+
+            if raw == ProcIdlePageType::PipCmd as u8 {
+                if byte == PipEncoding::SET_HVA && i + 8 <= data.len() {
+                    let mut addr_bytes = [0u8; 8];
+                    addr_bytes.copy_from_slice(&data[i..i + 8]);
+                    cursor = Some(u64::from_be_bytes(addr_bytes) & !(BASE_PAGE_SIZE - 1));
+                    moved = true;
+                    by_set_hva = true;
+                    i += 8;
+                }
+                continue;
+            }
+
+            self.report.entries += 1;
+            let Some(page_type) = ProcIdlePageType::from_raw(raw) else {
+                self.report.push(Anomaly::InvalidPageType {
+                    address: cursor,
+                    raw,
+                });
+                // The entry's size is unknown, so is the next address
+                cursor = None;
+                self.end_hole();
+                continue;
+            };
+            let Some(address) = cursor else {
+                continue;
+            };
+
+            if moved {
+                moved = false;
+                if let Some(covered_to) = self.covered_to.filter(|&end| address < end) {
+                    self.report.push(if by_set_hva {
+                        Anomaly::OverlappingRun {
+                            address,
+                            covered_to,
+                        }
+                    } else {
+                        Anomaly::AddressRegression {
+                            address,
+                            covered_to,
+                        }
+                    });
+                }
+            }
+
+            let end = address.saturating_add(page_type.page_size() * count);
+            if page_type.is_hole() {
+                match &mut self.hole {
+                    Some(hole) if hole.end == address => hole.end = end,
+                    _ => {
+                        self.end_hole();
+                        self.hole = Some(AddressRange::new(address, end));
+                    }
+                }
+            } else {
+                self.end_hole();
+            }
+            self.covered_to = Some(self.covered_to.map_or(end, |covered| covered.max(end)));
+            cursor = Some(end);
+        }
+    }
+
+    /// Get the anomalies found since the last reset
+    pub fn report(&self) -> AnomalyReport {
+        let mut report = self.report.clone();
+        if let Some(anomaly) = self.hole.and_then(|hole| self.absurd_hole(hole)) {
+            report.push(anomaly);
+        }
+        report
+    }
+
+    /// Close the run of holes being measured
+    fn end_hole(&mut self) {
+        if let Some(anomaly) = self.hole.take().and_then(|hole| self.absurd_hole(hole)) {
+            self.report.push(anomaly);
+        }
+    }
+
+    /// Flag a run of holes if it is too large
+    fn absurd_hole(&self, hole: AddressRange) -> Option<Anomaly> {
+        (hole.size() > self.max_hole_bytes).then_some(Anomaly::AbsurdHole {
+            start: hole.start,
+            bytes: hole.size(),
+        })
+    }
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_HOLE_BYTES)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(page_type: ProcIdlePageType, count: u8) -> u8 {
+        PipEncoding::compose(page_type as u8, count - 1)
+    }
+
+    fn set_hva(addr: u64) -> Vec<u8> {
+        let mut cmd = vec![PipEncoding::SET_HVA];
+        cmd.extend_from_slice(&addr.to_be_bytes());
+        cmd
+    }
+
+    #[test]
+    fn test_clean_walk() {
+        let mut detector = AnomalyDetector::default();
+        let mut data = vec![entry(ProcIdlePageType::PteIdle, 4)];
+        data.extend(set_hva(0x20_0000));
+        data.push(entry(ProcIdlePageType::PmdIdle, 1));
+        detector.observe_read(&data, 0x1000);
+        // The next read continues where the first stopped
+        detector.observe_read(&[entry(ProcIdlePageType::PteAccessed, 2)], 0x40_0000);
+
+        let report = detector.report();
+        assert!(report.is_clean());
+        assert_eq!(report.entries, 3);
+    }
+
+    #[test]
+    fn test_overlap_and_regression() {
+        let mut detector = AnomalyDetector::default();
+        let mut data = vec![entry(ProcIdlePageType::PteIdle, 16)];
+        data.extend(set_hva(0x4000));
+        data.push(entry(ProcIdlePageType::PteIdle, 1));
+        data.push(entry(ProcIdlePageType::PteIdle, 1));
+        detector.observe_read(&data, 0x1000);
+        detector.observe_read(&[entry(ProcIdlePageType::PteIdle, 1)], 0x2000);
+
+        let report = detector.report();
+        assert_eq!(
+            report.anomalies,
+            [
+                Anomaly::OverlappingRun {
+                    address: 0x4000,
+                    covered_to: 0x11000
+                },
+                Anomaly::AddressRegression {
+                    address: 0x2000,
+                    covered_to: 0x11000
+                },
+            ]
+        );
+        assert!(report.exceeds(1));
+        assert!(!report.exceeds(2));
+
+        detector.start_walk();
+        detector.observe_read(&[entry(ProcIdlePageType::PteIdle, 1)], 0x2000);
+        assert_eq!(detector.report().count, 2);
+
+        detector.reset();
+        detector.observe_read(&[entry(ProcIdlePageType::PteIdle, 1)], 0x2000);
+        assert!(detector.report().is_clean());
+    }
+
+    #[test]
+    fn test_invalid_page_type() {
+        let mut detector = AnomalyDetector::default();
+        let mut data = vec![
+            entry(ProcIdlePageType::PteIdle, 1),
+            PipEncoding::compose(13, 0),
+            entry(ProcIdlePageType::PteIdle, 1),
+        ];
+        data.extend(set_hva(0x10_0000));
+        data.push(entry(ProcIdlePageType::PteIdle, 1));
+        detector.observe_read(&data, 0x1000);
+
+        let report = detector.report();
+        assert_eq!(
+            report.anomalies,
+            [Anomaly::InvalidPageType {
+                address: Some(0x2000),
+                raw: 13
+            }]
+        );
+        assert_eq!(report.entries, 4);
+        assert_eq!(
+            report.to_string(),
+            "1 anomalies in 4 entries (first: Page type 13 out of range at 0x2000)"
+        );
+    }
+
+    #[test]
+    fn test_absurd_hole() {
+        let hole = entry(ProcIdlePageType::PmdHole, 16);
+        let mut detector = AnomalyDetector::new(64 * 1024 * 1024);
+
+        // Two 32MB hole entries: at the limit
+        detector.observe_read(&[hole, hole, entry(ProcIdlePageType::PteIdle, 1)], 0);
+        assert!(detector.report().is_clean());
+
+        // A run continuing into the next read is measured whole
+        detector.reset();
+        detector.observe_read(&[hole, hole], 0);
+        detector.observe_read(&[hole], 0x400_0000);
+        assert_eq!(
+            detector.report().anomalies,
+            [Anomaly::AbsurdHole {
+                start: 0,
+                bytes: 0x600_0000
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recorded_anomalies_are_capped() {
+        let mut detector = AnomalyDetector::default();
+        detector.observe_read(&[PipEncoding::compose(15, 0); 100], 0);
+        let report = detector.report();
+        assert_eq!(report.count, 100);
+        assert_eq!(report.anomalies.len(), MAX_RECORDED);
+    }
+}
@@ -0,0 +1,410 @@
+//! Scan cost accounting against a CPU and I/O budget
+//!
+//! Idle page scans walk page tables in the kernel on the scanning thread,
+//! so their cost shows up as CPU time and bytes read from
+//! `/proc/<pid>/idle_pages`. On latency-critical hosts that cost must stay
+//! bounded: [`CostLimiter`] measures every scan cycle and, once the cost of
+//! the last minute exceeds the [`ScanBudget`], delays the next cycle until
+//! enough of it has aged out.
+//!
+//! The cost is measured on the calling thread:
+//! - CPU time: user + system time from `getrusage(RUSAGE_THREAD)`
+//! - Bytes read: `rchar` from `/proc/thread-self/io` (0 if unavailable)
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use etmem_rs::budget::{CostLimiter, ScanBudget};
+//! use etmem_rs::{IdlePageScanner, ScanConfig};
+//!
+//! // At most 50ms of CPU time per minute
+//! let budget = ScanBudget::new().with_cpu_per_minute(Some(Duration::from_millis(50)));
+//! let mut limiter = CostLimiter::new(budget);
+//!
+//! for _ in 0..10 {
+//!     limiter.throttle();
+//!     let pages = limiter
+//!         .measure(|| IdlePageScanner::scan_process(1234, ScanConfig::default()))
+//!         .expect("Failed to scan");
+//!     println!("{} idle entries", pages.len());
+//! }
+//! println!("{}", limiter.stats());
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::error::{EtmemError, Result};
+use crate::util::format_bytes;
+
+/// Procfs path for I/O counters of the calling thread
+pub const PROC_THREAD_IO: &str = "/proc/thread-self/io";
+
+/// Period the budget applies to
+pub const BUDGET_WINDOW: Duration = Duration::from_secs(60);
+
+/// Resource usage counters of the calling thread
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// User + system CPU time
+    pub cpu_time: Duration,
+    /// Bytes read through `read()` and similar calls
+    pub read_bytes: u64,
+}
+
+impl ResourceUsage {
+    /// Sample the counters of the calling thread
+    ///
+    /// # Errors
+    /// Returns error if `getrusage` fails. A missing
+    /// `/proc/thread-self/io` only zeroes `read_bytes`.
+    pub fn current() -> Result<Self> {
+        let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+        // SAFETY: usage points to writable memory of the right size
+        let ret = unsafe { libc::getrusage(libc::RUSAGE_THREAD, usage.as_mut_ptr()) };
+        if ret < 0 {
+            return Err(EtmemError::from(std::io::Error::last_os_error()));
+        }
+        // SAFETY: getrusage succeeded and filled the struct
+        let usage = unsafe { usage.assume_init() };
+
+        let read_bytes = std::fs::read_to_string(PROC_THREAD_IO)
+            .ok()
+            .and_then(|content| parse_rchar(&content))
+            .unwrap_or(0);
+
+        Ok(Self {
+            cpu_time: timeval_to_duration(usage.ru_utime) + timeval_to_duration(usage.ru_stime),
+            read_bytes,
+        })
+    }
+
+    /// Cost accumulated since an earlier sample
+    pub fn since(&self, earlier: &Self) -> ScanCost {
+        ScanCost {
+            cpu_time: self.cpu_time.saturating_sub(earlier.cpu_time),
+            read_bytes: self.read_bytes.saturating_sub(earlier.read_bytes),
+        }
+    }
+}
+
+fn timeval_to_duration(tv: libc::timeval) -> Duration {
+    Duration::from_secs(tv.tv_sec.max(0) as u64) + Duration::from_micros(tv.tv_usec.max(0) as u64)
+}
+
+/// Parse the `rchar` field of a `/proc/<pid>/io` file
+fn parse_rchar(content: &str) -> Option<u64> {
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("rchar:"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Cost of one scan cycle
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanCost {
+    /// CPU time spent
+    pub cpu_time: Duration,
+    /// Bytes read
+    pub read_bytes: u64,
+}
+
+/// Per-minute cost limits for scanning
+///
+/// A limit of `None` is not enforced; the default enforces nothing and
+/// only measures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanBudget {
+    /// CPU time allowed per minute
+    pub cpu_per_minute: Option<Duration>,
+    /// Bytes allowed to be read per minute
+    pub read_bytes_per_minute: Option<u64>,
+}
+
+impl ScanBudget {
+    /// Create a budget without limits
+    pub const fn new() -> Self {
+        Self {
+            cpu_per_minute: None,
+            read_bytes_per_minute: None,
+        }
+    }
+
+    /// Set CPU time allowed per minute, `None` to disable
+    pub const fn with_cpu_per_minute(mut self, limit: Option<Duration>) -> Self {
+        self.cpu_per_minute = limit;
+        self
+    }
+
+    /// Set bytes allowed to be read per minute, `None` to disable
+    pub const fn with_read_bytes_per_minute(mut self, limit: Option<u64>) -> Self {
+        self.read_bytes_per_minute = limit;
+        self
+    }
+
+    /// Check if any limit is set
+    pub const fn is_limited(&self) -> bool {
+        self.cpu_per_minute.is_some() || self.read_bytes_per_minute.is_some()
+    }
+}
+
+/// Totals kept by a [`CostLimiter`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BudgetStats {
+    /// Scan cycles measured
+    pub cycles: u64,
+    /// CPU time of all cycles
+    pub cpu_time: Duration,
+    /// Bytes read by all cycles
+    pub read_bytes: u64,
+    /// Times a cycle was delayed for exceeding the budget
+    pub throttled: u64,
+    /// Total time cycles were delayed
+    pub throttled_time: Duration,
+}
+
+impl fmt::Display for BudgetStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cycles, {:.1}ms CPU, {} read",
+            self.cycles,
+            self.cpu_time.as_secs_f64() * 1000.0,
+            format_bytes(self.read_bytes)
+        )?;
+        if self.throttled > 0 {
+            write!(
+                f,
+                ", throttled {} times ({:.1}s)",
+                self.throttled,
+                self.throttled_time.as_secs_f64()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Scan cost limiter
+///
+/// Keeps the cost of the cycles of the last [`BUDGET_WINDOW`], so a single
+/// limiter should be reused across scan cycles.
+#[derive(Debug, Clone, Default)]
+pub struct CostLimiter {
+    /// Cost limits
+    budget: ScanBudget,
+    /// Cycles of the current window with their end time, oldest first
+    window: VecDeque<(Instant, ScanCost)>,
+    /// Running totals
+    stats: BudgetStats,
+}
+
+impl CostLimiter {
+    /// Create a new limiter
+    pub const fn new(budget: ScanBudget) -> Self {
+        Self {
+            budget,
+            window: VecDeque::new(),
+            stats: BudgetStats {
+                cycles: 0,
+                cpu_time: Duration::ZERO,
+                read_bytes: 0,
+                throttled: 0,
+                throttled_time: Duration::ZERO,
+            },
+        }
+    }
+
+    /// Get the budget
+    pub fn budget(&self) -> &ScanBudget {
+        &self.budget
+    }
+
+    /// Get the totals
+    pub fn stats(&self) -> &BudgetStats {
+        &self.stats
+    }
+
+    /// Run one scan cycle and record its cost
+    ///
+    /// If the counters cannot be sampled the cycle is not recorded.
+    pub fn measure<T>(&mut self, scan: impl FnOnce() -> T) -> T {
+        let before = ResourceUsage::current();
+        let result = scan();
+        match (before, ResourceUsage::current()) {
+            (Ok(before), Ok(after)) => self.record(Instant::now(), after.since(&before)),
+            (Err(e), _) | (_, Err(e)) => log::warn!("Failed to sample scan cost: {}", e),
+        }
+        result
+    }
+
+    /// Record the cost of a cycle that ended at `at`
+    pub fn record(&mut self, at: Instant, cost: ScanCost) {
+        self.stats.cycles += 1;
+        self.stats.cpu_time += cost.cpu_time;
+        self.stats.read_bytes += cost.read_bytes;
+        self.window.push_back((at, cost));
+    }
+
+    /// Cost of the cycles within the window ending at `now`
+    pub fn window_cost(&mut self, now: Instant) -> ScanCost {
+        self.expire(now);
+        self.window
+            .iter()
+            .fold(ScanCost::default(), |total, (_, cost)| ScanCost {
+                cpu_time: total.cpu_time + cost.cpu_time,
+                read_bytes: total.read_bytes + cost.read_bytes,
+            })
+    }
+
+    /// Check the budget at `now`
+    ///
+    /// Returns how long the next cycle must wait until the cost of the
+    /// window is back within budget, or `None` if it may run now.
+    pub fn check(&mut self, now: Instant) -> Option<Duration> {
+        let total = self.window_cost(now);
+        let cpu_wait = self.budget.cpu_per_minute.and_then(|limit| {
+            self.wait_until_within(now, total.cpu_time, limit, |cost| cost.cpu_time)
+        });
+        let read_wait = self.budget.read_bytes_per_minute.and_then(|limit| {
+            self.wait_until_within(now, total.read_bytes, limit, |cost| cost.read_bytes)
+        });
+        cpu_wait.max(read_wait)
+    }
+
+    /// Wait as long as [`check`](Self::check) requires and record it
+    ///
+    /// Returns the time waited.
+    pub fn throttle(&mut self) -> Duration {
+        match self.check(Instant::now()) {
+            Some(delay) => {
+                log::debug!("Scan budget exceeded, delaying next cycle by {:?}", delay);
+                self.stats.throttled += 1;
+                self.stats.throttled_time += delay;
+                std::thread::sleep(delay);
+                delay
+            }
+            None => Duration::ZERO,
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(&(at, _)) = self.window.front() {
+            if now.saturating_duration_since(at) < BUDGET_WINDOW {
+                break;
+            }
+            self.window.pop_front();
+        }
+    }
+
+    /// Time until enough old cycles leave the window to bring `total`
+    /// within `limit`
+    fn wait_until_within<T>(
+        &self,
+        now: Instant,
+        mut total: T,
+        limit: T,
+        field: impl Fn(&ScanCost) -> T,
+    ) -> Option<Duration>
+    where
+        T: PartialOrd + std::ops::SubAssign,
+    {
+        if total <= limit {
+            return None;
+        }
+        for (at, cost) in &self.window {
+            total -= field(cost);
+            if total <= limit {
+                return Some((*at + BUDGET_WINDOW).saturating_duration_since(now));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cost(cpu_ms: u64, read_bytes: u64) -> ScanCost {
+        ScanCost {
+            cpu_time: Duration::from_millis(cpu_ms),
+            read_bytes,
+        }
+    }
+
+    #[test]
+    fn test_parse_rchar() {
+        let io = "rchar: 4096\nwchar: 12\nsyscr: 3\nread_bytes: 0\n";
+        assert_eq!(parse_rchar(io), Some(4096));
+        assert_eq!(parse_rchar("wchar: 12\n"), None);
+    }
+
+    #[test]
+    fn test_resource_usage() {
+        let before = ResourceUsage::current().unwrap();
+        let after = ResourceUsage::current().unwrap();
+        assert!(after.cpu_time >= before.cpu_time);
+        assert_eq!(before.since(&after).cpu_time, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_unlimited_budget() {
+        let mut limiter = CostLimiter::default();
+        let now = Instant::now();
+        limiter.record(now, cost(10_000, 1 << 30));
+        assert_eq!(limiter.check(now), None);
+        assert_eq!(limiter.stats().cycles, 1);
+    }
+
+    #[test]
+    fn test_cpu_budget_delays_until_window_clears() {
+        let budget = ScanBudget::new().with_cpu_per_minute(Some(Duration::from_millis(100)));
+        let mut limiter = CostLimiter::new(budget);
+        let start = Instant::now();
+        limiter.record(start, cost(60, 0));
+        limiter.record(start + Duration::from_secs(10), cost(60, 0));
+        assert!(limiter.budget().is_limited());
+
+        // 120ms in the window: the first cycle must age out
+        let now = start + Duration::from_secs(20);
+        assert_eq!(limiter.check(now), Some(Duration::from_secs(40)));
+
+        // Once it has, the window holds 60ms and scanning may resume
+        assert_eq!(limiter.check(start + BUDGET_WINDOW), None);
+        assert_eq!(limiter.window_cost(start + BUDGET_WINDOW), cost(60, 0));
+        assert_eq!(limiter.stats().cpu_time, Duration::from_millis(120));
+    }
+
+    #[test]
+    fn test_read_budget() {
+        let budget = ScanBudget::new().with_read_bytes_per_minute(Some(1000));
+        let mut limiter = CostLimiter::new(budget);
+        let start = Instant::now();
+        for i in 0..3 {
+            limiter.record(start + Duration::from_secs(i * 10), cost(0, 600));
+        }
+
+        // Two of the three cycles must leave the window
+        assert_eq!(
+            limiter.check(start + Duration::from_secs(30)),
+            Some(Duration::from_secs(40))
+        );
+    }
+
+    #[test]
+    fn test_stats_display() {
+        let mut stats = BudgetStats {
+            cycles: 2,
+            cpu_time: Duration::from_millis(15),
+            read_bytes: 2048,
+            ..BudgetStats::default()
+        };
+        assert_eq!(stats.to_string(), "2 cycles, 15.0ms CPU, 2.00 KB read");
+
+        stats.throttled = 1;
+        stats.throttled_time = Duration::from_millis(1500);
+        assert!(stats.to_string().ends_with(", throttled 1 times (1.5s)"));
+    }
+}
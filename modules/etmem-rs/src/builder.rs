@@ -292,7 +292,8 @@ impl SwapBuilder {
 
         let mut session = SwapSession::new(self.pid, self.config)?;
         session.add_addresses(&self.addresses)?;
-        session.flush()
+        session.flush()?;
+        Ok(session.submitted())
     }
 }
 
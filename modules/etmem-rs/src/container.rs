@@ -0,0 +1,269 @@
+//! Targets inside containers
+//!
+//! A process in a container has its own PID namespace, mount namespace and
+//! often user namespace, so the PID, paths and uids its owner knows it by
+//! are not the ones the host sees. This module translates them for a
+//! daemon running on the host, so container targets can be scanned and
+//! swapped without entering their namespaces:
+//!
+//! - [`resolve_pid`] finds the host PID of a process given its PID inside
+//!   the container, identified by any host PID of one of its processes
+//!   (e.g. the one `docker inspect -f '{{.State.Pid}}'` prints).
+//! - [`root_path`] resolves a path inside the container through
+//!   `/proc/<pid>/root`.
+//! - [`UidMap`] translates uids between a user namespace and the host, so
+//!   root inside a container is not mistaken for root on the host.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::container::resolve_pid;
+//! use etmem_rs::{IdlePageScanner, ScanConfig};
+//!
+//! // PID 1 of the container whose init runs as host PID 4242
+//! let pid = resolve_pid(4242, 1).expect("Failed to resolve container PID");
+//! let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+//!     .expect("Failed to scan");
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{EtmemError, Result};
+
+/// One line of a `uid_map`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct UidRange {
+    /// First uid inside the namespace
+    inside: u32,
+    /// First uid outside the namespace
+    outside: u32,
+    /// Number of uids in the range
+    count: u32,
+}
+
+/// Uid mapping of a user namespace (`/proc/<pid>/uid_map`)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UidMap {
+    /// Mapped ranges
+    ranges: Vec<UidRange>,
+}
+
+impl UidMap {
+    /// Parse the contents of a `uid_map`
+    ///
+    /// # Errors
+    /// Returns `ProcfsError` if a line is not three numbers
+    pub fn parse(content: &str) -> Result<Self> {
+        let ranges = content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let fields: Vec<u32> = line
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<std::result::Result<_, _>>()
+                    .map_err(|_| EtmemError::ProcfsError(format!("Bad uid_map line: {}", line)))?;
+                match fields[..] {
+                    [inside, outside, count] => Ok(UidRange {
+                        inside,
+                        outside,
+                        count,
+                    }),
+                    _ => Err(EtmemError::ProcfsError(format!(
+                        "Bad uid_map line: {}",
+                        line
+                    ))),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { ranges })
+    }
+
+    /// Read the uid mapping of the user namespace of a process
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process does not exist and
+    /// `ProcfsError` if its `uid_map` is malformed
+    pub fn for_process(pid: u32) -> Result<Self> {
+        let path = format!("/proc/{}/uid_map", pid);
+        let content = fs::read_to_string(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => EtmemError::ProcessNotFound,
+            _ => EtmemError::ProcfsError(format!("{}: {}", path, e)),
+        })?;
+        Self::parse(&content)
+    }
+
+    /// Translate a uid inside the namespace to the host
+    ///
+    /// # Returns
+    /// `None` if the uid is not mapped
+    pub fn to_host(&self, uid: u32) -> Option<u32> {
+        self.ranges
+            .iter()
+            .find(|r| uid >= r.inside && uid - r.inside < r.count)
+            .map(|r| r.outside + (uid - r.inside))
+    }
+
+    /// Translate a host uid into the namespace
+    ///
+    /// # Returns
+    /// `None` if the uid is not mapped
+    pub fn to_namespace(&self, uid: u32) -> Option<u32> {
+        self.ranges
+            .iter()
+            .find(|r| uid >= r.outside && uid - r.outside < r.count)
+            .map(|r| r.inside + (uid - r.outside))
+    }
+
+    /// Check whether every uid maps to itself, as in the initial namespace
+    pub fn is_identity(&self) -> bool {
+        self.ranges.iter().all(|r| r.inside == r.outside)
+    }
+}
+
+/// Check whether the caller is root on the host, not only inside a user
+/// namespace
+pub fn is_host_root() -> bool {
+    let euid = unsafe { libc::geteuid() };
+    euid == 0
+        && UidMap::for_process(std::process::id()).map_or(true, |map| map.to_host(euid) == Some(0))
+}
+
+/// Find the host PID of a process in a container
+///
+/// # Arguments
+/// * `container_pid` - Host PID of any process in the container's PID
+///   namespace
+/// * `pid` - PID of the target inside the container
+///
+/// # Errors
+/// Returns `ProcessNotFound` if no process has PID `pid` in that namespace
+pub fn resolve_pid(container_pid: u32, pid: u32) -> Result<u32> {
+    resolve_pid_in(Path::new("/proc"), container_pid, pid)
+}
+
+/// Find the host PID of a process in a container under an alternative
+/// procfs root
+///
+/// See [`resolve_pid`].
+pub fn resolve_pid_in(proc_root: &Path, container_pid: u32, pid: u32) -> Result<u32> {
+    let namespace = pid_namespace(proc_root, container_pid).ok_or(EtmemError::ProcessNotFound)?;
+    let entries = fs::read_dir(proc_root)
+        .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", proc_root.display(), e)))?;
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_string_lossy().parse::<u32>().ok())
+        .find(|&host_pid| {
+            fs::read_to_string(proc_root.join(host_pid.to_string()).join("status"))
+                .ok()
+                .and_then(|status| innermost_pid(&status))
+                == Some(pid)
+                && pid_namespace(proc_root, host_pid).as_ref() == Some(&namespace)
+        })
+        .ok_or(EtmemError::ProcessNotFound)
+}
+
+/// Resolve a path inside the mount namespace of a process
+///
+/// `/etc/hosts` of a containerized process becomes
+/// `/proc/<pid>/root/etc/hosts`, which the host can open.
+pub fn root_path(pid: u32, path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    Path::new("/proc")
+        .join(pid.to_string())
+        .join("root")
+        .join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Identify the PID namespace of a process (`pid:[<inode>]`)
+fn pid_namespace(proc_root: &Path, pid: u32) -> Option<PathBuf> {
+    fs::read_link(proc_root.join(pid.to_string()).join("ns/pid")).ok()
+}
+
+/// Extract the PID in the innermost namespace from `/proc/<pid>/status`
+///
+/// The `NSpid` line lists the PID in every namespace the process is in,
+/// outermost first.
+fn innermost_pid(status: &str) -> Option<u32> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("NSpid:"))
+        .and_then(|pids| pids.split_whitespace().last())
+        .and_then(|pid| pid.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_uid_map() {
+        let map = UidMap::parse("         0     100000      65536\n").unwrap();
+        assert_eq!(map.to_host(0), Some(100000));
+        assert_eq!(map.to_host(33), Some(100033));
+        assert_eq!(map.to_host(65536), None);
+        assert_eq!(map.to_namespace(100033), Some(33));
+        assert_eq!(map.to_namespace(0), None);
+        assert!(!map.is_identity());
+
+        let host = UidMap::parse("0 0 4294967295\n").unwrap();
+        assert_eq!(host.to_host(1000), Some(1000));
+        assert!(host.is_identity());
+
+        assert!(UidMap::parse("0 100000\n").is_err());
+        assert!(UidMap::parse("a b c\n").is_err());
+    }
+
+    #[test]
+    fn test_uid_map_of_self() {
+        let map = UidMap::for_process(std::process::id()).unwrap();
+        let euid = unsafe { libc::geteuid() };
+        assert!(map.to_host(euid).is_some());
+    }
+
+    #[test]
+    fn test_innermost_pid() {
+        assert_eq!(innermost_pid("Name:\tnginx\nNSpid:\t4242\t1\n"), Some(1));
+        assert_eq!(innermost_pid("NSpid:\t4242\n"), Some(4242));
+        assert_eq!(innermost_pid("Name:\tnginx\n"), None);
+    }
+
+    #[test]
+    fn test_resolve_pid_in() {
+        use std::os::unix::fs::symlink;
+
+        let proc_root = tempfile::tempdir().unwrap();
+        let process = |pid: u32, nspid: &str, namespace: &str| {
+            let dir = proc_root.path().join(pid.to_string());
+            fs::create_dir_all(dir.join("ns")).unwrap();
+            fs::write(dir.join("status"), format!("NSpid:\t{}\n", nspid)).unwrap();
+            symlink(namespace, dir.join("ns/pid")).unwrap();
+        };
+        process(1, "1", "pid:[4026531836]");
+        process(4242, "4242\t1", "pid:[4026532500]");
+        process(4250, "4250\t8", "pid:[4026532500]");
+        process(5000, "5000\t8", "pid:[4026532600]");
+
+        assert_eq!(resolve_pid_in(proc_root.path(), 4242, 8), Ok(4250));
+        assert_eq!(resolve_pid_in(proc_root.path(), 5000, 8), Ok(5000));
+        assert_eq!(resolve_pid_in(proc_root.path(), 4242, 1), Ok(4242));
+        assert_eq!(
+            resolve_pid_in(proc_root.path(), 4242, 9),
+            Err(EtmemError::ProcessNotFound)
+        );
+        assert_eq!(
+            resolve_pid_in(proc_root.path(), 7, 1),
+            Err(EtmemError::ProcessNotFound)
+        );
+    }
+
+    #[test]
+    fn test_root_path() {
+        assert_eq!(
+            root_path(42, "/usr/lib/libc.so.6"),
+            PathBuf::from("/proc/42/root/usr/lib/libc.so.6")
+        );
+    }
+}
@@ -0,0 +1,564 @@
+//! DAMON-compatible region reports
+//!
+//! DAMON, the kernel's data access monitor, describes a process as a list
+//! of address ranges with an access frequency (`nr_accesses`, out of the
+//! samples taken per aggregation interval) and an age (how many
+//! aggregation intervals the frequency stayed the same). This module
+//! translates scan results into the same shape, so tooling built around
+//! DAMON output can consume them, and reads DAMON's own results from sysfs
+//! to cross-check the two.
+//!
+//! A single scan maps onto one aggregation interval with one sample: a
+//! region is accessed or not. Several scans of the same process are one
+//! sample each, giving `nr_accesses` out of the number of scans.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::damon::{DamonComparison, DamonReport};
+//! use etmem_rs::{IdlePageScanner, ScanConfig};
+//!
+//! let pid = 1234;
+//! let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())?;
+//! let etmem = DamonReport::from_pages(pid, &pages);
+//! print!("{etmem}");
+//!
+//! if let Some(damon) = DamonReport::from_sysfs(pid)? {
+//!     let comparison = DamonComparison::between(&etmem, &damon);
+//!     println!("{comparison}");
+//! }
+//! # Ok::<(), etmem_rs::EtmemError>(())
+//! ```
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EtmemError, Result};
+use crate::types::{IdlePageInfo, ProcIdlePageType};
+use crate::util::format_bytes;
+
+/// Root of the DAMON sysfs interface
+pub const DAMON_SYSFS_ROOT: &str = "/sys/kernel/mm/damon/admin";
+
+/// Check if the DAMON sysfs interface is present
+pub fn is_available() -> bool {
+    Path::new(DAMON_SYSFS_ROOT).join("kdamonds").is_dir()
+}
+
+/// Monitoring region in DAMON's terms
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamonRegion {
+    /// Start address (inclusive)
+    pub start: u64,
+    /// End address (exclusive)
+    pub end: u64,
+    /// Samples in which the region was accessed
+    pub nr_accesses: u32,
+    /// Intervals the access frequency stayed the same
+    pub age: u32,
+}
+
+impl DamonRegion {
+    /// Get the size of the region in bytes
+    pub const fn size(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Check if the region was accessed in any sample
+    pub const fn is_accessed(&self) -> bool {
+        self.nr_accesses > 0
+    }
+}
+
+/// Monitoring regions of one target, sorted by address
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DamonReport {
+    /// Monitored process
+    pub target_id: u64,
+    /// Samples per aggregation interval, the largest possible `nr_accesses`
+    pub max_nr_accesses: u32,
+    /// Regions in address order
+    pub regions: Vec<DamonRegion>,
+}
+
+impl DamonReport {
+    /// Translate the pages of one scan
+    pub fn from_pages(pid: u32, pages: &[IdlePageInfo]) -> Self {
+        Self::from_scans(pid, &[pages])
+    }
+
+    /// Translate successive scans of a process, oldest first
+    ///
+    /// Each scan is one sample: `nr_accesses` counts the scans that found
+    /// a page accessed and `age` the most recent scans agreeing with the
+    /// last one. Adjacent pages with the same counts form one region; holes
+    /// and pages absent from every scan are left out.
+    pub fn from_scans<S: AsRef<[IdlePageInfo]>>(pid: u32, scans: &[S]) -> Self {
+        let spans: Vec<Vec<(u64, u64, bool)>> =
+            scans.iter().map(|s| access_spans(s.as_ref())).collect();
+
+        let mut bounds: Vec<u64> = spans
+            .iter()
+            .flatten()
+            .flat_map(|&(start, end, _)| [start, end])
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut cursors = vec![0usize; spans.len()];
+        let mut regions: Vec<DamonRegion> = Vec::new();
+        for pair in bounds.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+
+            // Access state of [start, end) in each scan, None if absent
+            let states: Vec<Option<bool>> = spans
+                .iter()
+                .zip(cursors.iter_mut())
+                .map(|(spans, cursor)| {
+                    while spans.get(*cursor).is_some_and(|s| s.1 <= start) {
+                        *cursor += 1;
+                    }
+                    spans.get(*cursor).filter(|s| s.0 <= start).map(|s| s.2)
+                })
+                .collect();
+            if states.iter().all(Option::is_none) {
+                continue;
+            }
+
+            let accessed = |state: &Option<bool>| *state == Some(true);
+            let nr_accesses = states.iter().filter(|s| accessed(s)).count() as u32;
+            let last = states.last().is_some_and(accessed);
+            let age = states
+                .iter()
+                .rev()
+                .take_while(|s| accessed(s) == last)
+                .count() as u32;
+
+            match regions.last_mut() {
+                Some(prev)
+                    if prev.end == start && prev.nr_accesses == nr_accesses && prev.age == age =>
+                {
+                    prev.end = end
+                }
+                _ => regions.push(DamonRegion {
+                    start,
+                    end,
+                    nr_accesses,
+                    age,
+                }),
+            }
+        }
+
+        Self {
+            target_id: u64::from(pid),
+            max_nr_accesses: scans.len() as u32,
+            regions,
+        }
+    }
+
+    /// Read DAMON's monitoring results for a process from sysfs
+    ///
+    /// See [`DamonReport::from_sysfs_in`].
+    pub fn from_sysfs(pid: u32) -> Result<Option<Self>> {
+        Self::from_sysfs_in(DAMON_SYSFS_ROOT, pid)
+    }
+
+    /// Read DAMON's monitoring results for a process below a sysfs root
+    ///
+    /// Looks for a kdamond context whose only target is `pid` and returns
+    /// the regions of its first scheme that has tried regions. These are
+    /// only filled in after `update_schemes_tried_regions` is written to
+    /// the kdamond's `state` file, which this does not do.
+    ///
+    /// # Returns
+    /// `None` if no context monitors the process alone or none of its
+    /// schemes has tried regions
+    ///
+    /// # Errors
+    /// Returns `NotSupported` if there is no DAMON sysfs interface under
+    /// `root`, or a procfs error if a region cannot be read.
+    pub fn from_sysfs_in<P: AsRef<Path>>(root: P, pid: u32) -> Result<Option<Self>> {
+        let kdamonds = root.as_ref().join("kdamonds");
+        if !kdamonds.is_dir() {
+            return Err(EtmemError::NotSupported);
+        }
+
+        for kdamond in numbered_dirs(&kdamonds) {
+            for context in numbered_dirs(&kdamond.join("contexts")) {
+                let targets = numbered_dirs(&context.join("targets"));
+                let [target] = targets.as_slice() else {
+                    continue;
+                };
+                if read_number(&target.join("pid_target")).ok() != Some(u64::from(pid)) {
+                    continue;
+                }
+
+                let intervals = context.join("monitoring_attrs").join("intervals");
+                let sample_us = read_number(&intervals.join("sample_us")).unwrap_or(0);
+                let aggr_us = read_number(&intervals.join("aggr_us")).unwrap_or(0);
+                let max_nr_accesses = aggr_us.checked_div(sample_us).unwrap_or(0) as u32;
+
+                for scheme in numbered_dirs(&context.join("schemes")) {
+                    let mut regions = Vec::new();
+                    for region in numbered_dirs(&scheme.join("tried_regions")) {
+                        regions.push(DamonRegion {
+                            start: read_number(&region.join("start"))?,
+                            end: read_number(&region.join("end"))?,
+                            nr_accesses: read_number(&region.join("nr_accesses"))? as u32,
+                            age: read_number(&region.join("age"))? as u32,
+                        });
+                    }
+                    if !regions.is_empty() {
+                        regions.sort_unstable_by_key(|r| r.start);
+                        return Ok(Some(Self {
+                            target_id: u64::from(pid),
+                            max_nr_accesses,
+                            regions,
+                        }));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Total bytes covered by the regions
+    pub fn total_bytes(&self) -> u64 {
+        self.regions.iter().map(DamonRegion::size).sum()
+    }
+
+    /// Total bytes of regions accessed in any sample
+    pub fn accessed_bytes(&self) -> u64 {
+        self.regions
+            .iter()
+            .filter(|r| r.is_accessed())
+            .map(DamonRegion::size)
+            .sum()
+    }
+
+    /// Access heat of `bins` equal slices of the monitored span
+    ///
+    /// Each entry is the start address of a slice and its heat, the
+    /// size-weighted access frequency (0.0 - 1.0) of the regions in it;
+    /// unmonitored parts of a slice count as cold. This is the data of a
+    /// DAMON heatmap at a single point in time.
+    pub fn heats(&self, bins: usize) -> Vec<(u64, f64)> {
+        let (Some(first), Some(last)) = (self.regions.first(), self.regions.last()) else {
+            return Vec::new();
+        };
+        if bins == 0 {
+            return Vec::new();
+        }
+        let span = last.end - first.start;
+        let width = span.div_ceil(bins as u64).max(1);
+        let max = f64::from(self.max_nr_accesses.max(1));
+
+        let mut heat = vec![0.0; bins];
+        for region in &self.regions {
+            let rate = f64::from(region.nr_accesses) / max;
+            let mut addr = region.start;
+            while addr < region.end {
+                let bin = ((addr - first.start) / width) as usize;
+                let bin_end = first.start + (bin as u64 + 1) * width;
+                let end = region.end.min(bin_end);
+                heat[bin] += rate * (end - addr) as f64 / width as f64;
+                addr = end;
+            }
+        }
+
+        heat.into_iter()
+            .enumerate()
+            .map(|(bin, heat)| (first.start + bin as u64 * width, heat))
+            .collect()
+    }
+}
+
+/// Formatted like the regions of `damo report raw`
+impl fmt::Display for DamonReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "target_id: {}", self.target_id)?;
+        writeln!(f, "nr_regions: {}", self.regions.len())?;
+        for region in &self.regions {
+            writeln!(
+                f,
+                "{:x}-{:x}({:>10}): {:>3} {:>3}",
+                region.start,
+                region.end,
+                format_bytes(region.size()),
+                region.nr_accesses,
+                region.age
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Agreement between a report from scans and one from DAMON
+///
+/// Only bytes covered by both reports are compared; a region is hot when
+/// it was accessed in any sample.
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DamonComparison {
+    /// Bytes covered by both reports
+    pub compared_bytes: u64,
+    /// Compared bytes both reports call hot or both call cold
+    pub agreeing_bytes: u64,
+    /// Compared bytes only the scans found accessed
+    pub hot_only_etmem_bytes: u64,
+    /// Compared bytes only DAMON found accessed
+    pub hot_only_damon_bytes: u64,
+    /// Bytes of the scan report outside DAMON's regions
+    pub etmem_only_bytes: u64,
+}
+
+impl DamonComparison {
+    /// Compare a report translated from scans with DAMON's
+    pub fn between(etmem: &DamonReport, damon: &DamonReport) -> Self {
+        let mut comparison = Self::default();
+        let mut j = 0;
+        for ours in &etmem.regions {
+            let mut covered = 0;
+            while damon.regions.get(j).is_some_and(|r| r.end <= ours.start) {
+                j += 1;
+            }
+            for theirs in damon.regions[j..].iter().take_while(|r| r.start < ours.end) {
+                let overlap = ours.end.min(theirs.end) - ours.start.max(theirs.start);
+                covered += overlap;
+                match (ours.is_accessed(), theirs.is_accessed()) {
+                    (true, false) => comparison.hot_only_etmem_bytes += overlap,
+                    (false, true) => comparison.hot_only_damon_bytes += overlap,
+                    _ => comparison.agreeing_bytes += overlap,
+                }
+            }
+            comparison.compared_bytes += covered;
+            comparison.etmem_only_bytes += ours.size() - covered;
+        }
+        comparison
+    }
+
+    /// Fraction of the compared bytes both reports agree on (0.0 - 1.0)
+    pub fn agreement(&self) -> f64 {
+        if self.compared_bytes == 0 {
+            0.0
+        } else {
+            self.agreeing_bytes as f64 / self.compared_bytes as f64
+        }
+    }
+
+    /// Check if the reports agree on at least `min_agreement` of the
+    /// compared bytes
+    pub fn is_equivalent(&self, min_agreement: f64) -> bool {
+        self.compared_bytes > 0 && self.agreement() >= min_agreement
+    }
+}
+
+impl fmt::Display for DamonComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.1}% of {} agree, {} hot only in etmem, {} hot only in DAMON, {} not monitored by DAMON",
+            self.agreement() * 100.0,
+            format_bytes(self.compared_bytes),
+            format_bytes(self.hot_only_etmem_bytes),
+            format_bytes(self.hot_only_damon_bytes),
+            format_bytes(self.etmem_only_bytes)
+        )
+    }
+}
+
+/// Present pages of a scan as merged `(start, end, accessed)` spans
+fn access_spans(pages: &[IdlePageInfo]) -> Vec<(u64, u64, bool)> {
+    let mut spans: Vec<(u64, u64, bool)> = pages
+        .iter()
+        .filter_map(|p| {
+            let accessed = match p.page_type {
+                _ if p.is_idle() => false,
+                ProcIdlePageType::PteDirty | ProcIdlePageType::PmdDirty => true,
+                _ if p.is_accessed() => true,
+                _ => return None,
+            };
+            Some((p.address, p.end_address(), accessed))
+        })
+        .collect();
+    spans.sort_unstable_by_key(|s| s.0);
+
+    let mut merged: Vec<(u64, u64, bool)> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if last.1 == span.0 && last.2 == span.2 => last.1 = span.1,
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+/// Subdirectories of `dir` named by a number, in numeric order
+fn numbered_dirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut dirs: Vec<(u64, PathBuf)> = entries
+        .flatten()
+        .filter_map(|e| {
+            let index = e.file_name().to_str()?.parse().ok()?;
+            Some((index, e.path()))
+        })
+        .filter(|(_, path)| path.is_dir())
+        .collect();
+    dirs.sort_unstable_by_key(|(index, _)| *index);
+    dirs.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Read a sysfs file holding one decimal number
+fn read_number(path: &Path) -> Result<u64> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", path.display(), e)))?;
+    content
+        .trim()
+        .parse()
+        .map_err(|_| EtmemError::ProcfsError(format!("{}: not a number", path.display())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(address: u64, page_type: ProcIdlePageType, count: u8) -> IdlePageInfo {
+        IdlePageInfo::new(address, page_type, count)
+    }
+
+    fn region(start: u64, end: u64, nr_accesses: u32) -> DamonRegion {
+        DamonRegion {
+            start,
+            end,
+            nr_accesses,
+            age: 0,
+        }
+    }
+
+    #[test]
+    fn test_from_scans() {
+        let first = vec![
+            page(0x1000, ProcIdlePageType::PteAccessed, 2),
+            page(0x3000, ProcIdlePageType::PteIdle, 2),
+            page(0x5000, ProcIdlePageType::PteHole, 1),
+        ];
+        let second = vec![
+            page(0x1000, ProcIdlePageType::PteIdle, 1),
+            page(0x2000, ProcIdlePageType::PteDirty, 1),
+            page(0x3000, ProcIdlePageType::PteIdle, 3),
+        ];
+        let report = DamonReport::from_scans(42, &[first, second]);
+
+        assert_eq!(report.target_id, 42);
+        assert_eq!(report.max_nr_accesses, 2);
+        assert_eq!(
+            report.regions,
+            vec![
+                DamonRegion {
+                    start: 0x1000,
+                    end: 0x2000,
+                    nr_accesses: 1,
+                    age: 1,
+                },
+                DamonRegion {
+                    start: 0x2000,
+                    end: 0x3000,
+                    nr_accesses: 2,
+                    age: 2,
+                },
+                DamonRegion {
+                    start: 0x3000,
+                    end: 0x6000,
+                    nr_accesses: 0,
+                    age: 2,
+                },
+            ]
+        );
+        assert_eq!(report.accessed_bytes(), 0x2000);
+        assert!(
+            report
+                .to_string()
+                .starts_with("target_id: 42\nnr_regions: 3\n1000-2000(")
+        );
+    }
+
+    #[test]
+    fn test_heats() {
+        let report = DamonReport {
+            target_id: 1,
+            max_nr_accesses: 4,
+            regions: vec![region(0, 0x1000, 4), region(0x3000, 0x4000, 2)],
+        };
+        let heats = report.heats(2);
+        assert_eq!(heats, vec![(0, 0.5), (0x2000, 0.25)]);
+        assert!(DamonReport::default().heats(4).is_empty());
+    }
+
+    #[test]
+    fn test_comparison() {
+        let etmem = DamonReport {
+            regions: vec![region(0x1000, 0x3000, 1), region(0x3000, 0x5000, 0)],
+            ..DamonReport::default()
+        };
+        let damon = DamonReport {
+            regions: vec![region(0, 0x2000, 10), region(0x2000, 0x4000, 0)],
+            ..DamonReport::default()
+        };
+        let comparison = DamonComparison::between(&etmem, &damon);
+        assert_eq!(comparison.compared_bytes, 0x3000);
+        assert_eq!(comparison.agreeing_bytes, 0x2000);
+        assert_eq!(comparison.hot_only_etmem_bytes, 0x1000);
+        assert_eq!(comparison.hot_only_damon_bytes, 0);
+        assert_eq!(comparison.etmem_only_bytes, 0x1000);
+        assert!(comparison.is_equivalent(0.6));
+        assert!(!comparison.is_equivalent(0.7));
+    }
+
+    #[test]
+    fn test_from_sysfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let write = |path: &str, value: &str| {
+            let path = root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("{value}\n")).unwrap();
+        };
+
+        let context = "kdamonds/0/contexts/0";
+        write(&format!("{context}/targets/0/pid_target"), "42");
+        write(
+            &format!("{context}/monitoring_attrs/intervals/sample_us"),
+            "5000",
+        );
+        write(
+            &format!("{context}/monitoring_attrs/intervals/aggr_us"),
+            "100000",
+        );
+        for (idx, (start, end, nr)) in [(0x2000, 0x3000, "0"), (0x1000, 0x2000, "7")]
+            .into_iter()
+            .enumerate()
+        {
+            let region = format!("{context}/schemes/0/tried_regions/{idx}");
+            write(&format!("{region}/start"), &start.to_string());
+            write(&format!("{region}/end"), &end.to_string());
+            write(&format!("{region}/nr_accesses"), nr);
+            write(&format!("{region}/age"), "3");
+        }
+
+        assert_eq!(DamonReport::from_sysfs_in(root, 7).unwrap(), None);
+        let report = DamonReport::from_sysfs_in(root, 42).unwrap().unwrap();
+        assert_eq!(report.max_nr_accesses, 20);
+        assert_eq!(report.regions[0].start, 0x1000);
+        assert_eq!(report.regions[0].nr_accesses, 7);
+        assert_eq!(report.regions[1].age, 3);
+
+        assert_eq!(
+            DamonReport::from_sysfs_in(root.join("missing"), 42),
+            Err(EtmemError::NotSupported)
+        );
+    }
+}
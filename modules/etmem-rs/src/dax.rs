@@ -0,0 +1,167 @@
+//! Detection of DAX (direct access) mappings
+//!
+//! Device-DAX (`/dev/daxX.Y`) and fsdax (files on a filesystem mounted with
+//! `-o dax`) map persistent or device memory straight into the process,
+//! bypassing the page cache. Scans report their pages like any other, but
+//! they cannot be swapped, and reclaiming them would only fail or, worse,
+//! hit the device. Mappings are classified here so that reports count them
+//! separately and reclaim leaves them out.
+//!
+//! Device-DAX is recognised from the path in `/proc/<pid>/maps` alone.
+//! Fsdax needs the mount table of the target's mount namespace, read from
+//! `/proc/<pid>/mountinfo`: a file is fsdax if the mount it lives on has
+//! the `dax` or `dax=always` option.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::VmaMap;
+//!
+//! let vma_map = VmaMap::for_process(std::process::id() as u32).expect("Failed to parse VMAs");
+//! for vma in vma_map.regions() {
+//!     if let Some(kind) = vma.dax {
+//!         println!("{} is {}, never swapped", vma.name(), kind.as_str());
+//!     }
+//! }
+//! ```
+
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EtmemError, Result};
+
+/// Path prefix of device-DAX character devices
+pub const DEVICE_DAX_PREFIX: &str = "/dev/dax";
+
+/// Kind of DAX mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DaxKind {
+    /// Device-DAX character device (`/dev/daxX.Y`)
+    Device,
+    /// File on a filesystem mounted with DAX
+    Fs,
+}
+
+impl DaxKind {
+    /// Get a short name for the kind
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Device => "devdax",
+            Self::Fs => "fsdax",
+        }
+    }
+
+    /// Classify a mapping by its path alone
+    ///
+    /// Only device-DAX can be recognised this way; fsdax needs the mount
+    /// table.
+    pub fn from_pathname(pathname: Option<&str>) -> Option<Self> {
+        pathname
+            .filter(|p| p.starts_with(DEVICE_DAX_PREFIX))
+            .map(|_| Self::Device)
+    }
+}
+
+/// Mount table of a mount namespace, reduced to what DAX detection needs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DaxMounts {
+    /// Mount points and whether each has DAX enabled, longest first
+    mounts: Vec<(String, bool)>,
+}
+
+impl DaxMounts {
+    /// Read the mount table of a process's mount namespace
+    ///
+    /// # Errors
+    /// Returns error if `/proc/<pid>/mountinfo` cannot be read.
+    pub fn for_process(pid: u32) -> Result<Self> {
+        let path = format!("/proc/{}/mountinfo", pid);
+        let content = fs::read_to_string(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => EtmemError::ProcessNotFound,
+            _ => EtmemError::ProcfsError(format!("{}: {}", path, e)),
+        })?;
+        Ok(Self::parse(&content))
+    }
+
+    /// Parse `mountinfo` content; malformed lines are skipped
+    pub fn parse(content: &str) -> Self {
+        let mut mounts: Vec<(String, bool)> = content.lines().filter_map(parse_mount).collect();
+        mounts.sort_by_key(|(point, _)| std::cmp::Reverse(point.len()));
+        Self { mounts }
+    }
+
+    /// Check whether any mount has DAX enabled
+    pub fn has_dax(&self) -> bool {
+        self.mounts.iter().any(|&(_, dax)| dax)
+    }
+
+    /// Classify a mapping by its path
+    pub fn classify(&self, pathname: Option<&str>) -> Option<DaxKind> {
+        if let Some(kind) = DaxKind::from_pathname(pathname) {
+            return Some(kind);
+        }
+        let path = pathname.filter(|p| p.starts_with('/'))?;
+        let path = path.strip_suffix(" (deleted)").unwrap_or(path);
+        self.mounts
+            .iter()
+            .find(|(point, _)| is_under(path, point))
+            .and_then(|&(_, dax)| dax.then_some(DaxKind::Fs))
+    }
+}
+
+/// Parse one `mountinfo` line into its mount point and DAX state
+///
+/// Format: `id parent major:minor root mount-point options [tags] - fstype
+/// source super-options`. DAX shows up in the super options.
+fn parse_mount(line: &str) -> Option<(String, bool)> {
+    let (mount, fs) = line.split_once(" - ")?;
+    let point = mount.split_whitespace().nth(4)?;
+    let super_options = fs.split_whitespace().nth(2).unwrap_or_default();
+    let dax = super_options
+        .split(',')
+        .any(|option| option == "dax" || option == "dax=always");
+    Some((point.replace("\\040", " "), dax))
+}
+
+/// Check whether `path` lies on the mount at `point`
+fn is_under(path: &str, point: &str) -> bool {
+    point == "/"
+        || path
+            .strip_prefix(point)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+30 22 259:0 / /mnt/pmem rw,relatime shared:2 - ext4 /dev/pmem0 rw,dax=always
+31 30 8:2 / /mnt/pmem/cache rw,relatime shared:3 - ext4 /dev/sda2 rw
+32 22 259:1 / /mnt/pmem\\040two rw,relatime shared:4 - xfs /dev/pmem1 rw,attr2,dax
+33 22 259:2 / /mnt/pmem2 rw,relatime shared:5 - xfs /dev/pmem2 rw,dax=inode
+";
+
+    #[test]
+    fn test_classify() {
+        let mounts = DaxMounts::parse(MOUNTINFO);
+        assert!(mounts.has_dax());
+        assert_eq!(mounts.classify(Some("/dev/dax0.0")), Some(DaxKind::Device));
+        assert_eq!(
+            mounts.classify(Some("/mnt/pmem/table.db")),
+            Some(DaxKind::Fs)
+        );
+        assert_eq!(
+            mounts.classify(Some("/mnt/pmem two/log (deleted)")),
+            Some(DaxKind::Fs)
+        );
+        // Nested mount without DAX, prefix that is not a parent, per-inode DAX
+        assert_eq!(mounts.classify(Some("/mnt/pmem/cache/x")), None);
+        assert_eq!(mounts.classify(Some("/mnt/pmemx/file")), None);
+        assert_eq!(mounts.classify(Some("/mnt/pmem2/file")), None);
+        assert_eq!(mounts.classify(Some("[heap]")), None);
+        assert_eq!(mounts.classify(None), None);
+    }
+}
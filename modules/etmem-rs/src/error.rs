@@ -18,6 +18,8 @@ pub enum EtmemError {
     InvalidWatermark,
     /// Invalid scan flags
     InvalidFlags,
+    /// Scan flags rejected by the kernel (raw flag bits)
+    UnsupportedFlags(u32),
     /// Procfs operation failed
     ProcfsError(String),
     /// IOCTL operation failed
@@ -56,6 +58,16 @@ pub enum EtmemError {
     VmaParseError(String),
     /// Invalid VMA region
     InvalidVma(String),
+    /// Reclaim refused by the swap pressure guard
+    ReclaimRefused(String),
+    /// Invalid PSI trigger parameters
+    InvalidTrigger(String),
+    /// Invalid failpoint specification
+    #[cfg(feature = "failpoints")]
+    InvalidFailpoint(String),
+    /// Kubernetes pod or container cgroup not found
+    #[cfg(feature = "k8s")]
+    PodNotFound(String),
 }
 
 impl fmt::Display for EtmemError {
@@ -65,6 +77,9 @@ impl fmt::Display for EtmemError {
             EtmemError::InvalidAddress => write!(f, "Invalid virtual address"),
             EtmemError::InvalidWatermark => write!(f, "Invalid watermark configuration"),
             EtmemError::InvalidFlags => write!(f, "Invalid scan flags"),
+            EtmemError::UnsupportedFlags(bits) => {
+                write!(f, "Scan flags not supported by the kernel: {:#x}", bits)
+            }
             EtmemError::ProcfsError(msg) => write!(f, "Procfs error: {}", msg),
             EtmemError::IoctlError(code) => write!(f, "IOCTL failed with code: {}", code),
             EtmemError::BufferTooSmall => write!(
@@ -96,6 +111,12 @@ impl fmt::Display for EtmemError {
             EtmemError::InvalidRange => write!(f, "Invalid address range"),
             EtmemError::VmaParseError(msg) => write!(f, "VMA parse error: {}", msg),
             EtmemError::InvalidVma(msg) => write!(f, "Invalid VMA: {}", msg),
+            EtmemError::ReclaimRefused(msg) => write!(f, "Reclaim refused: {}", msg),
+            EtmemError::InvalidTrigger(msg) => write!(f, "Invalid PSI trigger: {}", msg),
+            #[cfg(feature = "failpoints")]
+            EtmemError::InvalidFailpoint(msg) => write!(f, "Invalid failpoint: {}", msg),
+            #[cfg(feature = "k8s")]
+            EtmemError::PodNotFound(msg) => write!(f, "Not found in kubepods cgroups: {}", msg),
         }
     }
 }
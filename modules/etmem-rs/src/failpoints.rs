@@ -0,0 +1,408 @@
+//! Error injection for resilience testing
+//!
+//! Available with the `failpoints` feature. Each [`Failpoint`] names a place
+//! where ETMEM talks to the kernel; arming it makes that call fail with a
+//! chosen [`FailAction`] instead, so retry and backoff paths can be driven
+//! deterministically without a misbehaving kernel.
+//!
+//! Failpoints are process-global and configured at runtime, either through
+//! [`set`]/[`set_times`] or from a spec string such as the one in the
+//! `ETMEM_FAILPOINTS` environment variable:
+//!
+//! ```text
+//! scan_read=eintr*3,swap_write=short:64,ioctl=enotty,scan_status=kbuf_full*1
+//! ```
+//!
+//! Each entry is `<point>=<action>[*<times>]`; without `*<times>` the
+//! failpoint fires on every call until cleared. Actions are `eintr`,
+//! `eagain`, `eio`, `enotty`, `errno:<n>`, `short:<bytes>`, `kbuf_full`,
+//! `buf_full` and `off`.
+//!
+//! # Example
+//! ```
+//! use etmem_rs::failpoints::{self, FailAction, Failpoint};
+//!
+//! // The next two idle page reads are interrupted
+//! failpoints::set_times(Failpoint::ScanRead, FailAction::Errno(libc::EINTR), 2);
+//! failpoints::clear_all();
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::{EtmemError, Result};
+use crate::types::BufferStatus;
+
+/// Environment variable read by [`configure_from_env`]
+pub const FAILPOINTS_ENV: &str = "ETMEM_FAILPOINTS";
+
+/// Place where a failure can be injected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Failpoint {
+    /// Reads from `/proc/[pid]/idle_pages`
+    ScanRead,
+    /// Buffer status of an idle page read, which decides whether to read on
+    ScanStatus,
+    /// Writes to `/proc/[pid]/swap_pages`
+    SwapWrite,
+    /// IOCTLs on procfs handles
+    Ioctl,
+}
+
+impl Failpoint {
+    /// All failpoints
+    pub const ALL: [Failpoint; 4] = [
+        Failpoint::ScanRead,
+        Failpoint::ScanStatus,
+        Failpoint::SwapWrite,
+        Failpoint::Ioctl,
+    ];
+
+    /// Name used in spec strings
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Failpoint::ScanRead => "scan_read",
+            Failpoint::ScanStatus => "scan_status",
+            Failpoint::SwapWrite => "swap_write",
+            Failpoint::Ioctl => "ioctl",
+        }
+    }
+}
+
+impl fmt::Display for Failpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Failpoint {
+    type Err = EtmemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|fp| fp.name() == s)
+            .ok_or_else(|| EtmemError::InvalidFailpoint(format!("unknown failpoint {s}")))
+    }
+}
+
+/// Failure injected when a failpoint fires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailAction {
+    /// Fail the call with this errno
+    Errno(i32),
+    /// Pass at most this many bytes to the kernel (writes only)
+    ShortWrite(usize),
+    /// Treat the read as ended with this buffer status (`scan_status` only)
+    Status(BufferStatus),
+}
+
+impl FromStr for FailAction {
+    type Err = EtmemError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || EtmemError::InvalidFailpoint(format!("invalid action {s}"));
+        let action = match s {
+            "eintr" => FailAction::Errno(libc::EINTR),
+            "eagain" => FailAction::Errno(libc::EAGAIN),
+            "eio" => FailAction::Errno(libc::EIO),
+            "enotty" => FailAction::Errno(libc::ENOTTY),
+            "kbuf_full" => FailAction::Status(BufferStatus::KbufFull),
+            "buf_full" => FailAction::Status(BufferStatus::BufFull),
+            _ => {
+                if let Some(errno) = s.strip_prefix("errno:") {
+                    FailAction::Errno(errno.parse().map_err(|_| invalid())?)
+                } else if let Some(bytes) = s.strip_prefix("short:") {
+                    FailAction::ShortWrite(bytes.parse().map_err(|_| invalid())?)
+                } else {
+                    return Err(invalid());
+                }
+            }
+        };
+        Ok(action)
+    }
+}
+
+/// Armed failpoint
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    action: FailAction,
+    /// Remaining firings, `None` for unlimited
+    remaining: Option<u32>,
+}
+
+/// Armed failpoints and how often each has fired
+#[derive(Debug, Default)]
+struct Registry {
+    armed: BTreeMap<Failpoint, Entry>,
+    hits: BTreeMap<Failpoint, u64>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    armed: BTreeMap::new(),
+    hits: BTreeMap::new(),
+});
+
+/// Fast path for hooks while nothing is armed
+static ARMED: AtomicBool = AtomicBool::new(false);
+
+fn with_registry<T>(f: impl FnOnce(&mut Registry) -> T) -> T {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|e| e.into_inner());
+    let result = f(&mut registry);
+    ARMED.store(!registry.armed.is_empty(), Ordering::Release);
+    result
+}
+
+/// Arm a failpoint to fire on every call until cleared
+pub fn set(point: Failpoint, action: FailAction) {
+    with_registry(|r| {
+        r.armed.insert(
+            point,
+            Entry {
+                action,
+                remaining: None,
+            },
+        )
+    });
+}
+
+/// Arm a failpoint to fire on the next `times` calls
+pub fn set_times(point: Failpoint, action: FailAction, times: u32) {
+    with_registry(|r| {
+        if times == 0 {
+            r.armed.remove(&point);
+        } else {
+            r.armed.insert(
+                point,
+                Entry {
+                    action,
+                    remaining: Some(times),
+                },
+            );
+        }
+    });
+}
+
+/// Disarm a failpoint
+pub fn clear(point: Failpoint) {
+    with_registry(|r| r.armed.remove(&point));
+}
+
+/// Disarm all failpoints and reset hit counters
+pub fn clear_all() {
+    with_registry(|r| {
+        r.armed.clear();
+        r.hits.clear();
+    });
+}
+
+/// Number of times a failpoint has fired since the last [`clear_all`]
+pub fn hits(point: Failpoint) -> u64 {
+    with_registry(|r| r.hits.get(&point).copied().unwrap_or(0))
+}
+
+/// Arm failpoints from a spec string
+///
+/// The spec is parsed completely before anything is armed, so an invalid
+/// entry leaves the current configuration untouched.
+///
+/// # Errors
+/// Returns `InvalidFailpoint` for unknown failpoints, actions or counts.
+pub fn configure(spec: &str) -> Result<()> {
+    let entries = parse_spec(spec)?;
+    for (point, action, times) in entries {
+        match action {
+            None => clear(point),
+            Some(action) => match times {
+                Some(times) => set_times(point, action, times),
+                None => set(point, action),
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Arm failpoints from the `ETMEM_FAILPOINTS` environment variable
+///
+/// Returns `Ok(false)` if the variable is not set.
+///
+/// # Errors
+/// Returns `InvalidFailpoint` if the variable holds an invalid spec.
+pub fn configure_from_env() -> Result<bool> {
+    match std::env::var(FAILPOINTS_ENV) {
+        Ok(spec) => {
+            configure(&spec)?;
+            log::warn!("Failpoints armed from {FAILPOINTS_ENV}: {spec}");
+            Ok(true)
+        }
+        Err(_) => Ok(false),
+    }
+}
+
+/// Parsed spec entry; a `None` action disarms the failpoint
+type SpecEntry = (Failpoint, Option<FailAction>, Option<u32>);
+
+fn parse_spec(spec: &str) -> Result<Vec<SpecEntry>> {
+    spec.split([',', ';'])
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (point, action) = entry.split_once('=').ok_or_else(|| {
+                EtmemError::InvalidFailpoint(format!("expected <point>=<action>, got {entry}"))
+            })?;
+            let (action, times) = match action.split_once('*') {
+                Some((action, times)) => {
+                    let times = times.parse().map_err(|_| {
+                        EtmemError::InvalidFailpoint(format!("invalid count {times}"))
+                    })?;
+                    (action, Some(times))
+                }
+                None => (action, None),
+            };
+            let action = match action.trim() {
+                "off" => None,
+                action => Some(action.parse()?),
+            };
+            Ok((point.trim().parse()?, action, times))
+        })
+        .collect()
+}
+
+/// Take the action of `point` if it is armed, consuming one firing
+pub(crate) fn fire(point: Failpoint) -> Option<FailAction> {
+    if !ARMED.load(Ordering::Acquire) {
+        return None;
+    }
+    with_registry(|r| {
+        let entry = r.armed.get_mut(&point)?;
+        let action = entry.action;
+        if let Some(remaining) = entry.remaining.as_mut() {
+            *remaining -= 1;
+            if *remaining == 0 {
+                r.armed.remove(&point);
+            }
+        }
+        *r.hits.entry(point).or_default() += 1;
+        log::debug!("Failpoint {point} fired: {action:?}");
+        Some(action)
+    })
+}
+
+/// Injected OS error of `point`, if it fires with an errno
+pub(crate) fn os_error(point: Failpoint) -> Option<std::io::Error> {
+    match fire(point)? {
+        FailAction::Errno(errno) => Some(std::io::Error::from_raw_os_error(errno)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::sys::ProcfsHandle;
+    use std::io::{Read, Seek};
+    use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+    /// Failpoints are global; tests touching them must not interleave
+    static SERIAL: Mutex<()> = Mutex::new(());
+
+    pub(crate) fn serial() -> std::sync::MutexGuard<'static, ()> {
+        let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+        clear_all();
+        guard
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let entries =
+            parse_spec("scan_read=eintr*3, swap_write=short:64;ioctl=enotty,scan_status=off")
+                .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                (
+                    Failpoint::ScanRead,
+                    Some(FailAction::Errno(libc::EINTR)),
+                    Some(3)
+                ),
+                (Failpoint::SwapWrite, Some(FailAction::ShortWrite(64)), None),
+                (
+                    Failpoint::Ioctl,
+                    Some(FailAction::Errno(libc::ENOTTY)),
+                    None
+                ),
+                (Failpoint::ScanStatus, None, None),
+            ]
+        );
+        assert_eq!(
+            "errno:5".parse::<FailAction>().unwrap(),
+            FailAction::Errno(5)
+        );
+        assert!(parse_spec("scan_write=eintr").is_err());
+        assert!(parse_spec("scan_read=boom").is_err());
+        assert!(parse_spec("scan_read=eintr*x").is_err());
+        assert!(parse_spec("scan_read").is_err());
+        assert!(parse_spec("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_fire_times() {
+        let _serial = serial();
+        set_times(Failpoint::Ioctl, FailAction::Errno(libc::ENOTTY), 2);
+        assert!(fire(Failpoint::ScanRead).is_none());
+        assert_eq!(
+            fire(Failpoint::Ioctl),
+            Some(FailAction::Errno(libc::ENOTTY))
+        );
+        assert!(os_error(Failpoint::Ioctl).is_some());
+        assert!(fire(Failpoint::Ioctl).is_none());
+        assert_eq!(hits(Failpoint::Ioctl), 2);
+
+        set(
+            Failpoint::ScanStatus,
+            FailAction::Status(BufferStatus::BufFull),
+        );
+        for _ in 0..5 {
+            assert!(fire(Failpoint::ScanStatus).is_some());
+        }
+        clear(Failpoint::ScanStatus);
+        assert!(fire(Failpoint::ScanStatus).is_none());
+        clear_all();
+    }
+
+    #[test]
+    fn test_configure_invalid_keeps_state() {
+        let _serial = serial();
+        configure("ioctl=eio").unwrap();
+        assert!(configure("scan_read=eintr,nope=eio").is_err());
+        assert!(fire(Failpoint::ScanRead).is_none());
+        assert_eq!(fire(Failpoint::Ioctl), Some(FailAction::Errno(libc::EIO)));
+        clear_all();
+    }
+
+    #[test]
+    fn test_injected_read_and_short_write() {
+        let _serial = serial();
+        let mut file = tempfile::tempfile().unwrap();
+        let handle = unsafe { ProcfsHandle::from_raw_fd(file.try_clone().unwrap().into_raw_fd()) };
+
+        set_times(Failpoint::ScanRead, FailAction::Errno(libc::EINTR), 1);
+        let mut buf = [0u8; 8];
+        let err = unsafe { handle.read_at(&mut buf, 0) }.unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::EINTR));
+        assert_eq!(unsafe { handle.read_at(&mut buf, 0) }.unwrap(), 0);
+
+        set_times(Failpoint::SwapWrite, FailAction::ShortWrite(4), 1);
+        assert_eq!(unsafe { handle.write(b"1000\n2000\n") }.unwrap(), 4);
+        assert_eq!(unsafe { handle.write(b"\n") }.unwrap(), 1);
+
+        let mut written = String::new();
+        file.rewind().unwrap();
+        file.read_to_string(&mut written).unwrap();
+        assert_eq!(written, "1000\n");
+        clear_all();
+    }
+}
@@ -0,0 +1,462 @@
+//! Checkpoint/restore (CRIU) interop
+//!
+//! CRIU stops a process tree before dumping it: it seizes every thread with
+//! ptrace, which leaves them in tracing stop, and with `--freeze-cgroup` it
+//! freezes their cgroup first. Reclaiming a process in that state makes the
+//! dump read the reclaimed pages back from swap, costing more than the
+//! reclaim saved, and races with the dumper's own walk of the page tables.
+//! [`FreezeDetector`] tells reclaim to wait until the process runs again.
+//!
+//! The other way round, a pre-dump copies every resident page, cold or not.
+//! [`IdleMap`] publishes the cold ranges found by our scans, and
+//! [`CriuHook`] lets a CRIU action script (`--action-script`) find the map
+//! of the process being dumped, for instance to leave cold ranges out of an
+//! iterative pre-dump.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::freeze::{CriuAction, CriuHook, FreezeDetector, IDLE_MAP_DIR};
+//! use etmem_rs::{SwapConfig, SwapSession};
+//!
+//! let pid = 1234;
+//! let state = FreezeDetector::new().state(pid).expect("Failed to read state");
+//! if state.is_frozen() {
+//!     println!("pid {} is {}, deferring reclaim", pid, state);
+//! }
+//!
+//! // Flushes fail with `ReclaimRefused` while the process is frozen
+//! let swap = SwapSession::new(pid, SwapConfig::default())
+//!     .expect("Failed to open session")
+//!     .with_freeze_detector(FreezeDetector::new());
+//!
+//! // In an action script
+//! if let Some(hook) = CriuHook::from_env()
+//!     && hook.action == CriuAction::PreDump
+//!     && let Some(map) = hook.idle_map(IDLE_MAP_DIR).expect("Failed to load map")
+//! {
+//!     println!("{} cold bytes need not be pre-dumped", map.idle_bytes());
+//! }
+//! ```
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EtmemError, Result};
+use crate::types::{AddressRange, IdlePageInfo, RangeSet};
+
+/// Default directory idle maps are published in
+pub const IDLE_MAP_DIR: &str = "/run/etmem";
+
+/// Whether a process can currently run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FreezeState {
+    /// Runnable or sleeping
+    Running,
+    /// Stopped by a signal (`T`)
+    Stopped,
+    /// Stopped under a tracer (`t`), as when seized by CRIU
+    Traced,
+    /// Frozen or freezing by the cgroup freezer
+    CgroupFrozen,
+}
+
+impl FreezeState {
+    /// Check if reclaim should be deferred
+    pub fn is_frozen(&self) -> bool {
+        !matches!(self, Self::Running)
+    }
+
+    /// Get a short description
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Running => "running",
+            Self::Stopped => "stopped",
+            Self::Traced => "stopped by a tracer",
+            Self::CgroupFrozen => "frozen by its cgroup",
+        }
+    }
+}
+
+impl fmt::Display for FreezeState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Extract the state letter from the contents of `/proc/<pid>/status`
+pub fn parse_process_state(status: &str) -> Option<char> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("State:"))
+        .and_then(|state| state.trim_start().chars().next())
+}
+
+/// Detects processes stopped or frozen for a checkpoint
+#[derive(Debug, Clone)]
+pub struct FreezeDetector {
+    /// Mount point of procfs
+    proc_root: PathBuf,
+    /// Mount point of the cgroup hierarchies
+    cgroup_root: PathBuf,
+}
+
+impl FreezeDetector {
+    /// Create a detector reading `/proc` and `/sys/fs/cgroup`
+    pub fn new() -> Self {
+        Self::with_roots("/proc", crate::psi::CGROUP2_ROOT)
+    }
+
+    /// Create a detector reading other procfs and cgroup mount points
+    pub fn with_roots<P: AsRef<Path>, Q: AsRef<Path>>(proc_root: P, cgroup_root: Q) -> Self {
+        Self {
+            proc_root: proc_root.as_ref().to_path_buf(),
+            cgroup_root: cgroup_root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Get the freeze state of a process
+    ///
+    /// The cgroup freezer is checked first: a frozen task still shows as
+    /// sleeping in its status. Both the unified (`cgroup.events`,
+    /// `cgroup.freeze`) and the v1 (`freezer.state`) freezer are
+    /// understood.
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process has exited.
+    pub fn state(&self, pid: u32) -> Result<FreezeState> {
+        let proc_dir = self.proc_root.join(pid.to_string());
+        let read = |name: &str| {
+            let path = proc_dir.join(name);
+            std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => EtmemError::ProcessNotFound,
+                _ => EtmemError::ProcfsError(format!("{}: {}", path.display(), e)),
+            })
+        };
+
+        if self.cgroup_frozen(&read("cgroup")?) {
+            return Ok(FreezeState::CgroupFrozen);
+        }
+        Ok(match parse_process_state(&read("status")?) {
+            Some('T') => FreezeState::Stopped,
+            Some('t') => FreezeState::Traced,
+            _ => FreezeState::Running,
+        })
+    }
+
+    /// Check the freezer of the cgroups listed in `/proc/<pid>/cgroup`
+    ///
+    /// Missing freezer files (no freezer, or the root cgroup) count as not
+    /// frozen.
+    fn cgroup_frozen(&self, cgroups: &str) -> bool {
+        let read = |path: PathBuf| std::fs::read_to_string(path).unwrap_or_default();
+        cgroups.lines().any(|line| {
+            let mut fields = line.splitn(3, ':');
+            let (Some(_), Some(controllers), Some(path)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return false;
+            };
+            let path = path.trim().trim_start_matches('/');
+            if controllers.is_empty() {
+                let dir = self.cgroup_root.join(path);
+                read(dir.join("cgroup.events"))
+                    .lines()
+                    .any(|line| line.trim() == "frozen 1")
+                    || read(dir.join("cgroup.freeze")).trim() == "1"
+            } else if controllers.split(',').any(|c| c == "freezer") {
+                let state = read(
+                    self.cgroup_root
+                        .join("freezer")
+                        .join(path)
+                        .join("freezer.state"),
+                );
+                matches!(state.trim(), "FROZEN" | "FREEZING")
+            } else {
+                false
+            }
+        })
+    }
+}
+
+impl Default for FreezeDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cold ranges of a process, published for checkpoint tooling
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleMap {
+    /// Process the ranges belong to
+    pub pid: u32,
+    /// When the map was built (seconds since the Unix epoch)
+    pub generated_at: u64,
+    /// Sorted, disjoint idle ranges
+    pub ranges: Vec<AddressRange>,
+}
+
+impl IdleMap {
+    /// Build a map from idle ranges, which may overlap
+    pub fn new<I: IntoIterator<Item = AddressRange>>(pid: u32, ranges: I) -> Self {
+        let generated_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        Self {
+            pid,
+            generated_at,
+            ranges: RangeSet::from_ranges(ranges).into_vec(),
+        }
+    }
+
+    /// Build a map from the idle entries of a scan
+    pub fn from_pages(pid: u32, pages: &[IdlePageInfo]) -> Self {
+        Self::new(
+            pid,
+            pages
+                .iter()
+                .filter(|page| page.is_idle())
+                .map(|page| AddressRange::new(page.address, page.end_address())),
+        )
+    }
+
+    /// Total bytes of the idle ranges
+    pub fn idle_bytes(&self) -> u64 {
+        self.ranges.iter().map(AddressRange::size).sum()
+    }
+
+    /// Check if an address lies in an idle range
+    pub fn contains(&self, addr: u64) -> bool {
+        let idx = self.ranges.partition_point(|r| r.end <= addr);
+        self.ranges.get(idx).is_some_and(|r| r.contains(addr))
+    }
+
+    /// Path of the map of `pid` in `dir`
+    pub fn path_in<P: AsRef<Path>>(dir: P, pid: u32) -> PathBuf {
+        dir.as_ref().join(format!("idle-{}.json", pid))
+    }
+
+    /// Write the map to `dir`, replacing the previous map of the process
+    ///
+    /// The map is written to a temporary file and renamed, so a hook never
+    /// reads a partial map. Returns the path written.
+    ///
+    /// # Errors
+    /// Returns an I/O error if the directory cannot be created or written.
+    pub fn publish<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        let path = Self::path_in(dir, self.pid);
+        let tmp = path.with_extension("json.tmp");
+        let json = serde_json::to_vec(self).map_err(|e| EtmemError::IoError(e.to_string()))?;
+        std::fs::write(&tmp, json)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(path)
+    }
+
+    /// Load the map of `pid` from `dir`, if one was published
+    ///
+    /// # Errors
+    /// Returns an I/O error if the map exists but cannot be read or parsed.
+    pub fn load<P: AsRef<Path>>(dir: P, pid: u32) -> Result<Option<Self>> {
+        let path = Self::path_in(dir, pid);
+        let content = match std::fs::read(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|e| EtmemError::IoError(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Stage of a CRIU run, from `CRTOOLS_SCRIPT_ACTION`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CriuAction {
+    /// Before a (pre-)dump starts
+    PreDump,
+    /// After a dump finished
+    PostDump,
+    /// Before a restore starts
+    PreRestore,
+    /// After a restore finished
+    PostRestore,
+    /// Any other action
+    Other(String),
+}
+
+impl CriuAction {
+    /// Parse an action name
+    pub fn parse(action: &str) -> Self {
+        match action {
+            "pre-dump" => Self::PreDump,
+            "post-dump" => Self::PostDump,
+            "pre-restore" => Self::PreRestore,
+            "post-restore" => Self::PostRestore,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// Context of a CRIU action script invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CriuHook {
+    /// Stage CRIU is at
+    pub action: CriuAction,
+    /// Root of the process tree being dumped or restored, when known
+    pub init_pid: Option<u32>,
+}
+
+impl CriuHook {
+    /// Read the hook context from the environment of an action script
+    ///
+    /// Returns `None` when not run by CRIU.
+    pub fn from_env() -> Option<Self> {
+        let action = std::env::var("CRTOOLS_SCRIPT_ACTION").ok()?;
+        let init_pid = std::env::var("CRTOOLS_INIT_PID").ok();
+        Some(Self::from_vars(&action, init_pid.as_deref()))
+    }
+
+    /// Build the hook context from the values of the CRIU variables
+    pub fn from_vars(action: &str, init_pid: Option<&str>) -> Self {
+        Self {
+            action: CriuAction::parse(action),
+            init_pid: init_pid.and_then(|pid| pid.trim().parse().ok()),
+        }
+    }
+
+    /// Load the idle map of the process being dumped
+    ///
+    /// Returns `None` without an init pid or a published map.
+    ///
+    /// # Errors
+    /// Returns an I/O error if the map cannot be read or parsed.
+    pub fn idle_map<P: AsRef<Path>>(&self, dir: P) -> Result<Option<IdleMap>> {
+        match self.init_pid {
+            Some(pid) => IdleMap::load(dir, pid),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProcIdlePageType;
+
+    /// Lay out a fake procfs entry and cgroup tree
+    fn fake_roots(pid: u32, state: &str, cgroup: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let proc_dir = dir.path().join("proc").join(pid.to_string());
+        std::fs::create_dir_all(&proc_dir).unwrap();
+        std::fs::write(
+            proc_dir.join("status"),
+            format!("Name:\tapp\nState:\t{}\nTgid:\t{}\n", state, pid),
+        )
+        .unwrap();
+        std::fs::write(proc_dir.join("cgroup"), cgroup).unwrap();
+        std::fs::create_dir_all(dir.path().join("cgroup")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_process_state() {
+        assert_eq!(parse_process_state("State:\tS (sleeping)\n"), Some('S'));
+        assert_eq!(parse_process_state("Name:\tx\n"), None);
+
+        for (state, expected) in [
+            ("S (sleeping)", FreezeState::Running),
+            ("T (stopped)", FreezeState::Stopped),
+            ("t (tracing stop)", FreezeState::Traced),
+        ] {
+            let dir = fake_roots(42, state, "0::/app.slice\n");
+            let detector =
+                FreezeDetector::with_roots(dir.path().join("proc"), dir.path().join("cgroup"));
+            assert_eq!(detector.state(42).unwrap(), expected);
+        }
+
+        let dir = fake_roots(42, "R (running)", "");
+        let detector =
+            FreezeDetector::with_roots(dir.path().join("proc"), dir.path().join("cgroup"));
+        assert_eq!(detector.state(43), Err(EtmemError::ProcessNotFound));
+    }
+
+    #[test]
+    fn test_cgroup_freezer() {
+        // Unified hierarchy: frozen, then freezing
+        let dir = fake_roots(42, "S (sleeping)", "0::/app.slice/app.scope\n");
+        let cgroup = dir.path().join("cgroup/app.slice/app.scope");
+        std::fs::create_dir_all(&cgroup).unwrap();
+        let detector =
+            FreezeDetector::with_roots(dir.path().join("proc"), dir.path().join("cgroup"));
+        std::fs::write(cgroup.join("cgroup.events"), "populated 1\nfrozen 0\n").unwrap();
+        assert_eq!(detector.state(42).unwrap(), FreezeState::Running);
+        std::fs::write(cgroup.join("cgroup.freeze"), "1\n").unwrap();
+        assert_eq!(detector.state(42).unwrap(), FreezeState::CgroupFrozen);
+        std::fs::write(cgroup.join("cgroup.freeze"), "0\n").unwrap();
+        std::fs::write(cgroup.join("cgroup.events"), "populated 1\nfrozen 1\n").unwrap();
+        assert!(detector.state(42).unwrap().is_frozen());
+
+        // v1 freezer controller
+        let dir = fake_roots(42, "S (sleeping)", "7:freezer:/criu\n0::/\n");
+        let freezer = dir.path().join("cgroup/freezer/criu");
+        std::fs::create_dir_all(&freezer).unwrap();
+        std::fs::write(freezer.join("freezer.state"), "FREEZING\n").unwrap();
+        let detector =
+            FreezeDetector::with_roots(dir.path().join("proc"), dir.path().join("cgroup"));
+        assert_eq!(detector.state(42).unwrap(), FreezeState::CgroupFrozen);
+        std::fs::write(freezer.join("freezer.state"), "THAWED\n").unwrap();
+        assert_eq!(detector.state(42).unwrap(), FreezeState::Running);
+    }
+
+    #[test]
+    fn test_idle_map_publish() {
+        let pages = [
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x3000, ProcIdlePageType::PteIdle, 1),
+            IdlePageInfo::new(0x4000, ProcIdlePageType::PteAccessed, 1),
+            IdlePageInfo::new(0x8000, ProcIdlePageType::PteIdle, 1),
+        ];
+        let map = IdleMap::from_pages(42, &pages);
+        assert_eq!(
+            map.ranges,
+            vec![
+                AddressRange::new(0x1000, 0x4000),
+                AddressRange::new(0x8000, 0x9000)
+            ]
+        );
+        assert_eq!(map.idle_bytes(), 0x4000);
+        assert!(map.contains(0x3fff));
+        assert!(!map.contains(0x4000));
+
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(IdleMap::load(dir.path(), 42).unwrap(), None);
+        let path = map.publish(dir.path().join("etmem")).unwrap();
+        assert_eq!(path, IdleMap::path_in(dir.path().join("etmem"), 42));
+        assert_eq!(
+            IdleMap::load(dir.path().join("etmem"), 42).unwrap(),
+            Some(map)
+        );
+    }
+
+    #[test]
+    fn test_criu_hook() {
+        let hook = CriuHook::from_vars("pre-dump", Some("42"));
+        assert_eq!(hook.action, CriuAction::PreDump);
+        assert_eq!(hook.init_pid, Some(42));
+
+        let hook = CriuHook::from_vars("network-lock", None);
+        assert_eq!(hook.action, CriuAction::Other("network-lock".to_string()));
+        assert_eq!(hook.idle_map("/nonexistent").unwrap(), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let map = IdleMap::new(42, [AddressRange::new(0x1000, 0x2000)]);
+        map.publish(dir.path()).unwrap();
+        let hook = CriuHook::from_vars("pre-dump", Some("42\n"));
+        assert_eq!(hook.idle_map(dir.path()).unwrap(), Some(map));
+    }
+}
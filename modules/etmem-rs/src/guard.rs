@@ -0,0 +1,478 @@
+//! System-wide swap pressure guardrails
+//!
+//! This module inspects system memory state before reclaim is performed
+//! and decides whether swapping should proceed, be throttled, or be
+//! refused. Swapping out pages while the system is already short on swap
+//! or thrashing only makes things worse.
+//!
+//! The following sources are consulted:
+//! - `/proc/meminfo`: free swap space
+//! - `/proc/swaps`: active swap devices
+//! - `/proc/vmstat`: swap-in rate (`pswpin`)
+//! - `/proc/pressure/memory`: PSI memory pressure (if available)
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::guard::{GuardConfig, GuardDecision, SwapGuard};
+//!
+//! let mut guard = SwapGuard::new(GuardConfig::default());
+//! match guard.check().expect("Failed to read system state") {
+//!     GuardDecision::Allow => println!("Safe to reclaim"),
+//!     GuardDecision::Throttle(delay) => println!("Back off for {:?}", delay),
+//!     GuardDecision::Refuse(reason) => println!("Refusing: {}", reason),
+//! }
+//! ```
+
+use std::time::{Duration, Instant};
+
+use crate::error::{EtmemError, Result};
+use crate::psi::PsiReading;
+
+/// Procfs path for memory information
+pub const PROC_MEMINFO: &str = "/proc/meminfo";
+
+/// Procfs path for active swap devices
+pub const PROC_SWAPS: &str = "/proc/swaps";
+
+/// Procfs path for virtual memory statistics
+pub const PROC_VMSTAT: &str = "/proc/vmstat";
+
+/// Subset of `/proc/meminfo` relevant to swapping (values in KB)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemInfo {
+    /// Total usable RAM
+    pub mem_total_kb: u64,
+    /// Memory available for new allocations without swapping
+    pub mem_available_kb: u64,
+    /// Total swap space
+    pub swap_total_kb: u64,
+    /// Unused swap space
+    pub swap_free_kb: u64,
+    /// Memory that was swapped out and is also still in RAM
+    pub swap_cached_kb: u64,
+}
+
+impl MemInfo {
+    /// Read `/proc/meminfo`
+    pub fn read() -> Result<Self> {
+        let content = std::fs::read_to_string(PROC_MEMINFO)
+            .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", PROC_MEMINFO, e)))?;
+        Self::parse(&content)
+    }
+
+    /// Parse the contents of `/proc/meminfo`
+    ///
+    /// # Errors
+    /// Returns error if `MemTotal` is missing or a value is malformed.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut info = Self::default();
+        let mut has_total = false;
+
+        for line in content.lines() {
+            let Some((key, rest)) = line.split_once(':') else {
+                continue;
+            };
+            let field = match key {
+                "MemTotal" => &mut info.mem_total_kb,
+                "MemAvailable" => &mut info.mem_available_kb,
+                "SwapTotal" => &mut info.swap_total_kb,
+                "SwapFree" => &mut info.swap_free_kb,
+                "SwapCached" => &mut info.swap_cached_kb,
+                _ => continue,
+            };
+            let value = rest.trim().trim_end_matches("kB").trim();
+            *field = value.parse().map_err(|_| {
+                EtmemError::ProcfsError(format!("Invalid meminfo value for {}: {}", key, value))
+            })?;
+            has_total |= key == "MemTotal";
+        }
+
+        if !has_total {
+            return Err(EtmemError::ProcfsError(
+                "MemTotal missing from meminfo".to_string(),
+            ));
+        }
+        Ok(info)
+    }
+
+    /// Calculate free swap ratio (0.0 - 1.0), 0.0 if there is no swap
+    pub fn swap_free_ratio(&self) -> f64 {
+        if self.swap_total_kb == 0 {
+            0.0
+        } else {
+            self.swap_free_kb as f64 / self.swap_total_kb as f64
+        }
+    }
+}
+
+/// An active swap device from `/proc/swaps` (sizes in KB)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapDevice {
+    /// Device or file path
+    pub filename: String,
+    /// Swap type (`partition` or `file`)
+    pub kind: String,
+    /// Total size
+    pub size_kb: u64,
+    /// Used size
+    pub used_kb: u64,
+    /// Swap priority
+    pub priority: i32,
+}
+
+impl SwapDevice {
+    /// Read all active swap devices from `/proc/swaps`
+    pub fn read_all() -> Result<Vec<Self>> {
+        let content = std::fs::read_to_string(PROC_SWAPS)
+            .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", PROC_SWAPS, e)))?;
+        Self::parse_all(&content)
+    }
+
+    /// Parse the contents of `/proc/swaps`
+    ///
+    /// The header line is skipped.
+    pub fn parse_all(content: &str) -> Result<Vec<Self>> {
+        content
+            .lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(Self::parse_line)
+            .collect()
+    }
+
+    /// Parse a single line from `/proc/swaps`
+    ///
+    /// Format: filename type size used priority
+    fn parse_line(line: &str) -> Result<Self> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 5 {
+            return Err(EtmemError::ProcfsError(format!(
+                "Invalid swaps line: {}",
+                line
+            )));
+        }
+
+        let invalid = || EtmemError::ProcfsError(format!("Invalid swaps line: {}", line));
+        Ok(Self {
+            filename: parts[0].to_string(),
+            kind: parts[1].to_string(),
+            size_kb: parts[2].parse().map_err(|_| invalid())?,
+            used_kb: parts[3].parse().map_err(|_| invalid())?,
+            priority: parts[4].parse().map_err(|_| invalid())?,
+        })
+    }
+
+    /// Get the free space on this device in KB
+    pub fn free_kb(&self) -> u64 {
+        self.size_kb.saturating_sub(self.used_kb)
+    }
+}
+
+/// Read the cumulative swap-in page count (`pswpin`) from `/proc/vmstat`
+pub fn read_pswpin() -> Result<u64> {
+    let content = std::fs::read_to_string(PROC_VMSTAT)
+        .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", PROC_VMSTAT, e)))?;
+    parse_vmstat_field(&content, "pswpin")
+}
+
+/// Extract a single counter from the contents of `/proc/vmstat`
+pub fn parse_vmstat_field(content: &str, field: &str) -> Result<u64> {
+    content
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .find(|(key, _)| *key == field)
+        .and_then(|(_, value)| value.trim().parse().ok())
+        .ok_or_else(|| EtmemError::ProcfsError(format!("{} missing from vmstat", field)))
+}
+
+/// Point-in-time view of system swap state
+#[derive(Debug, Clone, PartialEq)]
+pub struct PressureSnapshot {
+    /// Memory information
+    pub meminfo: MemInfo,
+    /// Active swap devices
+    pub swaps: Vec<SwapDevice>,
+    /// Cumulative swap-in page count
+    pub pswpin: u64,
+    /// PSI `some avg10` memory pressure (percent), if available
+    pub psi_some_avg10: Option<f64>,
+}
+
+impl PressureSnapshot {
+    /// Capture the current system state
+    pub fn capture() -> Result<Self> {
+        Ok(Self {
+            meminfo: MemInfo::read()?,
+            swaps: SwapDevice::read_all()?,
+            pswpin: read_pswpin()?,
+            // PSI is optional (CONFIG_PSI may be disabled)
+            psi_some_avg10: PsiReading::read_system().ok().map(|r| r.some.avg10),
+        })
+    }
+}
+
+/// Outcome of a guard check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardDecision {
+    /// Reclaim may proceed
+    Allow,
+    /// Reclaim may proceed after backing off for the given duration
+    Throttle(Duration),
+    /// Reclaim must not proceed
+    Refuse(String),
+}
+
+impl GuardDecision {
+    /// Check if reclaim may proceed (possibly after throttling)
+    pub fn is_allowed(&self) -> bool {
+        !matches!(self, Self::Refuse(_))
+    }
+}
+
+/// Thresholds for the swap guard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GuardConfig {
+    /// Refuse reclaim when free swap drops below this ratio (0.0 - 1.0)
+    pub min_free_swap_ratio: f64,
+    /// Throttle reclaim when swap-in exceeds this many pages per second
+    pub max_swapin_rate: Option<f64>,
+    /// Refuse reclaim when PSI `some avg10` exceeds this percentage
+    pub max_psi_some_avg10: Option<f64>,
+    /// Back-off applied when throttling
+    pub throttle_delay: Duration,
+}
+
+impl GuardConfig {
+    /// Create a guard configuration with default thresholds
+    ///
+    /// Defaults: 10% free swap, 1000 pages/s swap-in, 20% PSI, 100ms back-off.
+    pub const fn new() -> Self {
+        Self {
+            min_free_swap_ratio: 0.1,
+            max_swapin_rate: Some(1000.0),
+            max_psi_some_avg10: Some(20.0),
+            throttle_delay: Duration::from_millis(100),
+        }
+    }
+
+    /// Set minimum free swap ratio
+    pub const fn with_min_free_swap_ratio(mut self, ratio: f64) -> Self {
+        self.min_free_swap_ratio = ratio;
+        self
+    }
+
+    /// Set maximum swap-in rate (pages per second), `None` to disable
+    pub const fn with_max_swapin_rate(mut self, rate: Option<f64>) -> Self {
+        self.max_swapin_rate = rate;
+        self
+    }
+
+    /// Set maximum PSI `some avg10` percentage, `None` to disable
+    pub const fn with_max_psi_some_avg10(mut self, limit: Option<f64>) -> Self {
+        self.max_psi_some_avg10 = limit;
+        self
+    }
+
+    /// Set throttle back-off duration
+    pub const fn with_throttle_delay(mut self, delay: Duration) -> Self {
+        self.throttle_delay = delay;
+        self
+    }
+}
+
+impl Default for GuardConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Swap pressure guard
+///
+/// Tracks the swap-in counter between checks to derive a rate, so a
+/// single guard should be reused across reclaim rounds.
+#[derive(Debug, Clone)]
+pub struct SwapGuard {
+    /// Guard thresholds
+    config: GuardConfig,
+    /// Previous `pswpin` sample and when it was taken
+    last_pswpin: Option<(u64, Instant)>,
+}
+
+impl SwapGuard {
+    /// Create a new guard
+    pub const fn new(config: GuardConfig) -> Self {
+        Self {
+            config,
+            last_pswpin: None,
+        }
+    }
+
+    /// Get the guard configuration
+    pub fn config(&self) -> &GuardConfig {
+        &self.config
+    }
+
+    /// Capture the current system state and evaluate it
+    ///
+    /// # Errors
+    /// Returns error if `/proc/meminfo`, `/proc/swaps` or `/proc/vmstat`
+    /// cannot be read.
+    pub fn check(&mut self) -> Result<GuardDecision> {
+        let snapshot = PressureSnapshot::capture()?;
+        Ok(self.evaluate(&snapshot, Instant::now()))
+    }
+
+    /// Evaluate a snapshot taken at `now`
+    ///
+    /// The first evaluation has no swap-in baseline and never throttles.
+    pub fn evaluate(&mut self, snapshot: &PressureSnapshot, now: Instant) -> GuardDecision {
+        let swapin_rate = self.last_pswpin.and_then(|(prev, at)| {
+            let elapsed = now.saturating_duration_since(at).as_secs_f64();
+            (elapsed > 0.0).then(|| snapshot.pswpin.saturating_sub(prev) as f64 / elapsed)
+        });
+        self.last_pswpin = Some((snapshot.pswpin, now));
+
+        if snapshot.swaps.is_empty() {
+            return GuardDecision::Refuse("no active swap devices".to_string());
+        }
+
+        let free_ratio = snapshot.meminfo.swap_free_ratio();
+        if free_ratio < self.config.min_free_swap_ratio {
+            return GuardDecision::Refuse(format!(
+                "free swap {:.1}% below {:.1}%",
+                free_ratio * 100.0,
+                self.config.min_free_swap_ratio * 100.0
+            ));
+        }
+
+        if let (Some(limit), Some(psi)) = (self.config.max_psi_some_avg10, snapshot.psi_some_avg10)
+            && psi > limit
+        {
+            return GuardDecision::Refuse(format!(
+                "memory pressure {:.2}% above {:.2}%",
+                psi, limit
+            ));
+        }
+
+        if let (Some(limit), Some(rate)) = (self.config.max_swapin_rate, swapin_rate)
+            && rate > limit
+        {
+            return GuardDecision::Throttle(self.config.throttle_delay);
+        }
+
+        GuardDecision::Allow
+    }
+}
+
+impl Default for SwapGuard {
+    fn default() -> Self {
+        Self::new(GuardConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMINFO: &str = "MemTotal:        6158152 kB\n\
+                           MemFree:         3989736 kB\n\
+                           MemAvailable:    5682304 kB\n\
+                           SwapCached:          128 kB\n\
+                           SwapTotal:       1048576 kB\n\
+                           SwapFree:         524288 kB\n";
+
+    const SWAPS: &str = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                         /dev/zram0                              partition\t1048576\t\t524288\t\t100\n";
+
+    fn snapshot(pswpin: u64, psi: Option<f64>) -> PressureSnapshot {
+        PressureSnapshot {
+            meminfo: MemInfo::parse(MEMINFO).unwrap(),
+            swaps: SwapDevice::parse_all(SWAPS).unwrap(),
+            pswpin,
+            psi_some_avg10: psi,
+        }
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let info = MemInfo::parse(MEMINFO).unwrap();
+        assert_eq!(info.mem_total_kb, 6158152);
+        assert_eq!(info.mem_available_kb, 5682304);
+        assert_eq!(info.swap_total_kb, 1048576);
+        assert_eq!(info.swap_free_kb, 524288);
+        assert_eq!(info.swap_cached_kb, 128);
+        assert!((info.swap_free_ratio() - 0.5).abs() < 0.001);
+
+        assert!(MemInfo::parse("SwapTotal: 0 kB\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_swaps() {
+        let swaps = SwapDevice::parse_all(SWAPS).unwrap();
+        assert_eq!(swaps.len(), 1);
+        assert_eq!(swaps[0].filename, "/dev/zram0");
+        assert_eq!(swaps[0].kind, "partition");
+        assert_eq!(swaps[0].priority, 100);
+        assert_eq!(swaps[0].free_kb(), 524288);
+
+        let header_only = "Filename\tType\tSize\tUsed\tPriority\n";
+        assert!(SwapDevice::parse_all(header_only).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_vmstat_field() {
+        let vmstat = "pgpgout 100\npswpin 42\npswpout 7\n";
+        assert_eq!(parse_vmstat_field(vmstat, "pswpin").unwrap(), 42);
+        assert_eq!(parse_vmstat_field(vmstat, "pswpout").unwrap(), 7);
+        assert!(parse_vmstat_field(vmstat, "missing").is_err());
+    }
+
+    #[test]
+    fn test_guard_allows() {
+        let mut guard = SwapGuard::default();
+        let decision = guard.evaluate(&snapshot(0, Some(1.0)), Instant::now());
+        assert_eq!(decision, GuardDecision::Allow);
+    }
+
+    #[test]
+    fn test_guard_refuses_low_swap() {
+        let mut guard = SwapGuard::new(GuardConfig::new().with_min_free_swap_ratio(0.6));
+        let decision = guard.evaluate(&snapshot(0, None), Instant::now());
+        assert!(!decision.is_allowed());
+
+        let mut no_swap = snapshot(0, None);
+        no_swap.swaps.clear();
+        assert!(
+            !SwapGuard::default()
+                .evaluate(&no_swap, Instant::now())
+                .is_allowed()
+        );
+    }
+
+    #[test]
+    fn test_guard_refuses_psi() {
+        let mut guard = SwapGuard::default();
+        let decision = guard.evaluate(&snapshot(0, Some(50.0)), Instant::now());
+        assert!(matches!(decision, GuardDecision::Refuse(_)));
+    }
+
+    #[test]
+    fn test_guard_throttles_swapin_spike() {
+        let mut guard = SwapGuard::default();
+        let start = Instant::now();
+        assert_eq!(
+            guard.evaluate(&snapshot(0, None), start),
+            GuardDecision::Allow
+        );
+
+        // 5000 pages in one second exceeds the default 1000 pages/s
+        let later = start + Duration::from_secs(1);
+        let decision = guard.evaluate(&snapshot(5000, None), later);
+        assert_eq!(
+            decision,
+            GuardDecision::Throttle(GuardConfig::default().throttle_delay)
+        );
+        assert!(decision.is_allowed());
+    }
+}
@@ -0,0 +1,452 @@
+//! Kubernetes pod and container targeting
+//!
+//! The kubelet places every pod in its own cgroup under `kubepods`, named
+//! after the pod UID, with one child cgroup per container named after the
+//! container ID. This module finds those cgroups in the unified hierarchy
+//! and lists their member processes, so scan and swap operations can
+//! target a pod rather than individual PIDs.
+//!
+//! Both kubelet cgroup drivers are understood:
+//!
+//! - systemd: `kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod<uid>.slice/cri-containerd-<id>.scope`
+//!   (dashes in the UID are replaced by underscores)
+//! - cgroupfs: `kubepods/burstable/pod<uid>/<id>`
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::k8s::PodCgroup;
+//!
+//! let pod = PodCgroup::find("0f2a64e6-8f9b-4b7e-9d1c-2a3b4c5d6e7f")
+//!     .expect("Pod not found on this node");
+//! for pid in pod.pids().expect("Failed to read cgroup.procs") {
+//!     println!("{} ({:?}): {}", pod.uid, pod.qos, pid);
+//! }
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EtmemError, Result};
+use crate::psi::{CGROUP2_ROOT, cgroup_of_pid};
+
+/// Container runtimes whose systemd scope names prefix the container ID
+const RUNTIME_PREFIXES: &[&str] = &["cri-containerd", "crio", "docker"];
+
+/// Kubernetes QoS class of a pod, taken from its parent cgroup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum QosClass {
+    /// Requests equal limits; pod cgroups sit directly under `kubepods`
+    Guaranteed,
+    /// Requests below limits
+    Burstable,
+    /// No requests or limits
+    BestEffort,
+}
+
+impl QosClass {
+    /// Determine the QoS class from a pod cgroup path
+    fn from_path(path: &str) -> Self {
+        if path.contains("besteffort") {
+            Self::BestEffort
+        } else if path.contains("burstable") {
+            Self::Burstable
+        } else {
+            Self::Guaranteed
+        }
+    }
+}
+
+/// Cgroup of a container within a pod
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ContainerCgroup {
+    /// Full container ID (64 hex characters)
+    pub id: String,
+    /// Runtime named in the cgroup (`cri-containerd`, `crio`, `docker`),
+    /// `None` with the cgroupfs driver
+    pub runtime: Option<String>,
+    /// Cgroup path relative to the v2 root (e.g. `/kubepods/pod.../<id>`)
+    pub path: String,
+}
+
+/// Cgroup of a pod and its containers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PodCgroup {
+    /// Pod UID in its canonical dashed form
+    pub uid: String,
+    /// QoS class of the pod
+    pub qos: QosClass,
+    /// Cgroup path relative to the v2 root
+    pub path: String,
+    /// Container cgroups, sorted by ID
+    pub containers: Vec<ContainerCgroup>,
+    /// Mount point of the hierarchy the paths are relative to
+    root: PathBuf,
+}
+
+impl PodCgroup {
+    /// Find a pod by UID in the cgroup v2 hierarchy
+    ///
+    /// The UID may be given with dashes or with the underscores used in
+    /// systemd slice names.
+    ///
+    /// # Errors
+    /// Returns `PodNotFound` if no pod cgroup carries the UID.
+    pub fn find(uid: &str) -> Result<Self> {
+        Self::find_in(CGROUP2_ROOT, uid)
+    }
+
+    /// Find a pod by UID in a cgroup hierarchy mounted at `root`
+    pub fn find_in<P: AsRef<Path>>(root: P, uid: &str) -> Result<Self> {
+        let root = root.as_ref();
+        let uid = canonical_uid(uid);
+        pod_dirs(root)
+            .into_iter()
+            .find(|(pod_uid, _)| *pod_uid == uid)
+            .map(|(uid, dir)| Self::load(root, uid, &dir))
+            .ok_or_else(|| EtmemError::PodNotFound(format!("pod {}", uid)))?
+    }
+
+    /// Find the pod running a container
+    ///
+    /// `id` may be a unique prefix of the container ID and may carry the
+    /// runtime scheme reported in the pod status (`containerd://...`).
+    ///
+    /// # Errors
+    /// Returns `PodNotFound` if no container cgroup matches, or if the
+    /// prefix matches several containers.
+    pub fn for_container(id: &str) -> Result<(Self, ContainerCgroup)> {
+        Self::for_container_in(CGROUP2_ROOT, id)
+    }
+
+    /// Find the pod running a container in a hierarchy mounted at `root`
+    pub fn for_container_in<P: AsRef<Path>>(root: P, id: &str) -> Result<(Self, ContainerCgroup)> {
+        let root = root.as_ref();
+        let id = id.rsplit_once("://").map_or(id, |(_, id)| id);
+        if id.is_empty() {
+            return Err(EtmemError::PodNotFound("empty container ID".to_string()));
+        }
+
+        let mut found = Vec::new();
+        for (uid, dir) in pod_dirs(root) {
+            let pod = Self::load(root, uid, &dir)?;
+            for container in pod.containers.iter().filter(|c| c.id.starts_with(id)) {
+                found.push((pod.clone(), container.clone()));
+            }
+        }
+
+        match found.len() {
+            1 => Ok(found.remove(0)),
+            0 => Err(EtmemError::PodNotFound(format!("container {}", id))),
+            n => Err(EtmemError::PodNotFound(format!(
+                "container prefix {} is ambiguous ({} matches)",
+                id, n
+            ))),
+        }
+    }
+
+    /// Find the pod a process belongs to
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process doesn't exist, or
+    /// `PodNotFound` if it does not run in a pod.
+    pub fn for_pid(pid: u32) -> Result<Self> {
+        let cgroup = cgroup_of_pid(pid)?;
+        let (uid, _) = parse_cgroup_path(&cgroup)
+            .ok_or_else(|| EtmemError::PodNotFound(format!("PID {} is not in a pod", pid)))?;
+        Self::find(&uid)
+    }
+
+    /// Read a pod cgroup and its containers
+    fn load(root: &Path, uid: String, dir: &Path) -> Result<Self> {
+        let path = relative_path(root, dir);
+        let mut containers: Vec<ContainerCgroup> = child_dirs(dir)?
+            .into_iter()
+            .filter_map(|child| {
+                let name = child.file_name()?.to_str()?;
+                let (runtime, id) = parse_container_name(name)?;
+                Some(ContainerCgroup {
+                    id: id.to_string(),
+                    runtime: runtime.map(str::to_string),
+                    path: relative_path(root, &child),
+                })
+            })
+            .collect();
+        containers.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(Self {
+            uid,
+            qos: QosClass::from_path(&path),
+            path,
+            containers,
+            root: root.to_path_buf(),
+        })
+    }
+
+    /// Get the PIDs of every process in the pod, sorted
+    ///
+    /// This includes the sandbox (pause) process and processes of all
+    /// containers.
+    ///
+    /// # Errors
+    /// Returns an I/O error if `cgroup.procs` cannot be read, typically
+    /// because the pod was deleted.
+    pub fn pids(&self) -> Result<Vec<u32>> {
+        cgroup_pids(&self.root, &self.path)
+    }
+
+    /// Get the PIDs of one container, sorted
+    ///
+    /// # Errors
+    /// Returns an I/O error if `cgroup.procs` cannot be read.
+    pub fn container_pids(&self, container: &ContainerCgroup) -> Result<Vec<u32>> {
+        cgroup_pids(&self.root, &container.path)
+    }
+}
+
+/// Extract the pod UID and container ID from a cgroup path
+///
+/// # Returns
+/// `(uid, container_id)`, the container ID being `None` for processes
+/// attached to the pod cgroup itself
+pub fn parse_cgroup_path(cgroup: &str) -> Option<(String, Option<String>)> {
+    let mut components = cgroup.split('/').filter(|c| !c.is_empty());
+    let uid = components.by_ref().find_map(parse_pod_name)?;
+    let container = components
+        .next()
+        .and_then(parse_container_name)
+        .map(|(_, id)| id.to_string());
+    Some((uid, container))
+}
+
+/// Get the PIDs of a cgroup and all its descendants, sorted
+fn cgroup_pids(root: &Path, cgroup: &str) -> Result<Vec<u32>> {
+    let mut pids = Vec::new();
+    let mut pending = vec![root.join(cgroup.trim_start_matches('/'))];
+    while let Some(dir) = pending.pop() {
+        let procs = fs::read_to_string(dir.join("cgroup.procs"))?;
+        pids.extend(
+            procs
+                .lines()
+                .filter_map(|line| line.trim().parse::<u32>().ok()),
+        );
+        pending.extend(child_dirs(&dir)?);
+    }
+    pids.sort_unstable();
+    pids.dedup();
+    Ok(pids)
+}
+
+/// Find every pod cgroup below the `kubepods` cgroups of a hierarchy
+///
+/// Pod cgroups are at most two levels below `kubepods` (QoS class, then
+/// pod), so the walk stops there.
+fn pod_dirs(root: &Path) -> Vec<(String, PathBuf)> {
+    let mut pods = Vec::new();
+    let mut pending: Vec<(PathBuf, u32)> = ["kubepods.slice", "kubepods"]
+        .iter()
+        .map(|name| (root.join(name), 0))
+        .collect();
+
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(children) = child_dirs(&dir) else {
+            continue;
+        };
+        for child in children {
+            let Some(name) = child.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if let Some(uid) = parse_pod_name(name) {
+                pods.push((uid, child));
+            } else if depth == 0 {
+                pending.push((child, depth + 1));
+            }
+        }
+    }
+    pods
+}
+
+/// Get the subdirectories of a cgroup
+fn child_dirs(dir: &Path) -> Result<Vec<PathBuf>> {
+    Ok(fs::read_dir(dir)?
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+        .collect())
+}
+
+/// Path of a cgroup directory relative to the hierarchy root, with a
+/// leading slash as in `/proc/[pid]/cgroup`
+fn relative_path(root: &Path, dir: &Path) -> String {
+    format!(
+        "/{}",
+        dir.strip_prefix(root).unwrap_or(dir).to_string_lossy()
+    )
+}
+
+/// Parse the pod UID out of a pod cgroup name
+///
+/// Accepts `pod<uid>` (cgroupfs) and `kubepods[-<qos>]-pod<uid>.slice`
+/// (systemd).
+fn parse_pod_name(name: &str) -> Option<String> {
+    let name = name.strip_suffix(".slice").unwrap_or(name);
+    let (prefix, uid) = name.rsplit_once("pod")?;
+    let valid_prefix = prefix.is_empty() || prefix.starts_with("kubepods") && prefix.ends_with('-');
+    let valid_uid = !uid.is_empty()
+        && uid
+            .chars()
+            .all(|c| c.is_ascii_hexdigit() || c == '-' || c == '_');
+    (valid_prefix && valid_uid).then(|| canonical_uid(uid))
+}
+
+/// Parse the runtime and ID out of a container cgroup name
+///
+/// Accepts `<runtime>-<id>.scope` (systemd) and a bare `<id>` (cgroupfs).
+fn parse_container_name(name: &str) -> Option<(Option<&str>, &str)> {
+    let (runtime, id) = match name.strip_suffix(".scope") {
+        Some(scope) => {
+            let (runtime, id) = scope.rsplit_once('-')?;
+            (Some(RUNTIME_PREFIXES.iter().find(|&&r| r == runtime)?), id)
+        }
+        None => (None, name),
+    };
+    let valid = id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit());
+    valid.then_some((runtime.copied(), id))
+}
+
+/// Convert a pod UID to its dashed form
+fn canonical_uid(uid: &str) -> String {
+    uid.replace('_', "-").to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UID: &str = "0f2a64e6-8f9b-4b7e-9d1c-2a3b4c5d6e7f";
+    const CID_A: &str = "aa11111111111111111111111111111111111111111111111111111111111111";
+    const CID_B: &str = "bb22222222222222222222222222222222222222222222222222222222222222";
+
+    /// Create a cgroup directory holding `pids`
+    fn cgroup(root: &Path, path: &str, pids: &[u32]) {
+        let dir = root.join(path);
+        fs::create_dir_all(&dir).unwrap();
+        let procs: String = pids.iter().map(|p| format!("{}\n", p)).collect();
+        fs::write(dir.join("cgroup.procs"), procs).unwrap();
+    }
+
+    #[test]
+    fn test_parse_names() {
+        let systemd_uid = UID.replace('-', "_");
+        assert_eq!(
+            parse_pod_name(&format!("kubepods-burstable-pod{}.slice", systemd_uid)),
+            Some(UID.to_string())
+        );
+        assert_eq!(
+            parse_pod_name(&format!("pod{}", UID)),
+            Some(UID.to_string())
+        );
+        assert_eq!(parse_pod_name("kubepods-burstable.slice"), None);
+        assert_eq!(parse_pod_name("system.slice"), None);
+
+        assert_eq!(
+            parse_container_name(&format!("cri-containerd-{}.scope", CID_A)),
+            Some((Some("cri-containerd"), CID_A))
+        );
+        assert_eq!(parse_container_name(CID_B), Some((None, CID_B)));
+        assert_eq!(
+            parse_container_name(&format!("crio-conmon-{}.scope", CID_A)),
+            None
+        );
+        assert_eq!(parse_container_name("init.scope"), None);
+    }
+
+    #[test]
+    fn test_parse_cgroup_path() {
+        let path = format!(
+            "/kubepods.slice/kubepods-besteffort.slice/kubepods-besteffort-pod{}.slice/crio-{}.scope",
+            UID.replace('-', "_"),
+            CID_A
+        );
+        assert_eq!(
+            parse_cgroup_path(&path),
+            Some((UID.to_string(), Some(CID_A.to_string())))
+        );
+        assert_eq!(
+            parse_cgroup_path(&format!("/kubepods/pod{}", UID)),
+            Some((UID.to_string(), None))
+        );
+        assert_eq!(parse_cgroup_path("/system.slice/sshd.service"), None);
+    }
+
+    #[test]
+    fn test_find_pod_systemd() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let pod = format!(
+            "kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod{}.slice",
+            UID.replace('-', "_")
+        );
+        cgroup(root, &pod, &[100]);
+        cgroup(
+            root,
+            &format!("{}/cri-containerd-{}.scope", pod, CID_B),
+            &[300, 301],
+        );
+        cgroup(
+            root,
+            &format!("{}/cri-containerd-{}.scope", pod, CID_A),
+            &[200],
+        );
+
+        let found = PodCgroup::find_in(root, &UID.replace('-', "_")).unwrap();
+        assert_eq!(found.uid, UID);
+        assert_eq!(found.qos, QosClass::Burstable);
+        assert_eq!(found.path, format!("/{}", pod));
+        assert_eq!(found.containers.len(), 2);
+        assert_eq!(found.containers[0].id, CID_A);
+        assert_eq!(
+            found.containers[0].runtime.as_deref(),
+            Some("cri-containerd")
+        );
+        assert_eq!(found.pids().unwrap(), [100, 200, 300, 301]);
+        assert_eq!(
+            found.container_pids(&found.containers[1]).unwrap(),
+            [300, 301]
+        );
+
+        let (by_container, container) =
+            PodCgroup::for_container_in(root, "containerd://bb2222").unwrap();
+        assert_eq!(by_container.uid, UID);
+        assert_eq!(container.id, CID_B);
+
+        assert!(matches!(
+            PodCgroup::find_in(root, "ffffffff-0000-0000-0000-000000000000"),
+            Err(EtmemError::PodNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_find_pod_cgroupfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        cgroup(root, &format!("kubepods/pod{}", UID), &[10]);
+        cgroup(root, &format!("kubepods/pod{}/{}", UID, CID_A), &[11]);
+        cgroup(
+            root,
+            &format!("kubepods/besteffort/pod{}", UID.replace('0', "1")),
+            &[],
+        );
+
+        let found = PodCgroup::find_in(root, UID).unwrap();
+        assert_eq!(found.qos, QosClass::Guaranteed);
+        assert_eq!(found.containers[0].runtime, None);
+        assert_eq!(found.pids().unwrap(), [10, 11]);
+
+        let other = PodCgroup::find_in(root, &UID.replace('0', "1")).unwrap();
+        assert_eq!(other.qos, QosClass::BestEffort);
+        assert!(other.pids().unwrap().is_empty());
+    }
+}
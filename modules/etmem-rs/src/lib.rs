@@ -23,12 +23,35 @@
 //! - **`types`**: Data structures and constants
 //! - **`error`**: Error types and handling
 //! - **`vma`**: Virtual Memory Area discovery and management
+//! - **`container`**: PID, path and uid translation for container targets
+//! - **`dax`**: Device-DAX and fsdax mapping detection
 //! - **`session`**: Unified `EtmemSession` for combined operations
 //! - **`builder`**: Fluent builder APIs for ergonomic operations
 //! - **`workflow`**: High-level workflow builders for complex operations
 //! - **`scan`**: Safe wrappers for page scanning operations
+//! - **`anomaly`**: Sanity checks of scan results before reclaim
 //! - **`swap`**: Safe wrappers for page swapping operations
+//! - **`verify`**: Post-reclaim page placement per swap device
+//! - **`budget`**: CPU and I/O cost accounting of scan cycles
+//! - **`damon`**: DAMON-compatible region reports and cross-checks
+//! - **`aging`**: Compact multi-scan idle history of 2MB blocks
+//! - **`maps`**: Address-space change detection between scans
+//! - **`pool`**: Multi-process swap session pool
+//! - **`guard`**: System-wide swap pressure guardrails
+//! - **`freeze`**: Checkpoint (CRIU) freeze detection and idle map hooks
+//! - **`watchdog`**: Pausing reclaim that makes the target fault
+//! - **`policy`**: Multi-scan page aging and cold page selection
+//! - **`advisory`**: Cold region callbacks for applications scanning themselves
+//! - **`probe`**: Host capability probe (modules, interfaces, privileges)
+//! - **`psi`**: Pressure stall information readings and triggers
+//! - **`report`**: Per-mapping reports joining scan output with VMAs
+//! - **`rebase`**: ASLR-stable addresses relative to their mapping
+//! - **`sampling`**: Sampled scans with extrapolated statistics
+//! - **`pagecache`**: Per-file page cache idleness of file-backed mappings
+//! - **`shared`**: Shared memory segments scanned across processes
 //! - **`util`**: Utility functions and helpers
+//! - **`failpoints`**: Runtime error injection (`failpoints` feature)
+//! - **`k8s`**: Pod and container cgroup lookup (`k8s` feature)
 //!
 //! # Requirements
 //!
@@ -136,34 +159,89 @@
 #![warn(unsafe_op_in_unsafe_fn)]
 
 // Re-export modules
+pub mod advisory;
+pub mod aging;
+pub mod anomaly;
+pub mod budget;
 pub mod builder;
+pub mod container;
+pub mod damon;
+pub mod dax;
 pub mod error;
+#[cfg(feature = "failpoints")]
+pub mod failpoints;
+pub mod freeze;
+pub mod guard;
+#[cfg(feature = "k8s")]
+pub mod k8s;
+pub mod maps;
+pub mod pagecache;
+pub mod policy;
+pub mod pool;
+pub mod probe;
+pub mod psi;
+pub mod rebase;
+pub mod report;
+pub mod sampling;
 pub mod scan;
 pub mod session;
+pub mod shared;
 pub mod swap;
 pub mod sys;
 pub mod types;
 pub mod util;
+pub mod verify;
 pub mod vma;
+pub mod watchdog;
 pub mod workflow;
 
 // Public API exports
+pub use advisory::{Advice, AdvisorHandle, AdvisoryConfig, AdvisoryRound, ColdRegion, SelfAdvisor};
+pub use aging::AgingMap;
+pub use anomaly::{Anomaly, AnomalyDetector, AnomalyReport};
+pub use budget::{BudgetStats, CostLimiter, ScanBudget, ScanCost};
+pub use damon::{DamonComparison, DamonRegion, DamonReport};
+pub use dax::{DaxKind, DaxMounts};
 pub use error::{EtmemError, Result, ToEtmemResult};
-pub use scan::{IdlePageScanner, PageIdleCtrl, ScanSession};
+pub use freeze::{CriuHook, FreezeDetector, FreezeState, IdleMap};
+pub use guard::{GuardConfig, GuardDecision, SwapGuard};
+pub use maps::{MapsChange, MapsTracker};
+pub use pagecache::{FileCacheReport, FileCacheStats};
+pub use policy::{
+    AddressOrder, AgingPolicy, EvictionCandidate, EvictionOrder, LargestRunFirst, OldestFirst,
+    PageAger, PolicyEvent,
+};
+pub use pool::SwapPool;
+pub use probe::{EtmemProbe, ModuleState, probe};
+pub use psi::{PsiKind, PsiReading, PsiTrigger};
+pub use rebase::{AddressRebaser, RebasedAddress};
+pub use report::{RegionReport, RegionStats};
+pub use sampling::{
+    Adaptive, EstimatedStats, RssWeighted, SamplePlan, SampledScan, SamplingStrategy, Stratified,
+    Uniform,
+};
+pub use scan::{
+    IdlePageScanner, PageIdleCtrl, PartialScan, ScanBatch, ScanSession, ScanStamp, ScanStats,
+};
 pub use session::{EtmemSession, ScanAndSwapReport, SessionConfig, VmaScanResults};
-pub use swap::{PageSwapper, SwapSession, SwapcacheConfig};
+pub use shared::{SharedKind, SharedReport, SharedScan, SharedSegment};
+pub use swap::{PageSwapper, ReclaimStats, SwapSession, SwapcacheConfig, SwapcacheController};
 pub use types::{
-    AddressRange, BufferStatus, IDLE_SCAN_MAGIC, INVALID_PAGE, IdlePageInfo, PAGE_IDLE_BUF_MIN,
-    PAGE_IDLE_KBUF_SIZE, PipEncoding, ProcIdlePageType, RECLAIM_SWAPCACHE_MAGIC, RET_RESCAN_FLAG,
-    SWAP_SCAN_NUM_MAX, ScanConfig, ScanFlags, SwapConfig, SwapcacheWatermark, WATERMARK_MAX,
-    WatermarkConfig,
+    AddressRange, BASE_PAGE_SIZE, BufferStatus, DecodeWarning, HUGE_PAGE_SIZE, HugePagePolicy,
+    IDLE_SCAN_MAGIC, INVALID_PAGE, IdlePageInfo, PAGE_IDLE_BUF_MIN, PAGE_IDLE_KBUF_SIZE,
+    PipEncoding, ProcIdlePageType, RECLAIM_SWAPCACHE_MAGIC, RET_RESCAN_FLAG, RangeSet, RetryPolicy,
+    SWAP_SCAN_NUM_MAX, ScanConfig, ScanFlags, SwapConfig, SwapcacheWatermark,
+    UnsupportedFlagPolicy, WATERMARK_MAX, WatermarkConfig, WatermarkStatus,
 };
+pub use verify::{ReclaimVerification, SwapMedium, SwapTarget};
 pub use vma::{PathnameType, VmaFilter, VmaMap, VmaPermissions, VmaRegion};
+pub use watchdog::{Watchdog, WatchdogConfig, WatchdogDecision};
 // PageIdleCtrl is re-exported from scan module above
 pub use util::{
-    IdlePageStats, bytes_to_pages, filter_accessed_pages, filter_huge_pages, filter_idle_pages,
-    format_bytes, group_by_type, huge_page_align_down, is_etmem_available, is_huge_page_aligned,
-    is_page_aligned, is_root, page_align_down, page_align_up, pages_to_bytes, suggest_page_size,
+    IdlePageStats, StatsWindow, Trend, bytes_to_pages, filter_accessed_pages, filter_huge_pages,
+    filter_idle_pages, format_bytes, group_by_type, huge_page_align_down, is_etmem_available,
+    is_huge_page_aligned, is_page_aligned, is_root, page_align_down, page_align_up, pages_to_bytes,
+    suggest_page_size,
 };
 
 /// Convenience prelude module for common imports
@@ -178,7 +256,7 @@ pub mod prelude {
     };
     pub use crate::error::{EtmemError, Result};
     pub use crate::session::{EtmemSession, SessionConfig};
-    pub use crate::types::{AddressRange, IdlePageInfo, ScanConfig, SwapConfig};
+    pub use crate::types::{AddressRange, IdlePageInfo, RangeSet, ScanConfig, SwapConfig};
     pub use crate::vma::{VmaFilter, VmaMap, VmaRegion};
     pub use crate::workflow::ScanAndSwapWorkflow;
 }
@@ -207,7 +285,8 @@ pub fn is_available() -> bool {
 
 /// Check if the current process has required permissions
 ///
-/// ETMEM operations require CAP_SYS_ADMIN (root) capability.
+/// ETMEM operations require CAP_SYS_ADMIN (root) capability on the host;
+/// root inside a user namespace is not enough.
 ///
 /// # Example
 /// ```
@@ -218,7 +297,7 @@ pub fn is_available() -> bool {
 /// }
 /// ```
 pub fn has_permission() -> bool {
-    util::is_root()
+    container::is_host_root()
 }
 
 /// Initialize the ETMEM subsystem
@@ -0,0 +1,263 @@
+//! Address-space change detection between scans
+//!
+//! Aging state is keyed by virtual address, so it goes stale when the
+//! target maps, unmaps or remaps memory between two scans: a page freed
+//! with `munmap` and a fresh mapping placed at the same address would
+//! inherit its idle age. A [`MapsTracker`] hashes `/proc/<pid>/maps` at
+//! every scan into a generation and, when the hash changes, works out
+//! which address ranges are actually affected, so that only their aging
+//! state is dropped.
+//!
+//! A range counts as unchanged if it is mapped before and after with the
+//! same permissions, backing object and offset; resizing or splitting a
+//! mapping only changes the part that was added, removed or reprotected.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::maps::MapsTracker;
+//!
+//! let pid = std::process::id() as u32;
+//! let mut tracker = MapsTracker::new();
+//! tracker.observe_process(pid).expect("Failed to read maps");
+//! // ... scan ...
+//! if let Some(change) = tracker.observe_process(pid).expect("Failed to read maps") {
+//!     println!("{}", change);
+//! }
+//! ```
+
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::error::Result;
+use crate::types::{AddressRange, RangeSet};
+use crate::vma::{VmaMap, VmaRegion};
+
+/// Change of an address space between two scans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapsChange {
+    /// Generation of the address space after the change
+    pub generation: u64,
+    /// Hash of the maps before the change
+    pub previous_hash: u64,
+    /// Hash of the maps after the change
+    pub hash: u64,
+    /// Ranges mapped, unmapped or remapped by the change
+    pub changed: RangeSet,
+}
+
+impl MapsChange {
+    /// Get the number of bytes affected by the change
+    pub fn changed_bytes(&self) -> u64 {
+        self.changed.iter().map(|r| r.size()).sum()
+    }
+}
+
+impl fmt::Display for MapsChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "address space generation {} ({:016x}): {} ranges, {} bytes changed",
+            self.generation,
+            self.hash,
+            self.changed.ranges().len(),
+            self.changed_bytes()
+        )
+    }
+}
+
+/// Tracker of the address-space generation of a process
+///
+/// The generation starts at 0 with the first observed maps and is bumped
+/// every time an observation differs from the previous one.
+#[derive(Debug, Clone, Default)]
+pub struct MapsTracker {
+    /// Maps of the last observation
+    last: Option<VmaMap>,
+    /// Hash of `last`
+    hash: u64,
+    /// Number of changes seen
+    generation: u64,
+}
+
+impl MapsTracker {
+    /// Create a tracker that has not seen any maps yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the current generation
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Get the hash of the last observed maps, `None` before the first
+    pub fn hash(&self) -> Option<u64> {
+        self.last.as_ref().map(|_| self.hash)
+    }
+
+    /// Get the last observed maps
+    pub fn maps(&self) -> Option<&VmaMap> {
+        self.last.as_ref()
+    }
+
+    /// Read and observe the maps of a process
+    ///
+    /// # Errors
+    /// Returns error if `/proc/<pid>/maps` cannot be read or parsed.
+    pub fn observe_process(&mut self, pid: u32) -> Result<Option<MapsChange>> {
+        Ok(self.observe(VmaMap::for_process(pid)?))
+    }
+
+    /// Observe the maps taken at one scan
+    ///
+    /// Returns the change since the previous observation, or `None` for
+    /// the first observation and when nothing changed.
+    pub fn observe(&mut self, maps: VmaMap) -> Option<MapsChange> {
+        let hash = maps_hash(&maps);
+        let previous_hash = std::mem::replace(&mut self.hash, hash);
+        let previous = self.last.replace(maps)?;
+        if previous_hash == hash {
+            return None;
+        }
+        let changed = changed_ranges(&previous, self.last.as_ref()?);
+        self.generation += 1;
+        Some(MapsChange {
+            generation: self.generation,
+            previous_hash,
+            hash,
+            changed,
+        })
+    }
+
+    /// Forget the observed maps, keeping the generation
+    pub fn reset(&mut self) {
+        self.last = None;
+    }
+}
+
+/// Hash the regions of a maps snapshot
+pub fn maps_hash(maps: &VmaMap) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    maps.regions().hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Compute the address ranges that differ between two maps snapshots
+///
+/// Addresses mapped in only one of them, or mapped by different mappings
+/// in each, are changed.
+pub fn changed_ranges(before: &VmaMap, after: &VmaMap) -> RangeSet {
+    let region_range = |vma: &VmaRegion| AddressRange::new(vma.start, vma.end);
+    let all = RangeSet::from_ranges(
+        before
+            .regions()
+            .iter()
+            .chain(after.regions())
+            .map(region_range),
+    );
+
+    // Both lists are sorted and disjoint; walk them together
+    let mut unchanged = RangeSet::new();
+    let (old, new) = (before.regions(), after.regions());
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        let (a, b) = (&old[i], &new[j]);
+        let start = a.start.max(b.start);
+        let end = a.end.min(b.end);
+        if start < end && same_mapping(a, b) {
+            unchanged.insert(AddressRange::new(start, end));
+        }
+        if a.end <= b.end {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    all.difference(&unchanged)
+}
+
+/// Check whether two regions map the same object at the same addresses
+///
+/// The kernel reports offset 0 for anonymous mappings, so offsets are only
+/// compared for file-backed ones.
+fn same_mapping(a: &VmaRegion, b: &VmaRegion) -> bool {
+    a.permissions == b.permissions
+        && a.device == b.device
+        && a.inode == b.inode
+        && a.pathname == b.pathname
+        && (a.inode == 0 || a.offset.wrapping_sub(a.start) == b.offset.wrapping_sub(b.start))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn maps(lines: &[&str]) -> VmaMap {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        for line in lines {
+            writeln!(file, "{}", line).unwrap();
+        }
+        VmaMap::from_file(file.path(), 1).unwrap()
+    }
+
+    const HEAP: &str = "00600000-00a00000 rw-p 00000000 00:00 0 [heap]";
+    const LIB: &str = "7f0000000000-7f0000200000 r-xp 00000000 08:01 42 /usr/lib/libc.so";
+
+    #[test]
+    fn test_first_and_unchanged_observations() {
+        let mut tracker = MapsTracker::new();
+        assert_eq!(tracker.hash(), None);
+        assert_eq!(tracker.observe(maps(&[HEAP, LIB])), None);
+        let hash = tracker.hash();
+        assert!(hash.is_some());
+        assert_eq!(tracker.observe(maps(&[HEAP, LIB])), None);
+        assert_eq!(tracker.hash(), hash);
+        assert_eq!(tracker.generation(), 0);
+    }
+
+    #[test]
+    fn test_changed_ranges() {
+        let mut tracker = MapsTracker::new();
+        tracker.observe(maps(&[HEAP, LIB]));
+
+        // Heap grown, new anonymous mapping, library unmapped
+        let change = tracker
+            .observe(maps(&[
+                "00600000-00c00000 rw-p 00000000 00:00 0 [heap]",
+                "7e0000000000-7e0000100000 rw-p 00000000 00:00 0",
+            ]))
+            .unwrap();
+        assert_eq!(change.generation, 1);
+        assert_eq!(Some(change.hash), tracker.hash());
+        assert_eq!(
+            change.changed.ranges(),
+            [
+                AddressRange::new(0xa00000, 0xc00000),
+                AddressRange::new(0x7e00_0000_0000, 0x7e00_0010_0000),
+                AddressRange::new(0x7f00_0000_0000, 0x7f00_0020_0000),
+            ]
+        );
+        assert_eq!(change.changed_bytes(), 0x200000 + 0x100000 + 0x200000);
+
+        // Part of the heap reprotected and the mapping grown downwards:
+        // only those parts changed
+        let change = tracker
+            .observe(maps(&[
+                "00600000-00800000 rw-p 00000000 00:00 0 [heap]",
+                "00800000-00c00000 r--p 00000000 00:00 0 [heap]",
+                "7dffffe00000-7e0000100000 rw-p 00000000 00:00 0",
+            ]))
+            .unwrap();
+        assert_eq!(change.generation, 2);
+        assert_eq!(
+            change.changed.ranges(),
+            [
+                AddressRange::new(0x800000, 0xc00000),
+                AddressRange::new(0x7dff_ffe0_0000, 0x7e00_0000_0000),
+            ]
+        );
+    }
+}
@@ -0,0 +1,364 @@
+//! Page cache idleness of file-backed mappings
+//!
+//! Idle file pages are the cheapest memory to reclaim: clean pages are
+//! dropped without I/O and dirty ones are written back to their file
+//! instead of swap. This module joins idle page scan output with the page
+//! cache residency of every file mapped by a process, so policies can see
+//! how much of each file is cached but not in active use.
+//!
+//! Residency is read with `mincore(2)` on a read-only shared mapping of
+//! the same file window, opened through `/proc/[pid]/map_files` so that
+//! deleted and unreachable files are still covered.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::pagecache::FileCacheReport;
+//! use etmem_rs::{IdlePageScanner, ScanConfig, VmaMap};
+//!
+//! let pid = std::process::id() as u32;
+//! let vma_map = VmaMap::for_process(pid).expect("Failed to parse VMAs");
+//! let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+//!     .expect("Failed to scan");
+//!
+//! let report = FileCacheReport::build(&vma_map, &pages);
+//! for file in report.files.iter().take(5) {
+//!     println!("{}: {} reclaimable bytes", file.path, file.reclaimable_bytes());
+//! }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::File;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sys;
+use crate::types::{BASE_PAGE_SIZE, IdlePageInfo};
+use crate::util::format_bytes;
+use crate::vma::{VmaMap, VmaRegion};
+
+/// How the scanned process uses one page of a file
+///
+/// Ordered by heat so that a file page mapped several times keeps the
+/// hottest state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum PageUse {
+    /// Not present in the process page tables
+    Unreferenced,
+    /// Mapped but not accessed since the last scan
+    Idle,
+    /// Accessed or dirtied since the last scan
+    Hot,
+}
+
+/// Page cache statistics for one file mapped by a process
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileCacheStats {
+    /// Path of the file as shown in `/proc/[pid]/maps`
+    pub path: String,
+    /// Device number (major:minor) of the file
+    pub device: String,
+    /// Inode number of the file
+    pub inode: u64,
+    /// Bytes of the file covered by the process's mappings
+    pub mapped_bytes: u64,
+    /// Mapped bytes currently in the page cache
+    pub resident_bytes: u64,
+    /// Cached bytes the process maps but has not accessed
+    pub idle_bytes: u64,
+    /// Cached bytes not present in the process page tables
+    pub unreferenced_bytes: u64,
+}
+
+impl FileCacheStats {
+    /// Get cached bytes the process is not actively using
+    pub fn reclaimable_bytes(&self) -> u64 {
+        self.idle_bytes + self.unreferenced_bytes
+    }
+
+    /// Calculate the reclaimable share of resident bytes (0.0 - 1.0)
+    pub fn reclaimable_ratio(&self) -> f64 {
+        if self.resident_bytes == 0 {
+            0.0
+        } else {
+            self.reclaimable_bytes() as f64 / self.resident_bytes as f64
+        }
+    }
+}
+
+/// Per-file page cache idleness of a process
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileCacheReport {
+    /// Process ID
+    pub pid: u32,
+    /// Per-file statistics, sorted by reclaimable bytes (descending)
+    pub files: Vec<FileCacheStats>,
+    /// Files whose residency could not be read
+    pub skipped: Vec<String>,
+}
+
+impl FileCacheReport {
+    /// Join scan output with the page cache residency of mapped files
+    ///
+    /// Only mappings for which [`VmaRegion::is_file_backed`] holds are
+    /// considered. Files that cannot be opened or mapped are listed in
+    /// `skipped` rather than failing the whole report.
+    pub fn build(vma_map: &VmaMap, pages: &[IdlePageInfo]) -> Self {
+        let pid = vma_map.pid();
+        Self::build_with(vma_map, pages, |vma| {
+            let file = open_mapped_file(pid, vma)?;
+            sys::file_residency(&file, vma.offset, vma.size())
+        })
+    }
+
+    /// Build a report with a custom residency source
+    ///
+    /// `residency` returns one flag per base page of the mapping's file
+    /// window, as [`sys::file_residency`] does.
+    pub fn build_with<F>(vma_map: &VmaMap, pages: &[IdlePageInfo], mut residency: F) -> Self
+    where
+        F: FnMut(&VmaRegion) -> std::io::Result<Vec<bool>>,
+    {
+        // (device, inode) -> (stats, file page index -> hottest use)
+        let mut files: HashMap<(String, u64), (FileCacheStats, BTreeMap<u64, PageUse>)> =
+            HashMap::new();
+        let mut skipped = Vec::new();
+
+        for vma in vma_map.regions().iter().filter(|v| v.is_file_backed()) {
+            let resident = match residency(vma) {
+                Ok(resident) => resident,
+                Err(e) => {
+                    log::debug!("Skipping {} for page cache report: {}", vma.name(), e);
+                    if !skipped.iter().any(|s| s == vma.name()) {
+                        skipped.push(vma.name().to_string());
+                    }
+                    continue;
+                }
+            };
+
+            let (stats, usage) =
+                files
+                    .entry((vma.device.clone(), vma.inode))
+                    .or_insert_with(|| {
+                        (
+                            FileCacheStats {
+                                path: vma.name().to_string(),
+                                device: vma.device.clone(),
+                                inode: vma.inode,
+                                mapped_bytes: 0,
+                                resident_bytes: 0,
+                                idle_bytes: 0,
+                                unreferenced_bytes: 0,
+                            },
+                            BTreeMap::new(),
+                        )
+                    });
+
+            let first_page = vma.offset / BASE_PAGE_SIZE;
+            for (idx, page_use) in vma_page_use(vma, pages).into_iter().enumerate() {
+                if !resident.get(idx).copied().unwrap_or(false) {
+                    continue;
+                }
+                usage
+                    .entry(first_page + idx as u64)
+                    .and_modify(|u| *u = (*u).max(page_use))
+                    .or_insert(page_use);
+            }
+            stats.mapped_bytes += vma.size();
+        }
+
+        let mut files: Vec<FileCacheStats> = files
+            .into_values()
+            .map(|(mut stats, usage)| {
+                for page_use in usage.values() {
+                    stats.resident_bytes += BASE_PAGE_SIZE;
+                    match page_use {
+                        PageUse::Idle => stats.idle_bytes += BASE_PAGE_SIZE,
+                        PageUse::Unreferenced => stats.unreferenced_bytes += BASE_PAGE_SIZE,
+                        PageUse::Hot => {}
+                    }
+                }
+                stats
+            })
+            .collect();
+        files.sort_by(|a, b| {
+            b.reclaimable_bytes()
+                .cmp(&a.reclaimable_bytes())
+                .then(a.path.cmp(&b.path))
+        });
+
+        Self {
+            pid: vma_map.pid(),
+            files,
+            skipped,
+        }
+    }
+
+    /// Total page cache bytes of mapped files
+    pub fn total_resident_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.resident_bytes).sum()
+    }
+
+    /// Total reclaimable page cache bytes across all files
+    pub fn total_reclaimable_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.reclaimable_bytes()).sum()
+    }
+}
+
+/// Open the file behind a mapping
+///
+/// Prefers `/proc/[pid]/map_files`, which resolves deleted files and paths
+/// outside our mount namespace, and falls back to the mapped path resolved
+/// in the mount namespace of the process, so files of container targets
+/// are found too.
+fn open_mapped_file(pid: u32, vma: &VmaRegion) -> std::io::Result<File> {
+    let link = format!("/proc/{}/map_files/{:x}-{:x}", pid, vma.start, vma.end);
+    File::open(link).or_else(|e| match vma.pathname.as_deref() {
+        Some(path) => {
+            File::open(crate::container::root_path(pid, path)).or_else(|_| File::open(path))
+        }
+        None => Err(e),
+    })
+}
+
+/// Classify every base page of a mapping from scan output
+fn vma_page_use(vma: &VmaRegion, pages: &[IdlePageInfo]) -> Vec<PageUse> {
+    let mut usage = vec![PageUse::Unreferenced; vma.size().div_ceil(BASE_PAGE_SIZE) as usize];
+    let range = vma.to_address_range();
+
+    let first = pages.partition_point(|p| p.end_address() <= vma.start);
+    for page in &pages[first..] {
+        if page.address >= vma.end {
+            break;
+        }
+        let page_use = match page.page_type {
+            t if t.is_hole() => continue,
+            t if t.is_idle() => PageUse::Idle,
+            _ => PageUse::Hot,
+        };
+        let page_range = crate::types::AddressRange::new(page.address, page.end_address());
+        if let Some(overlap) = page_range.intersection(&range) {
+            let start = ((overlap.start - vma.start) / BASE_PAGE_SIZE) as usize;
+            let end = (overlap.end - vma.start).div_ceil(BASE_PAGE_SIZE) as usize;
+            usage[start..end].fill(page_use);
+        }
+    }
+    usage
+}
+
+impl fmt::Display for FileCacheReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Page cache report for PID {}:", self.pid)?;
+        writeln!(
+            f,
+            "  {:<40} {:>12} {:>12} {:>12} {:>7}",
+            "File", "Cached", "Idle", "Unreferenced", "Recl%"
+        )?;
+        for file in &self.files {
+            writeln!(
+                f,
+                "  {:<40} {:>12} {:>12} {:>12} {:>6.1}%",
+                file.path,
+                format_bytes(file.resident_bytes),
+                format_bytes(file.idle_bytes),
+                format_bytes(file.unreferenced_bytes),
+                file.reclaimable_ratio() * 100.0
+            )?;
+        }
+        for path in &self.skipped {
+            writeln!(f, "  {:<40} (residency unavailable)", path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProcIdlePageType;
+
+    fn test_map() -> VmaMap {
+        let maps = "\
+00400000-00404000 r-xp 00000000 08:01 100 /usr/bin/app
+00600000-00800000 rw-p 00000000 00:00 0 [heap]
+7f0000000000-7f0000002000 r--p 00000000 08:01 200 /usr/lib/libfoo.so
+7f0000002000-7f0000004000 r-xp 00001000 08:01 200 /usr/lib/libfoo.so
+";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("maps");
+        std::fs::write(&path, maps).unwrap();
+        VmaMap::from_file(&path, 42).unwrap()
+    }
+
+    #[test]
+    fn test_file_cache_report() {
+        let pages = vec![
+            IdlePageInfo::new(0x400000, ProcIdlePageType::PteAccessed, 1),
+            IdlePageInfo::new(0x401000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x600000, ProcIdlePageType::PmdIdle, 1),
+            IdlePageInfo::new(0x7f0000000000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x7f0000002000, ProcIdlePageType::PteAccessed, 1),
+        ];
+
+        let report = FileCacheReport::build_with(&test_map(), &pages, |vma| {
+            Ok(match vma.inode {
+                // Last page of the app was read by someone else
+                100 => vec![true, true, false, true],
+                _ => vec![true, true],
+            })
+        });
+
+        assert_eq!(report.pid, 42);
+        assert_eq!(report.files.len(), 2);
+        assert!(report.skipped.is_empty());
+
+        let app = &report.files[0];
+        assert_eq!(app.path, "/usr/bin/app");
+        assert_eq!(app.mapped_bytes, 4 * 4096);
+        assert_eq!(app.resident_bytes, 3 * 4096);
+        assert_eq!(app.idle_bytes, 4096);
+        assert_eq!(app.unreferenced_bytes, 4096);
+
+        // File page 1 is mapped twice: idle in the first mapping, hot in
+        // the second, so only file page 0 counts as idle
+        let lib = &report.files[1];
+        assert_eq!(lib.path, "/usr/lib/libfoo.so");
+        assert_eq!(lib.mapped_bytes, 4 * 4096);
+        assert_eq!(lib.resident_bytes, 3 * 4096);
+        assert_eq!(lib.idle_bytes, 4096);
+        assert_eq!(lib.unreferenced_bytes, 4096);
+
+        assert_eq!(report.total_resident_bytes(), 6 * 4096);
+        assert_eq!(report.total_reclaimable_bytes(), 4 * 4096);
+        assert!(report.to_string().contains("libfoo.so"));
+    }
+
+    #[test]
+    fn test_file_cache_report_skipped() {
+        let report = FileCacheReport::build_with(&test_map(), &[], |vma| {
+            if vma.inode == 200 {
+                Err(std::io::Error::from_raw_os_error(libc::EACCES))
+            } else {
+                Ok(vec![true; 4])
+            }
+        });
+
+        assert_eq!(report.files.len(), 1);
+        assert_eq!(report.files[0].unreferenced_bytes, 4 * 4096);
+        assert_eq!(report.skipped, vec!["/usr/lib/libfoo.so".to_string()]);
+    }
+
+    #[test]
+    fn test_file_residency_of_self() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data");
+        std::fs::write(&path, vec![1u8; 3 * 4096]).unwrap();
+        let file = File::open(&path).unwrap();
+
+        // Just written, so cached; the window runs past the end of the file
+        let resident = sys::file_residency(&file, 0, 4 * 4096).unwrap();
+        assert_eq!(resident.len(), 4);
+        assert!(!resident[3]);
+    }
+}
@@ -0,0 +1,984 @@
+//! Page aging policy for multi-scan cold page detection
+//!
+//! A single scan only tells whether a page was accessed since the previous
+//! scan. This module tracks how many consecutive scans each page has been
+//! idle for, so that only pages that stayed cold across several intervals
+//! are selected for reclaim.
+//!
+//! Which cold pages go first, and therefore which ones fit within
+//! `max_swap_bytes`, is decided by an [`EvictionOrder`]: oldest first by
+//! default, with largest-run-first and address order available for
+//! experimentation.
+//!
+//! Ages are keyed by address, so they go stale when the target remaps its
+//! address space between scans. Feeding the maps of each scan to
+//! [`PageAger::sync_maps`] drops the ages of the changed ranges only and
+//! queues a [`PolicyEvent::MapsChanged`] notification.
+//!
+//! A scan whose [`AnomalyReport`] has more than
+//! [`AgingPolicy::max_anomalies`] anomalies is not trusted: recording it
+//! with [`PageAger::observe_checked`] leaves the ages as they are, queues a
+//! [`PolicyEvent::ScanRejected`] notification and selects no cold pages
+//! until a scan is accepted.
+//!
+//! A [`SwapGuard`] attached with [`PageAger::with_guard`] is consulted by
+//! [`PageAger::reclaim_pages`] before cold pages are handed out: when the
+//! guard refuses, no pages are selected and a
+//! [`PolicyEvent::ReclaimRefused`] notification is queued, so the refused
+//! round never reaches the kernel.
+//!
+//! Per-page tracking needs memory proportional to the idle pages. For very
+//! large processes, [`PageAger::with_block_aging`] switches the ager to an
+//! [`AgingMap`], which applies the same [`AgingPolicy`] to 2MB blocks.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::policy::{AgingPolicy, PageAger};
+//! use etmem_rs::{IdlePageScanner, ScanConfig};
+//!
+//! let pid = std::process::id() as u32;
+//! let mut ager = PageAger::new(
+//!     AgingPolicy::new()
+//!         .with_min_idle_scans(3)
+//!         .with_max_swap_bytes(512 * 1024 * 1024),
+//! );
+//!
+//! for _ in 0..3 {
+//!     let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+//!         .expect("Failed to scan");
+//!     ager.observe(&pages);
+//!     std::thread::sleep(std::time::Duration::from_secs(30));
+//! }
+//!
+//! let cold = ager.cold_pages();
+//! println!("{} cold pages selected", cold.len());
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::aging::AgingMap;
+use crate::anomaly::AnomalyReport;
+use crate::error::Result;
+use crate::guard::{GuardDecision, PressureSnapshot, SwapGuard};
+use crate::maps::{MapsChange, MapsTracker};
+use crate::types::{
+    AddressRange, BASE_PAGE_SIZE, HugePagePolicy, IdlePageInfo, ProcIdlePageType, RangeSet,
+};
+use crate::vma::VmaMap;
+
+/// Policy deciding which aged pages are cold enough to reclaim
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AgingPolicy {
+    /// Number of consecutive idle scans before a page is considered cold
+    pub min_idle_scans: u32,
+    /// Upper bound on the bytes selected per round (None = unlimited)
+    pub max_swap_bytes: Option<u64>,
+    /// How selected huge pages are submitted for swap
+    pub huge_pages: HugePagePolicy,
+    /// Anomalies a scan may have before it is rejected
+    pub max_anomalies: u64,
+}
+
+impl AgingPolicy {
+    /// Create the default policy (two consecutive idle scans, no limit,
+    /// no anomalies tolerated)
+    pub const fn new() -> Self {
+        Self {
+            min_idle_scans: 2,
+            max_swap_bytes: None,
+            huge_pages: HugePagePolicy::Whole,
+            max_anomalies: 0,
+        }
+    }
+
+    /// Set the number of consecutive idle scans required (minimum 1)
+    pub const fn with_min_idle_scans(mut self, scans: u32) -> Self {
+        self.min_idle_scans = if scans < 1 { 1 } else { scans };
+        self
+    }
+
+    /// Limit the number of bytes selected per round
+    pub const fn with_max_swap_bytes(mut self, bytes: u64) -> Self {
+        self.max_swap_bytes = Some(bytes);
+        self
+    }
+
+    /// Set how huge pages are submitted for swap
+    pub const fn with_huge_pages(mut self, policy: HugePagePolicy) -> Self {
+        self.huge_pages = policy;
+        self
+    }
+
+    /// Set the number of anomalies a scan may have before it is rejected
+    pub const fn with_max_anomalies(mut self, anomalies: u64) -> Self {
+        self.max_anomalies = anomalies;
+        self
+    }
+}
+
+impl Default for AgingPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Idle age of a single page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PageAge {
+    /// Page type reported by the most recent scan
+    page_type: ProcIdlePageType,
+    /// Number of consecutive scans the page was idle
+    idle_scans: u32,
+}
+
+/// Cold page considered for eviction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictionCandidate {
+    /// Page address
+    pub address: u64,
+    /// Page type reported by the most recent scan
+    pub page_type: ProcIdlePageType,
+    /// Number of consecutive scans the page was idle
+    pub idle_scans: u32,
+    /// Size of the run of address-contiguous cold pages containing the page
+    pub run_bytes: u64,
+}
+
+impl EvictionCandidate {
+    /// Get the page size in bytes
+    pub const fn size(&self) -> u64 {
+        self.page_type.page_size()
+    }
+}
+
+/// Strategy ordering cold pages for eviction
+///
+/// Pages are selected in this order until `max_swap_bytes` is reached, and
+/// [`PageAger::cold_pages`] returns them in this order.
+pub trait EvictionOrder: fmt::Debug + Send + Sync {
+    /// Short name of the strategy
+    fn name(&self) -> &'static str;
+
+    /// Sort candidates, first to evict first
+    ///
+    /// Candidates arrive sorted by address.
+    fn sort(&self, candidates: &mut [EvictionCandidate]);
+}
+
+/// Evict the pages idle for the most scans first (LRU approximation)
+///
+/// Ties are broken by address.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OldestFirst;
+
+impl EvictionOrder for OldestFirst {
+    fn name(&self) -> &'static str {
+        "oldest-first"
+    }
+
+    fn sort(&self, candidates: &mut [EvictionCandidate]) {
+        candidates.sort_by(|a, b| {
+            b.idle_scans
+                .cmp(&a.idle_scans)
+                .then(a.address.cmp(&b.address))
+        });
+    }
+}
+
+/// Evict the pages of the largest contiguous cold runs first
+///
+/// Large runs swap out with fewer, larger I/Os and free contiguous memory.
+/// Pages of a run stay together in address order.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LargestRunFirst;
+
+impl EvictionOrder for LargestRunFirst {
+    fn name(&self) -> &'static str {
+        "largest-run-first"
+    }
+
+    fn sort(&self, candidates: &mut [EvictionCandidate]) {
+        candidates.sort_by(|a, b| {
+            b.run_bytes
+                .cmp(&a.run_bytes)
+                .then(a.address.cmp(&b.address))
+        });
+    }
+}
+
+/// Evict pages in address order, ignoring their age
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddressOrder;
+
+impl EvictionOrder for AddressOrder {
+    fn name(&self) -> &'static str {
+        "address-order"
+    }
+
+    fn sort(&self, _candidates: &mut [EvictionCandidate]) {}
+}
+
+/// Notification raised while aging pages
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyEvent {
+    /// The address space changed since the previous scan and the ages of
+    /// the changed ranges were dropped
+    MapsChanged {
+        /// Generation, hash and changed ranges of the address space
+        change: MapsChange,
+        /// Number of tracked pages whose age was dropped
+        invalidated: usize,
+    },
+    /// A scan had more anomalies than the policy allows and was not
+    /// recorded; no cold pages are selected until a scan is accepted
+    ScanRejected {
+        /// Anomalies of the rejected scan
+        report: AnomalyReport,
+        /// Anomalies the policy allows
+        max_anomalies: u64,
+    },
+    /// The swap guard refused reclaim and no cold pages were selected
+    ReclaimRefused {
+        /// Why the guard refused
+        reason: String,
+    },
+}
+
+impl fmt::Display for PolicyEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MapsChanged {
+                change,
+                invalidated,
+            } => write!(f, "{}, {} page ages dropped", change, invalidated),
+            Self::ScanRejected {
+                report,
+                max_anomalies,
+            } => write!(
+                f,
+                "Scan rejected, {} (at most {} allowed); reclaim paused",
+                report, max_anomalies
+            ),
+            Self::ReclaimRefused { reason } => write!(f, "Reclaim refused, {}", reason),
+        }
+    }
+}
+
+/// Tracks per-page idle age across successive scans
+///
+/// Pages are tracked individually (4KB for base pages, 2MB for huge
+/// pages). A page's age is reset as soon as a scan reports it accessed
+/// or stops reporting it.
+///
+/// With [`with_block_aging`](Self::with_block_aging), ages are kept per
+/// 2MB block in an [`AgingMap`] instead. Only the swappable regions of the
+/// maps last passed to [`sync_maps`](Self::sync_maps) are tracked, so call
+/// it before every [`observe`](Self::observe). Cold blocks are selected
+/// oldest first whatever the eviction order, and handed out as base pages.
+#[derive(Debug, Clone)]
+pub struct PageAger {
+    /// Selection policy
+    policy: AgingPolicy,
+    /// Order in which cold pages are selected
+    order: Arc<dyn EvictionOrder>,
+    /// Idle age by page address
+    ages: HashMap<u64, PageAge>,
+    /// Number of scans observed
+    scans: u32,
+    /// Address-space generation of the target
+    maps: MapsTracker,
+    /// DAX mappings of the target, never selected
+    dax: RangeSet,
+    /// Notifications not yet taken
+    events: Vec<PolicyEvent>,
+    /// Whether the last scan was rejected
+    rejected: bool,
+    /// Swap pressure guard consulted before reclaim
+    guard: Option<SwapGuard>,
+    /// Block ages replacing the per-page ages
+    blocks: Option<AgingMap>,
+}
+
+impl PageAger {
+    /// Create a new ager with the given policy
+    pub fn new(policy: AgingPolicy) -> Self {
+        Self {
+            policy,
+            order: Arc::new(OldestFirst),
+            ages: HashMap::new(),
+            scans: 0,
+            maps: MapsTracker::new(),
+            dax: RangeSet::default(),
+            events: Vec::new(),
+            rejected: false,
+            guard: None,
+            blocks: None,
+        }
+    }
+
+    /// Set the order in which cold pages are selected
+    pub fn with_eviction_order<O: EvictionOrder + 'static>(mut self, order: O) -> Self {
+        self.order = Arc::new(order);
+        self
+    }
+
+    /// Attach a swap pressure guard consulted by
+    /// [`reclaim_pages`](Self::reclaim_pages)
+    pub fn with_guard(mut self, guard: SwapGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Keep ages per 2MB block in `map` instead of per page
+    pub fn with_block_aging(mut self, map: AgingMap) -> Self {
+        self.ages.clear();
+        self.blocks = Some(map);
+        self
+    }
+
+    /// Get the block ages, if block aging is enabled
+    pub fn block_aging(&self) -> Option<&AgingMap> {
+        self.blocks.as_ref()
+    }
+
+    /// Get the selection policy
+    pub fn policy(&self) -> &AgingPolicy {
+        &self.policy
+    }
+
+    /// Get the eviction order
+    pub fn eviction_order(&self) -> &dyn EvictionOrder {
+        self.order.as_ref()
+    }
+
+    /// Record the results of one scan
+    ///
+    /// Returns the number of pages that are idle in this scan, or of 2MB
+    /// blocks with block aging.
+    pub fn observe(&mut self, pages: &[IdlePageInfo]) -> usize {
+        if let Some(blocks) = self.blocks.as_mut() {
+            self.scans += 1;
+            self.rejected = false;
+            return blocks.observe(pages);
+        }
+
+        let mut ages = HashMap::with_capacity(self.ages.len());
+
+        for page in pages.iter().filter(|p| p.is_idle()) {
+            let step = page.page_type.page_size();
+            let start = if page.page_type.is_huge() {
+                crate::util::huge_page_align_down(page.address)
+            } else {
+                page.address
+            };
+
+            for addr in (0..page.count as u64).map(|i| start + i * step) {
+                let idle_scans = match self.ages.get(&addr) {
+                    Some(prev) if prev.page_type.is_huge() == page.page_type.is_huge() => {
+                        prev.idle_scans + 1
+                    }
+                    _ => 1,
+                };
+                ages.insert(
+                    addr,
+                    PageAge {
+                        page_type: page.page_type,
+                        idle_scans,
+                    },
+                );
+            }
+        }
+
+        self.ages = ages;
+        self.scans += 1;
+        self.rejected = false;
+        self.ages.len()
+    }
+
+    /// Record the results of one scan unless it has too many anomalies
+    ///
+    /// A scan whose report exceeds [`AgingPolicy::max_anomalies`] leaves
+    /// the ages untouched, queues a [`PolicyEvent::ScanRejected`] and makes
+    /// [`cold_pages`](Self::cold_pages) select nothing until a scan is
+    /// accepted.
+    ///
+    /// Returns the number of pages that are idle in this scan, `None` if
+    /// it was rejected.
+    pub fn observe_checked(
+        &mut self,
+        pages: &[IdlePageInfo],
+        report: &AnomalyReport,
+    ) -> Option<usize> {
+        if !report.exceeds(self.policy.max_anomalies) {
+            return Some(self.observe(pages));
+        }
+        self.rejected = true;
+        let event = PolicyEvent::ScanRejected {
+            report: report.clone(),
+            max_anomalies: self.policy.max_anomalies,
+        };
+        log::debug!("{}", event);
+        self.events.push(event);
+        None
+    }
+
+    /// Check whether the last scan was rejected, pausing reclaim
+    pub fn is_rejected(&self) -> bool {
+        self.rejected
+    }
+
+    /// Get the number of scans observed
+    pub fn scans(&self) -> u32 {
+        self.scans
+    }
+
+    /// Get the number of pages currently idle, or of tracked 2MB blocks
+    /// with block aging
+    pub fn tracked(&self) -> usize {
+        match &self.blocks {
+            Some(blocks) => blocks.blocks(),
+            None => self.ages.len(),
+        }
+    }
+
+    /// Get the number of consecutive idle scans for a page
+    pub fn age_of(&self, addr: u64) -> u32 {
+        match &self.blocks {
+            Some(blocks) => blocks.age_of(addr),
+            None => self.ages.get(&addr).map(|a| a.idle_scans).unwrap_or(0),
+        }
+    }
+
+    /// Select cold pages according to the policy
+    ///
+    /// Pages are selected in eviction order until `max_swap_bytes` is
+    /// reached and returned in that order. Each returned entry covers a
+    /// single page.
+    pub fn cold_pages(&self) -> Vec<IdlePageInfo> {
+        if let Some(blocks) = &self.blocks {
+            if self.rejected {
+                return Vec::new();
+            }
+            return self
+                .block_pages(&blocks.cold_ranges(&self.policy))
+                .map(|address| IdlePageInfo::new(address, ProcIdlePageType::PteIdle, 1))
+                .collect();
+        }
+
+        let mut selected = Vec::new();
+        let mut bytes = 0u64;
+        for candidate in self.candidates() {
+            let size = candidate.size();
+            if let Some(max) = self.policy.max_swap_bytes
+                && bytes + size > max
+            {
+                // A smaller base page may still fit
+                if size > BASE_PAGE_SIZE {
+                    continue;
+                }
+                break;
+            }
+            bytes += size;
+            selected.push(IdlePageInfo::new(candidate.address, candidate.page_type, 1));
+        }
+        selected
+    }
+
+    /// Get the pages old enough to evict, in eviction order
+    ///
+    /// Pages in DAX mappings seen by [`sync_maps`](Self::sync_maps) are
+    /// never candidates, and there are none while the last scan is
+    /// rejected.
+    pub fn candidates(&self) -> Vec<EvictionCandidate> {
+        if self.rejected {
+            return Vec::new();
+        }
+        if let Some(blocks) = &self.blocks {
+            let policy = AgingPolicy {
+                max_swap_bytes: None,
+                ..self.policy
+            };
+            let mut candidates = Vec::new();
+            for range in blocks.cold_ranges(&policy) {
+                candidates.extend(self.block_pages(&[range]).map(|address| EvictionCandidate {
+                    address,
+                    page_type: ProcIdlePageType::PteIdle,
+                    idle_scans: blocks.age_of(address),
+                    run_bytes: range.size(),
+                }));
+            }
+            self.order.sort(&mut candidates);
+            return candidates;
+        }
+
+        let mut candidates: Vec<EvictionCandidate> = self
+            .ages
+            .iter()
+            .filter(|(address, age)| {
+                age.idle_scans >= self.policy.min_idle_scans && !self.dax.contains(**address)
+            })
+            .map(|(&address, age)| EvictionCandidate {
+                address,
+                page_type: age.page_type,
+                idle_scans: age.idle_scans,
+                run_bytes: 0,
+            })
+            .collect();
+        candidates.sort_by_key(|c| c.address);
+
+        // Measure runs of address-contiguous candidates
+        let mut start = 0;
+        while start < candidates.len() {
+            let mut end = start + 1;
+            while end < candidates.len()
+                && candidates[end - 1].address + candidates[end - 1].size()
+                    == candidates[end].address
+            {
+                end += 1;
+            }
+            let run_bytes = candidates[start..end].iter().map(|c| c.size()).sum();
+            for candidate in &mut candidates[start..end] {
+                candidate.run_bytes = run_bytes;
+            }
+            start = end;
+        }
+
+        self.order.sort(&mut candidates);
+        candidates
+    }
+
+    /// Base pages of cold block ranges outside DAX mappings
+    fn block_pages<'a>(&'a self, ranges: &'a [AddressRange]) -> impl Iterator<Item = u64> + 'a {
+        ranges
+            .iter()
+            .flat_map(|range| (range.start..range.end).step_by(BASE_PAGE_SIZE as usize))
+            .filter(|&address| !self.dax.contains(address))
+    }
+
+    /// Select cold pages for reclaim, consulting the attached guard
+    ///
+    /// Without a guard this is [`cold_pages`](Self::cold_pages). A
+    /// `Throttle` decision delays the selection; a `Refuse` decision
+    /// selects nothing and queues a [`PolicyEvent::ReclaimRefused`].
+    ///
+    /// # Errors
+    /// Returns error if the guard cannot capture the system state.
+    pub fn reclaim_pages(&mut self) -> Result<Vec<IdlePageInfo>> {
+        let decision = match self.guard.as_mut() {
+            Some(guard) => guard.check()?,
+            None => GuardDecision::Allow,
+        };
+        Ok(self.select_after(decision))
+    }
+
+    /// Select cold pages for reclaim, evaluating the attached guard
+    /// against a snapshot taken at `now`
+    pub fn reclaim_pages_with(
+        &mut self,
+        snapshot: &PressureSnapshot,
+        now: Instant,
+    ) -> Vec<IdlePageInfo> {
+        let decision = match self.guard.as_mut() {
+            Some(guard) => guard.evaluate(snapshot, now),
+            None => GuardDecision::Allow,
+        };
+        self.select_after(decision)
+    }
+
+    /// Select cold pages according to a guard decision
+    fn select_after(&mut self, decision: GuardDecision) -> Vec<IdlePageInfo> {
+        match decision {
+            GuardDecision::Allow => self.cold_pages(),
+            GuardDecision::Throttle(delay) => {
+                std::thread::sleep(delay);
+                self.cold_pages()
+            }
+            GuardDecision::Refuse(reason) => {
+                let event = PolicyEvent::ReclaimRefused { reason };
+                log::debug!("{}", event);
+                self.events.push(event);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Total bytes that `cold_pages` would select
+    pub fn cold_bytes(&self) -> u64 {
+        self.cold_pages().iter().map(|p| p.total_size()).sum()
+    }
+
+    /// Stop tracking pages, e.g. after they have been swapped out
+    ///
+    /// With block aging, the blocks containing the pages start over.
+    pub fn forget(&mut self, pages: &[IdlePageInfo]) {
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.invalidate(&RangeSet::from(pages.to_vec()));
+            return;
+        }
+        for page in pages {
+            self.ages.remove(&page.address);
+        }
+    }
+
+    /// Drop the ages of the pages overlapping `ranges`
+    ///
+    /// Returns the number of pages dropped, or of 2MB blocks with block
+    /// aging.
+    pub fn invalidate(&mut self, ranges: &RangeSet) -> usize {
+        if let Some(blocks) = self.blocks.as_mut() {
+            return blocks.invalidate(ranges);
+        }
+        let ranges = ranges.ranges();
+        let before = self.ages.len();
+        self.ages.retain(|&addr, age| {
+            let end = addr + age.page_type.page_size();
+            let first = ranges.partition_point(|r| r.end <= addr);
+            ranges.get(first).is_none_or(|r| r.start >= end)
+        });
+        before - self.ages.len()
+    }
+
+    /// Record the maps of the target taken with a scan
+    ///
+    /// Call before [`observe`](Self::observe). If the address space changed
+    /// since the previous call, the ages of the changed ranges are dropped
+    /// and a [`PolicyEvent::MapsChanged`] is queued. Pages of DAX mappings
+    /// are left out of the candidates from then on. Returns whether the
+    /// address space changed.
+    pub fn sync_maps(&mut self, vma_map: &VmaMap) -> bool {
+        self.dax = vma_map.dax_ranges();
+        if let Some(blocks) = self.blocks.as_mut() {
+            let untracked = blocks.sync_vmas(vma_map);
+            if untracked > 0 {
+                log::debug!("{} regions not tracked: aging map limit reached", untracked);
+            }
+        }
+        let Some(change) = self.maps.observe(vma_map.clone()) else {
+            return false;
+        };
+        let invalidated = self.invalidate(&change.changed);
+        let event = PolicyEvent::MapsChanged {
+            change,
+            invalidated,
+        };
+        log::debug!("{}", event);
+        self.events.push(event);
+        true
+    }
+
+    /// Get the address-space generation of the target
+    pub fn maps_generation(&self) -> u64 {
+        self.maps.generation()
+    }
+
+    /// Take the notifications raised since the previous call
+    pub fn take_events(&mut self) -> Vec<PolicyEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Clear all tracked ages
+    pub fn reset(&mut self) {
+        self.ages.clear();
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.reset();
+        }
+        self.scans = 0;
+        self.maps.reset();
+        self.rejected = false;
+    }
+}
+
+impl Default for PageAger {
+    fn default() -> Self {
+        Self::new(AgingPolicy::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AddressRange;
+
+    #[test]
+    fn test_page_ager_consecutive_idle() {
+        let mut ager = PageAger::new(AgingPolicy::new().with_min_idle_scans(2));
+
+        let scan1 = [IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2)];
+        assert_eq!(ager.observe(&scan1), 2);
+        assert!(ager.cold_pages().is_empty());
+
+        // 0x1000 accessed, 0x2000 idle again
+        let scan2 = [
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteAccessed, 1),
+            IdlePageInfo::new(0x2000, ProcIdlePageType::PteIdle, 1),
+        ];
+        ager.observe(&scan2);
+        assert_eq!(ager.scans(), 2);
+        assert_eq!(ager.age_of(0x1000), 0);
+        assert_eq!(ager.age_of(0x2000), 2);
+
+        let cold = ager.cold_pages();
+        assert_eq!(cold.len(), 1);
+        assert_eq!(cold[0].address, 0x2000);
+
+        ager.forget(&cold);
+        assert_eq!(ager.tracked(), 0);
+    }
+
+    #[test]
+    fn test_page_ager_sync_maps() {
+        use std::io::Write;
+
+        let maps = |lines: &[&str]| {
+            let mut file = tempfile::NamedTempFile::new().unwrap();
+            for line in lines {
+                writeln!(file, "{}", line).unwrap();
+            }
+            VmaMap::from_file(file.path(), 1).unwrap()
+        };
+        let heap = "00600000-00a00000 rw-p 00000000 00:00 0 [heap]";
+        let anon = "7f0000000000-7f0000400000 rw-p 00000000 00:00 0";
+
+        let mut ager = PageAger::new(AgingPolicy::new());
+        assert!(!ager.sync_maps(&maps(&[heap, anon])));
+        let pages = [
+            IdlePageInfo::new(0x600000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x7f00_0000_0000, ProcIdlePageType::PmdIdle, 2),
+        ];
+        ager.observe(&pages);
+        assert!(!ager.sync_maps(&maps(&[heap, anon])));
+        assert!(ager.take_events().is_empty());
+
+        // The second huge page was unmapped and mapped again
+        let remapped = [
+            heap,
+            "7f0000000000-7f0000200000 rw-p 00000000 00:00 0",
+            "7f0000200000-7f0000400000 rw-s 00000000 00:05 7 /dev/zero",
+        ];
+        assert!(ager.sync_maps(&maps(&remapped)));
+        assert_eq!(ager.maps_generation(), 1);
+        let events = ager.take_events();
+        assert!(matches!(
+            &events[..],
+            [PolicyEvent::MapsChanged { change, invalidated: 1 }]
+                if change.changed.ranges() == [AddressRange::new(0x7f00_0020_0000, 0x7f00_0040_0000)]
+        ));
+        assert!(ager.take_events().is_empty());
+
+        ager.observe(&pages);
+        assert_eq!(ager.age_of(0x600000), 2);
+        assert_eq!(ager.age_of(0x601000), 2);
+        assert_eq!(ager.age_of(0x7f00_0000_0000), 2);
+        assert_eq!(ager.age_of(0x7f00_0020_0000), 1);
+
+        // Pages of DAX mappings are aged but never selected
+        let dax = ["7f0000000000-7f0000400000 rw-s 00000000 00:06 300 /dev/dax0.0"];
+        assert!(ager.sync_maps(&maps(&dax)));
+        ager.observe(&pages);
+        ager.observe(&pages);
+        assert_eq!(ager.age_of(0x7f00_0000_0000), 2);
+        assert!(
+            ager.candidates()
+                .iter()
+                .all(|c| c.address < 0x7f00_0000_0000)
+        );
+    }
+
+    #[test]
+    fn test_page_ager_block_aging() {
+        use std::io::Write;
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "00600000-00a00000 rw-p 00000000 00:00 0 [heap]").unwrap();
+        let maps = VmaMap::from_file(file.path(), 1).unwrap();
+
+        let mut ager = PageAger::new(AgingPolicy::new().with_min_idle_scans(2))
+            .with_block_aging(AgingMap::new(4));
+        let scan = [IdlePageInfo::new(0x600000, ProcIdlePageType::PteIdle, 2)];
+        for _ in 0..2 {
+            ager.sync_maps(&maps);
+            assert_eq!(ager.observe(&scan), 1);
+        }
+        assert_eq!(ager.tracked(), 2);
+        assert_eq!(ager.age_of(0x7ff000), 2);
+        assert_eq!(ager.age_of(0x800000), 0);
+
+        // The whole idle block is handed out as base pages
+        let cold = ager.cold_pages();
+        assert_eq!(cold.len(), 512);
+        assert_eq!(
+            cold[0],
+            IdlePageInfo::new(0x600000, ProcIdlePageType::PteIdle, 1)
+        );
+        assert_eq!(ager.candidates().len(), 512);
+        assert!(ager.candidates().iter().all(|c| c.run_bytes == 0x200000));
+
+        ager.forget(&cold[..1]);
+        assert_eq!(ager.age_of(0x600000), 0);
+        assert!(ager.cold_pages().is_empty());
+    }
+
+    #[test]
+    fn test_page_ager_max_bytes() {
+        let policy = AgingPolicy::new()
+            .with_min_idle_scans(1)
+            .with_max_swap_bytes(2 * 1024 * 1024 + 4096);
+        let mut ager = PageAger::new(policy);
+
+        ager.observe(&[
+            IdlePageInfo::new(0x200000, ProcIdlePageType::PmdIdle, 1),
+            IdlePageInfo::new(0x400000, ProcIdlePageType::PteIdle, 2),
+        ]);
+
+        let cold = ager.cold_pages();
+        assert_eq!(ager.cold_bytes(), 2 * 1024 * 1024 + 4096);
+        assert_eq!(cold.len(), 2);
+        assert_eq!(cold[0].address, 0x200000);
+        assert_eq!(cold[1].address, 0x400000);
+    }
+
+    #[test]
+    fn test_eviction_order() {
+        let mut ager = PageAger::new(AgingPolicy::new().with_min_idle_scans(1));
+        ager.observe(&[IdlePageInfo::new(0x5000, ProcIdlePageType::PteIdle, 1)]);
+        ager.observe(&[
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 3),
+            IdlePageInfo::new(0x5000, ProcIdlePageType::PteIdle, 1),
+            IdlePageInfo::new(0x9000, ProcIdlePageType::PteIdle, 1),
+        ]);
+        let addresses =
+            |ager: &PageAger| -> Vec<u64> { ager.cold_pages().iter().map(|p| p.address).collect() };
+
+        assert_eq!(ager.eviction_order().name(), "oldest-first");
+        assert_eq!(addresses(&ager), [0x5000, 0x1000, 0x2000, 0x3000, 0x9000]);
+
+        let ager = ager.with_eviction_order(LargestRunFirst);
+        let candidates = ager.candidates();
+        assert_eq!(candidates[0].run_bytes, 3 * 4096);
+        assert_eq!(addresses(&ager), [0x1000, 0x2000, 0x3000, 0x5000, 0x9000]);
+
+        let ager = ager.with_eviction_order(AddressOrder);
+        assert_eq!(addresses(&ager), [0x1000, 0x2000, 0x3000, 0x5000, 0x9000]);
+    }
+
+    #[test]
+    fn test_eviction_order_limits_selection() {
+        let policy = AgingPolicy::new()
+            .with_min_idle_scans(1)
+            .with_max_swap_bytes(4096);
+        let mut ager = PageAger::new(policy);
+        ager.observe(&[IdlePageInfo::new(0x8000, ProcIdlePageType::PteIdle, 1)]);
+        ager.observe(&[
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 1),
+            IdlePageInfo::new(0x8000, ProcIdlePageType::PteIdle, 1),
+        ]);
+
+        // The older page wins over the lower address
+        assert_eq!(
+            ager.cold_pages()[..],
+            [IdlePageInfo::new(0x8000, ProcIdlePageType::PteIdle, 1)]
+        );
+
+        let ager = ager.with_eviction_order(AddressOrder);
+        assert_eq!(ager.cold_pages()[0].address, 0x1000);
+    }
+
+    #[test]
+    fn test_page_ager_rejects_anomalous_scan() {
+        use crate::anomaly::Anomaly;
+
+        let mut ager = PageAger::new(AgingPolicy::new().with_min_idle_scans(1));
+        let pages = [IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 1)];
+        let clean = AnomalyReport::default();
+        assert_eq!(ager.observe_checked(&pages, &clean), Some(1));
+        assert_eq!(ager.cold_pages().len(), 1);
+
+        let report = AnomalyReport {
+            anomalies: vec![Anomaly::InvalidPageType {
+                address: Some(0x2000),
+                raw: 13,
+            }],
+            count: 1,
+            entries: 2,
+        };
+        assert_eq!(ager.observe_checked(&pages, &report), None);
+        assert!(ager.is_rejected());
+        assert_eq!(ager.scans(), 1);
+        assert_eq!(ager.age_of(0x1000), 1);
+        assert!(ager.cold_pages().is_empty());
+        assert!(matches!(
+            &ager.take_events()[..],
+            [PolicyEvent::ScanRejected {
+                max_anomalies: 0,
+                ..
+            }]
+        ));
+
+        // Tolerated anomalies, and the next accepted scan resumes reclaim
+        let mut ager = PageAger::new(
+            AgingPolicy::new()
+                .with_min_idle_scans(1)
+                .with_max_anomalies(1),
+        );
+        assert_eq!(ager.observe_checked(&pages, &report), Some(1));
+        let report = AnomalyReport { count: 2, ..report };
+        assert_eq!(ager.observe_checked(&pages, &report), None);
+        assert!(ager.cold_pages().is_empty());
+        assert_eq!(ager.observe_checked(&pages, &clean), Some(1));
+        assert!(!ager.is_rejected());
+        assert_eq!(ager.cold_pages().len(), 1);
+    }
+
+    #[test]
+    fn test_reclaim_pages_guard() {
+        use crate::guard::{GuardConfig, MemInfo, SwapDevice};
+
+        let snapshot = |swap_free_kb: u64| PressureSnapshot {
+            meminfo: MemInfo {
+                swap_total_kb: 1024,
+                swap_free_kb,
+                ..MemInfo::default()
+            },
+            swaps: vec![SwapDevice {
+                filename: "/dev/zram0".to_string(),
+                kind: "partition".to_string(),
+                size_kb: 1024,
+                used_kb: 1024 - swap_free_kb,
+                priority: 100,
+            }],
+            pswpin: 0,
+            psi_some_avg10: None,
+        };
+        let pages = [IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 1)];
+        let mut ager = PageAger::new(AgingPolicy::new().with_min_idle_scans(1))
+            .with_guard(SwapGuard::new(GuardConfig::new()));
+        ager.observe(&pages);
+
+        // Swap nearly full: nothing is selected and the refusal is reported
+        assert!(
+            ager.reclaim_pages_with(&snapshot(16), Instant::now())
+                .is_empty()
+        );
+        assert!(matches!(
+            &ager.take_events()[..],
+            [PolicyEvent::ReclaimRefused { .. }]
+        ));
+        assert_eq!(ager.tracked(), 1);
+
+        assert_eq!(
+            ager.reclaim_pages_with(&snapshot(512), Instant::now())
+                .len(),
+            1
+        );
+        assert!(ager.take_events().is_empty());
+    }
+
+    #[test]
+    fn test_aging_policy_min_scans() {
+        assert_eq!(AgingPolicy::new().with_min_idle_scans(0).min_idle_scans, 1);
+    }
+}
@@ -0,0 +1,302 @@
+//! Multi-process swap session pool
+//!
+//! This module provides `SwapPool`, which manages `/proc/[pid]/swap_pages`
+//! handles for many processes at once. Handles are opened lazily on first
+//! use, batched per process, and evicted automatically when the target
+//! process exits.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::{SwapConfig, SwapPool};
+//!
+//! let mut pool = SwapPool::new(SwapConfig::default());
+//!
+//! // Swap pages in two different processes
+//! pool.swap(1234, &[0x7fff0000, 0x7fff1000])
+//!     .expect("Failed to swap pages");
+//! pool.swap(5678, &[0x7ffe0000])
+//!     .expect("Failed to swap pages");
+//!
+//! // Drop handles for processes that have exited
+//! let evicted = pool.evict_dead();
+//! println!("Evicted {} dead processes", evicted.len());
+//! ```
+
+use std::collections::HashMap;
+
+use crate::error::{EtmemError, Result};
+use crate::guard::SwapGuard;
+use crate::swap::SwapSession;
+use crate::types::SwapConfig;
+
+/// Pool of swap sessions keyed by process ID
+///
+/// Each process gets at most one open `SwapSession`. Sessions are created
+/// on demand and removed when the process is found to be gone, so callers
+/// never have to track per-process handles or their Drop-time flushes.
+#[derive(Debug)]
+pub struct SwapPool {
+    /// Configuration applied to every session in the pool
+    config: SwapConfig,
+    /// Swap pressure guard cloned into every new session
+    guard: Option<SwapGuard>,
+    /// Open sessions by process ID
+    sessions: HashMap<u32, SwapSession>,
+}
+
+impl SwapPool {
+    /// Create an empty pool
+    ///
+    /// The configuration is validated when the first session is opened.
+    pub fn new(config: SwapConfig) -> Self {
+        Self {
+            config,
+            guard: None,
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Attach a swap pressure guard to every session opened by the pool
+    pub fn with_guard(mut self, guard: SwapGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Swap a set of addresses in a process
+    ///
+    /// Opens the process's swap handle if needed, queues the addresses
+    /// (flushing in batches of `max_pages`) and flushes the remainder.
+    /// Returns the number of addresses submitted to the kernel.
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process has exited; its session is
+    /// evicted from the pool. Other errors are returned as-is and leave
+    /// the session open.
+    pub fn swap(&mut self, pid: u32, addrs: &[u64]) -> Result<usize> {
+        if addrs.is_empty() {
+            return Ok(0);
+        }
+
+        let result = self.session(pid).and_then(|session| {
+            // Addresses auto-flushed by add_addresses are counted too
+            let before = session.submitted();
+            session.add_addresses(addrs)?;
+            session.flush()?;
+            Ok(session.submitted() - before)
+        });
+
+        self.check_result(pid, result)
+    }
+
+    /// Queue addresses for a process without flushing
+    ///
+    /// Addresses are written once the per-session batch reaches
+    /// `max_pages`, or when `flush` / `flush_all` is called.
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process has exited, or
+    /// `InvalidAddress` if an address is not page-aligned.
+    pub fn queue(&mut self, pid: u32, addrs: &[u64]) -> Result<()> {
+        let result = self
+            .session(pid)
+            .and_then(|session| session.add_addresses(addrs));
+        self.check_result(pid, result)
+    }
+
+    /// Flush pending addresses for a single process
+    ///
+    /// Returns 0 if the process has no open session.
+    pub fn flush(&mut self, pid: u32) -> Result<usize> {
+        let result = match self.sessions.get_mut(&pid) {
+            Some(session) => session.flush(),
+            None => return Ok(0),
+        };
+        self.check_result(pid, result)
+    }
+
+    /// Flush pending addresses for every process in the pool
+    ///
+    /// Dead processes are evicted along the way. The first non-eviction
+    /// error is returned after all sessions have been attempted.
+    pub fn flush_all(&mut self) -> Result<usize> {
+        let mut total = 0;
+        let mut first_err = None;
+
+        for pid in self.pids() {
+            match self.flush(pid) {
+                Ok(count) => total += count,
+                Err(EtmemError::ProcessNotFound) => {}
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+
+        match first_err {
+            Some(e) => Err(e),
+            None => Ok(total),
+        }
+    }
+
+    /// Remove a process from the pool, discarding its pending addresses
+    ///
+    /// Returns `true` if the process had an open session.
+    pub fn evict(&mut self, pid: u32) -> bool {
+        match self.sessions.remove(&pid) {
+            Some(mut session) => {
+                // Avoid the Drop-time flush into a handle we are giving up on
+                session.clear_pending();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Evict every process that no longer exists
+    ///
+    /// Returns the evicted process IDs.
+    pub fn evict_dead(&mut self) -> Vec<u32> {
+        let dead: Vec<u32> = self
+            .sessions
+            .keys()
+            .copied()
+            .filter(|&pid| !process_exists(pid))
+            .collect();
+
+        for &pid in &dead {
+            self.evict(pid);
+        }
+
+        dead
+    }
+
+    /// Check if a process has an open session
+    pub fn contains(&self, pid: u32) -> bool {
+        self.sessions.contains_key(&pid)
+    }
+
+    /// Get the process IDs with open sessions
+    pub fn pids(&self) -> Vec<u32> {
+        self.sessions.keys().copied().collect()
+    }
+
+    /// Get the number of open sessions
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Check if the pool has no open sessions
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Get the number of pending addresses for a process
+    pub fn pending_count(&self, pid: u32) -> usize {
+        self.sessions
+            .get(&pid)
+            .map(|s| s.pending_count())
+            .unwrap_or(0)
+    }
+
+    /// Get the configuration applied to new sessions
+    pub fn config(&self) -> &SwapConfig {
+        &self.config
+    }
+
+    /// Get or open the session for a process
+    fn session(&mut self, pid: u32) -> Result<&mut SwapSession> {
+        if !self.sessions.contains_key(&pid) {
+            let mut session = SwapSession::new(pid, self.config.clone())?;
+            session.set_guard(self.guard.clone());
+            self.sessions.insert(pid, session);
+        }
+        Ok(self.sessions.get_mut(&pid).unwrap())
+    }
+
+    /// Evict the process if an operation failed because it has exited
+    fn check_result<T>(&mut self, pid: u32, result: Result<T>) -> Result<T> {
+        match result {
+            Err(EtmemError::ProcessNotFound) => {
+                self.evict(pid);
+                Err(EtmemError::ProcessNotFound)
+            }
+            Err(e) if pid != 0 && !process_exists(pid) => {
+                log::debug!("Evicting swap session for exited process {}: {}", pid, e);
+                self.evict(pid);
+                Err(EtmemError::ProcessNotFound)
+            }
+            other => other,
+        }
+    }
+}
+
+impl Default for SwapPool {
+    fn default() -> Self {
+        Self::new(SwapConfig::default())
+    }
+}
+
+impl Drop for SwapPool {
+    fn drop(&mut self) {
+        // Sessions of live processes flush on their own Drop
+        self.evict_dead();
+    }
+}
+
+/// Check whether a process still exists
+fn process_exists(pid: u32) -> bool {
+    std::path::Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PID that cannot belong to a live process (above PID_MAX_LIMIT)
+    const DEAD_PID: u32 = 0x7fff_ffff;
+
+    #[test]
+    fn test_swap_pool_default() {
+        let pool = SwapPool::default();
+        assert!(pool.is_empty());
+        assert_eq!(pool.len(), 0);
+        assert_eq!(pool.pending_count(1234), 0);
+    }
+
+    #[test]
+    fn test_swap_pool_empty_swap() {
+        let mut pool = SwapPool::default();
+        assert_eq!(pool.swap(DEAD_PID, &[]).unwrap(), 0);
+        assert!(!pool.contains(DEAD_PID));
+    }
+
+    #[test]
+    fn test_swap_pool_invalid_pid() {
+        let mut pool = SwapPool::default();
+        assert!(matches!(
+            pool.swap(0, &[0x1000]).unwrap_err(),
+            EtmemError::InvalidPid
+        ));
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_swap_pool_dead_pid() {
+        let mut pool = SwapPool::default();
+        assert!(matches!(
+            pool.swap(DEAD_PID, &[0x1000]).unwrap_err(),
+            EtmemError::ProcessNotFound
+        ));
+        assert!(!pool.contains(DEAD_PID));
+        assert!(pool.evict_dead().is_empty());
+    }
+
+    #[test]
+    fn test_swap_pool_flush_unknown_pid() {
+        let mut pool = SwapPool::default();
+        assert_eq!(pool.flush(1234).unwrap(), 0);
+        assert_eq!(pool.flush_all().unwrap(), 0);
+        assert!(!pool.evict(1234));
+    }
+}
@@ -0,0 +1,180 @@
+//! Host capability probe for ETMEM
+//!
+//! Reports whether this host can scan and swap with ETMEM before anything
+//! is attempted: the kernel release, whether the ETMEM modules are loaded
+//! (or built in), whether the procfs interfaces exist and whether the
+//! caller holds `CAP_SYS_ADMIN`, which every ETMEM operation requires.
+//!
+//! # Example
+//!
+//! ```no_run
+//! let probe = etmem_rs::probe();
+//! if !probe.can_scan() {
+//!     eprintln!("ETMEM scanning unavailable on {:?}", probe.kernel_release);
+//! }
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::sys::SYS_ETMEM_SWAP_ENABLE;
+
+/// Kernel modules providing ETMEM
+pub const ETMEM_MODULES: [&str; 2] = ["etmem_scan", "etmem_swap"];
+
+/// `CAP_SYS_ADMIN` bit in the capability sets of `/proc/<pid>/status`
+const CAP_SYS_ADMIN: u32 = 21;
+
+/// Load state of a kernel module
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleState {
+    /// Module name
+    pub name: String,
+    /// Whether the module is loaded or built into the kernel
+    pub loaded: bool,
+}
+
+/// ETMEM capabilities of the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EtmemProbe {
+    /// Kernel release (`uname -r`), if readable
+    pub kernel_release: Option<String>,
+    /// ETMEM modules and whether they are loaded
+    pub modules: Vec<ModuleState>,
+    /// Whether `/proc/<pid>/idle_pages` exists
+    pub idle_pages: bool,
+    /// Whether `/proc/<pid>/swap_pages` exists
+    pub swap_pages: bool,
+    /// Kernel swap switch, `None` if the kernel does not provide it
+    pub kernel_swap: Option<bool>,
+    /// Whether the caller holds `CAP_SYS_ADMIN`
+    pub cap_sys_admin: bool,
+}
+
+impl EtmemProbe {
+    /// Check whether idle page scanning can work
+    pub fn can_scan(&self) -> bool {
+        self.idle_pages && self.cap_sys_admin
+    }
+
+    /// Check whether page swapping can work
+    pub fn can_swap(&self) -> bool {
+        self.swap_pages && self.cap_sys_admin
+    }
+
+    /// Get the modules that are not loaded
+    pub fn missing_modules(&self) -> impl Iterator<Item = &str> {
+        self.modules
+            .iter()
+            .filter(|m| !m.loaded)
+            .map(|m| m.name.as_str())
+    }
+}
+
+/// Probe the ETMEM capabilities of this host
+pub fn probe() -> EtmemProbe {
+    probe_in(Path::new("/proc"), Path::new("/sys"))
+}
+
+/// Probe ETMEM capabilities under alternative procfs and sysfs roots
+///
+/// The calling process is looked up as `<proc_root>/self`.
+pub fn probe_in(proc_root: &Path, sys_root: &Path) -> EtmemProbe {
+    let loaded_modules = fs::read_to_string(proc_root.join("modules")).unwrap_or_default();
+    let modules = ETMEM_MODULES
+        .iter()
+        .map(|&name| ModuleState {
+            name: name.to_string(),
+            loaded: loaded_modules
+                .lines()
+                .any(|line| line.split_whitespace().next() == Some(name))
+                || sys_root.join("module").join(name).is_dir(),
+        })
+        .collect();
+
+    let swap_enable = SYS_ETMEM_SWAP_ENABLE
+        .strip_prefix("/sys/")
+        .unwrap_or(SYS_ETMEM_SWAP_ENABLE);
+    let kernel_swap = fs::read_to_string(sys_root.join(swap_enable))
+        .ok()
+        .map(|value| value.trim() == "1");
+
+    let this = proc_root.join("self");
+    EtmemProbe {
+        kernel_release: fs::read_to_string(proc_root.join("sys/kernel/osrelease"))
+            .ok()
+            .map(|release| release.trim().to_string()),
+        modules,
+        idle_pages: this.join("idle_pages").exists(),
+        swap_pages: this.join("swap_pages").exists(),
+        kernel_swap,
+        cap_sys_admin: fs::read_to_string(this.join("status"))
+            .ok()
+            .and_then(|status| effective_caps(&status))
+            .is_some_and(|caps| caps & (1 << CAP_SYS_ADMIN) != 0),
+    }
+}
+
+/// Extract the effective capability set from `/proc/<pid>/status`
+fn effective_caps(status: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("CapEff:"))
+        .and_then(|value| u64::from_str_radix(value.trim(), 16).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_effective_caps() {
+        let status = "Name:\tmemlink\nCapInh:\t0000000000000000\nCapEff:\t000001ffffffffff\n";
+        assert_eq!(effective_caps(status), Some(0x1ff_ffff_ffff));
+        assert_eq!(effective_caps("Name:\tmemlink\n"), None);
+    }
+
+    #[test]
+    fn test_probe_in() {
+        let proc_root = tempfile::tempdir().unwrap();
+        let sys_root = tempfile::tempdir().unwrap();
+        let this = proc_root.path().join("self");
+        fs::create_dir_all(&this).unwrap();
+        fs::create_dir_all(proc_root.path().join("sys/kernel")).unwrap();
+        fs::write(
+            proc_root.path().join("sys/kernel/osrelease"),
+            "5.10.0-etmem\n",
+        )
+        .unwrap();
+        fs::write(
+            proc_root.path().join("modules"),
+            "etmem_scan 40960 0 - Live 0x0000000000000000\n",
+        )
+        .unwrap();
+        fs::write(this.join("idle_pages"), "").unwrap();
+        fs::write(this.join("status"), "CapEff:\t0000000000200000\n").unwrap();
+
+        let probe = probe_in(proc_root.path(), sys_root.path());
+        assert_eq!(probe.kernel_release.as_deref(), Some("5.10.0-etmem"));
+        assert_eq!(probe.missing_modules().collect::<Vec<_>>(), ["etmem_swap"]);
+        assert!(probe.can_scan());
+        assert!(!probe.can_swap());
+        assert_eq!(probe.kernel_swap, None);
+
+        // Built-in modules only show up in sysfs
+        fs::create_dir_all(sys_root.path().join("module/etmem_swap")).unwrap();
+        fs::create_dir_all(sys_root.path().join("kernel/mm/swap")).unwrap();
+        fs::write(
+            sys_root.path().join("kernel/mm/swap/kernel_swap_enable"),
+            "1\n",
+        )
+        .unwrap();
+        fs::write(this.join("status"), "CapEff:\t0000000000000000\n").unwrap();
+        let probe = probe_in(proc_root.path(), sys_root.path());
+        assert_eq!(probe.missing_modules().count(), 0);
+        assert_eq!(probe.kernel_swap, Some(true));
+        assert!(!probe.can_scan());
+    }
+}
@@ -0,0 +1,427 @@
+//! PSI (Pressure Stall Information) integration
+//!
+//! This module parses memory pressure readings from `/proc/pressure/memory`
+//! and per-cgroup `memory.pressure` files, and exposes the kernel's
+//! poll-based trigger API so reclaim policies can react to actual memory
+//! stalls instead of fixed timers.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use std::time::Duration;
+//! use etmem_rs::psi::{PsiKind, PsiReading, PsiTrigger};
+//!
+//! // One-shot reading
+//! let reading = PsiReading::read_system().expect("PSI not available");
+//! println!("some avg10: {:.2}%", reading.some.avg10);
+//!
+//! // Wake up when tasks stall for 150ms within any 1s window
+//! let trigger = PsiTrigger::register_system(
+//!     PsiKind::Some,
+//!     Duration::from_millis(150),
+//!     Duration::from_secs(1),
+//! ).expect("Failed to register trigger");
+//!
+//! if trigger.wait(Some(Duration::from_secs(10))).expect("poll failed") {
+//!     println!("Memory pressure event");
+//! }
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{EtmemError, Result};
+
+/// Procfs path for system-wide memory pressure
+pub const PROC_PRESSURE_MEMORY: &str = "/proc/pressure/memory";
+
+/// Mount point of the unified (v2) cgroup hierarchy
+pub const CGROUP2_ROOT: &str = "/sys/fs/cgroup";
+
+/// Minimum trigger window accepted by the kernel
+pub const PSI_WINDOW_MIN: Duration = Duration::from_millis(500);
+
+/// Maximum trigger window accepted by the kernel
+pub const PSI_WINDOW_MAX: Duration = Duration::from_secs(10);
+
+/// PSI line kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PsiKind {
+    /// At least one task stalled on memory
+    Some,
+    /// All non-idle tasks stalled on memory simultaneously
+    Full,
+}
+
+impl PsiKind {
+    /// Get the keyword used in pressure files
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Some => "some",
+            Self::Full => "full",
+        }
+    }
+}
+
+/// A single `some` or `full` line of a pressure file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PsiLine {
+    /// Stall percentage averaged over 10 seconds
+    pub avg10: f64,
+    /// Stall percentage averaged over 60 seconds
+    pub avg60: f64,
+    /// Stall percentage averaged over 300 seconds
+    pub avg300: f64,
+    /// Cumulative stall time in microseconds
+    pub total_us: u64,
+}
+
+impl PsiLine {
+    /// Parse the fields of a pressure line (without the leading kind)
+    ///
+    /// Format: `avg10=0.00 avg60=0.00 avg300=0.00 total=0`
+    fn parse_fields(fields: &str) -> Result<Self> {
+        let mut line = Self::default();
+        for kv in fields.split_whitespace() {
+            let (key, value) = kv
+                .split_once('=')
+                .ok_or_else(|| EtmemError::ProcfsError(format!("Invalid PSI field: {}", kv)))?;
+            let invalid = || EtmemError::ProcfsError(format!("Invalid PSI value: {}", kv));
+            match key {
+                "avg10" => line.avg10 = value.parse().map_err(|_| invalid())?,
+                "avg60" => line.avg60 = value.parse().map_err(|_| invalid())?,
+                "avg300" => line.avg300 = value.parse().map_err(|_| invalid())?,
+                "total" => line.total_us = value.parse().map_err(|_| invalid())?,
+                _ => {}
+            }
+        }
+        Ok(line)
+    }
+}
+
+/// Parsed contents of a memory pressure file
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PsiReading {
+    /// Share of time at least one task stalled
+    pub some: PsiLine,
+    /// Share of time all tasks stalled (absent on some older kernels)
+    pub full: Option<PsiLine>,
+}
+
+impl PsiReading {
+    /// Read system-wide memory pressure
+    pub fn read_system() -> Result<Self> {
+        Self::read_from(PROC_PRESSURE_MEMORY)
+    }
+
+    /// Read memory pressure for a cgroup (path relative to the v2 root)
+    ///
+    /// # Example
+    /// ```no_run
+    /// use etmem_rs::psi::PsiReading;
+    ///
+    /// let reading = PsiReading::read_cgroup("/system.slice")
+    ///     .expect("Failed to read cgroup pressure");
+    /// ```
+    pub fn read_cgroup(cgroup: &str) -> Result<Self> {
+        Self::read_from(cgroup_pressure_path(cgroup))
+    }
+
+    /// Read memory pressure for the cgroup a process belongs to
+    pub fn read_for_pid(pid: u32) -> Result<Self> {
+        Self::read_cgroup(&cgroup_of_pid(pid)?)
+    }
+
+    /// Read and parse a pressure file
+    pub fn read_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => EtmemError::NotSupported,
+            _ => EtmemError::ProcfsError(format!("{}: {}", path.display(), e)),
+        })?;
+        Self::parse(&content)
+    }
+
+    /// Parse the contents of a pressure file
+    ///
+    /// # Errors
+    /// Returns error if the `some` line is missing or malformed.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut some = None;
+        let mut full = None;
+
+        for line in content.lines() {
+            if let Some(fields) = line.strip_prefix("some ") {
+                some = Some(PsiLine::parse_fields(fields)?);
+            } else if let Some(fields) = line.strip_prefix("full ") {
+                full = Some(PsiLine::parse_fields(fields)?);
+            }
+        }
+
+        Ok(Self {
+            some: some
+                .ok_or_else(|| EtmemError::ProcfsError("PSI 'some' line missing".to_string()))?,
+            full,
+        })
+    }
+
+    /// Get the line for a given kind
+    pub fn line(&self, kind: PsiKind) -> Option<&PsiLine> {
+        match kind {
+            PsiKind::Some => Some(&self.some),
+            PsiKind::Full => self.full.as_ref(),
+        }
+    }
+}
+
+/// Path of the `memory.pressure` file for a cgroup
+pub fn cgroup_pressure_path(cgroup: &str) -> PathBuf {
+    Path::new(CGROUP2_ROOT)
+        .join(cgroup.trim_start_matches('/'))
+        .join("memory.pressure")
+}
+
+/// Get the v2 cgroup path of a process from `/proc/[pid]/cgroup`
+///
+/// # Errors
+/// Returns `ProcessNotFound` if the process doesn't exist, or
+/// `NotSupported` if the process is not in a unified hierarchy.
+pub fn cgroup_of_pid(pid: u32) -> Result<String> {
+    let content =
+        std::fs::read_to_string(format!("/proc/{}/cgroup", pid)).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => EtmemError::ProcessNotFound,
+            _ => EtmemError::from(e),
+        })?;
+
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| path.trim().to_string())
+        .ok_or(EtmemError::NotSupported)
+}
+
+/// Registered PSI trigger
+///
+/// The kernel signals the trigger (`POLLPRI`) when the stall time of the
+/// selected kind exceeds `stall` within any `window`. The trigger is
+/// unregistered when this value is dropped.
+#[derive(Debug)]
+pub struct PsiTrigger {
+    /// Open pressure file holding the trigger registration
+    file: File,
+    /// Stall kind
+    kind: PsiKind,
+    /// Stall threshold within the window
+    stall: Duration,
+    /// Tracking window
+    window: Duration,
+}
+
+impl PsiTrigger {
+    /// Register a trigger on system-wide memory pressure
+    pub fn register_system(kind: PsiKind, stall: Duration, window: Duration) -> Result<Self> {
+        Self::register(PROC_PRESSURE_MEMORY, kind, stall, window)
+    }
+
+    /// Register a trigger on a cgroup's memory pressure
+    pub fn register_cgroup(
+        cgroup: &str,
+        kind: PsiKind,
+        stall: Duration,
+        window: Duration,
+    ) -> Result<Self> {
+        Self::register(cgroup_pressure_path(cgroup), kind, stall, window)
+    }
+
+    /// Register a trigger on an arbitrary pressure file
+    ///
+    /// # Errors
+    /// Returns `InvalidTrigger` if the window is outside 500ms-10s or the
+    /// stall threshold exceeds the window, and `NotSupported` if the file
+    /// does not exist.
+    pub fn register<P: AsRef<Path>>(
+        path: P,
+        kind: PsiKind,
+        stall: Duration,
+        window: Duration,
+    ) -> Result<Self> {
+        if window < PSI_WINDOW_MIN || window > PSI_WINDOW_MAX {
+            return Err(EtmemError::InvalidTrigger(format!(
+                "window {:?} outside {:?}-{:?}",
+                window, PSI_WINDOW_MIN, PSI_WINDOW_MAX
+            )));
+        }
+        if stall.is_zero() || stall > window {
+            return Err(EtmemError::InvalidTrigger(format!(
+                "stall {:?} must be non-zero and within window {:?}",
+                stall, window
+            )));
+        }
+
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => EtmemError::NotSupported,
+                _ => EtmemError::from(e),
+            })?;
+
+        file.write_all(trigger_spec(kind, stall, window).as_bytes())
+            .map_err(EtmemError::from)?;
+
+        Ok(Self {
+            file,
+            kind,
+            stall,
+            window,
+        })
+    }
+
+    /// Wait for the trigger to fire
+    ///
+    /// Returns `true` if the trigger fired, `false` on timeout. `None`
+    /// waits indefinitely.
+    ///
+    /// # Errors
+    /// Returns error if polling fails or the monitored cgroup was removed.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<bool> {
+        let timeout_ms = match timeout {
+            Some(t) => t.as_millis().min(i32::MAX as u128) as libc::c_int,
+            None => -1,
+        };
+        let mut pfd = libc::pollfd {
+            fd: self.file.as_raw_fd(),
+            events: libc::POLLPRI,
+            revents: 0,
+        };
+
+        loop {
+            let ret = unsafe { libc::poll(&mut pfd, 1, timeout_ms) };
+            if ret < 0 {
+                let err = std::io::Error::last_os_error();
+                if err.raw_os_error() == Some(libc::EINTR) {
+                    continue;
+                }
+                return Err(EtmemError::from(err));
+            }
+            if ret == 0 {
+                return Ok(false);
+            }
+            if pfd.revents & libc::POLLERR != 0 {
+                return Err(EtmemError::ProcfsError(
+                    "PSI event source is gone".to_string(),
+                ));
+            }
+            return Ok(pfd.revents & libc::POLLPRI != 0);
+        }
+    }
+
+    /// Get the stall kind
+    pub const fn kind(&self) -> PsiKind {
+        self.kind
+    }
+
+    /// Get the stall threshold
+    pub const fn stall(&self) -> Duration {
+        self.stall
+    }
+
+    /// Get the tracking window
+    pub const fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+impl AsRawFd for PsiTrigger {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+/// Format a trigger registration string (`<kind> <stall us> <window us>`)
+fn trigger_spec(kind: PsiKind, stall: Duration, window: Duration) -> String {
+    format!(
+        "{} {} {}",
+        kind.as_str(),
+        stall.as_micros(),
+        window.as_micros()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reading() {
+        let content = "some avg10=1.50 avg60=0.75 avg300=0.10 total=123456\n\
+                       full avg10=0.50 avg60=0.25 avg300=0.00 total=6543\n";
+        let reading = PsiReading::parse(content).unwrap();
+        assert!((reading.some.avg10 - 1.5).abs() < 0.001);
+        assert!((reading.some.avg60 - 0.75).abs() < 0.001);
+        assert_eq!(reading.some.total_us, 123456);
+
+        let full = reading.line(PsiKind::Full).unwrap();
+        assert!((full.avg10 - 0.5).abs() < 0.001);
+        assert_eq!(full.total_us, 6543);
+    }
+
+    #[test]
+    fn test_parse_reading_some_only() {
+        let reading =
+            PsiReading::parse("some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n").unwrap();
+        assert!(reading.full.is_none());
+        assert!(PsiReading::parse("").is_err());
+        assert!(PsiReading::parse("some avg10=abc\n").is_err());
+    }
+
+    #[test]
+    fn test_cgroup_pressure_path() {
+        assert_eq!(
+            cgroup_pressure_path("/system.slice/foo.service"),
+            PathBuf::from("/sys/fs/cgroup/system.slice/foo.service/memory.pressure")
+        );
+        assert_eq!(
+            cgroup_pressure_path("/"),
+            PathBuf::from("/sys/fs/cgroup/memory.pressure")
+        );
+    }
+
+    #[test]
+    fn test_trigger_spec() {
+        let spec = trigger_spec(
+            PsiKind::Some,
+            Duration::from_millis(150),
+            Duration::from_secs(1),
+        );
+        assert_eq!(spec, "some 150000 1000000");
+    }
+
+    #[test]
+    fn test_trigger_validation() {
+        let invalid_window = PsiTrigger::register_system(
+            PsiKind::Some,
+            Duration::from_millis(100),
+            Duration::from_millis(100),
+        );
+        assert!(matches!(
+            invalid_window.unwrap_err(),
+            EtmemError::InvalidTrigger(_)
+        ));
+
+        let stall_too_long = PsiTrigger::register_system(
+            PsiKind::Full,
+            Duration::from_secs(2),
+            Duration::from_secs(1),
+        );
+        assert!(matches!(
+            stall_too_long.unwrap_err(),
+            EtmemError::InvalidTrigger(_)
+        ));
+    }
+}
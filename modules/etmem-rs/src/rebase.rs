@@ -0,0 +1,207 @@
+//! ASLR-stable addresses relative to their mapping
+//!
+//! Address space layout randomisation places the heap, the stack, shared
+//! libraries and anonymous mappings at different addresses on every run, so
+//! absolute addresses from two runs of the same binary cannot be compared.
+//! An [`AddressRebaser`] rewrites an address as the mapping containing it
+//! plus an offset:
+//!
+//! - Named mappings (files, `[heap]`, `[stack]`, `[vdso]`, named anonymous
+//!   mappings) are based on their name. Mappings sharing a name, like the
+//!   text and data segments of a shared library, share the start of the
+//!   lowest one, so offsets are relative to the load address:
+//!   `/usr/lib/libc.so.6+0x1a2000`.
+//! - Unnamed anonymous mappings are numbered in address order:
+//!   `[anonymous#3]+0x2000`. These stay comparable only as long as the
+//!   program creates the same mappings in the same order.
+//!
+//! Addresses outside every mapping are left absolute.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::rebase::AddressRebaser;
+//! use etmem_rs::{IdlePageScanner, ScanConfig, VmaMap};
+//!
+//! let pid = std::process::id() as u32;
+//! let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+//!     .expect("Failed to scan");
+//! let rebaser = AddressRebaser::new(&VmaMap::for_process(pid).expect("Failed to parse VMAs"));
+//! for page in pages.iter().filter(|p| p.is_idle()) {
+//!     println!("{}", rebaser.display(page.address));
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vma::{PathnameType, VmaMap};
+
+/// An address written relative to the mapping containing it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RebasedAddress {
+    /// Name of the mapping the offset is relative to
+    pub base: String,
+    /// Offset from the start of the base
+    pub offset: u64,
+}
+
+impl fmt::Display for RebasedAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}+{:#x}", self.base, self.offset)
+    }
+}
+
+/// One mapping and the base its addresses are relative to
+#[derive(Debug, Clone)]
+struct Span {
+    /// Start address of the mapping
+    start: u64,
+    /// End address of the mapping (exclusive)
+    end: u64,
+    /// Name of the base
+    base: String,
+    /// Address of the base
+    base_address: u64,
+}
+
+/// Rewriter of absolute addresses into mapping-relative ones
+#[derive(Debug, Clone, Default)]
+pub struct AddressRebaser {
+    /// Mappings in address order
+    spans: Vec<Span>,
+}
+
+impl AddressRebaser {
+    /// Build a rebaser from the maps of a process
+    ///
+    /// The maps should be read around the scan whose addresses are
+    /// rebased, as addresses in mappings created or removed in between
+    /// are rebased wrongly or not at all.
+    pub fn new(vma_map: &VmaMap) -> Self {
+        let regions = vma_map.regions();
+
+        // Named mappings are relative to the lowest mapping of their name
+        let mut lowest: HashMap<&str, u64> = HashMap::new();
+        for vma in regions.iter().filter(|v| v.pathname.is_some()) {
+            lowest.entry(vma.name()).or_insert(vma.start);
+        }
+
+        let mut anonymous = 0;
+        let spans = regions
+            .iter()
+            .map(|vma| {
+                let (base, base_address) = match vma.pathname {
+                    Some(_) => (vma.name().to_string(), lowest[vma.name()]),
+                    None if vma.pathname_type == PathnameType::Anonymous => {
+                        anonymous += 1;
+                        (format!("[anonymous#{}]", anonymous), vma.start)
+                    }
+                    None => (vma.name().to_string(), vma.start),
+                };
+                Span {
+                    start: vma.start,
+                    end: vma.end,
+                    base,
+                    base_address,
+                }
+            })
+            .collect();
+        Self { spans }
+    }
+
+    /// Rewrite an address relative to its mapping
+    ///
+    /// # Returns
+    /// `None` if the address is outside every mapping
+    pub fn rebase(&self, address: u64) -> Option<RebasedAddress> {
+        let idx = self.spans.partition_point(|s| s.end <= address);
+        let span = self.spans.get(idx).filter(|s| s.start <= address)?;
+        Some(RebasedAddress {
+            base: span.base.clone(),
+            offset: address - span.base_address,
+        })
+    }
+
+    /// Format an address relative to its mapping, or as absolute hex
+    /// (`0x...`) if it is outside every mapping
+    pub fn display(&self, address: u64) -> String {
+        match self.rebase(address) {
+            Some(rebased) => rebased.to_string(),
+            None => format!("{:#x}", address),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rebaser(maps: &str) -> AddressRebaser {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("maps");
+        std::fs::write(&path, maps).unwrap();
+        AddressRebaser::new(&VmaMap::from_file(&path, 42).unwrap())
+    }
+
+    #[test]
+    fn test_rebase() {
+        let rebaser = rebaser(
+            "\
+55d4c3a00000-55d4c3c00000 rw-p 00000000 00:00 0 [heap]
+7f0000000000-7f0000100000 rw-p 00000000 00:00 0
+7f0000100000-7f0000180000 r-xp 00000000 08:01 200 /usr/lib/libfoo.so
+7f0000180000-7f0000190000 rw-p 00080000 08:01 200 /usr/lib/libfoo.so
+7f0000190000-7f00001a0000 rw-p 00000000 00:00 0
+",
+        );
+
+        assert_eq!(
+            rebaser.rebase(0x55d4_c3a0_1000),
+            Some(RebasedAddress {
+                base: "[heap]".to_string(),
+                offset: 0x1000
+            })
+        );
+        // Both segments of the library are relative to its load address
+        assert_eq!(
+            rebaser.display(0x7f00_0010_2000),
+            "/usr/lib/libfoo.so+0x2000"
+        );
+        assert_eq!(
+            rebaser.display(0x7f00_0018_3000),
+            "/usr/lib/libfoo.so+0x83000"
+        );
+        assert_eq!(rebaser.display(0x7f00_0000_0000), "[anonymous#1]+0x0");
+        assert_eq!(rebaser.display(0x7f00_0019_f000), "[anonymous#2]+0xf000");
+        assert_eq!(rebaser.rebase(0x1000), None);
+        assert_eq!(rebaser.display(0x7f00_001a_0000), "0x7f00001a0000");
+    }
+
+    #[test]
+    fn test_rebase_is_layout_independent() {
+        // The same program under two ASLR layouts
+        let first = rebaser(
+            "\
+5600000000-5600200000 rw-p 00000000 00:00 0 [heap]
+7f1000000000-7f1000080000 r-xp 00000000 08:01 200 /usr/lib/libfoo.so
+",
+        );
+        let second = rebaser(
+            "\
+5612340000-5612540000 rw-p 00000000 00:00 0 [heap]
+7fab00000000-7fab00080000 r-xp 00000000 08:01 200 /usr/lib/libfoo.so
+",
+        );
+        assert_eq!(
+            first.rebase(0x56_0000_0000 + 0xff000),
+            second.rebase(0x56_1234_0000 + 0xff000)
+        );
+        assert_eq!(
+            first.rebase(0x7f10_0001_0000),
+            second.rebase(0x7fab_0001_0000)
+        );
+    }
+}
@@ -0,0 +1,458 @@
+//! Per-region reporting of scan results
+//!
+//! This module joins idle page scan output with the process's
+//! `/proc/[pid]/maps` entries, producing per-mapping statistics such as
+//! "libfoo.so has 1.2GB idle" instead of raw address lists.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::report::RegionReport;
+//! use etmem_rs::{IdlePageScanner, ScanConfig, VmaMap};
+//!
+//! let pid = std::process::id() as u32;
+//! let vma_map = VmaMap::for_process(pid).expect("Failed to parse VMAs");
+//! let pages = IdlePageScanner::scan_process(pid, ScanConfig::default())
+//!     .expect("Failed to scan");
+//!
+//! let report = RegionReport::build(&vma_map, &pages);
+//! for region in report.by_name().iter().take(5) {
+//!     println!("{}: {} idle bytes", region.name, region.idle_bytes);
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::dax::DaxKind;
+use crate::error::{EtmemError, Result};
+use crate::types::{AddressRange, IdlePageInfo, ProcIdlePageType};
+use crate::util::format_bytes;
+use crate::vma::{PathnameType, VmaMap, VmaRegion};
+
+/// Scan statistics for a single mapping (or group of mappings)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionStats {
+    /// Mapping name (path, `[heap]`, `[stack]`, `[anonymous]`, ...)
+    pub name: String,
+    /// Pathname type of the mapping
+    pub pathname_type: PathnameType,
+    /// Whether the mapping is backed by a regular file's page cache
+    ///
+    /// Idle file pages can be dropped or written back without swap I/O,
+    /// making them cheaper to reclaim than anonymous memory.
+    #[serde(default)]
+    pub file_backed: bool,
+    /// DAX kind of the mapping; its pages are never swapped
+    #[serde(default)]
+    pub dax: Option<DaxKind>,
+    /// Address ranges covered by this entry
+    pub ranges: Vec<AddressRange>,
+    /// Total virtual size of the mapping(s)
+    pub size_bytes: u64,
+    /// Idle (cold) bytes
+    pub idle_bytes: u64,
+    /// Accessed or dirty (hot) bytes
+    pub hot_bytes: u64,
+    /// Dirty bytes (subset of `hot_bytes`)
+    pub dirty_bytes: u64,
+    /// Unmapped bytes within the mapping (page table holes)
+    pub hole_bytes: u64,
+    /// Bytes swapped out (from `/proc/[pid]/smaps`, if attached)
+    #[serde(default)]
+    pub swapped_bytes: u64,
+}
+
+impl RegionStats {
+    /// Create empty statistics for a VMA
+    fn for_vma(vma: &VmaRegion) -> Self {
+        Self {
+            name: vma.name().to_string(),
+            pathname_type: vma.pathname_type,
+            file_backed: vma.is_file_backed(),
+            dax: vma.dax,
+            ranges: vec![vma.to_address_range()],
+            size_bytes: vma.size(),
+            idle_bytes: 0,
+            hot_bytes: 0,
+            dirty_bytes: 0,
+            hole_bytes: 0,
+            swapped_bytes: 0,
+        }
+    }
+
+    /// Account `bytes` of a page of the given type
+    fn add(&mut self, page_type: ProcIdlePageType, bytes: u64) {
+        match page_type {
+            t if t.is_idle() => self.idle_bytes += bytes,
+            t if t.is_hole() => self.hole_bytes += bytes,
+            ProcIdlePageType::PteDirty | ProcIdlePageType::PmdDirty => {
+                self.hot_bytes += bytes;
+                self.dirty_bytes += bytes;
+            }
+            t if t.is_accessed() => self.hot_bytes += bytes,
+            _ => {}
+        }
+    }
+
+    /// Merge another entry's statistics into this one
+    fn merge(&mut self, other: &Self) {
+        self.ranges.extend_from_slice(&other.ranges);
+        self.size_bytes += other.size_bytes;
+        self.idle_bytes += other.idle_bytes;
+        self.hot_bytes += other.hot_bytes;
+        self.dirty_bytes += other.dirty_bytes;
+        self.hole_bytes += other.hole_bytes;
+        self.swapped_bytes += other.swapped_bytes;
+    }
+
+    /// Get the number of bytes reported by the scan (idle + hot)
+    pub fn present_bytes(&self) -> u64 {
+        self.idle_bytes + self.hot_bytes
+    }
+
+    /// Calculate idle ratio of present memory (0.0 - 1.0)
+    pub fn idle_ratio(&self) -> f64 {
+        let present = self.present_bytes();
+        if present == 0 {
+            0.0
+        } else {
+            self.idle_bytes as f64 / present as f64
+        }
+    }
+}
+
+/// Scan results grouped by `/proc/[pid]/maps` entries
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RegionReport {
+    /// Process ID
+    pub pid: u32,
+    /// Per-VMA statistics in address order
+    pub regions: Vec<RegionStats>,
+    /// Scanned bytes that fell outside every VMA
+    pub unmapped_bytes: u64,
+}
+
+impl RegionReport {
+    /// Join scan output with a VMA map
+    ///
+    /// Scan entries spanning several VMAs are split at VMA boundaries.
+    pub fn build(vma_map: &VmaMap, pages: &[IdlePageInfo]) -> Self {
+        let vmas = vma_map.regions();
+        let mut regions: Vec<RegionStats> = vmas.iter().map(RegionStats::for_vma).collect();
+        let mut unmapped_bytes = 0;
+
+        for page in pages {
+            let range = AddressRange::new(page.address, page.end_address());
+            let mut attributed = 0;
+
+            let first = vmas.partition_point(|v| v.end <= range.start);
+            for (vma, stats) in vmas[first..].iter().zip(&mut regions[first..]) {
+                if vma.start >= range.end {
+                    break;
+                }
+                if let Some(overlap) = range.intersection(&vma.to_address_range()) {
+                    stats.add(page.page_type, overlap.size());
+                    attributed += overlap.size();
+                }
+            }
+
+            unmapped_bytes += range.size() - attributed;
+        }
+
+        Self {
+            pid: vma_map.pid(),
+            regions,
+            unmapped_bytes,
+        }
+    }
+
+    /// Attach per-mapping swap usage from `/proc/[pid]/smaps`
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process has exited, or a procfs
+    /// error if smaps cannot be read.
+    pub fn attach_swap(&mut self) -> Result<()> {
+        let path = format!("/proc/{}/smaps", self.pid);
+        let content = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => EtmemError::ProcessNotFound,
+            _ => EtmemError::ProcfsError(format!("{}: {}", path, e)),
+        })?;
+        self.apply_smaps(&content);
+        Ok(())
+    }
+
+    /// Attach swap usage from smaps content
+    ///
+    /// Mappings are matched by start address; entries without a matching
+    /// region are ignored.
+    pub fn apply_smaps(&mut self, content: &str) {
+        let mut current: Option<usize> = None;
+
+        for line in content.lines() {
+            if let Some(start) = smaps_header_start(line) {
+                current = self
+                    .regions
+                    .binary_search_by_key(&start, |r| r.ranges[0].start)
+                    .ok();
+                continue;
+            }
+
+            if let (Some(idx), Some(value)) = (current, line.strip_prefix("Swap:")) {
+                let kb: u64 = value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse()
+                    .unwrap_or(0);
+                self.regions[idx].swapped_bytes = kb * 1024;
+            }
+        }
+    }
+
+    /// Aggregate mappings sharing a name, sorted by idle bytes (descending)
+    ///
+    /// A shared library typically has several mappings (text, data, bss);
+    /// these are combined into one entry.
+    pub fn by_name(&self) -> Vec<RegionStats> {
+        let mut groups: HashMap<&str, RegionStats> = HashMap::new();
+        for region in &self.regions {
+            groups
+                .entry(region.name.as_str())
+                .and_modify(|g| g.merge(region))
+                .or_insert_with(|| region.clone());
+        }
+
+        let mut grouped: Vec<RegionStats> = groups.into_values().collect();
+        grouped.sort_by(|a, b| b.idle_bytes.cmp(&a.idle_bytes).then(a.name.cmp(&b.name)));
+        grouped
+    }
+
+    /// Get the `n` mappings with the most idle bytes
+    pub fn top_idle(&self, n: usize) -> Vec<&RegionStats> {
+        let mut sorted: Vec<&RegionStats> = self.regions.iter().collect();
+        sorted.sort_by_key(|r| std::cmp::Reverse(r.idle_bytes));
+        sorted.truncate(n);
+        sorted
+    }
+
+    /// Total idle bytes across all mappings
+    pub fn total_idle_bytes(&self) -> u64 {
+        self.regions.iter().map(|r| r.idle_bytes).sum()
+    }
+
+    /// Idle bytes split into `(anonymous, file-backed)`
+    ///
+    /// Anonymous covers everything reclaimed through swap, including heap,
+    /// stack and shared memory segments. DAX mappings are in neither; see
+    /// [`idle_dax_bytes`](Self::idle_dax_bytes).
+    pub fn idle_split(&self) -> (u64, u64) {
+        self.regions
+            .iter()
+            .filter(|r| r.dax.is_none())
+            .fold((0, 0), |(anon, file), r| {
+                if r.file_backed {
+                    (anon, file + r.idle_bytes)
+                } else {
+                    (anon + r.idle_bytes, file)
+                }
+            })
+    }
+
+    /// Idle bytes in DAX mappings, which cannot be reclaimed
+    pub fn idle_dax_bytes(&self) -> u64 {
+        self.regions
+            .iter()
+            .filter(|r| r.dax.is_some())
+            .map(|r| r.idle_bytes)
+            .sum()
+    }
+
+    /// Get file-backed mappings, aggregated by name
+    pub fn file_backed(&self) -> Vec<RegionStats> {
+        self.by_name()
+            .into_iter()
+            .filter(|r| r.file_backed)
+            .collect()
+    }
+
+    /// Total hot bytes across all mappings
+    pub fn total_hot_bytes(&self) -> u64 {
+        self.regions.iter().map(|r| r.hot_bytes).sum()
+    }
+
+    /// Total hole bytes across all mappings
+    pub fn total_hole_bytes(&self) -> u64 {
+        self.regions.iter().map(|r| r.hole_bytes).sum()
+    }
+
+    /// Total swapped bytes across all mappings
+    pub fn total_swapped_bytes(&self) -> u64 {
+        self.regions.iter().map(|r| r.swapped_bytes).sum()
+    }
+}
+
+/// Parse the start address of an smaps mapping header line
+pub(crate) fn smaps_header_start(line: &str) -> Option<u64> {
+    let range = line.split_whitespace().next()?;
+    let (start, end) = range.split_once('-')?;
+    let start = u64::from_str_radix(start, 16).ok()?;
+    u64::from_str_radix(end, 16).ok()?;
+    Some(start)
+}
+
+impl fmt::Display for RegionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Region report for PID {}:", self.pid)?;
+        writeln!(
+            f,
+            "  {:<40} {:>12} {:>12} {:>12} {:>7}",
+            "Mapping", "Idle", "Hot", "Holes", "Idle%"
+        )?;
+        for region in self.by_name() {
+            if region.present_bytes() == 0 && region.hole_bytes == 0 {
+                continue;
+            }
+            writeln!(
+                f,
+                "  {:<40} {:>12} {:>12} {:>12} {:>6.1}%",
+                region.name,
+                format_bytes(region.idle_bytes),
+                format_bytes(region.hot_bytes),
+                format_bytes(region.hole_bytes),
+                region.idle_ratio() * 100.0
+            )?;
+        }
+        let (anon, file) = self.idle_split();
+        writeln!(
+            f,
+            "  Idle anonymous: {}, idle file-backed: {}",
+            format_bytes(anon),
+            format_bytes(file)
+        )?;
+        let dax = self.idle_dax_bytes();
+        if dax > 0 {
+            writeln!(f, "  Idle DAX (never swapped): {}", format_bytes(dax))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_map() -> VmaMap {
+        let maps = "\
+00400000-00402000 r-xp 00000000 08:01 100 /usr/bin/app
+00600000-00800000 rw-p 00000000 00:00 0 [heap]
+7f0000000000-7f0000002000 r-xp 00000000 08:01 200 /usr/lib/libfoo.so
+7f0000002000-7f0000004000 rw-p 00002000 08:01 200 /usr/lib/libfoo.so
+";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("maps");
+        std::fs::write(&path, maps).unwrap();
+        VmaMap::from_file(&path, 42).unwrap()
+    }
+
+    #[test]
+    fn test_region_report_build() {
+        let map = test_map();
+        let pages = vec![
+            IdlePageInfo::new(0x400000, ProcIdlePageType::PteAccessed, 1),
+            IdlePageInfo::new(0x401000, ProcIdlePageType::PteIdle, 1),
+            IdlePageInfo::new(0x600000, ProcIdlePageType::PmdIdle, 1),
+            IdlePageInfo::new(0x7f0000000000, ProcIdlePageType::PteIdle, 3),
+            IdlePageInfo::new(0x7f0000003000, ProcIdlePageType::PteDirty, 1),
+            IdlePageInfo::new(0x900000, ProcIdlePageType::PteHole, 1),
+        ];
+
+        let report = RegionReport::build(&map, &pages);
+        assert_eq!(report.pid, 42);
+        assert_eq!(report.regions.len(), 4);
+
+        assert_eq!(report.regions[0].hot_bytes, 4096);
+        assert_eq!(report.regions[0].idle_bytes, 4096);
+        assert_eq!(report.regions[1].idle_bytes, 2 * 1024 * 1024);
+
+        // The 3-page idle run spans both libfoo mappings
+        assert_eq!(report.regions[2].idle_bytes, 2 * 4096);
+        assert_eq!(report.regions[3].idle_bytes, 4096);
+        assert_eq!(report.regions[3].dirty_bytes, 4096);
+        assert_eq!(report.regions[3].hot_bytes, 4096);
+
+        assert_eq!(report.unmapped_bytes, 4096);
+        assert_eq!(report.total_hole_bytes(), 0);
+
+        assert!(report.regions[0].file_backed);
+        assert!(!report.regions[1].file_backed);
+        let (anon, file) = report.idle_split();
+        assert_eq!(anon, 2 * 1024 * 1024);
+        assert_eq!(file, 4 * 4096);
+        assert_eq!(report.file_backed().len(), 2);
+    }
+
+    #[test]
+    fn test_region_report_dax() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("maps");
+        std::fs::write(
+            &path,
+            "00600000-00800000 rw-p 00000000 00:00 0 [heap]\n\
+             7f0000000000-7f0000400000 rw-s 00000000 00:06 300 /dev/dax0.0\n",
+        )
+        .unwrap();
+        let vma_map = VmaMap::from_file(&path, 42).unwrap();
+        let pages = [
+            IdlePageInfo::new(0x600000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x7f00_0000_0000, ProcIdlePageType::PmdIdle, 2),
+        ];
+
+        let report = RegionReport::build(&vma_map, &pages);
+        assert_eq!(report.regions[1].dax, Some(DaxKind::Device));
+        assert_eq!(report.idle_split(), (0x2000, 0));
+        assert_eq!(report.idle_dax_bytes(), 0x400000);
+        assert!(report.to_string().contains("Idle DAX (never swapped): 4"));
+    }
+
+    #[test]
+    fn test_region_report_by_name() {
+        let map = test_map();
+        let pages = vec![
+            IdlePageInfo::new(0x7f0000000000, ProcIdlePageType::PteIdle, 4),
+            IdlePageInfo::new(0x600000, ProcIdlePageType::PteIdle, 1),
+        ];
+
+        let report = RegionReport::build(&map, &pages);
+        let grouped = report.by_name();
+        assert_eq!(grouped.len(), 3);
+        assert_eq!(grouped[0].name, "/usr/lib/libfoo.so");
+        assert_eq!(grouped[0].idle_bytes, 4 * 4096);
+        assert_eq!(grouped[0].ranges.len(), 2);
+        assert_eq!(grouped[1].name, "[heap]");
+
+        let top = report.top_idle(1);
+        assert_eq!(top[0].name, "/usr/lib/libfoo.so");
+        assert!(report.to_string().contains("libfoo.so"));
+    }
+
+    #[test]
+    fn test_region_report_apply_smaps() {
+        let mut report = RegionReport::build(&test_map(), &[]);
+        let smaps = "\
+00600000-00800000 rw-p 00000000 00:00 0 [heap]
+Size:               2048 kB
+Swap:                512 kB
+7f0000002000-7f0000004000 rw-p 00002000 08:01 200 /usr/lib/libfoo.so
+Swap:                  8 kB
+7fff00000000-7fff00001000 rw-p 00000000 00:00 0
+Swap:                  4 kB
+";
+        report.apply_smaps(smaps);
+        assert_eq!(report.regions[1].swapped_bytes, 512 * 1024);
+        assert_eq!(report.regions[3].swapped_bytes, 8 * 1024);
+        assert_eq!(report.total_swapped_bytes(), 520 * 1024);
+    }
+}
@@ -0,0 +1,807 @@
+//! Sampled scans with extrapolated, confidence-rated statistics
+//!
+//! Walking every page table of a large process is expensive. A sampled scan
+//! reads a subset of fixed-size windows and extrapolates the idle and
+//! accessed totals of the whole address space from them, together with a
+//! standard error telling how far the estimate can be trusted.
+//!
+//! The address space is divided into strata, each sampled at its own
+//! density; the estimate is the sum of the per-stratum extrapolations
+//! (stratified cluster sampling). Which strata exist and how many windows
+//! each gets is decided by a [`SamplingStrategy`]:
+//!
+//! - [`Uniform`]: one window in every `n` of every mapping
+//! - [`Stratified`]: the same number of windows in every mapping, so that
+//!   small mappings are not drowned out by large ones
+//! - [`RssWeighted`]: a window budget shared in proportion to the resident
+//!   set of each mapping
+//! - [`Adaptive`]: uniform, plus every window around the hot/cold
+//!   boundaries found by the previous scan
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::sampling::Adaptive;
+//! use etmem_rs::{IdlePageScanner, ScanConfig};
+//!
+//! let mut strategy = Adaptive::new(16);
+//! for _ in 0..3 {
+//!     let scan = IdlePageScanner::scan_sampled(1234, ScanConfig::default(), &mut strategy)?;
+//!     println!("{}", scan.estimate);
+//!     std::thread::sleep(std::time::Duration::from_secs(30));
+//! }
+//! # Ok::<(), etmem_rs::EtmemError>(())
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::error::{EtmemError, Result};
+use crate::types::{AddressRange, BASE_PAGE_SIZE, HUGE_PAGE_SIZE, IdlePageInfo};
+use crate::util::{IdlePageStats, format_bytes};
+use crate::vma::VmaRegion;
+
+/// Default sample window size
+///
+/// One huge page, so that huge pages never straddle two windows.
+pub const DEFAULT_WINDOW_SIZE: u64 = HUGE_PAGE_SIZE;
+
+/// z-score of a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// Part of the address space sampled at one density
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stratum {
+    /// Range covered by the stratum
+    pub range: AddressRange,
+    /// Number of window-sized slots the range touches
+    pub slots: u64,
+    /// Windows to scan, in address order
+    pub windows: Vec<AddressRange>,
+}
+
+/// Windows chosen by a [`SamplingStrategy`]
+///
+/// The address space is cut into window-sized slots aligned to the window
+/// size. Each stratum scans an evenly spaced subset of its slots, clipped
+/// to its range.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SamplePlan {
+    /// Size of one window in bytes
+    window_size: u64,
+    /// Strata in the order they were added
+    strata: Vec<Stratum>,
+}
+
+impl SamplePlan {
+    /// Create an empty plan with windows of `window_size` bytes
+    ///
+    /// The size is rounded up to whole base pages.
+    pub fn new(window_size: u64) -> Self {
+        Self {
+            window_size: window_size.max(1).next_multiple_of(BASE_PAGE_SIZE),
+            strata: Vec::new(),
+        }
+    }
+
+    /// Get the window size in bytes
+    pub fn window_size(&self) -> u64 {
+        self.window_size
+    }
+
+    /// Add a stratum scanning `windows` evenly spaced slots of `range`
+    ///
+    /// The count is capped at the slots of the range. A stratum without
+    /// windows is assumed to hold no pages. Strata must not share slots, so
+    /// ranges adjoining within one slot belong in the same stratum.
+    pub fn add_stratum(&mut self, range: AddressRange, windows: u64) {
+        if !range.is_valid() {
+            return;
+        }
+        let first = range.start / self.window_size;
+        let slots = (range.end - 1) / self.window_size - first + 1;
+        let count = windows.min(slots);
+
+        let windows = (0..count)
+            .filter_map(|i| {
+                // Centre of the i-th of `count` equal shares of the slots
+                let slot = first + (2 * i + 1) * slots / (2 * count);
+                let window = AddressRange::with_size(slot * self.window_size, self.window_size);
+                window.intersection(&range)
+            })
+            .collect();
+
+        self.strata.push(Stratum {
+            range,
+            slots,
+            windows,
+        });
+    }
+
+    /// Get the strata
+    pub fn strata(&self) -> &[Stratum] {
+        &self.strata
+    }
+
+    /// Iterate over the windows with the index of their stratum
+    pub fn windows(&self) -> impl Iterator<Item = (usize, AddressRange)> + '_ {
+        self.strata
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, s)| s.windows.iter().map(move |w| (idx, *w)))
+    }
+
+    /// Get the number of windows to scan
+    pub fn window_count(&self) -> usize {
+        self.strata.iter().map(|s| s.windows.len()).sum()
+    }
+
+    /// Bytes of address space covered by the strata
+    pub fn population_bytes(&self) -> u64 {
+        self.strata.iter().map(|s| s.range.size()).sum()
+    }
+
+    /// Bytes of address space scanned
+    pub fn sampled_bytes(&self) -> u64 {
+        self.windows().map(|(_, w)| w.size()).sum()
+    }
+}
+
+/// Statistics of one scanned window
+#[derive(Debug, Clone, Copy)]
+pub struct WindowSample {
+    /// Index of the window's stratum in the plan
+    pub stratum: usize,
+    /// Range scanned
+    pub range: AddressRange,
+    /// Pages found in the range
+    pub stats: IdlePageStats,
+}
+
+/// Statistics extrapolated from sampled windows
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EstimatedStats {
+    /// Estimated statistics of the whole population
+    pub stats: IdlePageStats,
+    /// Bytes of address space the estimate covers
+    pub population_bytes: u64,
+    /// Bytes of address space scanned
+    pub sampled_bytes: u64,
+    /// Standard error of `stats.idle_bytes`
+    pub idle_bytes_stderr: f64,
+}
+
+impl EstimatedStats {
+    /// Extrapolate the windows scanned for a plan
+    ///
+    /// Each stratum's totals are scaled by its slots over its scanned
+    /// windows. The standard error treats the evenly spaced windows as a
+    /// simple random sample of the slots, with the finite population
+    /// correction. A stratum sampled by a single window has no observed
+    /// variance and is assigned the largest one possible, half a window.
+    pub fn from_windows(plan: &SamplePlan, windows: &[WindowSample]) -> Self {
+        let mut estimate = Self {
+            population_bytes: plan.population_bytes(),
+            ..Self::default()
+        };
+        let mut variance = 0.0;
+
+        for (idx, stratum) in plan.strata().iter().enumerate() {
+            let samples: Vec<&WindowSample> = windows.iter().filter(|w| w.stratum == idx).collect();
+            if samples.is_empty() {
+                continue;
+            }
+
+            let n = samples.len() as f64;
+            let slots = stratum.slots as f64;
+            let mut sum = IdlePageStats::default();
+            for sample in &samples {
+                estimate.sampled_bytes += sample.range.size();
+                add_stats(&mut sum, &sample.stats);
+            }
+            add_stats(&mut estimate.stats, &scale_stats(&sum, slots / n));
+
+            if n < slots {
+                let s2 = if samples.len() >= 2 {
+                    let mean = sum.idle_bytes as f64 / n;
+                    samples
+                        .iter()
+                        .map(|s| (s.stats.idle_bytes as f64 - mean).powi(2))
+                        .sum::<f64>()
+                        / (n - 1.0)
+                } else {
+                    (plan.window_size() as f64 / 2.0).powi(2)
+                };
+                variance += slots * slots * (1.0 - n / slots) * s2 / n;
+            }
+        }
+
+        estimate.idle_bytes_stderr = variance.sqrt();
+        estimate
+    }
+
+    /// Fraction of the population scanned (0.0 - 1.0)
+    pub fn coverage(&self) -> f64 {
+        if self.population_bytes == 0 {
+            1.0
+        } else {
+            self.sampled_bytes as f64 / self.population_bytes as f64
+        }
+    }
+
+    /// Check whether every window was scanned, making the stats exact
+    pub fn is_exact(&self) -> bool {
+        self.idle_bytes_stderr == 0.0 && self.sampled_bytes >= self.population_bytes
+    }
+
+    /// Half-width of the 95% confidence interval of the idle bytes
+    pub fn idle_bytes_margin(&self) -> u64 {
+        (Z_95 * self.idle_bytes_stderr).round() as u64
+    }
+
+    /// 95% confidence interval of the idle bytes
+    pub fn idle_bytes_interval(&self) -> (u64, u64) {
+        let margin = self.idle_bytes_margin();
+        (
+            self.stats.idle_bytes.saturating_sub(margin),
+            (self.stats.idle_bytes + margin).min(self.stats.total_bytes),
+        )
+    }
+
+    /// 95% confidence interval of the idle ratio
+    ///
+    /// Treats the estimated total bytes as exact.
+    pub fn idle_ratio_interval(&self) -> (f64, f64) {
+        if self.stats.total_bytes == 0 {
+            return (0.0, 0.0);
+        }
+        let (low, high) = self.idle_bytes_interval();
+        let total = self.stats.total_bytes as f64;
+        (low as f64 / total, high as f64 / total)
+    }
+}
+
+impl fmt::Display for EstimatedStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (±{} idle at 95% confidence, {:.1}% sampled)",
+            self.stats,
+            format_bytes(self.idle_bytes_margin()),
+            self.coverage() * 100.0
+        )
+    }
+}
+
+/// Add the counters of `other` to `stats`
+fn add_stats(stats: &mut IdlePageStats, other: &IdlePageStats) {
+    stats.total_pages += other.total_pages;
+    stats.idle_pages += other.idle_pages;
+    stats.accessed_pages += other.accessed_pages;
+    stats.huge_pages += other.huge_pages;
+    stats.total_bytes += other.total_bytes;
+    stats.idle_bytes += other.idle_bytes;
+    stats.accessed_bytes += other.accessed_bytes;
+}
+
+/// Multiply the counters of `stats` by `factor`
+fn scale_stats(stats: &IdlePageStats, factor: f64) -> IdlePageStats {
+    let pages = |n: usize| (n as f64 * factor).round() as usize;
+    let bytes = |n: u64| (n as f64 * factor).round() as u64;
+    IdlePageStats {
+        total_pages: pages(stats.total_pages),
+        idle_pages: pages(stats.idle_pages),
+        accessed_pages: pages(stats.accessed_pages),
+        huge_pages: pages(stats.huge_pages),
+        total_bytes: bytes(stats.total_bytes),
+        idle_bytes: bytes(stats.idle_bytes),
+        accessed_bytes: bytes(stats.accessed_bytes),
+    }
+}
+
+/// Result of a sampled scan
+#[derive(Debug, Clone, Default)]
+pub struct SampledScan {
+    /// Pages found in the scanned windows
+    pub pages: Vec<IdlePageInfo>,
+    /// Statistics of each scanned window, in plan order
+    pub windows: Vec<WindowSample>,
+    /// Statistics extrapolated to the whole plan
+    pub estimate: EstimatedStats,
+}
+
+/// Strategy choosing which parts of an address space to sample
+///
+/// Used by [`IdlePageScanner::scan_sampled`](crate::scan::IdlePageScanner::scan_sampled),
+/// which plans each scan with [`plan`](Self::plan) and reports the result
+/// back through [`observe`](Self::observe).
+pub trait SamplingStrategy: fmt::Debug + Send {
+    /// Short name of the strategy
+    fn name(&self) -> &'static str;
+
+    /// Choose the windows to scan in the given mappings
+    fn plan(&self, regions: &[&VmaRegion]) -> SamplePlan;
+
+    /// Learn from a finished scan planned by this strategy
+    fn observe(&mut self, _scan: &SampledScan) {}
+}
+
+/// Slots of a range for a window size
+fn slot_count(range: AddressRange, window_size: u64) -> u64 {
+    if range.is_valid() {
+        (range.end - 1) / window_size - range.start / window_size + 1
+    } else {
+        0
+    }
+}
+
+/// One window in every `every` slots of every mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Uniform {
+    /// Slots per scanned window
+    every: u64,
+    /// Window size in bytes
+    window_size: u64,
+}
+
+impl Uniform {
+    /// Scan one window in every `every` (at least 1)
+    pub const fn new(every: u64) -> Self {
+        Self {
+            every: if every == 0 { 1 } else { every },
+            window_size: DEFAULT_WINDOW_SIZE,
+        }
+    }
+
+    /// Set the window size
+    pub const fn with_window_size(mut self, size: u64) -> Self {
+        self.window_size = size;
+        self
+    }
+}
+
+impl SamplingStrategy for Uniform {
+    fn name(&self) -> &'static str {
+        "uniform"
+    }
+
+    fn plan(&self, regions: &[&VmaRegion]) -> SamplePlan {
+        let mut plan = SamplePlan::new(self.window_size);
+        for region in regions {
+            let range = region.to_address_range();
+            let slots = slot_count(range, plan.window_size());
+            plan.add_stratum(range, slots.div_ceil(self.every));
+        }
+        plan
+    }
+}
+
+/// The same number of windows in every mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stratified {
+    /// Windows per mapping
+    per_region: u64,
+    /// Window size in bytes
+    window_size: u64,
+}
+
+impl Stratified {
+    /// Scan `per_region` windows of each mapping
+    pub const fn new(per_region: u64) -> Self {
+        Self {
+            per_region,
+            window_size: DEFAULT_WINDOW_SIZE,
+        }
+    }
+
+    /// Set the window size
+    pub const fn with_window_size(mut self, size: u64) -> Self {
+        self.window_size = size;
+        self
+    }
+}
+
+impl SamplingStrategy for Stratified {
+    fn name(&self) -> &'static str {
+        "stratified"
+    }
+
+    fn plan(&self, regions: &[&VmaRegion]) -> SamplePlan {
+        let mut plan = SamplePlan::new(self.window_size);
+        for region in regions {
+            plan.add_stratum(region.to_address_range(), self.per_region);
+        }
+        plan
+    }
+}
+
+/// A window budget shared in proportion to resident set sizes
+///
+/// Mappings with resident memory get at least one window; mappings
+/// without any are not scanned and count as holding no pages. Mappings
+/// missing from the RSS snapshot, such as ones created since, get one
+/// window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RssWeighted {
+    /// Total windows to scan
+    budget: u64,
+    /// Resident bytes by mapping start address
+    rss: HashMap<u64, u64>,
+    /// Window size in bytes
+    window_size: u64,
+}
+
+impl RssWeighted {
+    /// Weight by the current resident set of a process
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process has exited, or a procfs
+    /// error if `/proc/[pid]/smaps` cannot be read.
+    pub fn for_process(pid: u32, budget: u64) -> Result<Self> {
+        let path = format!("/proc/{}/smaps", pid);
+        let content = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => EtmemError::ProcessNotFound,
+            _ => EtmemError::ProcfsError(format!("{}: {}", path, e)),
+        })?;
+        Ok(Self::from_smaps(&content, budget))
+    }
+
+    /// Weight by the `Rss` lines of smaps content
+    pub fn from_smaps(content: &str, budget: u64) -> Self {
+        let mut rss = HashMap::new();
+        let mut current = None;
+        for line in content.lines() {
+            if let Some(start) = crate::report::smaps_header_start(line) {
+                current = Some(start);
+                continue;
+            }
+            if let (Some(start), Some(value)) = (current, line.strip_prefix("Rss:")) {
+                let kb: u64 = value
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse()
+                    .unwrap_or(0);
+                rss.insert(start, kb * 1024);
+            }
+        }
+        Self {
+            budget,
+            rss,
+            window_size: DEFAULT_WINDOW_SIZE,
+        }
+    }
+
+    /// Set the window size
+    pub const fn with_window_size(mut self, size: u64) -> Self {
+        self.window_size = size;
+        self
+    }
+
+    /// Get the resident bytes of the mapping starting at `start`
+    pub fn rss(&self, start: u64) -> Option<u64> {
+        self.rss.get(&start).copied()
+    }
+}
+
+impl SamplingStrategy for RssWeighted {
+    fn name(&self) -> &'static str {
+        "rss-weighted"
+    }
+
+    fn plan(&self, regions: &[&VmaRegion]) -> SamplePlan {
+        let total: u64 = regions.iter().filter_map(|r| self.rss(r.start)).sum();
+        let mut plan = SamplePlan::new(self.window_size);
+        for region in regions {
+            let windows = match self.rss(region.start) {
+                None => 1,
+                Some(0) => 0,
+                Some(rss) => {
+                    let share = self.budget as f64 * rss as f64 / total as f64;
+                    (share.round() as u64).max(1)
+                }
+            };
+            plan.add_stratum(region.to_address_range(), windows);
+        }
+        plan
+    }
+}
+
+/// Uniform sampling, dense around previously found hot/cold boundaries
+///
+/// After each scan, neighbouring windows whose idle ratios differ by at
+/// least the threshold mark a boundary somewhere between them. The next
+/// plan scans every slot from `radius` slots before such a span to
+/// `radius` slots after it, and one in `every` elsewhere, so boundaries
+/// are located more precisely with each scan.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Adaptive {
+    /// Slots per scanned window away from boundaries
+    every: u64,
+    /// Idle ratio difference marking a boundary
+    threshold: f64,
+    /// Slots scanned on each side of a boundary span
+    radius: u64,
+    /// Window size in bytes
+    window_size: u64,
+    /// Spans holding a boundary, from the last scan
+    boundaries: Vec<AddressRange>,
+}
+
+impl Adaptive {
+    /// Scan one window in every `every` (at least 1) away from boundaries
+    pub const fn new(every: u64) -> Self {
+        Self {
+            every: if every == 0 { 1 } else { every },
+            threshold: 0.5,
+            radius: 1,
+            window_size: DEFAULT_WINDOW_SIZE,
+            boundaries: Vec::new(),
+        }
+    }
+
+    /// Set the idle ratio difference marking a boundary (default 0.5)
+    pub const fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the slots scanned on each side of a boundary span (default 1)
+    pub const fn with_radius(mut self, radius: u64) -> Self {
+        self.radius = radius;
+        self
+    }
+
+    /// Set the window size
+    pub const fn with_window_size(mut self, size: u64) -> Self {
+        self.window_size = size;
+        self
+    }
+
+    /// Get the spans holding a boundary, from the last observed scan
+    pub fn boundaries(&self) -> &[AddressRange] {
+        &self.boundaries
+    }
+
+    /// Slot-aligned spans to scan densely, sorted and merged
+    fn dense_spans(&self, window_size: u64) -> Vec<AddressRange> {
+        let margin = self.radius.saturating_mul(window_size);
+        let mut spans: Vec<AddressRange> = self
+            .boundaries
+            .iter()
+            .map(|b| {
+                let start = (b.start / window_size * window_size).saturating_sub(margin);
+                let end = b.end.next_multiple_of(window_size).saturating_add(margin);
+                AddressRange::new(start, end)
+            })
+            .collect();
+        spans.sort_unstable_by_key(|s| s.start);
+
+        let mut merged: Vec<AddressRange> = Vec::with_capacity(spans.len());
+        for span in spans {
+            match merged.last_mut() {
+                Some(last) if span.start <= last.end => last.end = last.end.max(span.end),
+                _ => merged.push(span),
+            }
+        }
+        merged
+    }
+}
+
+impl SamplingStrategy for Adaptive {
+    fn name(&self) -> &'static str {
+        "adaptive"
+    }
+
+    fn plan(&self, regions: &[&VmaRegion]) -> SamplePlan {
+        let mut plan = SamplePlan::new(self.window_size);
+        let size = plan.window_size();
+        let dense = self.dense_spans(size);
+
+        for region in regions {
+            let range = region.to_address_range();
+            // Alternate sparse and dense pieces; dense spans are slot
+            // aligned, so the pieces never share a slot
+            let mut cursor = range.start;
+            for span in &dense {
+                let Some(piece) = span.intersection(&range) else {
+                    continue;
+                };
+                let sparse = AddressRange::new(cursor, piece.start);
+                plan.add_stratum(sparse, slot_count(sparse, size).div_ceil(self.every));
+                plan.add_stratum(piece, slot_count(piece, size));
+                cursor = piece.end;
+            }
+            let sparse = AddressRange::new(cursor, range.end);
+            plan.add_stratum(sparse, slot_count(sparse, size).div_ceil(self.every));
+        }
+        plan
+    }
+
+    fn observe(&mut self, scan: &SampledScan) {
+        let max_gap = self.every.saturating_mul(self.window_size);
+        let mut populated: Vec<&WindowSample> = scan
+            .windows
+            .iter()
+            .filter(|w| w.stats.total_bytes > 0)
+            .collect();
+        populated.sort_unstable_by_key(|w| w.range.start);
+
+        self.boundaries = populated
+            .windows(2)
+            .filter(|pair| {
+                let (a, b) = (pair[0], pair[1]);
+                b.range.start.saturating_sub(a.range.end) <= max_gap
+                    && (a.stats.idle_ratio() - b.stats.idle_ratio()).abs() >= self.threshold
+            })
+            .map(|pair| AddressRange::new(pair[0].range.start, pair[1].range.end))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ProcIdlePageType;
+    use crate::vma::VmaPermissions;
+
+    const WINDOW: u64 = 0x10000;
+
+    fn region(start: u64, end: u64) -> VmaRegion {
+        VmaRegion::new(start, end, VmaPermissions::default())
+    }
+
+    fn window(stratum: usize, start: u64, idle: u8, accessed: u8) -> WindowSample {
+        let mut pages = Vec::new();
+        if idle > 0 {
+            pages.push(IdlePageInfo::new(start, ProcIdlePageType::PteIdle, idle));
+        }
+        if accessed > 0 {
+            let addr = start + u64::from(idle) * BASE_PAGE_SIZE;
+            pages.push(IdlePageInfo::new(
+                addr,
+                ProcIdlePageType::PteAccessed,
+                accessed,
+            ));
+        }
+        WindowSample {
+            stratum,
+            range: AddressRange::with_size(start, WINDOW),
+            stats: IdlePageStats::from_pages(&pages),
+        }
+    }
+
+    #[test]
+    fn test_plan_spreads_windows() {
+        let mut plan = SamplePlan::new(WINDOW);
+        // Eight slots, the first and last clipped to the range
+        plan.add_stratum(AddressRange::new(0x8000, 0x78000), 2);
+        let stratum = &plan.strata()[0];
+        assert_eq!(stratum.slots, 8);
+        assert_eq!(
+            stratum.windows,
+            vec![
+                AddressRange::new(0x20000, 0x30000),
+                AddressRange::new(0x60000, 0x70000),
+            ]
+        );
+
+        plan.add_stratum(AddressRange::new(0x100000, 0x120000), 5);
+        assert_eq!(plan.strata()[1].windows.len(), 2);
+        plan.add_stratum(AddressRange::new(0x200000, 0x200000), 1);
+        assert_eq!(plan.strata().len(), 2);
+        assert_eq!(plan.window_count(), 4);
+        assert_eq!(plan.sampled_bytes(), 4 * WINDOW);
+        assert_eq!(plan.population_bytes(), 0x70000 + 0x20000);
+    }
+
+    #[test]
+    fn test_estimate_extrapolates() {
+        let mut plan = SamplePlan::new(WINDOW);
+        plan.add_stratum(AddressRange::with_size(0, 4 * WINDOW), 2);
+        plan.add_stratum(AddressRange::with_size(0x100000, WINDOW), 1);
+
+        let windows = [
+            window(0, 0x10000, 16, 0),
+            window(0, 0x30000, 0, 16),
+            window(1, 0x100000, 8, 8),
+        ];
+        let estimate = EstimatedStats::from_windows(&plan, &windows);
+        assert_eq!(estimate.stats.idle_bytes, 2 * WINDOW + WINDOW / 2);
+        assert_eq!(estimate.stats.accessed_bytes, 2 * WINDOW + WINDOW / 2);
+        assert_eq!(estimate.stats.total_pages, 80);
+        assert_eq!(estimate.sampled_bytes, 3 * WINDOW);
+        assert_eq!(estimate.population_bytes, 5 * WINDOW);
+        assert!(!estimate.is_exact());
+
+        // Only the half-sampled stratum contributes variance:
+        // 4^2 * (1 - 2/4) * s^2 / 2 with s^2 = 2 * (WINDOW / 2)^2
+        let expected = (8.0 * (WINDOW as f64 / 2.0).powi(2)).sqrt();
+        assert!((estimate.idle_bytes_stderr - expected).abs() < 1.0);
+        let (low, high) = estimate.idle_bytes_interval();
+        assert!(low < estimate.stats.idle_bytes && high <= estimate.stats.total_bytes);
+
+        // Scanning every slot is exact
+        let mut plan = SamplePlan::new(WINDOW);
+        plan.add_stratum(AddressRange::with_size(0x10000, 2 * WINDOW), 2);
+        let windows = [window(0, 0x10000, 16, 0), window(0, 0x20000, 0, 16)];
+        let estimate = EstimatedStats::from_windows(&plan, &windows);
+        assert!(estimate.is_exact());
+        assert_eq!(estimate.idle_bytes_interval(), (WINDOW, WINDOW));
+    }
+
+    #[test]
+    fn test_strategies_allocate_windows() {
+        let large = region(0, 64 * WINDOW);
+        let small = region(0x1000000, 0x1000000 + 2 * WINDOW);
+        let regions = [&large, &small];
+
+        let plan = Uniform::new(8).with_window_size(WINDOW).plan(&regions);
+        let counts: Vec<usize> = plan.strata().iter().map(|s| s.windows.len()).collect();
+        assert_eq!(counts, vec![8, 1]);
+
+        let plan = Stratified::new(4).with_window_size(WINDOW).plan(&regions);
+        let counts: Vec<usize> = plan.strata().iter().map(|s| s.windows.len()).collect();
+        assert_eq!(counts, vec![4, 2]);
+
+        let smaps = "\
+00000000-00400000 rw-p 00000000 00:00 0
+Size:               4096 kB
+Rss:                3072 kB
+01000000-01020000 rw-p 00000000 00:00 0
+Size:                128 kB
+Rss:                   0 kB
+";
+        let strategy = RssWeighted::from_smaps(smaps, 10).with_window_size(WINDOW);
+        assert_eq!(strategy.rss(0), Some(3 * 1024 * 1024));
+        let plan = strategy.plan(&regions);
+        let counts: Vec<usize> = plan.strata().iter().map(|s| s.windows.len()).collect();
+        assert_eq!(counts, vec![10, 0]);
+    }
+
+    #[test]
+    fn test_adaptive_densifies_boundaries() {
+        let heap = region(0, 64 * WINDOW);
+        let regions = [&heap];
+        let mut strategy = Adaptive::new(16).with_window_size(WINDOW);
+
+        let plan = strategy.plan(&regions);
+        assert_eq!(plan.window_count(), 4);
+        let starts: Vec<u64> = plan.windows().map(|(_, w)| w.start).collect();
+        assert_eq!(
+            starts,
+            vec![8 * WINDOW, 24 * WINDOW, 40 * WINDOW, 56 * WINDOW]
+        );
+
+        // Cold below 32 windows, hot above
+        let scan = SampledScan {
+            windows: plan
+                .windows()
+                .map(|(s, w)| {
+                    if w.start < 32 * WINDOW {
+                        window(s, w.start, 16, 0)
+                    } else {
+                        window(s, w.start, 0, 16)
+                    }
+                })
+                .collect(),
+            ..SampledScan::default()
+        };
+        strategy.observe(&scan);
+        assert_eq!(
+            strategy.boundaries(),
+            &[AddressRange::new(24 * WINDOW, 41 * WINDOW)]
+        );
+
+        // Every slot from 23 to 41 is scanned, sparse elsewhere
+        let plan = strategy.plan(&regions);
+        assert_eq!(plan.strata().len(), 3);
+        assert_eq!(
+            plan.strata()[1].range,
+            AddressRange::new(23 * WINDOW, 42 * WINDOW)
+        );
+        assert_eq!(plan.strata()[1].windows.len(), 19);
+        assert_eq!(plan.window_count(), 2 + 19 + 2);
+        assert_eq!(strategy.name(), "adaptive");
+    }
+}
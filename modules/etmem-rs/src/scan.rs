@@ -5,13 +5,21 @@
 //! versus "hot" (recently accessed).
 
 use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
 
+use etmem_types::{PipError, USER_ADDR_LIMIT};
+
+use crate::anomaly::{AnomalyDetector, AnomalyReport};
 use crate::error::{EtmemError, Result};
+use crate::sampling::{EstimatedStats, SamplePlan, SampledScan, SamplingStrategy, WindowSample};
 use crate::sys::ProcfsHandle;
 use crate::types::{
-    AddressRange, BufferStatus, IdlePageInfo, PAGE_IDLE_KBUF_SIZE, PipEncoding, ProcIdlePageType,
-    ScanConfig, ScanFlags,
+    AddressRange, BufferStatus, DecodeWarning, IdlePageInfo, PAGE_IDLE_KBUF_SIZE, ProcIdlePageType,
+    RetryPolicy, ScanConfig, ScanFlags, UnsupportedFlagPolicy,
 };
+use crate::util::IdlePageStats;
+use crate::vma::VmaMap;
 
 /// Internal control structure for page idle scanning
 ///
@@ -39,6 +47,8 @@ pub struct PageIdleCtrl {
     flags: ScanFlags,
     /// Accumulated results
     results: VecDeque<IdlePageInfo>,
+    /// Malformed addresses repaired by the last decode
+    warnings: Vec<DecodeWarning>,
 }
 
 impl PageIdleCtrl {
@@ -55,6 +65,7 @@ impl PageIdleCtrl {
             last_va: 0,
             flags,
             results: VecDeque::new(),
+            warnings: Vec::new(),
         }
     }
 
@@ -121,53 +132,29 @@ impl PageIdleCtrl {
     /// - Upper 4 bits: page type
     /// - Lower 4 bits: count of consecutive pages minus 1
     ///
-    /// Also handles special command entries for setting HVA.
+    /// Also handles special command entries for setting HVA. This is the
+    /// decoder of [`etmem_types::decode_pip`], which can be used on recorded
+    /// streams without a scan session.
+    ///
+    /// Addresses are not trusted: unaligned ones are aligned down and
+    /// entries outside the user address space are dropped. The repairs are
+    /// logged and kept until the next decode, see [`Self::warnings`].
     pub fn decode_pip_data(&mut self, data: &[u8], base_addr: u64) -> Result<Vec<IdlePageInfo>> {
-        let mut results = Vec::new();
-        let mut current_addr = base_addr;
-        let mut i = 0;
-
-        while i < data.len() {
-            let byte = data[i];
-            let page_type_raw = PipEncoding::extract_type(byte);
-            let count = PipEncoding::extract_size(byte) + 1;
-
-            // Check for command marker
-            if page_type_raw == ProcIdlePageType::PipCmd as u8 {
-                // Handle command (e.g., SET_HVA)
-                // Kernel format: [0xa0] [64-bit address 8 bytes BE]
-                // Reference: etmemd_scan.c get_address_from_buf()
-                if byte == PipEncoding::SET_HVA && i + 8 < data.len() {
-                    // Read 64-bit address from next 8 bytes (big-endian)
-                    let addr_bytes = &data[i + 1..i + 9];
-                    current_addr = u64::from_be_bytes([
-                        addr_bytes[0],
-                        addr_bytes[1],
-                        addr_bytes[2],
-                        addr_bytes[3],
-                        addr_bytes[4],
-                        addr_bytes[5],
-                        addr_bytes[6],
-                        addr_bytes[7],
-                    ]);
-                    i += 9; // 1 command byte + 8 address bytes
-                    continue;
-                }
-                // Unknown command, skip
-                i += 1;
-                continue;
-            }
-
-            // Decode page type
-            let page_type = ProcIdlePageType::from_raw(page_type_raw)
-                .ok_or(EtmemError::InvalidPageType(page_type_raw))?;
-
-            results.push(IdlePageInfo::new(current_addr, page_type, count));
-            current_addr += page_type.page_size() * count as u64;
-            i += 1;
+        let decoded = etmem_types::decode_pip_checked(data, base_addr, USER_ADDR_LIMIT).map_err(
+            |e| match e {
+                PipError::InvalidPageType(t) => EtmemError::InvalidPageType(t),
+            },
+        )?;
+        for warning in &decoded.warnings {
+            log::warn!("Malformed idle page data read at {base_addr:#x}: {warning}");
         }
+        self.warnings = decoded.warnings;
+        Ok(decoded.pages)
+    }
 
-        Ok(results)
+    /// Get the malformed addresses repaired by the last decode
+    pub fn warnings(&self) -> &[DecodeWarning] {
+        &self.warnings
     }
 
     /// Set the next HVA to continue scanning
@@ -217,6 +204,190 @@ impl Default for PageIdleCtrl {
     }
 }
 
+/// Read and retry counters of a [`ScanSession`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScanStats {
+    /// Successful reads
+    pub reads: u64,
+    /// Bytes returned by the kernel
+    pub bytes_read: u64,
+    /// Reads retried after `EINTR`
+    pub interrupted: u64,
+    /// Reads retried after `EAGAIN` or `EBUSY`
+    pub throttled: u64,
+    /// Total time spent in backoff
+    pub backoff_time: Duration,
+    /// Reads that failed, fatally or after exhausting their retries
+    pub failures: u64,
+    /// Malformed addresses repaired while decoding reads
+    pub decode_warnings: u64,
+    /// Anomalies found in reads, see [`crate::anomaly`]
+    pub anomalies: u64,
+}
+
+impl ScanStats {
+    /// Total number of retried reads
+    pub fn retries(&self) -> u64 {
+        self.interrupted + self.throttled
+    }
+}
+
+impl fmt::Display for ScanStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} reads, {} read",
+            self.reads,
+            crate::util::format_bytes(self.bytes_read)
+        )?;
+        if self.retries() > 0 {
+            write!(
+                f,
+                ", {} retries ({} interrupted, {} throttled, {:.1}ms backoff)",
+                self.retries(),
+                self.interrupted,
+                self.throttled,
+                self.backoff_time.as_secs_f64() * 1000.0
+            )?;
+        }
+        if self.failures > 0 {
+            write!(f, ", {} failed", self.failures)?;
+        }
+        if self.decode_warnings > 0 {
+            write!(f, ", {} malformed addresses", self.decode_warnings)?;
+        }
+        if self.anomalies > 0 {
+            write!(f, ", {} anomalies", self.anomalies)?;
+        }
+        Ok(())
+    }
+}
+
+/// When the entries of one kernel read were observed
+///
+/// The entries of a read are sampled together, but a walk over a large
+/// process is made of many reads that can be minutes apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScanStamp {
+    /// Scan cycle the read belongs to, see [`ScanSession::start_cycle`]
+    pub cycle: u64,
+    /// When the read returned
+    pub observed_at: Instant,
+}
+
+/// Entries returned by one kernel read
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanBatch {
+    /// When the entries were observed
+    pub stamp: ScanStamp,
+    /// Entries of the read
+    pub pages: Vec<IdlePageInfo>,
+    /// Address to continue reading from, `None` if the read was the last
+    pub next: Option<u64>,
+}
+
+/// Pages found by a walk that may have stopped at its time budget
+///
+/// Returned when [`ScanConfig::max_duration`] is set; a walk without a
+/// budget is always complete.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PartialScan {
+    /// Pages found so far
+    pub pages: Vec<IdlePageInfo>,
+    /// Address to resume the walk from, `None` once the walk is complete
+    pub resume_at: Option<u64>,
+    /// Stamp of each read that found pages, with the index in `pages` of
+    /// its first entry
+    pub stamps: Vec<(usize, ScanStamp)>,
+}
+
+impl PartialScan {
+    /// Check whether the walk covered its whole range
+    pub fn is_complete(&self) -> bool {
+        self.resume_at.is_none()
+    }
+
+    /// Get the stamp of the read that found `pages[index]`
+    pub fn stamp_of(&self, index: usize) -> Option<ScanStamp> {
+        if index >= self.pages.len() {
+            return None;
+        }
+        let reads = self.stamps.partition_point(|(first, _)| *first <= index);
+        reads.checked_sub(1).map(|read| self.stamps[read].1)
+    }
+
+    /// Iterate over the pages together with the stamp of their read
+    pub fn stamped(&self) -> impl Iterator<Item = (ScanStamp, &IdlePageInfo)> + '_ {
+        self.pages
+            .iter()
+            .enumerate()
+            .filter_map(|(index, page)| self.stamp_of(index).map(|stamp| (stamp, page)))
+    }
+
+    /// Get the time between the first and the last read that found pages
+    pub fn observed_span(&self) -> Duration {
+        match (self.stamps.first(), self.stamps.last()) {
+            (Some((_, first)), Some((_, last))) => {
+                last.observed_at.duration_since(first.observed_at)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Append the pages of a read, stamping them
+    fn push(&mut self, stamp: ScanStamp, pages: impl IntoIterator<Item = IdlePageInfo>) {
+        let first = self.pages.len();
+        self.pages.extend(pages);
+        if self.pages.len() > first {
+            self.stamps.push((first, stamp));
+        }
+    }
+}
+
+/// Deadline of one walk, from [`ScanConfig::max_duration`]
+fn walk_deadline(config: &ScanConfig) -> Option<Instant> {
+    config.max_duration.map(|limit| Instant::now() + limit)
+}
+
+/// Check whether a walk has used up its time budget
+fn past_deadline(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Set scan flags one at a time and return the flags in effect
+///
+/// A flag the kernel rejects with `EINVAL` fails the probe under
+/// [`UnsupportedFlagPolicy::Fail`], with all rejected flags in the error, and
+/// is left out with a warning under [`UnsupportedFlagPolicy::Drop`]. Other
+/// errors are returned as they are.
+fn probe_flags<F>(flags: ScanFlags, policy: UnsupportedFlagPolicy, mut add: F) -> Result<ScanFlags>
+where
+    F: FnMut(ScanFlags) -> std::io::Result<()>,
+{
+    let mut unsupported = ScanFlags::empty();
+    for flag in flags.iter() {
+        match add(flag) {
+            Ok(()) => {}
+            Err(e) if e.raw_os_error() == Some(libc::EINVAL) => unsupported |= flag,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    if unsupported.is_empty() {
+        return Ok(flags);
+    }
+    match policy {
+        UnsupportedFlagPolicy::Fail => Err(EtmemError::UnsupportedFlags(unsupported.bits())),
+        UnsupportedFlagPolicy::Drop => {
+            log::warn!(
+                "scan flags {:?} not supported by the kernel, scanning without them",
+                unsupported
+            );
+            Ok(flags - unsupported)
+        }
+    }
+}
+
 /// Safe wrapper for idle page scanning session
 ///
 /// This provides a safe interface to the kernel's idle page scanning
@@ -231,6 +402,16 @@ pub struct ScanSession {
     ctrl: PageIdleCtrl,
     /// Process ID being scanned
     pid: u32,
+    /// Read and retry counters
+    stats: ScanStats,
+    /// Buffer the kernel writes idle page data into, reused across reads
+    buffer: Vec<u8>,
+    /// Ranges left out of the results
+    excluded: Vec<AddressRange>,
+    /// Current scan cycle
+    cycle: u64,
+    /// Sanity checks of the reads of the current cycle
+    anomalies: AnomalyDetector,
 }
 
 impl ScanSession {
@@ -260,21 +441,100 @@ impl ScanSession {
         // Safe: handle construction is encapsulated
         let handle = unsafe { ProcfsHandle::open_idle_pages(pid)? };
 
-        // Apply scan flags via IOCTL
-        if !config.flags.is_empty() {
-            unsafe {
-                crate::sys::add_scan_flags(&handle, config.flags.bits())?;
-            }
-        }
+        // Apply scan flags via IOCTL, one at a time to find rejected ones
+        let mut config = config;
+        config.flags = probe_flags(config.flags, config.unsupported_flags, |flag| unsafe {
+            crate::sys::add_scan_flags(&handle, flag.bits())
+        })?;
 
         Ok(Self {
             handle,
             config: config.clone(),
             ctrl: PageIdleCtrl::new(config.buffer_size, config.flags),
             pid,
+            stats: ScanStats::default(),
+            buffer: vec![0u8; config.buffer_size],
+            excluded: Vec::new(),
+            cycle: 0,
+            anomalies: AnomalyDetector::new(config.max_hole_bytes),
         })
     }
 
+    /// Create a scan session for the calling process
+    ///
+    /// A process scanning itself disturbs what it measures: every read
+    /// writes the session's buffers, and the scanning thread keeps touching
+    /// its stack, so those pages always come back accessed. The session
+    /// excludes its own buffers and the stack of the calling thread from
+    /// its results. Ranges the caller touches while scanning, such as where
+    /// it stores the results, can be excluded with [`ScanSession::exclude`].
+    ///
+    /// The stack is that of the thread creating the session; scan from the
+    /// same thread.
+    ///
+    /// # Errors
+    /// Same as [`ScanSession::new`], or if the thread's stack cannot be
+    /// determined.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use etmem_rs::{ScanConfig, ScanSession};
+    ///
+    /// let mut session = ScanSession::for_self(ScanConfig::default())?;
+    /// let (pages, _) = session.read(0)?;
+    /// # Ok::<(), etmem_rs::EtmemError>(())
+    /// ```
+    pub fn for_self(config: ScanConfig) -> Result<Self> {
+        let mut session = Self::new(std::process::id(), config)?;
+        session.exclude(page_span(&session.buffer));
+        session.exclude(page_span(&session.ctrl.kpie));
+        let (stack, size) = crate::sys::current_thread_stack()?;
+        session.exclude(AddressRange::with_size(stack, size));
+        Ok(session)
+    }
+
+    /// Leave pages overlapping `range` out of the results of later reads
+    ///
+    /// Huge pages partially overlapping the range are left out whole.
+    pub fn exclude(&mut self, range: AddressRange) {
+        if range.is_valid() {
+            self.excluded.push(range);
+        }
+    }
+
+    /// Get the ranges left out of the results
+    pub fn excluded(&self) -> &[AddressRange] {
+        &self.excluded
+    }
+
+    /// Start a new scan cycle
+    ///
+    /// Reads are stamped with the current cycle, 0 before the first one,
+    /// and checked for anomalies together with the other reads of the
+    /// cycle. Every range walk starts a cycle of its own; callers driving
+    /// [`ScanSession::read_batch`] themselves start one per pass.
+    ///
+    /// # Returns
+    /// The new cycle
+    pub fn start_cycle(&mut self) -> u64 {
+        self.cycle += 1;
+        self.anomalies.reset();
+        self.cycle
+    }
+
+    /// Get the current scan cycle
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Get the anomalies found in the reads of the current cycle
+    ///
+    /// Also covers reads that failed to decode, such as those with page
+    /// types out of range.
+    pub fn anomalies(&self) -> AnomalyReport {
+        self.anomalies.report()
+    }
+
     /// Read idle pages starting from the given address
     ///
     /// Returns a vector of `IdlePageInfo` entries and an optional
@@ -285,71 +545,237 @@ impl ScanSession {
     /// - I/O error occurs
     /// - Invalid data received from kernel
     pub fn read(&mut self, start_addr: u64) -> Result<(Vec<IdlePageInfo>, Option<u64>)> {
+        self.read_batch(start_addr)
+            .map(|batch| (batch.pages, batch.next))
+    }
+
+    /// Read idle pages starting from the given address, stamped with when
+    /// the kernel returned them
+    ///
+    /// # Errors
+    /// Same as [`ScanSession::read`].
+    pub fn read_batch(&mut self, start_addr: u64) -> Result<ScanBatch> {
         // Validate address alignment (must be page-aligned)
         if !start_addr.is_multiple_of(4096) {
             return Err(EtmemError::InvalidAddress);
         }
 
-        let mut buffer = vec![0u8; self.config.buffer_size];
+        // Read from procfs into the session buffer, which stays in place so
+        // that it can be excluded when scanning the current process
+        let mut buffer = std::mem::take(&mut self.buffer);
+        let read = self.read_retrying(&mut buffer, start_addr);
+        self.buffer = buffer;
+        let bytes_read = read?;
+        let stamp = ScanStamp {
+            cycle: self.cycle,
+            observed_at: Instant::now(),
+        };
 
-        // Read from procfs
-        let bytes_read = unsafe {
-            self.handle
-                .read_at(&mut buffer, start_addr as i64)
-                .map_err(|e| EtmemError::IoError(e.to_string()))?
+        #[cfg(feature = "failpoints")]
+        let injected = match crate::failpoints::fire(crate::failpoints::Failpoint::ScanStatus) {
+            Some(crate::failpoints::FailAction::Status(status)) => Some(status),
+            _ => None,
         };
+        #[cfg(not(feature = "failpoints"))]
+        let injected = None;
 
         if bytes_read == 0 {
-            return Ok((Vec::new(), None));
+            return Ok(ScanBatch {
+                stamp,
+                pages: Vec::new(),
+                next: None,
+            });
+        }
+
+        // Check the raw data first, so that reads failing to decode are
+        // accounted for
+        let data = &self.buffer[..bytes_read as usize];
+        let before = self.anomalies.report().count;
+        self.anomalies.observe_read(data, start_addr);
+        let found = self.anomalies.report().count - before;
+        if found > 0 {
+            log::warn!(
+                "{found} anomalies in idle page data read at {start_addr:#x} of pid {}",
+                self.pid
+            );
+            self.stats.anomalies += found;
         }
 
         // Decode PIP data
-        let data = &buffer[..bytes_read as usize];
         let pages = self.ctrl.decode_pip_data(data, start_addr)?;
+        self.stats.decode_warnings += self.ctrl.warnings().len() as u64;
+        let status = injected;
+        let buffer_full = bytes_read as usize >= self.config.buffer_size;
+        let next_addr = next_read_address(&pages, start_addr, status, buffer_full);
+        if next_addr.is_none() && status.is_some_and(|s| s.has_more()) {
+            log::warn!(
+                "Idle page read at {start_addr:#x} of pid {} stopped early without progress",
+                self.pid
+            );
+        }
 
-        // Check if there might be more data
-        let next_addr = if bytes_read as usize >= self.config.buffer_size {
-            // Buffer was full, there might be more
-            pages.last().map(|p| p.end_address())
+        let pages = if self.excluded.is_empty() {
+            pages
         } else {
-            None
+            without_ranges(pages, &self.excluded)
         };
+        Ok(ScanBatch {
+            stamp,
+            pages,
+            next: next_addr,
+        })
+    }
 
-        Ok((pages, next_addr))
+    /// Read from procfs, retrying transient errors per the retry policy
+    fn read_retrying(&mut self, buffer: &mut [u8], offset: u64) -> Result<isize> {
+        let policy = self.config.retry;
+        let mut retry = 0;
+        let mut throttled = 0;
+        loop {
+            let err = match unsafe { self.handle.read_at(buffer, offset as i64) } {
+                Ok(bytes_read) => {
+                    self.stats.reads += 1;
+                    self.stats.bytes_read += bytes_read as u64;
+                    return Ok(bytes_read);
+                }
+                Err(err) => err,
+            };
+
+            let errno = err.raw_os_error().unwrap_or(0);
+            if !RetryPolicy::is_transient(errno) || retry >= policy.max_retries {
+                self.stats.failures += 1;
+                if RetryPolicy::is_transient(errno) {
+                    log::warn!(
+                        "Idle page read at {offset:#x} of pid {} still failing after {retry} retries: {err}",
+                        self.pid
+                    );
+                }
+                return Err(EtmemError::IoError(err.to_string()));
+            }
+
+            if errno == libc::EINTR {
+                self.stats.interrupted += 1;
+            } else {
+                let delay = policy.backoff(throttled);
+                throttled += 1;
+                self.stats.throttled += 1;
+                self.stats.backoff_time += delay;
+                log::debug!("Idle page read at {offset:#x}: {err}, retrying in {delay:?}");
+                std::thread::sleep(delay);
+            }
+            retry += 1;
+        }
     }
 
     /// Read all idle pages in a range
     ///
     /// This convenience method reads all idle pages in the specified range,
-    /// automatically handling pagination.
+    /// automatically handling pagination. With [`ScanConfig::max_duration`]
+    /// set, the pages found within the budget are returned; use
+    /// [`ScanSession::read_range_partial`] to learn where the walk stopped.
     ///
     /// # Errors
     /// Returns error if the range is invalid or I/O fails.
     pub fn read_range(&mut self, range: AddressRange) -> Result<Vec<IdlePageInfo>> {
+        self.read_range_partial(range).map(|scan| scan.pages)
+    }
+
+    /// Read idle pages in a range within the configured time budget
+    ///
+    /// Stops once [`ScanConfig::max_duration`] has elapsed and reports the
+    /// address to resume from. Resume by reading the remainder of the
+    /// range, `AddressRange::new(resume_at, range.end)`. Each call starts a
+    /// new scan cycle.
+    ///
+    /// # Errors
+    /// Returns error if the range is invalid or I/O fails.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use etmem_rs::{AddressRange, ScanConfig, ScanSession};
+    ///
+    /// let config = ScanConfig::default().with_max_duration(Duration::from_millis(50));
+    /// let mut session = ScanSession::new(1234, config)?;
+    /// let mut range = AddressRange::new(0x7f0000000000, 0x7f4000000000);
+    /// loop {
+    ///     let scan = session.read_range_partial(range)?;
+    ///     println!("{} entries", scan.pages.len());
+    ///     match scan.resume_at {
+    ///         Some(addr) => range = AddressRange::new(addr, range.end),
+    ///         None => break,
+    ///     }
+    /// }
+    /// # Ok::<(), etmem_rs::EtmemError>(())
+    /// ```
+    pub fn read_range_partial(&mut self, range: AddressRange) -> Result<PartialScan> {
         if !range.is_valid() {
             return Err(EtmemError::InvalidRange);
         }
+        self.start_cycle();
+        self.walk_range(range)
+    }
 
-        let mut all_pages = Vec::new();
+    /// Walk a valid range within the time budget in the current cycle
+    fn walk_range(&mut self, range: AddressRange) -> Result<PartialScan> {
+        let deadline = walk_deadline(&self.config);
+        let mut scan = PartialScan::default();
         let mut current_addr = range.start;
+        self.anomalies.start_walk();
 
         while current_addr < range.end {
-            let (pages, next) = self.read(current_addr)?;
+            let batch = self.read_batch(current_addr)?;
 
             // Filter pages to only include those in range
-            for page in pages {
-                if page.address >= range.start && page.address < range.end {
-                    all_pages.push(page);
-                }
-            }
-
-            match next {
+            scan.push(
+                batch.stamp,
+                batch
+                    .pages
+                    .into_iter()
+                    .filter(|page| page.address >= range.start && page.address < range.end),
+            );
+
+            match batch.next {
                 Some(addr) if addr < range.end => current_addr = addr,
                 _ => break,
             }
+
+            if past_deadline(deadline) {
+                scan.resume_at = Some(current_addr);
+                return Ok(scan);
+            }
         }
 
-        Ok(all_pages)
+        Ok(scan)
+    }
+
+    /// Read the windows of a sample plan and extrapolate their statistics
+    ///
+    /// # Errors
+    /// Returns error if I/O fails.
+    pub fn read_plan(&mut self, plan: &SamplePlan) -> Result<SampledScan> {
+        let mut pages = Vec::new();
+        let mut windows = Vec::with_capacity(plan.window_count());
+        self.start_cycle();
+        for (stratum, range) in plan.windows() {
+            if !range.is_valid() {
+                return Err(EtmemError::InvalidRange);
+            }
+            let found = self.walk_range(range)?.pages;
+            windows.push(WindowSample {
+                stratum,
+                range,
+                stats: IdlePageStats::from_pages(&found),
+            });
+            pages.extend(found);
+        }
+
+        let estimate = EstimatedStats::from_windows(plan, &windows);
+        Ok(SampledScan {
+            pages,
+            windows,
+            estimate,
+        })
     }
 
     /// Add scan flags
@@ -375,10 +801,23 @@ impl ScanSession {
     }
 
     /// Get current scan configuration
+    ///
+    /// The flags are those in effect, without any dropped at creation under
+    /// [`UnsupportedFlagPolicy::Drop`].
     pub fn config(&self) -> &ScanConfig {
         &self.config
     }
 
+    /// Get the read and retry counters
+    pub fn stats(&self) -> &ScanStats {
+        &self.stats
+    }
+
+    /// Reset the read and retry counters
+    pub fn reset_stats(&mut self) {
+        self.stats = ScanStats::default();
+    }
+
     /// Get the process ID being scanned
     pub fn pid(&self) -> u32 {
         self.pid
@@ -395,6 +834,24 @@ impl ScanSession {
     }
 }
 
+/// Get the address to continue after a read, `None` if it was the last
+///
+/// Only a full buffer hints at more data, unless a status injected through
+/// the `scan_status` failpoint says whether the kernel stopped early. A read that made no progress is
+/// the last, so a misbehaving module cannot make a walk loop forever.
+fn next_read_address(
+    pages: &[IdlePageInfo],
+    start_addr: u64,
+    status: Option<BufferStatus>,
+    buffer_full: bool,
+) -> Option<u64> {
+    let more = status.map_or(buffer_full, |status| status.has_more());
+    pages
+        .last()
+        .map(|p| p.end_address())
+        .filter(|&addr| more && addr > start_addr)
+}
+
 /// High-level idle page scanner
 ///
 /// This provides a convenient API for scanning without managing
@@ -419,22 +876,138 @@ impl IdlePageScanner {
     ///     println!("Found {:?} page at {:x}", page.page_type, page.address);
     /// }
     /// ```
+    ///
+    /// With [`ScanConfig::max_duration`] set, only the pages found within
+    /// the budget are returned; [`IdlePageScanner::scan_process_from`] also
+    /// reports where the scan stopped.
     pub fn scan_process(pid: u32, config: ScanConfig) -> Result<Vec<IdlePageInfo>> {
         let mut session = ScanSession::new(pid, config)?;
-        let mut all_pages = Vec::new();
-        let mut current_addr: u64 = 0;
+        Self::read_all(&mut session)
+    }
+
+    /// Scan a process for idle pages and check the results for anomalies
+    ///
+    /// Like [`IdlePageScanner::scan_process`], also returning the
+    /// [`AnomalyReport`] of the scan, to pass to
+    /// [`PageAger::observe_checked`](crate::policy::PageAger::observe_checked)
+    /// before reclaiming based on it.
+    pub fn scan_process_checked(
+        pid: u32,
+        config: ScanConfig,
+    ) -> Result<(Vec<IdlePageInfo>, AnomalyReport)> {
+        let mut session = ScanSession::new(pid, config)?;
+        let pages = Self::read_all(&mut session)?;
+        Ok((pages, session.anomalies()))
+    }
+
+    /// Scan a process from `start_addr` within the configured time budget
+    ///
+    /// Returns the pages found until [`ScanConfig::max_duration`] elapsed
+    /// and the address to pass as `start_addr` of the next call. Scanning
+    /// from 0 covers the whole address space.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use etmem_rs::{IdlePageScanner, ScanConfig};
+    ///
+    /// let config = ScanConfig::default().with_max_duration(Duration::from_millis(100));
+    /// let mut start = 0;
+    /// loop {
+    ///     let scan = IdlePageScanner::scan_process_from(1234, config.clone(), start)?;
+    ///     println!("{} entries", scan.pages.len());
+    ///     match scan.resume_at {
+    ///         Some(addr) => start = addr,
+    ///         None => break,
+    ///     }
+    /// }
+    /// # Ok::<(), etmem_rs::EtmemError>(())
+    /// ```
+    pub fn scan_process_from(pid: u32, config: ScanConfig, start_addr: u64) -> Result<PartialScan> {
+        let mut session = ScanSession::new(pid, config)?;
+        Self::read_from(&mut session, start_addr)
+    }
+
+    /// Scan the windows a sampling strategy picks in a process
+    ///
+    /// Plans the scan over the scannable mappings, reads the planned
+    /// windows and hands the result back to the strategy, so adaptive
+    /// strategies learn from it. The pages found cover the windows only;
+    /// [`SampledScan::estimate`] extrapolates them to the whole plan.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use etmem_rs::sampling::Stratified;
+    /// use etmem_rs::{IdlePageScanner, ScanConfig};
+    ///
+    /// let scan = IdlePageScanner::scan_sampled(1234, ScanConfig::default(), &mut Stratified::new(8))?;
+    /// let (low, high) = scan.estimate.idle_bytes_interval();
+    /// println!("{low}..{high} bytes idle");
+    /// # Ok::<(), etmem_rs::EtmemError>(())
+    /// ```
+    pub fn scan_sampled(
+        pid: u32,
+        config: ScanConfig,
+        strategy: &mut dyn SamplingStrategy,
+    ) -> Result<SampledScan> {
+        let vma_map = VmaMap::for_process(pid)?;
+        let plan = strategy.plan(&vma_map.scannable());
+        let mut session = ScanSession::new(pid, config)?;
+        let scan = session.read_plan(&plan)?;
+        strategy.observe(&scan);
+        Ok(scan)
+    }
+
+    /// Scan the calling process for idle pages
+    ///
+    /// Reads the whole address space through [`ScanSession::for_self`], so
+    /// the scanner's buffers and the calling thread's stack are left out.
+    /// The returned vector is filled while scanning; its pages show up as
+    /// accessed in a later scan.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use etmem_rs::{IdlePageScanner, ScanConfig};
+    ///
+    /// let pages = IdlePageScanner::scan_self(ScanConfig::default())
+    ///     .expect("Failed to scan process");
+    /// let idle = pages.iter().filter(|p| p.is_idle()).count();
+    /// println!("{idle} idle entries");
+    /// ```
+    pub fn scan_self(config: ScanConfig) -> Result<Vec<IdlePageInfo>> {
+        let mut session = ScanSession::for_self(config)?;
+        Self::read_all(&mut session)
+    }
+
+    /// Read the whole address space of a session from address 0
+    fn read_all(session: &mut ScanSession) -> Result<Vec<IdlePageInfo>> {
+        Self::read_from(session, 0).map(|scan| scan.pages)
+    }
+
+    /// Read the address space of a session from `start_addr` to its end
+    /// or the configured time budget
+    fn read_from(session: &mut ScanSession, start_addr: u64) -> Result<PartialScan> {
+        let deadline = walk_deadline(session.config());
+        let mut scan = PartialScan::default();
+        let mut current_addr = start_addr;
+        session.start_cycle();
 
         loop {
-            let (pages, next) = session.read(current_addr)?;
-            all_pages.extend(pages);
+            let batch = session.read_batch(current_addr)?;
+            scan.push(batch.stamp, batch.pages);
 
-            match next {
+            match batch.next {
                 Some(addr) => current_addr = addr,
                 None => break,
             }
+
+            if past_deadline(deadline) {
+                scan.resume_at = Some(current_addr);
+                return Ok(scan);
+            }
         }
 
-        Ok(all_pages)
+        Ok(scan)
     }
 
     /// Scan a specific address range in a process
@@ -458,11 +1031,132 @@ impl IdlePageScanner {
         let pages = Self::scan_process(pid, config)?;
         Ok(pages.into_iter().filter(|p| p.is_accessed()).collect())
     }
+
+    /// Scan a process twice, `interval` apart, and return the pages left idle
+    ///
+    /// Reading `idle_pages` reports each page as accessed or idle and clears
+    /// its accessed bit, so a single scan only tells whether a page was
+    /// touched since the previous scan (or ever, on the first one). The
+    /// first pass only clears the accessed bits and its report is
+    /// discarded; a page reported idle by the second pass was not touched
+    /// during `interval`.
+    ///
+    /// `SCAN_SKIM_IDLE` stops the walk at `PMD_IDLE_PTES` without visiting
+    /// the PTEs, which would leave their accessed bits set. It is therefore
+    /// left out of the mark pass and only applied to the verify pass when
+    /// requested in `config`.
+    ///
+    /// # Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use etmem_rs::{IdlePageScanner, ScanConfig};
+    ///
+    /// let pages = IdlePageScanner::two_pass(1234, ScanConfig::default(), Duration::from_secs(2))
+    ///     .expect("Failed to scan process");
+    /// let cold: u64 = pages.iter().map(|p| p.total_size()).sum();
+    /// println!("{} KB idle for at least 2s", cold / 1024);
+    /// ```
+    pub fn two_pass(pid: u32, config: ScanConfig, interval: Duration) -> Result<Vec<IdlePageInfo>> {
+        Self::run_two_pass(pid, None, config, interval)
+    }
+
+    /// Two-pass scan of a specific address range in a process
+    ///
+    /// See [`IdlePageScanner::two_pass`] for the semantics.
+    pub fn two_pass_range(
+        pid: u32,
+        range: AddressRange,
+        config: ScanConfig,
+        interval: Duration,
+    ) -> Result<Vec<IdlePageInfo>> {
+        if !range.is_valid() {
+            return Err(EtmemError::InvalidRange);
+        }
+        Self::run_two_pass(pid, Some(range), config, interval)
+    }
+
+    fn run_two_pass(
+        pid: u32,
+        range: Option<AddressRange>,
+        config: ScanConfig,
+        interval: Duration,
+    ) -> Result<Vec<IdlePageInfo>> {
+        let skim = config.flags & ScanFlags::SCAN_SKIM_IDLE;
+        let mark_flags = config.flags - skim;
+        let mark_config = config.with_flags(mark_flags);
+        let mut session = ScanSession::new(pid, mark_config)?;
+
+        let pass = |session: &mut ScanSession| match range {
+            Some(range) => session.read_range(range),
+            None => Self::read_all(session),
+        };
+
+        // Clears the accessed bits; what it reports predates `interval`
+        let _marked = pass(&mut session)?;
+        std::thread::sleep(interval);
+        if !skim.is_empty() {
+            session.add_flags(skim)?;
+        }
+        let verified = pass(&mut session)?;
+
+        Ok(verified.into_iter().filter(IdlePageInfo::is_idle).collect())
+    }
+}
+
+/// Largest page count of one `IdlePageInfo` entry
+const MAX_ENTRY_COUNT: u8 = 16;
+
+/// Page-aligned range covering a buffer
+fn page_span(buffer: &[u8]) -> AddressRange {
+    const PAGE_SIZE: u64 = 4096;
+    let start = buffer.as_ptr() as u64;
+    AddressRange::new(
+        start & !(PAGE_SIZE - 1),
+        (start + buffer.len() as u64).next_multiple_of(PAGE_SIZE),
+    )
+}
+
+/// Drop the pages of `pages` that overlap any of `excluded`
+///
+/// Entries overlapping a range are split into their pages and the rest
+/// coalesced again.
+fn without_ranges(pages: Vec<IdlePageInfo>, excluded: &[AddressRange]) -> Vec<IdlePageInfo> {
+    let overlaps = |start: u64, end: u64| {
+        let range = AddressRange::new(start, end);
+        excluded.iter().any(|r| r.overlaps(&range))
+    };
+
+    let mut result: Vec<IdlePageInfo> = Vec::with_capacity(pages.len());
+    for entry in pages {
+        if !overlaps(entry.address, entry.end_address()) {
+            result.push(entry);
+            continue;
+        }
+        let size = entry.page_type.page_size();
+        for i in 0..u64::from(entry.count) {
+            let addr = entry.address + i * size;
+            if overlaps(addr, addr + size) {
+                continue;
+            }
+            match result.last_mut() {
+                Some(last)
+                    if last.page_type == entry.page_type
+                        && last.end_address() == addr
+                        && last.count < MAX_ENTRY_COUNT =>
+                {
+                    last.count += 1
+                }
+                _ => result.push(IdlePageInfo::new(addr, entry.page_type, 1)),
+            }
+        }
+    }
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::types::{PAGE_IDLE_BUF_MIN, PipEncoding};
 
     #[test]
     fn test_page_idle_ctrl_new() {
@@ -568,6 +1262,74 @@ mod tests {
         assert_eq!(result[0].page_type, ProcIdlePageType::PteIdle);
     }
 
+    #[test]
+    fn test_decode_pip_data_malformed_address() {
+        let mut ctrl = PageIdleCtrl::default();
+
+        // A jump past the user address space followed by an entry
+        let mut data = vec![PipEncoding::SET_HVA];
+        data.extend_from_slice(&0xffff_8000_0000_0000u64.to_be_bytes());
+        data.push(PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 0));
+
+        let result = ctrl.decode_pip_data(&data, 0).unwrap();
+        assert!(result.is_empty());
+        assert!(matches!(
+            ctrl.warnings(),
+            [DecodeWarning::OutOfRange {
+                dropped_pages: 1,
+                ..
+            }]
+        ));
+
+        // Warnings only describe the last decode
+        ctrl.decode_pip_data(&data[9..], 0x1000).unwrap();
+        assert!(ctrl.warnings().is_empty());
+    }
+
+    #[test]
+    fn test_without_ranges() {
+        let pages = vec![
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 4),
+            IdlePageInfo::new(0x8000, ProcIdlePageType::PteAccessed, 2),
+            IdlePageInfo::new(0x20_0000, ProcIdlePageType::PmdIdle, 1),
+            IdlePageInfo::new(0x60_0000, ProcIdlePageType::PmdIdle, 1),
+        ];
+        let excluded = [
+            AddressRange::new(0x2000, 0x4000),
+            // Partially covers a huge page, which is dropped whole
+            AddressRange::new(0x3f_f000, 0x40_1000),
+        ];
+
+        assert_eq!(
+            without_ranges(pages, &excluded),
+            vec![
+                IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 1),
+                IdlePageInfo::new(0x4000, ProcIdlePageType::PteIdle, 1),
+                IdlePageInfo::new(0x8000, ProcIdlePageType::PteAccessed, 2),
+                IdlePageInfo::new(0x60_0000, ProcIdlePageType::PmdIdle, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_page_span() {
+        let buffer = vec![0u8; 5000];
+        let span = page_span(&buffer);
+        let start = buffer.as_ptr() as u64;
+        assert_eq!(span.start % 4096, 0);
+        assert_eq!(span.end % 4096, 0);
+        assert!(span.start <= start && start + 5000 <= span.end);
+        assert!(span.size() <= 3 * 4096);
+    }
+
+    #[test]
+    fn test_current_thread_stack() {
+        let local = 0u64;
+        let addr = &local as *const u64 as u64;
+        let (stack, size) = crate::sys::current_thread_stack().unwrap();
+        assert!(AddressRange::with_size(stack, size).contains(addr));
+    }
+
     #[test]
     fn test_scan_config_validation() {
         // Valid config should pass
@@ -584,4 +1346,307 @@ mod tests {
         let status = ctrl.add_page_internal(0x1000, 0x2000, ProcIdlePageType::PteIdle, 4096);
         assert!(matches!(status, BufferStatus::Success));
     }
+
+    #[test]
+    fn test_probe_flags() {
+        let flags =
+            ScanFlags::SCAN_HUGE_PAGE | ScanFlags::SCAN_DIRTY_PAGE | ScanFlags::SCAN_SKIM_IDLE;
+        let mut probed = ScanFlags::empty();
+        let old_kernel = |flag: ScanFlags| {
+            probed |= flag;
+            if flag == ScanFlags::SCAN_SKIM_IDLE {
+                Ok(())
+            } else {
+                Err(std::io::Error::from_raw_os_error(libc::EINVAL))
+            }
+        };
+
+        let rejected = ScanFlags::SCAN_HUGE_PAGE | ScanFlags::SCAN_DIRTY_PAGE;
+        assert_eq!(
+            probe_flags(flags, UnsupportedFlagPolicy::Fail, old_kernel),
+            Err(EtmemError::UnsupportedFlags(rejected.bits()))
+        );
+        // Every flag is probed, so the error names all rejected ones
+        assert_eq!(probed, flags);
+
+        let effective = probe_flags(
+            flags,
+            UnsupportedFlagPolicy::Drop,
+            |flag: ScanFlags| match flag {
+                ScanFlags::SCAN_SKIM_IDLE => Ok(()),
+                _ => Err(std::io::Error::from_raw_os_error(libc::EINVAL)),
+            },
+        );
+        assert_eq!(effective, Ok(ScanFlags::SCAN_SKIM_IDLE));
+
+        // Only EINVAL means unsupported
+        let denied = probe_flags(flags, UnsupportedFlagPolicy::Drop, |_| {
+            Err(std::io::Error::from_raw_os_error(libc::EPERM))
+        });
+        assert_eq!(denied, Err(EtmemError::PermissionDenied));
+
+        assert_eq!(
+            probe_flags(
+                ScanFlags::empty(),
+                UnsupportedFlagPolicy::Fail,
+                |_| unreachable!()
+            ),
+            Ok(ScanFlags::empty())
+        );
+    }
+
+    #[test]
+    fn test_next_read_address() {
+        let pages = [IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2)];
+        // An injected status decides, whatever the size of the read
+        assert_eq!(
+            next_read_address(&pages, 0x1000, Some(BufferStatus::KbufFull), false),
+            Some(0x3000)
+        );
+        assert_eq!(
+            next_read_address(&pages, 0x1000, Some(BufferStatus::BufFull), false),
+            Some(0x3000)
+        );
+        assert_eq!(
+            next_read_address(&pages, 0x1000, Some(BufferStatus::Success), true),
+            None
+        );
+        // Without a status, a full buffer may have more
+        assert_eq!(next_read_address(&pages, 0x1000, None, true), Some(0x3000));
+        assert_eq!(next_read_address(&pages, 0x1000, None, false), None);
+        // No progress, no next read
+        assert_eq!(
+            next_read_address(&[], 0x1000, Some(BufferStatus::KbufFull), true),
+            None
+        );
+        assert_eq!(
+            next_read_address(&pages, 0x3000, Some(BufferStatus::KbufFull), true),
+            None
+        );
+    }
+
+    #[test]
+    fn test_scan_stats_display() {
+        let mut stats = ScanStats {
+            reads: 3,
+            bytes_read: 2048,
+            ..Default::default()
+        };
+        assert_eq!(stats.to_string(), "3 reads, 2.00 KB read");
+
+        stats.interrupted = 2;
+        stats.throttled = 1;
+        stats.backoff_time = Duration::from_millis(4);
+        stats.failures = 1;
+        assert_eq!(stats.retries(), 3);
+        assert_eq!(
+            stats.to_string(),
+            "3 reads, 2.00 KB read, 3 retries (2 interrupted, 1 throttled, 4.0ms backoff), 1 failed"
+        );
+
+        stats.decode_warnings = 2;
+        assert!(
+            stats
+                .to_string()
+                .ends_with(", 1 failed, 2 malformed addresses")
+        );
+    }
+
+    /// Session reading from a regular file laid out like `idle_pages`
+    fn file_session(file: std::fs::File, config: ScanConfig) -> ScanSession {
+        use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+        ScanSession {
+            handle: unsafe { ProcfsHandle::from_raw_fd(file.into_raw_fd()) },
+            ctrl: PageIdleCtrl::new(config.buffer_size, config.flags),
+            buffer: vec![0u8; config.buffer_size],
+            anomalies: AnomalyDetector::new(config.max_hole_bytes),
+            config,
+            pid: std::process::id(),
+            stats: ScanStats::default(),
+            excluded: Vec::new(),
+            cycle: 0,
+        }
+    }
+
+    #[test]
+    fn test_max_duration_partial_scan() {
+        use std::os::unix::fs::FileExt;
+
+        // Two full buffers of single idle pages: the first read covers
+        // 0..0x13000 and the second continues at file offset 0x13000
+        let idle = PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 0);
+        let file = tempfile::tempfile().unwrap();
+        file.write_all_at(&[idle; PAGE_IDLE_BUF_MIN], 0).unwrap();
+        file.write_all_at(&[idle; PAGE_IDLE_BUF_MIN], 0x13000)
+            .unwrap();
+        let range = AddressRange::new(0, 0x100000);
+
+        let config = ScanConfig::default().with_buffer_size(PAGE_IDLE_BUF_MIN);
+        let mut session = file_session(file.try_clone().unwrap(), config.clone());
+        let full = session.read_range_partial(range).unwrap();
+        assert!(full.is_complete());
+        assert_eq!(full.pages.len(), 2 * PAGE_IDLE_BUF_MIN);
+
+        // A zero budget stops after one read
+        let config = config.with_max_duration(Duration::ZERO);
+        let mut session = file_session(file, config);
+        let first = session.read_range_partial(range).unwrap();
+        assert_eq!(first.pages.len(), PAGE_IDLE_BUF_MIN);
+        assert_eq!(first.resume_at, Some(0x13000));
+
+        let rest = session
+            .read_range_partial(AddressRange::new(0x13000, range.end))
+            .unwrap();
+        assert_eq!(rest.pages.len(), PAGE_IDLE_BUF_MIN);
+        assert_eq!(rest.pages[0].address, 0x13000);
+        assert_eq!(rest.resume_at, Some(0x26000));
+
+        let last = IdlePageScanner::read_from(&mut session, 0x26000).unwrap();
+        assert!(last.pages.is_empty());
+        assert!(last.is_complete());
+    }
+
+    #[test]
+    fn test_scan_anomalies() {
+        use crate::anomaly::Anomaly;
+        use std::os::unix::fs::FileExt;
+
+        // SET_HVA moving the walk back over the pages it just reported
+        let idle = PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 0);
+        let mut data = vec![idle; 4];
+        data.push(PipEncoding::SET_HVA);
+        data.extend_from_slice(&0x2000u64.to_be_bytes());
+        data.push(idle);
+        let file = tempfile::tempfile().unwrap();
+        file.write_all_at(&data, 0).unwrap();
+        let config = ScanConfig::default().with_buffer_size(PAGE_IDLE_BUF_MIN);
+        let mut session = file_session(file, config);
+
+        let pages = IdlePageScanner::read_all(&mut session).unwrap();
+        assert_eq!(pages.len(), 5);
+        assert_eq!(
+            session.anomalies().anomalies,
+            [Anomaly::OverlappingRun {
+                address: 0x2000,
+                covered_to: 0x4000
+            }]
+        );
+        assert_eq!(session.stats().anomalies, 1);
+        assert!(session.stats().to_string().ends_with(", 1 anomalies"));
+
+        session.start_cycle();
+        assert!(session.anomalies().is_clean());
+    }
+
+    #[test]
+    fn test_scan_stamps() {
+        use std::os::unix::fs::FileExt;
+
+        let idle = PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 0);
+        let file = tempfile::tempfile().unwrap();
+        file.write_all_at(&[idle; PAGE_IDLE_BUF_MIN], 0).unwrap();
+        file.write_all_at(&[idle; PAGE_IDLE_BUF_MIN], 0x13000)
+            .unwrap();
+        let config = ScanConfig::default().with_buffer_size(PAGE_IDLE_BUF_MIN);
+        let mut session = file_session(file, config);
+        assert_eq!(session.read_batch(0).unwrap().stamp.cycle, 0);
+
+        // One stamp per read, all in the cycle of the walk
+        let scan = session
+            .read_range_partial(AddressRange::new(0, 0x100000))
+            .unwrap();
+        assert_eq!(session.cycle(), 1);
+        assert_eq!(scan.stamps.len(), 2);
+        let (first, second) = (scan.stamps[0], scan.stamps[1]);
+        assert_eq!((first.0, second.0), (0, PAGE_IDLE_BUF_MIN));
+        assert_eq!((first.1.cycle, second.1.cycle), (1, 1));
+        assert!(first.1.observed_at <= second.1.observed_at);
+        assert_eq!(
+            scan.observed_span(),
+            second.1.observed_at - first.1.observed_at
+        );
+
+        assert_eq!(scan.stamp_of(PAGE_IDLE_BUF_MIN - 1), Some(first.1));
+        assert_eq!(scan.stamp_of(PAGE_IDLE_BUF_MIN), Some(second.1));
+        assert_eq!(scan.stamp_of(2 * PAGE_IDLE_BUF_MIN), None);
+        assert_eq!(scan.stamped().count(), scan.pages.len());
+
+        // Reads finding nothing in the range get no stamp
+        let scan = session
+            .read_range_partial(AddressRange::new(0x13000, 0x100000))
+            .unwrap();
+        assert_eq!(scan.stamps.len(), 1);
+        assert_eq!(scan.stamps[0].1.cycle, 2);
+        assert_eq!(
+            IdlePageScanner::read_from(&mut session, 0).unwrap().stamps[0]
+                .1
+                .cycle,
+            3
+        );
+    }
+
+    #[test]
+    fn test_read_plan() {
+        use std::os::unix::fs::FileExt;
+
+        // Four 64KB slots: the second is idle, the fourth accessed
+        let file = tempfile::tempfile().unwrap();
+        let idle = PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 15);
+        let accessed = PipEncoding::compose(ProcIdlePageType::PteAccessed as u8, 15);
+        file.write_all_at(&[idle], 0x10000).unwrap();
+        file.write_all_at(&[accessed], 0x30000).unwrap();
+
+        let mut plan = SamplePlan::new(0x10000);
+        plan.add_stratum(AddressRange::new(0, 0x40000), 2);
+        let config = ScanConfig::default().with_buffer_size(PAGE_IDLE_BUF_MIN);
+        let mut session = file_session(file, config);
+        let scan = session.read_plan(&plan).unwrap();
+
+        assert_eq!(scan.windows.len(), 2);
+        assert_eq!(scan.windows[0].stats.idle_bytes, 0x10000);
+        assert_eq!(scan.windows[1].stats.accessed_bytes, 0x10000);
+        assert_eq!(scan.pages.len(), 2);
+        assert_eq!(scan.estimate.stats.idle_bytes, 0x20000);
+        assert_eq!(scan.estimate.stats.total_bytes, 0x40000);
+        assert!(scan.estimate.idle_bytes_stderr > 0.0);
+    }
+
+    #[cfg(feature = "failpoints")]
+    #[test]
+    fn test_read_retries_transient_errors() {
+        use crate::failpoints::{self, FailAction, Failpoint};
+
+        let _serial = failpoints::tests::serial();
+        let file = tempfile::tempfile().unwrap();
+        let config = ScanConfig::default().with_retry(
+            RetryPolicy::new()
+                .with_max_retries(3)
+                .with_backoff(Duration::from_micros(10), Duration::from_micros(20)),
+        );
+        let mut session = file_session(file, config);
+
+        failpoints::set_times(Failpoint::ScanRead, FailAction::Errno(libc::EINTR), 2);
+        assert!(session.read(0).unwrap().0.is_empty());
+        failpoints::set_times(Failpoint::ScanRead, FailAction::Errno(libc::EAGAIN), 3);
+        assert!(session.read(0).unwrap().0.is_empty());
+        assert_eq!(session.stats().reads, 2);
+        assert_eq!(session.stats().interrupted, 2);
+        assert_eq!(session.stats().throttled, 3);
+        assert_eq!(session.stats().backoff_time, Duration::from_micros(50));
+
+        // Retries run out
+        failpoints::set_times(Failpoint::ScanRead, FailAction::Errno(libc::EBUSY), 4);
+        assert!(session.read(0).is_err());
+        // Fatal errors are not retried
+        failpoints::set_times(Failpoint::ScanRead, FailAction::Errno(libc::EIO), 2);
+        assert!(session.read(0).is_err());
+        assert_eq!(failpoints::hits(Failpoint::ScanRead), 10);
+        assert_eq!(session.stats().failures, 2);
+        assert_eq!(session.stats().throttled, 6);
+
+        session.reset_stats();
+        assert_eq!(*session.stats(), ScanStats::default());
+        failpoints::clear_all();
+    }
 }
@@ -27,7 +27,7 @@
 //! ```
 
 use crate::error::{EtmemError, Result};
-use crate::scan::ScanSession;
+use crate::scan::{ScanSession, ScanStats};
 use crate::swap::SwapSession;
 use crate::types::{AddressRange, IdlePageInfo, ScanConfig, SwapConfig};
 use crate::vma::{VmaFilter, VmaMap, VmaRegion};
@@ -158,6 +158,24 @@ impl VmaScanResults {
         self.total_accessed_bytes += other.total_accessed_bytes;
         self.total_scanned_bytes += other.total_scanned_bytes;
     }
+
+    /// One-line summary, e.g. `42 regions, 1.30 GB idle (61%), 12 huge`
+    ///
+    /// `huge` counts the huge page entries across all regions.
+    pub fn summary(&self) -> String {
+        let huge = self
+            .per_vma
+            .values()
+            .flatten()
+            .filter(|p| p.page_type.is_huge())
+            .count();
+        format!(
+            "{} regions, {} idle ({:.0}%), {huge} huge",
+            self.per_vma.len(),
+            crate::util::format_bytes(self.total_idle_bytes),
+            self.idle_ratio() * 100.0
+        )
+    }
 }
 
 /// Unified ETMEM session combining scan and swap operations
@@ -253,6 +271,13 @@ impl EtmemSession {
         self.vma_map.as_ref()
     }
 
+    /// Get the read and retry counters of the scan session
+    ///
+    /// Returns `None` until the first scan opens the session.
+    pub fn scan_stats(&self) -> Option<ScanStats> {
+        self.scan_session.as_ref().map(|session| *session.stats())
+    }
+
     /// Get or create the scan session
     fn get_scan_session(&mut self) -> Result<&mut ScanSession> {
         self.ensure_open()?;
@@ -361,8 +386,10 @@ impl EtmemSession {
     pub fn swap_addresses(&mut self, addrs: &[u64]) -> Result<usize> {
         self.ensure_open()?;
         let session = self.get_swap_session()?;
+        let before = session.submitted();
         session.add_addresses(addrs)?;
-        session.flush()
+        session.flush()?;
+        Ok(session.submitted() - before)
     }
 
     /// Swap pages in a specific VMA
@@ -533,6 +560,26 @@ mod tests {
         assert!((results.accessed_ratio() - 0.666).abs() < 0.01);
     }
 
+    #[test]
+    fn test_vma_scan_results_summary() {
+        use crate::types::ProcIdlePageType;
+
+        let mut results = VmaScanResults::new();
+        results.per_vma.insert(
+            AddressRange::new(0x200000, 0x600000),
+            vec![
+                IdlePageInfo::new(0x200000, ProcIdlePageType::PmdIdle, 1),
+                IdlePageInfo::new(0x400000, ProcIdlePageType::PmdAccessed, 1),
+            ],
+        );
+        results
+            .per_vma
+            .insert(AddressRange::new(0x1000, 0x2000), vec![]);
+        results.total_idle_bytes = 2 * 1024 * 1024;
+        results.total_scanned_bytes = 4 * 1024 * 1024;
+        assert_eq!(results.summary(), "2 regions, 2.00 MB idle (50%), 2 huge");
+    }
+
     #[test]
     fn test_scan_and_swap_report() {
         let report = ScanAndSwapReport {
@@ -0,0 +1,556 @@
+//! Idle scanning of shared memory segments
+//!
+//! SysV shared memory, memfd and tmpfs files are mapped by several processes
+//! at once, so a per-process scan attributes the same pages to every mapper
+//! and reports a page as idle in one process while another keeps touching
+//! it. This module picks the shared mappings out of `/proc/[pid]/maps`,
+//! identifies segments by device and inode, and joins the scans of all
+//! mapping processes in file offset space: a page of a segment is idle only
+//! if no mapper accessed it.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::shared::SharedScan;
+//!
+//! let report = SharedScan::new([1234, 1235, 1236])
+//!     .run()
+//!     .expect("Failed to scan shared memory");
+//!
+//! for segment in report.idle_segments(0.9) {
+//!     println!("{}: {} idle, mapped by {:?}",
+//!         segment.name, segment.idle_bytes(), segment.pids);
+//! }
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{EtmemError, Result};
+use crate::scan::ScanSession;
+use crate::types::{AddressRange, IdlePageInfo, ProcIdlePageType, RangeSet, ScanConfig};
+use crate::util::format_bytes;
+use crate::vma::{VmaMap, VmaRegion};
+
+/// Mount point assumed to be tmpfs when `/proc/self/mounts` is unreadable
+const DEFAULT_TMPFS_MOUNT: &str = "/dev/shm";
+
+/// Kind of shared memory segment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SharedKind {
+    /// SysV shared memory (`shmget`), mapped as `/SYSV<key>`
+    SysvShm,
+    /// Anonymous file from `memfd_create`, mapped as `/memfd:<name>`
+    Memfd,
+    /// File on a tmpfs mount (e.g. POSIX shm under `/dev/shm`)
+    Tmpfs,
+    /// `MAP_SHARED | MAP_ANONYMOUS` mapping, backed by `/dev/zero`
+    SharedAnonymous,
+}
+
+impl SharedKind {
+    /// Get a short name for the kind
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::SysvShm => "sysv",
+            Self::Memfd => "memfd",
+            Self::Tmpfs => "tmpfs",
+            Self::SharedAnonymous => "anon",
+        }
+    }
+}
+
+impl fmt::Display for SharedKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// A shared segment and its idle state across all mapping processes
+///
+/// Ranges are file offsets within the segment, not virtual addresses.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharedSegment {
+    /// Device number (major:minor) of the backing inode
+    pub device: String,
+    /// Backing inode number
+    pub inode: u64,
+    /// Segment name (pathname without the ` (deleted)` suffix)
+    pub name: String,
+    /// Kind of segment
+    pub kind: SharedKind,
+    /// Processes mapping the segment
+    pub pids: BTreeSet<u32>,
+    /// Offsets mapped by at least one process
+    pub mapped: RangeSet,
+    /// Offsets reported present by at least one scan
+    pub present: RangeSet,
+    /// Offsets reported accessed or dirty by at least one scan
+    pub hot: RangeSet,
+}
+
+impl SharedSegment {
+    /// Create an empty segment for a mapping
+    fn for_vma(vma: &VmaRegion, kind: SharedKind) -> Self {
+        Self {
+            device: vma.device.clone(),
+            inode: vma.inode,
+            name: segment_name(vma).to_string(),
+            kind,
+            pids: BTreeSet::new(),
+            mapped: RangeSet::new(),
+            present: RangeSet::new(),
+            hot: RangeSet::new(),
+        }
+    }
+
+    /// Get the offsets no mapping process accessed
+    pub fn idle_offsets(&self) -> RangeSet {
+        self.present.difference(&self.hot)
+    }
+
+    /// Get the bytes mapped by at least one process
+    pub fn mapped_bytes(&self) -> u64 {
+        self.mapped.total_size()
+    }
+
+    /// Get the bytes no mapping process accessed
+    pub fn idle_bytes(&self) -> u64 {
+        self.idle_offsets().total_size()
+    }
+
+    /// Get the bytes accessed by at least one mapping process
+    pub fn hot_bytes(&self) -> u64 {
+        self.hot.total_size()
+    }
+
+    /// Calculate idle ratio of present memory (0.0 - 1.0)
+    pub fn idle_ratio(&self) -> f64 {
+        let present = self.present.total_size();
+        if present == 0 {
+            0.0
+        } else {
+            self.idle_bytes() as f64 / present as f64
+        }
+    }
+}
+
+/// Shared segments found across a set of processes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SharedReport {
+    /// Segments ordered by device and inode
+    pub segments: Vec<SharedSegment>,
+    /// Processes whose maps were joined into the report
+    pub pids: BTreeSet<u32>,
+}
+
+impl SharedReport {
+    /// Create an empty report
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the scan of one shared mapping of a process
+    ///
+    /// Scan entries are clipped to the mapping and translated to file
+    /// offsets; entries outside the mapping are ignored.
+    pub fn add_mapping(
+        &mut self,
+        pid: u32,
+        vma: &VmaRegion,
+        kind: SharedKind,
+        pages: &[IdlePageInfo],
+    ) {
+        self.pids.insert(pid);
+        let idx = match self.find(&vma.device, vma.inode) {
+            Ok(idx) => idx,
+            Err(idx) => {
+                self.segments.insert(idx, SharedSegment::for_vma(vma, kind));
+                idx
+            }
+        };
+        let segment = &mut self.segments[idx];
+
+        segment.pids.insert(pid);
+        segment
+            .mapped
+            .insert(file_offsets(vma, vma.to_address_range()));
+
+        for page in pages {
+            let range = AddressRange::new(page.address, page.end_address());
+            let Some(overlap) = range.intersection(&vma.to_address_range()) else {
+                continue;
+            };
+            if page.page_type.is_hole() {
+                continue;
+            }
+            let offsets = file_offsets(vma, overlap);
+            segment.present.insert(offsets);
+            if is_hot(page.page_type) {
+                segment.hot.insert(offsets);
+            }
+        }
+    }
+
+    /// Get the segments whose idle ratio is at least `min_ratio`, sorted by
+    /// idle bytes (descending)
+    pub fn idle_segments(&self, min_ratio: f64) -> Vec<&SharedSegment> {
+        let mut idle: Vec<&SharedSegment> = self
+            .segments
+            .iter()
+            .filter(|s| s.idle_bytes() > 0 && s.idle_ratio() >= min_ratio)
+            .collect();
+        idle.sort_by_key(|s| std::cmp::Reverse(s.idle_bytes()));
+        idle
+    }
+
+    /// Find the segment backed by an inode
+    pub fn segment(&self, device: &str, inode: u64) -> Option<&SharedSegment> {
+        self.find(device, inode).ok().map(|idx| &self.segments[idx])
+    }
+
+    /// Binary search the segments by device and inode
+    fn find(&self, device: &str, inode: u64) -> std::result::Result<usize, usize> {
+        self.segments
+            .binary_search_by(|s| (s.device.as_str(), s.inode).cmp(&(device, inode)))
+    }
+
+    /// Total idle bytes across all segments, each counted once
+    pub fn total_idle_bytes(&self) -> u64 {
+        self.segments.iter().map(SharedSegment::idle_bytes).sum()
+    }
+
+    /// Total hot bytes across all segments, each counted once
+    pub fn total_hot_bytes(&self) -> u64 {
+        self.segments.iter().map(SharedSegment::hot_bytes).sum()
+    }
+}
+
+impl fmt::Display for SharedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Shared segments across {} processes:", self.pids.len())?;
+        writeln!(
+            f,
+            "  {:<32} {:<6} {:>12} {:>12} {:>7}  PIDs",
+            "Segment", "Kind", "Idle", "Hot", "Idle%"
+        )?;
+        for segment in &self.segments {
+            let pids: Vec<String> = segment.pids.iter().map(u32::to_string).collect();
+            writeln!(
+                f,
+                "  {:<32} {:<6} {:>12} {:>12} {:>6.1}%  {}",
+                segment.name,
+                segment.kind,
+                format_bytes(segment.idle_bytes()),
+                format_bytes(segment.hot_bytes()),
+                segment.idle_ratio() * 100.0,
+                pids.join(",")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Shared memory scan over a set of processes
+#[derive(Debug, Clone)]
+pub struct SharedScan {
+    /// Processes to scan
+    pids: Vec<u32>,
+    /// Scan configuration used for every process
+    config: ScanConfig,
+    /// Mount points whose files count as tmpfs segments
+    tmpfs_mounts: Vec<PathBuf>,
+}
+
+impl SharedScan {
+    /// Create a scan of the given processes
+    ///
+    /// tmpfs mount points are read from `/proc/self/mounts`, falling back to
+    /// `/dev/shm` if it cannot be read.
+    pub fn new<I: IntoIterator<Item = u32>>(pids: I) -> Self {
+        let tmpfs_mounts = tmpfs_mounts().unwrap_or_else(|e| {
+            log::debug!("Falling back to {}: {}", DEFAULT_TMPFS_MOUNT, e);
+            vec![PathBuf::from(DEFAULT_TMPFS_MOUNT)]
+        });
+        Self {
+            pids: pids.into_iter().collect(),
+            config: ScanConfig::default(),
+            tmpfs_mounts,
+        }
+    }
+
+    /// Set the scan configuration
+    pub fn with_scan_config(mut self, config: ScanConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Set the mount points whose files count as tmpfs segments
+    pub fn with_tmpfs_mounts<I: IntoIterator<Item = PathBuf>>(mut self, mounts: I) -> Self {
+        self.tmpfs_mounts = mounts.into_iter().collect();
+        self
+    }
+
+    /// Classify a mapping, `None` if it is not a shared memory segment
+    ///
+    /// Only `MAP_SHARED` mappings qualify; private mappings of the same
+    /// files are copy-on-write and belong to the process.
+    pub fn classify(&self, vma: &VmaRegion) -> Option<SharedKind> {
+        if !vma.permissions.shared {
+            return None;
+        }
+        let name = segment_name(vma);
+        if name.starts_with("/SYSV") {
+            Some(SharedKind::SysvShm)
+        } else if name.starts_with("/memfd:") {
+            Some(SharedKind::Memfd)
+        } else if name == "/dev/zero" {
+            Some(SharedKind::SharedAnonymous)
+        } else if self
+            .tmpfs_mounts
+            .iter()
+            .any(|mount| Path::new(name).starts_with(mount))
+        {
+            Some(SharedKind::Tmpfs)
+        } else {
+            None
+        }
+    }
+
+    /// Get the readable shared memory mappings of a process
+    pub fn shared_mappings<'a>(&self, vma_map: &'a VmaMap) -> Vec<(&'a VmaRegion, SharedKind)> {
+        vma_map
+            .regions()
+            .iter()
+            .filter(|vma| vma.permissions.read)
+            .filter_map(|vma| Some((vma, self.classify(vma)?)))
+            .collect()
+    }
+
+    /// Scan the shared mappings of every process and join the results
+    ///
+    /// Processes that exit during the scan are skipped.
+    ///
+    /// # Errors
+    /// Returns the first error other than `ProcessNotFound` raised while
+    /// reading maps or scanning.
+    pub fn run(&self) -> Result<SharedReport> {
+        let mut report = SharedReport::new();
+        for &pid in &self.pids {
+            match self.scan_process(pid, &mut report) {
+                Ok(()) => {}
+                Err(EtmemError::ProcessNotFound) => {
+                    log::debug!("PID {} exited, skipping shared scan", pid);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Scan the shared mappings of one process into `report`
+    fn scan_process(&self, pid: u32, report: &mut SharedReport) -> Result<()> {
+        let vma_map = VmaMap::for_process(pid)?;
+        let mappings = self.shared_mappings(&vma_map);
+        if mappings.is_empty() {
+            return Ok(());
+        }
+
+        let mut session = ScanSession::new(pid, self.config.clone())?;
+        for (vma, kind) in mappings {
+            let pages = session.read_range(vma.to_address_range())?;
+            report.add_mapping(pid, vma, kind, &pages);
+        }
+        Ok(())
+    }
+}
+
+/// Get the tmpfs mount points from `/proc/self/mounts`
+///
+/// # Errors
+/// Returns a procfs error if the mount table cannot be read.
+pub fn tmpfs_mounts() -> Result<Vec<PathBuf>> {
+    let content = std::fs::read_to_string("/proc/self/mounts")
+        .map_err(|e| EtmemError::ProcfsError(format!("/proc/self/mounts: {}", e)))?;
+    Ok(parse_tmpfs_mounts(&content))
+}
+
+/// Parse tmpfs mount points out of mount table content
+fn parse_tmpfs_mounts(content: &str) -> Vec<PathBuf> {
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let mount = fields.nth(1)?;
+            (fields.next()? == "tmpfs").then(|| PathBuf::from(unescape_mount(mount)))
+        })
+        .collect()
+}
+
+/// Decode the octal escapes (`\040` for space, ...) of a mount table path
+fn unescape_mount(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    let mut rest = path;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        let code = rest.get(pos + 1..pos + 4);
+        match code.and_then(|c| u8::from_str_radix(c, 8).ok()) {
+            Some(byte) => {
+                out.push(byte as char);
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Segment name of a mapping, without the ` (deleted)` suffix
+fn segment_name(vma: &VmaRegion) -> &str {
+    let name = vma.name();
+    name.strip_suffix(" (deleted)").unwrap_or(name)
+}
+
+/// Check if a page type counts as accessed
+fn is_hot(page_type: ProcIdlePageType) -> bool {
+    page_type.is_accessed()
+        || matches!(
+            page_type,
+            ProcIdlePageType::PteDirty | ProcIdlePageType::PmdDirty
+        )
+}
+
+/// Translate a virtual address range within a mapping to file offsets
+fn file_offsets(vma: &VmaRegion, range: AddressRange) -> AddressRange {
+    let base = vma.offset.wrapping_sub(vma.start);
+    AddressRange::new(range.start.wrapping_add(base), range.end.wrapping_add(base))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_maps(pid: u32, maps: &str) -> VmaMap {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("maps");
+        std::fs::write(&path, maps).unwrap();
+        VmaMap::from_file(&path, pid).unwrap()
+    }
+
+    fn test_scan() -> SharedScan {
+        SharedScan::new([]).with_tmpfs_mounts([PathBuf::from("/dev/shm")])
+    }
+
+    #[test]
+    fn test_classify() {
+        let map = test_maps(
+            1,
+            "\
+00400000-00402000 r-xp 00000000 08:01 100 /usr/bin/app
+7f0000000000-7f0000100000 rw-s 00000000 00:01 32768 /SYSV0000162e (deleted)
+7f0000100000-7f0000200000 rw-s 00000000 00:01 1025 /memfd:pool (deleted)
+7f0000200000-7f0000300000 rw-s 00000000 00:19 7 /dev/shm/ring
+7f0000300000-7f0000400000 rw-s 00000000 00:01 2048 /dev/zero (deleted)
+7f0000400000-7f0000500000 rw-p 00000000 00:19 7 /dev/shm/ring
+7f0000500000-7f0000600000 rw-s 00000000 08:01 300 /var/lib/data.db
+7f0000600000-7f0000700000 ---s 00000000 00:01 1026 /memfd:guard (deleted)
+",
+        );
+        let scan = test_scan();
+        let kinds: Vec<(&str, SharedKind)> = scan
+            .shared_mappings(&map)
+            .into_iter()
+            .map(|(vma, kind)| (segment_name(vma), kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("/SYSV0000162e", SharedKind::SysvShm),
+                ("/memfd:pool", SharedKind::Memfd),
+                ("/dev/shm/ring", SharedKind::Tmpfs),
+                ("/dev/zero", SharedKind::SharedAnonymous),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedup_across_processes() {
+        // Two processes map the same SysV segment at different addresses;
+        // the second maps only its upper half
+        let a = test_maps(
+            10,
+            "7f0000000000-7f0000004000 rw-s 00000000 00:01 32768 /SYSV0000162e (deleted)\n",
+        );
+        let b = test_maps(
+            20,
+            "7e0000000000-7e0000002000 rw-s 00002000 00:01 32768 /SYSV0000162e (deleted)\n",
+        );
+
+        let mut report = SharedReport::new();
+        report.add_mapping(
+            10,
+            &a.regions()[0],
+            SharedKind::SysvShm,
+            &[IdlePageInfo::new(
+                0x7f0000000000,
+                ProcIdlePageType::PteIdle,
+                4,
+            )],
+        );
+        report.add_mapping(
+            20,
+            &b.regions()[0],
+            SharedKind::SysvShm,
+            &[
+                IdlePageInfo::new(0x7e0000000000, ProcIdlePageType::PteAccessed, 1),
+                IdlePageInfo::new(0x7e0000001000, ProcIdlePageType::PteHole, 1),
+            ],
+        );
+
+        assert_eq!(report.segments.len(), 1);
+        let segment = report.segment("00:01", 32768).unwrap();
+        assert_eq!(segment.pids, BTreeSet::from([10, 20]));
+        assert_eq!(segment.mapped_bytes(), 4 * 4096);
+        // Offset 0x2000 is idle in pid 10 but accessed in pid 20
+        assert_eq!(segment.hot_bytes(), 4096);
+        assert_eq!(segment.idle_bytes(), 3 * 4096);
+        assert_eq!(
+            segment.idle_offsets().ranges(),
+            &[
+                AddressRange::new(0, 0x2000),
+                AddressRange::new(0x3000, 0x4000)
+            ]
+        );
+        assert_eq!(report.total_idle_bytes(), 3 * 4096);
+
+        assert_eq!(report.idle_segments(0.5).len(), 1);
+        assert!(report.idle_segments(0.8).is_empty());
+        assert!(report.to_string().contains("10,20"));
+    }
+
+    #[test]
+    fn test_parse_tmpfs_mounts() {
+        let mounts = "\
+sysfs /sys sysfs rw,nosuid 0 0
+tmpfs /dev/shm tmpfs rw,nosuid,nodev 0 0
+/dev/sda1 / ext4 rw 0 0
+tmpfs /run/user/1000 tmpfs rw 0 0
+tmpfs /mnt/with\\040space tmpfs rw 0 0
+";
+        assert_eq!(
+            parse_tmpfs_mounts(mounts),
+            [
+                PathBuf::from("/dev/shm"),
+                PathBuf::from("/run/user/1000"),
+                PathBuf::from("/mnt/with space"),
+            ]
+        );
+    }
+}
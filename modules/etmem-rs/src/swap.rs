@@ -3,12 +3,26 @@
 //! This module provides safe wrappers around the kernel's page swapping
 //! functionality. It allows reclaiming "cold" memory pages by swapping
 //! them out to secondary storage.
+//!
+//! Unless [`SwapConfig::exact_order`] is set, queued addresses are sorted
+//! and deduplicated before they count against a batch and before every
+//! flush, so repeated addresses do not take batch slots and the kernel
+//! walks each batch in address order.
 
 use std::fmt::Write as _;
+use std::time::{Duration, Instant};
 
 use crate::error::{EtmemError, Result};
+use crate::freeze::FreezeDetector;
+use crate::guard::{GuardDecision, SwapGuard};
 use crate::sys::ProcfsHandle;
-use crate::types::{SwapConfig, SwapcacheWatermark, WatermarkConfig};
+use crate::types::{
+    AddressRange, BASE_PAGE_SIZE, HUGE_PAGE_SIZE, HugePagePolicy, IdlePageInfo, SwapConfig,
+    SwapcacheWatermark, WatermarkConfig, WatermarkStatus,
+};
+
+/// How long to wait for the reclaim kernel thread to appear after enabling
+const RECLAIM_THREAD_START_TIMEOUT: Duration = Duration::from_millis(500);
 
 /// Safe wrapper for page swapping session
 ///
@@ -24,6 +38,12 @@ pub struct SwapSession {
     pid: u32,
     /// List of virtual addresses to swap (accumulated)
     pending_addrs: Vec<u64>,
+    /// Optional swap pressure guard consulted before each flush
+    guard: Option<SwapGuard>,
+    /// Optional detector deferring flushes while the process is frozen
+    freeze: Option<FreezeDetector>,
+    /// Addresses written to the kernel so far, auto-flushes included
+    submitted: usize,
 }
 
 impl SwapSession {
@@ -59,13 +79,47 @@ impl SwapSession {
             config,
             pid,
             pending_addrs: Vec::new(),
+            guard: None,
+            freeze: None,
+            submitted: 0,
         })
     }
 
+    /// Attach a swap pressure guard to this session
+    ///
+    /// The guard is consulted before every flush. A `Throttle` decision
+    /// delays the write; a `Refuse` decision fails the flush with
+    /// `ReclaimRefused` and drops the pending addresses, so a refused round
+    /// does not carry over into the next one.
+    pub fn with_guard(mut self, guard: SwapGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Set or clear the swap pressure guard
+    pub fn set_guard(&mut self, guard: Option<SwapGuard>) {
+        self.guard = guard;
+    }
+
+    /// Get the swap pressure guard, if any
+    pub fn guard(&self) -> Option<&SwapGuard> {
+        self.guard.as_ref()
+    }
+
+    /// Defer reclaim while the process is stopped or frozen for a checkpoint
+    ///
+    /// The detector is consulted before every flush, ahead of the guard.
+    /// While the process is frozen the flush fails with `ReclaimRefused`
+    /// and keeps the pending addresses, so it can be retried later.
+    pub fn with_freeze_detector(mut self, detector: FreezeDetector) -> Self {
+        self.freeze = Some(detector);
+        self
+    }
+
     /// Add a virtual address to the swap list
     ///
     /// The address will be buffered and swapped when `flush()` is called
-    /// or when the buffer reaches capacity.
+    /// or when the buffer reaches `max_pages` distinct addresses.
     ///
     /// # Errors
     /// Returns error if the address is not page-aligned.
@@ -77,9 +131,12 @@ impl SwapSession {
 
         self.pending_addrs.push(addr);
 
-        // Auto-flush if we reach the max
+        // Auto-flush if we reach the max, not counting duplicates
         if self.pending_addrs.len() >= self.config.max_pages as usize {
-            self.flush()?;
+            self.normalize_pending();
+            if self.pending_addrs.len() >= self.config.max_pages as usize {
+                self.flush()?;
+            }
         }
 
         Ok(())
@@ -95,6 +152,35 @@ impl SwapSession {
         Ok(())
     }
 
+    /// Add every page of an address range to the swap list
+    ///
+    /// `granularity` is the page size to step by: `BASE_PAGE_SIZE` submits
+    /// every 4KB address, `HUGE_PAGE_SIZE` submits one PMD-aligned address
+    /// per 2MB page. Returns the number of addresses queued.
+    ///
+    /// # Errors
+    /// Returns error if the range is invalid, the granularity is not a
+    /// supported page size, or the range is not aligned to it.
+    pub fn add_range(&mut self, range: AddressRange, granularity: u64) -> Result<usize> {
+        let mut count = 0;
+        for addr in range_addresses(range, granularity)? {
+            self.add_address(addr)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Add scanned pages to the swap list, honoring their page types
+    ///
+    /// Only idle entries are queued. Base pages are expanded to every 4KB
+    /// address; huge pages are submitted according to `policy`. Returns
+    /// the number of addresses queued.
+    pub fn add_pages(&mut self, pages: &[IdlePageInfo], policy: HugePagePolicy) -> Result<usize> {
+        let addrs = page_addresses(pages, policy)?;
+        self.add_addresses(&addrs)?;
+        Ok(addrs.len())
+    }
+
     /// Flush pending addresses to the kernel
     ///
     /// This writes the buffered addresses to `/proc/[pid]/swap_pages`
@@ -104,11 +190,42 @@ impl SwapSession {
     /// Returns error if:
     /// - I/O error occurs
     /// - Kernel rejects the addresses
+    /// - The attached guard refuses reclaim
+    /// - The process is frozen and a freeze detector is attached
     pub fn flush(&mut self) -> Result<usize> {
         if self.pending_addrs.is_empty() {
             return Ok(0);
         }
 
+        if let Some(detector) = &self.freeze {
+            let state = detector.state(self.pid)?;
+            if state.is_frozen() {
+                return Err(EtmemError::ReclaimRefused(format!(
+                    "pid {} is {}",
+                    self.pid, state
+                )));
+            }
+        }
+
+        // Consult the pressure guard before touching the kernel
+        if let Some(guard) = self.guard.as_mut() {
+            match guard.check()? {
+                GuardDecision::Allow => {}
+                GuardDecision::Throttle(delay) => std::thread::sleep(delay),
+                GuardDecision::Refuse(reason) => {
+                    self.pending_addrs.clear();
+                    return Err(EtmemError::ReclaimRefused(reason));
+                }
+            }
+        }
+
+        self.normalize_pending();
+        log::debug!(
+            "Submitting {} addresses for pid {}",
+            self.pending_addrs.len(),
+            self.pid
+        );
+
         // Format addresses as newline-separated hex strings
         let mut buf = String::new();
         for addr in &self.pending_addrs {
@@ -130,10 +247,18 @@ impl SwapSession {
 
         let count = self.pending_addrs.len();
         self.pending_addrs.clear();
+        self.submitted += count;
 
         Ok(count)
     }
 
+    /// Sort and deduplicate the pending addresses unless exact order is requested
+    fn normalize_pending(&mut self) {
+        if !self.config.exact_order {
+            sort_dedup(&mut self.pending_addrs);
+        }
+    }
+
     /// Swap a single address immediately
     ///
     /// Convenience method that adds an address and flushes immediately.
@@ -143,6 +268,15 @@ impl SwapSession {
         Ok(())
     }
 
+    /// Get the number of addresses submitted to the kernel so far
+    ///
+    /// Counts every flush, including those triggered by `add_address`
+    /// when the batch fills up. Addresses dropped by a guard refusal are
+    /// not counted.
+    pub fn submitted(&self) -> usize {
+        self.submitted
+    }
+
     /// Get the number of pending addresses
     pub fn pending_count(&self) -> usize {
         self.pending_addrs.len()
@@ -171,41 +305,46 @@ impl SwapSession {
     /// Set proactive reclaim watermark
     ///
     /// Configures the kernel's proactive swapcache reclaim watermarks
-    /// via IOCTL.
+    /// via IOCTL. If the kernel supports read-back, the stored values are
+    /// verified against what was written.
     ///
     /// # Errors
-    /// Returns error if the watermark is invalid or IOCTL fails.
+    /// Returns error if the watermark is invalid, IOCTL fails, or the
+    /// kernel reports different values than were written.
     pub fn set_watermark(&mut self, watermark: WatermarkConfig) -> Result<()> {
-        watermark.validate()?;
-
-        // Set low watermark
-        unsafe {
-            crate::sys::set_swapcache_watermark(
-                &self.handle,
-                SwapcacheWatermark::Low as u32,
-                watermark.low_percent as u32,
-            )?;
-
-            // Set high watermark
-            crate::sys::set_swapcache_watermark(
-                &self.handle,
-                SwapcacheWatermark::High as u32,
-                watermark.high_percent as u32,
-            )?;
-        }
-
+        write_watermark(&self.handle, watermark)?;
         self.config.watermark = watermark;
         Ok(())
     }
 
+    /// Read the proactive reclaim watermarks back from the kernel
+    ///
+    /// # Errors
+    /// Returns `NotSupported` if the kernel has no watermark read-back
+    /// IOCTL, or an I/O error if the IOCTL fails otherwise.
+    pub fn get_watermark(&self) -> Result<WatermarkConfig> {
+        read_watermark(&self.handle)
+    }
+
+    /// Get the current watermark status
+    ///
+    /// Combines the kernel watermarks (or the last configured values if
+    /// read-back is unsupported) with swapcache occupancy from
+    /// `/proc/meminfo` and the reclaim thread state.
+    pub fn watermark_status(&self) -> Result<WatermarkStatus> {
+        watermark_status(&self.handle, self.config.watermark)
+    }
+
     /// Enable proactive swapcache reclaim
     ///
     /// This starts a kernel thread that proactively reclaims swapcache
     /// pages when they exceed the configured watermarks.
+    ///
+    /// # Errors
+    /// Returns error if the IOCTL fails or the reclaim thread does not
+    /// appear shortly after enabling.
     pub fn enable_proactive_reclaim(&mut self) -> Result<()> {
-        unsafe {
-            crate::sys::enable_swapcache_reclaim(&self.handle)?;
-        }
+        enable_reclaim(&self.handle)?;
         self.config.proactive_reclaim = true;
         Ok(())
     }
@@ -255,10 +394,12 @@ impl PageSwapper {
     /// Swap multiple pages in a process
     ///
     /// Creates a temporary swap session and swaps multiple addresses.
+    /// Returns the number of addresses submitted to the kernel.
     pub fn swap_pages(pid: u32, addrs: &[u64]) -> Result<usize> {
         let mut session = SwapSession::new(pid, SwapConfig::default())?;
         session.add_addresses(addrs)?;
-        session.flush()
+        session.flush()?;
+        Ok(session.submitted())
     }
 
     /// Configure proactive reclaim for a process
@@ -323,6 +464,262 @@ impl SwapcacheConfig {
     }
 }
 
+/// Sort addresses and drop duplicates
+fn sort_dedup(addrs: &mut Vec<u64>) {
+    addrs.sort_unstable();
+    addrs.dedup();
+}
+
+/// Step through the page addresses of a range by `granularity`
+fn range_addresses(range: AddressRange, granularity: u64) -> Result<impl Iterator<Item = u64>> {
+    if !range.is_valid() {
+        return Err(EtmemError::InvalidRange);
+    }
+    if granularity != BASE_PAGE_SIZE && granularity != HUGE_PAGE_SIZE {
+        return Err(EtmemError::InvalidRange);
+    }
+    if !range.start.is_multiple_of(granularity) || !range.end.is_multiple_of(granularity) {
+        return Err(EtmemError::InvalidAddress);
+    }
+
+    Ok((range.start..range.end).step_by(granularity as usize))
+}
+
+/// Expand idle scan entries into swap addresses according to `policy`
+fn page_addresses(pages: &[IdlePageInfo], policy: HugePagePolicy) -> Result<Vec<u64>> {
+    let mut addrs = Vec::new();
+
+    for page in pages.iter().filter(|p| p.is_idle()) {
+        if !page.page_type.is_huge() {
+            let range = AddressRange::new(page.address, page.end_address());
+            addrs.extend(range_addresses(range, BASE_PAGE_SIZE)?);
+            continue;
+        }
+
+        // Huge entries may be reported at an unaligned address inside the PMD
+        let start = crate::util::huge_page_align_down(page.address);
+        let range = AddressRange::new(start, start + page.total_size());
+        match policy {
+            HugePagePolicy::Whole => addrs.extend(range_addresses(range, HUGE_PAGE_SIZE)?),
+            HugePagePolicy::Split => addrs.extend(range_addresses(range, BASE_PAGE_SIZE)?),
+            HugePagePolicy::Skip => {}
+        }
+    }
+
+    Ok(addrs)
+}
+
+/// Write both watermarks and verify them if the kernel supports read-back
+fn write_watermark(handle: &ProcfsHandle, watermark: WatermarkConfig) -> Result<()> {
+    watermark.validate()?;
+
+    // Set low watermark
+    unsafe {
+        crate::sys::set_swapcache_watermark(
+            handle,
+            SwapcacheWatermark::Low as u32,
+            watermark.low_percent as u32,
+        )?;
+
+        // Set high watermark
+        crate::sys::set_swapcache_watermark(
+            handle,
+            SwapcacheWatermark::High as u32,
+            watermark.high_percent as u32,
+        )?;
+    }
+
+    match read_watermark(handle) {
+        Ok(actual) if actual != watermark => Err(EtmemError::SwapFailed(format!(
+            "Kernel watermark {}/{} does not match requested {}/{}",
+            actual.low_percent, actual.high_percent, watermark.low_percent, watermark.high_percent
+        ))),
+        Ok(_) | Err(EtmemError::NotSupported) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Read both watermarks back from the kernel
+fn read_watermark(handle: &ProcfsHandle) -> Result<WatermarkConfig> {
+    let read = |level: SwapcacheWatermark| -> Result<u8> {
+        let percent = unsafe { crate::sys::get_swapcache_watermark(handle, level as u32) }
+            .map_err(watermark_read_error)?;
+        u8::try_from(percent).map_err(|_| EtmemError::WatermarkOutOfRange)
+    };
+
+    Ok(WatermarkConfig::new(
+        read(SwapcacheWatermark::Low)?,
+        read(SwapcacheWatermark::High)?,
+    ))
+}
+
+/// Map a failed watermark read-back
+///
+/// Kernels without the read-back command reject it with `ENOTTY` or
+/// `EINVAL`; both mean the watermark cannot be confirmed.
+fn watermark_read_error(e: std::io::Error) -> EtmemError {
+    match e.raw_os_error() {
+        Some(libc::ENOTTY | libc::EINVAL) => EtmemError::NotSupported,
+        _ => EtmemError::from(e),
+    }
+}
+
+/// Build a watermark status, falling back to `configured` without read-back
+fn watermark_status(handle: &ProcfsHandle, configured: WatermarkConfig) -> Result<WatermarkStatus> {
+    let (watermark, kernel_confirmed) = match read_watermark(handle) {
+        Ok(wm) => (wm, true),
+        Err(EtmemError::NotSupported) => (configured, false),
+        Err(e) => return Err(e),
+    };
+    let meminfo = crate::guard::MemInfo::read()?;
+
+    Ok(WatermarkStatus {
+        watermark,
+        kernel_confirmed,
+        swapcache_kb: meminfo.swap_cached_kb,
+        total_ram_kb: meminfo.mem_total_kb,
+        reclaim_active: crate::sys::kernel_thread_running(crate::sys::SWAPCACHE_RECLAIM_THREAD),
+    })
+}
+
+/// Enable reclaim and wait for the kernel thread to appear
+fn enable_reclaim(handle: &ProcfsHandle) -> Result<()> {
+    unsafe {
+        crate::sys::enable_swapcache_reclaim(handle)?;
+    }
+
+    let deadline = Instant::now() + RECLAIM_THREAD_START_TIMEOUT;
+    while !crate::sys::kernel_thread_running(crate::sys::SWAPCACHE_RECLAIM_THREAD) {
+        if Instant::now() >= deadline {
+            return Err(EtmemError::SwapFailed(format!(
+                "Reclaim thread {} did not start",
+                crate::sys::SWAPCACHE_RECLAIM_THREAD
+            )));
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+
+/// Swapcache reclaim statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReclaimStats {
+    /// Current swapcache size in KB
+    pub swapcache_kb: u64,
+    /// Free swap space in KB
+    pub swap_free_kb: u64,
+    /// Pages swapped out since the controller was opened
+    pub pages_swapped_out: u64,
+    /// Pages swapped in since the controller was opened
+    pub pages_swapped_in: u64,
+    /// Whether the proactive reclaim kernel thread is running
+    pub reclaim_active: bool,
+}
+
+/// Global swapcache reclaim controller
+///
+/// The swapcache reclaim IOCTLs act system-wide but are issued on a
+/// `swap_pages` file. This controller uses the calling process's own
+/// `/proc/self/swap_pages`, so no unrelated target PID is needed.
+///
+/// # Example
+/// ```no_run
+/// use etmem_rs::{SwapcacheController, WatermarkConfig};
+///
+/// let mut controller = SwapcacheController::open()
+///     .expect("Failed to open swapcache controller");
+/// controller.set_watermark(WatermarkConfig::new(30, 70))
+///     .expect("Failed to set watermark");
+/// controller.enable().expect("Failed to enable reclaim");
+///
+/// let stats = controller.stats().expect("Failed to read stats");
+/// println!("Swapcache: {} KB", stats.swapcache_kb);
+/// ```
+#[derive(Debug)]
+pub struct SwapcacheController {
+    /// Handle on `/proc/self/swap_pages`
+    handle: ProcfsHandle,
+    /// Last watermark written through this controller
+    watermark: WatermarkConfig,
+    /// `pswpin`/`pswpout` counters when the controller was opened
+    baseline: (u64, u64),
+}
+
+impl SwapcacheController {
+    /// Open the global swapcache reclaim interface
+    ///
+    /// # Errors
+    /// Returns error if the ETMEM swap module is not loaded or permission
+    /// is denied.
+    pub fn open() -> Result<Self> {
+        let handle = unsafe { ProcfsHandle::open_self_swap_pages()? };
+        Ok(Self {
+            handle,
+            watermark: WatermarkConfig::default(),
+            baseline: read_swap_counters()?,
+        })
+    }
+
+    /// Set proactive reclaim watermarks
+    pub fn set_watermark(&mut self, watermark: WatermarkConfig) -> Result<()> {
+        write_watermark(&self.handle, watermark)?;
+        self.watermark = watermark;
+        Ok(())
+    }
+
+    /// Read the proactive reclaim watermarks back from the kernel
+    pub fn get_watermark(&self) -> Result<WatermarkConfig> {
+        read_watermark(&self.handle)
+    }
+
+    /// Get the current watermark status
+    pub fn status(&self) -> Result<WatermarkStatus> {
+        watermark_status(&self.handle, self.watermark)
+    }
+
+    /// Enable proactive swapcache reclaim and verify the thread started
+    pub fn enable(&mut self) -> Result<()> {
+        enable_reclaim(&self.handle)
+    }
+
+    /// Disable proactive swapcache reclaim
+    pub fn disable(&mut self) -> Result<()> {
+        unsafe {
+            crate::sys::disable_swapcache_reclaim(&self.handle)?;
+        }
+        Ok(())
+    }
+
+    /// Report reclaim statistics since the controller was opened
+    pub fn stats(&self) -> Result<ReclaimStats> {
+        let meminfo = crate::guard::MemInfo::read()?;
+        let (pswpin, pswpout) = read_swap_counters()?;
+
+        Ok(ReclaimStats {
+            swapcache_kb: meminfo.swap_cached_kb,
+            swap_free_kb: meminfo.swap_free_kb,
+            pages_swapped_out: pswpout.saturating_sub(self.baseline.1),
+            pages_swapped_in: pswpin.saturating_sub(self.baseline.0),
+            reclaim_active: crate::sys::kernel_thread_running(crate::sys::SWAPCACHE_RECLAIM_THREAD),
+        })
+    }
+}
+
+/// Read the cumulative `pswpin`/`pswpout` counters from `/proc/vmstat`
+fn read_swap_counters() -> Result<(u64, u64)> {
+    let content = std::fs::read_to_string(crate::guard::PROC_VMSTAT)
+        .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", crate::guard::PROC_VMSTAT, e)))?;
+    parse_swap_counters(&content)
+}
+
+/// Parse the `pswpin`/`pswpout` counters from `/proc/vmstat` content
+fn parse_swap_counters(content: &str) -> Result<(u64, u64)> {
+    Ok((
+        crate::guard::parse_vmstat_field(content, "pswpin")?,
+        crate::guard::parse_vmstat_field(content, "pswpout")?,
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -346,10 +743,175 @@ mod tests {
         assert!(invalid.validate().is_err());
     }
 
+    #[test]
+    fn test_watermark_read_error() {
+        for errno in [libc::ENOTTY, libc::EINVAL] {
+            let e = std::io::Error::from_raw_os_error(errno);
+            assert!(matches!(watermark_read_error(e), EtmemError::NotSupported));
+        }
+        let e = std::io::Error::from_raw_os_error(libc::EACCES);
+        assert!(!matches!(watermark_read_error(e), EtmemError::NotSupported));
+    }
+
+    #[test]
+    fn test_range_addresses() {
+        let range = AddressRange::new(0x1000, 0x4000);
+        assert_eq!(
+            range_addresses(range, BASE_PAGE_SIZE)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![0x1000, 0x2000, 0x3000]
+        );
+
+        let huge = AddressRange::new(0x200000, 0x600000);
+        assert_eq!(
+            range_addresses(huge, HUGE_PAGE_SIZE)
+                .unwrap()
+                .collect::<Vec<_>>(),
+            vec![0x200000, 0x400000]
+        );
+
+        assert!(range_addresses(range, HUGE_PAGE_SIZE).is_err());
+        assert!(range_addresses(range, 8192).is_err());
+        assert!(range_addresses(AddressRange::new(0x2000, 0x1000), BASE_PAGE_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_page_addresses_policy() {
+        use crate::types::ProcIdlePageType;
+
+        let pages = vec![
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x3000, ProcIdlePageType::PteAccessed, 1),
+            IdlePageInfo::new(0x200000, ProcIdlePageType::PmdIdle, 1),
+        ];
+
+        let whole = page_addresses(&pages, HugePagePolicy::Whole).unwrap();
+        assert_eq!(whole, vec![0x1000, 0x2000, 0x200000]);
+
+        let split = page_addresses(&pages, HugePagePolicy::Split).unwrap();
+        assert_eq!(split.len(), 2 + 512);
+        assert_eq!(split[2], 0x200000);
+        assert_eq!(*split.last().unwrap(), 0x3ff000);
+
+        let skip = page_addresses(&pages, HugePagePolicy::Skip).unwrap();
+        assert_eq!(skip, vec![0x1000, 0x2000]);
+    }
+
+    #[test]
+    fn test_sort_dedup() {
+        let mut addrs = vec![0x3000, 0x1000, 0x2000, 0x1000, 0x1ff000, 0x200000, 0x9000];
+        sort_dedup(&mut addrs);
+        assert_eq!(
+            addrs,
+            vec![0x1000, 0x2000, 0x3000, 0x9000, 0x1ff000, 0x200000]
+        );
+    }
+
+    #[test]
+    fn test_parse_swap_counters() {
+        let vmstat = "nr_free_pages 12345\n\
+                      pswpin 42\n\
+                      pswpout 1337\n\
+                      pgpgin 9\n";
+        assert_eq!(parse_swap_counters(vmstat).unwrap(), (42, 1337));
+        assert!(parse_swap_counters("pswpin 42\n").is_err());
+    }
+
     #[test]
     fn test_address_formatting() {
         let mut buf = String::new();
         writeln!(buf, "{:x}", 0x7fff0000u64).unwrap();
         assert_eq!(buf, "7fff0000\n");
     }
+
+    #[test]
+    fn test_flush_refused_drops_pending() {
+        use crate::guard::GuardConfig;
+        use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+        let file = tempfile::tempfile().unwrap();
+        let handle = unsafe { ProcfsHandle::from_raw_fd(file.into_raw_fd()) };
+        // No amount of free swap satisfies the guard
+        let guard = SwapGuard::new(GuardConfig::new().with_min_free_swap_ratio(2.0));
+        let mut session = SwapSession {
+            handle,
+            config: SwapConfig::default(),
+            pid: std::process::id(),
+            pending_addrs: vec![0x1000, 0x2000],
+            guard: Some(guard),
+            freeze: None,
+            submitted: 0,
+        };
+
+        assert!(matches!(
+            session.flush(),
+            Err(EtmemError::ReclaimRefused(_))
+        ));
+        assert_eq!(session.pending_count(), 0);
+        assert_eq!(session.submitted(), 0);
+    }
+
+    #[test]
+    fn test_submitted_counts_auto_flushes() {
+        use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+        let file = tempfile::tempfile().unwrap();
+        let handle = unsafe { ProcfsHandle::from_raw_fd(file.into_raw_fd()) };
+        let mut session = SwapSession {
+            handle,
+            config: SwapConfig::default().with_max_pages(3),
+            pid: std::process::id(),
+            pending_addrs: Vec::new(),
+            guard: None,
+            freeze: None,
+            submitted: 0,
+        };
+
+        // Two full batches go out while adding, the last one on flush
+        let addrs: Vec<u64> = (1..=7).map(|i| i * 0x1000).collect();
+        session.add_addresses(&addrs).unwrap();
+        assert_eq!(session.submitted(), 6);
+        assert_eq!(session.flush().unwrap(), 1);
+        assert_eq!(session.submitted(), 7);
+
+        // Ranges are queued page by page and flushed the same way
+        let range = AddressRange::new(0x10000, 0x18000);
+        assert_eq!(session.add_range(range, BASE_PAGE_SIZE).unwrap(), 8);
+        assert_eq!(session.submitted(), 13);
+    }
+
+    #[test]
+    fn test_flush_sorts_and_dedups() {
+        use std::io::{Read, Seek};
+        use std::os::unix::io::{FromRawFd, IntoRawFd};
+
+        let mut file = tempfile::tempfile().unwrap();
+        let handle = unsafe { ProcfsHandle::from_raw_fd(file.try_clone().unwrap().into_raw_fd()) };
+        let mut session = SwapSession {
+            handle,
+            config: SwapConfig::default().with_max_pages(3),
+            pid: std::process::id(),
+            pending_addrs: Vec::new(),
+            guard: None,
+            freeze: None,
+            submitted: 0,
+        };
+
+        // Duplicates do not fill the batch
+        session
+            .add_addresses(&[0x3000, 0x1000, 0x3000, 0x1000])
+            .unwrap();
+        assert_eq!(session.pending_count(), 2);
+        assert_eq!(session.flush().unwrap(), 2);
+
+        session.config = session.config.clone().with_exact_order(true);
+        session.add_addresses(&[0x5000, 0x4000]).unwrap();
+        assert_eq!(session.flush().unwrap(), 2);
+
+        let mut written = String::new();
+        file.rewind().unwrap();
+        file.read_to_string(&mut written).unwrap();
+        assert_eq!(written, "1000\n3000\n5000\n4000\n");
+    }
 }
@@ -57,6 +57,12 @@ pub const RECLAIM_SWAPCACHE_OFF: u64 =
 /// IOCTL command to set swapcache watermark
 pub const SET_SWAPCACHE_WMARK: u64 =
     ((RECLAIM_SWAPCACHE_MAGIC as u64) << 8) | (0x02u64) | (8u64 << 16) | (1u64 << 30);
+/// IOCTL command to read back a swapcache watermark (_IOWR)
+pub const GET_SWAPCACHE_WMARK: u64 =
+    ((RECLAIM_SWAPCACHE_MAGIC as u64) << 8) | (0x03u64) | (8u64 << 16) | (3u64 << 30);
+
+/// Name of the kernel thread performing proactive swapcache reclaim
+pub const SWAPCACHE_RECLAIM_THREAD: &str = "etmem_recalim_swapcache";
 
 use crate::types::{IDLE_SCAN_MAGIC, RECLAIM_SWAPCACHE_MAGIC};
 
@@ -100,12 +106,33 @@ impl ProcfsHandle {
         Ok(Self { fd })
     }
 
+    /// Open `/proc/self/swap_pages` for writing
+    ///
+    /// Used for the system-wide swapcache reclaim IOCTLs, which do not
+    /// depend on the process the file belongs to.
+    ///
+    /// # Safety
+    /// This function uses unsafe FFI calls to open files.
+    /// The caller must ensure the ETMEM module is loaded.
+    pub unsafe fn open_self_swap_pages() -> std::io::Result<Self> {
+        let c_path = std::ffi::CString::new("/proc/self/swap_pages")?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(Self { fd })
+    }
+
     /// Read from procfs file at a specific offset
     ///
     /// # Safety
     /// This function performs raw system calls.
     /// The buffer must be valid and have the correct size.
     pub unsafe fn read_at(&self, buf: &mut [u8], offset: off_t) -> std::io::Result<ssize_t> {
+        #[cfg(feature = "failpoints")]
+        if let Some(err) = crate::failpoints::os_error(crate::failpoints::Failpoint::ScanRead) {
+            return Err(err);
+        }
         let result =
             unsafe { libc::pread(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len(), offset) };
         if result < 0 {
@@ -121,6 +148,10 @@ impl ProcfsHandle {
     /// This function performs raw system calls.
     /// The buffer must be valid and have the correct size.
     pub unsafe fn read(&self, buf: &mut [u8]) -> std::io::Result<ssize_t> {
+        #[cfg(feature = "failpoints")]
+        if let Some(err) = crate::failpoints::os_error(crate::failpoints::Failpoint::ScanRead) {
+            return Err(err);
+        }
         let result = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut c_void, buf.len()) };
         if result < 0 {
             Err(std::io::Error::last_os_error())
@@ -135,6 +166,14 @@ impl ProcfsHandle {
     /// This function performs raw system calls.
     /// The buffer must be valid and have the correct size.
     pub unsafe fn write(&self, buf: &[u8]) -> std::io::Result<ssize_t> {
+        #[cfg(feature = "failpoints")]
+        let buf = match crate::failpoints::fire(crate::failpoints::Failpoint::SwapWrite) {
+            Some(crate::failpoints::FailAction::Errno(errno)) => {
+                return Err(std::io::Error::from_raw_os_error(errno));
+            }
+            Some(crate::failpoints::FailAction::ShortWrite(len)) => &buf[..len.min(buf.len())],
+            _ => buf,
+        };
         let result = unsafe { libc::write(self.fd, buf.as_ptr() as *const c_void, buf.len()) };
         if result < 0 {
             Err(std::io::Error::last_os_error())
@@ -149,6 +188,10 @@ impl ProcfsHandle {
     /// This function performs raw IOCTL system calls.
     /// The argument pointer must be valid for the specific IOCTL command.
     pub unsafe fn ioctl(&self, request: u64, arg: *mut c_void) -> std::io::Result<c_int> {
+        #[cfg(feature = "failpoints")]
+        if let Some(err) = crate::failpoints::os_error(crate::failpoints::Failpoint::Ioctl) {
+            return Err(err);
+        }
         let result = unsafe { ioctl(self.fd, request, arg) };
         if result < 0 {
             Err(std::io::Error::last_os_error())
@@ -226,6 +269,152 @@ pub unsafe fn set_swapcache_watermark(
     Ok(())
 }
 
+/// Read back a swapcache watermark via IOCTL
+///
+/// Returns the watermark percentage for the given level. Kernels without
+/// read-back support fail with `ENOTTY`.
+///
+/// # Safety
+/// This function performs an IOCTL system call.
+pub unsafe fn get_swapcache_watermark(handle: &ProcfsHandle, level: u32) -> std::io::Result<u32> {
+    let mut arg = SwapcacheWmarkArg { level, percent: 0 };
+    unsafe { handle.ioctl(GET_SWAPCACHE_WMARK, &mut arg as *mut _ as *mut c_void) }?;
+    Ok(arg.percent)
+}
+
+/// Check if a kernel thread with the given name is running
+///
+/// Thread names in `/proc/[pid]/comm` are truncated to 15 bytes, so only
+/// that prefix of `name` is compared.
+pub fn kernel_thread_running(name: &str) -> bool {
+    const TASK_COMM_LEN: usize = 16;
+    let wanted = &name.as_bytes()[..name.len().min(TASK_COMM_LEN - 1)];
+
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return false;
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+        .filter_map(|entry| std::fs::read(entry.path().join("comm")).ok())
+        .any(|comm| comm.strip_suffix(b"\n").unwrap_or(&comm) == wanted)
+}
+
+/// Most ranges passed to one `process_madvise` call (`UIO_MAXIOV`)
+const MADVISE_MAX_IOV: usize = 1024;
+
+/// Ask the kernel to read back the given `(start, len)` ranges of a process
+///
+/// Issues `MADV_WILLNEED` through `process_madvise(2)` (Linux 5.10+), which
+/// starts swap-in readahead for swapped out anonymous pages. Returns the
+/// number of bytes advised.
+pub fn prefetch_ranges(pid: u32, ranges: &[(u64, u64)]) -> std::io::Result<u64> {
+    use std::os::fd::OwnedFd;
+
+    // Safe: pidfd_open takes no pointers and the fd is owned below
+    let pidfd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if pidfd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    // Safe: pidfd was just opened and is closed on drop
+    let pidfd = unsafe { OwnedFd::from_raw_fd(pidfd as c_int) };
+
+    let mut advised = 0;
+    for chunk in ranges.chunks(MADVISE_MAX_IOV) {
+        let iov: Vec<libc::iovec> = chunk
+            .iter()
+            .map(|&(start, len)| libc::iovec {
+                iov_base: start as *mut c_void,
+                iov_len: len as usize,
+            })
+            .collect();
+        // Safe: iov points to iov.len() valid iovecs describing remote memory
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_process_madvise,
+                pidfd.as_raw_fd(),
+                iov.as_ptr(),
+                iov.len(),
+                libc::MADV_WILLNEED,
+                0,
+            )
+        };
+        if ret < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        advised += ret as u64;
+    }
+    Ok(advised)
+}
+
+/// Report page cache residency of a window of a file
+///
+/// Maps `len` bytes of `file` starting at the page aligned `offset`
+/// read-only and shared, then queries `mincore(2)`. Returns one flag per
+/// page; pages past the end of the file are reported as not resident.
+pub fn file_residency(file: &std::fs::File, offset: u64, len: u64) -> std::io::Result<Vec<bool>> {
+    let page_size = crate::types::BASE_PAGE_SIZE;
+    let file_len = file.metadata()?.len();
+    let window = len.min(file_len.saturating_sub(offset));
+    let pages = len.div_ceil(page_size) as usize;
+    if window == 0 {
+        return Ok(vec![false; pages]);
+    }
+
+    // Safe: a fresh read-only mapping of a file we hold open, unmapped below
+    let addr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            window as usize,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            offset as off_t,
+        )
+    };
+    if addr == libc::MAP_FAILED {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut vec = vec![0u8; window.div_ceil(page_size) as usize];
+    // Safe: vec holds one byte per page of the mapping
+    let ret = unsafe { libc::mincore(addr, window as usize, vec.as_mut_ptr()) };
+    let err = std::io::Error::last_os_error();
+    // Safe: addr/window describe the mapping created above
+    unsafe { libc::munmap(addr, window as usize) };
+    if ret != 0 {
+        return Err(err);
+    }
+
+    let mut resident: Vec<bool> = vec.iter().map(|b| b & 1 != 0).collect();
+    resident.resize(pages, false);
+    Ok(resident)
+}
+
+/// Get the stack of the calling thread as `(lowest address, size)`
+///
+/// For the main thread this is the `[stack]` mapping up to the stack rlimit,
+/// for other threads the mapping allocated by the thread library.
+pub fn current_thread_stack() -> std::io::Result<(u64, u64)> {
+    let mut attr = std::mem::MaybeUninit::<libc::pthread_attr_t>::uninit();
+    let mut addr: *mut c_void = std::ptr::null_mut();
+    let mut size: libc::size_t = 0;
+    // Safe: attr is initialized by pthread_getattr_np and destroyed once read
+    unsafe {
+        let ret = libc::pthread_getattr_np(libc::pthread_self(), attr.as_mut_ptr());
+        if ret != 0 {
+            return Err(std::io::Error::from_raw_os_error(ret));
+        }
+        let ret = libc::pthread_attr_getstack(attr.as_ptr(), &mut addr, &mut size);
+        libc::pthread_attr_destroy(attr.as_mut_ptr());
+        if ret != 0 {
+            return Err(std::io::Error::from_raw_os_error(ret));
+        }
+    }
+    Ok((addr as u64, size as u64))
+}
+
 /// Enable proactive swapcache reclaim
 ///
 /// # Safety
@@ -302,5 +491,14 @@ mod tests {
         // _IOW(0x66, 0, u32) for IDLE_SCAN_ADD_FLAGS
         let expected = ((0x66u64) << 8) | (4u64 << 16) | (1u64 << 30);
         assert_eq!(IDLE_SCAN_ADD_FLAGS, expected);
+
+        // _IOWR(0x77, 3, struct { u32, u32 }) for GET_SWAPCACHE_WMARK
+        let expected = ((0x77u64) << 8) | 0x3 | (8u64 << 16) | (3u64 << 30);
+        assert_eq!(GET_SWAPCACHE_WMARK, expected);
+    }
+
+    #[test]
+    fn test_kernel_thread_running() {
+        assert!(!kernel_thread_running("no_such_kthread_name"));
     }
 }
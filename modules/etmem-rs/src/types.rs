@@ -1,11 +1,17 @@
 //! Type definitions for ETMEM operations
 //!
 //! This module contains data structures, constants, and type definitions
-//! for the ETMEM (Enhanced Tiered Memory) subsystem.
+//! for the ETMEM (Enhanced Tiered Memory) subsystem. The page and PIP
+//! types that need neither libc nor a kernel live in the `etmem-types`
+//! crate and are re-exported here.
+
+use std::time::Duration;
 
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 
+pub use etmem_types::{AddressRange, DecodeWarning, IdlePageInfo, PipEncoding, ProcIdlePageType};
+
 /// Maximum buffer size for idle page kernel buffer
 pub const PAGE_IDLE_KBUF_SIZE: usize = 8000;
 
@@ -32,138 +38,6 @@ pub const RET_RESCAN_FLAG: u32 = 0x10000;
 /// Default walk step (number of pages to skip between samples)
 pub const DEFAULT_WALK_STEP: u32 = 512;
 
-/// Page type enumeration for idle page detection
-///
-/// These types correspond to the hardware page table entry states
-/// and indicate the size and access status of memory pages.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[repr(u8)]
-pub enum ProcIdlePageType {
-    /// 4KB page was accessed (A bit set in PTE)
-    PteAccessed = 0,
-    /// 2MB page was accessed (A bit set in PMD)
-    PmdAccessed = 1,
-    /// 1GB page is present (PUD present bit)
-    PudPresent = 2,
-    /// 4KB page is dirty (D bit set in PTE)
-    PteDirty = 3,
-    /// 2MB page is dirty (D bit set in PMD)
-    PmdDirty = 4,
-    /// 4KB page is idle (A bit not set in PTE)
-    PteIdle = 5,
-    /// 2MB page is idle (A bit not set in PMD)
-    PmdIdle = 6,
-    /// All PTEs within a PMD are idle
-    PmdIdlePtes = 7,
-    /// 4KB page table entry is a hole (not present)
-    PteHole = 8,
-    /// 2MB PMD entry is a hole (not present)
-    PmdHole = 9,
-    /// Command marker for PIP protocol
-    PipCmd = 10,
-    /// Maximum valid type value
-    Max = 11,
-}
-
-impl ProcIdlePageType {
-    /// Convert from raw u8 value
-    pub const fn from_raw(value: u8) -> Option<Self> {
-        match value {
-            0 => Some(Self::PteAccessed),
-            1 => Some(Self::PmdAccessed),
-            2 => Some(Self::PudPresent),
-            3 => Some(Self::PteDirty),
-            4 => Some(Self::PmdDirty),
-            5 => Some(Self::PteIdle),
-            6 => Some(Self::PmdIdle),
-            7 => Some(Self::PmdIdlePtes),
-            8 => Some(Self::PteHole),
-            9 => Some(Self::PmdHole),
-            10 => Some(Self::PipCmd),
-            _ => None,
-        }
-    }
-
-    /// Check if this page type represents a huge page (2MB or 1GB)
-    pub const fn is_huge(&self) -> bool {
-        matches!(
-            self,
-            Self::PmdAccessed
-                | Self::PmdDirty
-                | Self::PmdIdle
-                | Self::PmdIdlePtes
-                | Self::PmdHole
-                | Self::PudPresent
-        )
-    }
-
-    /// Check if this page type represents an idle (cold) page
-    pub const fn is_idle(&self) -> bool {
-        matches!(self, Self::PteIdle | Self::PmdIdle | Self::PmdIdlePtes)
-    }
-
-    /// Check if this page type represents an accessed (hot) page
-    pub const fn is_accessed(&self) -> bool {
-        matches!(self, Self::PteAccessed | Self::PmdAccessed)
-    }
-
-    /// Check if this page type represents a hole (not mapped)
-    pub const fn is_hole(&self) -> bool {
-        matches!(self, Self::PteHole | Self::PmdHole)
-    }
-
-    /// Get the page size in bytes for this type
-    pub const fn page_size(&self) -> u64 {
-        match self {
-            Self::PteAccessed | Self::PteDirty | Self::PteIdle | Self::PteHole => 4096, // 4KB
-            Self::PmdAccessed
-            | Self::PmdDirty
-            | Self::PmdIdle
-            | Self::PmdIdlePtes
-            | Self::PmdHole => 2 * 1024 * 1024, // 2MB
-            Self::PudPresent => 1024 * 1024 * 1024,                                     // 1GB
-            _ => 4096, // Default to 4KB for command types
-        }
-    }
-}
-
-/// PIP (Proc Idle Page) encoding helpers
-///
-/// The kernel encodes idle page information in a compact byte format:
-/// - Upper 4 bits: page type
-/// - Lower 4 bits: count of consecutive pages (0 means 1 page)
-pub struct PipEncoding;
-
-impl PipEncoding {
-    /// Extract type from encoded byte
-    #[inline]
-    pub const fn extract_type(encoded: u8) -> u8 {
-        (encoded >> 4) & 0xf
-    }
-
-    /// Extract size/count from encoded byte
-    /// Returns count of consecutive pages minus 1 (so 0 means 1 page)
-    #[inline]
-    pub const fn extract_size(encoded: u8) -> u8 {
-        encoded & 0xf
-    }
-
-    /// Compose type and size into encoded byte
-    /// count is the number of consecutive pages minus 1 (0-15, representing 1-16 pages)
-    #[inline]
-    pub const fn compose(page_type: u8, count: u8) -> u8 {
-        ((page_type & 0xf) << 4) | (count & 0xf)
-    }
-
-    /// PIP command to set HVA (Host Virtual Address)
-    pub const SET_HVA: u8 = Self::compose(ProcIdlePageType::PipCmd as u8, 0);
-
-    /// Decode an encoded byte into (type, count)
-    pub const fn decode(encoded: u8) -> (u8, u8) {
-        (Self::extract_type(encoded), Self::extract_size(encoded))
-    }
-}
-
 bitflags! {
     /// Idle page scan flags
     ///
@@ -227,93 +101,198 @@ impl SwapcacheWatermark {
     }
 }
 
-/// Page idle information entry
+/// Set of virtual addresses stored as sorted, disjoint ranges
 ///
-/// Represents a single idle (or accessed) page detected during scanning.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
-pub struct IdlePageInfo {
-    /// Virtual address of the page
-    pub address: u64,
-    /// Page type (accessed, idle, dirty, etc.)
-    pub page_type: ProcIdlePageType,
-    /// Number of consecutive pages of this type (1-16)
-    pub count: u8,
+/// Overlapping and adjacent ranges are coalesced on insertion, so the
+/// stored ranges are always the minimal covering set.
+///
+/// # Example
+/// ```
+/// use etmem_rs::{AddressRange, RangeSet};
+///
+/// let a = RangeSet::from_ranges([AddressRange::new(0x1000, 0x3000)]);
+/// let b = RangeSet::from_ranges([AddressRange::new(0x2000, 0x5000)]);
+///
+/// assert_eq!(a.union(&b).ranges(), &[AddressRange::new(0x1000, 0x5000)]);
+/// assert_eq!(a.intersection(&b).ranges(), &[AddressRange::new(0x2000, 0x3000)]);
+/// assert_eq!(a.difference(&b).ranges(), &[AddressRange::new(0x1000, 0x2000)]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct RangeSet {
+    /// Sorted, disjoint, non-adjacent, non-empty ranges
+    ranges: Vec<AddressRange>,
 }
 
-impl IdlePageInfo {
-    /// Create a new IdlePageInfo
-    pub fn new(address: u64, page_type: ProcIdlePageType, count: u8) -> Self {
+impl RangeSet {
+    /// Create an empty set
+    pub const fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    /// Build a set from arbitrary (possibly overlapping) ranges
+    pub fn from_ranges<I: IntoIterator<Item = AddressRange>>(ranges: I) -> Self {
+        let mut sorted: Vec<AddressRange> = ranges.into_iter().filter(|r| r.is_valid()).collect();
+        sorted.sort_by_key(|r| r.start);
         Self {
-            address,
-            page_type,
-            count: if count < 1 { 1 } else { count },
+            ranges: Self::coalesce_sorted(sorted),
         }
     }
 
-    /// Get the total size covered by this entry in bytes
-    pub fn total_size(&self) -> u64 {
-        self.page_type.page_size() * self.count as u64
+    /// Build the set of addresses covered by scan entries
+    pub fn from_pages(pages: &[IdlePageInfo]) -> Self {
+        Self::from_ranges(
+            pages
+                .iter()
+                .map(|p| AddressRange::new(p.address, p.end_address())),
+        )
     }
 
-    /// Get the end address (exclusive) of this entry
-    pub fn end_address(&self) -> u64 {
-        self.address + self.total_size()
+    /// Merge overlapping or adjacent ranges of a start-sorted list
+    fn coalesce_sorted(sorted: Vec<AddressRange>) -> Vec<AddressRange> {
+        let mut merged: Vec<AddressRange> = Vec::with_capacity(sorted.len());
+        for range in sorted {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        merged
     }
 
-    /// Check if this entry represents an idle page
-    pub fn is_idle(&self) -> bool {
-        self.page_type.is_idle()
+    /// Add a range to the set
+    pub fn insert(&mut self, range: AddressRange) {
+        if !range.is_valid() {
+            return;
+        }
+        let idx = self.ranges.partition_point(|r| r.start < range.start);
+        self.ranges.insert(idx, range);
+        self.ranges = Self::coalesce_sorted(std::mem::take(&mut self.ranges));
     }
 
-    /// Check if this entry represents an accessed (hot) page
-    pub fn is_accessed(&self) -> bool {
-        self.page_type.is_accessed()
+    /// Remove a range from the set
+    pub fn remove(&mut self, range: AddressRange) {
+        *self = self.difference(&Self::from_ranges([range]));
     }
-}
 
-/// Virtual address range for scanning
-///
-/// Defines a range of virtual addresses to scan.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, Hash)]
-pub struct AddressRange {
-    /// Start address (inclusive)
-    pub start: u64,
-    /// End address (exclusive)
-    pub end: u64,
-}
-
-impl AddressRange {
-    /// Create a new address range
-    pub const fn new(start: u64, end: u64) -> Self {
-        Self { start, end }
+    /// Get all addresses in either set
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_ranges(self.ranges.iter().chain(&other.ranges).copied())
     }
 
-    /// Create a range from start with given size
-    pub const fn with_size(start: u64, size: u64) -> Self {
-        Self {
-            start,
-            end: start + size,
+    /// Get all addresses in both sets
+    pub fn intersection(&self, other: &Self) -> Self {
+        let mut result = Vec::new();
+        let (mut i, mut j) = (0, 0);
+
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (&self.ranges[i], &other.ranges[j]);
+            if let Some(overlap) = a.intersection(b) {
+                result.push(overlap);
+            }
+            if a.end <= b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        Self { ranges: result }
+    }
+
+    /// Get all addresses in this set but not in `other`
+    pub fn difference(&self, other: &Self) -> Self {
+        let mut result = Vec::new();
+        let mut j = 0;
+
+        for range in &self.ranges {
+            let mut start = range.start;
+            // Skip subtrahends entirely before this range
+            while j < other.ranges.len() && other.ranges[j].end <= start {
+                j += 1;
+            }
+
+            let mut k = j;
+            while k < other.ranges.len() && other.ranges[k].start < range.end {
+                let cut = &other.ranges[k];
+                if cut.start > start {
+                    result.push(AddressRange::new(start, cut.start));
+                }
+                start = start.max(cut.end);
+                k += 1;
+            }
+
+            if start < range.end {
+                result.push(AddressRange::new(start, range.end));
+            }
         }
+
+        Self { ranges: result }
     }
 
-    /// Check if an address is within this range
-    pub const fn contains(&self, addr: u64) -> bool {
-        addr >= self.start && addr < self.end
+    /// Check if an address is in the set
+    pub fn contains(&self, addr: u64) -> bool {
+        let idx = self.ranges.partition_point(|r| r.end <= addr);
+        self.ranges.get(idx).is_some_and(|r| r.contains(addr))
     }
 
-    /// Get the size of this range in bytes
-    pub const fn size(&self) -> u64 {
-        self.end.saturating_sub(self.start)
+    /// Get the coalesced ranges
+    pub fn ranges(&self) -> &[AddressRange] {
+        &self.ranges
     }
 
-    /// Check if the range is valid (start < end)
-    pub const fn is_valid(&self) -> bool {
-        self.start < self.end
+    /// Get the number of disjoint ranges
+    pub fn len(&self) -> usize {
+        self.ranges.len()
     }
 
-    /// Check if this range overlaps with another
-    pub const fn overlaps(&self, other: &Self) -> bool {
-        self.start < other.end && other.start < self.end
+    /// Check if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Get the total number of bytes covered
+    pub fn total_size(&self) -> u64 {
+        self.ranges.iter().map(|r| r.size()).sum()
+    }
+
+    /// Iterate over the coalesced ranges
+    pub fn iter(&self) -> std::slice::Iter<'_, AddressRange> {
+        self.ranges.iter()
+    }
+
+    /// Consume the set, returning the coalesced ranges
+    pub fn into_vec(self) -> Vec<AddressRange> {
+        self.ranges
+    }
+}
+
+impl FromIterator<AddressRange> for RangeSet {
+    fn from_iter<I: IntoIterator<Item = AddressRange>>(iter: I) -> Self {
+        Self::from_ranges(iter)
+    }
+}
+
+impl From<Vec<IdlePageInfo>> for RangeSet {
+    fn from(pages: Vec<IdlePageInfo>) -> Self {
+        Self::from_pages(&pages)
+    }
+}
+
+impl IntoIterator for RangeSet {
+    type Item = AddressRange;
+    type IntoIter = std::vec::IntoIter<AddressRange>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a RangeSet {
+    type Item = &'a AddressRange;
+    type IntoIter = std::slice::Iter<'a, AddressRange>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.iter()
     }
 }
 
@@ -365,6 +344,42 @@ impl Default for WatermarkConfig {
     }
 }
 
+/// Swapcache watermark state as observed from the kernel
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WatermarkStatus {
+    /// Watermarks in effect
+    pub watermark: WatermarkConfig,
+    /// Whether `watermark` was read back from the kernel (vs. last set value)
+    pub kernel_confirmed: bool,
+    /// Current swapcache size in KB
+    pub swapcache_kb: u64,
+    /// Total RAM in KB (watermarks are a percentage of this)
+    pub total_ram_kb: u64,
+    /// Whether the proactive reclaim kernel thread is running
+    pub reclaim_active: bool,
+}
+
+impl WatermarkStatus {
+    /// Calculate swapcache occupancy as a percentage of total RAM
+    pub fn occupancy_percent(&self) -> f64 {
+        if self.total_ram_kb == 0 {
+            0.0
+        } else {
+            self.swapcache_kb as f64 * 100.0 / self.total_ram_kb as f64
+        }
+    }
+
+    /// Check if swapcache occupancy exceeds the low watermark
+    pub fn exceeds_low(&self) -> bool {
+        self.occupancy_percent() > self.watermark.low_percent as f64
+    }
+
+    /// Check if swapcache occupancy exceeds the high watermark
+    pub fn exceeds_high(&self) -> bool {
+        self.occupancy_percent() > self.watermark.high_percent as f64
+    }
+}
+
 /// Kernel buffer status after scan operation
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -394,6 +409,80 @@ impl BufferStatus {
     }
 }
 
+/// Retry policy for transient scan read errors
+///
+/// `EINTR` is retried immediately; `EAGAIN` and `EBUSY` are retried after
+/// an exponentially growing backoff. Any other errno is fatal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries of a single read before giving up
+    pub max_retries: u32,
+    /// Backoff before the first retry after `EAGAIN`/`EBUSY`
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Create the default policy: 8 retries, 1ms backoff doubling up to 100ms
+    pub const fn new() -> Self {
+        Self {
+            max_retries: 8,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(100),
+        }
+    }
+
+    /// Policy that fails on the first error
+    pub const fn none() -> Self {
+        Self::new().with_max_retries(0)
+    }
+
+    /// Set the maximum number of retries
+    pub const fn with_max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// Set the initial and maximum backoff
+    pub const fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Check if an errno is worth retrying
+    pub const fn is_transient(errno: i32) -> bool {
+        matches!(errno, libc::EINTR | libc::EAGAIN | libc::EBUSY)
+    }
+
+    /// Backoff before the given retry (0-based) of a throttled read
+    pub fn backoff(&self, retry: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1u32.checked_shl(retry).unwrap_or(u32::MAX))
+            .min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What a scan session does with flags the kernel rejects
+///
+/// Older kernels reject some flags, such as `SCAN_HUGE_PAGE` or
+/// `SCAN_DIRTY_PAGE`, with `EINVAL` when they are set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum UnsupportedFlagPolicy {
+    /// Fail session creation with [`crate::EtmemError::UnsupportedFlags`]
+    #[default]
+    Fail,
+    /// Log a warning and scan without the rejected flags
+    Drop,
+}
+
 /// ETMEM scan session configuration
 #[derive(Debug, Clone)]
 pub struct ScanConfig {
@@ -403,6 +492,14 @@ pub struct ScanConfig {
     pub buffer_size: usize,
     /// Walk step in pages (how many pages to skip between samples)
     pub walk_step: u32,
+    /// Retry policy for transient read errors
+    pub retry: RetryPolicy,
+    /// Time budget of one range or address space walk (`None`: unbounded)
+    pub max_duration: Option<Duration>,
+    /// What to do with flags the kernel rejects
+    pub unsupported_flags: UnsupportedFlagPolicy,
+    /// Size above which a run of hole entries is reported as an anomaly
+    pub max_hole_bytes: u64,
 }
 
 impl ScanConfig {
@@ -412,6 +509,10 @@ impl ScanConfig {
             flags: ScanFlags::empty(),
             buffer_size: PAGE_IDLE_KBUF_SIZE,
             walk_step: DEFAULT_WALK_STEP,
+            retry: RetryPolicy::new(),
+            max_duration: None,
+            unsupported_flags: UnsupportedFlagPolicy::Fail,
+            max_hole_bytes: crate::anomaly::DEFAULT_MAX_HOLE_BYTES,
         }
     }
 
@@ -433,6 +534,40 @@ impl ScanConfig {
         self
     }
 
+    /// Set the retry policy for transient read errors
+    pub const fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Bound the time spent walking a range or address space
+    ///
+    /// Once `limit` has elapsed the walk stops after the current read and
+    /// returns the pages found so far, with the address to resume from (see
+    /// [`crate::scan::PartialScan`]). At least one read is always made, so a
+    /// resumed walk makes progress even with a zero limit.
+    pub const fn with_max_duration(mut self, limit: Duration) -> Self {
+        self.max_duration = Some(limit);
+        self
+    }
+
+    /// Set what to do with flags the kernel rejects
+    ///
+    /// Flags are probed one at a time when the session is created; with
+    /// [`UnsupportedFlagPolicy::Drop`] the session scans without the rejected
+    /// ones and [`crate::ScanSession::config`] reports the flags in effect.
+    pub const fn with_unsupported_flags(mut self, policy: UnsupportedFlagPolicy) -> Self {
+        self.unsupported_flags = policy;
+        self
+    }
+
+    /// Set the size above which a run of hole entries is reported as an
+    /// anomaly (see [`crate::anomaly`])
+    pub const fn with_max_hole_bytes(mut self, bytes: u64) -> Self {
+        self.max_hole_bytes = bytes;
+        self
+    }
+
     /// Validate the configuration
     pub fn validate(&self) -> crate::error::Result<()> {
         use crate::error::EtmemError;
@@ -456,6 +591,24 @@ impl Default for ScanConfig {
     }
 }
 
+/// Base (4KB) page size
+pub const BASE_PAGE_SIZE: u64 = 4096;
+
+/// PMD-mapped huge page size (2MB)
+pub const HUGE_PAGE_SIZE: u64 = 2 * 1024 * 1024;
+
+/// How huge (PMD-mapped) pages are submitted for swapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum HugePagePolicy {
+    /// Submit one PMD-aligned address per huge page
+    #[default]
+    Whole,
+    /// Submit every 4KB address, letting the kernel split the huge page
+    Split,
+    /// Leave huge pages resident
+    Skip,
+}
+
 /// Page swap configuration
 #[derive(Debug, Clone)]
 pub struct SwapConfig {
@@ -465,6 +618,8 @@ pub struct SwapConfig {
     pub watermark: WatermarkConfig,
     /// Maximum number of pages to swap per operation
     pub max_pages: u32,
+    /// Submit addresses exactly as queued instead of sorted and deduplicated
+    pub exact_order: bool,
 }
 
 impl SwapConfig {
@@ -474,6 +629,7 @@ impl SwapConfig {
             proactive_reclaim: false,
             watermark: WatermarkConfig::new(30, 70),
             max_pages: SWAP_SCAN_NUM_MAX,
+            exact_order: false,
         }
     }
 
@@ -494,6 +650,12 @@ impl SwapConfig {
         self.max_pages = max;
         self
     }
+
+    /// Keep queued addresses in order, including duplicates
+    pub const fn with_exact_order(mut self, exact: bool) -> Self {
+        self.exact_order = exact;
+        self
+    }
 }
 
 impl Default for SwapConfig {
@@ -507,49 +669,111 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_pip_encoding() {
-        let encoded = PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 5);
+    fn test_scan_flags() {
+        let flags = ScanFlags::SCAN_HUGE_PAGE | ScanFlags::SCAN_DIRTY_PAGE;
+        assert!(flags.is_valid());
+        assert!(flags.contains(ScanFlags::SCAN_HUGE_PAGE));
+        assert!(flags.contains(ScanFlags::SCAN_DIRTY_PAGE));
+    }
+
+    #[test]
+    fn test_range_set_coalesce() {
+        let set = RangeSet::from_ranges([
+            AddressRange::new(0x5000, 0x6000),
+            AddressRange::new(0x1000, 0x2000),
+            AddressRange::new(0x2000, 0x3000),
+            AddressRange::new(0x2800, 0x4000),
+            AddressRange::new(0x9000, 0x9000),
+        ]);
         assert_eq!(
-            PipEncoding::extract_type(encoded),
-            ProcIdlePageType::PteIdle as u8
+            set.ranges(),
+            &[
+                AddressRange::new(0x1000, 0x4000),
+                AddressRange::new(0x5000, 0x6000)
+            ]
         );
-        assert_eq!(PipEncoding::extract_size(encoded), 5);
+        assert_eq!(set.total_size(), 0x4000);
+        assert!(set.contains(0x3fff));
+        assert!(!set.contains(0x4000));
+        assert!(!set.contains(0x0));
 
-        let (t, s) = PipEncoding::decode(encoded);
-        assert_eq!(t, ProcIdlePageType::PteIdle as u8);
-        assert_eq!(s, 5);
+        let mut inserted = set.clone();
+        inserted.insert(AddressRange::new(0x4000, 0x5000));
+        assert_eq!(inserted.ranges(), &[AddressRange::new(0x1000, 0x6000)]);
     }
 
     #[test]
-    fn test_proc_idle_page_type() {
-        assert!(ProcIdlePageType::PmdIdle.is_huge());
-        assert!(!ProcIdlePageType::PteIdle.is_huge());
-        assert!(ProcIdlePageType::PteIdle.is_idle());
-        assert!(ProcIdlePageType::PteAccessed.is_accessed());
-        assert!(ProcIdlePageType::PteHole.is_hole());
-        assert_eq!(ProcIdlePageType::PteAccessed.page_size(), 4096);
-        assert_eq!(ProcIdlePageType::PmdAccessed.page_size(), 2 * 1024 * 1024);
-        assert_eq!(ProcIdlePageType::PudPresent.page_size(), 1024 * 1024 * 1024);
+    fn test_range_set_algebra() {
+        let a = RangeSet::from_ranges([
+            AddressRange::new(0x1000, 0x4000),
+            AddressRange::new(0x8000, 0xa000),
+        ]);
+        let b = RangeSet::from_ranges([
+            AddressRange::new(0x2000, 0x3000),
+            AddressRange::new(0x9000, 0xc000),
+        ]);
+
+        assert_eq!(
+            a.union(&b).ranges(),
+            &[
+                AddressRange::new(0x1000, 0x4000),
+                AddressRange::new(0x8000, 0xc000)
+            ]
+        );
+        assert_eq!(
+            a.intersection(&b).ranges(),
+            &[
+                AddressRange::new(0x2000, 0x3000),
+                AddressRange::new(0x9000, 0xa000)
+            ]
+        );
+        assert_eq!(
+            a.difference(&b).ranges(),
+            &[
+                AddressRange::new(0x1000, 0x2000),
+                AddressRange::new(0x3000, 0x4000),
+                AddressRange::new(0x8000, 0x9000)
+            ]
+        );
+        assert!(a.difference(&a).is_empty());
+
+        let mut removed = a.clone();
+        removed.remove(AddressRange::new(0x0, 0x9000));
+        assert_eq!(removed.ranges(), &[AddressRange::new(0x9000, 0xa000)]);
     }
 
     #[test]
-    fn test_scan_flags() {
-        let flags = ScanFlags::SCAN_HUGE_PAGE | ScanFlags::SCAN_DIRTY_PAGE;
-        assert!(flags.is_valid());
-        assert!(flags.contains(ScanFlags::SCAN_HUGE_PAGE));
-        assert!(flags.contains(ScanFlags::SCAN_DIRTY_PAGE));
+    fn test_range_set_from_pages() {
+        let pages = vec![
+            IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2),
+            IdlePageInfo::new(0x3000, ProcIdlePageType::PteIdle, 1),
+            IdlePageInfo::new(0x200000, ProcIdlePageType::PmdIdle, 1),
+        ];
+        let set = RangeSet::from(pages);
+        assert_eq!(
+            set.ranges(),
+            &[
+                AddressRange::new(0x1000, 0x4000),
+                AddressRange::new(0x200000, 0x400000)
+            ]
+        );
     }
 
     #[test]
-    fn test_address_range() {
-        let range = AddressRange::new(0x1000, 0x5000);
-        assert!(range.contains(0x2000));
-        assert!(!range.contains(0x5000));
-        assert_eq!(range.size(), 0x4000);
-        assert!(range.is_valid());
-
-        let with_size = AddressRange::with_size(0x1000, 0x4000);
-        assert_eq!(with_size, range);
+    fn test_retry_policy() {
+        let policy = RetryPolicy::new();
+        assert!(RetryPolicy::is_transient(libc::EINTR));
+        assert!(RetryPolicy::is_transient(libc::EAGAIN));
+        assert!(RetryPolicy::is_transient(libc::EBUSY));
+        assert!(!RetryPolicy::is_transient(libc::EIO));
+        assert!(!RetryPolicy::is_transient(libc::ESRCH));
+
+        assert_eq!(policy.backoff(0), Duration::from_millis(1));
+        assert_eq!(policy.backoff(3), Duration::from_millis(8));
+        assert_eq!(policy.backoff(7), Duration::from_millis(100));
+        assert_eq!(policy.backoff(64), Duration::from_millis(100));
+        assert_eq!(RetryPolicy::none().max_retries, 0);
+        assert_eq!(ScanConfig::default().retry, policy);
     }
 
     #[test]
@@ -565,12 +789,23 @@ mod tests {
     }
 
     #[test]
-    fn test_idle_page_info() {
-        let info = IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2);
-        assert_eq!(info.address, 0x1000);
-        assert!(info.is_idle());
-        assert_eq!(info.total_size(), 4096 * 2);
-        assert_eq!(info.end_address(), 0x1000 + 4096 * 2);
+    fn test_watermark_status() {
+        let status = WatermarkStatus {
+            watermark: WatermarkConfig::new(30, 70),
+            kernel_confirmed: true,
+            swapcache_kb: 500,
+            total_ram_kb: 1000,
+            reclaim_active: true,
+        };
+        assert!((status.occupancy_percent() - 50.0).abs() < 0.001);
+        assert!(status.exceeds_low());
+        assert!(!status.exceeds_high());
+
+        let empty = WatermarkStatus {
+            total_ram_kb: 0,
+            ..status
+        };
+        assert_eq!(empty.occupancy_percent(), 0.0);
     }
 
     #[test]
@@ -4,6 +4,10 @@
 //! including address manipulation, page size calculations, and
 //! statistics helpers.
 
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
 use crate::types::{IdlePageInfo, ProcIdlePageType};
 
 /// Check if an address is page-aligned (4KB)
@@ -140,6 +144,208 @@ impl IdlePageStats {
     }
 }
 
+impl fmt::Display for IdlePageStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pages, {} idle ({:.0}%), {} huge",
+            self.total_pages,
+            format_bytes(self.idle_bytes),
+            self.idle_ratio() * 100.0,
+            self.huge_pages
+        )
+    }
+}
+
+/// Direction of the idle ratio over a stats window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    /// Memory is getting colder
+    Rising,
+    /// Memory is getting hotter
+    Falling,
+    /// No significant change
+    Stable,
+}
+
+/// Rolling window of idle page statistics from successive scans
+///
+/// Samples older than `max_age` are dropped as new ones arrive. An EWMA
+/// of the idle ratio is maintained across all samples, so policies can
+/// act on sustained behavior instead of a single noisy scan.
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use etmem_rs::util::{IdlePageStats, StatsWindow};
+///
+/// let mut window = StatsWindow::new(Duration::from_secs(300));
+/// window.push(IdlePageStats { total_bytes: 100, idle_bytes: 70, ..Default::default() });
+///
+/// if window.idle_above_for(0.6, Duration::from_secs(300)) {
+///     println!("Memory has been mostly idle for 5 minutes");
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct StatsWindow {
+    /// Samples in arrival order
+    samples: VecDeque<(Instant, IdlePageStats)>,
+    /// Maximum sample age retained
+    max_age: Duration,
+    /// EWMA smoothing factor (0.0 - 1.0, weight of the newest sample)
+    alpha: f64,
+    /// Minimum mean difference treated as a trend
+    trend_tolerance: f64,
+    /// Current EWMA of the idle ratio
+    ewma: Option<f64>,
+}
+
+impl StatsWindow {
+    /// Create a window retaining samples up to `max_age` old
+    ///
+    /// Defaults: EWMA alpha 0.3, trend tolerance 0.05.
+    pub fn new(max_age: Duration) -> Self {
+        Self {
+            samples: VecDeque::new(),
+            max_age,
+            alpha: 0.3,
+            trend_tolerance: 0.05,
+            ewma: None,
+        }
+    }
+
+    /// Set the EWMA smoothing factor (clamped to 0.0 - 1.0)
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Set the minimum idle ratio change reported as a trend
+    pub fn with_trend_tolerance(mut self, tolerance: f64) -> Self {
+        self.trend_tolerance = tolerance.abs();
+        self
+    }
+
+    /// Add a sample taken now
+    pub fn push(&mut self, stats: IdlePageStats) {
+        self.push_at(stats, Instant::now());
+    }
+
+    /// Add a sample taken at `at`
+    pub fn push_at(&mut self, stats: IdlePageStats, at: Instant) {
+        let ratio = stats.idle_ratio();
+        self.ewma = Some(match self.ewma {
+            Some(prev) => self.alpha * ratio + (1.0 - self.alpha) * prev,
+            None => ratio,
+        });
+
+        self.samples.push_back((at, stats));
+        while let Some(&(oldest, _)) = self.samples.front() {
+            if at.saturating_duration_since(oldest) > self.max_age {
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Get the number of samples in the window
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Check if the window has no samples
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Get the most recent sample
+    pub fn latest(&self) -> Option<&IdlePageStats> {
+        self.samples.back().map(|(_, stats)| stats)
+    }
+
+    /// Get the time covered by the samples in the window
+    pub fn span(&self) -> Duration {
+        match (self.samples.front(), self.samples.back()) {
+            (Some((first, _)), Some((last, _))) => last.saturating_duration_since(*first),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Calculate the mean idle ratio over the window
+    pub fn mean_idle_ratio(&self) -> Option<f64> {
+        Self::mean(self.samples.iter().map(|(_, s)| s.idle_ratio()))
+    }
+
+    /// Calculate the mean idle bytes over the window
+    pub fn mean_idle_bytes(&self) -> Option<f64> {
+        Self::mean(self.samples.iter().map(|(_, s)| s.idle_bytes as f64))
+    }
+
+    /// Get the exponentially weighted moving average of the idle ratio
+    pub fn ewma_idle_ratio(&self) -> Option<f64> {
+        self.ewma
+    }
+
+    /// Compare the older and newer halves of the window
+    pub fn trend(&self) -> Trend {
+        if self.samples.len() < 2 {
+            return Trend::Stable;
+        }
+
+        let mid = self.samples.len() / 2;
+        let ratios = || self.samples.iter().map(|(_, s)| s.idle_ratio());
+        let older = Self::mean(ratios().take(mid)).unwrap_or(0.0);
+        let newer = Self::mean(ratios().skip(mid)).unwrap_or(0.0);
+
+        if newer - older > self.trend_tolerance {
+            Trend::Rising
+        } else if older - newer > self.trend_tolerance {
+            Trend::Falling
+        } else {
+            Trend::Stable
+        }
+    }
+
+    /// Check if every sample in the last `duration` was above `threshold`
+    ///
+    /// Returns `false` until the window spans at least `duration`.
+    pub fn idle_above_for(&self, threshold: f64, duration: Duration) -> bool {
+        let Some(&(newest, _)) = self.samples.back() else {
+            return false;
+        };
+        if self.span() < duration {
+            return false;
+        }
+
+        // Include the newest sample at or before the cutoff so the run
+        // covers the full duration
+        let cutoff = newest.checked_sub(duration).unwrap_or(newest);
+        let start = self
+            .samples
+            .iter()
+            .rposition(|(at, _)| *at <= cutoff)
+            .unwrap_or(0);
+
+        self.samples
+            .iter()
+            .skip(start)
+            .all(|(_, s)| s.idle_ratio() > threshold)
+    }
+
+    /// Remove all samples and reset the EWMA
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.ewma = None;
+    }
+
+    /// Arithmetic mean of an iterator, `None` if empty
+    fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+        let (sum, count) = values.fold((0.0, 0usize), |(sum, n), v| (sum + v, n + 1));
+        (count > 0).then(|| sum / count as f64)
+    }
+}
+
 /// Group pages by their type
 pub fn group_by_type(
     pages: &[IdlePageInfo],
@@ -271,6 +477,18 @@ mod tests {
         assert_eq!(stats.total_bytes, 4096 + 4096 + 2 * 1024 * 1024);
     }
 
+    #[test]
+    fn test_stats_display() {
+        let stats = IdlePageStats {
+            total_pages: 3,
+            huge_pages: 1,
+            total_bytes: 4096,
+            idle_bytes: 1024,
+            ..Default::default()
+        };
+        assert_eq!(stats.to_string(), "3 pages, 1.00 KB idle (25%), 1 huge");
+    }
+
     #[test]
     fn test_idle_ratio() {
         let stats = IdlePageStats {
@@ -284,6 +502,67 @@ mod tests {
         assert!((stats.accessed_ratio() - 0.7).abs() < 0.001);
     }
 
+    fn stats_with_ratio(idle_percent: u64) -> IdlePageStats {
+        IdlePageStats {
+            total_bytes: 100,
+            idle_bytes: idle_percent,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_stats_window_averages() {
+        let start = Instant::now();
+        let mut window = StatsWindow::new(Duration::from_secs(60)).with_alpha(0.5);
+        assert!(window.mean_idle_ratio().is_none());
+        assert_eq!(window.trend(), Trend::Stable);
+
+        window.push_at(stats_with_ratio(20), start);
+        window.push_at(stats_with_ratio(40), start + Duration::from_secs(10));
+        window.push_at(stats_with_ratio(60), start + Duration::from_secs(20));
+
+        assert_eq!(window.len(), 3);
+        assert!((window.mean_idle_ratio().unwrap() - 0.4).abs() < 0.001);
+        assert!((window.mean_idle_bytes().unwrap() - 40.0).abs() < 0.001);
+        // 0.2 -> 0.3 -> 0.45
+        assert!((window.ewma_idle_ratio().unwrap() - 0.45).abs() < 0.001);
+        assert_eq!(window.trend(), Trend::Rising);
+        assert_eq!(window.latest().unwrap().idle_bytes, 60);
+    }
+
+    #[test]
+    fn test_stats_window_expiry() {
+        let start = Instant::now();
+        let mut window = StatsWindow::new(Duration::from_secs(30));
+
+        window.push_at(stats_with_ratio(90), start);
+        window.push_at(stats_with_ratio(10), start + Duration::from_secs(40));
+        assert_eq!(window.len(), 1);
+        assert_eq!(window.trend(), Trend::Stable);
+
+        window.clear();
+        assert!(window.is_empty());
+        assert!(window.ewma_idle_ratio().is_none());
+    }
+
+    #[test]
+    fn test_stats_window_idle_above_for() {
+        let start = Instant::now();
+        let minute = Duration::from_secs(60);
+        let mut window = StatsWindow::new(minute * 10);
+
+        for i in 0..6 {
+            window.push_at(stats_with_ratio(70), start + minute * i);
+        }
+        assert!(window.idle_above_for(0.6, minute * 5));
+        assert!(!window.idle_above_for(0.6, minute * 6));
+        assert!(!window.idle_above_for(0.8, minute * 5));
+
+        window.push_at(stats_with_ratio(55), start + minute * 6);
+        assert!(!window.idle_above_for(0.6, minute * 5));
+        assert_eq!(window.trend(), Trend::Stable);
+    }
+
     #[test]
     fn test_filter_functions() {
         let pages = vec![
@@ -0,0 +1,497 @@
+//! Post-reclaim verification and swap device attribution
+//!
+//! Swapping a page out is a request: the kernel may skip pages that are
+//! locked, shared or refaulted before writeback completes. This module
+//! reads `/proc/[pid]/pagemap` after reclaim to check where each page of
+//! the reclaimed ranges actually is, and which swap device holds the ones
+//! that left memory.
+//!
+//! The device matters for the cost of a future refault: a page in zram is
+//! decompressed in microseconds, a page on disk costs a block I/O that is
+//! an order of magnitude slower or worse. [`ReclaimVerification`] therefore
+//! splits the swapped bytes per device and per [`SwapMedium`].
+//!
+//! A pagemap entry of a swapped page names the swap type, the index of the
+//! device in the kernel's swap table. The kernel does not publish the type
+//! of a device: `/proc/swaps` lists devices in type order but skips the
+//! slots of devices that were `swapoff`ed, so a line's position is not its
+//! type. [`SwapTarget::probe`] therefore leaves the types unknown, and a
+//! caller that set up swap itself can supply them with
+//! [`SwapTarget::with_swap_type`]. Swapped pages are counted as on an
+//! unknown device unless their type matches a known one or only one device
+//! is active. The swap type and offset are only visible to `CAP_SYS_ADMIN`.
+//! `/proc/[pid]/smaps` has no per-device information and is not consulted.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::verify::{ReclaimVerification, SwapMedium};
+//! use etmem_rs::AddressRange;
+//!
+//! let pid = 1234;
+//! let reclaimed = [AddressRange::with_size(0x7f00_0000_0000, 64 * 1024 * 1024)];
+//! let verification = ReclaimVerification::check(pid, &reclaimed)?;
+//! println!("{verification}");
+//! println!(
+//!     "{} bytes on zram",
+//!     verification.bytes_on(SwapMedium::Zram)
+//! );
+//! # Ok::<(), etmem_rs::EtmemError>(())
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+
+use crate::error::{EtmemError, Result};
+use crate::guard::SwapDevice;
+use crate::types::{AddressRange, BASE_PAGE_SIZE};
+use crate::util::format_bytes;
+
+/// Size of one `/proc/[pid]/pagemap` entry
+const PAGEMAP_ENTRY_SIZE: u64 = 8;
+
+/// Pagemap entries read per `pread`
+const PAGEMAP_BATCH: usize = 512;
+
+/// Kind of storage behind a swap device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SwapMedium {
+    /// Compressed RAM block device (`/dev/zram*`)
+    Zram,
+    /// Block device partition
+    Partition,
+    /// Swap file on a filesystem
+    File,
+}
+
+impl SwapMedium {
+    /// Classify a `/proc/swaps` entry
+    pub fn of(device: &SwapDevice) -> Self {
+        let name = device
+            .filename
+            .rsplit('/')
+            .next()
+            .unwrap_or(&device.filename);
+        if name.starts_with("zram") {
+            Self::Zram
+        } else if device.kind == "file" {
+            Self::File
+        } else {
+            Self::Partition
+        }
+    }
+
+    /// Whether refaults are served from memory rather than block I/O
+    pub fn is_memory_backed(&self) -> bool {
+        matches!(self, Self::Zram)
+    }
+
+    /// Get the lowercase name of the medium
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Zram => "zram",
+            Self::Partition => "partition",
+            Self::File => "file",
+        }
+    }
+}
+
+impl fmt::Display for SwapMedium {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An active swap device with its kernel swap type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapTarget {
+    /// Swap type as reported in pagemap entries, `None` if unknown
+    pub swap_type: Option<u8>,
+    /// The device as listed in `/proc/swaps`
+    pub device: SwapDevice,
+    /// Storage behind the device
+    pub medium: SwapMedium,
+}
+
+impl SwapTarget {
+    /// Read the active swap devices, with unknown swap types
+    ///
+    /// # Errors
+    /// Returns `ProcfsError` if `/proc/swaps` cannot be read or parsed
+    pub fn probe() -> Result<Vec<Self>> {
+        Ok(Self::from_devices(SwapDevice::read_all()?))
+    }
+
+    /// Wrap devices listed in `/proc/swaps`, with unknown swap types
+    ///
+    /// Types are not derived from the listing order, which skips the types
+    /// of devices that were swapped off.
+    pub fn from_devices(devices: Vec<SwapDevice>) -> Vec<Self> {
+        devices
+            .into_iter()
+            .map(|device| Self {
+                swap_type: None,
+                medium: SwapMedium::of(&device),
+                device,
+            })
+            .collect()
+    }
+
+    /// Set the swap type of the device, for callers that know it
+    #[must_use]
+    pub fn with_swap_type(mut self, swap_type: u8) -> Self {
+        self.swap_type = Some(swap_type);
+        self
+    }
+}
+
+/// A single `/proc/[pid]/pagemap` entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PagemapEntry(pub u64);
+
+impl PagemapEntry {
+    /// Whether the page is in RAM
+    pub const fn is_present(&self) -> bool {
+        self.0 & (1 << 63) != 0
+    }
+
+    /// Whether the page is swapped out
+    pub const fn is_swapped(&self) -> bool {
+        self.0 & (1 << 62) != 0
+    }
+
+    /// Swap type of a swapped page (bits 0-4)
+    pub const fn swap_type(&self) -> u8 {
+        (self.0 & 0x1f) as u8
+    }
+
+    /// Offset of a swapped page in its device (bits 5-54)
+    ///
+    /// Zero when the reader lacks `CAP_SYS_ADMIN`: offset 0 holds the swap
+    /// header and never a page.
+    pub const fn swap_offset(&self) -> u64 {
+        (self.0 >> 5) & ((1 << 50) - 1)
+    }
+}
+
+/// Read the pagemap entries of an address range of a process
+///
+/// # Errors
+/// Returns `ProcessNotFound` if the process does not exist and
+/// `InvalidAddress` if the range is empty or not page aligned
+pub fn read_pagemap(pid: u32, range: AddressRange) -> Result<Vec<PagemapEntry>> {
+    let dir = format!("/proc/{}", pid);
+    if !Path::new(&dir).exists() {
+        return Err(EtmemError::ProcessNotFound);
+    }
+    read_pagemap_in(&Path::new(&dir).join("pagemap"), range)
+}
+
+/// Read the pagemap entries of an address range from a pagemap file
+///
+/// # Errors
+/// Returns `InvalidAddress` if the range is empty or not page aligned and
+/// `ProcfsError` if the file cannot be read
+pub fn read_pagemap_in(path: &Path, range: AddressRange) -> Result<Vec<PagemapEntry>> {
+    if !range.is_valid()
+        || !range.start.is_multiple_of(BASE_PAGE_SIZE)
+        || !range.end.is_multiple_of(BASE_PAGE_SIZE)
+    {
+        return Err(EtmemError::InvalidAddress);
+    }
+    let file = File::open(path)
+        .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", path.display(), e)))?;
+
+    let pages = range.size() / BASE_PAGE_SIZE;
+    let mut entries = Vec::with_capacity(pages as usize);
+    let mut buf = vec![0u8; PAGEMAP_BATCH * PAGEMAP_ENTRY_SIZE as usize];
+    let mut page = range.start / BASE_PAGE_SIZE;
+    let end = range.end / BASE_PAGE_SIZE;
+    while page < end {
+        let batch = (end - page).min(PAGEMAP_BATCH as u64) as usize;
+        let chunk = &mut buf[..batch * PAGEMAP_ENTRY_SIZE as usize];
+        file.read_exact_at(chunk, page * PAGEMAP_ENTRY_SIZE)
+            .map_err(|e| EtmemError::ProcfsError(format!("{}: {}", path.display(), e)))?;
+        entries.extend(
+            chunk
+                .chunks_exact(PAGEMAP_ENTRY_SIZE as usize)
+                .map(|raw| PagemapEntry(u64::from_ne_bytes(raw.try_into().unwrap_or_default()))),
+        );
+        page += batch as u64;
+    }
+    Ok(entries)
+}
+
+/// Bytes of reclaimed memory held by one swap device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceUsage {
+    /// The swap device
+    pub target: SwapTarget,
+    /// Bytes of the checked ranges swapped to it
+    pub bytes: u64,
+}
+
+/// Where the pages of reclaimed ranges are after reclaim
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReclaimVerification {
+    /// Bytes checked
+    pub checked_bytes: u64,
+    /// Bytes still (or again) in RAM
+    pub resident_bytes: u64,
+    /// Swapped bytes per device, in `/proc/swaps` order
+    pub devices: Vec<DeviceUsage>,
+    /// Swapped bytes whose device could not be determined
+    pub unknown_device_bytes: u64,
+    /// Bytes neither resident nor swapped (never populated or freed)
+    pub unmapped_bytes: u64,
+}
+
+impl ReclaimVerification {
+    /// Check reclaimed ranges of a process against its pagemap
+    ///
+    /// # Errors
+    /// Returns `ProcessNotFound` if the process exited, `InvalidAddress`
+    /// for a range that is not page aligned and `ProcfsError` if
+    /// `/proc/swaps` or the pagemap cannot be read
+    pub fn check(pid: u32, ranges: &[AddressRange]) -> Result<Self> {
+        let targets = SwapTarget::probe()?;
+        let mut verification = Self::new(targets);
+        for range in ranges {
+            verification.record(&read_pagemap(pid, *range)?);
+        }
+        Ok(verification)
+    }
+
+    /// Create an empty verification for a set of swap devices
+    pub fn new(targets: Vec<SwapTarget>) -> Self {
+        Self {
+            devices: targets
+                .into_iter()
+                .map(|target| DeviceUsage { target, bytes: 0 })
+                .collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Account for the pagemap entries of one range
+    pub fn record(&mut self, entries: &[PagemapEntry]) {
+        for entry in entries {
+            self.checked_bytes += BASE_PAGE_SIZE;
+            if entry.is_present() {
+                self.resident_bytes += BASE_PAGE_SIZE;
+            } else if !entry.is_swapped() {
+                self.unmapped_bytes += BASE_PAGE_SIZE;
+            } else if let Some(usage) = self.device_of(entry) {
+                usage.bytes += BASE_PAGE_SIZE;
+            } else {
+                self.unknown_device_bytes += BASE_PAGE_SIZE;
+            }
+        }
+    }
+
+    /// Find the device holding a swapped page
+    fn device_of(&mut self, entry: &PagemapEntry) -> Option<&mut DeviceUsage> {
+        if self.devices.len() == 1 {
+            return self.devices.first_mut();
+        }
+        if entry.swap_offset() == 0 {
+            // Swap location hidden from unprivileged readers
+            return None;
+        }
+        self.devices
+            .iter_mut()
+            .find(|usage| usage.target.swap_type == Some(entry.swap_type()))
+    }
+
+    /// Total swapped bytes, including those on an unknown device
+    pub fn swapped_bytes(&self) -> u64 {
+        self.devices.iter().map(|usage| usage.bytes).sum::<u64>() + self.unknown_device_bytes
+    }
+
+    /// Swapped bytes on devices of one medium
+    pub fn bytes_on(&self, medium: SwapMedium) -> u64 {
+        self.devices
+            .iter()
+            .filter(|usage| usage.target.medium == medium)
+            .map(|usage| usage.bytes)
+            .sum()
+    }
+
+    /// Swapped bytes whose refault needs block I/O
+    pub fn disk_bytes(&self) -> u64 {
+        self.devices
+            .iter()
+            .filter(|usage| !usage.target.medium.is_memory_backed())
+            .map(|usage| usage.bytes)
+            .sum()
+    }
+
+    /// Fraction of the populated checked memory that left RAM (0.0 - 1.0)
+    pub fn reclaimed_ratio(&self) -> f64 {
+        let populated = self.resident_bytes + self.swapped_bytes();
+        if populated == 0 {
+            0.0
+        } else {
+            self.swapped_bytes() as f64 / populated as f64
+        }
+    }
+}
+
+impl fmt::Display for ReclaimVerification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Checked {}: {} swapped ({:.1}%), {} resident, {} unmapped",
+            format_bytes(self.checked_bytes),
+            format_bytes(self.swapped_bytes()),
+            self.reclaimed_ratio() * 100.0,
+            format_bytes(self.resident_bytes),
+            format_bytes(self.unmapped_bytes)
+        )?;
+        for usage in self.devices.iter().filter(|usage| usage.bytes > 0) {
+            let swap_type = usage
+                .target
+                .swap_type
+                .map_or_else(|| "unknown".to_string(), |t| t.to_string());
+            writeln!(
+                f,
+                "  {} ({}, type {}): {}",
+                usage.target.device.filename,
+                usage.target.medium,
+                swap_type,
+                format_bytes(usage.bytes)
+            )?;
+        }
+        if self.unknown_device_bytes > 0 {
+            writeln!(
+                f,
+                "  unknown device: {}",
+                format_bytes(self.unknown_device_bytes)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SWAPS: &str = "\
+Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority
+/dev/zram0                              partition\t8388604\t\t1024\t\t100
+/dev/nvme0n1p3                          partition\t16777212\t0\t\t-2
+/swapfile                               file\t\t2097148\t\t0\t\t-3
+";
+
+    fn swapped(swap_type: u8, offset: u64) -> PagemapEntry {
+        PagemapEntry((1 << 62) | (offset << 5) | u64::from(swap_type))
+    }
+
+    /// Targets of [`SWAPS`] with the given swap types
+    fn typed_targets(types: [u8; 3]) -> Vec<SwapTarget> {
+        SwapTarget::from_devices(SwapDevice::parse_all(SWAPS).unwrap())
+            .into_iter()
+            .zip(types)
+            .map(|(target, swap_type)| target.with_swap_type(swap_type))
+            .collect()
+    }
+
+    #[test]
+    fn test_swap_targets() {
+        let targets = SwapTarget::from_devices(SwapDevice::parse_all(SWAPS).unwrap());
+        let media: Vec<_> = targets.iter().map(|t| (t.swap_type, t.medium)).collect();
+        assert_eq!(
+            media,
+            vec![
+                (None, SwapMedium::Zram),
+                (None, SwapMedium::Partition),
+                (None, SwapMedium::File),
+            ]
+        );
+        assert_eq!(typed_targets([0, 2, 3])[1].swap_type, Some(2));
+        assert!(SwapMedium::Zram.is_memory_backed());
+        assert!(!SwapMedium::File.is_memory_backed());
+    }
+
+    #[test]
+    fn test_pagemap_entry() {
+        let entry = swapped(3, 0x1234);
+        assert!(entry.is_swapped());
+        assert!(!entry.is_present());
+        assert_eq!(entry.swap_type(), 3);
+        assert_eq!(entry.swap_offset(), 0x1234);
+        assert!(PagemapEntry(1 << 63).is_present());
+    }
+
+    #[test]
+    fn test_verification_attribution() {
+        let mut verification = ReclaimVerification::new(typed_targets([0, 1, 2]));
+        verification.record(&[
+            swapped(0, 10),
+            swapped(0, 11),
+            swapped(1, 7),
+            swapped(9, 1),
+            PagemapEntry(1 << 63),
+            PagemapEntry(0),
+        ]);
+
+        assert_eq!(verification.checked_bytes, 6 * BASE_PAGE_SIZE);
+        assert_eq!(verification.bytes_on(SwapMedium::Zram), 2 * BASE_PAGE_SIZE);
+        assert_eq!(verification.disk_bytes(), BASE_PAGE_SIZE);
+        assert_eq!(verification.unknown_device_bytes, BASE_PAGE_SIZE);
+        assert_eq!(verification.swapped_bytes(), 4 * BASE_PAGE_SIZE);
+        assert_eq!(verification.resident_bytes, BASE_PAGE_SIZE);
+        assert_eq!(verification.unmapped_bytes, BASE_PAGE_SIZE);
+        assert!((verification.reclaimed_ratio() - 0.8).abs() < 1e-9);
+        assert!(
+            verification
+                .to_string()
+                .contains("/dev/zram0 (zram, type 0)")
+        );
+
+        // Hidden swap location: attributable only with a single device
+        let only = SwapTarget::from_devices(SwapDevice::parse_all(SWAPS).unwrap())
+            .into_iter()
+            .take(1)
+            .collect();
+        let mut single = ReclaimVerification::new(only);
+        single.record(&[swapped(0, 0)]);
+        assert_eq!(single.bytes_on(SwapMedium::Zram), BASE_PAGE_SIZE);
+        assert!(
+            single
+                .to_string()
+                .contains("/dev/zram0 (zram, type unknown)")
+        );
+        verification.record(&[swapped(0, 0)]);
+        assert_eq!(verification.unknown_device_bytes, 2 * BASE_PAGE_SIZE);
+
+        // Without known types, pages of several devices are not attributed
+        let untyped = SwapTarget::from_devices(SwapDevice::parse_all(SWAPS).unwrap());
+        let mut untyped = ReclaimVerification::new(untyped);
+        untyped.record(&[swapped(0, 10), swapped(1, 7)]);
+        assert_eq!(untyped.unknown_device_bytes, 2 * BASE_PAGE_SIZE);
+        assert_eq!(untyped.bytes_on(SwapMedium::Zram), 0);
+    }
+
+    #[test]
+    fn test_read_pagemap_in() {
+        let entries = [PagemapEntry(1 << 63), swapped(1, 42), PagemapEntry(0)];
+        let mut raw = vec![0u8; 16 * PAGEMAP_ENTRY_SIZE as usize];
+        for (i, entry) in entries.iter().enumerate() {
+            let at = (4 + i) * PAGEMAP_ENTRY_SIZE as usize;
+            raw[at..at + 8].copy_from_slice(&entry.0.to_ne_bytes());
+        }
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), raw).unwrap();
+
+        let range = AddressRange::with_size(4 * BASE_PAGE_SIZE, 3 * BASE_PAGE_SIZE);
+        assert_eq!(read_pagemap_in(file.path(), range).unwrap(), entries);
+        assert!(matches!(
+            read_pagemap_in(file.path(), AddressRange::new(1, BASE_PAGE_SIZE)),
+            Err(EtmemError::InvalidAddress)
+        ));
+    }
+}
@@ -30,14 +30,15 @@ use std::path::Path;
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 
+use crate::dax::{DaxKind, DaxMounts};
 use crate::error::{EtmemError, Result};
-use crate::types::AddressRange;
+use crate::types::{AddressRange, RangeSet};
 
 /// Represents a Virtual Memory Area (memory mapping)
 ///
 /// This struct contains all the metadata for a single memory region
 /// as reported by `/proc/[pid]/maps`.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct VmaRegion {
     /// Start address of the region (inclusive)
     pub start: u64,
@@ -55,10 +56,13 @@ pub struct VmaRegion {
     pub pathname: Option<String>,
     /// Parsed pathname type
     pub pathname_type: PathnameType,
+    /// DAX kind, if the mapping bypasses the page cache
+    #[serde(default)]
+    pub dax: Option<DaxKind>,
 }
 
 /// Type of pathname for a VMA
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PathnameType {
     /// Anonymous mapping (no file)
     Anonymous,
@@ -95,7 +99,7 @@ impl PathnameType {
 }
 
 /// Memory permissions for a VMA
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct VmaPermissions {
     /// Read permission
     pub read: bool,
@@ -184,6 +188,8 @@ bitflags! {
         const SCANNABLE = 0x0400;
         /// Swappable regions (writable anonymous)
         const SWAPPABLE = 0x0800;
+        /// DAX mappings (device-DAX or fsdax)
+        const DAX = 0x1000;
     }
 }
 
@@ -199,6 +205,7 @@ impl VmaRegion {
             inode: 0,
             pathname: None,
             pathname_type: PathnameType::Anonymous,
+            dax: None,
         }
     }
 
@@ -222,6 +229,30 @@ impl VmaRegion {
         self.pathname_type == PathnameType::Anonymous
     }
 
+    /// Check if this VMA is backed by a regular file's page cache
+    ///
+    /// Shared memory (SysV, memfd, `/dev/zero`) also shows a path and inode
+    /// but is swap-backed like anonymous memory, so it is excluded.
+    pub fn is_file_backed(&self) -> bool {
+        let Some(path) = self.pathname.as_deref() else {
+            return false;
+        };
+        self.inode != 0
+            && matches!(
+                self.pathname_type,
+                PathnameType::Program | PathnameType::SharedLibrary | PathnameType::MappedFile
+            )
+            && !path.starts_with("/SYSV")
+            && !path.starts_with("/memfd:")
+            && !path.starts_with("/dev/")
+            && self.dax.is_none()
+    }
+
+    /// Check if this VMA maps DAX memory (device-DAX or fsdax)
+    pub fn is_dax(&self) -> bool {
+        self.dax.is_some()
+    }
+
     /// Check if this is the heap VMA
     pub fn is_heap(&self) -> bool {
         self.pathname_type == PathnameType::Heap
@@ -251,10 +282,12 @@ impl VmaRegion {
     /// - Writable (to avoid swapping read-only data)
     /// - Anonymous or private (file-backed shared pages have different semantics)
     /// - Not the stack (stack pages shouldn't be swapped mid-execution)
+    /// - Not DAX (device or persistent memory cannot be swapped)
     pub fn is_swappable(&self) -> bool {
         self.permissions.write
             && (self.is_anonymous() || self.permissions.is_private())
             && !self.is_stack()
+            && !self.is_dax()
     }
 
     /// Check if this region matches the given filter criteria
@@ -297,6 +330,9 @@ impl VmaRegion {
         if filter.contains(VmaFilter::SWAPPABLE) && !self.is_swappable() {
             return false;
         }
+        if filter.contains(VmaFilter::DAX) && !self.is_dax() {
+            return false;
+        }
         true
     }
 
@@ -351,7 +387,12 @@ impl VmaMap {
     /// - Parse error
     pub fn for_process(pid: u32) -> Result<Self> {
         let path = format!("/proc/{}/maps", pid);
-        Self::from_file(&path, pid)
+        let mut map = Self::from_file(&path, pid)?;
+        match DaxMounts::for_process(pid) {
+            Ok(mounts) => map.classify_dax(&mounts),
+            Err(e) => log::debug!("Cannot detect fsdax mappings of {}: {}", pid, e),
+        }
+        Ok(map)
     }
 
     /// Parse VMAs from a file (useful for testing)
@@ -447,6 +488,7 @@ impl VmaMap {
         };
 
         let pathname_type = PathnameType::from_pathname(pathname.as_deref());
+        let dax = DaxKind::from_pathname(pathname.as_deref());
 
         Ok(VmaRegion {
             start,
@@ -457,6 +499,7 @@ impl VmaMap {
             inode,
             pathname,
             pathname_type,
+            dax,
         })
     }
 
@@ -561,33 +604,31 @@ impl VmaMap {
     ///
     /// Adjacent or overlapping regions are merged into single ranges.
     pub fn merged_ranges(&self, filter: VmaFilter) -> Vec<AddressRange> {
-        let mut ranges: Vec<AddressRange> = self
-            .regions
+        self.regions
             .iter()
             .filter(|r| r.matches_filter(filter))
             .map(|r| r.to_address_range())
-            .collect();
-
-        if ranges.is_empty() {
-            return ranges;
-        }
-
-        // Sort by start address
-        ranges.sort_by_key(|r| r.start);
+            .collect::<RangeSet>()
+            .into_vec()
+    }
 
-        // Merge overlapping or adjacent ranges
-        let mut merged = vec![ranges[0]];
-        for range in ranges.iter().skip(1) {
-            let last = merged.last_mut().unwrap();
-            if range.start <= last.end {
-                // Overlapping or adjacent, merge
-                last.end = last.end.max(range.end);
-            } else {
-                merged.push(*range);
-            }
+    /// Classify DAX mappings using the mount table of the process
+    ///
+    /// [`for_process`](Self::for_process) does this already; maps read
+    /// with [`from_file`](Self::from_file) only know device-DAX.
+    pub fn classify_dax(&mut self, mounts: &DaxMounts) {
+        for region in &mut self.regions {
+            region.dax = mounts.classify(region.pathname.as_deref());
         }
+    }
 
-        merged
+    /// Get the address ranges of DAX mappings, which must never be swapped
+    pub fn dax_ranges(&self) -> RangeSet {
+        self.regions
+            .iter()
+            .filter(|r| r.is_dax())
+            .map(|r| r.to_address_range())
+            .collect()
     }
 }
 
@@ -674,6 +715,57 @@ mod tests {
 
         assert_eq!(vma.pathname_type, PathnameType::SharedLibrary);
         assert!(!vma.is_anonymous());
+        assert!(vma.is_file_backed());
+    }
+
+    #[test]
+    fn test_is_file_backed() {
+        let heap = VmaMap::parse_line("55c3e616e000-55c3e618f000 rw-p 00000000 00:00 0 [heap]");
+        assert!(!heap.unwrap().is_file_backed());
+
+        let sysv = VmaMap::parse_line(
+            "7f0000000000-7f0000100000 rw-s 00000000 00:01 32768 /SYSV00001234 (deleted)",
+        );
+        assert!(!sysv.unwrap().is_file_backed());
+
+        let memfd = VmaMap::parse_line(
+            "7f0000000000-7f0000100000 rw-s 00000000 00:01 1025 /memfd:pool (deleted)",
+        );
+        assert!(!memfd.unwrap().is_file_backed());
+
+        let file = VmaMap::parse_line(
+            "7f0000000000-7f0000100000 r--s 00000000 08:01 4242 /var/data/index.db",
+        );
+        assert!(file.unwrap().is_file_backed());
+    }
+
+    #[test]
+    fn test_dax_mappings() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut file,
+            b"7f0000000000-7f0040000000 rw-s 00000000 00:06 300 /dev/dax0.0\n\
+              7f0040000000-7f0040200000 rw-s 00000000 103:01 12 /mnt/pmem/table.db\n\
+              7f0040200000-7f0040400000 rw-p 00000000 00:00 0\n",
+        )
+        .unwrap();
+        let mut map = VmaMap::from_file(file.path(), 1).unwrap();
+        assert_eq!(map.regions()[0].dax, Some(DaxKind::Device));
+        assert_eq!(map.regions()[1].dax, None);
+
+        map.classify_dax(&DaxMounts::parse(
+            "30 22 103:1 / /mnt/pmem rw - ext4 /dev/pmem0 rw,dax=always\n",
+        ));
+        let fsdax = &map.regions()[1];
+        assert_eq!(fsdax.dax, Some(DaxKind::Fs));
+        assert!(!fsdax.is_file_backed());
+        assert!(!fsdax.is_swappable());
+        assert_eq!(map.filter(VmaFilter::DAX).len(), 2);
+        assert_eq!(map.swappable().len(), 1);
+        assert_eq!(
+            map.dax_ranges().ranges(),
+            [AddressRange::new(0x7f00_0000_0000, 0x7f00_4020_0000)]
+        );
     }
 
     #[test]
@@ -687,6 +779,7 @@ mod tests {
             inode: 0,
             pathname: None,
             pathname_type: PathnameType::Anonymous,
+            dax: None,
         };
 
         assert_eq!(vma.size(), 0x4000);
@@ -707,6 +800,7 @@ mod tests {
             inode: 0,
             pathname: None,
             pathname_type: PathnameType::Anonymous,
+            dax: None,
         };
 
         assert!(vma.matches_filter(VmaFilter::ANONYMOUS));
@@ -0,0 +1,443 @@
+//! Watchdog stopping reclaim that hurts the target process
+//!
+//! The swap guard decides whether reclaim may start; it cannot tell whether
+//! the pages taken were actually cold. When they were not, the target
+//! process faults them straight back in. The watchdog samples the target's
+//! major fault count (`/proc/<pid>/stat`) and the system swap-in count
+//! (`pswpin` in `/proc/vmstat`) around each reclaim round, and pauses the
+//! policy for a while once either rate exceeds its threshold. It can also
+//! prefetch the most recently reclaimed ranges back, so the process does
+//! not pay for each fault one page at a time.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use etmem_rs::watchdog::{Watchdog, WatchdogConfig, WatchdogDecision};
+//! use etmem_rs::{AddressRange, SwapConfig, SwapSession};
+//!
+//! let pid = 1234;
+//! let mut watchdog = Watchdog::new(pid, WatchdogConfig::new().with_prefetch_ranges(64));
+//! let mut swap = SwapSession::new(pid, SwapConfig::default()).expect("Failed to open session");
+//!
+//! let range = AddressRange::new(0x7f00_0000_0000, 0x7f00_0040_0000);
+//! let decision = watchdog
+//!     .run(|| {
+//!         swap.add_range(range, 4096)?;
+//!         swap.flush()?;
+//!         Ok(vec![range])
+//!     })
+//!     .expect("Failed to reclaim");
+//! if let WatchdogDecision::Tripped(reason) = decision {
+//!     println!("Reclaim paused: {}", reason);
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::error::{EtmemError, Result};
+use crate::guard::read_pswpin;
+use crate::types::AddressRange;
+
+/// Read the cumulative major fault count of a process
+///
+/// # Errors
+/// Returns `ProcessNotFound` if the process has exited.
+pub fn read_major_faults(pid: u32) -> Result<u64> {
+    let path = format!("/proc/{}/stat", pid);
+    let content = std::fs::read_to_string(&path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => EtmemError::ProcessNotFound,
+        _ => EtmemError::ProcfsError(format!("{}: {}", path, e)),
+    })?;
+    parse_major_faults(&content)
+}
+
+/// Extract `majflt` from the contents of `/proc/<pid>/stat`
+///
+/// The command name may contain spaces and parentheses, so fields are
+/// counted from the last `)`.
+pub fn parse_major_faults(content: &str) -> Result<u64> {
+    // majflt is field 12; field 3 (state) is the first after the name
+    const MAJFLT_AFTER_NAME: usize = 12 - 3;
+    content
+        .rsplit_once(')')
+        .and_then(|(_, rest)| rest.split_whitespace().nth(MAJFLT_AFTER_NAME))
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| EtmemError::ProcfsError("majflt missing from stat".to_string()))
+}
+
+/// Counters sampled by the watchdog
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultSample {
+    /// Cumulative major faults of the target process
+    pub major_faults: u64,
+    /// Cumulative system swap-in page count
+    pub pswpin: u64,
+}
+
+impl FaultSample {
+    /// Sample the counters of a process
+    pub fn capture(pid: u32) -> Result<Self> {
+        Ok(Self {
+            major_faults: read_major_faults(pid)?,
+            pswpin: read_pswpin()?,
+        })
+    }
+}
+
+/// Thresholds and reaction of the watchdog
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchdogConfig {
+    /// Trip when the target exceeds this many major faults per second
+    pub max_major_fault_rate: Option<f64>,
+    /// Trip when system swap-in exceeds this many pages per second
+    pub max_swapin_rate: Option<f64>,
+    /// How long reclaim stays paused after a trip
+    pub pause: Duration,
+    /// Number of most recently reclaimed ranges prefetched on a trip (0 = off)
+    pub prefetch_ranges: usize,
+}
+
+impl WatchdogConfig {
+    /// Create a watchdog configuration with default thresholds
+    ///
+    /// Defaults: 200 major faults/s, 2000 pages/s swap-in, 60s pause, no
+    /// prefetch.
+    pub const fn new() -> Self {
+        Self {
+            max_major_fault_rate: Some(200.0),
+            max_swapin_rate: Some(2000.0),
+            pause: Duration::from_secs(60),
+            prefetch_ranges: 0,
+        }
+    }
+
+    /// Set maximum major fault rate of the target, `None` to disable
+    pub const fn with_max_major_fault_rate(mut self, rate: Option<f64>) -> Self {
+        self.max_major_fault_rate = rate;
+        self
+    }
+
+    /// Set maximum system swap-in rate (pages per second), `None` to disable
+    pub const fn with_max_swapin_rate(mut self, rate: Option<f64>) -> Self {
+        self.max_swapin_rate = rate;
+        self
+    }
+
+    /// Set how long reclaim is paused after a trip
+    pub const fn with_pause(mut self, pause: Duration) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Prefetch up to `count` of the most recently reclaimed ranges on a trip
+    pub const fn with_prefetch_ranges(mut self, count: usize) -> Self {
+        self.prefetch_ranges = count;
+        self
+    }
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Outcome of a watchdog check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchdogDecision {
+    /// Reclaim may continue
+    Continue,
+    /// Reclaim is paused for the remaining duration
+    Paused(Duration),
+    /// A threshold was just exceeded and reclaim is now paused
+    Tripped(String),
+}
+
+impl WatchdogDecision {
+    /// Check if reclaim may continue
+    pub fn is_continue(&self) -> bool {
+        matches!(self, Self::Continue)
+    }
+}
+
+/// Watchdog over the reclaim of one process
+///
+/// Rates are derived from consecutive samples, so a single watchdog should
+/// be reused across reclaim rounds.
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    /// Process being reclaimed
+    pid: u32,
+    /// Thresholds
+    config: WatchdogConfig,
+    /// Previous sample and when it was taken
+    last: Option<(FaultSample, Instant)>,
+    /// End of the current pause
+    paused_until: Option<Instant>,
+    /// Most recently reclaimed ranges, newest last
+    recent: VecDeque<AddressRange>,
+    /// Number of trips so far
+    trips: u64,
+}
+
+impl Watchdog {
+    /// Create a watchdog for a process
+    pub fn new(pid: u32, config: WatchdogConfig) -> Self {
+        Self {
+            pid,
+            config,
+            last: None,
+            paused_until: None,
+            recent: VecDeque::new(),
+            trips: 0,
+        }
+    }
+
+    /// Get the watchdog configuration
+    pub fn config(&self) -> &WatchdogConfig {
+        &self.config
+    }
+
+    /// Get the process being watched
+    pub fn pid(&self) -> u32 {
+        self.pid
+    }
+
+    /// Get the number of trips so far
+    pub fn trips(&self) -> u64 {
+        self.trips
+    }
+
+    /// Check if reclaim is paused at `now`
+    pub fn is_paused(&self, now: Instant) -> bool {
+        self.paused_until.is_some_and(|until| now < until)
+    }
+
+    /// End the current pause early
+    pub fn resume(&mut self) {
+        self.paused_until = None;
+    }
+
+    /// Remember ranges that were just reclaimed, for prefetching on a trip
+    ///
+    /// Only the `prefetch_ranges` most recent ranges are kept.
+    pub fn record_reclaimed(&mut self, ranges: &[AddressRange]) {
+        let keep = self.config.prefetch_ranges;
+        if keep == 0 {
+            return;
+        }
+        self.recent
+            .extend(ranges.iter().copied().filter(|r| r.is_valid()));
+        let excess = self.recent.len().saturating_sub(keep);
+        self.recent.drain(..excess);
+    }
+
+    /// Get the ranges that a trip would prefetch, oldest first
+    pub fn recent_ranges(&self) -> impl Iterator<Item = &AddressRange> {
+        self.recent.iter()
+    }
+
+    /// Sample the counters, evaluate them and prefetch on a trip
+    ///
+    /// A failed prefetch is logged and does not fail the check.
+    ///
+    /// # Errors
+    /// Returns error if the counters cannot be read, for instance because
+    /// the process exited.
+    pub fn check(&mut self) -> Result<WatchdogDecision> {
+        let sample = FaultSample::capture(self.pid)?;
+        let decision = self.evaluate(sample, Instant::now());
+        if matches!(decision, WatchdogDecision::Tripped(_)) {
+            self.prefetch();
+        }
+        Ok(decision)
+    }
+
+    /// Evaluate a sample taken at `now`
+    ///
+    /// The first evaluation has no baseline and never trips. While paused
+    /// the baseline keeps moving, so a pause ends with fresh rates.
+    pub fn evaluate(&mut self, sample: FaultSample, now: Instant) -> WatchdogDecision {
+        let rates = self.last.and_then(|(prev, at)| {
+            let elapsed = now.saturating_duration_since(at).as_secs_f64();
+            (elapsed > 0.0).then(|| {
+                (
+                    sample.major_faults.saturating_sub(prev.major_faults) as f64 / elapsed,
+                    sample.pswpin.saturating_sub(prev.pswpin) as f64 / elapsed,
+                )
+            })
+        });
+        self.last = Some((sample, now));
+
+        if let Some(until) = self.paused_until {
+            if now < until {
+                return WatchdogDecision::Paused(until - now);
+            }
+            self.paused_until = None;
+        }
+
+        let Some((fault_rate, swapin_rate)) = rates else {
+            return WatchdogDecision::Continue;
+        };
+        let reason = match (
+            self.config.max_major_fault_rate,
+            self.config.max_swapin_rate,
+        ) {
+            (Some(limit), _) if fault_rate > limit => format!(
+                "pid {} major faults {:.0}/s above {:.0}/s",
+                self.pid, fault_rate, limit
+            ),
+            (_, Some(limit)) if swapin_rate > limit => {
+                format!("swap-in {:.0} pages/s above {:.0}/s", swapin_rate, limit)
+            }
+            _ => return WatchdogDecision::Continue,
+        };
+
+        self.trips += 1;
+        self.paused_until = Some(now + self.config.pause);
+        log::warn!(
+            "Watchdog tripped, pausing reclaim for {:?}: {}",
+            self.config.pause,
+            reason
+        );
+        WatchdogDecision::Tripped(reason)
+    }
+
+    /// Run one reclaim round under the watchdog
+    ///
+    /// Checks before the round and skips it unless reclaim may continue.
+    /// Otherwise runs `reclaim`, which returns the ranges it reclaimed, and
+    /// checks again so that damage done by the round pauses the next one.
+    ///
+    /// # Errors
+    /// Returns the error of `reclaim` or of a check.
+    pub fn run(
+        &mut self,
+        reclaim: impl FnOnce() -> Result<Vec<AddressRange>>,
+    ) -> Result<WatchdogDecision> {
+        let before = self.check()?;
+        if !before.is_continue() {
+            return Ok(before);
+        }
+        let ranges = reclaim()?;
+        self.record_reclaimed(&ranges);
+        self.check()
+    }
+
+    /// Prefetch the recently reclaimed ranges back into memory
+    fn prefetch(&mut self) {
+        if self.recent.is_empty() {
+            return;
+        }
+        let ranges: Vec<(u64, u64)> = self.recent.drain(..).map(|r| (r.start, r.size())).collect();
+        match crate::sys::prefetch_ranges(self.pid, &ranges) {
+            Ok(bytes) => log::info!(
+                "Prefetched {} bytes in {} ranges of pid {}",
+                bytes,
+                ranges.len(),
+                self.pid
+            ),
+            Err(e) => log::warn!("Failed to prefetch ranges of pid {}: {}", self.pid, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT: &str = "4242 (my (odd) app) S 1 4242 4242 0 -1 4194560 1500 0 37 0 \
+                        12 3 0 0 20 0 4 0 123456 1000000 250 18446744073709551615";
+
+    fn sample(major_faults: u64, pswpin: u64) -> FaultSample {
+        FaultSample {
+            major_faults,
+            pswpin,
+        }
+    }
+
+    #[test]
+    fn test_parse_major_faults() {
+        assert_eq!(parse_major_faults(STAT).unwrap(), 37);
+        assert!(parse_major_faults("4242 (short) S 1").is_err());
+        assert!(parse_major_faults("").is_err());
+    }
+
+    #[test]
+    fn test_watchdog_continues_below_thresholds() {
+        let mut watchdog = Watchdog::new(1, WatchdogConfig::default());
+        let start = Instant::now();
+        assert!(watchdog.evaluate(sample(0, 0), start).is_continue());
+        let later = start + Duration::from_secs(1);
+        assert!(watchdog.evaluate(sample(100, 1000), later).is_continue());
+        assert_eq!(watchdog.trips(), 0);
+    }
+
+    #[test]
+    fn test_watchdog_trips_and_pauses() {
+        let config = WatchdogConfig::new().with_pause(Duration::from_secs(10));
+        let mut watchdog = Watchdog::new(1, config);
+        let start = Instant::now();
+        watchdog.evaluate(sample(0, 0), start);
+
+        // 500 major faults in one second exceeds the default 200/s
+        let t1 = start + Duration::from_secs(1);
+        let decision = watchdog.evaluate(sample(500, 0), t1);
+        assert!(matches!(decision, WatchdogDecision::Tripped(ref r) if r.contains("major faults")));
+        assert!(watchdog.is_paused(t1));
+        assert_eq!(watchdog.trips(), 1);
+
+        let t2 = t1 + Duration::from_secs(4);
+        assert_eq!(
+            watchdog.evaluate(sample(500, 0), t2),
+            WatchdogDecision::Paused(Duration::from_secs(6))
+        );
+
+        // Quiet again once the pause is over
+        let t3 = t1 + Duration::from_secs(11);
+        assert!(watchdog.evaluate(sample(510, 0), t3).is_continue());
+        assert!(!watchdog.is_paused(t3));
+    }
+
+    #[test]
+    fn test_watchdog_trips_on_swapin() {
+        let config = WatchdogConfig::new().with_max_major_fault_rate(None);
+        let mut watchdog = Watchdog::new(1, config);
+        let start = Instant::now();
+        watchdog.evaluate(sample(0, 0), start);
+        let decision = watchdog.evaluate(sample(10_000, 5000), start + Duration::from_secs(1));
+        assert!(matches!(decision, WatchdogDecision::Tripped(ref r) if r.contains("swap-in")));
+
+        watchdog.resume();
+        assert!(!watchdog.is_paused(start + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_record_reclaimed_keeps_most_recent() {
+        let mut watchdog = Watchdog::new(1, WatchdogConfig::new().with_prefetch_ranges(2));
+        watchdog.record_reclaimed(&[
+            AddressRange::new(0x1000, 0x2000),
+            AddressRange::new(0x3000, 0x3000),
+            AddressRange::new(0x4000, 0x6000),
+        ]);
+        watchdog.record_reclaimed(&[AddressRange::new(0x8000, 0x9000)]);
+        let recent: Vec<_> = watchdog.recent_ranges().copied().collect();
+        assert_eq!(
+            recent,
+            vec![
+                AddressRange::new(0x4000, 0x6000),
+                AddressRange::new(0x8000, 0x9000)
+            ]
+        );
+
+        let mut off = Watchdog::new(1, WatchdogConfig::default());
+        off.record_reclaimed(&[AddressRange::new(0x1000, 0x2000)]);
+        assert_eq!(off.recent_ranges().count(), 0);
+    }
+
+    #[test]
+    fn test_read_major_faults_self() {
+        assert!(read_major_faults(std::process::id()).is_ok());
+    }
+}
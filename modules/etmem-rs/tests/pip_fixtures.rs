@@ -0,0 +1,90 @@
+//! Golden tests decoding `idle_pages` buffers
+//!
+//! Every `tests/fixtures/idle_pages/<name>.bin` is a synthetic buffer laid
+//! out as `/proc/<pid>/idle_pages` (or the VM variant) would return it; see
+//! the fixtures' README. Its expected decoding is in
+//! `<name>.golden`, one `IdlePageInfo` per line in its `Display` form. Decoding
+//! starts at address 0, as a scan session does before the first `SET_HVA`.
+//!
+//! Run with `ETMEM_BLESS=1` to rewrite the golden files after an intended
+//! decoder change or when adding a capture, then review the diff.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use etmem_rs::{IdlePageInfo, PageIdleCtrl};
+
+/// Directory of the fixture buffers
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/idle_pages")
+}
+
+/// Decode a buffer with the decoder used by scan sessions
+fn decode(data: &[u8]) -> Vec<IdlePageInfo> {
+    PageIdleCtrl::default()
+        .decode_pip_data(data, 0)
+        .expect("buffer decodes")
+}
+
+/// Render decoded pages in golden file form
+fn render(pages: &[IdlePageInfo]) -> String {
+    pages.iter().map(|page| format!("{page}\n")).collect()
+}
+
+#[test]
+fn test_decode_fixtures() {
+    let bless = std::env::var_os("ETMEM_BLESS").is_some();
+    let mut buffers: Vec<PathBuf> = fs::read_dir(fixtures_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bin"))
+        .collect();
+    buffers.sort();
+    assert!(!buffers.is_empty(), "no buffers in {:?}", fixtures_dir());
+
+    let mut mismatched = Vec::new();
+    for buffer in &buffers {
+        let actual = render(&decode(&fs::read(buffer).unwrap()));
+        let golden = buffer.with_extension("golden");
+        if bless {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&golden)
+            .unwrap_or_else(|e| panic!("{}: {e} (run with ETMEM_BLESS=1)", golden.display()));
+        if actual != expected {
+            eprintln!(
+                "{} decodes differently:\n--- expected\n{expected}--- actual\n{actual}",
+                buffer.display()
+            );
+            mismatched.push(buffer.file_name().unwrap().to_string_lossy().into_owned());
+        }
+    }
+    assert!(mismatched.is_empty(), "golden mismatch: {mismatched:?}");
+}
+
+#[test]
+fn test_fixtures_are_contiguous() {
+    // Between two SET_HVA commands the decoder must advance the address by
+    // exactly the size of each run, including 2MB entries
+    let data = fs::read(fixtures_dir().join("x86_64_thp.bin")).unwrap();
+    let pages = decode(&data);
+    let runs: Vec<_> = pages
+        .iter()
+        .take_while(|page| page.address < 0x7f3a_4640_0000)
+        .collect();
+    for pair in runs.windows(2) {
+        assert_eq!(
+            pair[0].end_address(),
+            pair[1].address,
+            "gap after {}",
+            pair[0]
+        );
+    }
+    let huge: u64 = runs
+        .iter()
+        .filter(|page| page.page_type.is_huge())
+        .map(|page| u64::from(page.count))
+        .sum();
+    assert_eq!(huge, 26);
+}
@@ -0,0 +1,35 @@
+//! Core ETMEM data types
+//!
+//! The page types, idle page entries, address ranges and PIP (Proc Idle
+//! Page) encoding shared by `etmem-rs` and by tooling that only needs to
+//! decode recorded scan data. The crate is `no_std` (it needs `alloc`)
+//! and has no dependency on libc, so it also builds for WASM targets.
+//!
+//! # Features
+//!
+//! - **`serde`**: `Serialize`/`Deserialize` for the data types
+//!
+//! # Example
+//!
+//! ```
+//! use etmem_types::{PipEncoding, ProcIdlePageType, decode_pip};
+//!
+//! // A recorded stream: two idle 4KB pages starting at 0x1000
+//! let data = [PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 1)];
+//! let pages = decode_pip(&data, 0x1000).unwrap();
+//! assert_eq!(pages[0].total_size(), 8192);
+//! ```
+
+#![no_std]
+#![warn(missing_docs)]
+
+extern crate alloc;
+
+pub mod page;
+pub mod pip;
+
+pub use page::{AddressRange, IdlePageInfo, ProcIdlePageType};
+pub use pip::{
+    DecodeWarning, PipDecode, PipEncoding, PipError, USER_ADDR_LIMIT, decode_pip,
+    decode_pip_checked,
+};
@@ -0,0 +1,285 @@
+//! Page types, idle page entries and address ranges
+
+use core::fmt;
+
+/// Page type enumeration for idle page detection
+///
+/// These types correspond to the hardware page table entry states
+/// and indicate the size and access status of memory pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum ProcIdlePageType {
+    /// 4KB page was accessed (A bit set in PTE)
+    PteAccessed = 0,
+    /// 2MB page was accessed (A bit set in PMD)
+    PmdAccessed = 1,
+    /// 1GB page is present (PUD present bit)
+    PudPresent = 2,
+    /// 4KB page is dirty (D bit set in PTE)
+    PteDirty = 3,
+    /// 2MB page is dirty (D bit set in PMD)
+    PmdDirty = 4,
+    /// 4KB page is idle (A bit not set in PTE)
+    PteIdle = 5,
+    /// 2MB page is idle (A bit not set in PMD)
+    PmdIdle = 6,
+    /// All PTEs within a PMD are idle
+    PmdIdlePtes = 7,
+    /// 4KB page table entry is a hole (not present)
+    PteHole = 8,
+    /// 2MB PMD entry is a hole (not present)
+    PmdHole = 9,
+    /// Command marker for PIP protocol
+    PipCmd = 10,
+    /// Maximum valid type value
+    Max = 11,
+}
+
+impl ProcIdlePageType {
+    /// Convert from raw u8 value
+    pub const fn from_raw(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::PteAccessed),
+            1 => Some(Self::PmdAccessed),
+            2 => Some(Self::PudPresent),
+            3 => Some(Self::PteDirty),
+            4 => Some(Self::PmdDirty),
+            5 => Some(Self::PteIdle),
+            6 => Some(Self::PmdIdle),
+            7 => Some(Self::PmdIdlePtes),
+            8 => Some(Self::PteHole),
+            9 => Some(Self::PmdHole),
+            10 => Some(Self::PipCmd),
+            _ => None,
+        }
+    }
+
+    /// Check if this page type represents a huge page (2MB or 1GB)
+    pub const fn is_huge(&self) -> bool {
+        matches!(
+            self,
+            Self::PmdAccessed
+                | Self::PmdDirty
+                | Self::PmdIdle
+                | Self::PmdIdlePtes
+                | Self::PmdHole
+                | Self::PudPresent
+        )
+    }
+
+    /// Check if this page type represents an idle (cold) page
+    pub const fn is_idle(&self) -> bool {
+        matches!(self, Self::PteIdle | Self::PmdIdle | Self::PmdIdlePtes)
+    }
+
+    /// Check if this page type represents an accessed (hot) page
+    pub const fn is_accessed(&self) -> bool {
+        matches!(self, Self::PteAccessed | Self::PmdAccessed)
+    }
+
+    /// Check if this page type represents a hole (not mapped)
+    pub const fn is_hole(&self) -> bool {
+        matches!(self, Self::PteHole | Self::PmdHole)
+    }
+
+    /// Get the page size in bytes for this type
+    pub const fn page_size(&self) -> u64 {
+        match self {
+            Self::PteAccessed | Self::PteDirty | Self::PteIdle | Self::PteHole => 4096, // 4KB
+            Self::PmdAccessed
+            | Self::PmdDirty
+            | Self::PmdIdle
+            | Self::PmdIdlePtes
+            | Self::PmdHole => 2 * 1024 * 1024, // 2MB
+            Self::PudPresent => 1024 * 1024 * 1024,                                     // 1GB
+            _ => 4096, // Default to 4KB for command types
+        }
+    }
+
+    /// Get the stable snake_case name of this page type
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::PteAccessed => "pte_accessed",
+            Self::PmdAccessed => "pmd_accessed",
+            Self::PudPresent => "pud_present",
+            Self::PteDirty => "pte_dirty",
+            Self::PmdDirty => "pmd_dirty",
+            Self::PteIdle => "pte_idle",
+            Self::PmdIdle => "pmd_idle",
+            Self::PmdIdlePtes => "pmd_idle_ptes",
+            Self::PteHole => "pte_hole",
+            Self::PmdHole => "pmd_hole",
+            Self::PipCmd => "pip_cmd",
+            Self::Max => "max",
+        }
+    }
+}
+
+impl fmt::Display for ProcIdlePageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Page idle information entry
+///
+/// Represents a single idle (or accessed) page detected during scanning.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IdlePageInfo {
+    /// Virtual address of the page
+    pub address: u64,
+    /// Page type (accessed, idle, dirty, etc.)
+    pub page_type: ProcIdlePageType,
+    /// Number of consecutive pages of this type (1-16)
+    pub count: u8,
+}
+
+impl IdlePageInfo {
+    /// Create a new IdlePageInfo
+    pub fn new(address: u64, page_type: ProcIdlePageType, count: u8) -> Self {
+        Self {
+            address,
+            page_type,
+            count: if count < 1 { 1 } else { count },
+        }
+    }
+
+    /// Get the total size covered by this entry in bytes
+    pub fn total_size(&self) -> u64 {
+        self.page_type.page_size() * self.count as u64
+    }
+
+    /// Get the end address (exclusive) of this entry
+    pub fn end_address(&self) -> u64 {
+        self.address + self.total_size()
+    }
+
+    /// Check if this entry represents an idle page
+    pub fn is_idle(&self) -> bool {
+        self.page_type.is_idle()
+    }
+
+    /// Check if this entry represents an accessed (hot) page
+    pub fn is_accessed(&self) -> bool {
+        self.page_type.is_accessed()
+    }
+}
+
+impl fmt::Display for IdlePageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x} {} x{}", self.address, self.page_type, self.count)
+    }
+}
+
+/// Virtual address range for scanning
+///
+/// Defines a range of virtual addresses to scan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AddressRange {
+    /// Start address (inclusive)
+    pub start: u64,
+    /// End address (exclusive)
+    pub end: u64,
+}
+
+impl AddressRange {
+    /// Create a new address range
+    pub const fn new(start: u64, end: u64) -> Self {
+        Self { start, end }
+    }
+
+    /// Create a range from start with given size
+    pub const fn with_size(start: u64, size: u64) -> Self {
+        Self {
+            start,
+            end: start + size,
+        }
+    }
+
+    /// Check if an address is within this range
+    pub const fn contains(&self, addr: u64) -> bool {
+        addr >= self.start && addr < self.end
+    }
+
+    /// Get the size of this range in bytes
+    pub const fn size(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// Check if the range is valid (start < end)
+    pub const fn is_valid(&self) -> bool {
+        self.start < self.end
+    }
+
+    /// Check if this range overlaps with another
+    pub const fn overlaps(&self, other: &Self) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// Get the overlapping part of two ranges, if any
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let range = Self::new(self.start.max(other.start), self.end.min(other.end));
+        range.is_valid().then_some(range)
+    }
+}
+
+impl fmt::Display for AddressRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}-{:#x}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proc_idle_page_type() {
+        assert!(ProcIdlePageType::PmdIdle.is_huge());
+        assert!(!ProcIdlePageType::PteIdle.is_huge());
+        assert!(ProcIdlePageType::PteIdle.is_idle());
+        assert!(ProcIdlePageType::PteAccessed.is_accessed());
+        assert!(ProcIdlePageType::PteHole.is_hole());
+        assert_eq!(ProcIdlePageType::PteAccessed.page_size(), 4096);
+        assert_eq!(ProcIdlePageType::PmdAccessed.page_size(), 2 * 1024 * 1024);
+        assert_eq!(ProcIdlePageType::PudPresent.page_size(), 1024 * 1024 * 1024);
+        assert_eq!(ProcIdlePageType::PmdIdlePtes.as_str(), "pmd_idle_ptes");
+    }
+
+    #[test]
+    fn test_display() {
+        use alloc::format;
+
+        let info = IdlePageInfo::new(0x7f0000000000, ProcIdlePageType::PteIdle, 2);
+        assert_eq!(format!("{info}"), "0x7f0000000000 pte_idle x2");
+        assert_eq!(format!("{:<10}|", ProcIdlePageType::PmdIdle), "pmd_idle  |");
+        assert_eq!(
+            format!("{}", AddressRange::new(0x1000, 0x5000)),
+            "0x1000-0x5000"
+        );
+    }
+
+    #[test]
+    fn test_address_range() {
+        let range = AddressRange::new(0x1000, 0x5000);
+        assert!(range.contains(0x2000));
+        assert!(!range.contains(0x5000));
+        assert_eq!(range.size(), 0x4000);
+        assert!(range.is_valid());
+
+        let with_size = AddressRange::with_size(0x1000, 0x4000);
+        assert_eq!(with_size, range);
+    }
+
+    #[test]
+    fn test_idle_page_info() {
+        let info = IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 2);
+        assert_eq!(info.address, 0x1000);
+        assert!(info.is_idle());
+        assert_eq!(info.total_size(), 4096 * 2);
+        assert_eq!(info.end_address(), 0x1000 + 4096 * 2);
+    }
+}
@@ -0,0 +1,384 @@
+//! PIP (Proc Idle Page) stream encoding and decoding
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::page::{IdlePageInfo, ProcIdlePageType};
+
+/// PIP (Proc Idle Page) encoding helpers
+///
+/// The kernel encodes idle page information in a compact byte format:
+/// - Upper 4 bits: page type
+/// - Lower 4 bits: count of consecutive pages (0 means 1 page)
+pub struct PipEncoding;
+
+impl PipEncoding {
+    /// Extract type from encoded byte
+    #[inline]
+    pub const fn extract_type(encoded: u8) -> u8 {
+        (encoded >> 4) & 0xf
+    }
+
+    /// Extract size/count from encoded byte
+    /// Returns count of consecutive pages minus 1 (so 0 means 1 page)
+    #[inline]
+    pub const fn extract_size(encoded: u8) -> u8 {
+        encoded & 0xf
+    }
+
+    /// Compose type and size into encoded byte
+    /// count is the number of consecutive pages minus 1 (0-15, representing 1-16 pages)
+    #[inline]
+    pub const fn compose(page_type: u8, count: u8) -> u8 {
+        ((page_type & 0xf) << 4) | (count & 0xf)
+    }
+
+    /// PIP command to set HVA (Host Virtual Address)
+    pub const SET_HVA: u8 = Self::compose(ProcIdlePageType::PipCmd as u8, 0);
+
+    /// Decode an encoded byte into (type, count)
+    pub const fn decode(encoded: u8) -> (u8, u8) {
+        (Self::extract_type(encoded), Self::extract_size(encoded))
+    }
+}
+
+/// Errors from decoding a PIP stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipError {
+    /// Entry with a page type the decoder does not know
+    InvalidPageType(u8),
+}
+
+impl fmt::Display for PipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPageType(t) => write!(f, "Invalid page type: {t}"),
+        }
+    }
+}
+
+impl core::error::Error for PipError {}
+
+/// Exclusive upper bound of user-space addresses on the build target
+///
+/// The widest user address space the architecture supports (52-bit VAs on
+/// aarch64, 5-level page tables on x86_64 and Sv57 on riscv64), so that
+/// streams from any paging mode decode.
+pub const USER_ADDR_LIMIT: u64 = if cfg!(target_arch = "aarch64") {
+    1 << 52
+} else {
+    1 << 56
+};
+
+/// Granularity addresses in a PIP stream are aligned to
+const BASE_PAGE_SIZE: u64 = 4096;
+
+/// Malformed address met while decoding a PIP stream
+///
+/// Offsets are byte offsets into the stream. The decoder repairs or drops
+/// the affected entries instead of returning addresses that would fail
+/// later, e.g. when the pages are submitted for swapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeWarning {
+    /// Address not aligned to 4KB, aligned down
+    Unaligned {
+        /// Offset of the `SET_HVA` command, or 0 for the base address
+        offset: usize,
+        /// Address as given
+        addr: u64,
+    },
+    /// Address outside the user address space; the entries that follow
+    /// are dropped up to the next `SET_HVA`
+    OutOfRange {
+        /// Offset of the first dropped entry or of the `SET_HVA` command
+        offset: usize,
+        /// First address outside the user address space
+        addr: u64,
+        /// Pages dropped
+        dropped_pages: u64,
+    },
+    /// `SET_HVA` command cut off before its 8 address bytes
+    TruncatedCommand {
+        /// Offset of the command
+        offset: usize,
+    },
+}
+
+impl fmt::Display for DecodeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unaligned { offset, addr } => {
+                write!(f, "Unaligned address {addr:#x} at offset {offset}")
+            }
+            Self::OutOfRange {
+                offset,
+                addr,
+                dropped_pages,
+            } => write!(
+                f,
+                "Address {addr:#x} at offset {offset} outside user space, {dropped_pages} pages dropped"
+            ),
+            Self::TruncatedCommand { offset } => {
+                write!(f, "Truncated SET_HVA command at offset {offset}")
+            }
+        }
+    }
+}
+
+/// Entries of a PIP stream and the problems repaired while decoding it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PipDecode {
+    /// Decoded entries, all within the user address space
+    pub pages: Vec<IdlePageInfo>,
+    /// Malformed addresses met, in stream order
+    pub warnings: Vec<DecodeWarning>,
+}
+
+/// Decode a PIP stream as read from `/proc/<pid>/idle_pages`
+///
+/// Each byte encodes a page type in the upper 4 bits and the count of
+/// consecutive pages minus 1 in the lower 4 bits. A `SET_HVA` command
+/// byte is followed by a 64-bit big-endian address and moves the cursor
+/// there (reference: etmemd_scan.c `get_address_from_buf()`). Other
+/// command bytes are skipped.
+///
+/// `base_addr` is the address of the first entry when the stream does not
+/// start with `SET_HVA`.
+///
+/// Malformed addresses are repaired as by [`decode_pip_checked`] with
+/// [`USER_ADDR_LIMIT`]; use that function to see the warnings.
+///
+/// # Errors
+/// Returns `PipError::InvalidPageType` on an entry with an unknown page type.
+pub fn decode_pip(data: &[u8], base_addr: u64) -> Result<Vec<IdlePageInfo>, PipError> {
+    decode_pip_checked(data, base_addr, USER_ADDR_LIMIT).map(|decoded| decoded.pages)
+}
+
+/// Decode a PIP stream, validating its addresses against `addr_limit`
+///
+/// Decodes like [`decode_pip`], but does not trust the addresses of the
+/// stream: unaligned addresses are aligned down to 4KB, entries reaching
+/// past `addr_limit` (or wrapping around) are cut at the limit, and the
+/// entries after them are dropped until a `SET_HVA` moves the cursor back
+/// into range. Each repair is reported as a [`DecodeWarning`].
+///
+/// Pass [`USER_ADDR_LIMIT`] for streams read on this machine, or the limit
+/// of the machine a recorded stream comes from.
+///
+/// # Errors
+/// Returns `PipError::InvalidPageType` on an entry with an unknown page type.
+pub fn decode_pip_checked(
+    data: &[u8],
+    base_addr: u64,
+    addr_limit: u64,
+) -> Result<PipDecode, PipError> {
+    let mut decoded = PipDecode::default();
+    let mut cursor = Cursor::new(base_addr, 0, addr_limit, &mut decoded.warnings);
+    let mut i = 0;
+
+    while i < data.len() {
+        let byte = data[i];
+        let page_type_raw = PipEncoding::extract_type(byte);
+        let count = PipEncoding::extract_size(byte) + 1;
+
+        // Check for command marker
+        if page_type_raw == ProcIdlePageType::PipCmd as u8 {
+            if byte == PipEncoding::SET_HVA {
+                if i + 8 < data.len() {
+                    let mut addr_bytes = [0u8; 8];
+                    addr_bytes.copy_from_slice(&data[i + 1..i + 9]);
+                    let addr = u64::from_be_bytes(addr_bytes);
+                    cursor = Cursor::new(addr, i, addr_limit, &mut decoded.warnings);
+                    i += 9; // 1 command byte + 8 address bytes
+                    continue;
+                }
+                decoded
+                    .warnings
+                    .push(DecodeWarning::TruncatedCommand { offset: i });
+            }
+            // Unknown command, skip
+            i += 1;
+            continue;
+        }
+
+        let page_type = ProcIdlePageType::from_raw(page_type_raw)
+            .ok_or(PipError::InvalidPageType(page_type_raw))?;
+
+        if let Some(addr) = cursor.addr {
+            let page_size = page_type.page_size();
+            let fitting = (addr_limit - addr) / page_size;
+            if fitting >= u64::from(count) {
+                decoded
+                    .pages
+                    .push(IdlePageInfo::new(addr, page_type, count));
+                cursor.addr = Some(addr + page_size * u64::from(count));
+            } else {
+                // Keep the pages below the limit, drop the rest
+                if fitting > 0 {
+                    decoded
+                        .pages
+                        .push(IdlePageInfo::new(addr, page_type, fitting as u8));
+                }
+                let end = addr + page_size * fitting;
+                decoded.warnings.push(DecodeWarning::OutOfRange {
+                    offset: i,
+                    addr: end,
+                    dropped_pages: u64::from(count) - fitting,
+                });
+                cursor.addr = None;
+            }
+        } else if let Some(DecodeWarning::OutOfRange { dropped_pages, .. }) =
+            decoded.warnings.last_mut()
+        {
+            *dropped_pages += u64::from(count);
+        }
+        i += 1;
+    }
+
+    Ok(decoded)
+}
+
+/// Address of the next entry of a stream being decoded
+struct Cursor {
+    /// Next address, `None` while it is outside the user address space
+    addr: Option<u64>,
+}
+
+impl Cursor {
+    /// Move the cursor to `addr`, given at `offset`, repairing it if needed
+    fn new(addr: u64, offset: usize, limit: u64, warnings: &mut Vec<DecodeWarning>) -> Self {
+        let aligned = addr & !(BASE_PAGE_SIZE - 1);
+        if aligned != addr {
+            warnings.push(DecodeWarning::Unaligned { offset, addr });
+        }
+        if aligned >= limit {
+            warnings.push(DecodeWarning::OutOfRange {
+                offset,
+                addr,
+                dropped_pages: 0,
+            });
+            return Self { addr: None };
+        }
+        Self {
+            addr: Some(aligned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_pip_encoding() {
+        let encoded = PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 5);
+        assert_eq!(
+            PipEncoding::extract_type(encoded),
+            ProcIdlePageType::PteIdle as u8
+        );
+        assert_eq!(PipEncoding::extract_size(encoded), 5);
+
+        let (t, s) = PipEncoding::decode(encoded);
+        assert_eq!(t, ProcIdlePageType::PteIdle as u8);
+        assert_eq!(s, 5);
+    }
+
+    #[test]
+    fn test_decode_pip() {
+        let mut data = vec![PipEncoding::compose(ProcIdlePageType::PteAccessed as u8, 1)];
+        data.push(PipEncoding::SET_HVA);
+        data.extend_from_slice(&0x20_0000u64.to_be_bytes());
+        data.push(PipEncoding::compose(ProcIdlePageType::PmdIdle as u8, 0));
+
+        let pages = decode_pip(&data, 0x1000).unwrap();
+        assert_eq!(
+            pages,
+            vec![
+                IdlePageInfo::new(0x1000, ProcIdlePageType::PteAccessed, 2),
+                IdlePageInfo::new(0x20_0000, ProcIdlePageType::PmdIdle, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_pip_checked() {
+        let idle = PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 0);
+        let set_hva = |addr: u64| {
+            let mut cmd = vec![PipEncoding::SET_HVA];
+            cmd.extend_from_slice(&addr.to_be_bytes());
+            cmd
+        };
+        let limit = 0x10_0000;
+
+        // Unaligned jump: aligned down
+        let mut data = set_hva(0x2345);
+        data.push(idle);
+        let decoded = decode_pip_checked(&data, 0, limit).unwrap();
+        assert_eq!(
+            decoded.pages,
+            [IdlePageInfo::new(0x2000, ProcIdlePageType::PteIdle, 1)]
+        );
+        assert_eq!(
+            decoded.warnings,
+            [DecodeWarning::Unaligned {
+                offset: 0,
+                addr: 0x2345
+            }]
+        );
+
+        // A run crossing the limit is cut, later entries are dropped until
+        // the cursor is moved back into range
+        let mut data = vec![PipEncoding::compose(ProcIdlePageType::PteIdle as u8, 3)];
+        data.extend([idle, idle]);
+        data.extend(set_hva(0x1000));
+        data.push(idle);
+        let decoded = decode_pip_checked(&data, limit - 0x2000, limit).unwrap();
+        assert_eq!(
+            decoded.pages,
+            [
+                IdlePageInfo::new(limit - 0x2000, ProcIdlePageType::PteIdle, 2),
+                IdlePageInfo::new(0x1000, ProcIdlePageType::PteIdle, 1),
+            ]
+        );
+        assert_eq!(
+            decoded.warnings,
+            [DecodeWarning::OutOfRange {
+                offset: 0,
+                addr: limit,
+                dropped_pages: 4
+            }]
+        );
+
+        // Garbage jumps and truncated commands never yield entries
+        let mut data = set_hva(u64::MAX);
+        data.push(idle);
+        data.push(PipEncoding::SET_HVA);
+        let decoded = decode_pip_checked(&data, 0, USER_ADDR_LIMIT).unwrap();
+        assert!(decoded.pages.is_empty());
+        assert_eq!(
+            decoded.warnings,
+            [
+                DecodeWarning::Unaligned {
+                    offset: 0,
+                    addr: u64::MAX
+                },
+                DecodeWarning::OutOfRange {
+                    offset: 0,
+                    addr: u64::MAX,
+                    dropped_pages: 1
+                },
+                DecodeWarning::TruncatedCommand { offset: 10 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_pip_invalid_type() {
+        let data = [PipEncoding::compose(ProcIdlePageType::Max as u8, 0)];
+        assert_eq!(
+            decode_pip(&data, 0),
+            Err(PipError::InvalidPageType(ProcIdlePageType::Max as u8))
+        );
+    }
+}
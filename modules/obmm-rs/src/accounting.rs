@@ -0,0 +1,454 @@
+//! Process-wide accounting of exported and imported memory
+//!
+//! Every successful export, import, unexport and unimport made through this
+//! crate is recorded in a process-wide ledger. [`usage`] returns a snapshot
+//! of the ledger as an [`ObmmUsage`]: live bytes and regions per direction,
+//! broken down by NUMA node and by flag set, together with the peak and the
+//! lifetime total.
+//!
+//! A [`Quota`] set with [`set_quota`] limits how many bytes may be exported
+//! at once, in total or from one NUMA node. Quotas are soft: they are
+//! checked before each export against the bytes already recorded, so
+//! exports made concurrently, or made outside this crate, can overshoot.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::accounting::{self, Quota};
+//! use obmm_rs::export::{ByteSize, ExportRequest};
+//! use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+//!
+//! accounting::set_quota(Quota::new().total(64.gib()).node(0, 32.gib()));
+//!
+//! let request = ExportRequest::new()
+//!     .numa(0, 1.gib())
+//!     .flags(ObmmExportFlags::ALLOWMMAP);
+//! let (_mem_id, _desc) = request.export::<UbPrivData>().expect("Export failed");
+//!
+//! let usage = accounting::usage();
+//! println!(
+//!     "exported {} bytes ({} on node 0, peak {})",
+//!     usage.exported.bytes, usage.exported.by_node[0], usage.exported.peak_bytes
+//! );
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{LazyLock, Mutex, MutexGuard, PoisonError};
+
+use crate::error::{ObmmError, Result};
+use crate::types::{MAX_NUMA_NODES, MemId};
+
+/// Usage of one direction (exports or imports)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UsageStats {
+    /// Bytes currently live
+    pub bytes: u64,
+    /// Regions currently live
+    pub regions: usize,
+    /// Highest value `bytes` has reached
+    pub peak_bytes: u64,
+    /// Bytes ever recorded over the process lifetime
+    pub total_bytes: u64,
+    /// Live bytes on each NUMA node (regions with no known node are only
+    /// counted in `bytes`)
+    pub by_node: [u64; MAX_NUMA_NODES],
+    /// Live bytes per flag set, keyed by the raw flag bits
+    /// (`ObmmExportFlags` for exports, `ObmmImportFlags` for imports)
+    pub by_flags: BTreeMap<u64, u64>,
+}
+
+impl UsageStats {
+    /// Add a region
+    fn add(&mut self, record: &Record) {
+        self.bytes = self.bytes.saturating_add(record.bytes);
+        self.regions = self.regions.saturating_add(1);
+        self.peak_bytes = self.peak_bytes.max(self.bytes);
+        self.total_bytes = self.total_bytes.saturating_add(record.bytes);
+        for (used, bytes) in self.by_node.iter_mut().zip(record.by_node) {
+            *used = used.saturating_add(bytes);
+        }
+        let flags = self.by_flags.entry(record.flags).or_default();
+        *flags = flags.saturating_add(record.bytes);
+    }
+
+    /// Remove a region added earlier
+    fn remove(&mut self, record: &Record) {
+        self.bytes = self.bytes.saturating_sub(record.bytes);
+        self.regions = self.regions.saturating_sub(1);
+        for (used, bytes) in self.by_node.iter_mut().zip(record.by_node) {
+            *used = used.saturating_sub(bytes);
+        }
+        if let Some(flags) = self.by_flags.get_mut(&record.flags) {
+            *flags = flags.saturating_sub(record.bytes);
+            if *flags == 0 {
+                self.by_flags.remove(&record.flags);
+            }
+        }
+    }
+}
+
+/// Snapshot of the memory exported and imported by this process
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ObmmUsage {
+    /// Exported memory
+    pub exported: UsageStats,
+    /// Imported memory
+    pub imported: UsageStats,
+}
+
+/// Soft limits on exported memory
+///
+/// # Example
+/// ```
+/// use obmm_rs::accounting::Quota;
+///
+/// let quota = Quota::new().total(1 << 30).node(1, 1 << 28);
+/// assert_eq!(quota.total_limit(), Some(1 << 30));
+/// assert_eq!(quota.node_limit(1), Some(1 << 28));
+/// assert_eq!(quota.node_limit(0), None);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Limit on live exported bytes
+    total: Option<u64>,
+    /// Limit on live exported bytes from each NUMA node
+    by_node: [Option<u64>; MAX_NUMA_NODES],
+}
+
+impl Quota {
+    /// Create a quota with no limits
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            total: None,
+            by_node: [None; MAX_NUMA_NODES],
+        }
+    }
+
+    /// Limit the live exported bytes across all NUMA nodes
+    #[inline]
+    #[must_use]
+    pub fn total(mut self, limit: usize) -> Self {
+        self.total = Some(bytes_of(limit));
+        self
+    }
+
+    /// Limit the live exported bytes from one NUMA node
+    ///
+    /// Nodes at or above `MAX_NUMA_NODES` are ignored.
+    #[inline]
+    #[must_use]
+    pub fn node(mut self, node: usize, limit: usize) -> Self {
+        if let Some(slot) = self.by_node.get_mut(node) {
+            *slot = Some(bytes_of(limit));
+        }
+        self
+    }
+
+    /// Get the limit across all NUMA nodes
+    #[inline]
+    #[must_use]
+    pub const fn total_limit(&self) -> Option<u64> {
+        self.total
+    }
+
+    /// Get the limit of one NUMA node
+    #[inline]
+    #[must_use]
+    pub fn node_limit(&self, node: usize) -> Option<u64> {
+        self.by_node.get(node).copied().flatten()
+    }
+}
+
+/// Convert a length to bytes, saturating on (theoretical) overflow
+fn bytes_of(len: usize) -> u64 {
+    u64::try_from(len).unwrap_or(u64::MAX)
+}
+
+/// Bytes of one recorded region
+#[derive(Debug, Clone, Copy)]
+struct Record {
+    /// Length of the region
+    bytes: u64,
+    /// Share of the length on each NUMA node
+    by_node: [u64; MAX_NUMA_NODES],
+    /// Raw flag bits
+    flags: u64,
+}
+
+impl Record {
+    /// Region spread over NUMA nodes by a lengths array
+    fn from_lengths(lengths: &[usize], flags: u64) -> Self {
+        let mut by_node = [0; MAX_NUMA_NODES];
+        for (bytes, &len) in by_node.iter_mut().zip(lengths) {
+            *bytes = bytes_of(len);
+        }
+        let bytes = lengths
+            .iter()
+            .fold(0_u64, |sum, &len| sum.saturating_add(bytes_of(len)));
+        Self {
+            bytes,
+            by_node,
+            flags,
+        }
+    }
+
+    /// Region of `bytes` on `node`, or on no known node
+    fn on_node(bytes: u64, node: Option<usize>, flags: u64) -> Self {
+        let mut by_node = [0; MAX_NUMA_NODES];
+        if let Some(slot) = node.and_then(|node| by_node.get_mut(node)) {
+            *slot = bytes;
+        }
+        Self {
+            bytes,
+            by_node,
+            flags,
+        }
+    }
+}
+
+/// Ledger state behind the lock
+#[derive(Debug, Default)]
+struct LedgerState {
+    /// Usage built from the live records
+    usage: ObmmUsage,
+    /// Live exports
+    exports: HashMap<MemId, Record>,
+    /// Live imports
+    imports: HashMap<MemId, Record>,
+    /// Export limits
+    quota: Quota,
+}
+
+/// Record of the regions exported and imported through this crate
+#[derive(Debug, Default)]
+struct Ledger {
+    /// Locked state
+    state: Mutex<LedgerState>,
+}
+
+impl Ledger {
+    /// Lock the state, ignoring poisoning (the counters stay consistent)
+    fn lock(&self) -> MutexGuard<'_, LedgerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Check an export against the quota
+    fn check_export(&self, record: &Record) -> Result<()> {
+        let state = self.lock();
+        let exported = &state.usage.exported;
+        if let Some(limit) = state.quota.total {
+            let requested = exported.bytes.saturating_add(record.bytes);
+            if requested > limit {
+                return Err(ObmmError::QuotaExceeded {
+                    node: None,
+                    requested,
+                    limit,
+                });
+            }
+        }
+        let nodes = exported.by_node.iter().zip(record.by_node);
+        for (node, ((&used, bytes), limit)) in nodes.zip(state.quota.by_node).enumerate() {
+            let requested = used.saturating_add(bytes);
+            match limit {
+                Some(limit) if bytes > 0 && requested > limit => {
+                    return Err(ObmmError::QuotaExceeded {
+                        node: u16::try_from(node).ok(),
+                        requested,
+                        limit,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a new export, replacing any stale record with the same ID
+    fn export(&self, mem_id: MemId, record: Record) {
+        let mut state = self.lock();
+        if let Some(old) = state.exports.insert(mem_id, record) {
+            state.usage.exported.remove(&old);
+        }
+        state.usage.exported.add(&record);
+    }
+
+    /// Forget an export
+    fn unexport(&self, mem_id: MemId) {
+        let mut state = self.lock();
+        if let Some(old) = state.exports.remove(&mem_id) {
+            state.usage.exported.remove(&old);
+        }
+    }
+
+    /// Record a new import, replacing any stale record with the same ID
+    fn import(&self, mem_id: MemId, record: Record) {
+        let mut state = self.lock();
+        if let Some(old) = state.imports.insert(mem_id, record) {
+            state.usage.imported.remove(&old);
+        }
+        state.usage.imported.add(&record);
+    }
+
+    /// Forget an import
+    fn unimport(&self, mem_id: MemId) {
+        let mut state = self.lock();
+        if let Some(old) = state.imports.remove(&mem_id) {
+            state.usage.imported.remove(&old);
+        }
+    }
+}
+
+/// Ledger of this process
+static LEDGER: LazyLock<Ledger> = LazyLock::new(Ledger::default);
+
+/// Get a snapshot of the memory exported and imported by this process
+#[inline]
+#[must_use]
+pub fn usage() -> ObmmUsage {
+    LEDGER.lock().usage.clone()
+}
+
+/// Get the current export quota
+#[inline]
+#[must_use]
+pub fn quota() -> Quota {
+    LEDGER.lock().quota
+}
+
+/// Replace the export quota
+///
+/// Regions already exported are kept even if they exceed the new quota;
+/// only later exports are refused.
+#[inline]
+pub fn set_quota(quota: Quota) {
+    LEDGER.lock().quota = quota;
+}
+
+/// Check that exporting `lengths` stays within the quota
+pub(crate) fn check_export(lengths: &[usize]) -> Result<()> {
+    LEDGER.check_export(&Record::from_lengths(lengths, 0))
+}
+
+/// Check that exporting `len` bytes from an unknown node stays within the
+/// quota
+pub(crate) fn check_export_len(len: usize) -> Result<()> {
+    LEDGER.check_export(&Record::on_node(bytes_of(len), None, 0))
+}
+
+/// Record an export spread over NUMA nodes by `lengths`
+pub(crate) fn record_export(mem_id: MemId, lengths: &[usize], flags: u64) {
+    LEDGER.export(mem_id, Record::from_lengths(lengths, flags));
+}
+
+/// Record an export of `len` bytes from an unknown node
+pub(crate) fn record_export_len(mem_id: MemId, len: usize, flags: u64) {
+    LEDGER.export(mem_id, Record::on_node(bytes_of(len), None, flags));
+}
+
+/// Record an unexport
+pub(crate) fn record_unexport(mem_id: MemId) {
+    LEDGER.unexport(mem_id);
+}
+
+/// Record an import of `bytes` placed on `numa_node`
+pub(crate) fn record_import(mem_id: MemId, bytes: u64, numa_node: i32, flags: u64) {
+    let node = usize::try_from(numa_node).ok();
+    LEDGER.import(mem_id, Record::on_node(bytes, node, flags));
+}
+
+/// Record an unimport
+pub(crate) fn record_unimport(mem_id: MemId) {
+    LEDGER.unimport(mem_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lengths(node0: usize, node1: usize) -> Vec<usize> {
+        let mut lengths = vec![0; MAX_NUMA_NODES];
+        lengths[0] = node0;
+        lengths[1] = node1;
+        lengths
+    }
+
+    #[test]
+    fn test_ledger_tracks_usage() {
+        let ledger = Ledger::default();
+        ledger.export(1, Record::from_lengths(&lengths(4096, 8192), 0x1));
+        ledger.export(2, Record::from_lengths(&lengths(4096, 0), 0x3));
+        ledger.import(7, Record::on_node(2048, Some(3), 0x1));
+
+        let usage = ledger.lock().usage.clone();
+        assert_eq!(usage.exported.bytes, 16384);
+        assert_eq!(usage.exported.regions, 2);
+        assert_eq!(usage.exported.by_node[0], 8192);
+        assert_eq!(usage.exported.by_node[1], 8192);
+        assert_eq!(usage.exported.by_flags.get(&0x1), Some(&12288));
+        assert_eq!(usage.exported.by_flags.get(&0x3), Some(&4096));
+        assert_eq!(usage.imported.by_node[3], 2048);
+
+        ledger.unexport(1);
+        ledger.unimport(7);
+        ledger.unimport(7);
+        let usage = ledger.lock().usage.clone();
+        assert_eq!(usage.exported.bytes, 4096);
+        assert_eq!(usage.exported.regions, 1);
+        assert_eq!(usage.exported.peak_bytes, 16384);
+        assert_eq!(usage.exported.total_bytes, 16384);
+        assert_eq!(usage.exported.by_node[1], 0);
+        assert_eq!(usage.exported.by_flags.get(&0x1), None);
+        assert_eq!(
+            usage.imported,
+            UsageStats {
+                peak_bytes: 2048,
+                total_bytes: 2048,
+                ..UsageStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_ledger_replaces_stale_record() {
+        let ledger = Ledger::default();
+        ledger.export(1, Record::from_lengths(&lengths(4096, 0), 0));
+        ledger.export(1, Record::from_lengths(&lengths(8192, 0), 0));
+        let usage = ledger.lock().usage.clone();
+        assert_eq!(usage.exported.bytes, 8192);
+        assert_eq!(usage.exported.regions, 1);
+    }
+
+    #[test]
+    fn test_quota() {
+        let ledger = Ledger::default();
+        ledger.lock().quota = Quota::new().total(16384).node(1, 4096);
+        ledger.export(1, Record::from_lengths(&lengths(8192, 4096), 0));
+
+        let ok = Record::from_lengths(&lengths(4096, 0), 0);
+        assert_eq!(ledger.check_export(&ok), Ok(()));
+        let total = Record::from_lengths(&lengths(8192, 0), 0);
+        assert_eq!(
+            ledger.check_export(&total),
+            Err(ObmmError::QuotaExceeded {
+                node: None,
+                requested: 20480,
+                limit: 16384,
+            })
+        );
+        let node = Record::from_lengths(&lengths(0, 1), 0);
+        assert_eq!(
+            ledger.check_export(&node),
+            Err(ObmmError::QuotaExceeded {
+                node: Some(1),
+                requested: 4097,
+                limit: 4096,
+            })
+        );
+        let unknown = Record::on_node(8192, None, 0);
+        assert!(ledger.check_export(&unknown).is_err());
+    }
+}
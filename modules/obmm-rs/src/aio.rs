@@ -0,0 +1,370 @@
+//! Async wrappers for slow OBMM operations
+//!
+//! Exporting or importing tens of gigabytes pins memory in the kernel and
+//! can block for seconds. The functions in this module run the blocking
+//! call on a dedicated thread and return an [`ObmmFuture`] that can be
+//! awaited from any executor.
+//!
+//! Dropping an `ObmmFuture` before it completes is safe: the operation
+//! still runs to completion on its thread, and a successful result that
+//! nobody is waiting for is cleaned up (unexported, unimported or dropped)
+//! instead of leaking.
+//!
+//! Only available with the `aio` feature. Each operation spawns one
+//! thread, so these wrappers suit large, infrequent calls rather than many
+//! small ones. If the thread cannot be spawned, the future resolves to an
+//! `ObmmError::IoError` right away.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::aio::mem_export_async;
+//! use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+//!
+//! async fn export() -> anyhow::Result<()> {
+//!     let lengths = vec![1024 * 1024 * 1024 * 32]; // 32GB on NUMA node 0
+//!     let (mem_id, _desc) =
+//!         mem_export_async::<UbPrivData>(lengths, ObmmExportFlags::ALLOWMMAP).await?;
+//!     println!("Exported memory ID: {mem_id}");
+//!     Ok(())
+//! }
+//! ```
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::{io, thread};
+
+use crate::error::{ObmmError, Result};
+use crate::export::{mem_export, mem_unexport};
+use crate::handle::{ExportedMemory, ImportedMemory};
+use crate::import::{ImportOptions, mem_import, mem_unimport};
+use crate::types::{
+    ImportResult, MemId, ObmmExportFlags, ObmmMemDesc, ObmmUnexportFlags, UbPrivData,
+};
+
+/// Progress of a blocking operation
+enum State<T> {
+    /// Still running; holds the waker of the last poll
+    Pending(Option<Waker>),
+    /// Finished; the result has not been taken yet
+    Ready(T),
+    /// The result was taken by the future
+    Taken,
+    /// The future was dropped before the operation finished
+    Cancelled,
+}
+
+/// Cleanup of a result nobody is waiting for
+type Cleanup<T> = Box<dyn FnOnce(T) + Send>;
+
+/// State shared between a future and its worker thread
+struct Shared<T> {
+    /// Progress of the operation
+    state: State<T>,
+    /// Cleanup of an unclaimed result, taken by whoever runs it
+    cleanup: Option<Cleanup<T>>,
+}
+
+/// Future resolving to the result of an OBMM operation run on its own thread
+///
+/// Created by the functions in this module. Polling never blocks.
+#[must_use = "futures do nothing unless awaited"]
+pub struct ObmmFuture<T> {
+    /// State shared with the worker thread
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> std::fmt::Debug for ObmmFuture<T> {
+    #[inline]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObmmFuture").finish_non_exhaustive()
+    }
+}
+
+impl<T: Send + 'static> ObmmFuture<T> {
+    /// Run `op` on a new thread
+    ///
+    /// If the future is dropped before its result is taken, `cleanup`
+    /// receives the result: on the worker thread if `op` is still running,
+    /// on the dropping thread otherwise.
+    ///
+    /// # Errors
+    /// Returns the error of [`thread::Builder::spawn`] if the thread cannot
+    /// be spawned; `op` is not run
+    fn spawn<F, C>(name: &str, op: F, cleanup: C) -> io::Result<Self>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        C: FnOnce(T) + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            state: State::Pending(None),
+            cleanup: Some(Box::new(cleanup) as Cleanup<T>),
+        }));
+        let worker = Arc::clone(&shared);
+
+        thread::Builder::new()
+            .name(format!("obmm-{name}"))
+            .spawn(move || {
+                let value = op();
+                let mut shared = worker.lock().unwrap_or_else(PoisonError::into_inner);
+                match std::mem::replace(&mut shared.state, State::Taken) {
+                    State::Pending(waker) => {
+                        shared.state = State::Ready(value);
+                        drop(shared);
+                        if let Some(waker) = waker {
+                            waker.wake();
+                        }
+                    }
+                    _ => {
+                        let cleanup = shared.cleanup.take();
+                        drop(shared);
+                        if let Some(cleanup) = cleanup {
+                            cleanup(value);
+                        }
+                    }
+                }
+            })?;
+        Ok(Self { shared })
+    }
+
+    /// Create a future that is already resolved
+    fn ready(value: T) -> Self {
+        Self {
+            shared: Arc::new(Mutex::new(Shared {
+                state: State::Ready(value),
+                cleanup: None,
+            })),
+        }
+    }
+}
+
+impl<T> Future for ObmmFuture<T> {
+    type Output = T;
+
+    #[inline]
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        match std::mem::replace(&mut shared.state, State::Taken) {
+            State::Ready(value) => Poll::Ready(value),
+            State::Pending(_) => {
+                shared.state = State::Pending(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+            State::Taken | State::Cancelled => panic!("ObmmFuture polled after completion"),
+        }
+    }
+}
+
+impl<T> Drop for ObmmFuture<T> {
+    #[inline]
+    fn drop(&mut self) {
+        let mut shared = self.shared.lock().unwrap_or_else(PoisonError::into_inner);
+        match std::mem::replace(&mut shared.state, State::Cancelled) {
+            // The worker runs the cleanup when it finishes
+            State::Pending(_) => {}
+            State::Ready(value) => {
+                let cleanup = shared.cleanup.take();
+                drop(shared);
+                if let Some(cleanup) = cleanup {
+                    cleanup(value);
+                }
+            }
+            State::Taken | State::Cancelled => shared.state = State::Taken,
+        }
+    }
+}
+
+/// Error of a future whose worker thread could not be spawned
+fn spawn_error(e: &io::Error) -> ObmmError {
+    ObmmError::IoError(format!("failed to spawn OBMM worker thread: {e}"))
+}
+
+/// Export memory regions without blocking the caller
+///
+/// Async version of [`mem_export`]. If the future is dropped before the
+/// export finishes, the region is unexported once it does.
+///
+/// # Arguments
+/// * `lengths` - Length of the region on each NUMA node
+/// * `flags` - Export flags
+///
+/// # Errors
+/// Resolves to the error of [`mem_export`] if the export fails
+#[inline]
+pub fn mem_export_async<T>(
+    lengths: Vec<usize>,
+    flags: ObmmExportFlags,
+) -> ObmmFuture<anyhow::Result<(MemId, ObmmMemDesc<T>)>>
+where
+    T: Default + Send + 'static,
+{
+    ObmmFuture::spawn(
+        "export",
+        move || mem_export::<T>(&lengths, flags),
+        |result| {
+            if let Ok((mem_id, _)) = result {
+                // Best effort cleanup, nobody is left to report to
+                let _result = mem_unexport(mem_id, ObmmUnexportFlags::empty());
+            }
+        },
+    )
+    .unwrap_or_else(|e| ObmmFuture::ready(Err(spawn_error(&e).into())))
+}
+
+/// Import a memory region without blocking the caller
+///
+/// Async version of [`mem_import`]. If the future is dropped before the
+/// import finishes, the region is unimported once it does.
+///
+/// # Arguments
+/// * `desc` - Memory descriptor from the remote export
+/// * `flags` - Import flags
+/// * `base_dist` - Base distribution hint for NUMA placement
+///
+/// # Errors
+/// Resolves to the error of [`mem_import`] if the import fails
+#[inline]
+pub fn mem_import_async(
+    desc: ObmmMemDesc<UbPrivData>,
+    flags: ObmmExportFlags,
+    base_dist: i32,
+) -> ObmmFuture<Result<ImportResult>> {
+    ObmmFuture::spawn(
+        "import",
+        move || mem_import(&desc, flags, base_dist),
+        |result| {
+            if let Ok(result) = result {
+                // Best effort cleanup, nobody is left to report to
+                let _result = mem_unimport(result.mem_id, ObmmExportFlags::empty());
+            }
+        },
+    )
+    .unwrap_or_else(|e| ObmmFuture::ready(Err(spawn_error(&e))))
+}
+
+impl<T: Default + Send + 'static> ExportedMemory<T> {
+    /// Export memory regions without blocking the caller
+    ///
+    /// Async version of [`ExportedMemory::export`]. If the future is dropped
+    /// before the export finishes, the handle is dropped (and the memory
+    /// unexported) once it does.
+    ///
+    /// # Errors
+    /// Resolves to the error of [`ExportedMemory::export`] if the export fails
+    #[inline]
+    pub fn export_async(lengths: Vec<usize>, flags: ObmmExportFlags) -> ObmmFuture<Result<Self>> {
+        ObmmFuture::spawn("export", move || Self::export(&lengths, flags), drop)
+            .unwrap_or_else(|e| ObmmFuture::ready(Err(spawn_error(&e))))
+    }
+}
+
+impl ImportedMemory {
+    /// Import a memory region without blocking the caller
+    ///
+    /// Async version of [`ImportedMemory::import_with`]. If the future is
+    /// dropped before the import finishes, the handle is dropped (and the
+    /// memory unimported) once it does.
+    ///
+    /// # Errors
+    /// Resolves to the error of [`ImportedMemory::import_with`] if the
+    /// import fails
+    #[inline]
+    pub fn import_async(
+        desc: ObmmMemDesc<UbPrivData>,
+        options: ImportOptions,
+    ) -> ObmmFuture<Result<Self>> {
+        ObmmFuture::spawn("import", move || Self::import_with(&desc, &options), drop)
+            .unwrap_or_else(|e| ObmmFuture::ready(Err(spawn_error(&e))))
+    }
+}
+
+#[cfg(all(test, not(feature = "native")))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::task::Wake;
+    use std::time::Duration;
+
+    /// Waker that unparks the polling thread
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Minimal executor for tests
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(value) = future.as_mut().poll(&mut cx) {
+                return value;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn test_export_and_import_async() {
+        let (mem_id, desc) = block_on(mem_export_async::<UbPrivData>(
+            vec![4096],
+            ObmmExportFlags::ALLOWMMAP,
+        ))
+        .unwrap();
+        assert_ne!(mem_id, 0);
+        let result = block_on(mem_import_async(desc, ObmmExportFlags::ALLOWMMAP, 0)).unwrap();
+        assert_ne!(result.mem_id, 0);
+
+        let memory = block_on(ExportedMemory::<UbPrivData>::export_async(
+            vec![4096],
+            ObmmExportFlags::ALLOWMMAP,
+        ))
+        .unwrap();
+        assert_eq!(memory.mem_id(), mem_id);
+    }
+
+    #[test]
+    fn test_dropped_future_runs_cleanup() {
+        let (gate_tx, gate_rx) = mpsc::channel::<()>();
+        let (done_tx, done_rx) = mpsc::channel();
+        let future = ObmmFuture::spawn(
+            "test",
+            move || {
+                gate_rx.recv().unwrap();
+                7
+            },
+            move |value| done_tx.send(value).unwrap(),
+        )
+        .unwrap();
+        drop(future);
+        gate_tx.send(()).unwrap();
+        assert_eq!(done_rx.recv_timeout(Duration::from_secs(5)), Ok(7));
+    }
+
+    #[test]
+    fn test_dropped_ready_future_runs_cleanup() {
+        let (done_tx, done_rx) = mpsc::channel();
+        let future =
+            ObmmFuture::spawn("test", || 7, move |value| done_tx.send(value).unwrap()).unwrap();
+        // Wait for the result without taking it
+        while matches!(future.shared.lock().unwrap().state, State::Pending(_)) {
+            thread::yield_now();
+        }
+        assert!(done_rx.try_recv().is_err());
+        drop(future);
+        assert_eq!(done_rx.try_recv(), Ok(7));
+
+        // A taken result is not cleaned up
+        let (done_tx, done_rx) = mpsc::channel();
+        let mut future =
+            ObmmFuture::spawn("test", || 8, move |value| done_tx.send(value).unwrap()).unwrap();
+        assert_eq!(block_on(&mut future), 8);
+        drop(future);
+        assert!(done_rx.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+}
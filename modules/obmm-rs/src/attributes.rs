@@ -0,0 +1,273 @@
+//! Cacheability and coherence attribute negotiation
+//!
+//! The [`UbPrivData`] bits of a descriptor decide how the importer maps the
+//! region: [`UbPrivData::CACHEABLE`] lets its CPUs cache the memory and
+//! [`UbPrivData::OCHIP`] routes accesses through the owning chip. Whether a
+//! cached mapping is safe depends on how the exporter shares the region:
+//! if other nodes keep writing it, the importer's caches must be kept
+//! coherent, by the interconnect or by explicit cache maintenance.
+//!
+//! [`MemoryAttributes`] describes both sides of that contract. The exporter
+//! states the features it uses and the coherence its sharing pattern
+//! requires; the importer states the features it supports and the strongest
+//! coherence it provides. [`MemoryAttributes::negotiate`] refuses the import
+//! with an [`AttributeError`] when they do not fit, instead of letting the
+//! importer read stale data.
+//!
+//! # Example
+//!
+//! ```
+//! use obmm_rs::attributes::{Coherence, MemoryAttributes};
+//! use obmm_rs::types::UbPrivData;
+//!
+//! // The exporter keeps writing the region while it is imported
+//! let required = MemoryAttributes::new(UbPrivData::CACHEABLE, Coherence::Hardware);
+//!
+//! // An importer without a coherent interconnect is refused
+//! let importer = MemoryAttributes::new(UbPrivData::all(), Coherence::Software);
+//! assert!(required.negotiate(&importer).is_err());
+//!
+//! let coherent = MemoryAttributes::new(UbPrivData::all(), Coherence::Hardware);
+//! assert!(required.negotiate(&coherent).is_ok());
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AttributeError, ObmmError, Result};
+use crate::types::{ObmmMemDesc, UbPrivData};
+
+/// Cache coherence between the exporter and an importer, weakest first
+#[derive(
+    Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Coherence {
+    /// No coherence: only one node accesses the region at a time, as after
+    /// an ownership transfer
+    #[default]
+    None,
+    /// Software-managed: sharers flush and invalidate their caches around
+    /// shared accesses
+    Software,
+    /// Hardware: the interconnect keeps all caches coherent
+    Hardware,
+}
+
+impl Coherence {
+    /// Get the stable snake_case name
+    #[inline]
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Software => "software",
+            Self::Hardware => "hardware",
+        }
+    }
+}
+
+impl fmt::Display for Coherence {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Mapping attributes required by an exporter or supported by an importer
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MemoryAttributes {
+    /// Privilege data features: used by the exporter, or supported by the
+    /// importer
+    pub priv_data: UbPrivData,
+    /// Coherence: required by the exporter of cached importers, or the
+    /// strongest provided by the importer
+    pub coherence: Coherence,
+}
+
+impl MemoryAttributes {
+    /// Create attributes from privilege data features and a coherence level
+    #[inline]
+    #[must_use]
+    pub const fn new(priv_data: UbPrivData, coherence: Coherence) -> Self {
+        Self {
+            priv_data,
+            coherence,
+        }
+    }
+
+    /// Requirements of a descriptor that is not shared while imported
+    ///
+    /// Uses the descriptor's privilege data with [`Coherence::None`], which
+    /// is what offers made before attribute negotiation implied.
+    #[inline]
+    #[must_use]
+    pub const fn of_desc(desc: &ObmmMemDesc<UbPrivData>) -> Self {
+        Self::new(desc.priv_data, Coherence::None)
+    }
+
+    /// Set the privilege data features
+    #[inline]
+    #[must_use]
+    pub const fn priv_data(mut self, priv_data: UbPrivData) -> Self {
+        self.priv_data = priv_data;
+        self
+    }
+
+    /// Set the coherence level
+    #[inline]
+    #[must_use]
+    pub const fn coherence(mut self, coherence: Coherence) -> Self {
+        self.coherence = coherence;
+        self
+    }
+
+    /// Check whether the importer may cache the region
+    #[inline]
+    #[must_use]
+    pub const fn is_cacheable(&self) -> bool {
+        self.priv_data.contains(UbPrivData::CACHEABLE)
+    }
+
+    /// Negotiate these exporter requirements with importer capabilities
+    ///
+    /// Every privilege data feature the exporter uses must be supported.
+    /// A cacheable mapping additionally needs the importer to provide at
+    /// least the required coherence; an uncached mapping bypasses the
+    /// importer's caches and is coherent whatever the requirement.
+    ///
+    /// # Arguments
+    /// * `capabilities` - Features and coherence of the importer
+    ///
+    /// # Returns
+    /// The agreed attributes: the exporter's features with the coherence
+    /// the mapping actually gets
+    ///
+    /// # Errors
+    /// Returns `ObmmError::AttributeMismatch` with the first incompatibility
+    #[inline]
+    pub fn negotiate(&self, capabilities: &Self) -> Result<Self> {
+        let missing = self.priv_data.difference(capabilities.priv_data);
+        if !missing.is_empty() {
+            return Err(ObmmError::AttributeMismatch(AttributeError::Unsupported(
+                missing.bits(),
+            )));
+        }
+
+        if !self.is_cacheable() {
+            return Ok(Self::new(self.priv_data, Coherence::Hardware));
+        }
+        if capabilities.coherence < self.coherence {
+            return Err(ObmmError::AttributeMismatch(AttributeError::Coherence {
+                required: self.coherence,
+                provided: capabilities.coherence,
+            }));
+        }
+        Ok(Self::new(self.priv_data, capabilities.coherence))
+    }
+
+    /// Check that agreed attributes satisfy these requirements
+    ///
+    /// Used by the exporter to double-check what an importer reports
+    /// having agreed to.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::AttributeMismatch` if `agreed` uses other
+    /// features or weaker coherence than required
+    #[inline]
+    pub fn check_agreed(&self, agreed: &Self) -> Result<()> {
+        if agreed.priv_data != self.priv_data {
+            let changed = agreed.priv_data.symmetric_difference(self.priv_data);
+            return Err(ObmmError::AttributeMismatch(AttributeError::Unsupported(
+                changed.bits(),
+            )));
+        }
+        if agreed.coherence < self.coherence {
+            return Err(ObmmError::AttributeMismatch(AttributeError::Coherence {
+                required: self.coherence,
+                provided: agreed.coherence,
+            }));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MemoryAttributes {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cache = if self.is_cacheable() {
+            "cacheable"
+        } else {
+            "uncached"
+        };
+        write!(f, "{cache}, {} coherence", self.coherence)?;
+        if self.priv_data.contains(UbPrivData::OCHIP) {
+            write!(f, ", owner chip")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate_features() {
+        let required = MemoryAttributes::new(UbPrivData::OCHIP, Coherence::None);
+        let importer = MemoryAttributes::new(UbPrivData::CACHEABLE, Coherence::Hardware);
+        assert_eq!(
+            required.negotiate(&importer),
+            Err(ObmmError::AttributeMismatch(AttributeError::Unsupported(
+                UbPrivData::OCHIP.bits()
+            )))
+        );
+
+        let importer = importer
+            .priv_data(UbPrivData::all())
+            .coherence(Coherence::None);
+        let agreed = required.negotiate(&importer).unwrap();
+        assert_eq!(agreed.priv_data, UbPrivData::OCHIP);
+        // Uncached mappings are coherent regardless of the importer
+        assert_eq!(agreed.coherence, Coherence::Hardware);
+    }
+
+    #[test]
+    fn test_negotiate_coherence() {
+        let required = MemoryAttributes::new(UbPrivData::CACHEABLE, Coherence::Software);
+        let none = MemoryAttributes::new(UbPrivData::all(), Coherence::None);
+        assert_eq!(
+            required.negotiate(&none),
+            Err(ObmmError::AttributeMismatch(AttributeError::Coherence {
+                required: Coherence::Software,
+                provided: Coherence::None,
+            }))
+        );
+
+        let hardware = none.coherence(Coherence::Hardware);
+        let agreed = required.negotiate(&hardware).unwrap();
+        assert_eq!(agreed.coherence, Coherence::Hardware);
+        assert!(required.check_agreed(&agreed).is_ok());
+
+        // An importer claiming weaker coherence than required is caught
+        let weaker = agreed.coherence(Coherence::None);
+        assert!(required.check_agreed(&weaker).is_err());
+        let uncached = MemoryAttributes::new(UbPrivData::empty(), Coherence::Hardware);
+        assert!(required.check_agreed(&uncached).is_err());
+    }
+
+    #[test]
+    fn test_attributes_serde() {
+        let attrs = MemoryAttributes::new(UbPrivData::CACHEABLE, Coherence::Software);
+        let json = serde_json::to_string(&attrs).unwrap();
+        assert_eq!(json, r#"{"priv_data":"CACHEABLE","coherence":"software"}"#);
+        assert_eq!(
+            serde_json::from_str::<MemoryAttributes>(&json).unwrap(),
+            attrs
+        );
+        assert_eq!(attrs.to_string(), "cacheable, software coherence");
+    }
+}
@@ -0,0 +1,247 @@
+//! Human-readable breakdown of memory descriptors
+//!
+//! A descriptor received from another node is a handful of raw numbers:
+//! little-endian EIDs, 24-bit CNAs and privilege data bits.
+//! [`ObmmMemDesc::describe`] turns them into a [`DescDescription`] that
+//! prints as an aligned listing for operators, and serializes to JSON for
+//! scripts, so a descriptor can be sanity-checked before it is imported.
+//!
+//! # Example
+//!
+//! ```
+//! use obmm_rs::types::{ObmmMemDesc, UbPrivData};
+//!
+//! let mut desc = ObmmMemDesc::<UbPrivData>::new();
+//! desc.length = 64 * 1024 * 1024;
+//! let description = desc.describe();
+//! assert_eq!(description.size, "64.00 MiB");
+//! println!("{description}");
+//! ```
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::attributes::MemoryAttributes;
+use crate::types::{ObmmMemDesc, UbPrivData};
+
+/// One side of a descriptor: the node exporting or importing the region
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DescEndpoint {
+    /// EID as a 128-bit hex number
+    pub eid: String,
+    /// CNA of the node
+    pub cna: u32,
+}
+
+impl DescEndpoint {
+    /// Describe an EID and CNA pair
+    fn new(eid: [u8; 16], cna: u32) -> Self {
+        Self {
+            eid: format_eid(eid),
+            cna,
+        }
+    }
+}
+
+impl fmt::Display for DescEndpoint {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "eid {}, cna {:#08x}", self.eid, self.cna)
+    }
+}
+
+/// Breakdown of a descriptor, returned by [`ObmmMemDesc::describe`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DescDescription {
+    /// Base address of the region
+    pub addr: u64,
+    /// Length of the region in bytes
+    pub length: u64,
+    /// Length of the region in binary units (e.g. "64.00 MiB")
+    pub size: String,
+    /// Exporting node
+    pub source: DescEndpoint,
+    /// Node allowed to import the region, `None` for any node
+    pub destination: Option<DescEndpoint>,
+    /// Token ID
+    pub tokenid: u32,
+    /// Names of the privilege data flags set
+    pub flags: Vec<String>,
+    /// Privilege data bits without a known name
+    pub unknown_flags: u16,
+    /// Whether importers may cache the region
+    pub cacheable: bool,
+    /// Whether accesses are routed through the owning chip
+    pub owner_chip: bool,
+    /// First problem [`ObmmMemDesc::validate`] found, `None` if valid
+    pub problem: Option<String>,
+}
+
+impl DescDescription {
+    /// Check whether the descriptor passed validation
+    #[inline]
+    #[must_use]
+    pub const fn is_valid(&self) -> bool {
+        self.problem.is_none()
+    }
+}
+
+impl fmt::Display for DescDescription {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Address:     {:#x}", self.addr)?;
+        writeln!(f, "Size:        {} ({} bytes)", self.size, self.length)?;
+        writeln!(f, "Source:      {}", self.source)?;
+        match &self.destination {
+            Some(destination) => writeln!(f, "Destination: {destination}")?,
+            None => writeln!(f, "Destination: any node")?,
+        }
+        writeln!(f, "Token ID:    {}", self.tokenid)?;
+        let mut flags = self.flags.clone();
+        if self.unknown_flags != 0 {
+            flags.push(format!("{:#06x}", self.unknown_flags));
+        }
+        if flags.is_empty() {
+            writeln!(f, "Flags:       none")?;
+        } else {
+            writeln!(f, "Flags:       {}", flags.join(" | "))?;
+        }
+        writeln!(
+            f,
+            "Attributes:  {}, {}",
+            if self.cacheable {
+                "cacheable"
+            } else {
+                "uncached"
+            },
+            if self.owner_chip {
+                "routed through the owning chip"
+            } else {
+                "direct access"
+            }
+        )?;
+        match &self.problem {
+            Some(problem) => write!(f, "Valid:       no, {problem}"),
+            None => write!(f, "Valid:       yes"),
+        }
+    }
+}
+
+impl ObmmMemDesc<UbPrivData> {
+    /// Break the descriptor down for display
+    ///
+    /// Never fails: a malformed descriptor is described along with the
+    /// first problem [`validate`](Self::validate) finds.
+    ///
+    /// # Returns
+    /// The breakdown, printable with `{}` or serializable to JSON
+    #[inline]
+    #[must_use]
+    pub fn describe(&self) -> DescDescription {
+        let attributes = MemoryAttributes::of_desc(self);
+        let known = self.priv_data & UbPrivData::all();
+        DescDescription {
+            addr: self.addr,
+            length: self.length,
+            size: format_size(self.length),
+            source: DescEndpoint::new(self.seid, self.scna),
+            destination: (self.deid != [0; 16] || self.dcna != 0)
+                .then(|| DescEndpoint::new(self.deid, self.dcna)),
+            tokenid: self.tokenid,
+            flags: known
+                .iter_names()
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            unknown_flags: self.priv_data.bits() & !UbPrivData::all().bits(),
+            cacheable: attributes.is_cacheable(),
+            owner_chip: known.contains(UbPrivData::OCHIP),
+            problem: self.validate().err().map(|e| e.to_string()),
+        }
+    }
+}
+
+/// Format a little-endian EID as a 128-bit hex number
+fn format_eid(eid: [u8; 16]) -> String {
+    format!("{:#034x}", u128::from_le_bytes(eid))
+}
+
+/// Format a byte count in binary units with two decimals
+fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.2} {}", UNITS[unit])
+    }
+}
+
+#[cfg(all(test, not(feature = "native")))]
+mod tests {
+    use super::*;
+
+    fn desc() -> ObmmMemDesc<UbPrivData> {
+        let mut desc = ObmmMemDesc::<UbPrivData>::new();
+        desc.addr = 0x2000_0000;
+        desc.length = 2 * 1024 * 1024;
+        desc.seid[0] = 0x12;
+        desc.scna = 0x42;
+        desc.tokenid = 7;
+        desc.priv_len = 2;
+        desc.priv_data = UbPrivData::CACHEABLE;
+        desc
+    }
+
+    #[test]
+    fn test_describe() {
+        let description = desc().describe();
+        assert_eq!(description.size, "2.00 MiB");
+        assert_eq!(description.source.eid, "0x00000000000000000000000000000012");
+        assert_eq!(description.destination, None);
+        assert_eq!(description.flags, ["CACHEABLE"]);
+        assert!(description.cacheable);
+        assert!(!description.owner_chip);
+        assert!(description.is_valid());
+
+        let text = description.to_string();
+        assert!(text.contains("Size:        2.00 MiB (2097152 bytes)"));
+        assert!(text.contains("Source:      eid 0x00000000000000000000000000000012, cna 0x000042"));
+        assert!(text.contains("Destination: any node"));
+        assert!(text.contains("Flags:       CACHEABLE"));
+        assert!(text.ends_with("Valid:       yes"));
+    }
+
+    #[test]
+    fn test_describe_invalid() {
+        let mut desc = desc();
+        desc.dcna = 0x43;
+        desc.priv_data = UbPrivData::from_bits_retain(0x8040);
+        let description = desc.describe();
+        assert_eq!(description.destination.as_ref().map(|d| d.cna), Some(0x43));
+        assert_eq!(description.unknown_flags, 0x8000);
+        assert!(!description.is_valid());
+
+        let text = description.to_string();
+        assert!(text.contains("Flags:       CACHEABLE | 0x8000"));
+        assert!(text.contains("Valid:       no, "));
+
+        let json = serde_json::to_value(&description).unwrap();
+        assert_eq!(json["destination"]["cna"], 0x43);
+        assert_eq!(json["flags"][0], "CACHEABLE");
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size(3 << 30), "3.00 GiB");
+    }
+}
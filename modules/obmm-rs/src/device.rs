@@ -3,14 +3,12 @@
 //! This module provides thread-safe access to the /dev/obmm device file
 //! using ioctl system calls.
 
-use crate::error::ObmmError;
+use crate::error::{ObmmError, OpContext};
+use crate::probe::OBMM_DEV_PATH;
 use libc::{O_CLOEXEC, O_RDWR, c_char, c_int, c_ulong, c_void, close, ioctl, open};
 use std::os::fd::RawFd;
 use std::sync::{Mutex, MutexGuard};
 
-/// OBMM device path
-const OBMM_DEV_PATH: &str = "/dev/obmm";
-
 /// Thread-safe singleton device handle
 static DEVICE: Mutex<Option<Device>> = Mutex::new(None);
 
@@ -25,13 +23,27 @@ impl Device {
     ///
     /// # Errors
     ///
-    /// Returns an error if the device file cannot be opened.
+    /// Returns `ObmmError::NotAvailable` if the device does not exist (the
+    /// OBMM kernel module is not loaded) and `ObmmError::DeviceError` if it
+    /// exists but cannot be opened.
     pub fn open() -> Result<Self, ObmmError> {
         let path = b"/dev/obmm\0";
         let fd = unsafe { open(path.as_ptr().cast::<c_char>(), O_RDWR | O_CLOEXEC) };
 
         if fd < 0 {
             let err = std::io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::ENOENT | libc::ENODEV | libc::ENXIO) => {
+                    return Err(ObmmError::NotAvailable);
+                }
+                Some(errno @ (libc::EACCES | libc::EPERM)) => {
+                    return Err(ObmmError::from_errno(
+                        errno,
+                        OpContext::new("open /dev/obmm", None),
+                    ));
+                }
+                _ => {}
+            }
             return Err(ObmmError::DeviceError(format!(
                 "Failed to open /dev/obmm: {} (is the OBMM kernel module loaded?)",
                 err
@@ -55,14 +67,16 @@ impl Device {
     ///
     /// # Errors
     ///
-    /// Returns an error if the ioctl call fails.
+    /// Returns a kernel error if the ioctl call fails.
     pub unsafe fn ioctl<T>(&self, request: c_ulong, arg: *mut T) -> Result<c_int, ObmmError> {
         unsafe {
             let ret = ioctl(self.fd, request, arg as *mut c_void);
 
             if ret < 0 {
-                let err = std::io::Error::last_os_error();
-                Err(ObmmError::IoError(format!("ioctl failed: {}", err)))
+                let errno = std::io::Error::last_os_error()
+                    .raw_os_error()
+                    .unwrap_or(libc::EIO);
+                Err(ObmmError::from_errno(errno, OpContext::new("ioctl", None)))
             } else {
                 Ok(ret)
             }
@@ -100,6 +114,14 @@ fn get_device() -> Result<MutexGuard<'static, Option<Device>>, ObmmError> {
     Ok(guard)
 }
 
+/// Check whether the OBMM device can be opened
+///
+/// Opens the shared device instance on first use, so a successful probe
+/// also prepares later calls.
+pub fn is_available() -> bool {
+    get_device().is_ok()
+}
+
 /// Execute a closure with access to the device
 ///
 /// This function handles device initialization and provides the closure
@@ -162,10 +184,15 @@ mod tests {
         // Skip if not available
         match Device::open() {
             Ok(_) => {}
-            Err(ObmmError::DeviceError(_)) => {
+            Err(
+                ObmmError::NotAvailable
+                | ObmmError::PermissionDenied(_)
+                | ObmmError::DeviceError(_),
+            ) => {
                 // Expected if kernel module is not loaded
             }
             Err(e) => panic!("Unexpected error: {:?}", e),
         }
+        assert_eq!(is_available(), Device::open().is_ok());
     }
 }
@@ -1,10 +1,19 @@
 //! Error handling for OBMM (Ownership-Based Memory Management)
 //!
 //! This module provides custom error types and result aliases for OBMM operations.
+//!
+//! Operations that reach the kernel turn a failure into the variant
+//! [`ObmmError::from_errno`] maps its `errno` to: a dedicated variant such
+//! as [`ObmmError::Busy`] or [`ObmmError::OutOfMemory`] where one exists,
+//! [`ObmmError::Os`] otherwise. The `# Errors` sections of those operations
+//! call this a kernel error.
 
 use std::fmt;
 use std::result;
 
+use crate::attributes::Coherence;
+use crate::types::{MemId, OBMM_INVALID_MEMID};
+
 /// Result type alias for OBMM operations
 pub type Result<T> = result::Result<T, ObmmError>;
 
@@ -42,6 +51,308 @@ pub enum ObmmError {
     OwnershipFailed(String),
     /// Serialization/deserialization error
     SerializationError(String),
+    /// Memory mapping failed
+    MapFailed(String),
+    /// Memory descriptor is malformed or incompatible with this host
+    InvalidDescriptor(DescError),
+    /// Export lengths array would be rejected by the kernel
+    InvalidLayout(LayoutError),
+    /// Descriptor signature does not match its contents
+    SignatureMismatch,
+    /// Sealed descriptor was issued too long ago, or in the future
+    SealExpired {
+        /// Seconds between issue and verification, negative if issued in
+        /// the future
+        age_secs: i64,
+    },
+    /// OBMM is not available on this machine (no `/dev/obmm`)
+    NotAvailable,
+    /// The kernel ran out of memory (`ENOMEM`)
+    OutOfMemory(OpContext),
+    /// The caller lacks permission (`EPERM` or `EACCES`)
+    PermissionDenied(OpContext),
+    /// The region is in use (`EBUSY`)
+    Busy(OpContext),
+    /// The kernel rejected an argument (`EINVAL`)
+    InvalidArgument(OpContext),
+    /// Any other kernel error
+    Os {
+        /// Failed operation
+        context: OpContext,
+        /// Raw `errno` value
+        errno: i32,
+    },
+    /// An export would exceed the quota set with `accounting::set_quota`
+    QuotaExceeded {
+        /// NUMA node whose limit would be exceeded, `None` for the total
+        node: Option<u16>,
+        /// Bytes that would be exported after the export
+        requested: u64,
+        /// Configured limit in bytes
+        limit: u64,
+    },
+    /// A grant would exceed the quota of a remote peer set with
+    /// `Registry::set_peer_quota`
+    PeerQuotaExceeded {
+        /// Bytes granted to the peer after the grant
+        requested: u64,
+        /// Configured limit in bytes
+        limit: u64,
+    },
+    /// The export has no lease that can be renewed: it is not registered,
+    /// was made without a lease, or its lease ran out past the grace period
+    LeaseNotFound(MemId),
+    /// A serialized descriptor uses a layout version this build cannot read
+    VersionMismatch {
+        /// Version found in the descriptor
+        found: u16,
+        /// Newest version this build reads
+        supported: u16,
+    },
+    /// Exporter and importer disagree on cacheability or coherence
+    AttributeMismatch(AttributeError),
+}
+
+/// Operation and memory ID that a kernel error refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpContext {
+    /// Name of the operation, e.g. `"export"`
+    pub op: &'static str,
+    /// Memory ID the operation acted on (`OBMM_INVALID_MEMID` if none)
+    pub mem_id: MemId,
+}
+
+impl OpContext {
+    /// Create a context for an operation
+    #[inline]
+    #[must_use]
+    pub const fn new(op: &'static str, mem_id: Option<MemId>) -> Self {
+        let mem_id = match mem_id {
+            Some(mem_id) => mem_id,
+            None => OBMM_INVALID_MEMID,
+        };
+        Self { op, mem_id }
+    }
+}
+
+impl fmt::Display for OpContext {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.mem_id == OBMM_INVALID_MEMID {
+            write!(f, "{}", self.op)
+        } else {
+            write!(f, "{} of memid {}", self.op, self.mem_id)
+        }
+    }
+}
+
+impl ObmmError {
+    /// Map an `errno` value to an error
+    ///
+    /// `ENOMEM`, `EPERM`/`EACCES`, `EBUSY` and `EINVAL` get dedicated
+    /// variants; `ENODEV` means the device is gone and maps to
+    /// [`ObmmError::NotAvailable`]. Anything else becomes [`ObmmError::Os`].
+    #[inline]
+    #[must_use]
+    pub const fn from_errno(errno: i32, context: OpContext) -> Self {
+        match errno {
+            libc::ENOMEM => ObmmError::OutOfMemory(context),
+            libc::EPERM | libc::EACCES => ObmmError::PermissionDenied(context),
+            libc::EBUSY => ObmmError::Busy(context),
+            libc::EINVAL => ObmmError::InvalidArgument(context),
+            libc::ENODEV => ObmmError::NotAvailable,
+            _ => ObmmError::Os { context, errno },
+        }
+    }
+
+    /// Build an error from the current `errno` of the calling thread
+    ///
+    /// Call this right after a failed `sys` function, before anything
+    /// else can overwrite `errno`.
+    #[inline]
+    #[must_use]
+    pub fn last_os_error(op: &'static str, mem_id: Option<MemId>) -> Self {
+        let errno = std::io::Error::last_os_error()
+            .raw_os_error()
+            .filter(|e| *e != 0)
+            .unwrap_or(libc::EIO);
+        Self::from_errno(errno, OpContext::new(op, mem_id))
+    }
+
+    /// Get the `errno` value behind a kernel error
+    ///
+    /// # Returns
+    /// The `errno` for errno-based variants, `None` otherwise
+    #[inline]
+    #[must_use]
+    pub const fn raw_os_error(&self) -> Option<i32> {
+        match *self {
+            ObmmError::OutOfMemory(_) => Some(libc::ENOMEM),
+            ObmmError::PermissionDenied(_) => Some(libc::EPERM),
+            ObmmError::Busy(_) => Some(libc::EBUSY),
+            ObmmError::InvalidArgument(_) => Some(libc::EINVAL),
+            ObmmError::NotAvailable => Some(libc::ENODEV),
+            ObmmError::Os { errno, .. } => Some(errno),
+            _ => None,
+        }
+    }
+}
+
+/// Reason a memory descriptor was rejected
+///
+/// Returned inside [`ObmmError::InvalidDescriptor`] by
+/// [`ObmmMemDesc::validate`](crate::types::ObmmMemDesc::validate) and
+/// [`ObmmMemDesc::is_compatible_with`](crate::types::ObmmMemDesc::is_compatible_with).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DescError {
+    /// Region length is zero
+    ZeroLength,
+    /// Region address or length is not aligned to the required boundary
+    Unaligned {
+        /// Required alignment in bytes
+        align: u64,
+    },
+    /// `priv_len` does not match the size of the privilege data type
+    PrivLenMismatch {
+        /// Size of the privilege data type
+        expected: u16,
+        /// Value of `priv_len`
+        found: u16,
+    },
+    /// Privilege data is set but `priv_len` is zero, or contains unknown bits
+    InvalidPrivData(u16),
+    /// Source EID is all zeros or all ones
+    InvalidEid,
+    /// CNA does not fit in 24 bits
+    InvalidCna(u32),
+    /// Destination EID does not name this host
+    EidMismatch,
+    /// Destination CNA does not name this host
+    CnaMismatch(u32),
+    /// Region is larger than this host can import
+    TooLarge {
+        /// Region length in bytes
+        length: u64,
+        /// Largest importable length in bytes
+        max: u64,
+    },
+    /// Privilege data requests features this host does not support
+    UnsupportedPrivData(u16),
+}
+
+impl fmt::Display for DescError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DescError::ZeroLength => write!(f, "region length is zero"),
+            DescError::Unaligned { align } => {
+                write!(f, "address or length is not {align}-byte aligned")
+            }
+            DescError::PrivLenMismatch { expected, found } => {
+                write!(f, "priv_len is {found}, expected {expected}")
+            }
+            DescError::InvalidPrivData(bits) => write!(f, "invalid privilege data {bits:#x}"),
+            DescError::InvalidEid => write!(f, "source EID is not a valid endpoint"),
+            DescError::InvalidCna(cna) => write!(f, "CNA {cna:#x} is out of range"),
+            DescError::EidMismatch => write!(f, "destination EID does not match this host"),
+            DescError::CnaMismatch(cna) => {
+                write!(f, "destination CNA {cna:#x} does not match this host")
+            }
+            DescError::TooLarge { length, max } => {
+                write!(f, "region length {length} exceeds the maximum of {max}")
+            }
+            DescError::UnsupportedPrivData(bits) => {
+                write!(f, "unsupported privilege data {bits:#x}")
+            }
+        }
+    }
+}
+
+/// Reason an export lengths array was rejected
+///
+/// Returned inside [`ObmmError::InvalidLayout`] by
+/// [`validate_export_layout`](crate::layout::validate_export_layout).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LayoutError {
+    /// The array does not have one entry per NUMA node
+    WrongLength {
+        /// Number of entries given
+        len: usize,
+        /// Required number of entries (`MAX_NUMA_NODES`)
+        expected: usize,
+    },
+    /// All lengths are zero
+    Empty,
+    /// A length is not a multiple of the export alignment (`EXPORT_ALIGN`)
+    Unaligned {
+        /// NUMA node of the length
+        node: usize,
+        /// Length in bytes
+        len: usize,
+    },
+    /// A length is requested from a NUMA node this machine does not have
+    NodeNotPresent(usize),
+    /// The total length overflows `usize`
+    TooLarge,
+}
+
+impl fmt::Display for LayoutError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LayoutError::WrongLength { len, expected } => {
+                write!(f, "lengths array has {len} entries, expected {expected}")
+            }
+            LayoutError::Empty => write!(f, "no memory requested on any NUMA node"),
+            LayoutError::Unaligned { node, len } => {
+                write!(
+                    f,
+                    "length {len} on NUMA node {node} is not a multiple of {}",
+                    crate::export::EXPORT_ALIGN
+                )
+            }
+            LayoutError::NodeNotPresent(node) => write!(f, "NUMA node {node} is not present"),
+            LayoutError::TooLarge => write!(f, "total length overflows"),
+        }
+    }
+}
+
+/// Reason memory attribute negotiation failed
+///
+/// Returned inside [`ObmmError::AttributeMismatch`] by
+/// [`MemoryAttributes::negotiate`](crate::attributes::MemoryAttributes::negotiate).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttributeError {
+    /// The exporter uses privilege data features the importer lacks
+    Unsupported(u16),
+    /// The importer cannot keep a cached mapping coherent enough
+    Coherence {
+        /// Coherence the exporter requires
+        required: Coherence,
+        /// Coherence the importer provides
+        provided: Coherence,
+    },
+}
+
+impl fmt::Display for AttributeError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            AttributeError::Unsupported(bits) => {
+                write!(
+                    f,
+                    "privilege data {bits:#x} is not supported by the importer"
+                )
+            }
+            AttributeError::Coherence { required, provided } => write!(
+                f,
+                "cacheable mapping requires {required} coherence, importer provides {provided}"
+            ),
+        }
+    }
 }
 
 impl fmt::Display for ObmmError {
@@ -75,6 +386,54 @@ impl fmt::Display for ObmmError {
             ObmmError::DeviceError(ref msg) => write!(f, "Device error: {msg}"),
             ObmmError::OwnershipFailed(ref msg) => write!(f, "Ownership operation failed: {msg}"),
             ObmmError::SerializationError(ref msg) => write!(f, "Serialization error: {msg}"),
+            ObmmError::MapFailed(ref msg) => write!(f, "Memory mapping failed: {msg}"),
+            ObmmError::InvalidDescriptor(ref err) => write!(f, "Invalid memory descriptor: {err}"),
+            ObmmError::InvalidLayout(ref err) => write!(f, "Invalid export layout: {err}"),
+            ObmmError::SignatureMismatch => write!(f, "Descriptor signature mismatch"),
+            ObmmError::SealExpired { age_secs } => {
+                write!(f, "Sealed descriptor expired (issued {age_secs}s ago)")
+            }
+            ObmmError::NotAvailable => {
+                write!(
+                    f,
+                    "OBMM is not available (is the OBMM kernel module loaded?)"
+                )
+            }
+            ObmmError::OutOfMemory(ref ctx) => write!(f, "Out of memory during {ctx}"),
+            ObmmError::PermissionDenied(ref ctx) => write!(f, "Permission denied for {ctx}"),
+            ObmmError::Busy(ref ctx) => write!(f, "Memory busy during {ctx}"),
+            ObmmError::InvalidArgument(ref ctx) => write!(f, "Invalid argument to {ctx}"),
+            ObmmError::Os { ref context, errno } => write!(
+                f,
+                "{context} failed: {}",
+                std::io::Error::from_raw_os_error(errno)
+            ),
+            ObmmError::QuotaExceeded {
+                node,
+                requested,
+                limit,
+            } => {
+                write!(
+                    f,
+                    "Export quota exceeded: {requested} bytes exceed the limit of {limit}"
+                )?;
+                match node {
+                    Some(node) => write!(f, " on NUMA node {node}"),
+                    None => Ok(()),
+                }
+            }
+            ObmmError::PeerQuotaExceeded { requested, limit } => write!(
+                f,
+                "Peer quota exceeded: {requested} bytes granted to the peer exceed the limit of {limit}"
+            ),
+            ObmmError::LeaseNotFound(mem_id) => write!(f, "No active lease on memid {mem_id}"),
+            ObmmError::VersionMismatch { found, supported } => write!(
+                f,
+                "Descriptor version {found} is not supported (this build reads up to version {supported})"
+            ),
+            ObmmError::AttributeMismatch(ref err) => {
+                write!(f, "Memory attribute mismatch: {err}")
+            }
         }
     }
 }
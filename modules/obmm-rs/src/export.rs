@@ -5,12 +5,20 @@
 
 #[cfg(feature = "native")]
 use std::ffi::c_void;
+use std::thread;
+use std::time::{Duration, Instant};
 
+use crate::accounting;
 use crate::error::{ObmmError, Result};
+use crate::query::query_importers;
+use crate::registry::EntryKind;
+use crate::trace::OpSpan;
 
 #[cfg(feature = "native")]
 use crate::sys;
-use crate::types::{MemId, OBMM_INVALID_MEMID, ObmmExportFlags, ObmmMemDesc, ObmmUnexportFlags};
+use crate::types::{
+    MAX_NUMA_NODES, MemId, OBMM_INVALID_MEMID, ObmmExportFlags, ObmmMemDesc, ObmmUnexportFlags,
+};
 
 /// Export memory region
 ///
@@ -26,7 +34,9 @@ use crate::types::{MemId, OBMM_INVALID_MEMID, ObmmExportFlags, ObmmMemDesc, Obmm
 /// - The memory descriptor containing metadata about the export
 ///
 /// # Errors
-/// Returns `ObmmError::ExportFailed` if the export operation fails
+/// Returns `ObmmError::QuotaExceeded` if the export would exceed the quota
+/// set with [`accounting::set_quota`], and `ObmmError::ExportFailed` if the
+/// export operation fails
 ///
 /// # Example
 /// ```
@@ -45,16 +55,32 @@ use crate::types::{MemId, OBMM_INVALID_MEMID, ObmmExportFlags, ObmmMemDesc, Obmm
 #[inline]
 pub fn mem_export<T: Default>(
     length: &[usize],
-    _: ObmmExportFlags,
+    flags: ObmmExportFlags,
 ) -> anyhow::Result<(MemId, ObmmMemDesc<T>)> {
-    let mut desc = ObmmMemDesc::<T>::default();
+    let span = OpSpan::lifecycle("export");
+    accounting::check_export(length).map_err(|e| span.fail(e))?;
     // Hooked implementation for testing
-    let memid = 1;
-    desc.addr = 0xffff_fc00_0000;
-    desc.length = length.iter().sum::<usize>().try_into()?;
+    let mut desc = ObmmMemDesc::<T> {
+        length: length.iter().sum::<usize>().try_into()?,
+        ..Default::default()
+    };
+    let memid = match crate::mock::export(desc.length) {
+        Some(region) => {
+            desc.addr = region.addr;
+            desc.seid = region.seid;
+            desc.deid = region.deid;
+            region.mem_id
+        }
+        None => {
+            desc.addr = 0xffff_fc00_0000;
+            1
+        }
+    };
     if memid == OBMM_INVALID_MEMID {
-        Err(anyhow::anyhow!("Failed to export memory"))
+        Err(span.fail(anyhow::anyhow!("Failed to export memory")))
     } else {
+        accounting::record_export(memid, length, flags.bits());
+        span.exported(memid, &desc);
         Ok((memid, desc))
     }
 }
@@ -77,6 +103,7 @@ pub fn mem_export<T: Default>(
 /// Returns an error if:
 /// - The kernel OBMM subsystem is not available
 /// - The export operation fails (e.g., insufficient memory)
+/// - The export would exceed the quota set with [`accounting::set_quota`]
 /// - The flags are invalid
 #[cfg(feature = "native")]
 #[inline]
@@ -84,13 +111,17 @@ pub fn mem_export<T: Default>(
     length: &[usize],
     flags: ObmmExportFlags,
 ) -> anyhow::Result<(MemId, ObmmMemDesc<T>)> {
+    let span = OpSpan::lifecycle("export");
+    accounting::check_export(length).map_err(|e| span.fail(e))?;
     let mut desc = ObmmMemDesc::<T>::default();
     let desc_ptr = std::ptr::addr_of_mut!(desc);
     let memid =
         unsafe { sys::obmm_export(length.as_ptr(), flags.bits(), desc_ptr.cast::<c_void>()) };
     if memid == OBMM_INVALID_MEMID {
-        Err(anyhow::anyhow!("Failed to export memory"))
+        Err(span.fail(ObmmError::last_os_error("export", None)).into())
     } else {
+        accounting::record_export(memid, length, flags.bits());
+        span.exported(memid, &desc);
         Ok((memid, desc))
     }
 }
@@ -105,7 +136,7 @@ pub fn mem_export<T: Default>(
 /// * `flags` - Unexport flags (e.g., `ObmmUnexportFlags::FORCE`)
 ///
 /// # Errors
-/// Returns `ObmmError::UnexportFailed` if the unexport operation fails
+/// Returns a kernel error if the unexport operation fails
 ///
 /// # Example
 /// ```
@@ -119,8 +150,14 @@ pub fn mem_export<T: Default>(
 /// ```
 #[cfg(not(feature = "native"))]
 #[inline]
-pub fn mem_unexport(_: MemId, _: ObmmUnexportFlags) -> Result<()> {
+pub fn mem_unexport(mem_id: MemId, flags: ObmmUnexportFlags) -> Result<()> {
+    let span = OpSpan::lifecycle("unexport").region(EntryKind::Export, mem_id);
     // Hooked implementation for testing
+    crate::mock::unexport(mem_id, flags)
+        .transpose()
+        .map_err(|e| span.fail(e))?;
+    accounting::record_unexport(mem_id);
+    span.released(EntryKind::Export);
     Ok(())
 }
 
@@ -140,11 +177,91 @@ pub fn mem_unexport(_: MemId, _: ObmmUnexportFlags) -> Result<()> {
 #[cfg(feature = "native")]
 #[inline]
 pub fn mem_unexport(mem_id: MemId, flags: ObmmUnexportFlags) -> Result<()> {
+    let span = OpSpan::lifecycle("unexport").region(EntryKind::Export, mem_id);
     let ret = unsafe { sys::obmm_unexport(mem_id, flags.bits()) };
     if ret == 0 {
+        accounting::record_unexport(mem_id);
+        span.released(EntryKind::Export);
         Ok(())
     } else {
-        Err(ObmmError::UnexportFailed(format!("return code: {}", ret)))
+        Err(span.fail(ObmmError::last_os_error("unexport", Some(mem_id))))
+    }
+}
+
+/// Outcome of [`unexport_graceful`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnexportOutcome {
+    /// All importers detached and the region was unexported normally
+    Clean,
+    /// The timeout expired and the region was unexported with
+    /// `ObmmUnexportFlags::FORCE` while still imported
+    Forced {
+        /// Importers still attached when the region was forced out, `None`
+        /// if they could not be counted
+        importers: Option<u32>,
+    },
+}
+
+/// Interval between importer checks in [`unexport_graceful`]
+const GRACEFUL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Unexport a region once its importers have detached
+///
+/// Polls [`query_importers`] until no node imports the region, then
+/// unexports it normally. If importers remain when `timeout` expires, the
+/// region is unexported with `ObmmUnexportFlags::FORCE`, which pulls the
+/// memory from under the remaining importers. If the importers cannot be
+/// counted, a normal unexport is attempted on every poll instead, with the
+/// same forced fallback.
+///
+/// # Arguments
+/// * `mem_id` - Memory ID to unexport
+/// * `timeout` - How long to wait for importers to detach
+///
+/// # Returns
+/// Whether the unexport was clean or forced
+///
+/// # Errors
+/// Returns the error of [`mem_unexport`] if the unexport fails
+///
+/// # Example
+/// ```
+/// use std::time::Duration;
+/// use obmm_rs::export::{UnexportOutcome, unexport_graceful};
+///
+/// match unexport_graceful(12345, Duration::from_secs(10)) {
+///     Ok(UnexportOutcome::Forced { importers: Some(importers) }) => {
+///         eprintln!("Forced unexport, {importers} importer(s) lost access");
+///     }
+///     Ok(_) => println!("Successfully unexported"),
+///     Err(e) => eprintln!("Unexport failed: {e}"),
+/// }
+/// ```
+#[inline]
+pub fn unexport_graceful(mem_id: MemId, timeout: Duration) -> Result<UnexportOutcome> {
+    // `None` if the timeout is too large to represent: wait indefinitely
+    let deadline = Instant::now().checked_add(timeout);
+    loop {
+        // Unknown if the query fails: let the kernel refuse a busy region
+        let importers = query_importers(mem_id).ok();
+        if importers.is_none_or(|importers| importers == 0) {
+            match mem_unexport(mem_id, ObmmUnexportFlags::empty()) {
+                Ok(()) => return Ok(UnexportOutcome::Clean),
+                // A new importer attached since the check
+                Err(ObmmError::Busy(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let remaining = deadline.map_or(GRACEFUL_POLL_INTERVAL, |deadline| {
+            deadline.saturating_duration_since(Instant::now())
+        });
+        if remaining.is_zero() {
+            mem_unexport(mem_id, ObmmUnexportFlags::FORCE)?;
+            return Ok(UnexportOutcome::Forced { importers });
+        }
+        thread::sleep(remaining.min(GRACEFUL_POLL_INTERVAL));
     }
 }
 
@@ -166,7 +283,9 @@ pub fn mem_unexport(mem_id: MemId, flags: ObmmUnexportFlags) -> Result<()> {
 /// - The memory descriptor containing metadata
 ///
 /// # Errors
-/// Returns `ObmmError::InvalidMemId` if the export operation fails
+/// Returns `ObmmError::QuotaExceeded` if the export would exceed the quota
+/// set with [`accounting::set_quota`], and a kernel error if the export
+/// operation fails
 ///
 /// # Safety
 /// The virtual address range must be valid and accessible in the target process.
@@ -190,18 +309,34 @@ pub fn export_useraddr<T: Default>(
     _pid: i32,
     _va: u64,
     length: usize,
-    _: ObmmExportFlags,
+    flags: ObmmExportFlags,
 ) -> Result<(MemId, ObmmMemDesc<T>)> {
+    let span = OpSpan::lifecycle("export_useraddr");
+    accounting::check_export_len(length).map_err(|e| span.fail(e))?;
     // Hooked implementation for testing
-    let mut desc = ObmmMemDesc::<T>::default();
-    let memid = 1;
-    desc.addr = 0x7fff_fc00_0000;
-    desc.length = length
-        .try_into()
-        .map_err(|_e| ObmmError::InvalidInput("length too large"))?;
+    let mut desc = ObmmMemDesc::<T> {
+        length: length
+            .try_into()
+            .map_err(|_e| span.fail(ObmmError::InvalidInput("length too large")))?,
+        ..Default::default()
+    };
+    let memid = match crate::mock::export(desc.length) {
+        Some(region) => {
+            desc.addr = region.addr;
+            desc.seid = region.seid;
+            desc.deid = region.deid;
+            region.mem_id
+        }
+        None => {
+            desc.addr = 0x7fff_fc00_0000;
+            1
+        }
+    };
     if memid == OBMM_INVALID_MEMID {
-        Err(ObmmError::InvalidMemId)
+        Err(span.fail(ObmmError::last_os_error("export_useraddr", None)))
     } else {
+        accounting::record_export_len(memid, length, flags.bits());
+        span.exported(memid, &desc);
         Ok((memid, desc))
     }
 }
@@ -226,6 +361,7 @@ pub fn export_useraddr<T: Default>(
 /// - The kernel OBMM subsystem is not available
 /// - The virtual address is invalid or not accessible
 /// - The export operation fails (e.g., insufficient memory)
+/// - The export would exceed the quota set with [`accounting::set_quota`]
 #[cfg(feature = "native")]
 #[inline]
 #[allow(clippy::as_conversions)]
@@ -235,6 +371,8 @@ pub fn export_useraddr<T: Default>(
     length: usize,
     flags: ObmmExportFlags,
 ) -> Result<(MemId, ObmmMemDesc<T>)> {
+    let span = OpSpan::lifecycle("export_useraddr");
+    accounting::check_export_len(length).map_err(|e| span.fail(e))?;
     let mut desc = ObmmMemDesc::<T>::default();
     let desc_ptr = std::ptr::addr_of_mut!(desc);
     let memid = unsafe {
@@ -247,8 +385,397 @@ pub fn export_useraddr<T: Default>(
         )
     };
     if memid == OBMM_INVALID_MEMID {
-        Err(ObmmError::InvalidMemId)
+        Err(span.fail(ObmmError::InvalidMemId))
     } else {
+        accounting::record_export_len(memid, length, flags.bits());
+        span.exported(memid, &desc);
         Ok((memid, desc))
     }
 }
+
+/// Convert an error from [`mem_export`] into an `ObmmError`
+///
+/// Kernel errors are passed through; anything else becomes
+/// `ObmmError::ExportFailed`.
+pub(crate) fn export_error(err: anyhow::Error) -> ObmmError {
+    err.downcast::<ObmmError>()
+        .unwrap_or_else(|e| ObmmError::ExportFailed(e.to_string()))
+}
+
+/// Default alignment of per-node export lengths (2MB)
+pub const EXPORT_ALIGN: usize = 2 * 1024 * 1024;
+
+/// Byte size helpers for building export lengths
+///
+/// # Example
+/// ```
+/// use obmm_rs::export::ByteSize;
+///
+/// assert_eq!(64.mib(), 64 * 1024 * 1024);
+/// assert_eq!(1.gib(), 1024.mib());
+/// ```
+pub trait ByteSize {
+    /// Interpret the value as kibibytes
+    fn kib(self) -> usize;
+    /// Interpret the value as mebibytes
+    fn mib(self) -> usize;
+    /// Interpret the value as gibibytes
+    fn gib(self) -> usize;
+}
+
+impl ByteSize for usize {
+    #[inline]
+    fn kib(self) -> usize {
+        self.saturating_mul(1024)
+    }
+
+    #[inline]
+    fn mib(self) -> usize {
+        self.saturating_mul(1024 * 1024)
+    }
+
+    #[inline]
+    fn gib(self) -> usize {
+        self.saturating_mul(1024 * 1024 * 1024)
+    }
+}
+
+/// Builder for a memory export request
+///
+/// Collects the length to export from each NUMA node and produces the
+/// `MAX_NUMA_NODES`-sized lengths array expected by [`mem_export`].
+///
+/// # Example
+/// ```
+/// use obmm_rs::export::{ByteSize, ExportRequest};
+/// use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+///
+/// let request = ExportRequest::new()
+///     .numa(0, 64.mib())
+///     .numa(2, 128.mib())
+///     .flags(ObmmExportFlags::ALLOWMMAP);
+/// assert_eq!(request.total_len(), 192.mib());
+///
+/// match request.export::<UbPrivData>() {
+///     Ok((mem_id, _desc)) => println!("Exported memory ID: {}", mem_id),
+///     Err(e) => eprintln!("Export failed: {}", e),
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportRequest {
+    /// Length to export from each NUMA node
+    lengths: [usize; MAX_NUMA_NODES],
+    /// Export flags
+    flags: ObmmExportFlags,
+    /// Required alignment of each non-zero length
+    align: usize,
+    /// First out-of-range NUMA node passed to `numa`
+    invalid_node: Option<usize>,
+}
+
+impl ExportRequest {
+    /// Create an empty request with no flags and 2MB alignment
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            lengths: [0; MAX_NUMA_NODES],
+            flags: ObmmExportFlags::empty(),
+            align: EXPORT_ALIGN,
+            invalid_node: None,
+        }
+    }
+
+    /// Set the length to export from a NUMA node
+    ///
+    /// Setting the same node again replaces its length. Out-of-range nodes
+    /// are reported by [`lengths`](Self::lengths) and [`export`](Self::export).
+    ///
+    /// # Arguments
+    /// * `node` - NUMA node index (must be below `MAX_NUMA_NODES`)
+    /// * `len` - Length in bytes
+    #[inline]
+    #[must_use]
+    pub const fn numa(mut self, node: usize, len: usize) -> Self {
+        if node < MAX_NUMA_NODES {
+            self.lengths[node] = len;
+        } else if self.invalid_node.is_none() {
+            self.invalid_node = Some(node);
+        }
+        self
+    }
+
+    /// Set the export flags
+    #[inline]
+    #[must_use]
+    pub const fn flags(mut self, flags: ObmmExportFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the required alignment of each per-node length
+    ///
+    /// # Arguments
+    /// * `align` - Alignment in bytes (must be a power of two)
+    #[inline]
+    #[must_use]
+    pub const fn align(mut self, align: usize) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Get the export flags
+    #[inline]
+    #[must_use]
+    pub const fn export_flags(&self) -> ObmmExportFlags {
+        self.flags
+    }
+
+    /// Get the total length requested across all nodes
+    #[inline]
+    #[must_use]
+    pub fn total_len(&self) -> usize {
+        self.lengths
+            .iter()
+            .fold(0, |total, len| total.saturating_add(*len))
+    }
+
+    /// Validate the request and produce the per-node lengths array
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if:
+    /// - A NUMA node is out of range
+    /// - The total length is zero
+    /// - The alignment is not a power of two
+    /// - A per-node length is not aligned
+    #[inline]
+    pub fn lengths(&self) -> Result<[usize; MAX_NUMA_NODES]> {
+        if self.invalid_node.is_some() {
+            return Err(ObmmError::InvalidInput("NUMA node index out of range"));
+        }
+        if self.total_len() == 0 {
+            return Err(ObmmError::InvalidInput("export length must be non-zero"));
+        }
+        if !self.align.is_power_of_two() {
+            return Err(ObmmError::InvalidInput(
+                "export alignment must be a power of two",
+            ));
+        }
+        if self
+            .lengths
+            .iter()
+            .any(|len| !len.is_multiple_of(self.align))
+        {
+            return Err(ObmmError::InvalidInput(
+                "export length is not aligned to the export alignment",
+            ));
+        }
+        Ok(self.lengths)
+    }
+
+    /// Validate the request and export the memory
+    ///
+    /// # Returns
+    /// The memory ID and descriptor of the exported region
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if validation fails and
+    /// `ObmmError::ExportFailed` if the export operation fails
+    #[inline]
+    pub fn export<T: Default>(&self) -> Result<(MemId, ObmmMemDesc<T>)> {
+        let lengths = self.lengths()?;
+        mem_export::<T>(&lengths, self.flags).map_err(export_error)
+    }
+}
+
+impl Default for ExportRequest {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Result of a striped export
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct StripedExport<T> {
+    /// Memory ID of the exported region
+    pub mem_id: MemId,
+    /// Memory descriptor of the exported region
+    pub desc: ObmmMemDesc<T>,
+    /// Length exported from each NUMA node as `(node, length)`, in the
+    /// order the nodes were given
+    pub per_node: Vec<(usize, usize)>,
+}
+
+/// Export memory striped evenly across several NUMA nodes
+///
+/// The total length is split into `EXPORT_ALIGN` units that are spread as
+/// evenly as possible; the first nodes receive one extra unit when the
+/// split is uneven.
+///
+/// # Arguments
+/// * `total_len` - Total length in bytes (must be a multiple of `EXPORT_ALIGN`)
+/// * `nodes` - NUMA nodes to export from
+/// * `flags` - Export flags
+///
+/// # Errors
+/// Returns `ObmmError::InvalidInput` if the nodes or length are invalid and
+/// `ObmmError::ExportFailed` if the export operation fails
+///
+/// # Example
+/// ```
+/// use obmm_rs::export::{ByteSize, mem_export_striped};
+/// use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+///
+/// match mem_export_striped::<UbPrivData>(256.mib(), &[0, 1], ObmmExportFlags::ALLOWMMAP) {
+///     Ok(export) => println!("Exported {} as {:?}", export.mem_id, export.per_node),
+///     Err(e) => eprintln!("Export failed: {}", e),
+/// }
+/// ```
+#[inline]
+pub fn mem_export_striped<T: Default>(
+    total_len: usize,
+    nodes: &[usize],
+    flags: ObmmExportFlags,
+) -> Result<StripedExport<T>> {
+    let weighted: Vec<(usize, u32)> = nodes.iter().map(|&node| (node, 1)).collect();
+    mem_export_weighted(total_len, &weighted, flags)
+}
+
+/// Export memory distributed across NUMA nodes in proportion to weights
+///
+/// # Arguments
+/// * `total_len` - Total length in bytes (must be a multiple of `EXPORT_ALIGN`)
+/// * `nodes` - NUMA nodes and their relative weights as `(node, weight)`
+/// * `flags` - Export flags
+///
+/// # Errors
+/// Returns `ObmmError::InvalidInput` if the nodes, weights or length are
+/// invalid and `ObmmError::ExportFailed` if the export operation fails
+#[inline]
+pub fn mem_export_weighted<T: Default>(
+    total_len: usize,
+    nodes: &[(usize, u32)],
+    flags: ObmmExportFlags,
+) -> Result<StripedExport<T>> {
+    let per_node = stripe_lengths(total_len, nodes, EXPORT_ALIGN)?;
+    let request = per_node.iter().fold(
+        ExportRequest::new().flags(flags),
+        |request, &(node, len)| request.numa(node, len),
+    );
+    let (mem_id, desc) = request.export::<T>()?;
+
+    Ok(StripedExport {
+        mem_id,
+        desc,
+        per_node,
+    })
+}
+
+/// Split `total_len` across weighted nodes in units of `align` bytes
+///
+/// Units are assigned in proportion to the weights; units left over by
+/// rounding go to the nodes with the largest remainders, earlier nodes
+/// first on ties.
+fn stripe_lengths(
+    total_len: usize,
+    nodes: &[(usize, u32)],
+    align: usize,
+) -> Result<Vec<(usize, usize)>> {
+    if nodes.is_empty() {
+        return Err(ObmmError::InvalidInput("no NUMA nodes given"));
+    }
+    if nodes.iter().any(|&(node, _)| node >= MAX_NUMA_NODES) {
+        return Err(ObmmError::InvalidInput("NUMA node index out of range"));
+    }
+    if nodes
+        .iter()
+        .enumerate()
+        .any(|(i, &(node, _))| nodes[..i].iter().any(|&(other, _)| other == node))
+    {
+        return Err(ObmmError::InvalidInput("duplicate NUMA node"));
+    }
+    if total_len == 0 || !total_len.is_multiple_of(align) {
+        return Err(ObmmError::InvalidInput(
+            "total length must be a non-zero multiple of the export alignment",
+        ));
+    }
+    let total_weight: u64 = nodes.iter().map(|&(_, weight)| u64::from(weight)).sum();
+    if total_weight == 0 {
+        return Err(ObmmError::InvalidInput("total weight must be non-zero"));
+    }
+
+    let units = (total_len / align) as u64;
+    let mut shares: Vec<(u64, u64)> = nodes
+        .iter()
+        .map(|&(_, weight)| {
+            let scaled = units * u64::from(weight);
+            (scaled / total_weight, scaled % total_weight)
+        })
+        .collect();
+
+    let assigned: u64 = shares.iter().map(|&(share, _)| share).sum();
+    let mut order: Vec<usize> = (0..shares.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(shares[i].1));
+    for &i in order.iter().take((units - assigned) as usize) {
+        shares[i].0 += 1;
+    }
+
+    Ok(nodes
+        .iter()
+        .zip(shares)
+        .map(|(&(node, _), (share, _))| (node, share as usize * align))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    #[test]
+    fn test_stripe_lengths_even() {
+        let lengths = stripe_lengths(10 * MB, &[(0, 1), (1, 1), (3, 1)], 2 * MB).unwrap();
+        assert_eq!(lengths, vec![(0, 4 * MB), (1, 4 * MB), (3, 2 * MB)]);
+    }
+
+    #[test]
+    fn test_stripe_lengths_weighted() {
+        let lengths = stripe_lengths(16 * MB, &[(0, 3), (1, 1)], 2 * MB).unwrap();
+        assert_eq!(lengths, vec![(0, 12 * MB), (1, 4 * MB)]);
+
+        let lengths = stripe_lengths(2 * MB, &[(0, 1), (1, 0)], 2 * MB).unwrap();
+        assert_eq!(lengths, vec![(0, 2 * MB), (1, 0)]);
+    }
+
+    #[test]
+    fn test_stripe_lengths_invalid() {
+        assert!(stripe_lengths(4 * MB, &[], 2 * MB).is_err());
+        assert!(stripe_lengths(4 * MB, &[(0, 1), (0, 1)], 2 * MB).is_err());
+        assert!(stripe_lengths(4 * MB, &[(MAX_NUMA_NODES, 1)], 2 * MB).is_err());
+        assert!(stripe_lengths(3 * MB, &[(0, 1)], 2 * MB).is_err());
+        assert!(stripe_lengths(4 * MB, &[(0, 0)], 2 * MB).is_err());
+    }
+
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn test_unexport_graceful() {
+        let outcome = unexport_graceful(1, Duration::ZERO).unwrap();
+        assert_eq!(outcome, UnexportOutcome::Clean);
+    }
+
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn test_mem_export_striped() {
+        let export = mem_export_striped::<crate::types::UbPrivData>(
+            6 * MB,
+            &[2, 0],
+            ObmmExportFlags::ALLOWMMAP,
+        )
+        .unwrap();
+        assert_eq!(export.desc.length, 6 * MB as u64);
+        assert_eq!(export.per_node, vec![(2, 4 * MB), (0, 2 * MB)]);
+    }
+}
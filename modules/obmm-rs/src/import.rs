@@ -3,7 +3,12 @@
 //! This module provides safe wrappers for memory import operations including
 //! standard memory import, preimport, and their unimport counterparts.
 
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::accounting;
 use crate::error::{ObmmError, Result};
+use crate::registry::EntryKind;
+use crate::trace::OpSpan;
 
 #[cfg(feature = "native")]
 use std::ffi::c_void;
@@ -11,8 +16,8 @@ use std::ffi::c_void;
 #[cfg(feature = "native")]
 use crate::sys;
 use crate::types::{
-    ImportResult, MemId, OBMM_INVALID_MEMID, ObmmExportFlags, ObmmMemDesc, ObmmPreimportFlags,
-    ObmmPreimportInfo, UbPrivData,
+    ImportResult, MemId, OBMM_INVALID_MEMID, ObmmExportFlags, ObmmImportFlags, ObmmMemDesc,
+    ObmmPreimportFlags, ObmmPreimportInfo, UbPrivData,
 };
 
 /// Import memory region
@@ -30,7 +35,9 @@ use crate::types::{
 /// - The NUMA node where the memory was placed
 ///
 /// # Errors
-/// Returns `ObmmError::ImportFailed` if the import operation fails
+/// Returns `ObmmError::InvalidDescriptor` if `desc` fails
+/// [`ObmmMemDesc::validate`] and `ObmmError::ImportFailed` if the import
+/// operation fails
 ///
 /// # Example
 /// ```
@@ -45,60 +52,109 @@ use crate::types::{
 ///     Err(e) => eprintln!("Import failed: {}", e),
 /// }
 /// ```
-#[cfg(not(feature = "native"))]
 #[inline]
-pub fn mem_import(_: &ObmmMemDesc<UbPrivData>, _: ObmmExportFlags, _: i32) -> Result<ImportResult> {
-    // Hooked implementation for testing
-    let memid = 1;
-    let numa = 0;
-    if memid == OBMM_INVALID_MEMID {
-        Err(ObmmError::ImportFailed(
-            "invalid memid returned".to_string(),
-        ))
-    } else {
-        Ok(ImportResult {
-            mem_id: memid,
-            numa_node: numa,
-        })
-    }
+pub fn mem_import(
+    desc: &ObmmMemDesc<UbPrivData>,
+    flags: ObmmExportFlags,
+    base_dist: i32,
+) -> Result<ImportResult> {
+    mem_import_on_node(desc, flags, base_dist, -1)
 }
 
-/// Import memory region (real implementation)
+/// Import memory region onto a requested NUMA node
 ///
-/// Imports a remote memory region using the actual OBMM C library.
+/// Like [`mem_import`], but asks the kernel to place the imported memory
+/// on `numa_id`. Pass `-1` to let the kernel choose.
 ///
 /// # Arguments
 /// * `desc` - Memory descriptor from the remote export
 /// * `flags` - Import flags
 /// * `base_dist` - Base distribution hint for NUMA placement
+/// * `numa_id` - Requested NUMA node, or `-1` for any
 ///
 /// # Returns
-/// An `ImportResult` containing:
-/// - The memory ID assigned to the imported region
-/// - The NUMA node where the memory was placed
+/// An `ImportResult` containing the memory ID and the NUMA node where
+/// the memory was actually placed
 ///
 /// # Errors
-/// Returns an error if:
-/// - The kernel OBMM subsystem is not available
-/// - The memory descriptor is invalid
-/// - The import operation fails (e.g., insufficient memory)
-#[cfg(feature = "native")]
+/// Returns a kernel error if the import operation fails
+///
+/// # Example
+/// ```
+/// use obmm_rs::import::mem_import_on_node;
+/// use obmm_rs::types::{ObmmMemDesc, ObmmExportFlags, UbPrivData};
+///
+/// let desc = ObmmMemDesc::<UbPrivData>::default();
+///
+/// match mem_import_on_node(&desc, ObmmExportFlags::ALLOWMMAP, 0, 2) {
+///     Ok(result) => println!("Imported to NUMA node {}", result.numa_node),
+///     Err(e) => eprintln!("Import failed: {}", e),
+/// }
+/// ```
 #[inline]
-pub fn mem_import(
+pub fn mem_import_on_node(
     desc: &ObmmMemDesc<UbPrivData>,
     flags: ObmmExportFlags,
     base_dist: i32,
+    numa_id: i32,
 ) -> Result<ImportResult> {
-    let mut numa: i32 = -1;
+    import_raw(
+        desc,
+        ObmmImportFlags::from_bits_retain(flags.bits()),
+        base_dist,
+        numa_id,
+    )
+}
+
+/// Issue an import with kernel import flags (hooked implementation for testing)
+#[cfg(not(feature = "native"))]
+pub(crate) fn import_raw(
+    desc: &ObmmMemDesc<UbPrivData>,
+    flags: ObmmImportFlags,
+    _: i32,
+    numa_id: i32,
+) -> Result<ImportResult> {
+    let span = OpSpan::lifecycle("import");
+    // Hooked implementation for testing
+    let memid = crate::mock::import(desc)
+        .transpose()
+        .map_err(|e| span.fail(e))?
+        .unwrap_or(1);
+    let numa = numa_id.max(0);
+    if memid == OBMM_INVALID_MEMID {
+        Err(span.fail(ObmmError::ImportFailed(
+            "invalid memid returned".to_string(),
+        )))
+    } else {
+        accounting::record_import(memid, desc.length, numa, flags.bits());
+        span.imported(memid, desc);
+        Ok(ImportResult {
+            mem_id: memid,
+            numa_node: numa,
+        })
+    }
+}
+
+/// Issue an import with kernel import flags (real implementation)
+#[cfg(feature = "native")]
+pub(crate) fn import_raw(
+    desc: &ObmmMemDesc<UbPrivData>,
+    flags: ObmmImportFlags,
+    base_dist: i32,
+    numa_id: i32,
+) -> Result<ImportResult> {
+    let span = OpSpan::lifecycle("import");
+    desc.validate().map_err(|e| span.fail(e))?;
+    let mut numa: i32 = numa_id;
     let desc_ptr = std::ptr::addr_of!(*desc);
     let numa_ptr = std::ptr::addr_of_mut!(numa);
     let memid =
         unsafe { sys::obmm_import(desc_ptr.cast::<c_void>(), flags.bits(), base_dist, numa_ptr) };
     if memid == OBMM_INVALID_MEMID {
-        Err(ObmmError::ImportFailed(
-            "invalid memid returned".to_string(),
-        ))
+        Err(span.fail(ObmmError::last_os_error("import", None)))
     } else {
+        accounting::record_import(memid, desc.length, numa, flags.bits());
+        span.imported(memid, desc);
         Ok(ImportResult {
             mem_id: memid,
             numa_node: numa,
@@ -115,7 +171,7 @@ pub fn mem_import(
 /// * `flags` - Unimport flags
 ///
 /// # Errors
-/// Returns `ObmmError::UnimportFailed` if the unimport operation fails
+/// Returns a kernel error if the unimport operation fails
 ///
 /// # Example
 /// ```
@@ -130,8 +186,14 @@ pub fn mem_import(
 /// ```
 #[cfg(not(feature = "native"))]
 #[inline]
-pub fn mem_unimport(_: MemId, _: ObmmExportFlags) -> Result<()> {
+pub fn mem_unimport(mem_id: MemId, _: ObmmExportFlags) -> Result<()> {
+    let span = OpSpan::lifecycle("unimport").region(EntryKind::Import, mem_id);
     // Hooked implementation for testing
+    crate::mock::unimport(mem_id)
+        .transpose()
+        .map_err(|e| span.fail(e))?;
+    accounting::record_unimport(mem_id);
+    span.released(EntryKind::Import);
     Ok(())
 }
 
@@ -151,11 +213,14 @@ pub fn mem_unimport(_: MemId, _: ObmmExportFlags) -> Result<()> {
 #[cfg(feature = "native")]
 #[inline]
 pub fn mem_unimport(mem_id: MemId, flags: ObmmExportFlags) -> Result<()> {
+    let span = OpSpan::lifecycle("unimport").region(EntryKind::Import, mem_id);
     let ret = unsafe { sys::obmm_unimport(mem_id, flags.bits()) };
     if ret == 0 {
+        accounting::record_unimport(mem_id);
+        span.released(EntryKind::Import);
         Ok(())
     } else {
-        Err(ObmmError::UnimportFailed(format!("return code: {}", ret)))
+        Err(span.fail(ObmmError::last_os_error("unimport", Some(mem_id))))
     }
 }
 
@@ -170,7 +235,7 @@ pub fn mem_unimport(mem_id: MemId, flags: ObmmExportFlags) -> Result<()> {
 /// * `flags` - Preimport flags
 ///
 /// # Errors
-/// Returns `ObmmError::PreimportFailed` if the preimport operation fails
+/// Returns a kernel error if the preimport operation fails
 ///
 /// # Example
 /// ```
@@ -214,7 +279,7 @@ pub fn preimport(info: &mut ObmmPreimportInfo, flags: ObmmPreimportFlags) -> Res
     if ret == 0 {
         Ok(())
     } else {
-        Err(ObmmError::PreimportFailed(format!("return code: {}", ret)))
+        Err(ObmmError::last_os_error("preimport", None))
     }
 }
 
@@ -227,7 +292,7 @@ pub fn preimport(info: &mut ObmmPreimportInfo, flags: ObmmPreimportFlags) -> Res
 /// * `flags` - Unpreimport flags
 ///
 /// # Errors
-/// Returns `ObmmError::UnpreimportFailed` if the unpreimport operation fails
+/// Returns a kernel error if the unpreimport operation fails
 ///
 /// # Example
 /// ```
@@ -267,9 +332,327 @@ pub fn unpreimport(info: &ObmmPreimportInfo, flags: ObmmPreimportFlags) -> Resul
     if ret == 0 {
         Ok(())
     } else {
-        Err(ObmmError::UnpreimportFailed(format!(
-            "return code: {}",
-            ret
-        )))
+        Err(ObmmError::last_os_error("unpreimport", None))
+    }
+}
+
+/// NUMA placement policy for an import
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum NumaPolicy {
+    /// Let the kernel choose the node
+    #[default]
+    Any,
+    /// Try the given node, falling back to any node if it fails
+    Preferred(i32),
+    /// Place on the given node or fail
+    Strict(i32),
+    /// Rotate successive imports across the given nodes
+    Interleave(Vec<i32>),
+}
+
+/// Placement that was actually honored by an import
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HonoredPolicy {
+    /// The kernel chose the node
+    Any,
+    /// The preferred node was used
+    Preferred,
+    /// The strict node was used
+    Strict,
+    /// One of the interleave nodes was used
+    Interleave,
+    /// The requested nodes were unavailable and the kernel chose the node
+    Fallback,
+}
+
+/// Result of an import with [`ImportOptions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ImportOutcome {
+    /// Memory ID and placement of the imported region
+    pub result: ImportResult,
+    /// Placement policy that was honored
+    pub honored: HonoredPolicy,
+    /// Preimport reservation backing the import, which must be released
+    /// with [`unpreimport`] after the memory is unimported
+    pub preimport: Option<ObmmPreimportInfo>,
+}
+
+/// Next start position for interleaved imports
+static INTERLEAVE_CURSOR: AtomicUsize = AtomicUsize::new(0);
+
+/// Options for importing memory with an explicit NUMA placement policy
+///
+/// # Example
+/// ```
+/// use obmm_rs::import::{HonoredPolicy, ImportOptions, NumaPolicy};
+/// use obmm_rs::types::{ObmmMemDesc, UbPrivData};
+///
+/// let desc = ObmmMemDesc::<UbPrivData>::default();
+///
+/// match ImportOptions::new().policy(NumaPolicy::Preferred(2)).import(&desc) {
+///     Ok(outcome) if outcome.honored == HonoredPolicy::Fallback => {
+///         println!("Node 2 unavailable, placed on {}", outcome.result.numa_node)
+///     }
+///     Ok(outcome) => println!("Imported to NUMA node {}", outcome.result.numa_node),
+///     Err(e) => eprintln!("Import failed: {}", e),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportOptions {
+    /// Import flags
+    flags: ObmmImportFlags,
+    /// Base distance hint for NUMA placement
+    base_dist: i32,
+    /// Placement policy
+    policy: NumaPolicy,
+    /// Physical address to reserve through preimport
+    preimport_pa: Option<u64>,
+    /// Whether the importer only reads the region
+    read_only: bool,
+}
+
+impl ImportOptions {
+    /// Create options allowing mmap, with no placement preference
+    #[inline]
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            flags: ObmmImportFlags::ALLOWMMAP,
+            base_dist: 0,
+            policy: NumaPolicy::Any,
+            preimport_pa: None,
+            read_only: false,
+        }
+    }
+
+    /// Set the import flags
+    #[inline]
+    #[must_use]
+    pub const fn flags(mut self, flags: ObmmImportFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the base distance hint
+    #[inline]
+    #[must_use]
+    pub const fn base_dist(mut self, base_dist: i32) -> Self {
+        self.base_dist = base_dist;
+        self
+    }
+
+    /// Set the placement policy
+    #[inline]
+    #[must_use]
+    pub fn policy(mut self, policy: NumaPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Reserve the physical address range at `pa` through preimport
+    ///
+    /// The range is declared with [`preimport`] on the selected node before
+    /// importing into it.
+    #[inline]
+    #[must_use]
+    pub const fn preimport_at(mut self, pa: u64) -> Self {
+        self.preimport_pa = Some(pa);
+        self
+    }
+
+    /// Declare that the importer only reads the region
+    ///
+    /// For memory the exporter keeps writing to. Handles imported with
+    /// these options map the region with read-only protection and take
+    /// only read ownership of the mapping, so the importer can neither
+    /// write it through the mapping nor claim write ownership from the
+    /// exporter. The import itself is unchanged; the kernel has no
+    /// read-only import flag.
+    #[inline]
+    #[must_use]
+    pub const fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Check whether the importer only reads the region
+    #[inline]
+    #[must_use]
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Import a memory region according to these options
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` for an empty interleave set and
+    /// `ObmmError::ImportFailed` if no allowed placement succeeds or a
+    /// strict placement is not honored
+    #[inline]
+    pub fn import(&self, desc: &ObmmMemDesc<UbPrivData>) -> Result<ImportOutcome> {
+        match &self.policy {
+            NumaPolicy::Any => self.import_on(desc, -1, HonoredPolicy::Any),
+            NumaPolicy::Preferred(node) => self
+                .import_on(desc, *node, HonoredPolicy::Preferred)
+                .or_else(|_| self.import_on(desc, -1, HonoredPolicy::Fallback)),
+            NumaPolicy::Strict(node) => self.import_on(desc, *node, HonoredPolicy::Strict),
+            NumaPolicy::Interleave(nodes) => {
+                if nodes.is_empty() {
+                    return Err(ObmmError::InvalidInput("interleave node set is empty"));
+                }
+                let start = INTERLEAVE_CURSOR.fetch_add(1, Ordering::Relaxed);
+                let mut last_err = None;
+                for i in 0..nodes.len() {
+                    let node = nodes[(start + i) % nodes.len()];
+                    match self.import_on(desc, node, HonoredPolicy::Interleave) {
+                        Ok(outcome) => return Ok(outcome),
+                        Err(e) => last_err = Some(e),
+                    }
+                }
+                Err(last_err.unwrap_or(ObmmError::ImportFailed(
+                    "no interleave node accepted the import".to_string(),
+                )))
+            }
+        }
+    }
+
+    /// Import onto `node` (or any node for -1), checking the placement
+    fn import_on(
+        &self,
+        desc: &ObmmMemDesc<UbPrivData>,
+        node: i32,
+        policy: HonoredPolicy,
+    ) -> Result<ImportOutcome> {
+        let preimport = self
+            .preimport_pa
+            .map(|pa| self.reserve(desc, pa, node))
+            .transpose()?;
+        let (flags, numa_id) = match &preimport {
+            Some(info) => (self.flags | ObmmImportFlags::PREIMPORT, info.numa_id),
+            None => (self.flags, node),
+        };
+
+        let result = match import_raw(desc, flags, self.base_dist, numa_id) {
+            Ok(result) => result,
+            Err(e) => {
+                if let Some(info) = &preimport {
+                    let _result = unpreimport(info, ObmmPreimportFlags::empty());
+                }
+                return Err(e);
+            }
+        };
+
+        let honored = if node < 0 || result.numa_node == node {
+            policy
+        } else if policy == HonoredPolicy::Strict {
+            let _result = mem_unimport(result.mem_id, ObmmExportFlags::empty());
+            if let Some(info) = &preimport {
+                let _result = unpreimport(info, ObmmPreimportFlags::empty());
+            }
+            return Err(ObmmError::ImportFailed(format!(
+                "placed on NUMA node {} instead of {node}",
+                result.numa_node
+            )));
+        } else {
+            HonoredPolicy::Fallback
+        };
+
+        Ok(ImportOutcome {
+            result,
+            honored,
+            preimport,
+        })
+    }
+
+    /// Declare a preimport reservation for `desc` at `pa` on `node`
+    fn reserve(
+        &self,
+        desc: &ObmmMemDesc<UbPrivData>,
+        pa: u64,
+        node: i32,
+    ) -> Result<ObmmPreimportInfo> {
+        let mut info = ObmmPreimportInfo {
+            pa,
+            length: desc.length,
+            base_dist: self.base_dist,
+            numa_id: node,
+            seid: desc.seid,
+            deid: desc.deid,
+            scna: desc.scna,
+            dcna: desc.dcna,
+            priv_len: 0,
+        };
+        let flags = if self.flags.contains(ObmmImportFlags::ALLOWMMAP) {
+            ObmmPreimportFlags::ALLOWMMAP
+        } else {
+            ObmmPreimportFlags::empty()
+        };
+        preimport(&mut info, flags)?;
+        Ok(info)
+    }
+}
+
+impl Default for ImportOptions {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, not(feature = "native")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_options_policies() {
+        let desc = ObmmMemDesc::<UbPrivData>::default();
+
+        let outcome = ImportOptions::new().import(&desc).unwrap();
+        assert_eq!(outcome.honored, HonoredPolicy::Any);
+
+        let outcome = ImportOptions::new()
+            .policy(NumaPolicy::Preferred(2))
+            .import(&desc)
+            .unwrap();
+        assert_eq!(outcome.honored, HonoredPolicy::Preferred);
+        assert_eq!(outcome.result.numa_node, 2);
+
+        let outcome = ImportOptions::new()
+            .policy(NumaPolicy::Strict(1))
+            .import(&desc)
+            .unwrap();
+        assert_eq!(outcome.honored, HonoredPolicy::Strict);
+        assert_eq!(outcome.result.numa_node, 1);
+        assert!(outcome.preimport.is_none());
+    }
+
+    #[test]
+    fn test_import_options_interleave() {
+        let options = ImportOptions::new().policy(NumaPolicy::Interleave(vec![1, 3]));
+        let first = options.import(&ObmmMemDesc::default()).unwrap();
+        let second = options.import(&ObmmMemDesc::default()).unwrap();
+        assert_eq!(first.honored, HonoredPolicy::Interleave);
+        assert_ne!(first.result.numa_node, second.result.numa_node);
+
+        let empty = ImportOptions::new().policy(NumaPolicy::Interleave(Vec::new()));
+        assert!(empty.import(&ObmmMemDesc::default()).is_err());
+    }
+
+    #[test]
+    fn test_import_options_preimport() {
+        let desc = ObmmMemDesc::<UbPrivData> {
+            length: 1024 * 1024 * 2,
+            ..Default::default()
+        };
+        let outcome = ImportOptions::new()
+            .policy(NumaPolicy::Strict(1))
+            .preimport_at(0x2_0000_0000)
+            .import(&desc)
+            .unwrap();
+        let info = outcome.preimport.expect("preimport reservation");
+        assert_eq!(info.pa, 0x2_0000_0000);
+        assert_eq!(info.length, desc.length);
+        assert_eq!(outcome.result.numa_node, 1);
     }
 }
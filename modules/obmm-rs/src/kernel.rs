@@ -10,6 +10,27 @@ use crate::error::ObmmError;
 use crate::kernel_abi::*;
 use crate::types::{MemId, ObmmPreimportInfo};
 
+/// Report a failed command the way the C library does
+///
+/// Stores the `errno` of `err` for the caller and returns `ret`, so the
+/// safe wrappers can recover the cause with `ObmmError::last_os_error`.
+fn fail<T>(err: &ObmmError, ret: T) -> T {
+    set_errno(err.raw_os_error().unwrap_or(libc::EIO));
+    ret
+}
+
+/// Report an argument rejected before reaching the kernel (`EINVAL`)
+fn invalid<T>(ret: T) -> T {
+    set_errno(libc::EINVAL);
+    ret
+}
+
+/// Set `errno` of the calling thread
+fn set_errno(errno: i32) {
+    // SAFETY: errno is a thread-local integer
+    unsafe { *libc::__errno_location() = errno };
+}
+
 /// Export memory regions for remote access
 ///
 /// # Arguments
@@ -27,7 +48,7 @@ use crate::types::{MemId, ObmmPreimportInfo};
 /// - `desc` points to a valid, writable `ObmmMemDesc` structure
 pub unsafe fn obmm_export(length: *const usize, flags: u64, desc: *mut c_void) -> MemId {
     if length.is_null() || desc.is_null() {
-        return OBMM_INVALID_MEMID;
+        return invalid(OBMM_INVALID_MEMID);
     }
 
     // Convert desc pointer to ObmmMemDesc for field access
@@ -61,10 +82,8 @@ pub unsafe fn obmm_export(length: *const usize, flags: u64, desc: *mut c_void) -
     }
 
     // Execute ioctl
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_EXPORT as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::ExportFailed(e.to_string()))
-    });
+    let result =
+        with_device(|dev| unsafe { dev.ioctl(OBMM_CMD_EXPORT as libc::c_ulong, &mut cmd) });
 
     match result {
         Ok(_) => {
@@ -74,7 +93,7 @@ pub unsafe fn obmm_export(length: *const usize, flags: u64, desc: *mut c_void) -
             mem_desc.tokenid = cmd.tokenid;
             cmd.mem_id
         }
-        Err(_) => OBMM_INVALID_MEMID,
+        Err(e) => fail(&e, OBMM_INVALID_MEMID),
     }
 }
 
@@ -88,7 +107,7 @@ pub unsafe fn obmm_export(length: *const usize, flags: u64, desc: *mut c_void) -
 /// 0 on success, -1 on failure
 pub fn obmm_unexport(id: MemId, flags: u64) -> i32 {
     if id == OBMM_INVALID_MEMID {
-        return -1;
+        return invalid(-1);
     }
 
     let mut cmd = ObmmCmdUnexport {
@@ -96,14 +115,12 @@ pub fn obmm_unexport(id: MemId, flags: u64) -> i32 {
         flags: flags & OBMM_UNEXPORT_FLAG_MASK,
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_UNEXPORT as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::UnexportFailed(e.to_string()))
-    });
+    let result =
+        with_device(|dev| unsafe { dev.ioctl(OBMM_CMD_UNEXPORT as libc::c_ulong, &mut cmd) });
 
     match result {
         Ok(_) => 0,
-        Err(_) => -1,
+        Err(e) => fail(&e, -1),
     }
 }
 
@@ -130,7 +147,7 @@ pub unsafe fn obmm_import(
     numa: *mut i32,
 ) -> MemId {
     if desc.is_null() {
-        return OBMM_INVALID_MEMID;
+        return invalid(OBMM_INVALID_MEMID);
     }
 
     // Convert desc pointer to ObmmMemDesc for field access
@@ -141,7 +158,7 @@ pub unsafe fn obmm_import(
         && (flags & OBMM_IMPORT_FLAG_PREIMPORT) == 0
         && !(0..=255).contains(&base_dist)
     {
-        return OBMM_INVALID_MEMID;
+        return invalid(OBMM_INVALID_MEMID);
     }
 
     let numa_id = if numa.is_null() { -1 } else { unsafe { *numa } };
@@ -162,10 +179,8 @@ pub unsafe fn obmm_import(
         priv_data: std::ptr::null(),
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_IMPORT as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::ImportFailed(e.to_string()))
-    });
+    let result =
+        with_device(|dev| unsafe { dev.ioctl(OBMM_CMD_IMPORT as libc::c_ulong, &mut cmd) });
 
     match result {
         Ok(_) => {
@@ -176,7 +191,7 @@ pub unsafe fn obmm_import(
             }
             cmd.mem_id
         }
-        Err(_) => OBMM_INVALID_MEMID,
+        Err(e) => fail(&e, OBMM_INVALID_MEMID),
     }
 }
 
@@ -190,7 +205,7 @@ pub unsafe fn obmm_import(
 /// 0 on success, -1 on failure
 pub fn obmm_unimport(id: MemId, flags: u64) -> i32 {
     if id == OBMM_INVALID_MEMID {
-        return -1;
+        return invalid(-1);
     }
 
     let mut cmd = ObmmCmdUnimport {
@@ -198,14 +213,12 @@ pub fn obmm_unimport(id: MemId, flags: u64) -> i32 {
         flags: flags & OBMM_UNIMPORT_FLAG_MASK,
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_UNIMPORT as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::UnimportFailed(e.to_string()))
-    });
+    let result =
+        with_device(|dev| unsafe { dev.ioctl(OBMM_CMD_UNIMPORT as libc::c_ulong, &mut cmd) });
 
     match result {
         Ok(_) => 0,
-        Err(_) => -1,
+        Err(e) => fail(&e, -1),
     }
 }
 
@@ -224,14 +237,14 @@ pub fn obmm_unimport(id: MemId, flags: u64) -> i32 {
 /// `ObmmPreimportInfo` structure.
 pub unsafe fn obmm_preimport(info: *mut ObmmPreimportInfo, flags: u64) -> i32 {
     if info.is_null() {
-        return -1;
+        return invalid(-1);
     }
 
     let pre_info = unsafe { &*info };
 
     // Validate base_dist
     if !(0..=255).contains(&pre_info.base_dist) {
-        return -1;
+        return invalid(-1);
     }
 
     let mut cmd = ObmmCmdPreimport {
@@ -248,9 +261,8 @@ pub unsafe fn obmm_preimport(info: *mut ObmmPreimportInfo, flags: u64) -> i32 {
         priv_data: std::ptr::null(),
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_DECLARE_PREIMPORT as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::ImportFailed(e.to_string()))
+    let result = with_device(|dev| unsafe {
+        dev.ioctl(OBMM_CMD_DECLARE_PREIMPORT as libc::c_ulong, &mut cmd)
     });
 
     match result {
@@ -261,7 +273,7 @@ pub unsafe fn obmm_preimport(info: *mut ObmmPreimportInfo, flags: u64) -> i32 {
             }
             0
         }
-        Err(_) => -1,
+        Err(e) => fail(&e, -1),
     }
 }
 
@@ -279,7 +291,7 @@ pub unsafe fn obmm_preimport(info: *mut ObmmPreimportInfo, flags: u64) -> i32 {
 /// The caller must ensure that `info` points to a valid `ObmmPreimportInfo` structure.
 pub unsafe fn obmm_unpreimport(info: *const ObmmPreimportInfo, flags: u64) -> i32 {
     if info.is_null() {
-        return -1;
+        return invalid(-1);
     }
 
     let pre_info = unsafe { &*info };
@@ -301,14 +313,13 @@ pub unsafe fn obmm_unpreimport(info: *const ObmmPreimportInfo, flags: u64) -> i3
     // Note: OBMM_CMD_UNDECLARE_PREIMPORT uses _IOW (write only), so we pass
     // a const pointer by casting to a mutable pointer (the kernel won't modify it)
     let cmd_ptr: *mut ObmmCmdPreimport = &cmd as *const _ as *mut _;
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_UNDECLARE_PREIMPORT as libc::c_ulong, cmd_ptr) }
-            .map_err(|e| ObmmError::UnimportFailed(e.to_string()))
+    let result = with_device(|dev| unsafe {
+        dev.ioctl(OBMM_CMD_UNDECLARE_PREIMPORT as libc::c_ulong, cmd_ptr)
     });
 
     match result {
         Ok(_) => 0,
-        Err(_) => -1,
+        Err(e) => fail(&e, -1),
     }
 }
 
@@ -340,7 +351,7 @@ pub unsafe fn obmm_export_useraddr(
     desc: *mut c_void,
 ) -> MemId {
     if desc.is_null() {
-        return OBMM_INVALID_MEMID;
+        return invalid(OBMM_INVALID_MEMID);
     }
 
     // Convert desc pointer to ObmmMemDesc for field access
@@ -362,10 +373,8 @@ pub unsafe fn obmm_export_useraddr(
         priv_data: std::ptr::null(),
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_EXPORT_PID as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::ExportFailed(e.to_string()))
-    });
+    let result =
+        with_device(|dev| unsafe { dev.ioctl(OBMM_CMD_EXPORT_PID as libc::c_ulong, &mut cmd) });
 
     match result {
         Ok(_) => {
@@ -374,7 +383,7 @@ pub unsafe fn obmm_export_useraddr(
             mem_desc.tokenid = cmd.tokenid;
             cmd.mem_id
         }
-        Err(_) => OBMM_INVALID_MEMID,
+        Err(e) => fail(&e, OBMM_INVALID_MEMID),
     }
 }
 
@@ -397,7 +406,7 @@ pub unsafe fn obmm_export_useraddr(
 /// The caller must ensure that `start` and `end` are valid virtual addresses.
 pub unsafe fn obmm_set_ownership(_fd: i32, start: *mut c_void, end: *mut c_void, prot: i32) -> i32 {
     if start.is_null() || end.is_null() {
-        return -1;
+        return invalid(-1);
     }
 
     // Convert protection bits to memory state
@@ -405,7 +414,7 @@ pub unsafe fn obmm_set_ownership(_fd: i32, start: *mut c_void, end: *mut c_void,
         0 => OBMM_SHM_MEM_NORMAL_NC | OBMM_SHM_MEM_NO_ACCESS,
         1 => OBMM_SHM_MEM_NORMAL | OBMM_SHM_MEM_READONLY,
         2 | 3 => OBMM_SHM_MEM_NORMAL | OBMM_SHM_MEM_READWRITE,
-        _ => return -1,
+        _ => return invalid(-1),
     };
 
     let mut cmd = ObmmCmdUpdateRange {
@@ -416,14 +425,13 @@ pub unsafe fn obmm_set_ownership(_fd: i32, start: *mut c_void, end: *mut c_void,
         _pad: [0; 6],
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_SHMDEV_UPDATE_RANGE as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::OwnershipFailed(e.to_string()))
+    let result = with_device(|dev| unsafe {
+        dev.ioctl(OBMM_SHMDEV_UPDATE_RANGE as libc::c_ulong, &mut cmd)
     });
 
     match result {
         Ok(_) => 0,
-        Err(_) => -1,
+        Err(e) => fail(&e, -1),
     }
 }
 
@@ -444,7 +452,7 @@ pub unsafe fn obmm_set_ownership(_fd: i32, start: *mut c_void, end: *mut c_void,
 /// - `offset` is either null or points to a writable `u64`
 pub unsafe fn obmm_query_memid_by_pa(pa: u64, id: *mut MemId, offset: *mut u64) -> i32 {
     if id.is_null() && offset.is_null() {
-        return -1;
+        return invalid(-1);
     }
 
     let mut cmd = ObmmCmdAddrQuery {
@@ -455,10 +463,8 @@ pub unsafe fn obmm_query_memid_by_pa(pa: u64, id: *mut MemId, offset: *mut u64)
         pa,
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_ADDR_QUERY as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::QueryFailed(e.to_string()))
-    });
+    let result =
+        with_device(|dev| unsafe { dev.ioctl(OBMM_CMD_ADDR_QUERY as libc::c_ulong, &mut cmd) });
 
     match result {
         Ok(_) => {
@@ -474,7 +480,7 @@ pub unsafe fn obmm_query_memid_by_pa(pa: u64, id: *mut MemId, offset: *mut u64)
             }
             0
         }
-        Err(_) => -1,
+        Err(e) => fail(&e, -1),
     }
 }
 
@@ -493,7 +499,7 @@ pub unsafe fn obmm_query_memid_by_pa(pa: u64, id: *mut MemId, offset: *mut u64)
 /// The caller must ensure that `pa` is either null or points to a writable `u64`.
 pub unsafe fn obmm_query_pa_by_memid(id: MemId, offset: u64, pa: *mut u64) -> i32 {
     if pa.is_null() {
-        return -1;
+        return invalid(-1);
     }
 
     let mut cmd = ObmmCmdAddrQuery {
@@ -504,10 +510,8 @@ pub unsafe fn obmm_query_pa_by_memid(id: MemId, offset: u64, pa: *mut u64) -> i3
         pa: 0,
     };
 
-    let result = with_device(|dev| {
-        unsafe { dev.ioctl(OBMM_CMD_ADDR_QUERY as libc::c_ulong, &mut cmd) }
-            .map_err(|e| ObmmError::QueryFailed(e.to_string()))
-    });
+    let result =
+        with_device(|dev| unsafe { dev.ioctl(OBMM_CMD_ADDR_QUERY as libc::c_ulong, &mut cmd) });
 
     match result {
         Ok(_) => {
@@ -516,6 +520,6 @@ pub unsafe fn obmm_query_pa_by_memid(id: MemId, offset: u64, pa: *mut u64) -> i3
             }
             0
         }
-        Err(_) => -1,
+        Err(e) => fail(&e, -1),
     }
 }
@@ -0,0 +1,284 @@
+//! Alignment, size normalization and export layout checks
+//!
+//! The kernel rejects a malformed export with a bare `EINVAL`. The helpers
+//! here check a per-node lengths array up front and report the exact
+//! reason as a [`LayoutError`].
+
+use std::fs;
+use std::path::Path;
+
+use crate::error::{LayoutError, ObmmError, Result};
+use crate::export::EXPORT_ALIGN;
+use crate::types::MAX_NUMA_NODES;
+
+/// Sysfs directory with one `node<N>` subdirectory per NUMA node
+pub const NUMA_SYSFS_DIR: &str = "/sys/devices/system/node";
+
+/// Check if a length is a multiple of [`EXPORT_ALIGN`] (2MB)
+///
+/// # Example
+/// ```
+/// use obmm_rs::layout::is_export_aligned;
+///
+/// assert!(is_export_aligned(4 * 1024 * 1024));
+/// assert!(!is_export_aligned(4096));
+/// ```
+#[inline]
+#[must_use]
+pub const fn is_export_aligned(len: usize) -> bool {
+    len.is_multiple_of(EXPORT_ALIGN)
+}
+
+/// Round a length down to a multiple of `align`
+///
+/// `align` must be a power of two.
+#[inline]
+#[must_use]
+pub const fn align_down(len: usize, align: usize) -> usize {
+    len & !(align - 1)
+}
+
+/// Round a length up to a multiple of `align`
+///
+/// `align` must be a power of two.
+///
+/// # Returns
+/// The rounded length, or `None` if it does not fit in `usize`
+#[inline]
+#[must_use]
+pub const fn align_up(len: usize, align: usize) -> Option<usize> {
+    match len.checked_add(align - 1) {
+        Some(len) => Some(align_down(len, align)),
+        None => None,
+    }
+}
+
+/// Round a length up to the export granularity ([`EXPORT_ALIGN`])
+///
+/// # Example
+/// ```
+/// use obmm_rs::layout::export_align_up;
+///
+/// assert_eq!(export_align_up(1), Some(2 * 1024 * 1024));
+/// assert_eq!(export_align_up(0), Some(0));
+/// ```
+#[inline]
+#[must_use]
+pub const fn export_align_up(len: usize) -> Option<usize> {
+    align_up(len, EXPORT_ALIGN)
+}
+
+/// Get the NUMA nodes present on this machine
+///
+/// # Returns
+/// Node IDs in ascending order
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the node directory cannot be read
+#[cfg(not(feature = "native"))]
+#[inline]
+pub fn numa_nodes() -> Result<Vec<usize>> {
+    // Hooked implementation for testing
+    Ok((0..MAX_NUMA_NODES).collect())
+}
+
+/// Get the NUMA nodes present on this machine (real implementation)
+///
+/// Reads [`NUMA_SYSFS_DIR`].
+///
+/// # Returns
+/// Node IDs in ascending order
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the node directory cannot be read
+#[cfg(feature = "native")]
+#[inline]
+pub fn numa_nodes() -> Result<Vec<usize>> {
+    numa_nodes_in(Path::new(NUMA_SYSFS_DIR))
+}
+
+/// Get the number of NUMA nodes present on this machine
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the node directory cannot be read
+#[inline]
+pub fn numa_node_count() -> Result<usize> {
+    numa_nodes().map(|nodes| nodes.len())
+}
+
+/// Get the NUMA nodes listed under a sysfs-style directory
+///
+/// Entries not named `node<N>` are ignored.
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the directory cannot be read
+#[inline]
+pub fn numa_nodes_in(root: &Path) -> Result<Vec<usize>> {
+    let entries = fs::read_dir(root)
+        .map_err(|e| ObmmError::QueryFailed(format!("{}: {e}", root.display())))?;
+
+    let mut nodes: Vec<usize> = entries
+        .flatten()
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|n| n.strip_prefix("node"))
+                .and_then(|id| id.parse().ok())
+        })
+        .collect();
+    nodes.sort_unstable();
+    Ok(nodes)
+}
+
+/// Check that a lengths array will be accepted by [`mem_export`](crate::export::mem_export)
+///
+/// Node presence is checked against [`numa_nodes`]; if the nodes cannot be
+/// probed, only the layout itself is checked.
+///
+/// # Errors
+/// Returns `ObmmError::InvalidLayout` with the first problem found
+///
+/// # Example
+/// ```
+/// use obmm_rs::layout::validate_export_layout;
+/// use obmm_rs::types::MAX_NUMA_NODES;
+///
+/// let mut lens = [0; MAX_NUMA_NODES];
+/// lens[0] = 3 * 1024 * 1024;
+/// if let Err(e) = validate_export_layout(&lens) {
+///     println!("{e}"); // length 3145728 on NUMA node 0 is not a multiple of 2097152
+/// }
+/// ```
+#[inline]
+pub fn validate_export_layout(lens: &[usize]) -> Result<()> {
+    match numa_nodes() {
+        Ok(nodes) => validate_export_layout_on(lens, &nodes),
+        Err(_) => check_layout(lens, None),
+    }
+}
+
+/// Check that a lengths array can be exported from the given NUMA nodes
+///
+/// # Errors
+/// Returns `ObmmError::InvalidLayout` with the first problem found
+#[inline]
+pub fn validate_export_layout_on(lens: &[usize], nodes: &[usize]) -> Result<()> {
+    check_layout(lens, Some(nodes))
+}
+
+fn check_layout(lens: &[usize], nodes: Option<&[usize]>) -> Result<()> {
+    if lens.len() != MAX_NUMA_NODES {
+        return Err(ObmmError::InvalidLayout(LayoutError::WrongLength {
+            len: lens.len(),
+            expected: MAX_NUMA_NODES,
+        }));
+    }
+
+    let mut total: usize = 0;
+    for (node, &len) in lens.iter().enumerate() {
+        if len == 0 {
+            continue;
+        }
+        if !is_export_aligned(len) {
+            return Err(ObmmError::InvalidLayout(LayoutError::Unaligned {
+                node,
+                len,
+            }));
+        }
+        if nodes.is_some_and(|nodes| !nodes.contains(&node)) {
+            return Err(ObmmError::InvalidLayout(LayoutError::NodeNotPresent(node)));
+        }
+        total = total
+            .checked_add(len)
+            .ok_or(ObmmError::InvalidLayout(LayoutError::TooLarge))?;
+    }
+
+    if total == 0 {
+        return Err(ObmmError::InvalidLayout(LayoutError::Empty));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MB: usize = 1024 * 1024;
+
+    fn lens(entries: &[(usize, usize)]) -> [usize; MAX_NUMA_NODES] {
+        let mut lens = [0; MAX_NUMA_NODES];
+        for &(node, len) in entries {
+            lens[node] = len;
+        }
+        lens
+    }
+
+    fn layout_error(result: Result<()>) -> LayoutError {
+        match result {
+            Err(ObmmError::InvalidLayout(err)) => err,
+            other => panic!("expected a layout error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_alignment_helpers() {
+        assert!(is_export_aligned(0));
+        assert!(is_export_aligned(4 * MB));
+        assert!(!is_export_aligned(3 * MB));
+        assert_eq!(align_down(3 * MB, 2 * MB), 2 * MB);
+        assert_eq!(align_up(3 * MB, 2 * MB), Some(4 * MB));
+        assert_eq!(export_align_up(4 * MB), Some(4 * MB));
+        assert_eq!(export_align_up(usize::MAX), None);
+    }
+
+    #[test]
+    fn test_validate_export_layout() {
+        let nodes = [0, 1];
+        assert!(validate_export_layout_on(&lens(&[(0, 2 * MB), (1, 4 * MB)]), &nodes).is_ok());
+
+        assert_eq!(
+            layout_error(validate_export_layout_on(&[2 * MB], &nodes)),
+            LayoutError::WrongLength {
+                len: 1,
+                expected: MAX_NUMA_NODES
+            }
+        );
+        assert_eq!(
+            layout_error(validate_export_layout_on(&lens(&[]), &nodes)),
+            LayoutError::Empty
+        );
+        assert_eq!(
+            layout_error(validate_export_layout_on(&lens(&[(1, 3 * MB)]), &nodes)),
+            LayoutError::Unaligned {
+                node: 1,
+                len: 3 * MB
+            }
+        );
+        assert_eq!(
+            layout_error(validate_export_layout_on(&lens(&[(2, 2 * MB)]), &nodes)),
+            LayoutError::NodeNotPresent(2)
+        );
+        let huge = align_down(usize::MAX, EXPORT_ALIGN);
+        assert_eq!(
+            layout_error(validate_export_layout_on(
+                &lens(&[(0, huge), (1, huge)]),
+                &nodes
+            )),
+            LayoutError::TooLarge
+        );
+    }
+
+    #[test]
+    fn test_numa_nodes_in() {
+        let root = std::env::temp_dir().join(format!("obmm-nodes-{}", std::process::id()));
+        for name in ["node0", "node2", "node10", "possible", "power"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+        }
+
+        assert_eq!(numa_nodes_in(&root).unwrap(), vec![0, 2, 10]);
+        assert!(numa_nodes_in(&root.join("missing")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
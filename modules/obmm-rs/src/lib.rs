@@ -8,6 +8,9 @@
 //!
 //! The crate is organized into several modules:
 //!
+//! - [`accounting`]: Usage statistics and soft export quotas
+//! - [`attributes`]: Cacheability and coherence attribute negotiation
+//! - [`describe`]: Human-readable breakdown of memory descriptors
 //! - [`error`]: Custom error types and result aliases
 //! - [`types`]: Type definitions, constants, and bitflags
 //! - `kernel_abi`: Kernel ABI definitions (ioctl constants and structures)
@@ -15,9 +18,21 @@
 //! - `kernel`: Pure Rust implementation of OBMM kernel interface
 //! - [`export`]: Safe wrappers for memory export operations
 //! - [`import`]: Safe wrappers for memory import operations
+//! - [`layout`]: Alignment helpers and export layout validation
+//! - `mock`: Simulated nodes sharing exports in one process (stub builds)
 //! - [`query`]: Safe wrappers for memory query operations
 //! - [`ownership`]: Safe wrappers for ownership management
 //! - [`handle`]: RAII memory handles for automatic cleanup
+//! - [`mmap`]: Memory mapping of exported and imported regions
+//! - [`pool`]: Chunk allocator on top of one exported region
+//! - [`probe`]: Host capability probe (module, device, permissions)
+//! - [`registry`]: Persistent registry of active exports and imports
+//! - [`ring`]: Single-producer single-consumer ring buffer in shared memory
+//! - [`shared`]: Reference-counted imports shared within a process
+//! - [`trace`]: Per-operation logs tagged with region correlation IDs
+//! - [`transfer`]: Ownership transfer handshake between nodes
+//! - `aio`: Async wrappers for slow operations (`aio` feature)
+//! - `sign`: Descriptor signing and sealed envelopes (`crypto` feature)
 //!
 //! # Feature Flags
 //!
@@ -25,6 +40,10 @@
 //!
 //! - `native` (enabled by default): Uses the pure Rust implementation that directly
 //!   communicates with the OBMM kernel module via ioctl system calls.
+//! - `aio`: Enables the `aio` module with futures that run exports and
+//!   imports on worker threads, usable from any async executor.
+//! - `crypto`: Enables the `sign` module for authenticating descriptors
+//!   exchanged between nodes with HMAC-SHA256.
 //!
 //! When `native` is disabled, stub implementations are used automatically. These
 //! return test data without making actual system calls, which is useful for
@@ -98,12 +117,30 @@
 )]
 
 // Module declarations
+pub mod accounting;
+#[cfg(feature = "aio")]
+pub mod aio;
+pub mod attributes;
+pub mod describe;
 pub mod error;
 pub mod export;
 pub mod handle;
 pub mod import;
+pub mod layout;
+pub mod mmap;
+#[cfg(not(feature = "native"))]
+pub mod mock;
 pub mod ownership;
+pub mod pool;
+pub mod probe;
 pub mod query;
+pub mod registry;
+pub mod ring;
+pub mod shared;
+#[cfg(feature = "crypto")]
+pub mod sign;
+pub mod trace;
+pub mod transfer;
 pub mod types;
 
 // Pure Rust kernel interface modules (native feature)
@@ -124,38 +161,88 @@ pub mod sys;
 ///
 /// This module re-exports commonly used types and functions for convenience.
 pub mod prelude {
-    pub use crate::error::{ObmmError, Result, ToObmmResult};
-    pub use crate::export::{export_useraddr, mem_export, mem_unexport};
-    pub use crate::handle::{ExportedMemory, ImportedMemory};
-    pub use crate::import::{mem_import, mem_unimport, preimport, unpreimport};
+    pub use crate::accounting::{ObmmUsage, Quota, UsageStats};
+    pub use crate::attributes::{Coherence, MemoryAttributes};
+    pub use crate::error::{
+        AttributeError, DescError, LayoutError, ObmmError, OpContext, Result, ToObmmResult,
+    };
+    pub use crate::export::{
+        ByteSize, ExportRequest, StripedExport, UnexportOutcome, export_useraddr, mem_export,
+        mem_export_striped, mem_export_weighted, mem_unexport, unexport_graceful,
+    };
+    pub use crate::handle::{ExportedMemory, ImportedMemory, PreimportedRegion};
+    pub use crate::import::{
+        HonoredPolicy, ImportOptions, ImportOutcome, NumaPolicy, mem_import, mem_import_on_node,
+        mem_unimport, preimport, unpreimport,
+    };
+    pub use crate::layout::{
+        export_align_up, is_export_aligned, numa_node_count, numa_nodes, validate_export_layout,
+    };
+    pub use crate::mmap::MappedRegion;
     pub use crate::ownership::{
-        OwnershipSetter,
+        ObmmDevice, OwnershipSetter,
         prot::{self},
         set_ownership,
     };
-    pub use crate::query::{query_memid_by_pa, query_pa_by_memid};
+    pub use crate::pool::{ObmmPool, PoolAllocation, PoolStrategy};
+    pub use crate::probe::ObmmProbe;
+    pub use crate::query::{
+        RegionInfo, list_exports, list_imports, list_regions, query_importers, query_memid_by_pa,
+        query_pa_by_memid,
+    };
+    pub use crate::registry::{EntryKind, Lease, PeerQuota, Registry, RegistryEntry};
+    pub use crate::ring::RingBuffer;
+    pub use crate::shared::SharedImportedMemory;
+    #[cfg(feature = "crypto")]
+    pub use crate::sign::{SealedDesc, Signature};
     pub use crate::sys;
     pub use crate::types::{
-        ImportResult, MAX_NUMA_NODES, MemId, OBMM_INVALID_MEMID, OBMM_MAX_LOCAL_NUMA_NODES,
-        ObmmExportFlags, ObmmMemDesc, ObmmPreimportFlags, ObmmPreimportInfo, ObmmUnexportFlags,
-        QueryResult, UbPrivData,
+        DESC_BINARY_LEN, DESC_DIR, DescEnvelope, ImportResult, LocalCaps, MAX_NUMA_NODES, MemId,
+        OBMM_INVALID_MEMID, OBMM_MAX_LOCAL_NUMA_NODES, ObmmExportFlags, ObmmImportFlags,
+        ObmmMemDesc, ObmmPreimportFlags, ObmmPreimportInfo, ObmmUnexportFlags, QueryResult,
+        UbPrivData, desc_file_path,
     };
 }
 
 // Backward compatibility: re-export common items at crate root
-pub use error::{ObmmError, Result, ToObmmResult};
-pub use export::{export_useraddr, mem_export, mem_unexport};
-pub use import::{mem_import, mem_unimport, preimport, unpreimport};
+pub use accounting::{ObmmUsage, Quota, UsageStats};
+pub use attributes::{Coherence, MemoryAttributes};
+pub use error::{
+    AttributeError, DescError, LayoutError, ObmmError, OpContext, Result, ToObmmResult,
+};
+pub use export::{
+    ByteSize, ExportRequest, StripedExport, UnexportOutcome, export_useraddr, mem_export,
+    mem_export_striped, mem_export_weighted, mem_unexport, unexport_graceful,
+};
+pub use import::{
+    HonoredPolicy, ImportOptions, ImportOutcome, NumaPolicy, mem_import, mem_import_on_node,
+    mem_unimport, preimport, unpreimport,
+};
+pub use layout::{
+    export_align_up, is_export_aligned, numa_node_count, numa_nodes, validate_export_layout,
+};
+pub use mmap::MappedRegion;
 pub use ownership::{
-    OwnershipSetter,
+    ObmmDevice, OwnershipSetter,
     prot::{self},
     set_ownership,
 };
-pub use query::{query_memid_by_pa, query_pa_by_memid};
+pub use pool::{ObmmPool, PoolAllocation, PoolStrategy};
+pub use probe::ObmmProbe;
+pub use query::{
+    RegionInfo, list_exports, list_imports, list_regions, query_importers, query_memid_by_pa,
+    query_pa_by_memid,
+};
+pub use registry::{EntryKind, Lease, PeerQuota, Registry, RegistryEntry};
+pub use ring::RingBuffer;
+pub use shared::SharedImportedMemory;
+#[cfg(feature = "crypto")]
+pub use sign::{SealedDesc, Signature};
 pub use types::{
-    ImportResult, MAX_NUMA_NODES, MemId, OBMM_INVALID_MEMID, OBMM_MAX_LOCAL_NUMA_NODES,
-    ObmmExportFlags, ObmmMemDesc, ObmmPreimportFlags, ObmmPreimportInfo, ObmmUnexportFlags,
-    QueryResult, UbPrivData,
+    DESC_BINARY_LEN, DESC_DIR, DescEnvelope, ImportResult, LocalCaps, MAX_NUMA_NODES, MemId,
+    OBMM_INVALID_MEMID, OBMM_MAX_LOCAL_NUMA_NODES, ObmmExportFlags, ObmmImportFlags, ObmmMemDesc,
+    ObmmPreimportFlags, ObmmPreimportInfo, ObmmUnexportFlags, QueryResult, UbPrivData,
+    desc_file_path,
 };
 
 #[cfg(test)]
@@ -192,6 +279,213 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_desc_validate() {
+        let mut desc = ObmmMemDesc::<UbPrivData> {
+            addr: 0xffff_fc00_0000,
+            length: 1024 * 1024 * 2,
+            seid: [1; 16],
+            deid: [0; 16],
+            tokenid: 0,
+            scna: 0x10,
+            dcna: 0,
+            priv_len: 2,
+            priv_data: UbPrivData::CACHEABLE,
+        };
+        assert_eq!(desc.validate(), Ok(()));
+
+        desc.length += 1;
+        assert_eq!(
+            desc.validate(),
+            Err(ObmmError::InvalidDescriptor(DescError::Unaligned {
+                align: 4096
+            }))
+        );
+        desc.length -= 1;
+
+        desc.priv_len = 8;
+        assert_eq!(
+            desc.validate(),
+            Err(ObmmError::InvalidDescriptor(DescError::PrivLenMismatch {
+                expected: 2,
+                found: 8
+            }))
+        );
+        desc.priv_len = 0;
+        assert!(matches!(
+            desc.validate(),
+            Err(ObmmError::InvalidDescriptor(DescError::InvalidPrivData(_)))
+        ));
+        desc.priv_len = 2;
+
+        desc.scna = 0x0100_0000;
+        assert_eq!(
+            desc.validate(),
+            Err(ObmmError::InvalidDescriptor(DescError::InvalidCna(
+                0x0100_0000
+            )))
+        );
+        desc.scna = 0x10;
+
+        desc.seid = [0; 16];
+        assert_eq!(
+            desc.validate(),
+            Err(ObmmError::InvalidDescriptor(DescError::InvalidEid))
+        );
+    }
+
+    #[test]
+    fn test_desc_compatibility() {
+        let mut desc = ObmmMemDesc::<UbPrivData> {
+            addr: 0,
+            length: 1024 * 1024 * 4,
+            seid: [1; 16],
+            deid: [2; 16],
+            tokenid: 0,
+            scna: 0x10,
+            dcna: 0x20,
+            priv_len: 2,
+            priv_data: UbPrivData::CACHEABLE,
+        };
+        let caps = LocalCaps::new([2; 16], 0x20);
+        assert_eq!(desc.is_compatible_with(&caps), Ok(()));
+
+        let other = LocalCaps::new([3; 16], 0x20);
+        assert_eq!(
+            desc.is_compatible_with(&other),
+            Err(ObmmError::InvalidDescriptor(DescError::EidMismatch))
+        );
+        desc.deid = [0; 16];
+        assert_eq!(desc.is_compatible_with(&other), Ok(()));
+
+        let small = caps.max_length(1024 * 1024);
+        assert!(matches!(
+            desc.is_compatible_with(&small),
+            Err(ObmmError::InvalidDescriptor(DescError::TooLarge { .. }))
+        ));
+
+        let uncached = caps.priv_data(UbPrivData::OCHIP);
+        assert_eq!(
+            desc.is_compatible_with(&uncached),
+            Err(ObmmError::InvalidDescriptor(
+                DescError::UnsupportedPrivData(UbPrivData::CACHEABLE.bits())
+            ))
+        );
+    }
+
+    #[test]
+    fn test_desc_binary_roundtrip() {
+        let desc = ObmmMemDesc::<UbPrivData> {
+            addr: 0xffff_fc00_0000,
+            length: 1024 * 1024 * 2,
+            seid: [1; 16],
+            deid: [2; 16],
+            tokenid: 7,
+            scna: 0x10,
+            dcna: 0x20,
+            priv_len: 2,
+            priv_data: UbPrivData::OCHIP,
+        };
+        let mut bytes = desc.to_bytes();
+        assert_eq!(bytes.len(), DESC_BINARY_LEN);
+        assert!(bytes.len() < desc.to_json().unwrap().len());
+
+        bytes.extend_from_slice(b"trailing");
+        let decoded = ObmmMemDesc::<UbPrivData>::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.addr, desc.addr);
+        assert_eq!(decoded.seid, desc.seid);
+        assert_eq!(decoded.deid, desc.deid);
+        assert_eq!(decoded.tokenid, 7);
+        assert_eq!(decoded.dcna, 0x20);
+        assert_eq!(decoded.priv_data, UbPrivData::OCHIP);
+
+        assert!(ObmmMemDesc::<UbPrivData>::from_bytes(&bytes[..DESC_BINARY_LEN - 1]).is_err());
+        bytes[0] = b'X';
+        assert!(ObmmMemDesc::<UbPrivData>::from_bytes(&bytes).is_err());
+        bytes[0] = b'O';
+        bytes[4] = 9;
+        assert_eq!(
+            ObmmMemDesc::<UbPrivData>::from_bytes(&bytes).err(),
+            Some(ObmmError::VersionMismatch {
+                found: 9,
+                supported: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_desc_json_versioning() {
+        let desc = ObmmMemDesc::<UbPrivData> {
+            addr: 0xffff_fc00_0000,
+            length: 1024 * 1024 * 2,
+            tokenid: 7,
+            ..Default::default()
+        };
+        let value: serde_json::Value = serde_json::from_str(&desc.to_json().unwrap()).unwrap();
+        assert_eq!(value["magic"], "OBMD");
+        assert_eq!(value["version"], 1);
+        assert_eq!(value["length"], 64);
+        assert_eq!(value["payload"]["tokenid"], 7);
+
+        // Descriptors written before the envelope are still read
+        let legacy = serde_json::to_value(&desc).unwrap();
+        let read = ObmmMemDesc::<UbPrivData>::from_json_value(legacy).unwrap();
+        assert_eq!(read.addr, desc.addr);
+        assert_eq!(read.tokenid, 7);
+
+        let mut newer = value.clone();
+        newer["version"] = 2.into();
+        assert_eq!(
+            ObmmMemDesc::<UbPrivData>::from_json_value(newer).err(),
+            Some(ObmmError::VersionMismatch {
+                found: 2,
+                supported: 1
+            })
+        );
+        let message = ObmmMemDesc::<UbPrivData>::from_json(
+            &value.to_string().replace("\"version\":1", "\"version\":3"),
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(message.contains("version 3 is not supported"), "{message}");
+
+        let mut foreign = value.clone();
+        foreign["magic"] = "XXXX".into();
+        assert!(matches!(
+            ObmmMemDesc::<UbPrivData>::from_json_value(foreign),
+            Err(ObmmError::SerializationError(_))
+        ));
+        let mut short = value;
+        short["length"] = 32.into();
+        assert!(ObmmMemDesc::<UbPrivData>::from_json_value(short).is_err());
+    }
+
+    #[test]
+    fn test_error_from_errno() {
+        let ctx = OpContext::new("unexport", Some(5));
+        assert_eq!(
+            ObmmError::from_errno(libc::EBUSY, ctx),
+            ObmmError::Busy(ctx)
+        );
+        assert_eq!(
+            ObmmError::from_errno(libc::ENOMEM, ctx).raw_os_error(),
+            Some(libc::ENOMEM)
+        );
+        assert!(matches!(
+            ObmmError::from_errno(libc::EACCES, ctx),
+            ObmmError::PermissionDenied(_)
+        ));
+        assert_eq!(
+            ObmmError::from_errno(libc::EINVAL, ctx).to_string(),
+            "Invalid argument to unexport of memid 5"
+        );
+
+        let other = ObmmError::from_errno(libc::EEXIST, OpContext::new("export", None));
+        assert_eq!(other.raw_os_error(), Some(libc::EEXIST));
+        assert!(other.to_string().starts_with("export failed: "));
+        assert_eq!(ObmmError::InvalidMemId.raw_os_error(), None);
+    }
+
     #[test]
     fn test_import_roundtrip() {
         let desc = ObmmMemDesc::<UbPrivData> {
@@ -270,6 +564,244 @@ mod tests {
         assert_eq!(desc.priv_data, deserialized.priv_data);
     }
 
+    #[test]
+    fn test_json_path_roundtrip() {
+        let desc = ObmmMemDesc::<UbPrivData> {
+            addr: 0xffff_fc00_0000,
+            length: 1024 * 1024 * 2,
+            tokenid: 7,
+            ..Default::default()
+        };
+
+        let dir = std::env::temp_dir().join(format!("obmm-rs-test-{}", std::process::id()));
+        let path = dir.join("nested").join("desc.json");
+        desc.to_json_path(&path).expect("write descriptor");
+        let read: ObmmMemDesc<UbPrivData> =
+            ObmmMemDesc::from_json_path(&path).expect("read descriptor");
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(read.addr, desc.addr);
+        assert_eq!(read.length, desc.length);
+        assert_eq!(read.tokenid, desc.tokenid);
+        assert_eq!(
+            desc_file_path(5),
+            std::path::Path::new(DESC_DIR).join("memdesc_5.json")
+        );
+    }
+
+    #[test]
+    fn test_json_without_default_priv_data() {
+        /// Privilege data without a meaningful default
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Tagged(String);
+
+        let desc = ObmmMemDesc {
+            addr: 0xffff_fc00_0000,
+            length: 1024 * 1024 * 2,
+            seid: [0; 16],
+            deid: [0; 16],
+            tokenid: 9,
+            scna: 0,
+            dcna: 0,
+            priv_len: 0,
+            priv_data: Tagged("remote".to_string()),
+        };
+        let json = desc.to_json().expect("serialize descriptor");
+        let read = ObmmMemDesc::<Tagged>::from_json(&json).expect("deserialize descriptor");
+        assert_eq!(read.tokenid, 9);
+        assert_eq!(read.priv_data, desc.priv_data);
+    }
+
+    #[test]
+    fn test_registry_record_and_gc() {
+        let dir = std::env::temp_dir().join(format!("obmm-rs-registry-{}", std::process::id()));
+        let registry = Registry::open(&dir).expect("open registry");
+
+        let desc = ObmmMemDesc::<UbPrivData> {
+            length: 1024 * 1024 * 4,
+            tokenid: 3,
+            ..Default::default()
+        };
+        let path = registry
+            .record_export(11, ObmmExportFlags::ALLOWMMAP, &desc)
+            .expect("record export");
+        assert_eq!(path, registry.desc_path(11));
+        registry
+            .record_import(12, 1024 * 1024 * 8, ObmmExportFlags::ALLOWMMAP, 1)
+            .expect("record import");
+
+        let exports = registry.exports().expect("list exports");
+        assert_eq!(exports.len(), 1);
+        assert_eq!(exports[0].size, desc.length);
+        assert_eq!(exports[0].owner_pid, std::process::id());
+        let imports = registry.imports().expect("list imports");
+        assert_eq!(imports[0].numa_node, Some(1));
+
+        let loaded: ObmmMemDesc<UbPrivData> = registry.load_desc(11).expect("load descriptor");
+        assert_eq!(loaded.tokenid, 3);
+
+        // Our own entries are alive, so the default collection keeps them
+        assert!(registry.gc().expect("gc").is_empty());
+        let removed = registry
+            .gc_with(|e| e.kind == EntryKind::Export)
+            .expect("gc exports");
+        assert_eq!(removed.len(), 1);
+        assert!(!registry.desc_path(11).exists());
+
+        let removed = registry.remove(EntryKind::Import, 12).expect("remove");
+        assert_eq!(removed.map(|e| e.mem_id), Some(12));
+        assert!(registry.entries().expect("list").is_empty());
+        assert!(
+            registry
+                .remove(EntryKind::Import, 12)
+                .expect("remove")
+                .is_none()
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_registry_peer_quota() {
+        let dir = std::env::temp_dir().join(format!("obmm-rs-peers-{}", std::process::id()));
+        let registry = Registry::open(&dir).expect("open registry");
+        let (peer, other) = ([1; 16], [2; 16]);
+
+        let desc = ObmmMemDesc::<UbPrivData> {
+            length: 4096,
+            deid: peer,
+            ..Default::default()
+        };
+        registry
+            .record_export(21, ObmmExportFlags::ALLOWMMAP, &desc)
+            .expect("record export");
+        registry
+            .set_peer_quota(peer, Some(12288))
+            .expect("set quota");
+        assert_eq!(registry.peer_usage(&peer), Ok(4096));
+
+        registry.grant_to_peer(22, peer, 8192).expect("grant");
+        registry.grant_to_peer(22, peer, 8192).expect("re-grant");
+        assert_eq!(registry.peer_usage(&peer), Ok(12288));
+        assert_eq!(
+            registry.grant_to_peer(23, peer, 1),
+            Err(ObmmError::PeerQuotaExceeded {
+                requested: 12289,
+                limit: 12288,
+            })
+        );
+        assert!(registry.get(EntryKind::Export, 23).expect("get").is_none());
+
+        // Peers without a quota are not limited
+        registry.grant_to_peer(23, other, 1 << 40).expect("grant");
+        // Moving an export to another peer releases its bytes
+        registry.grant_to_peer(21, other, 4096).expect("move");
+        assert_eq!(registry.peer_usage(&peer), Ok(8192));
+        // Released exports stay registered but free the peer's bytes
+        registry.release_peer(22).expect("release");
+        assert_eq!(registry.peer_usage(&peer), Ok(0));
+        assert!(registry.get(EntryKind::Export, 22).expect("get").is_some());
+        registry.grant_to_peer(22, peer, 8192).expect("grant again");
+        let entry = registry.get(EntryKind::Export, 21).expect("get");
+        assert_eq!(
+            entry.map(|e| e.flags),
+            Some(ObmmExportFlags::ALLOWMMAP.bits())
+        );
+
+        registry.set_peer_quota(peer, None).expect("clear quota");
+        assert!(registry.peer_quotas().expect("list quotas").is_empty());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_registry_leases() {
+        use std::time::Duration;
+
+        let dir = std::env::temp_dir().join(format!("obmm-rs-leases-{}", std::process::id()));
+        let registry = Registry::open(&dir).expect("open registry");
+        let desc = ObmmMemDesc::<UbPrivData>::default();
+        for mem_id in [31, 32, 33] {
+            registry
+                .record_export(mem_id, ObmmExportFlags::ALLOWMMAP, &desc)
+                .expect("record export");
+        }
+
+        let live = Lease::new(Duration::from_secs(60), Duration::from_secs(30));
+        registry.set_lease(31, Some(live)).expect("set lease");
+        // Zero TTL and grace: expired and past grace right away
+        let dead = Lease::new(Duration::ZERO, Duration::ZERO);
+        registry.set_lease(32, Some(dead)).expect("set lease");
+        assert_eq!(
+            registry.set_lease(34, Some(live)),
+            Err(ObmmError::InvalidMemId)
+        );
+
+        let renewed = registry.renew_lease(31).expect("renew");
+        assert!(renewed.expires_at >= live.expires_at);
+        assert!(!renewed.is_expired(renewed.expires_at - 1));
+        assert!(renewed.is_expired(renewed.expires_at));
+        assert!(!renewed.is_reclaimable(renewed.expires_at + 29));
+        assert!(renewed.remaining() <= Duration::from_secs(60));
+
+        assert_eq!(registry.renew_lease(32), Err(ObmmError::LeaseNotFound(32)));
+        assert_eq!(registry.renew_lease(33), Err(ObmmError::LeaseNotFound(33)));
+        let expired = registry.expired_leases().expect("expired leases");
+        assert_eq!(expired.iter().map(|e| e.mem_id).collect::<Vec<_>>(), [32]);
+
+        registry.set_lease(32, None).expect("clear lease");
+        assert!(
+            registry
+                .expired_leases()
+                .expect("expired leases")
+                .is_empty()
+        );
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_export_request_validation() {
+        let request = ExportRequest::new()
+            .numa(0, 64.mib())
+            .numa(2, 128.mib())
+            .numa(0, 32.mib())
+            .flags(ObmmExportFlags::ALLOWMMAP);
+        let lengths = request.lengths().expect("valid request");
+        assert_eq!(lengths.len(), MAX_NUMA_NODES);
+        assert_eq!(lengths[0], 32.mib());
+        assert_eq!(lengths[2], 128.mib());
+        assert_eq!(request.total_len(), 160.mib());
+
+        match request.export::<UbPrivData>() {
+            Ok((_, desc)) => assert_eq!(desc.length, 160 * 1024 * 1024),
+            Err(e) => println!("Export failed (expected on non-OBMM system): {e}"),
+        }
+
+        assert!(ExportRequest::new().lengths().is_err());
+        assert!(
+            ExportRequest::new()
+                .numa(MAX_NUMA_NODES, 2.mib())
+                .lengths()
+                .is_err()
+        );
+        assert!(ExportRequest::new().numa(0, 4.kib()).lengths().is_err());
+        assert!(
+            ExportRequest::new()
+                .numa(0, 4.kib())
+                .align(4.kib())
+                .lengths()
+                .is_ok()
+        );
+        assert!(
+            ExportRequest::new()
+                .numa(0, 3.mib())
+                .align(3.mib())
+                .lengths()
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_priv_data_flags() {
         let priv_data = UbPrivData::OCHIP | UbPrivData::CACHEABLE;
@@ -343,7 +875,11 @@ mod tests {
 
     #[test]
     fn test_ownership_builder_api() {
-        let result = OwnershipSetter::new(3)
+        let Ok(mut device) = ObmmDevice::open(1) else {
+            println!("Device open failed (expected on non-OBMM system)");
+            return;
+        };
+        let result = OwnershipSetter::new(&mut device)
             .range(0xffff_fc00_0000, 0xffff_fd00_0000)
             .read_write()
             .apply();
@@ -0,0 +1,363 @@
+//! Memory mapping of exported and imported OBMM memory
+//!
+//! Every exported or imported region is backed by a shared memory device
+//! (`/dev/obmm_shmdev<memid>`). This module maps that device into the
+//! address space of the calling process and unmaps it on drop.
+//!
+//! Mappings are read-write unless the region was imported read-only (see
+//! [`ImportOptions::read_only`](crate::import::ImportOptions::read_only)),
+//! in which case they are mapped with `PROT_READ` only and the mutable
+//! accessors refuse to hand out the memory.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::handle::ExportedMemory;
+//! use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+//!
+//! let lengths = vec![1024 * 1024 * 2]; // 2MB on NUMA node 0
+//! let memory = ExportedMemory::<UbPrivData>::export(&lengths, ObmmExportFlags::ALLOWMMAP)
+//!     .expect("Export failed");
+//!
+//! let mut region = memory.map().expect("Map failed");
+//! region.as_mut_slice()[0] = 0x5a;
+//! assert_eq!(region.as_slice()[0], 0x5a);
+//! // The mapping is removed when `region` goes out of scope
+//! ```
+
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::ptr::NonNull;
+
+use crate::error::{ObmmError, Result};
+use crate::types::MemId;
+
+/// Path prefix of the per-region shared memory devices
+pub const SHMDEV_PREFIX: &str = "/dev/obmm_shmdev";
+
+/// Get the shared memory device path of a memory ID
+///
+/// # Returns
+/// `/dev/obmm_shmdev<mem_id>`
+#[inline]
+#[must_use]
+pub fn shmdev_path(mem_id: MemId) -> PathBuf {
+    PathBuf::from(format!("{SHMDEV_PREFIX}{mem_id}"))
+}
+
+/// A mapping of OBMM memory into the current process
+///
+/// The mapping borrows the handle it was created from, so it cannot
+/// outlive the exported or imported memory. It is unmapped on drop.
+#[derive(Debug)]
+pub struct MappedRegion<'a> {
+    /// Memory ID of the mapped region
+    mem_id: MemId,
+    /// Offset of the mapping within the region
+    offset: u64,
+    /// Start of the mapping
+    ptr: NonNull<u8>,
+    /// Length of the mapping in bytes
+    len: usize,
+    /// Whether the mapping allows writes
+    writable: bool,
+    /// Ties the mapping to the owning handle
+    _owner: PhantomData<&'a ()>,
+}
+
+// SAFETY: the mapping is plain shared memory owned by this value
+unsafe impl Send for MappedRegion<'_> {}
+// SAFETY: shared access only hands out `&[u8]`
+unsafe impl Sync for MappedRegion<'_> {}
+
+impl MappedRegion<'_> {
+    /// Map `len` bytes of a region starting at `offset`
+    ///
+    /// # Arguments
+    /// * `mem_id` - Memory ID of the exported or imported region
+    /// * `region_len` - Total length of the region in bytes
+    /// * `offset` - Offset of the mapping (must be page aligned)
+    /// * `len` - Length of the mapping in bytes
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the range is empty, unaligned or
+    /// exceeds the region, and `ObmmError::MapFailed` if the mapping fails
+    pub(crate) fn map(mem_id: MemId, region_len: u64, offset: u64, len: usize) -> Result<Self> {
+        Self::map_with(mem_id, region_len, offset, len, true)
+    }
+
+    /// Map `len` bytes of a region starting at `offset` without write access
+    ///
+    /// # Errors
+    /// Same as [`map`](Self::map)
+    pub(crate) fn map_read_only(
+        mem_id: MemId,
+        region_len: u64,
+        offset: u64,
+        len: usize,
+    ) -> Result<Self> {
+        Self::map_with(mem_id, region_len, offset, len, false)
+    }
+
+    /// Map a range of a region, with or without write access
+    fn map_with(
+        mem_id: MemId,
+        region_len: u64,
+        offset: u64,
+        len: usize,
+        writable: bool,
+    ) -> Result<Self> {
+        if len == 0 {
+            return Err(ObmmError::InvalidInput("mapping length must be non-zero"));
+        }
+        if !offset.is_multiple_of(page_size()) {
+            return Err(ObmmError::InvalidInput(
+                "mapping offset must be page aligned",
+            ));
+        }
+        if offset
+            .checked_add(len as u64)
+            .is_none_or(|end| end > region_len)
+        {
+            return Err(ObmmError::InvalidInput("mapping exceeds the memory region"));
+        }
+
+        let ptr = map_pages(mem_id, offset, len, writable)?;
+        Ok(Self {
+            mem_id,
+            offset,
+            ptr,
+            len,
+            writable,
+            _owner: PhantomData,
+        })
+    }
+
+    /// Get the memory ID of the mapped region
+    #[inline]
+    #[must_use]
+    pub const fn mem_id(&self) -> MemId {
+        self.mem_id
+    }
+
+    /// Get the offset of the mapping within the region
+    #[inline]
+    #[must_use]
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Get the length of the mapping in bytes
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the mapping is empty (never true for a live mapping)
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Check whether the mapping allows writes
+    #[inline]
+    #[must_use]
+    pub const fn is_writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Get a raw pointer to the start of the mapping
+    ///
+    /// Writing through the pointer faults if the mapping is read-only.
+    #[inline]
+    #[must_use]
+    pub const fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// View the whole mapping as a byte slice
+    #[inline]
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: `ptr` is valid for `len` bytes until the mapping is dropped
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// View the whole mapping as a mutable byte slice
+    ///
+    /// # Panics
+    /// Panics if the mapping is read-only; use [`get_mut`](Self::get_mut)
+    /// to handle that case
+    #[inline]
+    #[must_use]
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        assert!(
+            self.writable,
+            "mapping of memid {} is read-only",
+            self.mem_id
+        );
+        // SAFETY: `ptr` is valid for `len` bytes and `&mut self` is exclusive
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+
+    /// View part of the mapping
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if `range` is out of bounds
+    #[inline]
+    pub fn get(&self, range: Range<usize>) -> Result<&[u8]> {
+        self.as_slice()
+            .get(range)
+            .ok_or(ObmmError::InvalidInput("range exceeds the mapping"))
+    }
+
+    /// Mutably view part of the mapping
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the mapping is read-only or
+    /// `range` is out of bounds
+    #[inline]
+    pub fn get_mut(&mut self, range: Range<usize>) -> Result<&mut [u8]> {
+        if !self.writable {
+            return Err(ObmmError::InvalidInput("mapping is read-only"));
+        }
+        self.as_mut_slice()
+            .get_mut(range)
+            .ok_or(ObmmError::InvalidInput("range exceeds the mapping"))
+    }
+}
+
+impl Drop for MappedRegion<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created by `map_pages` with this length
+        let _result = unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+    }
+}
+
+/// Get the system page size
+fn page_size() -> u64 {
+    // SAFETY: sysconf has no preconditions
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    u64::try_from(size).unwrap_or(4096)
+}
+
+/// Convert an `mmap` result into a pointer
+fn mapped_ptr(addr: *mut libc::c_void, mem_id: MemId) -> Result<NonNull<u8>> {
+    if addr == libc::MAP_FAILED {
+        return Err(ObmmError::MapFailed(format!(
+            "mmap of memid {mem_id} failed: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+    NonNull::new(addr.cast()).ok_or_else(|| ObmmError::MapFailed("mmap returned null".to_string()))
+}
+
+/// Get the `mmap` protection of a mapping
+const fn protection(writable: bool) -> libc::c_int {
+    if writable {
+        libc::PROT_READ | libc::PROT_WRITE
+    } else {
+        libc::PROT_READ
+    }
+}
+
+/// Map pages of a region (hooked implementation for testing)
+///
+/// Uses anonymous memory so that handles can be mapped without OBMM.
+#[cfg(not(feature = "native"))]
+fn map_pages(mem_id: MemId, _offset: u64, len: usize, writable: bool) -> Result<NonNull<u8>> {
+    // SAFETY: anonymous mapping with no address hint
+    let addr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            protection(writable),
+            libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+            -1,
+            0,
+        )
+    };
+    mapped_ptr(addr, mem_id)
+}
+
+/// Map pages of a region from its shared memory device
+#[cfg(feature = "native")]
+fn map_pages(mem_id: MemId, offset: u64, len: usize, writable: bool) -> Result<NonNull<u8>> {
+    use std::os::fd::AsRawFd;
+
+    let path = shmdev_path(mem_id);
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(writable)
+        .open(&path)
+        .map_err(|e| ObmmError::MapFailed(format!("Failed to open {}: {e}", path.display())))?;
+    let offset = libc::off_t::try_from(offset)
+        .map_err(|_| ObmmError::InvalidInput("mapping offset out of range"))?;
+
+    // SAFETY: the file descriptor is valid for the duration of the call;
+    // the mapping stays valid after the file is closed
+    let addr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            len,
+            protection(writable),
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            offset,
+        )
+    };
+    mapped_ptr(addr, mem_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shmdev_path() {
+        assert_eq!(shmdev_path(7), PathBuf::from("/dev/obmm_shmdev7"));
+    }
+
+    #[test]
+    fn test_map_range_checks() {
+        let page = page_size();
+        assert!(MappedRegion::map(1, page, 0, 0).is_err());
+        assert!(MappedRegion::map(1, page * 2, 1, 16).is_err());
+        assert!(MappedRegion::map(1, page, 0, page as usize + 1).is_err());
+        assert!(MappedRegion::map(1, page * 2, page, page as usize + 1).is_err());
+    }
+
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn test_mapped_region_access() {
+        let mut region = MappedRegion::map(1, 8192, 0, 8192).expect("map");
+        assert_eq!(region.len(), 8192);
+        region
+            .get_mut(4096..4100)
+            .expect("in bounds")
+            .copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(region.get(4096..4100).expect("in bounds"), &[1, 2, 3, 4]);
+        assert!(region.get(8190..8200).is_err());
+    }
+
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn test_read_only_mapping() {
+        let mut region = MappedRegion::map_read_only(1, 8192, 0, 8192).expect("map");
+        assert!(!region.is_writable());
+        assert_eq!(region.get(0..4).expect("in bounds"), &[0, 0, 0, 0]);
+        assert!(matches!(
+            region.get_mut(0..4),
+            Err(ObmmError::InvalidInput("mapping is read-only"))
+        ));
+        assert!(
+            MappedRegion::map(1, 8192, 0, 8192)
+                .expect("map")
+                .is_writable()
+        );
+    }
+}
@@ -0,0 +1,515 @@
+//! In-process simulation of OBMM nodes for stub builds
+//!
+//! Without the `native` feature every OBMM operation is a stateless stub.
+//! A [`MockFabric`] backs those stubs with a shared table of exports and
+//! imports, so that several simulated nodes can exchange memory within one
+//! process, deterministically:
+//!
+//! - Exports get distinct memory IDs, starting at 1, and a descriptor with
+//!   the exporting node in `seid` and a node-specific address
+//! - An import must match a live export by `seid` and address, and counts
+//!   as one importer of it
+//! - Unexporting a region that is still imported fails with
+//!   `ObmmError::Busy` unless `ObmmUnexportFlags::FORCE` is given
+//! - Ownership changes must fall within a region of the calling node
+//!
+//! Operations are routed to the node entered on the calling thread with
+//! [`MockNode::enter`]. Threads that entered no node, such as the workers
+//! of the `aio` module, keep the stateless stub behaviour.
+//!
+//! # Example
+//!
+//! ```
+//! use obmm_rs::handle::{ExportedMemory, ImportedMemory};
+//! use obmm_rs::import::ImportOptions;
+//! use obmm_rs::mock::MockFabric;
+//! use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+//!
+//! # fn main() -> obmm_rs::Result<()> {
+//! let fabric = MockFabric::new();
+//! let (source, destination) = (fabric.node(1), fabric.node(2));
+//!
+//! let memory = {
+//!     let _node = source.enter();
+//!     ExportedMemory::<UbPrivData>::export(&[1024 * 1024 * 2], ObmmExportFlags::ALLOWMMAP)?
+//! };
+//! let imported = {
+//!     let _node = destination.enter();
+//!     ImportedMemory::import_with(memory.descriptor(), &ImportOptions::new())?
+//! };
+//! assert_eq!(fabric.importers(memory.mem_id()), Some(1));
+//! # drop(imported);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::error::{ObmmError, OpContext, Result};
+use crate::types::{MemId, ObmmMemDesc, ObmmUnexportFlags, UbPrivData};
+
+/// Address of the first simulated export of a node
+const EXPORT_BASE: u64 = 0xffff_fc00_0000;
+
+/// Address space reserved for each simulated export
+const EXPORT_STRIDE_SHIFT: u32 = 30;
+
+thread_local! {
+    /// Node entered on this thread
+    static CURRENT: RefCell<Option<MockNode>> = const { RefCell::new(None) };
+}
+
+/// Exported region on a simulated node
+#[derive(Debug, Clone, Copy)]
+struct MockExport {
+    /// Entity ID of the exporting node
+    node: [u8; 16],
+    /// Address in the descriptor
+    addr: u64,
+    /// Length in bytes
+    length: u64,
+    /// Nodes currently importing the region
+    importers: u32,
+}
+
+/// Imported region on a simulated node
+#[derive(Debug, Clone, Copy)]
+struct MockImport {
+    /// Entity ID of the importing node
+    node: [u8; 16],
+    /// Memory ID of the export it maps
+    export: MemId,
+}
+
+/// Ownership change made by a simulated node
+#[derive(Debug, Clone, Copy)]
+struct MockOwnership {
+    /// Entity ID of the node
+    node: [u8; 16],
+    /// Start virtual address
+    start: u64,
+    /// End virtual address
+    end: u64,
+    /// Protection bits
+    prot: i32,
+}
+
+/// Shared state of a fabric
+#[derive(Debug, Default)]
+struct FabricState {
+    /// Last memory ID handed out
+    last_mem_id: MemId,
+    /// Live exports
+    exports: BTreeMap<MemId, MockExport>,
+    /// Live imports
+    imports: BTreeMap<MemId, MockImport>,
+    /// Destination stamped into the descriptors of each node's exports
+    destinations: BTreeMap<[u8; 16], [u8; 16]>,
+    /// Ownership changes, oldest first
+    ownership: Vec<MockOwnership>,
+}
+
+impl FabricState {
+    /// Hand out the next memory ID
+    fn next_mem_id(&mut self) -> MemId {
+        self.last_mem_id = self.last_mem_id.wrapping_add(1);
+        self.last_mem_id
+    }
+
+    /// Check whether `start..end` lies in a region of `node`
+    fn owns_range(&self, node: [u8; 16], start: u64, end: u64) -> bool {
+        let within = |export: &MockExport| {
+            start >= export.addr && end <= export.addr.saturating_add(export.length)
+        };
+        self.exports.values().filter(|e| e.node == node).any(within)
+            || self
+                .imports
+                .values()
+                .filter(|i| i.node == node)
+                .filter_map(|i| self.exports.get(&i.export))
+                .any(within)
+    }
+}
+
+/// Set of simulated nodes sharing one export table
+///
+/// Clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct MockFabric {
+    /// Shared state
+    state: Arc<Mutex<FabricState>>,
+}
+
+impl MockFabric {
+    /// Create an empty fabric
+    #[inline]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a node of the fabric
+    ///
+    /// # Arguments
+    /// * `id` - Node number, used as the last byte of its entity ID
+    #[inline]
+    #[must_use]
+    pub fn node(&self, id: u8) -> MockNode {
+        let mut eid = [0; 16];
+        eid[15] = id;
+        MockNode {
+            fabric: self.clone(),
+            eid,
+        }
+    }
+
+    /// Get the number of importers of an export
+    ///
+    /// # Returns
+    /// The importer count, or `None` if no node exports `mem_id`
+    #[inline]
+    #[must_use]
+    pub fn importers(&self, mem_id: MemId) -> Option<u32> {
+        self.lock().exports.get(&mem_id).map(|e| e.importers)
+    }
+
+    /// Lock the shared state
+    fn lock(&self) -> MutexGuard<'_, FabricState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Simulated node of a [`MockFabric`]
+#[derive(Debug, Clone)]
+pub struct MockNode {
+    /// Fabric the node belongs to
+    fabric: MockFabric,
+    /// Entity ID of the node
+    eid: [u8; 16],
+}
+
+impl MockNode {
+    /// Get the entity ID of the node
+    #[inline]
+    #[must_use]
+    pub const fn eid(&self) -> [u8; 16] {
+        self.eid
+    }
+
+    /// Route OBMM operations of the calling thread to this node
+    ///
+    /// # Returns
+    /// A guard restoring the previously entered node when dropped
+    #[inline]
+    #[must_use]
+    pub fn enter(&self) -> NodeGuard {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        NodeGuard {
+            previous,
+            _thread: PhantomData,
+        }
+    }
+
+    /// Set the destination stamped into the `deid` of later exports
+    ///
+    /// Exports name no destination by default.
+    #[inline]
+    pub fn set_destination(&self, deid: Option<[u8; 16]>) {
+        let mut state = self.fabric.lock();
+        match deid {
+            Some(deid) => state.destinations.insert(self.eid, deid),
+            None => state.destinations.remove(&self.eid),
+        };
+    }
+
+    /// List the memory IDs exported by this node
+    #[inline]
+    #[must_use]
+    pub fn exports(&self) -> Vec<MemId> {
+        let state = self.fabric.lock();
+        state
+            .exports
+            .iter()
+            .filter(|(_, e)| e.node == self.eid)
+            .map(|(mem_id, _)| *mem_id)
+            .collect()
+    }
+
+    /// List the memory IDs imported by this node
+    #[inline]
+    #[must_use]
+    pub fn imports(&self) -> Vec<MemId> {
+        let state = self.fabric.lock();
+        state
+            .imports
+            .iter()
+            .filter(|(_, i)| i.node == self.eid)
+            .map(|(mem_id, _)| *mem_id)
+            .collect()
+    }
+
+    /// Get the export an import of this node maps
+    ///
+    /// # Returns
+    /// The memory ID of the export, or `None` if `mem_id` is not an import
+    /// of this node or its export was forcibly unexported
+    #[inline]
+    #[must_use]
+    pub fn import_source(&self, mem_id: MemId) -> Option<MemId> {
+        let state = self.fabric.lock();
+        state
+            .imports
+            .get(&mem_id)
+            .filter(|i| i.node == self.eid)
+            .map(|i| i.export)
+            .filter(|export| state.exports.contains_key(export))
+    }
+
+    /// Get the ownership this node most recently set on an address
+    #[inline]
+    #[must_use]
+    pub fn ownership(&self, addr: u64) -> Option<i32> {
+        let state = self.fabric.lock();
+        state
+            .ownership
+            .iter()
+            .rev()
+            .find(|o| o.node == self.eid && o.start <= addr && addr < o.end)
+            .map(|o| o.prot)
+    }
+}
+
+/// Guard of a node entered with [`MockNode::enter`]
+///
+/// Not `Send`: the node is entered on one thread only.
+#[derive(Debug)]
+pub struct NodeGuard {
+    /// Node entered before this one
+    previous: Option<MockNode>,
+    /// Keeps the guard on the thread that entered the node
+    _thread: PhantomData<*const ()>,
+}
+
+impl Drop for NodeGuard {
+    #[inline]
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| current.replace(previous));
+    }
+}
+
+/// Region handed out by a simulated export
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct MockRegion {
+    /// Memory ID of the export
+    pub(crate) mem_id: MemId,
+    /// Address for the descriptor
+    pub(crate) addr: u64,
+    /// Entity ID of the exporting node
+    pub(crate) seid: [u8; 16],
+    /// Destination entity ID, zero if none was set
+    pub(crate) deid: [u8; 16],
+}
+
+/// Run `f` on the node entered on this thread, if any
+fn with_current<R>(f: impl FnOnce([u8; 16], &mut FabricState) -> R) -> Option<R> {
+    let node = CURRENT.with(|current| current.borrow().clone())?;
+    let mut state = node.fabric.lock();
+    Some(f(node.eid, &mut state))
+}
+
+/// Error of a kernel operation
+const fn os_error(errno: i32, op: &'static str, mem_id: Option<MemId>) -> ObmmError {
+    ObmmError::from_errno(errno, OpContext::new(op, mem_id))
+}
+
+/// Export `length` bytes from the current node
+pub(crate) fn export(length: u64) -> Option<MockRegion> {
+    with_current(|node, state| {
+        let mem_id = state.next_mem_id();
+        let addr = EXPORT_BASE.wrapping_add(mem_id << EXPORT_STRIDE_SHIFT);
+        state.exports.insert(
+            mem_id,
+            MockExport {
+                node,
+                addr,
+                length,
+                importers: 0,
+            },
+        );
+        MockRegion {
+            mem_id,
+            addr,
+            seid: node,
+            deid: state.destinations.get(&node).copied().unwrap_or_default(),
+        }
+    })
+}
+
+/// Unexport a region of the current node
+pub(crate) fn unexport(mem_id: MemId, flags: ObmmUnexportFlags) -> Option<Result<()>> {
+    with_current(|node, state| {
+        let export = state
+            .exports
+            .get(&mem_id)
+            .filter(|e| e.node == node)
+            .ok_or(os_error(libc::ENOENT, "unexport", Some(mem_id)))?;
+        if export.importers > 0 && !flags.contains(ObmmUnexportFlags::FORCE) {
+            return Err(os_error(libc::EBUSY, "unexport", Some(mem_id)));
+        }
+        state.exports.remove(&mem_id);
+        Ok(())
+    })
+}
+
+/// Import a region described by `desc` on the current node
+pub(crate) fn import(desc: &ObmmMemDesc<UbPrivData>) -> Option<Result<MemId>> {
+    with_current(|node, state| {
+        let export = state
+            .exports
+            .iter_mut()
+            .find(|(_, e)| e.node == desc.seid && e.addr == desc.addr)
+            .filter(|(_, e)| desc.length <= e.length)
+            .map(|(mem_id, e)| {
+                e.importers = e.importers.saturating_add(1);
+                *mem_id
+            })
+            .ok_or(os_error(libc::ENOENT, "import", None))?;
+        let mem_id = state.next_mem_id();
+        state.imports.insert(mem_id, MockImport { node, export });
+        Ok(mem_id)
+    })
+}
+
+/// Unimport a region of the current node
+pub(crate) fn unimport(mem_id: MemId) -> Option<Result<()>> {
+    with_current(|node, state| {
+        let import = state
+            .imports
+            .get(&mem_id)
+            .filter(|i| i.node == node)
+            .copied()
+            .ok_or(os_error(libc::ENOENT, "unimport", Some(mem_id)))?;
+        state.imports.remove(&mem_id);
+        if let Some(export) = state.exports.get_mut(&import.export) {
+            export.importers = export.importers.saturating_sub(1);
+        }
+        Ok(())
+    })
+}
+
+/// Get the importer count of an export of the current node
+pub(crate) fn importers(mem_id: MemId) -> Option<Result<u32>> {
+    with_current(|node, state| {
+        state
+            .exports
+            .get(&mem_id)
+            .filter(|e| e.node == node)
+            .map(|e| e.importers)
+            .ok_or(ObmmError::InvalidMemId)
+    })
+}
+
+/// Change the ownership of a range of the current node
+pub(crate) fn set_ownership(start: u64, end: u64, prot: i32) -> Option<Result<()>> {
+    with_current(|node, state| {
+        if !state.owns_range(node, start, end) {
+            return Err(os_error(libc::EINVAL, "set_ownership", None));
+        }
+        state.ownership.push(MockOwnership {
+            node,
+            start,
+            end,
+            prot,
+        });
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{mem_export, mem_unexport};
+    use crate::import::{ImportOptions, mem_unimport};
+    use crate::ownership::{prot, set_ownership};
+    use crate::query::query_importers;
+    use crate::types::{ObmmExportFlags, UbPrivData};
+
+    #[test]
+    fn test_nodes_share_exports() {
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.node(1), fabric.node(2));
+
+        let (first, desc) = {
+            let _node = a.enter();
+            mem_export::<UbPrivData>(&[4096], ObmmExportFlags::empty()).unwrap()
+        };
+        let (second, other) = {
+            let _node = b.enter();
+            mem_export::<UbPrivData>(&[8192], ObmmExportFlags::empty()).unwrap()
+        };
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(desc.seid, a.eid());
+        assert_ne!(desc.addr, other.addr);
+        assert_eq!((a.exports(), b.exports()), (vec![1], vec![2]));
+
+        let _node = b.enter();
+        assert!(matches!(
+            mem_unexport(first, ObmmUnexportFlags::empty()),
+            Err(ObmmError::Os {
+                errno: libc::ENOENT,
+                ..
+            })
+        ));
+        let imported = ImportOptions::new().import(&desc).unwrap().result.mem_id;
+        assert_eq!(b.import_source(imported), Some(first));
+        assert!(matches!(
+            query_importers(first),
+            Err(ObmmError::InvalidMemId)
+        ));
+        assert_eq!(fabric.importers(first), Some(1));
+
+        {
+            let _node = a.enter();
+            assert_eq!(query_importers(first).unwrap(), 1);
+            assert!(matches!(
+                mem_unexport(first, ObmmUnexportFlags::empty()),
+                Err(ObmmError::Busy(_))
+            ));
+        }
+        mem_unimport(imported, ObmmExportFlags::empty()).unwrap();
+        assert!(b.imports().is_empty());
+        assert_eq!(fabric.importers(first), Some(0));
+    }
+
+    #[test]
+    fn test_ownership_within_regions() {
+        let fabric = MockFabric::new();
+        let node = fabric.node(1);
+        let _node = node.enter();
+
+        let (_mem_id, desc) = mem_export::<UbPrivData>(&[8192], ObmmExportFlags::empty()).unwrap();
+        set_ownership(-1, desc.addr, desc.addr + 4096, prot::READ).unwrap();
+        assert_eq!(node.ownership(desc.addr), Some(prot::READ));
+        assert_eq!(node.ownership(desc.addr + 4096), None);
+        assert!(set_ownership(-1, desc.addr, desc.addr + 16384, prot::NONE).is_err());
+    }
+
+    #[test]
+    fn test_guard_restores_previous_node() {
+        let fabric = MockFabric::new();
+        let (a, b) = (fabric.node(1), fabric.node(2));
+
+        let _outer = a.enter();
+        {
+            let _inner = b.enter();
+            assert_eq!(export(4096).unwrap().seid, b.eid());
+        }
+        assert_eq!(export(4096).unwrap().seid, a.eid());
+        drop(_outer);
+        assert!(export(4096).is_none());
+    }
+}
@@ -1,14 +1,20 @@
 //! Ownership management for OBMM (Ownership-Based Memory Management)
 //!
 //! This module provides safe wrappers for setting memory ownership
-//! permissions on OBMM memory regions.
+//! permissions on OBMM memory regions, either on a raw file descriptor or
+//! through an [`ObmmDevice`] handle.
 
 #[cfg(feature = "native")]
 use std::ffi::c_void;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 
 use crate::error::{ObmmError, Result};
+use crate::mmap::{SHMDEV_PREFIX, shmdev_path};
 #[cfg(feature = "native")]
 use crate::sys;
+use crate::trace::OpSpan;
+use crate::types::MemId;
 
 /// Memory protection constants (matching C PROT_* values)
 pub mod prot {
@@ -38,7 +44,7 @@ pub mod prot {
 ///   - `prot::READWRITE` (3) - Read-write access
 ///
 /// # Errors
-/// Returns `ObmmError::SetOwnershipFailed` if the operation fails
+/// Returns a kernel error if the operation fails
 ///
 /// # Safety
 /// The address range must be valid OBMM-managed memory.
@@ -59,9 +65,9 @@ pub mod prot {
 /// ```
 #[cfg(not(feature = "native"))]
 #[inline]
-pub fn set_ownership(_fd: i32, _start: u64, _end: u64, _prot: i32) -> Result<()> {
+pub fn set_ownership(_fd: i32, start: u64, end: u64, prot: i32) -> Result<()> {
     // Hooked implementation for testing
-    Ok(())
+    crate::mock::set_ownership(start, end, prot).unwrap_or(Ok(()))
 }
 
 /// Set ownership of a memory region (real implementation)
@@ -89,10 +95,216 @@ pub fn set_ownership(fd: i32, start: u64, end: u64, prot: i32) -> Result<()> {
     if ret == 0 {
         Ok(())
     } else {
-        Err(ObmmError::SetOwnershipFailed(format!(
-            "return code: {}",
-            ret
-        )))
+        Err(ObmmError::last_os_error("set_ownership", None))
+    }
+}
+
+/// Ownership applied to an address range through an [`ObmmDevice`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OwnershipRange {
+    /// Start virtual address of the range
+    pub start: u64,
+    /// End virtual address of the range
+    pub end: u64,
+    /// Protection bits applied to the range
+    pub prot: i32,
+}
+
+impl OwnershipRange {
+    /// Check whether the range contains an address
+    #[inline]
+    #[must_use]
+    pub const fn contains(&self, addr: u64) -> bool {
+        self.start <= addr && addr < self.end
+    }
+}
+
+/// Handle to the shared memory device of an OBMM memory region
+///
+/// Opens `/dev/obmm_shmdev<memid>` and applies ownership changes through
+/// it. The ranges changed through the handle are remembered so that the
+/// current ownership of an address can be queried.
+///
+/// # Example
+/// ```no_run
+/// use obmm_rs::ownership::{ObmmDevice, prot};
+///
+/// let mut device = ObmmDevice::open(1).expect("Failed to open device");
+/// device
+///     .set_ownership(0xffff_fc00_0000, 0xffff_fd00_0000, prot::READ)
+///     .expect("Failed to set ownership");
+/// assert_eq!(device.ownership(0xffff_fc00_1000), Some(prot::READ));
+/// ```
+#[derive(Debug)]
+pub struct ObmmDevice {
+    /// Memory ID of the region, if known
+    mem_id: Option<MemId>,
+    /// Path of the device node
+    path: PathBuf,
+    /// Open device file (None in the hooked implementation)
+    file: Option<File>,
+    /// Ranges changed through this handle, oldest first
+    ranges: Vec<OwnershipRange>,
+}
+
+impl ObmmDevice {
+    /// Open the shared memory device of a memory region
+    ///
+    /// # Arguments
+    /// * `mem_id` - Memory ID of an exported or imported region
+    ///
+    /// # Errors
+    /// Returns `ObmmError::DeviceError` if the device cannot be opened
+    #[inline]
+    pub fn open(mem_id: MemId) -> Result<Self> {
+        let mut device = Self::open_path(shmdev_path(mem_id))?;
+        device.mem_id = Some(mem_id);
+        Ok(device)
+    }
+
+    /// Open a shared memory device by path (hooked implementation for testing)
+    ///
+    /// # Errors
+    /// Never fails in the hooked implementation
+    #[cfg(not(feature = "native"))]
+    #[inline]
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self {
+            mem_id: None,
+            path: path.as_ref().to_path_buf(),
+            file: None,
+            ranges: Vec::new(),
+        })
+    }
+
+    /// Open a shared memory device by path
+    ///
+    /// # Errors
+    /// Returns `ObmmError::DeviceError` if the device cannot be opened
+    #[cfg(feature = "native")]
+    #[inline]
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .map_err(|e| {
+                ObmmError::DeviceError(format!("Failed to open {}: {e}", path.display()))
+            })?;
+        Ok(Self {
+            mem_id: None,
+            path,
+            file: Some(file),
+            ranges: Vec::new(),
+        })
+    }
+
+    /// List the memory IDs that have a shared memory device
+    ///
+    /// # Errors
+    /// Returns `ObmmError::IoError` if `/dev` cannot be read
+    #[inline]
+    pub fn discover() -> Result<Vec<MemId>> {
+        let prefix = Path::new(SHMDEV_PREFIX);
+        let dir = prefix.parent().unwrap_or_else(|| Path::new("/dev"));
+        let name_prefix = prefix
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| ObmmError::IoError(format!("{}: {e}", dir.display())))?;
+        let mut mem_ids: Vec<MemId> = entries
+            .flatten()
+            .filter_map(|entry| {
+                entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix(name_prefix)?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        mem_ids.sort_unstable();
+        Ok(mem_ids)
+    }
+
+    /// Get the memory ID of the region, if opened by memory ID
+    #[inline]
+    #[must_use]
+    pub const fn mem_id(&self) -> Option<MemId> {
+        self.mem_id
+    }
+
+    /// Get the path of the device node
+    #[inline]
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the raw file descriptor (-1 in the hooked implementation)
+    #[inline]
+    #[must_use]
+    pub fn fd(&self) -> i32 {
+        use std::os::fd::AsRawFd;
+
+        self.file.as_ref().map_or(-1, AsRawFd::as_raw_fd)
+    }
+
+    /// Set ownership of an address range
+    ///
+    /// # Arguments
+    /// * `start` - Start virtual address
+    /// * `end` - End virtual address
+    /// * `prot` - Protection bits (see [`prot`])
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the range is empty and
+    /// `ObmmError::SetOwnershipFailed` if the kernel operation fails
+    #[inline]
+    pub fn set_ownership(&mut self, start: u64, end: u64, prot: i32) -> Result<()> {
+        let span = match self.mem_id {
+            Some(mem_id) => OpSpan::lifecycle("set_ownership").mem_id(mem_id),
+            None => OpSpan::lifecycle("set_ownership"),
+        };
+        if start >= end {
+            return Err(span.fail(ObmmError::InvalidInput("ownership range is empty")));
+        }
+        set_ownership(self.fd(), start, end, prot).map_err(|e| span.fail(e))?;
+        self.ranges.push(OwnershipRange { start, end, prot });
+        span.ok();
+        Ok(())
+    }
+
+    /// Get the ownership of an address
+    ///
+    /// # Returns
+    /// The protection bits most recently applied to a range containing
+    /// `addr` through this handle, or `None` if it was never changed
+    #[inline]
+    #[must_use]
+    pub fn ownership(&self, addr: u64) -> Option<i32> {
+        self.ranges
+            .iter()
+            .rev()
+            .find(|r| r.contains(addr))
+            .map(|r| r.prot)
+    }
+
+    /// Get the ranges changed through this handle, oldest first
+    #[inline]
+    #[must_use]
+    pub fn ranges(&self) -> &[OwnershipRange] {
+        &self.ranges
+    }
+
+    /// Start building an ownership change on this device
+    #[inline]
+    #[must_use]
+    pub fn setter(&mut self) -> OwnershipSetter<'_> {
+        OwnershipSetter::new(self)
     }
 }
 
@@ -101,20 +313,20 @@ pub fn set_ownership(fd: i32, start: u64, end: u64, prot: i32) -> Result<()> {
 /// Provides a more ergonomic way to set ownership on memory regions.
 ///
 /// # Example
-/// ```
-/// use obmm_rs::ownership::OwnershipSetter;
-/// use obmm_rs::ownership::prot;
+/// ```no_run
+/// use obmm_rs::ownership::{ObmmDevice, OwnershipSetter};
 ///
-/// OwnershipSetter::new(3)
+/// let mut device = ObmmDevice::open(1).expect("Failed to open device");
+/// OwnershipSetter::new(&mut device)
 ///     .range(0xffff_fc00_0000, 0xffff_fd00_0000)
 ///     .read_write()
 ///     .apply()
 ///     .expect("Failed to set ownership");
 /// ```
-#[derive(Debug, Clone, Copy)]
-pub struct OwnershipSetter {
-    /// File descriptor of the OBMM memory device
-    fd: i32,
+#[derive(Debug)]
+pub struct OwnershipSetter<'a> {
+    /// Device of the OBMM memory region
+    device: &'a mut ObmmDevice,
     /// Start virtual address of the range (None if not set)
     start: Option<u64>,
     /// End virtual address of the range (None if not set)
@@ -123,16 +335,16 @@ pub struct OwnershipSetter {
     prot: i32,
 }
 
-impl OwnershipSetter {
-    /// Create a new ownership setter for the given file descriptor
+impl<'a> OwnershipSetter<'a> {
+    /// Create a new ownership setter for the given device
     ///
     /// # Arguments
-    /// * `fd` - File descriptor of OBMM memory device
+    /// * `device` - Shared memory device of the OBMM memory region
     #[inline]
     #[must_use]
-    pub const fn new(fd: i32) -> Self {
+    pub const fn new(device: &'a mut ObmmDevice) -> Self {
         Self {
-            fd,
+            device,
             start: None,
             end: None,
             prot: prot::NONE,
@@ -188,7 +400,7 @@ impl OwnershipSetter {
     ///
     /// # Errors
     /// Returns `ObmmError::InvalidInput` if start or end address is not set
-    /// Returns `ObmmError::SetOwnershipFailed` if the kernel operation fails
+    /// Returns a kernel error if the kernel operation fails
     #[inline]
     pub fn apply(self) -> Result<()> {
         let start = self
@@ -198,7 +410,7 @@ impl OwnershipSetter {
             .end
             .ok_or(ObmmError::InvalidInput("end address not set"))?;
 
-        set_ownership(self.fd, start, end, self.prot)
+        self.device.set_ownership(start, end, self.prot)
     }
 }
 
@@ -206,11 +418,23 @@ impl OwnershipSetter {
 mod tests {
     use super::*;
 
+    fn test_device() -> ObmmDevice {
+        ObmmDevice {
+            mem_id: Some(3),
+            path: shmdev_path(3),
+            file: None,
+            ranges: Vec::new(),
+        }
+    }
+
     #[test]
     fn test_ownership_builder() {
-        let setter = OwnershipSetter::new(3).range(0x1000, 0x2000).read_write();
+        let mut device = test_device();
+        let setter = OwnershipSetter::new(&mut device)
+            .range(0x1000, 0x2000)
+            .read_write();
 
-        assert_eq!(setter.fd, 3);
+        assert_eq!(setter.device.mem_id(), Some(3));
         assert_eq!(setter.start, Some(0x1000));
         assert_eq!(setter.end, Some(0x2000));
         assert_eq!(setter.prot, prot::READWRITE);
@@ -218,15 +442,40 @@ mod tests {
 
     #[test]
     fn test_ownership_builder_chaining() {
-        let setter = OwnershipSetter::new(5).range(0x1000, 0x2000).read_only();
+        let mut device = test_device();
+        let setter = device.setter().range(0x1000, 0x2000).read_only();
 
         assert_eq!(setter.prot, prot::READ);
 
-        let setternew = OwnershipSetter::new(5).range(0x1000, 0x2000).no_access();
+        let setternew = device.setter().range(0x1000, 0x2000).no_access();
 
         assert_eq!(setternew.prot, prot::NONE);
     }
 
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn test_device_ownership_query() {
+        let mut device = ObmmDevice::open(3).expect("open");
+        assert_eq!(device.path(), shmdev_path(3));
+        assert_eq!(device.ownership(0x1800), None);
+
+        device
+            .set_ownership(0x1000, 0x3000, prot::READWRITE)
+            .unwrap();
+        device
+            .setter()
+            .range(0x2000, 0x3000)
+            .read_only()
+            .apply()
+            .unwrap();
+        assert!(device.set_ownership(0x3000, 0x3000, prot::READ).is_err());
+
+        assert_eq!(device.ownership(0x1800), Some(prot::READWRITE));
+        assert_eq!(device.ownership(0x2800), Some(prot::READ));
+        assert_eq!(device.ownership(0x3000), None);
+        assert_eq!(device.ranges().len(), 2);
+    }
+
     #[test]
     fn test_prot_constants() {
         assert_eq!(prot::NONE, 0);
@@ -0,0 +1,455 @@
+//! Chunk allocator on top of one exported region
+//!
+//! Exporting a fresh region for every small buffer pins and registers
+//! memory in the kernel each time, which is far too slow for RPC-style
+//! workloads. An [`ObmmPool`] exports one large region up front and hands
+//! out chunks of it as [`PoolAllocation`]s. Each allocation can be mapped
+//! locally or shared on its own through a sub-descriptor that covers just
+//! its range.
+//!
+//! Two strategies are available:
+//!
+//! - [`PoolStrategy::Fixed`]: equal chunks of one size, O(1) alloc and free
+//! - [`PoolStrategy::Buddy`]: power-of-two blocks from a minimum block size
+//!   up, split on allocation and merged again on free
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::export::{ByteSize, ExportRequest};
+//! use obmm_rs::pool::{ObmmPool, PoolStrategy};
+//! use obmm_rs::types::ObmmExportFlags;
+//!
+//! let request = ExportRequest::new()
+//!     .numa(0, 64.mib())
+//!     .flags(ObmmExportFlags::ALLOWMMAP);
+//! let pool = ObmmPool::export(&request, PoolStrategy::Buddy { min_block: 64.kib() })
+//!     .expect("Export failed");
+//!
+//! let buffer = pool.alloc(100.kib()).expect("Pool exhausted");
+//! let desc = buffer.descriptor(); // share just this buffer
+//! assert_eq!(desc.length, 128 * 1024);
+//! // The chunk returns to the pool when `buffer` is dropped
+//! ```
+
+use std::collections::BTreeSet;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::error::{ObmmError, OpContext, Result};
+use crate::export::ExportRequest;
+use crate::handle::ExportedMemory;
+use crate::mmap::MappedRegion;
+use crate::types::{DESC_ALIGN, MemId, ObmmMemDesc, UbPrivData};
+
+/// How an [`ObmmPool`] divides its region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PoolStrategy {
+    /// Equal chunks of `chunk` bytes
+    Fixed(usize),
+    /// Power-of-two blocks of at least `min_block` bytes
+    Buddy {
+        /// Smallest block size (a power of two)
+        min_block: usize,
+    },
+}
+
+/// Free space bookkeeping
+#[derive(Debug)]
+enum Allocator {
+    /// Free chunk offsets of a fixed-size allocator
+    Fixed { chunk: usize, free: Vec<usize> },
+    /// Free block offsets per order of a buddy allocator
+    Buddy {
+        min_block: usize,
+        free: Vec<BTreeSet<usize>>,
+    },
+}
+
+impl Allocator {
+    /// Set up an allocator covering `capacity` bytes
+    fn new(strategy: PoolStrategy, capacity: usize) -> Result<Self> {
+        let page = DESC_ALIGN as usize;
+        match strategy {
+            PoolStrategy::Fixed(chunk) => {
+                if chunk == 0 || !chunk.is_multiple_of(page) {
+                    return Err(ObmmError::InvalidInput(
+                        "pool chunk size must be a non-zero multiple of the page size",
+                    ));
+                }
+                // Hand out low offsets first
+                let free = (0..capacity / chunk).rev().map(|i| i * chunk).collect();
+                Ok(Self::Fixed { chunk, free })
+            }
+            PoolStrategy::Buddy { min_block } => {
+                if !min_block.is_power_of_two() || min_block < page {
+                    return Err(ObmmError::InvalidInput(
+                        "pool block size must be a power of two of at least a page",
+                    ));
+                }
+                let blocks = capacity / min_block;
+                let orders = (usize::BITS - blocks.leading_zeros()) as usize;
+                let mut free = vec![BTreeSet::new(); orders];
+
+                // Cover the region with the largest aligned blocks that fit
+                let mut offset = 0;
+                while capacity - offset >= min_block {
+                    let order = (0..orders)
+                        .rev()
+                        .find(|&o| {
+                            let size = min_block << o;
+                            offset.is_multiple_of(size) && size <= capacity - offset
+                        })
+                        .unwrap_or(0);
+                    free[order].insert(offset);
+                    offset += min_block << order;
+                }
+                Ok(Self::Buddy { min_block, free })
+            }
+        }
+    }
+
+    /// Allocate a block of at least `len` bytes
+    ///
+    /// # Returns
+    /// Offset and size of the block, or `None` if no block is free
+    fn alloc(&mut self, len: usize) -> Option<(usize, usize)> {
+        match self {
+            Self::Fixed { chunk, free } => {
+                if len > *chunk {
+                    return None;
+                }
+                free.pop().map(|offset| (offset, *chunk))
+            }
+            Self::Buddy { min_block, free } => {
+                let want = len
+                    .div_ceil(*min_block)
+                    .checked_next_power_of_two()?
+                    .trailing_zeros() as usize;
+                let found = (want..free.len()).find(|&o| !free[o].is_empty())?;
+                let offset = free[found].pop_first()?;
+                for order in (want..found).rev() {
+                    free[order].insert(offset + (*min_block << order));
+                }
+                Some((offset, *min_block << want))
+            }
+        }
+    }
+
+    /// Return a block to the free space
+    fn free(&mut self, offset: usize, size: usize) {
+        match self {
+            Self::Fixed { free, .. } => free.push(offset),
+            Self::Buddy { min_block, free } => {
+                let mut offset = offset;
+                let mut order = (size / *min_block).trailing_zeros() as usize;
+                while order + 1 < free.len() {
+                    let buddy = offset ^ (*min_block << order);
+                    if !free[order].remove(&buddy) {
+                        break;
+                    }
+                    offset = offset.min(buddy);
+                    order += 1;
+                }
+                free[order].insert(offset);
+            }
+        }
+    }
+
+    /// Total free bytes
+    fn available(&self) -> usize {
+        match self {
+            Self::Fixed { chunk, free } => chunk * free.len(),
+            Self::Buddy { min_block, free } => free
+                .iter()
+                .enumerate()
+                .map(|(order, blocks)| blocks.len() * (*min_block << order))
+                .sum(),
+        }
+    }
+}
+
+/// A pool of chunks carved out of one exported region
+///
+/// The region is unexported when the pool is dropped. Allocations borrow
+/// the pool, so they cannot outlive it.
+#[derive(Debug)]
+pub struct ObmmPool {
+    /// The exported backing region
+    memory: ExportedMemory<UbPrivData>,
+    /// Usable bytes (the region length rounded down to whole chunks)
+    capacity: usize,
+    /// Free space
+    allocator: Mutex<Allocator>,
+}
+
+impl ObmmPool {
+    /// Create a pool over an already exported region
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the chunk or block size is not
+    /// page aligned or the region is too small for a single chunk
+    #[inline]
+    pub fn new(memory: ExportedMemory<UbPrivData>, strategy: PoolStrategy) -> Result<Self> {
+        let length = usize::try_from(memory.descriptor().length)
+            .map_err(|_| ObmmError::InvalidInput("region too large for a pool"))?;
+        let allocator = Allocator::new(strategy, length)?;
+        let capacity = allocator.available();
+        if capacity == 0 {
+            return Err(ObmmError::InvalidInput(
+                "region is smaller than one pool chunk",
+            ));
+        }
+
+        Ok(Self {
+            memory,
+            capacity,
+            allocator: Mutex::new(allocator),
+        })
+    }
+
+    /// Export a region and create a pool over it
+    ///
+    /// # Errors
+    /// Returns the export error if the export fails, or the error of
+    /// [`ObmmPool::new`]
+    #[inline]
+    pub fn export(request: &ExportRequest, strategy: PoolStrategy) -> Result<Self> {
+        Self::new(ExportedMemory::from_request(request)?, strategy)
+    }
+
+    /// Allocate a chunk of at least `len` bytes
+    ///
+    /// The chunk may be larger than `len`: a fixed pool always returns a
+    /// whole chunk and a buddy pool rounds up to a power of two.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if `len` is zero and
+    /// `ObmmError::OutOfMemory` if no free chunk is large enough
+    #[inline]
+    pub fn alloc(&self, len: usize) -> Result<PoolAllocation<'_>> {
+        if len == 0 {
+            return Err(ObmmError::InvalidInput(
+                "allocation length must be non-zero",
+            ));
+        }
+        let (offset, len) =
+            self.lock()
+                .alloc(len)
+                .ok_or(ObmmError::OutOfMemory(OpContext::new(
+                    "pool allocation",
+                    Some(self.mem_id()),
+                )))?;
+
+        Ok(PoolAllocation {
+            pool: self,
+            offset,
+            len,
+        })
+    }
+
+    /// Get the memory ID of the backing region
+    #[inline]
+    #[must_use]
+    pub const fn mem_id(&self) -> MemId {
+        self.memory.mem_id()
+    }
+
+    /// Get the descriptor of the whole backing region
+    #[inline]
+    #[must_use]
+    pub const fn descriptor(&self) -> &ObmmMemDesc<UbPrivData> {
+        self.memory.descriptor()
+    }
+
+    /// Get the number of bytes that can be allocated in total
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the number of bytes currently free
+    #[inline]
+    #[must_use]
+    pub fn available(&self) -> usize {
+        self.lock().available()
+    }
+
+    /// Lock the allocator, ignoring poisoning (the free lists stay valid)
+    fn lock(&self) -> MutexGuard<'_, Allocator> {
+        self.allocator
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A chunk allocated from an [`ObmmPool`]
+///
+/// The chunk is returned to the pool on drop.
+#[derive(Debug)]
+pub struct PoolAllocation<'a> {
+    /// Pool the chunk belongs to
+    pool: &'a ObmmPool,
+    /// Offset of the chunk within the region
+    offset: usize,
+    /// Length of the chunk in bytes
+    len: usize,
+}
+
+impl PoolAllocation<'_> {
+    /// Get the memory ID of the backing region
+    #[inline]
+    #[must_use]
+    pub const fn mem_id(&self) -> MemId {
+        self.pool.mem_id()
+    }
+
+    /// Get the offset of the chunk within the region
+    #[inline]
+    #[must_use]
+    pub const fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Get the length of the chunk in bytes
+    #[inline]
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the chunk is empty (never true)
+    #[inline]
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Build a descriptor covering only this chunk
+    ///
+    /// The descriptor is the region descriptor with `addr` moved to the
+    /// start of the chunk and `length` set to the chunk length, so a peer
+    /// importing it sees just this chunk.
+    #[inline]
+    #[must_use]
+    pub fn descriptor(&self) -> ObmmMemDesc<UbPrivData> {
+        let desc = self.pool.descriptor();
+        ObmmMemDesc {
+            addr: desc.addr + self.offset as u64,
+            length: self.len as u64,
+            seid: desc.seid,
+            deid: desc.deid,
+            tokenid: desc.tokenid,
+            scna: desc.scna,
+            dcna: desc.dcna,
+            priv_len: desc.priv_len,
+            priv_data: desc.priv_data,
+        }
+    }
+
+    /// Map the chunk into this process
+    ///
+    /// # Errors
+    /// Returns `ObmmError::MapFailed` if the chunk cannot be mapped
+    #[inline]
+    pub fn map(&self) -> Result<MappedRegion<'_>> {
+        self.pool.memory.map_range(self.offset as u64, self.len)
+    }
+}
+
+impl Drop for PoolAllocation<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.pool.lock().free(self.offset, self.len);
+    }
+}
+
+#[cfg(all(test, not(feature = "native")))]
+mod tests {
+    use super::*;
+    use crate::export::ByteSize;
+
+    fn pool(len: usize, strategy: PoolStrategy) -> ObmmPool {
+        ObmmPool::export(&ExportRequest::new().numa(0, len), strategy).unwrap()
+    }
+
+    #[test]
+    fn test_fixed_pool() {
+        let pool = pool(2.mib(), PoolStrategy::Fixed(512.kib()));
+        assert_eq!(pool.capacity(), 2.mib());
+
+        let a = pool.alloc(1).unwrap();
+        let b = pool.alloc(512.kib()).unwrap();
+        assert_eq!((a.offset(), b.offset()), (0, 512.kib()));
+        assert!(pool.alloc(512.kib() + 1).is_err());
+
+        let _c = pool.alloc(1).unwrap();
+        let _d = pool.alloc(1).unwrap();
+        assert!(matches!(pool.alloc(1), Err(ObmmError::OutOfMemory(_))));
+
+        drop(b);
+        assert_eq!(pool.available(), 512.kib());
+        assert_eq!(pool.alloc(1).unwrap().offset(), 512.kib());
+        assert!(
+            ObmmPool::export(
+                &ExportRequest::new().numa(0, 2.mib()),
+                PoolStrategy::Fixed(100)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_buddy_pool_split_and_merge() {
+        let pool = pool(
+            4.mib(),
+            PoolStrategy::Buddy {
+                min_block: 64.kib(),
+            },
+        );
+        assert_eq!(pool.available(), 4.mib());
+
+        let a = pool.alloc(100.kib()).unwrap();
+        assert_eq!(a.len(), 128.kib());
+        let b = pool.alloc(64.kib()).unwrap();
+        let c = pool.alloc(64.kib()).unwrap();
+        assert_eq!(
+            (a.offset(), b.offset(), c.offset()),
+            (0, 128.kib(), 192.kib())
+        );
+        assert_eq!(pool.available(), 4.mib() - 256.kib());
+
+        drop(b);
+        drop(a);
+        drop(c);
+        assert_eq!(pool.available(), 4.mib());
+        // Everything merged back: the whole region is one block again
+        assert_eq!(pool.alloc(4.mib()).unwrap().offset(), 0);
+    }
+
+    #[test]
+    fn test_buddy_pool_uneven_region() {
+        // 6MB is covered by a 4MB and a 2MB block
+        let pool = pool(6.mib(), PoolStrategy::Buddy { min_block: 2.mib() });
+        assert_eq!(pool.capacity(), 6.mib());
+        let big = pool.alloc(4.mib()).unwrap();
+        let small = pool.alloc(2.mib()).unwrap();
+        assert_eq!((big.offset(), small.offset()), (0, 4.mib()));
+        assert!(pool.alloc(1).is_err());
+    }
+
+    #[test]
+    fn test_allocation_descriptor() {
+        let pool = pool(2.mib(), PoolStrategy::Fixed(1.mib()));
+        let _first = pool.alloc(1).unwrap();
+        let second = pool.alloc(1).unwrap();
+        let desc = second.descriptor();
+        assert_eq!(desc.addr, pool.descriptor().addr + 1.mib() as u64);
+        assert_eq!(desc.length, 1.mib() as u64);
+
+        let mut region = second.map().unwrap();
+        region.as_mut_slice()[0] = 1;
+        assert_eq!(region.len(), 1.mib());
+    }
+}
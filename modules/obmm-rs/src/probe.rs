@@ -0,0 +1,135 @@
+//! Host capability probe for OBMM
+//!
+//! Reports whether this host can export and import memory before anything
+//! is attempted: whether the kernel module is loaded, whether the device
+//! node exists and can be opened by the caller, and which NUMA nodes and
+//! regions the kernel currently reports.
+//!
+//! Unlike [`sys::is_available`](crate::sys::is_available), probing never
+//! opens the shared device instance used by the other operations.
+
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::layout::numa_nodes_in;
+use crate::query::list_regions_in;
+use crate::registry::EntryKind;
+
+/// Path of the OBMM device node
+pub const OBMM_DEV_PATH: &str = "/dev/obmm";
+
+/// OBMM capabilities of the host
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct ObmmProbe {
+    /// Whether the `obmm` kernel module is loaded or built in
+    pub module: bool,
+    /// Whether the device node exists
+    pub device: bool,
+    /// Whether the caller can open the device node for reading and writing
+    pub accessible: bool,
+    /// Whether the shared memory devices of the regions could be listed
+    pub listing: bool,
+    /// NUMA nodes present on this machine
+    pub numa_nodes: Vec<usize>,
+    /// Regions with a shared memory device, of any kind
+    pub regions: usize,
+    /// Regions currently exported by this host
+    pub exports: usize,
+    /// Regions currently imported by this host
+    pub imports: usize,
+}
+
+impl ObmmProbe {
+    /// Check whether exports and imports can be attempted
+    #[inline]
+    #[must_use]
+    pub const fn is_usable(&self) -> bool {
+        self.device && self.accessible
+    }
+}
+
+/// Probe the OBMM capabilities of this host
+///
+/// # Returns
+/// What was found; missing pieces are reported as absent, not as errors
+#[inline]
+#[must_use]
+pub fn probe() -> ObmmProbe {
+    probe_in(Path::new(OBMM_DEV_PATH), Path::new("/sys"))
+}
+
+/// Probe OBMM capabilities with an alternative device node and sysfs root
+///
+/// # Arguments
+/// * `dev` - Path of the device node
+/// * `sys_root` - Root of the sysfs tree, normally `/sys`
+///
+/// # Returns
+/// What was found; missing pieces are reported as absent, not as errors
+#[must_use]
+pub fn probe_in(dev: &Path, sys_root: &Path) -> ObmmProbe {
+    let dev_dir = dev.parent().unwrap_or_else(|| Path::new("/dev"));
+    let regions = list_regions_in(dev_dir, &sys_root.join("class/obmm")).ok();
+    let count = |kind| {
+        regions
+            .iter()
+            .flatten()
+            .filter(|r| r.kind == Some(kind))
+            .count()
+    };
+
+    ObmmProbe {
+        module: sys_root.join("module/obmm").is_dir(),
+        device: dev.exists(),
+        accessible: OpenOptions::new().read(true).write(true).open(dev).is_ok(),
+        listing: regions.is_some(),
+        numa_nodes: numa_nodes_in(&sys_root.join("devices/system/node")).unwrap_or_default(),
+        regions: regions.as_ref().map_or(0, Vec::len),
+        exports: count(EntryKind::Export),
+        imports: count(EntryKind::Import),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_in() {
+        let root = std::env::temp_dir().join(format!("obmm-rs-probe-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let dev = root.join("obmm");
+
+        let probe = probe_in(&dev, &root);
+        assert!(!probe.module && !probe.device && !probe.listing);
+        assert!(!probe.is_usable());
+        assert!(probe.numa_nodes.is_empty());
+
+        for dir in [
+            "module/obmm",
+            "devices/system/node/node1",
+            "devices/system/node/node0",
+        ] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for (mem_id, kind) in [(1, "export"), (2, "import"), (3, "export")] {
+            fs::write(root.join(format!("obmm_shmdev{mem_id}")), "").unwrap();
+            let region = root.join(format!("class/obmm/obmm_shmdev{mem_id}"));
+            fs::create_dir_all(&region).unwrap();
+            fs::write(region.join("type"), kind).unwrap();
+        }
+        fs::write(root.join("obmm_shmdev4"), "").unwrap();
+        fs::write(&dev, "").unwrap();
+
+        let probe = probe_in(&dev, &root);
+        assert!(probe.module && probe.is_usable() && probe.listing);
+        assert_eq!(probe.numa_nodes, [0, 1]);
+        assert_eq!(probe.regions, 4);
+        assert_eq!((probe.exports, probe.imports), (2, 1));
+
+        let _ = fs::remove_dir_all(&root);
+    }
+}
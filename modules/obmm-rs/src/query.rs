@@ -1,11 +1,23 @@
 //! Query operations for OBMM (Ownership-Based Memory Management)
 //!
 //! This module provides safe wrappers for querying memory information
-//! including memory ID to physical address translation and vice versa.
+//! including memory ID to physical address translation and vice versa, and
+//! enumeration of all regions known to the local OBMM subsystem.
+//!
+//! Every live region has a shared memory device `/dev/obmm_shmdev<memid>`,
+//! which is what the enumeration relies on. The driver does not document
+//! per-region sysfs attributes, so the kind, size, NUMA node, flags and
+//! importer count are read from `/sys/class/obmm/obmm_shmdev<memid>` only
+//! when present and reported as unknown otherwise.
+
+use std::fs;
+use std::path::Path;
 
 use crate::error::{ObmmError, Result};
+use crate::registry::EntryKind;
 #[cfg(feature = "native")]
 use crate::sys;
+use crate::trace::OpSpan;
 use crate::types::{MemId, QueryResult};
 
 /// Query memory ID by physical address
@@ -22,8 +34,7 @@ use crate::types::{MemId, QueryResult};
 /// - The offset within that region
 ///
 /// # Errors
-/// Returns `ObmmError::QueryFailed` if the address is not found
-/// or the query operation fails
+/// Returns a kernel error if the query fails (e.g. the address is not found)
 ///
 /// # Example
 /// ```
@@ -41,10 +52,12 @@ use crate::types::{MemId, QueryResult};
 #[cfg(not(feature = "native"))]
 #[inline]
 pub fn query_memid_by_pa(pa: u64) -> Result<QueryResult> {
+    let span = OpSpan::query("query_memid_by_pa");
     // Hooked implementation for testing
     if pa == 0 {
-        Err(ObmmError::QueryFailed("query failed".to_string()))
+        Err(span.fail(ObmmError::QueryFailed("query failed".to_string())))
     } else {
+        span.mem_id(1).ok();
         Ok(QueryResult {
             mem_id: 1,
             offset: pa & 0xFFF, // Simulate page offset
@@ -72,6 +85,7 @@ pub fn query_memid_by_pa(pa: u64) -> Result<QueryResult> {
 #[cfg(feature = "native")]
 #[inline]
 pub fn query_memid_by_pa(pa: u64) -> Result<QueryResult> {
+    let span = OpSpan::query("query_memid_by_pa");
     let mut mem_id: MemId = 0;
     let mut offset: u64 = 0;
     let mem_id_ptr = std::ptr::addr_of_mut!(mem_id);
@@ -79,13 +93,14 @@ pub fn query_memid_by_pa(pa: u64) -> Result<QueryResult> {
     let ret = unsafe { sys::obmm_query_memid_by_pa(pa, mem_id_ptr, offset_ptr) };
 
     if ret == 0 {
+        span.mem_id(mem_id).ok();
         Ok(QueryResult {
             mem_id,
             offset,
             phys_addr: 0,
         })
     } else {
-        Err(ObmmError::QueryFailed(format!("return code: {}", ret)))
+        Err(span.fail(ObmmError::last_os_error("query_memid_by_pa", None)))
     }
 }
 
@@ -101,8 +116,7 @@ pub fn query_memid_by_pa(pa: u64) -> Result<QueryResult> {
 /// The physical address corresponding to the memory ID and offset
 ///
 /// # Errors
-/// Returns `ObmmError::QueryFailed` if the memory ID is invalid
-/// or the query operation fails
+/// Returns a kernel error if the query fails (e.g. the memory ID is invalid)
 ///
 /// # Example
 /// ```
@@ -119,13 +133,15 @@ pub fn query_memid_by_pa(pa: u64) -> Result<QueryResult> {
 #[cfg(not(feature = "native"))]
 #[inline]
 pub fn query_pa_by_memid(mem_id: MemId, offset: u64) -> Result<u64> {
+    let span = OpSpan::query("query_pa_by_memid").mem_id(mem_id);
     // Hooked implementation for testing
     if mem_id == 0 {
-        Err(ObmmError::QueryFailed("query failed".to_string()))
+        Err(span.fail(ObmmError::QueryFailed("query failed".to_string())))
     } else {
         // Using wrapping_add to avoid potential overflow panics in debug mode
         let base: u64 = 0x1000_0000;
         let shifted = mem_id.checked_shl(12).unwrap_or(0);
+        span.ok();
         Ok(base.wrapping_add(shifted).wrapping_add(offset))
     }
 }
@@ -149,13 +165,370 @@ pub fn query_pa_by_memid(mem_id: MemId, offset: u64) -> Result<u64> {
 #[cfg(feature = "native")]
 #[inline]
 pub fn query_pa_by_memid(mem_id: MemId, offset: u64) -> Result<u64> {
+    let span = OpSpan::query("query_pa_by_memid").mem_id(mem_id);
     let mut pa: u64 = 0;
     let pa_ptr = std::ptr::addr_of_mut!(pa);
     let ret = unsafe { sys::obmm_query_pa_by_memid(mem_id, offset, pa_ptr) };
 
     if ret == 0 {
+        span.ok();
         Ok(pa)
     } else {
-        Err(ObmmError::QueryFailed(format!("return code: {}", ret)))
+        Err(span.fail(ObmmError::last_os_error("query_pa_by_memid", Some(mem_id))))
+    }
+}
+
+/// Directory holding the shared memory device of each live region
+pub const OBMM_DEV_DIR: &str = "/dev";
+
+/// Sysfs class directory of the shared memory devices
+///
+/// Attributes found in a device's subdirectory (`type`, `size`,
+/// `numa_node`, `flags`, `refcount`) are reported; none are required.
+pub const OBMM_SYSFS_DIR: &str = "/sys/class/obmm";
+
+/// Name prefix of the shared memory devices
+const SHMDEV_NAME: &str = "obmm_shmdev";
+
+/// Information about a region known to the local OBMM subsystem
+///
+/// Only the memory ID is always known; the other fields are `None` when
+/// the kernel does not report them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RegionInfo {
+    /// Memory ID
+    pub mem_id: MemId,
+    /// Whether the region is exported or imported
+    pub kind: Option<EntryKind>,
+    /// Size of the region in bytes
+    pub size: Option<u64>,
+    /// NUMA node of the region
+    pub numa_node: Option<i32>,
+    /// Export or import flags
+    pub flags: Option<u64>,
+    /// Number of users holding the region
+    pub refcount: Option<u32>,
+}
+
+/// List all regions with a shared memory device
+///
+/// # Returns
+/// Regions sorted by memory ID, including those of unknown kind
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the region listing cannot be read
+///
+/// # Example
+/// ```
+/// use obmm_rs::query::list_regions;
+///
+/// match list_regions() {
+///     Ok(regions) => {
+///         for region in regions {
+///             println!("{}: {:?} bytes", region.mem_id, region.size);
+///         }
+///     }
+///     Err(e) => eprintln!("Listing failed: {}", e),
+/// }
+/// ```
+#[cfg(not(feature = "native"))]
+#[inline]
+pub fn list_regions() -> Result<Vec<RegionInfo>> {
+    // Hooked implementation for testing
+    Ok(Vec::new())
+}
+
+/// List all regions with a shared memory device (real implementation)
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the region listing cannot be read
+#[cfg(feature = "native")]
+#[inline]
+pub fn list_regions() -> Result<Vec<RegionInfo>> {
+    list_regions_in(Path::new(OBMM_DEV_DIR), Path::new(OBMM_SYSFS_DIR))
+}
+
+/// List all exported regions
+///
+/// Regions of unknown kind are left out; see [`list_regions`].
+///
+/// # Returns
+/// Exported regions sorted by memory ID
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the region listing cannot be read
+#[inline]
+pub fn list_exports() -> Result<Vec<RegionInfo>> {
+    list_kind(EntryKind::Export)
+}
+
+/// List all imported regions
+///
+/// Regions of unknown kind are left out; see [`list_regions`].
+///
+/// # Returns
+/// Imported regions sorted by memory ID
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if the region listing cannot be read
+#[inline]
+pub fn list_imports() -> Result<Vec<RegionInfo>> {
+    list_kind(EntryKind::Import)
+}
+
+/// List the regions of one kind
+fn list_kind(kind: EntryKind) -> Result<Vec<RegionInfo>> {
+    let mut regions = list_regions()?;
+    regions.retain(|r| r.kind == Some(kind));
+    Ok(regions)
+}
+
+/// List the regions whose shared memory device is in `dev_dir`
+///
+/// Entries not named `obmm_shmdev<memid>` are ignored. Attributes are read
+/// from the matching subdirectory of `sysfs_dir` when present: `type`
+/// (`export` or `import`), `size`, `flags` and `refcount` as decimal or
+/// `0x`-prefixed hexadecimal unsigned numbers, and `numa_node` as a
+/// decimal number with `-1` for no node.
+///
+/// # Errors
+/// Returns `ObmmError::QueryFailed` if `dev_dir` cannot be read or an
+/// attribute is malformed
+#[inline]
+pub fn list_regions_in(dev_dir: &Path, sysfs_dir: &Path) -> Result<Vec<RegionInfo>> {
+    let entries = fs::read_dir(dev_dir)
+        .map_err(|e| ObmmError::QueryFailed(format!("{}: {e}", dev_dir.display())))?;
+
+    let mut regions = Vec::new();
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(mem_id) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(SHMDEV_NAME))
+            .and_then(|id| id.parse::<MemId>().ok())
+        else {
+            continue;
+        };
+
+        let dir = sysfs_dir.join(&name);
+        let kind = match read_attr(&dir, "type")?.as_deref() {
+            Some("export") => Some(EntryKind::Export),
+            Some("import") => Some(EntryKind::Import),
+            _ => None,
+        };
+        let numa_node = match read_attr(&dir, "numa_node")? {
+            Some(node) => Some(
+                node.parse::<i32>()
+                    .map_err(|_| malformed(&dir, "numa_node", &node))?,
+            )
+            .filter(|n| *n >= 0),
+            None => None,
+        };
+
+        regions.push(RegionInfo {
+            mem_id,
+            kind,
+            size: read_num(&dir, "size")?,
+            numa_node,
+            flags: read_num(&dir, "flags")?,
+            refcount: read_num(&dir, "refcount")?,
+        });
+    }
+
+    regions.sort_by_key(|r| r.mem_id);
+    Ok(regions)
+}
+
+/// Get the number of nodes that currently import an exported region
+///
+/// Reads the `refcount` attribute of the region under
+/// [`OBMM_SYSFS_DIR`]. A region with importers cannot be unexported without
+/// `ObmmUnexportFlags::FORCE`.
+///
+/// # Errors
+/// Returns `ObmmError::InvalidMemId` if no region has this memory ID and
+/// `ObmmError::QueryFailed` if the count is not reported or malformed
+///
+/// # Example
+/// ```
+/// use obmm_rs::query::query_importers;
+///
+/// match query_importers(12345) {
+///     Ok(0) => println!("Safe to unexport"),
+///     Ok(n) => println!("Still imported by {n} node(s)"),
+///     Err(e) => eprintln!("Query failed: {e}"),
+/// }
+/// ```
+#[cfg(not(feature = "native"))]
+#[inline]
+pub fn query_importers(mem_id: MemId) -> Result<u32> {
+    let span = OpSpan::query("query_importers").region(EntryKind::Export, mem_id);
+    // Hooked implementation for testing
+    crate::mock::importers(mem_id)
+        .unwrap_or(Ok(0))
+        .inspect(|_| span.ok())
+        .map_err(|e| span.fail(e))
+}
+
+/// Get the number of nodes that currently import an exported region (real
+/// implementation)
+///
+/// # Errors
+/// Returns `ObmmError::InvalidMemId` if no region has this memory ID and
+/// `ObmmError::QueryFailed` if the count is not reported or malformed
+#[cfg(feature = "native")]
+#[inline]
+pub fn query_importers(mem_id: MemId) -> Result<u32> {
+    let span = OpSpan::query("query_importers").region(EntryKind::Export, mem_id);
+    query_importers_in(Path::new(OBMM_DEV_DIR), Path::new(OBMM_SYSFS_DIR), mem_id)
+        .inspect(|_| span.ok())
+        .map_err(|e| span.fail(e))
+}
+
+/// Get the importer count of a region whose shared memory device is in
+/// `dev_dir`, reading its `refcount` attribute under `sysfs_dir`
+///
+/// # Errors
+/// Returns `ObmmError::InvalidMemId` if `dev_dir` has no device for the
+/// region and `ObmmError::QueryFailed` if the attribute is missing or
+/// malformed
+#[inline]
+pub fn query_importers_in(dev_dir: &Path, sysfs_dir: &Path, mem_id: MemId) -> Result<u32> {
+    let name = format!("{SHMDEV_NAME}{mem_id}");
+    if !dev_dir.join(&name).exists() {
+        return Err(ObmmError::InvalidMemId);
+    }
+    let dir = sysfs_dir.join(name);
+    read_num(&dir, "refcount")?.ok_or_else(|| {
+        ObmmError::QueryFailed(format!("{}: not reported", dir.join("refcount").display()))
+    })
+}
+
+/// Read a trimmed attribute file, returning `None` if it does not exist
+fn read_attr(dir: &Path, name: &str) -> Result<Option<String>> {
+    match fs::read_to_string(dir.join(name)) {
+        Ok(value) => Ok(Some(value.trim().to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(ObmmError::QueryFailed(format!(
+            "{}: {e}",
+            dir.join(name).display()
+        ))),
+    }
+}
+
+/// Read an unsigned decimal or `0x`-prefixed hexadecimal attribute,
+/// returning `None` if it does not exist
+fn read_num<T: TryFrom<u64>>(dir: &Path, name: &str) -> Result<Option<T>> {
+    let Some(value) = read_attr(dir, name)? else {
+        return Ok(None);
+    };
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse::<u64>().ok(),
+    };
+    parsed
+        .and_then(|v| T::try_from(v).ok())
+        .map(Some)
+        .ok_or_else(|| malformed(dir, name, &value))
+}
+
+/// Error for an attribute with an unparsable value
+fn malformed(dir: &Path, name: &str, value: &str) -> ObmmError {
+    ObmmError::QueryFailed(format!(
+        "{}: invalid value {value:?}",
+        dir.join(name).display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a device node stand-in and its sysfs attributes
+    fn write_region(root: &Path, mem_id: MemId, attrs: &[(&str, &str)]) {
+        let name = format!("obmm_shmdev{mem_id}");
+        fs::create_dir_all(root.join("dev")).unwrap();
+        fs::write(root.join("dev").join(&name), "").unwrap();
+        let dir = root.join("sysfs").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        for (attr, value) in attrs {
+            fs::write(dir.join(attr), format!("{value}\n")).unwrap();
+        }
+    }
+
+    fn list(root: &Path) -> Result<Vec<RegionInfo>> {
+        list_regions_in(&root.join("dev"), &root.join("sysfs"))
+    }
+
+    #[test]
+    fn test_list_regions_in() {
+        let root = std::env::temp_dir().join(format!("obmm-rs-sysfs-{}", std::process::id()));
+        write_region(
+            &root,
+            4,
+            &[
+                ("type", "import"),
+                ("size", "0x4000000"),
+                ("numa_node", "2"),
+                ("refcount", "1"),
+            ],
+        );
+        write_region(
+            &root,
+            2,
+            &[
+                ("type", "export"),
+                ("size", "18446744073709551615"),
+                ("numa_node", "-1"),
+                ("flags", "0x1"),
+            ],
+        );
+        // A live region whose attributes the kernel does not report
+        write_region(&root, 9, &[]);
+        fs::write(root.join("dev/unrelated"), "").unwrap();
+
+        let regions = list(&root).unwrap();
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].mem_id, 2);
+        assert_eq!(regions[0].kind, Some(EntryKind::Export));
+        assert_eq!(regions[0].size, Some(u64::MAX));
+        assert_eq!(regions[0].numa_node, None);
+        assert_eq!(regions[0].flags, Some(1));
+        assert_eq!(regions[1].kind, Some(EntryKind::Import));
+        assert_eq!(regions[1].size, Some(64 * 1024 * 1024));
+        assert_eq!(regions[1].numa_node, Some(2));
+        assert_eq!(regions[1].refcount, Some(1));
+        assert_eq!(regions[2].mem_id, 9);
+        assert_eq!(regions[2].kind, None);
+        assert_eq!(regions[2].size, None);
+    }
+
+    #[test]
+    fn test_query_importers_in() {
+        let root = std::env::temp_dir().join(format!("obmm-rs-sysfs-refs-{}", std::process::id()));
+        write_region(&root, 3, &[("type", "export"), ("refcount", "2")]);
+        write_region(&root, 5, &[("type", "export")]);
+
+        let (dev, sysfs) = (root.join("dev"), root.join("sysfs"));
+        let busy = query_importers_in(&dev, &sysfs, 3);
+        let unknown = query_importers_in(&dev, &sysfs, 5);
+        let missing = query_importers_in(&dev, &sysfs, 7);
+        let _ = fs::remove_dir_all(&root);
+
+        assert_eq!(busy, Ok(2));
+        assert!(matches!(unknown, Err(ObmmError::QueryFailed(_))));
+        assert_eq!(missing, Err(ObmmError::InvalidMemId));
+    }
+
+    #[test]
+    fn test_list_regions_in_malformed() {
+        let root = std::env::temp_dir().join(format!("obmm-rs-sysfs-bad-{}", std::process::id()));
+        write_region(&root, 1, &[("type", "export"), ("size", "-1")]);
+        let result = list(&root);
+        let _ = fs::remove_dir_all(&root);
+        assert!(result.is_err());
     }
 }
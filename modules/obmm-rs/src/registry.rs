@@ -0,0 +1,819 @@
+//! Persistent registry of exported and imported memory
+//!
+//! The registry keeps track of the memory regions exported and imported on
+//! this node so that they can be enumerated and cleaned up across process
+//! restarts. It lives in a state directory containing:
+//!
+//! - `index.json`: the list of active entries (memid, size, flags, owner
+//!   pid, peer, lease and timestamps) and the per-peer quotas
+//! - `memdesc_<memid>.json`: the descriptor of each exported region
+//!
+//! Updates to the index are serialized with an advisory lock on
+//! `index.lock` and written through a temporary file that is synced and
+//! renamed over the index, so a crash never leaves a partially written
+//! index behind.
+//!
+//! Exports can be charged to the remote peer they are granted to, keyed by
+//! the peer's destination entity ID (deid). A [`PeerQuota`] set with
+//! [`Registry::set_peer_quota`] caps the bytes one peer may hold at once;
+//! [`Registry::grant_to_peer`] refuses grants that would exceed it and
+//! [`Registry::release_peer`] drops the charge again. Unlike
+//! the process-wide quota of the `accounting` module, the check and the
+//! charge happen under the registry lock, so the limit holds across
+//! processes sharing the state directory.
+//!
+//! An export can also carry a [`Lease`]: the importer must renew it with
+//! [`Registry::renew_lease`] (through the exporter's agent) before it
+//! expires. Exports whose lease ran out more than the grace period ago are
+//! listed by [`Registry::expired_leases`] so the agent can unexport them,
+//! keeping a crashed importer from pinning donor memory forever.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::prelude::*;
+//! use obmm_rs::registry::Registry;
+//!
+//! let registry = Registry::open_default().expect("Failed to open registry");
+//!
+//! let mut lengths = vec![0; MAX_NUMA_NODES];
+//! lengths[0] = 1024 * 1024 * 64;
+//! let flags = ObmmExportFlags::ALLOWMMAP;
+//! let (mem_id, desc) = mem_export::<UbPrivData>(&lengths, flags).expect("Export failed");
+//! registry
+//!     .record_export(mem_id, flags, &desc)
+//!     .expect("Failed to record export");
+//!
+//! for entry in registry.exports().expect("Failed to read registry") {
+//!     println!("{} ({} bytes)", entry.mem_id, entry.size);
+//! }
+//! ```
+
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{ObmmError, Result};
+use crate::query::query_pa_by_memid;
+use crate::types::{DescEnvelope, MemId, ObmmExportFlags, ObmmMemDesc};
+
+/// Default state directory of the registry
+pub const DEFAULT_STATE_DIR: &str = "/var/lib/memlink";
+
+/// Environment variable overriding the state directory
+pub const STATE_DIR_ENV: &str = "MEMLINK_STATE_DIR";
+
+/// Name of the index file inside the state directory
+pub const INDEX_FILE: &str = "index.json";
+
+/// Name of the lock file inside the state directory
+const LOCK_FILE: &str = "index.lock";
+
+/// Current index format version
+const INDEX_VERSION: u32 = 1;
+
+/// Kind of a registry entry
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// Memory exported from this node
+    Export,
+    /// Memory imported from a remote node
+    Import,
+}
+
+/// A single exported or imported memory region
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RegistryEntry {
+    /// Memory ID
+    pub mem_id: MemId,
+    /// Whether the memory was exported or imported
+    pub kind: EntryKind,
+    /// Size of the region in bytes
+    pub size: u64,
+    /// Export or import flags
+    pub flags: u64,
+    /// NUMA node of imported memory
+    #[serde(default)]
+    pub numa_node: Option<i32>,
+    /// Destination entity ID of the peer an export is granted to
+    #[serde(default)]
+    pub peer: Option<[u8; 16]>,
+    /// Lease the importer of an export must keep renewing
+    #[serde(default)]
+    pub lease: Option<Lease>,
+    /// Process that created the entry
+    pub owner_pid: u32,
+    /// Creation time (seconds since the Unix epoch)
+    pub created_at: u64,
+    /// Last update time (seconds since the Unix epoch)
+    pub updated_at: u64,
+}
+
+impl RegistryEntry {
+    /// Create an entry owned by the current process
+    ///
+    /// # Arguments
+    /// * `mem_id` - Memory ID
+    /// * `kind` - Export or import
+    /// * `size` - Size of the region in bytes
+    /// * `flags` - Export or import flags
+    #[inline]
+    #[must_use]
+    pub fn new(mem_id: MemId, kind: EntryKind, size: u64, flags: u64) -> Self {
+        let now = unix_now();
+        Self {
+            mem_id,
+            kind,
+            size,
+            flags,
+            numa_node: None,
+            peer: None,
+            lease: None,
+            owner_pid: std::process::id(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Set the NUMA node of the region
+    #[inline]
+    #[must_use]
+    pub const fn with_numa_node(mut self, numa_node: i32) -> Self {
+        self.numa_node = Some(numa_node);
+        self
+    }
+
+    /// Set the peer the region is granted to
+    #[inline]
+    #[must_use]
+    pub const fn with_peer(mut self, deid: [u8; 16]) -> Self {
+        self.peer = Some(deid);
+        self
+    }
+
+    /// Set the lease of the region
+    #[inline]
+    #[must_use]
+    pub const fn with_lease(mut self, lease: Lease) -> Self {
+        self.lease = Some(lease);
+        self
+    }
+
+    /// Check whether the owning process is still running
+    #[inline]
+    #[must_use]
+    pub fn owner_alive(&self) -> bool {
+        Path::new("/proc").join(self.owner_pid.to_string()).exists()
+    }
+
+    /// Check whether the entry no longer refers to live memory
+    ///
+    /// An entry is stale when its owner has exited and the kernel no
+    /// longer knows its memory ID.
+    #[inline]
+    #[must_use]
+    pub fn is_stale(&self) -> bool {
+        !self.owner_alive() && query_pa_by_memid(self.mem_id, 0).is_err()
+    }
+}
+
+/// Time-limited claim of an importer on an export
+///
+/// Times are in seconds; `expires_at` is seconds since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Lease {
+    /// Lifetime granted by each renewal
+    pub ttl: u64,
+    /// Time past expiry before the export is reclaimed
+    pub grace: u64,
+    /// Expiry time
+    pub expires_at: u64,
+}
+
+impl Lease {
+    /// Create a lease expiring `ttl` from now
+    ///
+    /// Durations are rounded down to whole seconds.
+    #[inline]
+    #[must_use]
+    pub fn new(ttl: Duration, grace: Duration) -> Self {
+        let ttl = ttl.as_secs();
+        Self {
+            ttl,
+            grace: grace.as_secs(),
+            expires_at: unix_now().saturating_add(ttl),
+        }
+    }
+
+    /// Get the lease renewed at `now`
+    #[inline]
+    #[must_use]
+    pub const fn renewed(self, now: u64) -> Self {
+        Self {
+            expires_at: now.saturating_add(self.ttl),
+            ..self
+        }
+    }
+
+    /// Check whether the lease has expired at `now`
+    #[inline]
+    #[must_use]
+    pub const fn is_expired(&self, now: u64) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Check whether the lease expired more than the grace period before
+    /// `now`, so that the export may be reclaimed
+    #[inline]
+    #[must_use]
+    pub const fn is_reclaimable(&self, now: u64) -> bool {
+        now >= self.expires_at.saturating_add(self.grace)
+    }
+
+    /// Get the time left before the lease expires, zero if it has
+    #[inline]
+    #[must_use]
+    pub fn remaining(&self) -> Duration {
+        Duration::from_secs(self.expires_at.saturating_sub(unix_now()))
+    }
+}
+
+/// Limit on the bytes exported to one remote peer
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PeerQuota {
+    /// Destination entity ID of the peer
+    pub deid: [u8; 16],
+    /// Limit on live bytes granted to the peer
+    pub limit: u64,
+}
+
+/// On-disk index format
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct RegistryIndex {
+    /// Format version
+    version: u32,
+    /// Active entries
+    entries: Vec<RegistryEntry>,
+    /// Limits on the bytes granted to each peer
+    #[serde(default)]
+    peer_quotas: Vec<PeerQuota>,
+}
+
+impl RegistryIndex {
+    /// Live bytes exported to a peer, not counting `exclude`
+    fn peer_usage(&self, deid: &[u8; 16], exclude: Option<MemId>) -> u64 {
+        self.entries
+            .iter()
+            .filter(|e| e.kind == EntryKind::Export && e.peer.as_ref() == Some(deid))
+            .filter(|e| Some(e.mem_id) != exclude)
+            .fold(0_u64, |sum, e| sum.saturating_add(e.size))
+    }
+
+    /// Limit of a peer, if any
+    fn peer_limit(&self, deid: &[u8; 16]) -> Option<u64> {
+        self.peer_quotas
+            .iter()
+            .find(|q| q.deid == *deid)
+            .map(|q| q.limit)
+    }
+}
+
+/// Handle to a registry state directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registry {
+    /// State directory
+    dir: PathBuf,
+}
+
+impl Registry {
+    /// Open the registry in `dir`, creating the directory if needed
+    ///
+    /// # Errors
+    /// Returns `ObmmError::IoError` if the directory cannot be created
+    #[inline]
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| io_error(&dir, &e))?;
+        Ok(Self { dir })
+    }
+
+    /// Open the registry in the default state directory
+    ///
+    /// The directory is taken from `MEMLINK_STATE_DIR` if set, otherwise
+    /// `DEFAULT_STATE_DIR` is used.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::IoError` if the directory cannot be created
+    #[inline]
+    pub fn open_default() -> Result<Self> {
+        Self::open(default_state_dir())
+    }
+
+    /// Get the state directory
+    #[inline]
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Get the path of the index file
+    #[inline]
+    #[must_use]
+    pub fn index_path(&self) -> PathBuf {
+        self.dir.join(INDEX_FILE)
+    }
+
+    /// Get the path of the descriptor file of an exported region
+    #[inline]
+    #[must_use]
+    pub fn desc_path(&self, mem_id: MemId) -> PathBuf {
+        self.dir.join(format!("memdesc_{mem_id}.json"))
+    }
+
+    /// List all entries
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read or parsed
+    #[inline]
+    pub fn entries(&self) -> Result<Vec<RegistryEntry>> {
+        Ok(self.read_index()?.entries)
+    }
+
+    /// List exported regions
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read or parsed
+    #[inline]
+    pub fn exports(&self) -> Result<Vec<RegistryEntry>> {
+        self.entries_of(EntryKind::Export)
+    }
+
+    /// List imported regions
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read or parsed
+    #[inline]
+    pub fn imports(&self) -> Result<Vec<RegistryEntry>> {
+        self.entries_of(EntryKind::Import)
+    }
+
+    /// Look up a single entry
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read or parsed
+    #[inline]
+    pub fn get(&self, kind: EntryKind, mem_id: MemId) -> Result<Option<RegistryEntry>> {
+        Ok(self
+            .read_index()?
+            .entries
+            .into_iter()
+            .find(|e| e.kind == kind && e.mem_id == mem_id))
+    }
+
+    /// Insert or replace an entry
+    ///
+    /// Replacing an existing entry keeps its creation time.
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be updated
+    #[inline]
+    pub fn record(&self, entry: RegistryEntry) -> Result<()> {
+        self.update(|index| {
+            match index
+                .entries
+                .iter_mut()
+                .find(|e| e.kind == entry.kind && e.mem_id == entry.mem_id)
+            {
+                Some(existing) => {
+                    let created_at = existing.created_at;
+                    *existing = entry;
+                    existing.created_at = created_at;
+                    existing.updated_at = unix_now();
+                }
+                None => index.entries.push(entry),
+            }
+        })
+    }
+
+    /// Record an exported region and store its descriptor
+    ///
+    /// # Returns
+    /// Path of the stored descriptor file
+    ///
+    /// # Errors
+    /// Returns an error if the descriptor or the index cannot be written
+    #[inline]
+    pub fn record_export<T: Serialize>(
+        &self,
+        mem_id: MemId,
+        flags: ObmmExportFlags,
+        desc: &ObmmMemDesc<T>,
+    ) -> Result<PathBuf> {
+        let path = self.desc_path(mem_id);
+        let json = serde_json::to_vec_pretty(&DescEnvelope::new(desc))
+            .map_err(|e| ObmmError::SerializationError(e.to_string()))?;
+        write_atomic(&path, &json)?;
+
+        let mut entry = RegistryEntry::new(mem_id, EntryKind::Export, desc.length, flags.bits());
+        if desc.deid != [0; 16] {
+            entry = entry.with_peer(desc.deid);
+        }
+        self.record(entry)?;
+        Ok(path)
+    }
+
+    /// Record an imported region
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be updated
+    #[inline]
+    pub fn record_import(
+        &self,
+        mem_id: MemId,
+        size: u64,
+        flags: ObmmExportFlags,
+        numa_node: i32,
+    ) -> Result<()> {
+        self.record(
+            RegistryEntry::new(mem_id, EntryKind::Import, size, flags.bits())
+                .with_numa_node(numa_node),
+        )
+    }
+
+    /// List the per-peer quotas
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read or parsed
+    #[inline]
+    pub fn peer_quotas(&self) -> Result<Vec<PeerQuota>> {
+        Ok(self.read_index()?.peer_quotas)
+    }
+
+    /// Set or clear the quota of a peer
+    ///
+    /// Exports already granted are kept even if they exceed the new limit;
+    /// only later grants are refused.
+    ///
+    /// # Arguments
+    /// * `deid` - Destination entity ID of the peer
+    /// * `limit` - Limit on live bytes granted to the peer, `None` to remove it
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be updated
+    #[inline]
+    pub fn set_peer_quota(&self, deid: [u8; 16], limit: Option<u64>) -> Result<()> {
+        self.update(|index| {
+            index.peer_quotas.retain(|q| q.deid != deid);
+            if let Some(limit) = limit {
+                index.peer_quotas.push(PeerQuota { deid, limit });
+            }
+        })
+    }
+
+    /// Get the live bytes exported to a peer
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read or parsed
+    #[inline]
+    pub fn peer_usage(&self, deid: &[u8; 16]) -> Result<u64> {
+        Ok(self.read_index()?.peer_usage(deid, None))
+    }
+
+    /// Charge an export to a peer, refusing grants over the peer's quota
+    ///
+    /// A registered export is moved to the peer and its size updated;
+    /// otherwise a new export entry without flags is recorded. Re-granting
+    /// the same memory ID does not count it twice.
+    ///
+    /// # Arguments
+    /// * `mem_id` - Memory ID of the export
+    /// * `deid` - Destination entity ID of the peer
+    /// * `size` - Size of the export in bytes
+    ///
+    /// # Errors
+    /// Returns `ObmmError::PeerQuotaExceeded` if the peer would hold more
+    /// than its quota, or an error if the index cannot be updated
+    #[inline]
+    pub fn grant_to_peer(&self, mem_id: MemId, deid: [u8; 16], size: u64) -> Result<()> {
+        self.update(|index| {
+            if let Some(limit) = index.peer_limit(&deid) {
+                let requested = index.peer_usage(&deid, Some(mem_id)).saturating_add(size);
+                if requested > limit {
+                    return Err(ObmmError::PeerQuotaExceeded { requested, limit });
+                }
+            }
+
+            match index
+                .entries
+                .iter_mut()
+                .find(|e| e.kind == EntryKind::Export && e.mem_id == mem_id)
+            {
+                Some(existing) => {
+                    existing.size = size;
+                    existing.peer = Some(deid);
+                    existing.updated_at = unix_now();
+                }
+                None => index
+                    .entries
+                    .push(RegistryEntry::new(mem_id, EntryKind::Export, size, 0).with_peer(deid)),
+            }
+            Ok(())
+        })?
+    }
+
+    /// Release the charge of an export on its peer
+    ///
+    /// The export stays registered but no longer counts against the
+    /// quota of the peer it was granted to. Releasing an export that is
+    /// not registered or not granted to a peer does nothing.
+    ///
+    /// # Arguments
+    /// * `mem_id` - Memory ID of the export
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be updated
+    #[inline]
+    pub fn release_peer(&self, mem_id: MemId) -> Result<()> {
+        self.update(|index| {
+            if let Some(entry) = index
+                .entries
+                .iter_mut()
+                .find(|e| e.kind == EntryKind::Export && e.mem_id == mem_id && e.peer.is_some())
+            {
+                entry.peer = None;
+                entry.updated_at = unix_now();
+            }
+        })
+    }
+
+    /// Set or clear the lease of a registered export
+    ///
+    /// # Arguments
+    /// * `mem_id` - Memory ID of the export
+    /// * `lease` - New lease, `None` to let the export live until unexported
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidMemId` if the export is not registered,
+    /// or an error if the index cannot be updated
+    #[inline]
+    pub fn set_lease(&self, mem_id: MemId, lease: Option<Lease>) -> Result<()> {
+        self.update(|index| {
+            let entry = index
+                .entries
+                .iter_mut()
+                .find(|e| e.kind == EntryKind::Export && e.mem_id == mem_id)
+                .ok_or(ObmmError::InvalidMemId)?;
+            entry.lease = lease;
+            entry.updated_at = unix_now();
+            Ok(())
+        })?
+    }
+
+    /// Renew the lease of an export for another `ttl`
+    ///
+    /// A lease that expired but is still within its grace period can be
+    /// renewed; once past the grace period the export is due for reclaim
+    /// and renewal is refused.
+    ///
+    /// # Returns
+    /// The renewed lease
+    ///
+    /// # Errors
+    /// Returns `ObmmError::LeaseNotFound` if the export is not registered,
+    /// has no lease or is past its grace period, or an error if the index
+    /// cannot be updated
+    #[inline]
+    pub fn renew_lease(&self, mem_id: MemId) -> Result<Lease> {
+        self.update(|index| {
+            let now = unix_now();
+            let entry = index
+                .entries
+                .iter_mut()
+                .find(|e| e.kind == EntryKind::Export && e.mem_id == mem_id)
+                .ok_or(ObmmError::LeaseNotFound(mem_id))?;
+            let lease = entry
+                .lease
+                .filter(|lease| !lease.is_reclaimable(now))
+                .ok_or(ObmmError::LeaseNotFound(mem_id))?
+                .renewed(now);
+            entry.lease = Some(lease);
+            entry.updated_at = now;
+            Ok(lease)
+        })?
+    }
+
+    /// List the exports whose lease ran out past its grace period
+    ///
+    /// The caller unexports them and then removes them with
+    /// [`Registry::remove`].
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be read or parsed
+    #[inline]
+    pub fn expired_leases(&self) -> Result<Vec<RegistryEntry>> {
+        let now = unix_now();
+        let mut entries = self.exports()?;
+        entries.retain(|e| e.lease.is_some_and(|lease| lease.is_reclaimable(now)));
+        Ok(entries)
+    }
+
+    /// Read the stored descriptor of an exported region
+    ///
+    /// # Errors
+    /// Returns an error if the descriptor file is missing or invalid
+    #[inline]
+    pub fn load_desc<T>(&self, mem_id: MemId) -> Result<ObmmMemDesc<T>>
+    where
+        T: for<'de> Deserialize<'de>,
+    {
+        let path = self.desc_path(mem_id);
+        let json = fs::read_to_string(&path).map_err(|e| io_error(&path, &e))?;
+        let value = serde_json::from_str(&json)
+            .map_err(|e| ObmmError::SerializationError(e.to_string()))?;
+        ObmmMemDesc::from_json_value(value)
+    }
+
+    /// Remove an entry, deleting its descriptor file for exports
+    ///
+    /// # Returns
+    /// The removed entry, or `None` if it was not registered
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be updated
+    #[inline]
+    pub fn remove(&self, kind: EntryKind, mem_id: MemId) -> Result<Option<RegistryEntry>> {
+        let removed = self.update(|index| {
+            let pos = index
+                .entries
+                .iter()
+                .position(|e| e.kind == kind && e.mem_id == mem_id)?;
+            Some(index.entries.remove(pos))
+        })?;
+
+        if kind == EntryKind::Export {
+            self.remove_desc(mem_id)?;
+        }
+        Ok(removed)
+    }
+
+    /// Remove stale entries
+    ///
+    /// See [`RegistryEntry::is_stale`].
+    ///
+    /// # Returns
+    /// The removed entries
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be updated
+    #[inline]
+    pub fn gc(&self) -> Result<Vec<RegistryEntry>> {
+        self.gc_with(RegistryEntry::is_stale)
+    }
+
+    /// Remove the entries matching `is_stale`
+    ///
+    /// Descriptor files of removed exports are deleted as well.
+    ///
+    /// # Returns
+    /// The removed entries
+    ///
+    /// # Errors
+    /// Returns an error if the index cannot be updated
+    #[inline]
+    pub fn gc_with<F>(&self, mut is_stale: F) -> Result<Vec<RegistryEntry>>
+    where
+        F: FnMut(&RegistryEntry) -> bool,
+    {
+        let removed = self.update(|index| {
+            let (stale, live) = std::mem::take(&mut index.entries)
+                .into_iter()
+                .partition::<Vec<_>, _>(|e| is_stale(e));
+            index.entries = live;
+            stale
+        })?;
+
+        for entry in removed.iter().filter(|e| e.kind == EntryKind::Export) {
+            self.remove_desc(entry.mem_id)?;
+        }
+        Ok(removed)
+    }
+
+    /// List entries of one kind
+    fn entries_of(&self, kind: EntryKind) -> Result<Vec<RegistryEntry>> {
+        let mut entries = self.entries()?;
+        entries.retain(|e| e.kind == kind);
+        Ok(entries)
+    }
+
+    /// Delete the descriptor file of an export if present
+    fn remove_desc(&self, mem_id: MemId) -> Result<()> {
+        let path = self.desc_path(mem_id);
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(io_error(&path, &e)),
+        }
+    }
+
+    /// Read the index, treating a missing file as empty
+    fn read_index(&self) -> Result<RegistryIndex> {
+        let path = self.index_path();
+        match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)
+                .map_err(|e| ObmmError::SerializationError(format!("{}: {e}", path.display()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RegistryIndex {
+                version: INDEX_VERSION,
+                ..RegistryIndex::default()
+            }),
+            Err(e) => Err(io_error(&path, &e)),
+        }
+    }
+
+    /// Apply `f` to the index under the registry lock and persist the result
+    fn update<R, F>(&self, f: F) -> Result<R>
+    where
+        F: FnOnce(&mut RegistryIndex) -> R,
+    {
+        let lock_path = self.dir.join(LOCK_FILE);
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| io_error(&lock_path, &e))?;
+        lock.lock().map_err(|e| io_error(&lock_path, &e))?;
+
+        let mut index = self.read_index()?;
+        let result = f(&mut index);
+        index.version = INDEX_VERSION;
+        index
+            .entries
+            .sort_by_key(|e| (e.kind == EntryKind::Import, e.mem_id));
+
+        let json = serde_json::to_vec_pretty(&index)
+            .map_err(|e| ObmmError::SerializationError(e.to_string()))?;
+        write_atomic(&self.index_path(), &json)?;
+
+        // The lock is released when `lock` is dropped
+        Ok(result)
+    }
+}
+
+/// Get the state directory configured by the environment
+///
+/// # Returns
+/// `MEMLINK_STATE_DIR` if set and non-empty, otherwise `DEFAULT_STATE_DIR`
+#[inline]
+#[must_use]
+pub fn default_state_dir() -> PathBuf {
+    std::env::var_os(STATE_DIR_ENV)
+        .filter(|dir| !dir.is_empty())
+        .map_or_else(|| PathBuf::from(DEFAULT_STATE_DIR), PathBuf::from)
+}
+
+/// Write a file so that readers see either the old or the new contents
+///
+/// The data is written to a temporary file in the same directory, synced
+/// and renamed over `path`. The directory is synced afterwards so the
+/// rename survives a crash.
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file_name = path
+        .file_name()
+        .ok_or(ObmmError::InvalidInput("registry path has no file name"))?;
+    let mut tmp_name = file_name.to_os_string();
+    tmp_name.push(format!(".tmp.{}", std::process::id()));
+    let tmp = dir.join(tmp_name);
+
+    let write = || -> std::io::Result<()> {
+        let mut file = File::create(&tmp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        File::open(dir)?.sync_all()
+    };
+
+    write().map_err(|e| {
+        let _ = fs::remove_file(&tmp);
+        io_error(path, &e)
+    })
+}
+
+/// Build an I/O error mentioning the path
+fn io_error(path: &Path, err: &std::io::Error) -> ObmmError {
+    ObmmError::IoError(format!("{}: {err}", path.display()))
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
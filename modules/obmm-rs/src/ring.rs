@@ -0,0 +1,416 @@
+//! Single-producer single-consumer ring buffer in shared OBMM memory
+//!
+//! A reference for passing messages through pooled memory: the producer
+//! maps an exported region and formats it with [`RingBuffer::create`], the
+//! consumer imports the region on another node and attaches with
+//! [`RingBuffer::open`].
+//!
+//! # Layout
+//!
+//! ```text
+//! 0             CACHE_LINE      2*CACHE_LINE    RING_HEADER_SIZE
+//! | magic, cap  | head          | tail          | data (cap bytes) ...
+//! ```
+//!
+//! `head` and `tail` are free-running byte counters, written only by the
+//! producer and the consumer respectively and kept on separate cache lines
+//! so the two sides do not contend. Each message is stored as a 4-byte
+//! length followed by the payload, padded to [`RECORD_ALIGN`] so the length
+//! never wraps; the payload may wrap around the end of the data area.
+//!
+//! # Memory ordering
+//!
+//! The producer copies the payload and then publishes it by storing `head`
+//! with `Release` ordering ([`publish_fence`]); the consumer loads `head`
+//! with `Acquire` ordering ([`consume_fence`]) before reading the payload.
+//! The same pairing on `tail` hands space back to the producer. These
+//! fences order accesses but do not write back CPU caches, so the region
+//! must be imported without [`UbPrivData::CACHEABLE`] unless the
+//! interconnect keeps caches coherent.
+//!
+//! [`UbPrivData::CACHEABLE`]: crate::types::UbPrivData::CACHEABLE
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::handle::ExportedMemory;
+//! use obmm_rs::ring::RingBuffer;
+//! use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+//!
+//! let memory = ExportedMemory::<UbPrivData>::export(&[2 * 1024 * 1024], ObmmExportFlags::ALLOWMMAP)
+//!     .expect("Export failed");
+//! let mut ring = RingBuffer::create(memory.map().expect("Map failed")).expect("Region too small");
+//! assert!(ring.push(b"hello").expect("Message too large"));
+//!
+//! // On the importing node:
+//! // let mut ring = RingBuffer::open(imported.map()?)?;
+//! // let mut buf = Vec::new();
+//! // while ring.pop(&mut buf)?.is_some() { ... }
+//! ```
+
+use std::ptr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering, fence};
+
+use crate::error::{ObmmError, Result};
+use crate::mmap::MappedRegion;
+
+/// Marks a formatted ring buffer ("OBMMRING")
+pub const RING_MAGIC: u64 = u64::from_le_bytes(*b"OBMMRING");
+
+/// Version of the ring buffer layout
+pub const RING_VERSION: u32 = 1;
+
+/// Distance between the header fields written by different sides
+pub const CACHE_LINE: usize = 128;
+
+/// Bytes in front of the data area
+pub const RING_HEADER_SIZE: usize = 3 * CACHE_LINE;
+
+/// Alignment of every record in the data area
+pub const RECORD_ALIGN: usize = 8;
+
+/// Size of the length prefix of a record
+const LEN_SIZE: usize = size_of::<u32>();
+
+/// Smallest data area accepted
+const MIN_CAPACITY: usize = 64;
+
+const MAGIC_OFFSET: usize = 0;
+const VERSION_OFFSET: usize = 8;
+const CAPACITY_OFFSET: usize = 16;
+const HEAD_OFFSET: usize = CACHE_LINE;
+const TAIL_OFFSET: usize = 2 * CACHE_LINE;
+
+/// Order payload writes before the index store that publishes them
+///
+/// Called by the producer after copying a message and before advancing
+/// `head`, and by the consumer after reading a message and before
+/// advancing `tail`.
+#[inline]
+pub fn publish_fence() {
+    fence(Ordering::Release);
+}
+
+/// Order the index load before reading what it covers
+///
+/// Called by the consumer after loading `head` and by the producer after
+/// loading `tail`.
+#[inline]
+pub fn consume_fence() {
+    fence(Ordering::Acquire);
+}
+
+/// Round a record length up to [`RECORD_ALIGN`]
+#[inline]
+const fn record_size(payload: usize) -> usize {
+    (LEN_SIZE + payload).next_multiple_of(RECORD_ALIGN)
+}
+
+/// A ring buffer over a mapped OBMM region
+///
+/// Exactly one side may call [`push`](Self::push) and exactly one side may
+/// call [`pop`](Self::pop); the two may be in different processes on
+/// different nodes.
+#[derive(Debug)]
+pub struct RingBuffer<'a> {
+    /// Mapping holding header and data
+    region: MappedRegion<'a>,
+    /// Size of the data area (a power of two)
+    capacity: usize,
+}
+
+impl<'a> RingBuffer<'a> {
+    /// Format a mapping as an empty ring buffer
+    ///
+    /// The data area is the largest power of two that fits behind the
+    /// header. The magic is written last, so [`open`](Self::open) on the
+    /// other side never sees a half-initialised header.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the mapping is too small
+    pub fn create(region: MappedRegion<'a>) -> Result<Self> {
+        let space = region.len().saturating_sub(RING_HEADER_SIZE);
+        if space < MIN_CAPACITY {
+            return Err(ObmmError::InvalidInput(
+                "mapping too small for a ring buffer",
+            ));
+        }
+        let capacity = 1 << space.ilog2();
+
+        let ring = Self { region, capacity };
+        ring.atomic_u64(MAGIC_OFFSET).store(0, Ordering::Relaxed);
+        ring.atomic_u32(VERSION_OFFSET)
+            .store(RING_VERSION, Ordering::Relaxed);
+        ring.atomic_u64(CAPACITY_OFFSET)
+            .store(capacity as u64, Ordering::Relaxed);
+        ring.head().store(0, Ordering::Relaxed);
+        ring.tail().store(0, Ordering::Relaxed);
+        ring.atomic_u64(MAGIC_OFFSET)
+            .store(RING_MAGIC, Ordering::Release);
+        Ok(ring)
+    }
+
+    /// Attach to a ring buffer formatted by [`create`](Self::create)
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the mapping does not hold a
+    /// ring buffer of this version or its capacity exceeds the mapping
+    pub fn open(region: MappedRegion<'a>) -> Result<Self> {
+        if region.len() < RING_HEADER_SIZE + MIN_CAPACITY {
+            return Err(ObmmError::InvalidInput(
+                "mapping too small for a ring buffer",
+            ));
+        }
+        let ring = Self {
+            region,
+            capacity: 0,
+        };
+        if ring.atomic_u64(MAGIC_OFFSET).load(Ordering::Acquire) != RING_MAGIC {
+            return Err(ObmmError::InvalidInput("mapping is not a ring buffer"));
+        }
+        if ring.atomic_u32(VERSION_OFFSET).load(Ordering::Relaxed) != RING_VERSION {
+            return Err(ObmmError::InvalidInput("unsupported ring buffer version"));
+        }
+        let capacity = usize::try_from(ring.atomic_u64(CAPACITY_OFFSET).load(Ordering::Relaxed))
+            .map_err(|_| ObmmError::InvalidInput("ring buffer capacity out of range"))?;
+        if !capacity.is_power_of_two() || capacity > ring.region.len() - RING_HEADER_SIZE {
+            return Err(ObmmError::InvalidInput("ring buffer capacity is invalid"));
+        }
+        Ok(Self { capacity, ..ring })
+    }
+
+    /// Get the size of the data area in bytes
+    #[inline]
+    #[must_use]
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Get the largest message that fits into an empty ring
+    #[inline]
+    #[must_use]
+    pub const fn max_message_len(&self) -> usize {
+        self.capacity - LEN_SIZE
+    }
+
+    /// Get the bytes currently occupied by messages (including framing)
+    #[inline]
+    #[must_use]
+    pub fn used(&self) -> usize {
+        let head = self.head().load(Ordering::Acquire);
+        let tail = self.tail().load(Ordering::Acquire);
+        usize::try_from(head.wrapping_sub(tail)).unwrap_or(usize::MAX)
+    }
+
+    /// Check if no message is waiting
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.used() == 0
+    }
+
+    /// Append a message (producer side)
+    ///
+    /// # Returns
+    /// `Ok(true)` if the message was queued, `Ok(false)` if the ring is too
+    /// full right now
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the message can never fit
+    pub fn push(&mut self, msg: &[u8]) -> Result<bool> {
+        if msg.len() > self.max_message_len() {
+            return Err(ObmmError::InvalidInput(
+                "message larger than the ring buffer",
+            ));
+        }
+        let size = record_size(msg.len());
+
+        let head = self.head().load(Ordering::Relaxed);
+        let tail = self.tail().load(Ordering::Relaxed);
+        // Space freed by the consumer must not be overwritten before its reads
+        consume_fence();
+        if head.wrapping_sub(tail) as usize + size > self.capacity {
+            return Ok(false);
+        }
+
+        let pos = self.index(head);
+        // `msg.len()` fits in u32: it is below the capacity check above
+        self.write(pos, &(msg.len() as u32).to_le_bytes());
+        self.write(self.index(head + LEN_SIZE as u64), msg);
+
+        publish_fence();
+        self.head()
+            .store(head.wrapping_add(size as u64), Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Take the oldest message (consumer side)
+    ///
+    /// The message replaces the contents of `buf`.
+    ///
+    /// # Returns
+    /// `Ok(Some(len))` with the message length, `Ok(None)` if the ring is
+    /// empty
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the record is corrupt
+    pub fn pop(&mut self, buf: &mut Vec<u8>) -> Result<Option<usize>> {
+        let tail = self.tail().load(Ordering::Relaxed);
+        let head = self.head().load(Ordering::Relaxed);
+        consume_fence();
+        let used = head.wrapping_sub(tail) as usize;
+        if used == 0 {
+            return Ok(None);
+        }
+
+        let mut len = [0; LEN_SIZE];
+        self.read(self.index(tail), &mut len);
+        let len = u32::from_le_bytes(len) as usize;
+        let size = record_size(len);
+        if size > used {
+            return Err(ObmmError::InvalidInput("corrupt ring buffer record"));
+        }
+
+        buf.clear();
+        buf.resize(len, 0);
+        self.read(self.index(tail + LEN_SIZE as u64), buf);
+
+        publish_fence();
+        self.tail()
+            .store(tail.wrapping_add(size as u64), Ordering::Relaxed);
+        Ok(Some(len))
+    }
+
+    /// Give back the mapping
+    #[inline]
+    #[must_use]
+    pub fn into_inner(self) -> MappedRegion<'a> {
+        self.region
+    }
+
+    /// Position of a free-running counter within the data area
+    #[inline]
+    const fn index(&self, counter: u64) -> usize {
+        (counter as usize) & (self.capacity - 1)
+    }
+
+    fn atomic_u64(&self, offset: usize) -> &AtomicU64 {
+        // SAFETY: the offset lies in the page-aligned header, is 8-byte
+        // aligned and the mapping lives as long as `self`
+        unsafe { AtomicU64::from_ptr(self.region.as_ptr().add(offset).cast()) }
+    }
+
+    fn atomic_u32(&self, offset: usize) -> &AtomicU32 {
+        // SAFETY: as for `atomic_u64`, with 4-byte alignment
+        unsafe { AtomicU32::from_ptr(self.region.as_ptr().add(offset).cast()) }
+    }
+
+    fn head(&self) -> &AtomicU64 {
+        self.atomic_u64(HEAD_OFFSET)
+    }
+
+    fn tail(&self) -> &AtomicU64 {
+        self.atomic_u64(TAIL_OFFSET)
+    }
+
+    /// Copy `src` into the data area at `pos`, wrapping at the end
+    fn write(&mut self, pos: usize, src: &[u8]) {
+        let first = src.len().min(self.capacity - pos);
+        let data = self.data();
+        // SAFETY: both parts lie within the data area; the space between
+        // tail and head + capacity belongs to the producer
+        unsafe {
+            ptr::copy_nonoverlapping(src.as_ptr(), data.add(pos), first);
+            ptr::copy_nonoverlapping(src.as_ptr().add(first), data, src.len() - first);
+        }
+    }
+
+    /// Copy from the data area at `pos` into `dst`, wrapping at the end
+    fn read(&self, pos: usize, dst: &mut [u8]) {
+        let first = dst.len().min(self.capacity - pos);
+        let data = self.data();
+        // SAFETY: both parts lie within the data area; the space between
+        // tail and head belongs to the consumer
+        unsafe {
+            ptr::copy_nonoverlapping(data.add(pos), dst.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(data, dst.as_mut_ptr().add(first), dst.len() - first);
+        }
+    }
+
+    fn data(&self) -> *mut u8 {
+        // SAFETY: the mapping is larger than the header
+        unsafe { self.region.as_ptr().add(RING_HEADER_SIZE) }
+    }
+}
+
+#[cfg(all(test, not(feature = "native")))]
+mod tests {
+    use super::*;
+
+    const REGION: usize = 8192;
+
+    fn ring() -> RingBuffer<'static> {
+        let region = MappedRegion::map(1, REGION as u64, 0, REGION).expect("map");
+        RingBuffer::create(region).expect("create")
+    }
+
+    #[test]
+    fn test_create_and_open() {
+        let ring = ring();
+        assert_eq!(ring.capacity(), 4096);
+        assert!(ring.is_empty());
+
+        let ring = RingBuffer::open(ring.into_inner()).expect("open");
+        assert_eq!(ring.capacity(), 4096);
+
+        let blank = MappedRegion::map(1, REGION as u64, 0, REGION).expect("map");
+        assert!(RingBuffer::open(blank).is_err());
+        let small = MappedRegion::map(1, REGION as u64, 0, RING_HEADER_SIZE).expect("map");
+        assert!(RingBuffer::create(small).is_err());
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut ring = ring();
+        let mut buf = Vec::new();
+        assert_eq!(ring.pop(&mut buf).unwrap(), None);
+
+        assert!(ring.push(b"hello").unwrap());
+        assert!(ring.push(b"").unwrap());
+        assert_eq!(ring.used(), 16 + 8);
+
+        assert_eq!(ring.pop(&mut buf).unwrap(), Some(5));
+        assert_eq!(buf, b"hello");
+        assert_eq!(ring.pop(&mut buf).unwrap(), Some(0));
+        assert!(buf.is_empty());
+        assert!(ring.is_empty());
+
+        let too_big = vec![0; ring.max_message_len() + 1];
+        assert!(ring.push(&too_big).is_err());
+    }
+
+    #[test]
+    fn test_full_and_wrap_around() {
+        let mut ring = ring();
+        let mut buf = Vec::new();
+
+        // 1000-byte messages take 1008 bytes: four fit into 4096
+        let msg = |n: u8| vec![n; 1000];
+        for n in 0..4 {
+            assert!(ring.push(&msg(n)).unwrap());
+        }
+        assert!(!ring.push(&msg(4)).unwrap());
+
+        // Keep going so payloads straddle the end of the data area
+        for n in 4..20 {
+            assert_eq!(ring.pop(&mut buf).unwrap(), Some(1000));
+            assert_eq!(buf, msg(n - 4));
+            assert!(ring.push(&msg(n)).unwrap());
+        }
+        for n in 16..20 {
+            ring.pop(&mut buf).unwrap();
+            assert_eq!(buf, msg(n));
+        }
+        assert!(ring.is_empty());
+    }
+}
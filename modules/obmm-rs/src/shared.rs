@@ -0,0 +1,210 @@
+//! Reference-counted imports shared within a process
+//!
+//! [`ImportedMemory`] has a single owner. When several components of one
+//! process need the same remote region, [`SharedImportedMemory`] lets them
+//! share one import: importing a descriptor that is already imported
+//! returns another reference to the existing import, and the region is
+//! unimported only when the last reference is dropped.
+//!
+//! Imports are deduplicated through a process-local table keyed by the
+//! exporter's identity in the descriptor (source EID and CNA, address,
+//! length and token ID).
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::import::ImportOptions;
+//! use obmm_rs::shared::SharedImportedMemory;
+//! use obmm_rs::types::{ObmmMemDesc, UbPrivData};
+//!
+//! let desc = ObmmMemDesc::<UbPrivData>::from_json_file(42).expect("No descriptor");
+//! let options = ImportOptions::new();
+//!
+//! let cache = SharedImportedMemory::import(&desc, &options).expect("Import failed");
+//! let log = SharedImportedMemory::import(&desc, &options).expect("Import failed");
+//! assert_eq!(cache.mem_id(), log.mem_id());
+//! assert_eq!(cache.ref_count(), 2);
+//! // Unimported once both `cache` and `log` are dropped
+//! ```
+
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError, Weak};
+
+use crate::error::Result;
+use crate::handle::ImportedMemory;
+use crate::import::ImportOptions;
+use crate::types::{MemId, ObmmMemDesc, UbPrivData};
+
+/// Identity of an exported region as seen in its descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct DescKey {
+    /// Source EID
+    seid: [u8; 16],
+    /// Source CNA
+    scna: u32,
+    /// Base address
+    addr: u64,
+    /// Length in bytes
+    length: u64,
+    /// Token ID
+    tokenid: u32,
+}
+
+impl DescKey {
+    /// Key of a descriptor
+    const fn of(desc: &ObmmMemDesc<UbPrivData>) -> Self {
+        Self {
+            seid: desc.seid,
+            scna: desc.scna,
+            addr: desc.addr,
+            length: desc.length,
+            tokenid: desc.tokenid,
+        }
+    }
+}
+
+/// Live shared imports of this process
+static SHARED_IMPORTS: LazyLock<Mutex<HashMap<DescKey, Weak<SharedInner>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Lock the shared import table, ignoring poisoning (entries stay valid)
+fn shared_imports() -> MutexGuard<'static, HashMap<DescKey, Weak<SharedInner>>> {
+    SHARED_IMPORTS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// State shared by all references to one import
+#[derive(Debug)]
+struct SharedInner {
+    /// Table key of the import
+    key: DescKey,
+    /// The import itself
+    memory: ImportedMemory,
+}
+
+impl Drop for SharedInner {
+    #[inline]
+    fn drop(&mut self) {
+        let mut imports = shared_imports();
+        // The entry may already point to a newer import of the same region
+        if imports
+            .get(&self.key)
+            .is_some_and(|weak| weak.strong_count() == 0)
+        {
+            imports.remove(&self.key);
+        }
+        // Unimport under the lock so a concurrent import of the same
+        // descriptor does not race with the teardown
+        let _result = self.memory.unimport();
+    }
+}
+
+/// A reference-counted handle to an imported region
+///
+/// Cloning the handle adds a reference; the region is unimported when the
+/// last clone is dropped. Dereferences to [`ImportedMemory`] for the
+/// memory ID, NUMA node and mapping.
+#[derive(Debug, Clone)]
+pub struct SharedImportedMemory {
+    /// Shared import state
+    inner: Arc<SharedInner>,
+}
+
+impl SharedImportedMemory {
+    /// Import a region, or share an existing import of the same descriptor
+    ///
+    /// `options` only apply when a new import is made; an existing import
+    /// is returned as is.
+    ///
+    /// # Errors
+    /// Returns the error of [`ImportedMemory::import_with`] if a new import
+    /// fails
+    #[inline]
+    pub fn import(desc: &ObmmMemDesc<UbPrivData>, options: &ImportOptions) -> Result<Self> {
+        let key = DescKey::of(desc);
+        let mut imports = shared_imports();
+        if let Some(inner) = imports.get(&key).and_then(Weak::upgrade) {
+            return Ok(Self { inner });
+        }
+
+        let memory = ImportedMemory::import_with(desc, options)?;
+        let inner = Arc::new(SharedInner { key, memory });
+        imports.insert(key, Arc::downgrade(&inner));
+        Ok(Self { inner })
+    }
+
+    /// Find a shared import by its local memory ID
+    ///
+    /// # Returns
+    /// A new reference to the import, or `None` if no shared import has
+    /// this memory ID
+    #[inline]
+    #[must_use]
+    pub fn get(mem_id: MemId) -> Option<Self> {
+        // Drop the other references after unlocking: if one of them turns
+        // out to be the last, its teardown takes the lock again
+        let live: Vec<_> = shared_imports()
+            .values()
+            .filter_map(Weak::upgrade)
+            .collect();
+        live.into_iter()
+            .find(|inner| inner.memory.mem_id() == mem_id)
+            .map(|inner| Self { inner })
+    }
+
+    /// Get the number of live references to this import
+    #[inline]
+    #[must_use]
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.inner)
+    }
+}
+
+impl Deref for SharedImportedMemory {
+    type Target = ImportedMemory;
+
+    #[inline]
+    fn deref(&self) -> &ImportedMemory {
+        &self.inner.memory
+    }
+}
+
+#[cfg(all(test, not(feature = "native")))]
+mod tests {
+    use super::*;
+
+    fn test_desc(tokenid: u32) -> ObmmMemDesc<UbPrivData> {
+        let mut desc = ObmmMemDesc::<UbPrivData>::new();
+        desc.addr = 0xffff_fc00_0000;
+        desc.length = 2 * 1024 * 1024;
+        desc.seid = [0x5e; 16];
+        desc.tokenid = tokenid;
+        desc
+    }
+
+    #[test]
+    fn test_shared_import_dedup() {
+        let desc = test_desc(0x5a5a_0001);
+        let options = ImportOptions::new();
+
+        let first = SharedImportedMemory::import(&desc, &options).unwrap();
+        let second = SharedImportedMemory::import(&desc, &options).unwrap();
+        assert!(Arc::ptr_eq(&first.inner, &second.inner));
+        assert_eq!(first.ref_count(), 2);
+        assert_eq!(second.length(), desc.length);
+
+        let other = SharedImportedMemory::import(&test_desc(0x5a5a_0002), &options).unwrap();
+        assert!(!Arc::ptr_eq(&first.inner, &other.inner));
+
+        drop(second);
+        assert_eq!(first.ref_count(), 1);
+        let key = DescKey::of(&desc);
+        drop(first);
+        assert!(!shared_imports().contains_key(&key));
+
+        let again = SharedImportedMemory::import(&desc, &options).unwrap();
+        assert_eq!(again.ref_count(), 1);
+    }
+}
@@ -0,0 +1,395 @@
+//! Descriptor signing for cross-node trust
+//!
+//! Memory descriptors travel between nodes as plain JSON, so a tampered
+//! `addr` or `length` could make the importer map the wrong physical
+//! region. This module authenticates descriptors with HMAC-SHA256 under a
+//! key shared by the cooperating nodes, and defines a sealed envelope that
+//! carries a descriptor together with its signature.
+//!
+//! The signature also covers the memory ID the descriptor is published
+//! under and when it was sealed, so an envelope cannot be replayed for
+//! another memory ID, nor accepted once it is older than the age the
+//! importer allows.
+//!
+//! Only available with the `crypto` feature, which pulls in the `hmac` and
+//! `sha2` crates.
+//!
+//! # Example
+//!
+//! ```
+//! use obmm_rs::sign::{SEAL_MAX_AGE, SealedDesc};
+//! use obmm_rs::types::{ObmmMemDesc, UbPrivData};
+//!
+//! let key = b"cluster shared secret";
+//! let mut desc = ObmmMemDesc::<UbPrivData>::new();
+//! desc.addr = 0xffff_fc00_0000;
+//! desc.length = 2 * 1024 * 1024;
+//!
+//! let json = desc.seal(key, 7).to_json().expect("Serialization failed");
+//!
+//! // On the importing node
+//! let desc = SealedDesc::from_json(&json)
+//!     .and_then(|sealed| sealed.open(key, 7, SEAL_MAX_AGE))
+//!     .expect("Descriptor was tampered with");
+//! assert_eq!(desc.length, 2 * 1024 * 1024);
+//! ```
+
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha256;
+
+use crate::error::{ObmmError, Result};
+use crate::types::{MemId, ObmmMemDesc, UbPrivData};
+
+/// Length of a descriptor signature in bytes
+pub const SIGNATURE_LEN: usize = 32;
+
+/// Version of the sealed envelope format
+pub const SEAL_VERSION: u32 = 2;
+
+/// Default age after which a sealed descriptor is no longer accepted
+pub const SEAL_MAX_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Domain separation prefix of the signed bytes
+const SIGN_DOMAIN: &[u8] = b"obmm-desc-v2";
+
+/// HMAC-SHA256 as provided by the `hmac` and `sha2` crates
+type HmacSha256 = Hmac<Sha256>;
+
+/// HMAC-SHA256 signature of a memory descriptor
+///
+/// Serialized as a lowercase hex string.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Signature([u8; SIGNATURE_LEN]);
+
+impl Signature {
+    /// Compute the HMAC-SHA256 of arbitrary bytes under `key`
+    ///
+    /// For other payloads exchanged between nodes, such as reports, that
+    /// should be authenticated with the same shared key as descriptors.
+    #[inline]
+    #[must_use]
+    pub fn compute(key: &[u8], data: &[u8]) -> Self {
+        Self(hmac(key, data).finalize().into_bytes().into())
+    }
+
+    /// Check that this is the HMAC-SHA256 of `data` under `key`
+    ///
+    /// The comparison takes constant time.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SignatureMismatch` if it is not
+    #[inline]
+    pub fn verify(&self, key: &[u8], data: &[u8]) -> Result<()> {
+        hmac(key, data)
+            .verify_slice(&self.0)
+            .map_err(|_| ObmmError::SignatureMismatch)
+    }
+
+    /// Get the raw signature bytes
+    #[inline]
+    #[must_use]
+    pub const fn as_bytes(&self) -> &[u8; SIGNATURE_LEN] {
+        &self.0
+    }
+
+    /// Encode the signature as lowercase hex
+    #[inline]
+    #[must_use]
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    /// Decode a signature from hex
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` if `hex` is not
+    /// `2 * SIGNATURE_LEN` hex digits
+    #[inline]
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let invalid = || ObmmError::SerializationError(format!("invalid signature: {hex:?}"));
+        if hex.len() != SIGNATURE_LEN * 2 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; SIGNATURE_LEN];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+}
+
+impl fmt::Debug for Signature {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Signature({})", self.to_hex())
+    }
+}
+
+impl Serialize for Signature {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_hex())
+    }
+}
+
+impl<'de> Deserialize<'de> for Signature {
+    #[inline]
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        Self::from_hex(&hex).map_err(serde::de::Error::custom)
+    }
+}
+
+impl ObmmMemDesc<UbPrivData> {
+    /// Sign the descriptor with a shared key
+    ///
+    /// Every field is covered by the signature, together with the memory ID
+    /// the descriptor is published under and the time it was issued.
+    ///
+    /// # Arguments
+    /// * `key` - Key shared by the cooperating nodes
+    /// * `mem_id` - Memory ID of the export on the signing node
+    /// * `issued_at` - Issue time in seconds since the Unix epoch
+    #[inline]
+    #[must_use]
+    pub fn sign(&self, key: &[u8], mem_id: MemId, issued_at: u64) -> Signature {
+        Signature::compute(key, &self.signed_bytes(mem_id, issued_at))
+    }
+
+    /// Check a signature produced by [`sign`](Self::sign)
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SignatureMismatch` if the descriptor, memory ID,
+    /// issue time or signature was modified or a different key was used
+    #[inline]
+    pub fn verify(
+        &self,
+        key: &[u8],
+        mem_id: MemId,
+        issued_at: u64,
+        signature: &Signature,
+    ) -> Result<()> {
+        signature.verify(key, &self.signed_bytes(mem_id, issued_at))
+    }
+
+    /// Sign the descriptor now and wrap it in a sealed envelope
+    ///
+    /// # Arguments
+    /// * `key` - Key shared by the cooperating nodes
+    /// * `mem_id` - Memory ID of the export on the signing node
+    #[inline]
+    #[must_use]
+    pub fn seal(self, key: &[u8], mem_id: MemId) -> SealedDesc {
+        let issued_at = unix_now();
+        let signature = self.sign(key, mem_id, issued_at);
+        SealedDesc {
+            version: SEAL_VERSION,
+            mem_id,
+            issued_at,
+            desc: self,
+            signature,
+        }
+    }
+
+    /// Canonical byte encoding of the descriptor covered by the signature
+    fn signed_bytes(&self, mem_id: MemId, issued_at: u64) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SIGN_DOMAIN.len() + 80);
+        bytes.extend_from_slice(SIGN_DOMAIN);
+        bytes.extend_from_slice(&mem_id.to_le_bytes());
+        bytes.extend_from_slice(&issued_at.to_le_bytes());
+        bytes.extend_from_slice(&self.addr.to_le_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        bytes.extend_from_slice(&self.seid);
+        bytes.extend_from_slice(&self.deid);
+        bytes.extend_from_slice(&self.tokenid.to_le_bytes());
+        bytes.extend_from_slice(&self.scna.to_le_bytes());
+        bytes.extend_from_slice(&self.dcna.to_le_bytes());
+        bytes.extend_from_slice(&self.priv_len.to_le_bytes());
+        bytes.extend_from_slice(&self.priv_data.bits().to_le_bytes());
+        bytes
+    }
+}
+
+/// A descriptor sealed with its signature
+///
+/// Serialized as
+/// `{"version":2,"mem_id":7,"issued_at":...,"desc":{...},"signature":"<hex>"}`.
+#[derive(Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct SealedDesc {
+    /// Envelope format version
+    pub version: u32,
+    /// Memory ID the descriptor is published under
+    pub mem_id: MemId,
+    /// Issue time in seconds since the Unix epoch
+    pub issued_at: u64,
+    /// Signed descriptor
+    #[serde(with = "crate::types::versioned")]
+    pub desc: ObmmMemDesc<UbPrivData>,
+    /// Signature of `mem_id`, `issued_at` and `desc`
+    pub signature: Signature,
+}
+
+impl SealedDesc {
+    /// Verify the envelope and return the descriptor
+    ///
+    /// # Arguments
+    /// * `key` - Key shared by the cooperating nodes
+    /// * `mem_id` - Memory ID the descriptor was requested for
+    /// * `max_age` - How long after sealing the envelope is accepted;
+    ///   envelopes issued further than this in the future are refused too
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` for an unknown envelope
+    /// version, `ObmmError::SignatureMismatch` if verification fails or the
+    /// envelope was sealed for another memory ID and
+    /// `ObmmError::SealExpired` if it is too old
+    #[inline]
+    pub fn open(
+        self,
+        key: &[u8],
+        mem_id: MemId,
+        max_age: Duration,
+    ) -> Result<ObmmMemDesc<UbPrivData>> {
+        if self.version != SEAL_VERSION {
+            return Err(ObmmError::SerializationError(format!(
+                "unsupported envelope version {}",
+                self.version
+            )));
+        }
+        self.desc
+            .verify(key, self.mem_id, self.issued_at, &self.signature)?;
+        if self.mem_id != mem_id {
+            return Err(ObmmError::SignatureMismatch);
+        }
+        let age_secs = i64::try_from(unix_now())
+            .unwrap_or(i64::MAX)
+            .saturating_sub(i64::try_from(self.issued_at).unwrap_or(i64::MAX));
+        if age_secs.unsigned_abs() > max_age.as_secs() {
+            return Err(ObmmError::SealExpired { age_secs });
+        }
+        Ok(self.desc)
+    }
+
+    /// Deserialize an envelope from json format
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` if the JSON is malformed
+    #[inline]
+    pub fn from_json(json_str: &str) -> Result<Self> {
+        serde_json::from_str(json_str).map_err(|e| ObmmError::SerializationError(e.to_string()))
+    }
+
+    /// Serialize the envelope into json format
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` if serialization fails
+    #[inline]
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| ObmmError::SerializationError(e.to_string()))
+    }
+}
+
+/// Start an HMAC-SHA256 of `data` under `key`
+fn hmac(key: &[u8], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac_sha256_vectors() {
+        // RFC 4231 test cases 2 and 6
+        let signature = Signature::compute(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            signature.to_hex(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            signature.verify(b"Jefe", b"what do ya want for nothing?"),
+            Ok(())
+        );
+        assert_eq!(
+            signature.verify(b"Jefe", b"what do ya want for nothing!"),
+            Err(ObmmError::SignatureMismatch)
+        );
+        assert_eq!(
+            Signature::compute(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )
+            .to_hex(),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = b"shared";
+        let mut desc = ObmmMemDesc::<UbPrivData>::new();
+        desc.addr = 0xffff_fc00_0000;
+        desc.length = 4096;
+        let signature = desc.sign(key, 7, 1000);
+        assert_eq!(desc.verify(key, 7, 1000, &signature), Ok(()));
+        for (key, mem_id, issued_at) in [(&b"other"[..], 7, 1000), (key, 8, 1000), (key, 7, 1001)] {
+            assert_eq!(
+                desc.verify(key, mem_id, issued_at, &signature),
+                Err(ObmmError::SignatureMismatch)
+            );
+        }
+
+        let open = |json: &str, mem_id| {
+            SealedDesc::from_json(json)
+                .unwrap()
+                .open(key, mem_id, SEAL_MAX_AGE)
+        };
+        let sealed = desc.seal(key, 7);
+        assert_eq!(sealed.mem_id, 7);
+        let json = sealed.to_json().unwrap();
+        assert_eq!(open(&json, 7).unwrap().addr, 0xffff_fc00_0000);
+
+        // Replayed for another memory ID
+        assert_eq!(open(&json, 8).err(), Some(ObmmError::SignatureMismatch));
+        let tampered = json.replace("\"mem_id\":7", "\"mem_id\":8");
+        assert_eq!(open(&tampered, 8).err(), Some(ObmmError::SignatureMismatch));
+
+        let tampered = json.replace("\"length\":4096", "\"length\":8192");
+        assert_ne!(tampered, json);
+        assert_eq!(open(&tampered, 7).err(), Some(ObmmError::SignatureMismatch));
+        assert!(Signature::from_hex("zz").is_err());
+    }
+
+    #[test]
+    fn test_open_expired() {
+        let key = b"shared";
+        let desc = ObmmMemDesc::<UbPrivData>::new();
+        let issued_at = unix_now() - 600;
+        let sealed = SealedDesc {
+            version: SEAL_VERSION,
+            mem_id: 3,
+            issued_at,
+            signature: desc.sign(key, 3, issued_at),
+            desc,
+        };
+        assert!(matches!(
+            sealed.open(key, 3, SEAL_MAX_AGE),
+            Err(ObmmError::SealExpired { age_secs }) if age_secs >= 600
+        ));
+    }
+}
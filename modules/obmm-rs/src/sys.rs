@@ -10,6 +10,13 @@
 //!
 //! For backward compatibility, this module re-exports the same functions with
 //! the same signatures as the previous FFI-based implementation.
+//!
+//! Because nothing is linked against `libobmm.so`, the crate builds on any
+//! Linux machine. Whether OBMM can actually be used is decided at runtime:
+//! [`is_available`] probes `/dev/obmm`, and operations on a machine without
+//! the kernel module fail with `ObmmError::NotAvailable` at device open.
+//! Bindings generated from the C header are therefore not needed; the
+//! ioctl structures in `kernel_abi` mirror `/usr/include/ub/obmm.h`.
 
 // Re-export all kernel functions when native feature is enabled
 #[cfg(feature = "native")]
@@ -18,6 +25,37 @@ pub use crate::kernel::{
     obmm_query_pa_by_memid, obmm_set_ownership, obmm_unexport, obmm_unimport, obmm_unpreimport,
 };
 
+/// Check whether the OBMM kernel interface is usable on this machine
+///
+/// # Returns
+/// `true` if `/dev/obmm` can be opened
+///
+/// # Example
+/// ```
+/// use obmm_rs::sys;
+///
+/// if !sys::is_available() {
+///     eprintln!("OBMM is not available, skipping");
+/// }
+/// ```
+#[cfg(feature = "native")]
+#[inline]
+#[must_use]
+pub fn is_available() -> bool {
+    crate::device::is_available()
+}
+
+/// Check whether the OBMM kernel interface is usable on this machine
+///
+/// Always `true`: the stub implementations succeed without a kernel.
+#[cfg(not(feature = "native"))]
+#[inline]
+#[must_use]
+pub const fn is_available() -> bool {
+    // Hooked implementation for testing
+    true
+}
+
 // Stub implementations when native feature is disabled
 #[cfg(not(feature = "native"))]
 mod stubs {
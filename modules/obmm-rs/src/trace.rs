@@ -0,0 +1,295 @@
+//! Per-operation tracing with region correlation IDs
+//!
+//! Exports, imports, unexports, unimports, ownership changes and queries
+//! each log one line when they complete, through the `log` crate under the
+//! `obmm_rs::trace` target. The line names the operation, the local memory
+//! ID, the peer EID, the region's [`CorrelationId`], how long the
+//! operation took and its outcome:
+//!
+//! ```text
+//! export mem_id=3 peer=2 region=1@ffff40000000 took=35µs: ok
+//! ```
+//!
+//! Memory IDs are local to each node, so the exporter's and the importer's
+//! memory IDs of one region differ. The correlation ID is derived from the
+//! descriptor the two nodes exchange, so both sides log the same one, and
+//! the lifecycle of a region can be followed across nodes by searching the
+//! logs of both for it. The ID of a region is remembered from its export
+//! or import until its unexport or unimport, so operations that only take
+//! a memory ID are tagged too.
+//!
+//! Lifecycle operations log at `debug` level; queries, which may be polled,
+//! log at `trace` level.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::trace::{self, CorrelationId};
+//! use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+//! use obmm_rs::{EntryKind, mem_export};
+//!
+//! let lengths = [1024 * 1024 * 2];
+//! let (mem_id, desc) = mem_export::<UbPrivData>(&lengths, ObmmExportFlags::ALLOWMMAP)
+//!     .expect("Export failed");
+//! // Hand the ID to the peer along with the descriptor, or log it locally
+//! assert_eq!(trace::correlation(EntryKind::Export, mem_id), Some(CorrelationId::of(&desc)));
+//! println!("exported region {}", CorrelationId::of(&desc));
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::Instant;
+
+use log::Level;
+use serde::{Deserialize, Serialize};
+
+use crate::registry::EntryKind;
+use crate::types::{MemId, ObmmMemDesc};
+
+/// Log target of operation traces
+pub const TRACE_TARGET: &str = "obmm_rs::trace";
+
+/// Identifier of a region shared by its exporter and its importers
+///
+/// Made of the exporter's EID and the base address of the region on the
+/// exporter, both carried by the descriptor, which no two live exports
+/// of a node share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct CorrelationId {
+    /// EID of the exporting node
+    pub seid: [u8; 16],
+    /// Base address of the region on the exporting node
+    pub addr: u64,
+}
+
+impl CorrelationId {
+    /// Get the correlation ID of the region a descriptor describes
+    #[inline]
+    #[must_use]
+    pub const fn of<T>(desc: &ObmmMemDesc<T>) -> Self {
+        Self {
+            seid: desc.seid,
+            addr: desc.addr,
+        }
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}@{:x}", u128::from_le_bytes(self.seid), self.addr)
+    }
+}
+
+/// What is remembered of a live region
+#[derive(Debug, Clone, Copy)]
+struct Tag {
+    /// Correlation ID of the region
+    correlation: CorrelationId,
+    /// EID of the other side, if known
+    peer: Option<[u8; 16]>,
+}
+
+/// Tags of the live regions of this process
+static TAGS: LazyLock<Mutex<HashMap<(EntryKind, MemId), Tag>>> = LazyLock::new(Default::default);
+
+/// Get the correlation ID of a live region exported or imported by this
+/// process
+///
+/// # Returns
+/// `None` if the region was not exported or imported through this crate,
+/// or is gone
+#[inline]
+#[must_use]
+pub fn correlation(kind: EntryKind, mem_id: MemId) -> Option<CorrelationId> {
+    lookup(kind, mem_id).map(|tag| tag.correlation)
+}
+
+/// Get the tag of a live region
+fn lookup(kind: EntryKind, mem_id: MemId) -> Option<Tag> {
+    TAGS.lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&(kind, mem_id))
+        .copied()
+}
+
+/// EID of a descriptor side, `None` if unset
+fn eid(eid: [u8; 16]) -> Option<[u8; 16]> {
+    (eid != [0; 16]).then_some(eid)
+}
+
+/// One traced operation, logged when it completes
+#[derive(Debug)]
+pub(crate) struct OpSpan {
+    /// Operation name
+    op: &'static str,
+    /// Level of the completion line
+    level: Level,
+    /// Local memory ID, once known
+    mem_id: Option<MemId>,
+    /// Tag of the region, once known
+    tag: Option<Tag>,
+    /// When the operation started
+    start: Instant,
+}
+
+impl OpSpan {
+    /// Start tracing a lifecycle operation
+    pub(crate) fn lifecycle(op: &'static str) -> Self {
+        Self {
+            op,
+            level: Level::Debug,
+            mem_id: None,
+            tag: None,
+            start: Instant::now(),
+        }
+    }
+
+    /// Start tracing a query
+    pub(crate) fn query(op: &'static str) -> Self {
+        Self {
+            level: Level::Trace,
+            ..Self::lifecycle(op)
+        }
+    }
+
+    /// Set the memory ID of a region of `kind`, tagging the span with the
+    /// region's correlation ID if it is known
+    pub(crate) fn region(mut self, kind: EntryKind, mem_id: MemId) -> Self {
+        self.mem_id = Some(mem_id);
+        self.tag = lookup(kind, mem_id);
+        self
+    }
+
+    /// Set the memory ID of a region that may be exported or imported
+    pub(crate) fn mem_id(mut self, mem_id: MemId) -> Self {
+        self.mem_id = Some(mem_id);
+        self.tag = lookup(EntryKind::Export, mem_id).or_else(|| lookup(EntryKind::Import, mem_id));
+        self
+    }
+
+    /// Log a successful export and remember its region
+    pub(crate) fn exported<T>(mut self, mem_id: MemId, desc: &ObmmMemDesc<T>) {
+        self.remember(EntryKind::Export, mem_id, eid(desc.deid), desc);
+        self.ok();
+    }
+
+    /// Log a successful import and remember its region
+    pub(crate) fn imported<T>(mut self, mem_id: MemId, desc: &ObmmMemDesc<T>) {
+        self.remember(EntryKind::Import, mem_id, eid(desc.seid), desc);
+        self.ok();
+    }
+
+    /// Log a successful unexport or unimport and forget its region
+    pub(crate) fn released(self, kind: EntryKind) {
+        if let Some(mem_id) = self.mem_id {
+            TAGS.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .remove(&(kind, mem_id));
+        }
+        self.ok();
+    }
+
+    /// Log a successful operation
+    pub(crate) fn ok(&self) {
+        log::log!(target: TRACE_TARGET, self.level, "{self}: ok");
+    }
+
+    /// Log a failed operation, passing the error through
+    pub(crate) fn fail<E: fmt::Display>(&self, err: E) -> E {
+        log::log!(target: TRACE_TARGET, self.level, "{self}: {err}");
+        err
+    }
+
+    /// Tag the span with a new region and remember it
+    fn remember<T>(
+        &mut self,
+        kind: EntryKind,
+        mem_id: MemId,
+        peer: Option<[u8; 16]>,
+        desc: &ObmmMemDesc<T>,
+    ) {
+        let tag = Tag {
+            correlation: CorrelationId::of(desc),
+            peer,
+        };
+        TAGS.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert((kind, mem_id), tag);
+        self.mem_id = Some(mem_id);
+        self.tag = Some(tag);
+    }
+}
+
+impl fmt::Display for OpSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.op)?;
+        if let Some(mem_id) = self.mem_id {
+            write!(f, " mem_id={mem_id}")?;
+        }
+        if let Some(tag) = self.tag {
+            if let Some(peer) = tag.peer {
+                write!(f, " peer={:x}", u128::from_le_bytes(peer))?;
+            }
+            write!(f, " region={}", tag.correlation)?;
+        }
+        write!(f, " took={:?}", self.start.elapsed())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::UbPrivData;
+
+    fn desc(seid: u8, deid: u8, addr: u64) -> ObmmMemDesc<UbPrivData> {
+        let mut desc = ObmmMemDesc::<UbPrivData> {
+            addr,
+            ..Default::default()
+        };
+        desc.seid[0] = seid;
+        desc.deid[0] = deid;
+        desc
+    }
+
+    #[test]
+    fn test_correlation_id() {
+        let exported = desc(1, 2, 0xffff_4000_0000);
+        let id = CorrelationId::of(&exported);
+        assert_eq!(id.to_string(), "1@ffff40000000");
+        // Only the exporter's EID and address matter, not the destination
+        assert_eq!(CorrelationId::of(&desc(1, 3, 0xffff_4000_0000)), id);
+        assert_ne!(CorrelationId::of(&desc(1, 2, 0xffff_8000_0000)), id);
+    }
+
+    #[test]
+    fn test_span_tags_region_lifecycle() {
+        let exported = desc(1, 2, 0xffff_4000_0000);
+        let mem_id = 0xdead_0001;
+        OpSpan::lifecycle("export").exported(mem_id, &exported);
+        assert_eq!(
+            correlation(EntryKind::Export, mem_id),
+            Some(CorrelationId::of(&exported))
+        );
+        assert_eq!(correlation(EntryKind::Import, mem_id), None);
+
+        // Operations that only know the memory ID are tagged
+        let span = OpSpan::query("query_importers").region(EntryKind::Export, mem_id);
+        let line = span.to_string();
+        assert!(
+            line.starts_with(
+                "query_importers mem_id=3735879681 peer=2 region=1@ffff40000000 took="
+            )
+        );
+
+        OpSpan::lifecycle("unexport")
+            .region(EntryKind::Export, mem_id)
+            .released(EntryKind::Export);
+        assert_eq!(correlation(EntryKind::Export, mem_id), None);
+        let err = OpSpan::lifecycle("unexport")
+            .region(EntryKind::Export, mem_id)
+            .fail("gone");
+        assert_eq!(err, "gone");
+    }
+}
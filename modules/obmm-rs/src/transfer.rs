@@ -0,0 +1,704 @@
+//! Ownership transfer protocol between nodes
+//!
+//! Moving a region from one node to another takes several steps that must
+//! happen in order:
+//!
+//! 1. The source exports the memory ([`SourceTransfer::new`])
+//! 2. The source grants it by sending a serialized [`TransferOffer`]
+//! 3. The destination imports it and answers with a [`TransferAck`]
+//! 4. The source revokes its own access via `set_ownership`
+//! 5. The source confirms, producing a [`TransferReceipt`]
+//!
+//! The source side is a typed state machine: `confirm` only exists once
+//! `revoke` has succeeded, so the revocation step cannot be skipped.
+//!
+//! The offer carries the [`MemoryAttributes`] the source requires, set
+//! with [`SourceTransfer::require_coherence`]. The destination negotiates
+//! them against its own capabilities in [`TransferOffer::accept_with`] and
+//! refuses the import on a mismatch; the source checks the agreed
+//! attributes echoed in the acknowledgement again before revoking.
+//!
+//! [`SourceTransfer::grant_checked`] charges the grant to the destination
+//! peer in a [`Registry`] first, refusing it if the peer's quota would be
+//! exceeded. The charge is released when the transfer is aborted or the
+//! source revokes its access.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use obmm_rs::handle::ExportedMemory;
+//! use obmm_rs::import::ImportOptions;
+//! use obmm_rs::ownership::ObmmDevice;
+//! use obmm_rs::transfer::{SourceTransfer, TransferAck, TransferOffer};
+//! use obmm_rs::types::{ObmmExportFlags, UbPrivData};
+//!
+//! # fn main() -> obmm_rs::Result<()> {
+//! // Source node
+//! let memory = ExportedMemory::<UbPrivData>::export(&[1024 * 1024 * 2], ObmmExportFlags::ALLOWMMAP)?;
+//! let mut device = ObmmDevice::open(memory.mem_id())?;
+//! let (granted, offer) = SourceTransfer::new(memory).grant();
+//! let offer_json = offer.to_json()?;
+//!
+//! // Destination node
+//! let offer = TransferOffer::from_json(&offer_json)?;
+//! let (imported, ack) = offer.accept(&ImportOptions::new())?;
+//! let ack_json = ack.to_json()?;
+//!
+//! // Source node
+//! let ack = TransferAck::from_json(&ack_json)?;
+//! let revoked = granted
+//!     .revoke(&ack, &mut device, 0xffff_fc00_0000, 0xffff_fc20_0000)
+//!     .map_err(|(_granted, e)| e)?;
+//! let (_memory, receipt) = revoked.confirm();
+//! println!("{} is now imported as {}", receipt.mem_id, receipt.remote_mem_id);
+//! # drop(imported);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::attributes::{Coherence, MemoryAttributes};
+use crate::error::{AttributeError, ObmmError, Result};
+use crate::handle::{ExportedMemory, ImportedMemory};
+use crate::import::ImportOptions;
+use crate::ownership::{ObmmDevice, prot};
+use crate::registry::Registry;
+use crate::types::{MemId, ObmmMemDesc, UbPrivData};
+
+/// Counter making transfer IDs unique within a process
+static TRANSFER_SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// Identifier of a transfer
+pub type TransferId = u64;
+
+/// Offer sent from the source to the destination
+#[derive(Serialize, Deserialize, Debug)]
+#[non_exhaustive]
+pub struct TransferOffer {
+    /// Transfer identifier
+    pub transfer_id: TransferId,
+    /// Memory ID on the source node
+    pub mem_id: MemId,
+    /// Descriptor of the exported memory
+    #[serde(with = "crate::types::versioned")]
+    pub desc: ObmmMemDesc<UbPrivData>,
+    /// Attributes the source requires, absent in offers from sources that
+    /// predate attribute negotiation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<MemoryAttributes>,
+}
+
+impl TransferOffer {
+    /// Get the attributes the source requires
+    ///
+    /// Falls back to [`MemoryAttributes::of_desc`] for offers without
+    /// attributes.
+    #[inline]
+    #[must_use]
+    pub fn requirements(&self) -> MemoryAttributes {
+        self.attributes
+            .unwrap_or_else(|| MemoryAttributes::of_desc(&self.desc))
+    }
+
+    /// Import the offered memory and acknowledge the transfer
+    ///
+    /// Negotiates as an importer that supports every privilege data feature
+    /// but provides no coherence, so offers of cacheable memory that stays
+    /// shared are refused; use [`accept_with`](Self::accept_with) to state
+    /// the capabilities of this host.
+    ///
+    /// # Arguments
+    /// * `options` - Import flags and NUMA placement policy
+    ///
+    /// # Returns
+    /// The imported memory and the acknowledgement to send back
+    ///
+    /// # Errors
+    /// Returns `ObmmError::AttributeMismatch` if the source requires more
+    /// coherence, or a kernel error if the import operation fails
+    #[inline]
+    pub fn accept(&self, options: &ImportOptions) -> Result<(ImportedMemory, TransferAck)> {
+        self.accept_with(
+            options,
+            &MemoryAttributes::new(UbPrivData::all(), Coherence::None),
+        )
+    }
+
+    /// Negotiate attributes, import the offered memory and acknowledge
+    ///
+    /// The memory is only imported once negotiation succeeded.
+    ///
+    /// # Arguments
+    /// * `options` - Import flags and NUMA placement policy
+    /// * `capabilities` - Features and coherence this host provides
+    ///
+    /// # Returns
+    /// The imported memory and the acknowledgement to send back, which
+    /// carries the agreed attributes
+    ///
+    /// # Errors
+    /// Returns `ObmmError::AttributeMismatch` if the capabilities do not
+    /// meet the source's requirements, or a kernel error if the import
+    /// operation fails
+    #[inline]
+    pub fn accept_with(
+        &self,
+        options: &ImportOptions,
+        capabilities: &MemoryAttributes,
+    ) -> Result<(ImportedMemory, TransferAck)> {
+        let agreed = self.requirements().negotiate(capabilities)?;
+        let memory = ImportedMemory::import_with(&self.desc, options)?;
+        let ack = TransferAck {
+            transfer_id: self.transfer_id,
+            mem_id: self.mem_id,
+            remote_mem_id: memory.mem_id(),
+            numa_node: memory.numa_node(),
+            attributes: Some(agreed),
+        };
+        Ok((memory, ack))
+    }
+
+    /// Serialize the offer to JSON
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` if serialization fails
+    #[inline]
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| ObmmError::SerializationError(e.to_string()))
+    }
+
+    /// Deserialize an offer from JSON
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` if the JSON is invalid
+    #[inline]
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| ObmmError::SerializationError(e.to_string()))
+    }
+}
+
+/// Acknowledgement sent from the destination back to the source
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TransferAck {
+    /// Transfer identifier from the offer
+    pub transfer_id: TransferId,
+    /// Memory ID on the source node
+    pub mem_id: MemId,
+    /// Memory ID of the import on the destination node
+    pub remote_mem_id: MemId,
+    /// NUMA node of the import on the destination node
+    pub numa_node: i32,
+    /// Attributes agreed by the destination, absent in acknowledgements
+    /// from destinations that predate attribute negotiation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<MemoryAttributes>,
+}
+
+impl TransferAck {
+    /// Serialize the acknowledgement to JSON
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` if serialization fails
+    #[inline]
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string(self).map_err(|e| ObmmError::SerializationError(e.to_string()))
+    }
+
+    /// Deserialize an acknowledgement from JSON
+    ///
+    /// # Errors
+    /// Returns `ObmmError::SerializationError` if the JSON is invalid
+    #[inline]
+    pub fn from_json(json: &str) -> Result<Self> {
+        serde_json::from_str(json).map_err(|e| ObmmError::SerializationError(e.to_string()))
+    }
+}
+
+/// Record of a completed transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TransferReceipt {
+    /// Transfer identifier
+    pub transfer_id: TransferId,
+    /// Memory ID on the source node
+    pub mem_id: MemId,
+    /// Memory ID of the import on the destination node
+    pub remote_mem_id: MemId,
+    /// NUMA node of the import on the destination node
+    pub numa_node: i32,
+    /// Start of the address range revoked on the source
+    pub revoked_start: u64,
+    /// End of the address range revoked on the source
+    pub revoked_end: u64,
+}
+
+/// Memory exported, not yet offered
+#[derive(Debug, Clone, Copy)]
+pub struct Exported;
+
+/// Offer sent, waiting for the acknowledgement
+#[derive(Debug, Clone)]
+pub struct Granted {
+    /// Transfer identifier
+    transfer_id: TransferId,
+    /// Registry the grant is charged in, if granted with `grant_checked`
+    charged: Option<Registry>,
+}
+
+/// Source access revoked, ready to confirm
+#[derive(Debug, Clone, Copy)]
+pub struct Revoked {
+    /// Acknowledgement from the destination
+    ack: TransferAck,
+    /// Start of the revoked range
+    start: u64,
+    /// End of the revoked range
+    end: u64,
+}
+
+/// Source side of an ownership transfer in state `S`
+#[derive(Debug)]
+pub struct SourceTransfer<S> {
+    /// Exported memory being transferred
+    memory: ExportedMemory<UbPrivData>,
+    /// Coherence required of destinations caching the memory
+    coherence: Coherence,
+    /// Protocol state
+    state: S,
+}
+
+impl SourceTransfer<Exported> {
+    /// Start a transfer of exported memory
+    #[inline]
+    #[must_use]
+    pub const fn new(memory: ExportedMemory<UbPrivData>) -> Self {
+        Self {
+            memory,
+            coherence: Coherence::None,
+            state: Exported,
+        }
+    }
+
+    /// Require a coherence level from destinations caching the memory
+    ///
+    /// Defaults to [`Coherence::None`], which suits a plain transfer where
+    /// the source stops accessing the region. A source that keeps writing
+    /// it must require more.
+    #[inline]
+    #[must_use]
+    pub const fn require_coherence(mut self, coherence: Coherence) -> Self {
+        self.coherence = coherence;
+        self
+    }
+
+    /// Grant the memory to a destination
+    ///
+    /// # Returns
+    /// The granted transfer and the offer to send to the destination
+    #[inline]
+    #[must_use]
+    pub fn grant(self) -> (SourceTransfer<Granted>, TransferOffer) {
+        let transfer_id = next_transfer_id();
+        let desc = self.memory.descriptor();
+        let offer = TransferOffer {
+            transfer_id,
+            mem_id: self.memory.mem_id(),
+            desc: ObmmMemDesc {
+                addr: desc.addr,
+                length: desc.length,
+                seid: desc.seid,
+                deid: desc.deid,
+                tokenid: desc.tokenid,
+                scna: desc.scna,
+                dcna: desc.dcna,
+                priv_len: desc.priv_len,
+                priv_data: desc.priv_data,
+            },
+            attributes: Some(self.requirements()),
+        };
+        (
+            SourceTransfer {
+                memory: self.memory,
+                coherence: self.coherence,
+                state: Granted {
+                    transfer_id,
+                    charged: None,
+                },
+            },
+            offer,
+        )
+    }
+
+    /// Grant the memory to a destination within the peer's quota
+    ///
+    /// The export is charged to the peer named by the descriptor's `deid`
+    /// with [`Registry::grant_to_peer`] before the offer is created, and
+    /// released by [`abort`](SourceTransfer::abort) or a successful
+    /// [`revoke`](SourceTransfer::revoke).
+    ///
+    /// # Returns
+    /// The granted transfer and the offer to send to the destination
+    ///
+    /// # Errors
+    /// Returns `ObmmError::PeerQuotaExceeded` if the peer would exceed its
+    /// quota, or an error if the registry cannot be updated; the transfer
+    /// is returned unchanged on error
+    #[inline]
+    pub fn grant_checked(
+        self,
+        registry: &Registry,
+    ) -> std::result::Result<(SourceTransfer<Granted>, TransferOffer), (Self, ObmmError)> {
+        let desc = self.memory.descriptor();
+        match registry.grant_to_peer(self.memory.mem_id(), desc.deid, desc.length) {
+            Ok(()) => {
+                let (mut granted, offer) = self.grant();
+                granted.state.charged = Some(registry.clone());
+                Ok((granted, offer))
+            }
+            Err(e) => Err((self, e)),
+        }
+    }
+}
+
+impl SourceTransfer<Granted> {
+    /// Get the transfer identifier
+    #[inline]
+    #[must_use]
+    pub const fn transfer_id(&self) -> TransferId {
+        self.state.transfer_id
+    }
+
+    /// Revoke the source's access after the destination acknowledged
+    ///
+    /// Sets the ownership of `start..end` (the source's mapping of the
+    /// region) to `prot::NONE`. Nothing is changed unless `device` was
+    /// opened for the transferred memory ID and the range lies inside the
+    /// exported region.
+    ///
+    /// # Arguments
+    /// * `ack` - Acknowledgement from the destination
+    /// * `device` - Shared memory device of the exported region
+    /// * `start` - Start virtual address of the source mapping
+    /// * `end` - End virtual address of the source mapping
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidInput` if the acknowledgement belongs to
+    /// another transfer, the device to another region or the range is not
+    /// inside the region, `ObmmError::AttributeMismatch` if its agreed
+    /// attributes do not meet the requirements and
+    /// `ObmmError::SetOwnershipFailed` if the downgrade fails; the transfer
+    /// is returned unchanged on error
+    #[inline]
+    #[allow(
+        clippy::result_large_err,
+        reason = "the transfer is handed back for a retry"
+    )]
+    pub fn revoke(
+        self,
+        ack: &TransferAck,
+        device: &mut ObmmDevice,
+        start: u64,
+        end: u64,
+    ) -> std::result::Result<SourceTransfer<Revoked>, (Self, ObmmError)> {
+        if ack.transfer_id != self.state.transfer_id || ack.mem_id != self.memory.mem_id() {
+            return Err((
+                self,
+                ObmmError::InvalidInput("acknowledgement does not match the transfer"),
+            ));
+        }
+        if device.mem_id() != Some(self.memory.mem_id()) {
+            return Err((
+                self,
+                ObmmError::InvalidInput("device does not belong to the transferred memory"),
+            ));
+        }
+        let desc = self.memory.descriptor();
+        if start >= end || start < desc.addr || end > desc.addr.saturating_add(desc.length) {
+            return Err((
+                self,
+                ObmmError::InvalidInput("range is not inside the transferred memory"),
+            ));
+        }
+        if let Err(e) = self.check_agreed(ack) {
+            return Err((self, e));
+        }
+        if let Err(e) = device.set_ownership(start, end, prot::NONE) {
+            return Err((self, e));
+        }
+        self.release();
+
+        Ok(SourceTransfer {
+            memory: self.memory,
+            coherence: self.coherence,
+            state: Revoked {
+                ack: *ack,
+                start,
+                end,
+            },
+        })
+    }
+
+    /// Check the attributes agreed in an acknowledgement
+    ///
+    /// Acknowledgements without attributes are only accepted when no
+    /// coherence is required.
+    fn check_agreed(&self, ack: &TransferAck) -> Result<()> {
+        let required = self.requirements();
+        match ack.attributes {
+            Some(agreed) => required.check_agreed(&agreed),
+            None if required.coherence == Coherence::None => Ok(()),
+            None => Err(ObmmError::AttributeMismatch(AttributeError::Coherence {
+                required: required.coherence,
+                provided: Coherence::None,
+            })),
+        }
+    }
+
+    /// Abort the transfer, returning the exported memory
+    ///
+    /// A grant charged to the peer is released.
+    #[inline]
+    #[must_use]
+    pub fn abort(self) -> ExportedMemory<UbPrivData> {
+        self.release();
+        self.memory
+    }
+
+    /// Release the peer's charge of a checked grant
+    ///
+    /// Failures are logged: the transfer has moved on and the stale charge
+    /// only makes the quota stricter until the export is removed.
+    fn release(&self) {
+        if let Some(registry) = &self.state.charged {
+            let mem_id = self.memory.mem_id();
+            if let Err(e) = registry.release_peer(mem_id) {
+                log::warn!("Failed to release the peer charge of memid {mem_id}: {e}");
+            }
+        }
+    }
+}
+
+impl SourceTransfer<Revoked> {
+    /// Complete the transfer
+    ///
+    /// # Returns
+    /// The exported memory, which must stay exported while the destination
+    /// uses it, and a receipt of the transfer
+    #[inline]
+    #[must_use]
+    pub fn confirm(self) -> (ExportedMemory<UbPrivData>, TransferReceipt) {
+        let Revoked { ack, start, end } = self.state;
+        let receipt = TransferReceipt {
+            transfer_id: ack.transfer_id,
+            mem_id: ack.mem_id,
+            remote_mem_id: ack.remote_mem_id,
+            numa_node: ack.numa_node,
+            revoked_start: start,
+            revoked_end: end,
+        };
+        (self.memory, receipt)
+    }
+}
+
+impl<S> SourceTransfer<S> {
+    /// Get the exported memory
+    #[inline]
+    #[must_use]
+    pub const fn memory(&self) -> &ExportedMemory<UbPrivData> {
+        &self.memory
+    }
+
+    /// Get the attributes required of the destination
+    #[inline]
+    #[must_use]
+    pub fn requirements(&self) -> MemoryAttributes {
+        MemoryAttributes::of_desc(self.memory.descriptor()).coherence(self.coherence)
+    }
+}
+
+/// Generate a transfer ID unlikely to collide across processes
+fn next_transfer_id() -> TransferId {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64);
+    let seq = TRANSFER_SEQ.fetch_add(1, Ordering::Relaxed);
+    nanos ^ (u64::from(std::process::id()) << 32) ^ seq
+}
+
+#[cfg(all(test, not(feature = "native")))]
+mod tests {
+    use super::*;
+    use crate::types::ObmmExportFlags;
+
+    fn exported() -> ExportedMemory<UbPrivData> {
+        ExportedMemory::export(&[1024 * 1024 * 2], ObmmExportFlags::ALLOWMMAP).unwrap()
+    }
+
+    /// First page of the transferred region
+    fn first_page<S>(transfer: &SourceTransfer<S>) -> (u64, u64) {
+        let addr = transfer.memory().descriptor().addr;
+        (addr, addr + 0x1000)
+    }
+
+    #[test]
+    fn test_transfer_handshake() {
+        let memory = exported();
+        let mut device = ObmmDevice::open(memory.mem_id()).unwrap();
+        let (granted, offer) = SourceTransfer::new(memory).grant();
+
+        let offer = TransferOffer::from_json(&offer.to_json().unwrap()).unwrap();
+        assert_eq!(offer.transfer_id, granted.transfer_id());
+        assert_eq!(offer.desc.length, 1024 * 1024 * 2);
+
+        let (_imported, ack) = offer.accept(&ImportOptions::new()).unwrap();
+        let ack = TransferAck::from_json(&ack.to_json().unwrap()).unwrap();
+
+        let (start, end) = first_page(&granted);
+        let revoked = granted
+            .revoke(&ack, &mut device, start, end)
+            .map_err(|(_, e)| e)
+            .unwrap();
+        assert_eq!(device.ownership(start + 0x800), Some(prot::NONE));
+
+        let (_memory, receipt) = revoked.confirm();
+        assert_eq!(receipt.transfer_id, ack.transfer_id);
+        assert_eq!(receipt.remote_mem_id, ack.remote_mem_id);
+        assert_eq!((receipt.revoked_start, receipt.revoked_end), (start, end));
+    }
+
+    #[test]
+    fn test_transfer_rejects_foreign_ack() {
+        let memory = exported();
+        let mut device = ObmmDevice::open(memory.mem_id()).unwrap();
+        let (granted, offer) = SourceTransfer::new(memory).grant();
+        let (_imported, mut ack) = offer.accept(&ImportOptions::new()).unwrap();
+        ack.transfer_id ^= 1;
+
+        let (start, end) = first_page(&granted);
+        let (granted, err) = granted.revoke(&ack, &mut device, start, end).unwrap_err();
+        assert!(matches!(err, ObmmError::InvalidInput(_)));
+        assert!(device.ranges().is_empty());
+        let _memory = granted.abort();
+    }
+
+    #[test]
+    fn test_transfer_rejects_foreign_device_and_range() {
+        let memory = exported();
+        let mut device = ObmmDevice::open(memory.mem_id()).unwrap();
+        let mut other_device = ObmmDevice::open(memory.mem_id() + 1).unwrap();
+        let (granted, offer) = SourceTransfer::new(memory).grant();
+        let (_imported, ack) = offer.accept(&ImportOptions::new()).unwrap();
+        let (start, end) = first_page(&granted);
+        let region_end = start + offer.desc.length;
+
+        let (mut granted, err) = granted
+            .revoke(&ack, &mut other_device, start, end)
+            .unwrap_err();
+        assert!(matches!(err, ObmmError::InvalidInput(_)));
+        for (start, end) in [
+            (region_end, region_end + 0x1000),
+            (end, start),
+            (start - 1, end),
+        ] {
+            let (returned, err) = granted.revoke(&ack, &mut device, start, end).unwrap_err();
+            assert!(matches!(err, ObmmError::InvalidInput(_)));
+            granted = returned;
+        }
+        assert!(device.ranges().is_empty());
+        assert!(other_device.ranges().is_empty());
+        let _memory = granted.abort();
+    }
+
+    #[test]
+    fn test_transfer_attribute_negotiation() {
+        let memory = exported();
+        let mut device = ObmmDevice::open(memory.mem_id()).unwrap();
+        let (granted, mut offer) = SourceTransfer::new(memory)
+            .require_coherence(Coherence::Hardware)
+            .grant();
+        let required = offer.attributes.unwrap();
+        assert_eq!(required.coherence, Coherence::Hardware);
+        let offer_json = offer.to_json().unwrap();
+
+        // A cached import without coherent caches is refused before importing
+        offer.attributes = Some(required.priv_data(UbPrivData::CACHEABLE));
+        let err = offer.accept(&ImportOptions::new()).unwrap_err();
+        assert_eq!(
+            err,
+            ObmmError::AttributeMismatch(AttributeError::Coherence {
+                required: Coherence::Hardware,
+                provided: Coherence::None,
+            })
+        );
+
+        // An acknowledgement without agreed attributes is refused
+        let offer = TransferOffer::from_json(&offer_json).unwrap();
+        let (_imported, mut ack) = offer.accept(&ImportOptions::new()).unwrap();
+        let agreed = ack.attributes.take().unwrap();
+        let (start, end) = first_page(&granted);
+        let (granted, err) = granted.revoke(&ack, &mut device, start, end).unwrap_err();
+        assert!(matches!(err, ObmmError::AttributeMismatch(_)));
+        assert!(device.ranges().is_empty());
+
+        ack.attributes = Some(agreed);
+        let revoked = granted
+            .revoke(&ack, &mut device, start, end)
+            .map_err(|(_, e)| e)
+            .unwrap();
+        let _memory = revoked.confirm();
+    }
+
+    #[test]
+    fn test_transfer_peer_quota() {
+        let dir = std::env::temp_dir().join(format!("obmm-rs-transfer-{}", std::process::id()));
+        let registry = Registry::open(&dir).unwrap();
+        let memory = exported();
+        let deid = memory.descriptor().deid;
+        registry.set_peer_quota(deid, Some(1024 * 1024)).unwrap();
+
+        let (transfer, err) = SourceTransfer::new(memory)
+            .grant_checked(&registry)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ObmmError::PeerQuotaExceeded {
+                requested: 1024 * 1024 * 2,
+                limit: 1024 * 1024,
+            }
+        );
+        assert!(registry.exports().unwrap().is_empty());
+
+        registry
+            .set_peer_quota(deid, Some(1024 * 1024 * 2))
+            .unwrap();
+        let (granted, offer) = transfer.grant_checked(&registry).unwrap();
+        assert_eq!(offer.transfer_id, granted.transfer_id());
+        assert_eq!(registry.peer_usage(&deid).unwrap(), 1024 * 1024 * 2);
+
+        let _memory = granted.abort();
+        assert_eq!(registry.peer_usage(&deid).unwrap(), 0);
+
+        // A revoked transfer releases the charge as well
+        let memory = exported();
+        let mut device = ObmmDevice::open(memory.mem_id()).unwrap();
+        let (granted, offer) = SourceTransfer::new(memory)
+            .grant_checked(&registry)
+            .map_err(|(_, e)| e)
+            .unwrap();
+        assert_eq!(registry.peer_usage(&deid).unwrap(), 1024 * 1024 * 2);
+        let (_imported, ack) = offer.accept(&ImportOptions::new()).unwrap();
+        let (start, end) = first_page(&granted);
+        let revoked = granted
+            .revoke(&ack, &mut device, start, end)
+            .map_err(|(_, e)| e)
+            .unwrap();
+        assert_eq!(registry.peer_usage(&deid).unwrap(), 0);
+        let _memory = revoked.confirm();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -3,9 +3,14 @@
 //! This module provides constants, type aliases, bitflags, and structures
 //! used throughout the OBMM library.
 
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+
 use bitflags::bitflags;
 use serde::{Deserialize, Serialize};
 
+use crate::error::{DescError, ObmmError, Result};
+
 /// Maximum number of NUMA nodes supported
 pub const MAX_NUMA_NODES: usize = 16;
 
@@ -18,6 +23,22 @@ pub const OBMM_MAX_LOCAL_NUMA_NODES: usize = 16;
 /// Memory ID type
 pub type MemId = u64;
 
+/// Directory where memory descriptor files are stored
+///
+/// Used by `to_json_file`/`from_json_file`. Long-lived exports should be
+/// tracked with [`crate::registry::Registry`] instead.
+pub const DESC_DIR: &str = "/tmp/memlink";
+
+/// Get the descriptor file path for a memory ID
+///
+/// # Returns
+/// `DESC_DIR/memdesc_<mem_id>.json`
+#[inline]
+#[must_use]
+pub fn desc_file_path(mem_id: MemId) -> PathBuf {
+    Path::new(DESC_DIR).join(format!("memdesc_{mem_id}.json"))
+}
+
 bitflags! {
     /// Privilege data for UB memory regions
     #[derive(Serialize, Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
@@ -50,6 +71,19 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// Import flags as understood by the kernel
+    #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ObmmImportFlags: u64 {
+        /// Allow memory mapping of the imported region
+        const ALLOWMMAP = 1 << 0;
+        /// Import into a previously preimported physical address range
+        const PREIMPORT = 1 << 1;
+        /// Place the imported memory on a remote NUMA node
+        const NUMA_REMOTE = 1 << 2;
+    }
+}
+
 bitflags! {
     /// Preimport flags for memory preimporting
     #[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
@@ -87,10 +121,7 @@ pub struct ObmmMemDesc<T> {
     pub priv_data: T,
 }
 
-impl<T> ObmmMemDesc<T>
-where
-    T: Default + Serialize + for<'de> Deserialize<'de>,
-{
+impl<T: Default> ObmmMemDesc<T> {
     /// Create a new `ObmmMemDesc` with default values
     ///
     /// # Returns
@@ -100,34 +131,387 @@ where
     pub fn new() -> Self {
         ObmmMemDesc::<T>::default()
     }
+}
 
-    /// Deserialize the `ObmmMemDesc` from json format
+impl<T: Serialize> ObmmMemDesc<T> {
+    /// Serialize the `ObmmMemDesc` to json format
+    ///
+    /// The descriptor is wrapped in a [`DescEnvelope`] of the current
+    /// version.
+    ///
+    /// # Returns
+    /// JSON string on success, `anyhow::Error` on failure
+    ///
+    /// # Errors
+    /// Returns an error if serialization fails
+    #[inline]
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        let json_str = serde_json::to_string(&DescEnvelope::new(self))?;
+        Ok(json_str)
+    }
+
+    /// Write the `ObmmMemDesc` to a json file
     ///
     /// # Arguments
-    /// * `json_str` - JSON string representation
+    /// * `mem_id` - Memory ID used to construct the filename
     ///
     /// # Returns
-    /// `ObmmMemDesc` on success, `anyhow::Error` on failure
+    /// `Ok(())` on success, `anyhow::Error` on failure
     ///
     /// # Errors
-    /// Returns an error if the JSON string is invalid or cannot be deserialized
+    /// Returns an error if the file cannot be written or serialization fails
     #[inline]
-    pub fn from_json(json_str: &str) -> anyhow::Result<Self> {
-        let desc: ObmmMemDesc<T> = serde_json::from_str(json_str)?;
-        Ok(desc)
+    pub fn to_json_file(&self, mem_id: MemId) -> anyhow::Result<()> {
+        self.to_json_path(desc_file_path(mem_id))
     }
 
-    /// Serialize the `ObmmMemDesc` to json format
+    /// Write the `ObmmMemDesc` to a json file at an arbitrary path
+    ///
+    /// Missing parent directories are created.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the descriptor file
     ///
     /// # Returns
-    /// JSON string on success, `anyhow::Error` on failure
+    /// `Ok(())` on success, `anyhow::Error` on failure
     ///
     /// # Errors
-    /// Returns an error if serialization fails
+    /// Returns an error if the file cannot be written or serialization fails
     #[inline]
-    pub fn to_json(&self) -> anyhow::Result<String> {
-        let json_str = serde_json::to_string(self)?;
-        Ok(json_str)
+    pub fn to_json_path<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let json_str = serde_json::to_string_pretty(&DescEnvelope::new(self))?;
+        std::fs::write(path, json_str)?;
+        Ok(())
+    }
+}
+
+/// Alignment required of descriptor addresses and lengths
+pub const DESC_ALIGN: u64 = 4096;
+
+/// Largest valid CNA (CNAs are 24-bit)
+pub const MAX_CNA: u32 = 0x00ff_ffff;
+
+/// Capabilities of the local host used to check imported descriptors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LocalCaps {
+    /// EID of this host (128-bit, little-endian)
+    pub eid: [u8; 16],
+    /// CNA of this host
+    pub cna: u32,
+    /// Largest region this host can import, in bytes
+    pub max_length: u64,
+    /// Privilege data features supported by this host
+    pub priv_data: UbPrivData,
+}
+
+impl LocalCaps {
+    /// Create capabilities for a host with no size limit and all
+    /// privilege data features
+    #[inline]
+    #[must_use]
+    pub const fn new(eid: [u8; 16], cna: u32) -> Self {
+        Self {
+            eid,
+            cna,
+            max_length: u64::MAX,
+            priv_data: UbPrivData::all(),
+        }
+    }
+
+    /// Set the largest importable region length
+    #[inline]
+    #[must_use]
+    pub const fn max_length(mut self, max_length: u64) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Set the supported privilege data features
+    #[inline]
+    #[must_use]
+    pub const fn priv_data(mut self, priv_data: UbPrivData) -> Self {
+        self.priv_data = priv_data;
+        self
+    }
+}
+
+impl ObmmMemDesc<UbPrivData> {
+    /// Check that the descriptor is well formed
+    ///
+    /// Checks that the address and length are non-zero and
+    /// [`DESC_ALIGN`]-aligned, that `priv_len` is either zero or the size of
+    /// [`UbPrivData`] with privilege data only present when it is non-zero,
+    /// that the source EID is neither all zeros nor all ones, and that both
+    /// CNAs fit in 24 bits.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidDescriptor` with the first problem found
+    ///
+    /// # Example
+    /// ```
+    /// use obmm_rs::error::{DescError, ObmmError};
+    /// use obmm_rs::types::{ObmmMemDesc, UbPrivData};
+    ///
+    /// let desc = ObmmMemDesc::<UbPrivData>::new();
+    /// assert_eq!(
+    ///     desc.validate(),
+    ///     Err(ObmmError::InvalidDescriptor(DescError::ZeroLength))
+    /// );
+    /// ```
+    #[inline]
+    pub fn validate(&self) -> Result<()> {
+        if self.length == 0 {
+            return Err(ObmmError::InvalidDescriptor(DescError::ZeroLength));
+        }
+        if !self.addr.is_multiple_of(DESC_ALIGN) || !self.length.is_multiple_of(DESC_ALIGN) {
+            return Err(ObmmError::InvalidDescriptor(DescError::Unaligned {
+                align: DESC_ALIGN,
+            }));
+        }
+
+        let expected = size_of::<UbPrivData>() as u16;
+        if self.priv_len != 0 && self.priv_len != expected {
+            return Err(ObmmError::InvalidDescriptor(DescError::PrivLenMismatch {
+                expected,
+                found: self.priv_len,
+            }));
+        }
+        let bits = self.priv_data.bits();
+        if (self.priv_len == 0 && bits != 0) || UbPrivData::from_bits(bits).is_none() {
+            return Err(ObmmError::InvalidDescriptor(DescError::InvalidPrivData(
+                bits,
+            )));
+        }
+
+        if self.seid == [0; 16] || self.seid == [0xff; 16] {
+            return Err(ObmmError::InvalidDescriptor(DescError::InvalidEid));
+        }
+        for cna in [self.scna, self.dcna] {
+            if cna > MAX_CNA {
+                return Err(ObmmError::InvalidDescriptor(DescError::InvalidCna(cna)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the descriptor is valid and can be imported on this host
+    ///
+    /// In addition to [`validate`](Self::validate), the destination EID and
+    /// CNA must either be zero (any importer) or match `local_caps`, the
+    /// length must not exceed `local_caps.max_length`, and the privilege
+    /// data must only use supported features.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::InvalidDescriptor` with the first problem found
+    #[inline]
+    pub fn is_compatible_with(&self, local_caps: &LocalCaps) -> Result<()> {
+        self.validate()?;
+
+        if self.deid != [0; 16] && self.deid != local_caps.eid {
+            return Err(ObmmError::InvalidDescriptor(DescError::EidMismatch));
+        }
+        if self.dcna != 0 && self.dcna != local_caps.cna {
+            return Err(ObmmError::InvalidDescriptor(DescError::CnaMismatch(
+                self.dcna,
+            )));
+        }
+        if self.length > local_caps.max_length {
+            return Err(ObmmError::InvalidDescriptor(DescError::TooLarge {
+                length: self.length,
+                max: local_caps.max_length,
+            }));
+        }
+        if !local_caps.priv_data.contains(self.priv_data) {
+            return Err(ObmmError::InvalidDescriptor(
+                DescError::UnsupportedPrivData(self.priv_data.bits()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Magic bytes at the start of a binary descriptor
+pub const DESC_MAGIC: [u8; 4] = *b"OBMD";
+
+/// Version of the descriptor layout
+///
+/// Written in the header of the binary format and in the envelope of the
+/// JSON format. Bumped whenever a field is added or changes meaning.
+pub const DESC_BINARY_VERSION: u16 = 1;
+
+/// Length of the binary descriptor header (magic, version, body length)
+const DESC_HEADER_LEN: usize = 8;
+
+/// Length of the version 1 binary descriptor body
+const DESC_BODY_LEN: usize = 64;
+
+/// Length of a version 1 binary descriptor
+pub const DESC_BINARY_LEN: usize = DESC_HEADER_LEN + DESC_BODY_LEN;
+
+impl ObmmMemDesc<UbPrivData> {
+    /// Encode the descriptor in the compact binary format
+    ///
+    /// The encoding is an 8-byte header (`DESC_MAGIC`, a `u16` format
+    /// version and a `u16` body length) followed by the fields in
+    /// declaration order, all little-endian. Decoders skip body bytes they
+    /// do not understand, so later versions may append fields.
+    ///
+    /// # Returns
+    /// [`DESC_BINARY_LEN`] bytes
+    ///
+    /// # Example
+    /// ```
+    /// use obmm_rs::types::{DESC_BINARY_LEN, ObmmMemDesc, UbPrivData};
+    ///
+    /// let mut desc = ObmmMemDesc::<UbPrivData>::new();
+    /// desc.length = 2 * 1024 * 1024;
+    ///
+    /// let bytes = desc.to_bytes();
+    /// assert_eq!(bytes.len(), DESC_BINARY_LEN);
+    /// let decoded = ObmmMemDesc::<UbPrivData>::from_bytes(&bytes).expect("Decode failed");
+    /// assert_eq!(decoded.length, desc.length);
+    /// ```
+    #[inline]
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(DESC_BINARY_LEN);
+        bytes.extend_from_slice(&DESC_MAGIC);
+        bytes.extend_from_slice(&DESC_BINARY_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&(DESC_BODY_LEN as u16).to_le_bytes());
+        bytes.extend_from_slice(&self.addr.to_le_bytes());
+        bytes.extend_from_slice(&self.length.to_le_bytes());
+        bytes.extend_from_slice(&self.seid);
+        bytes.extend_from_slice(&self.deid);
+        bytes.extend_from_slice(&self.tokenid.to_le_bytes());
+        bytes.extend_from_slice(&self.scna.to_le_bytes());
+        bytes.extend_from_slice(&self.dcna.to_le_bytes());
+        bytes.extend_from_slice(&self.priv_len.to_le_bytes());
+        bytes.extend_from_slice(&self.priv_data.bits().to_le_bytes());
+        bytes
+    }
+
+    /// Decode a descriptor from the binary format of [`to_bytes`](Self::to_bytes)
+    ///
+    /// Trailing bytes after the body are ignored, so a descriptor can be
+    /// decoded from the front of a larger message.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::VersionMismatch` if the version is newer than
+    /// [`DESC_BINARY_VERSION`], and `ObmmError::SerializationError` if the
+    /// magic is unknown or the input is truncated
+    #[inline]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let truncated = || ObmmError::SerializationError("binary descriptor is truncated".into());
+        let header = bytes.get(..DESC_HEADER_LEN).ok_or_else(truncated)?;
+        if header[..4] != DESC_MAGIC {
+            return Err(ObmmError::SerializationError(
+                "not a binary descriptor".to_string(),
+            ));
+        }
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version == 0 || version > DESC_BINARY_VERSION {
+            return Err(ObmmError::VersionMismatch {
+                found: version,
+                supported: DESC_BINARY_VERSION,
+            });
+        }
+        let body_len = usize::from(u16::from_le_bytes([header[6], header[7]]));
+        if body_len < DESC_BODY_LEN {
+            return Err(truncated());
+        }
+        let body = bytes
+            .get(DESC_HEADER_LEN..DESC_HEADER_LEN + body_len)
+            .ok_or_else(truncated)?;
+
+        let mut reader = FieldReader(body);
+        Ok(Self {
+            addr: u64::from_le_bytes(reader.take()),
+            length: u64::from_le_bytes(reader.take()),
+            seid: reader.take(),
+            deid: reader.take(),
+            tokenid: u32::from_le_bytes(reader.take()),
+            scna: u32::from_le_bytes(reader.take()),
+            dcna: u32::from_le_bytes(reader.take()),
+            priv_len: u16::from_le_bytes(reader.take()),
+            priv_data: UbPrivData::from_bits_retain(u16::from_le_bytes(reader.take())),
+        })
+    }
+}
+
+/// Sequential reader of fixed-size fields from a body of known length
+struct FieldReader<'a>(&'a [u8]);
+
+impl FieldReader<'_> {
+    /// Take the next `N` bytes
+    ///
+    /// The caller checks the body length up front, so running out of bytes
+    /// is a bug.
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.0.split_at(N);
+        self.0 = rest;
+        field.try_into().expect("field length")
+    }
+}
+
+/// Versioned envelope of a serialized descriptor
+///
+/// JSON descriptors, on disk and on the wire, are written as
+/// `{"magic": "OBMD", "version": 1, "length": 64, "payload": {...}}` where
+/// `length` is the size of the descriptor body in the binary layout of
+/// `version`. Readers reject versions newer than they know instead of
+/// silently misreading fields, and descriptors written before the envelope
+/// existed (a bare payload) are read as version 0.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct DescEnvelope<P> {
+    /// Always [`DESC_MAGIC`] as a string
+    pub magic: String,
+    /// Layout version of the payload
+    pub version: u16,
+    /// Size of the descriptor body in the binary layout of `version`
+    pub length: u16,
+    /// The descriptor
+    pub payload: P,
+}
+
+impl<'a, T> DescEnvelope<&'a ObmmMemDesc<T>> {
+    /// Wrap a descriptor in an envelope of the current version
+    #[inline]
+    #[must_use]
+    pub fn new(desc: &'a ObmmMemDesc<T>) -> Self {
+        Self {
+            magic: String::from_utf8_lossy(&DESC_MAGIC).into_owned(),
+            version: DESC_BINARY_VERSION,
+            length: DESC_BODY_LEN as u16,
+            payload: desc,
+        }
+    }
+}
+
+impl<T> ObmmMemDesc<T>
+where
+    T: for<'de> Deserialize<'de>,
+{
+    /// Deserialize the `ObmmMemDesc` from json format
+    ///
+    /// # Arguments
+    /// * `json_str` - JSON string representation
+    ///
+    /// # Returns
+    /// `ObmmMemDesc` on success, `anyhow::Error` on failure
+    ///
+    /// # Errors
+    /// Returns an error if the JSON string is invalid or cannot be
+    /// deserialized, or if it was written by a newer, unsupported version
+    #[inline]
+    pub fn from_json(json_str: &str) -> anyhow::Result<Self> {
+        let value = serde_json::from_str(json_str)?;
+        Ok(Self::from_json_value(value)?)
     }
 
     /// Read the `ObmmMemDesc` from a json file
@@ -142,28 +526,101 @@ where
     /// Returns an error if the file cannot be read or the JSON is invalid
     #[inline]
     pub fn from_json_file(mem_id: MemId) -> anyhow::Result<Self> {
-        let file_path = format!("/tmp/memlink/memdesc_{mem_id}.json");
-        let json_str = std::fs::read_to_string(file_path)?;
-        let desc: ObmmMemDesc<T> = serde_json::from_str(&json_str)?;
-        Ok(desc)
+        Self::from_json_path(desc_file_path(mem_id))
     }
 
-    /// Write the `ObmmMemDesc` to a json file
+    /// Read the `ObmmMemDesc` from a json file at an arbitrary path
     ///
     /// # Arguments
-    /// * `mem_id` - Memory ID used to construct the filename
+    /// * `path` - Path of the descriptor file
     ///
     /// # Returns
-    /// `Ok(())` on success, `anyhow::Error` on failure
+    /// `ObmmMemDesc` on success, `anyhow::Error` on failure
     ///
     /// # Errors
-    /// Returns an error if the file cannot be written or serialization fails
+    /// Returns an error if the file cannot be read, the JSON is invalid or
+    /// was written by a newer, unsupported version
     #[inline]
-    pub fn to_json_file(&self, mem_id: MemId) -> anyhow::Result<()> {
-        let file_path = format!("/tmp/memlink/memdesc_{mem_id}.json");
-        let json_str = serde_json::to_string_pretty(self)?;
-        std::fs::write(file_path, json_str)?;
-        Ok(())
+    pub fn from_json_path<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let json_str = std::fs::read_to_string(path)?;
+        Self::from_json(&json_str)
+    }
+
+    /// Decode a descriptor from a parsed JSON value
+    ///
+    /// Accepts a [`DescEnvelope`] of any version up to
+    /// [`DESC_BINARY_VERSION`], or a bare descriptor as written before
+    /// envelopes were introduced.
+    ///
+    /// # Errors
+    /// Returns `ObmmError::VersionMismatch` if the envelope is of an unknown
+    /// version, and `ObmmError::SerializationError` if it is malformed
+    #[inline]
+    pub fn from_json_value(value: serde_json::Value) -> Result<Self> {
+        let malformed = |e: serde_json::Error| ObmmError::SerializationError(e.to_string());
+        if value.get("magic").is_none() {
+            return serde_json::from_value(value).map_err(malformed);
+        }
+
+        let envelope: DescEnvelope<serde_json::Value> =
+            serde_json::from_value(value).map_err(malformed)?;
+        if envelope.magic.as_bytes() != DESC_MAGIC {
+            return Err(ObmmError::SerializationError(format!(
+                "not a memory descriptor: magic {:?}",
+                envelope.magic
+            )));
+        }
+        if envelope.version == 0 || envelope.version > DESC_BINARY_VERSION {
+            return Err(ObmmError::VersionMismatch {
+                found: envelope.version,
+                supported: DESC_BINARY_VERSION,
+            });
+        }
+        if usize::from(envelope.length) < DESC_BODY_LEN {
+            return Err(ObmmError::SerializationError(format!(
+                "descriptor body of {} bytes is shorter than version {} allows",
+                envelope.length, envelope.version
+            )));
+        }
+        serde_json::from_value(envelope.payload).map_err(malformed)
+    }
+}
+
+/// Serde adapter writing a descriptor field in its [`DescEnvelope`]
+///
+/// Use with `#[serde(with = "obmm_rs::types::versioned")]` on fields of
+/// type `ObmmMemDesc<T>` in messages that carry descriptors.
+pub mod versioned {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{DescEnvelope, ObmmMemDesc};
+
+    /// Serialize a descriptor in an envelope of the current version
+    ///
+    /// # Errors
+    /// Returns the serializer's error
+    #[inline]
+    pub fn serialize<T, S>(desc: &ObmmMemDesc<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: Serialize,
+        S: Serializer,
+    {
+        DescEnvelope::new(desc).serialize(serializer)
+    }
+
+    /// Deserialize a descriptor written by any supported version
+    ///
+    /// # Errors
+    /// Returns the deserializer's error, or a custom error carrying the
+    /// version mismatch
+    #[inline]
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<ObmmMemDesc<T>, D::Error>
+    where
+        T: for<'a> Deserialize<'a>,
+        D: Deserializer<'de>,
+    {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        ObmmMemDesc::from_json_value(value).map_err(serde::de::Error::custom)
     }
 }
 
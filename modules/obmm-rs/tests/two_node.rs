@@ -0,0 +1,244 @@
+//! End-to-end tests of two simulated nodes sharing a mock fabric
+//!
+//! Only built without the `native` feature:
+//! `cargo test -p obmm-rs --no-default-features`
+#![cfg(not(feature = "native"))]
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use obmm_rs::ObmmError;
+use obmm_rs::export::{UnexportOutcome, mem_unexport, unexport_graceful};
+use obmm_rs::handle::ExportedMemory;
+use obmm_rs::import::ImportOptions;
+use obmm_rs::mock::{MockFabric, MockNode};
+use obmm_rs::ownership::{ObmmDevice, prot};
+use obmm_rs::registry::{EntryKind, Lease, Registry};
+use obmm_rs::transfer::{SourceTransfer, TransferAck, TransferOffer};
+use obmm_rs::types::{ObmmExportFlags, ObmmUnexportFlags, UbPrivData};
+
+const LEN: usize = 1024 * 1024 * 2;
+
+/// Registry in a fresh directory for one node of one test
+fn registry(test: &str, node: &MockNode) -> (Registry, PathBuf) {
+    let dir = std::env::temp_dir().join(format!(
+        "obmm-rs-{test}-{}-{}",
+        node.eid()[15],
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    (Registry::open(&dir).expect("open registry"), dir)
+}
+
+/// Export `len` bytes on `node` and record it in its registry
+fn export(node: &MockNode, registry: &Registry, len: usize) -> ExportedMemory<UbPrivData> {
+    let _node = node.enter();
+    let memory = ExportedMemory::export(&[len], ObmmExportFlags::ALLOWMMAP).expect("export");
+    registry
+        .record_export(
+            memory.mem_id(),
+            ObmmExportFlags::ALLOWMMAP,
+            memory.descriptor(),
+        )
+        .expect("record export");
+    memory
+}
+
+#[test]
+fn test_transfer_between_nodes() {
+    let fabric = MockFabric::new();
+    let (source, destination) = (fabric.node(1), fabric.node(2));
+    source.set_destination(Some(destination.eid()));
+    let (source_registry, source_dir) = registry("transfer", &source);
+    let (destination_registry, destination_dir) = registry("transfer", &destination);
+
+    // Source: export and grant within the destination's quota
+    let memory = export(&source, &source_registry, LEN);
+    let mem_id = memory.mem_id();
+    let desc = memory.descriptor();
+    let (start, end) = (desc.addr, desc.addr + desc.length);
+    assert_eq!(desc.seid, source.eid());
+    assert_eq!(desc.deid, destination.eid());
+    source_registry
+        .set_peer_quota(destination.eid(), Some(2 * LEN as u64))
+        .expect("set quota");
+    let (granted, offer_json) = {
+        let _node = source.enter();
+        let (granted, offer) = SourceTransfer::new(memory)
+            .grant_checked(&source_registry)
+            .map_err(|(_, e)| e)
+            .expect("grant");
+        (granted, offer.to_json().expect("serialize offer"))
+    };
+    assert_eq!(
+        source_registry.peer_usage(&destination.eid()),
+        Ok(LEN as u64)
+    );
+
+    // Destination: import the offered descriptor and acknowledge
+    let (mut imported, ack_json) = {
+        let _node = destination.enter();
+        let offer = TransferOffer::from_json(&offer_json).expect("parse offer");
+        let (imported, ack) = offer.accept(&ImportOptions::new()).expect("accept");
+        destination_registry
+            .record_import(
+                imported.mem_id(),
+                offer.desc.length,
+                ObmmExportFlags::empty(),
+                imported.numa_node(),
+            )
+            .expect("record import");
+        (imported, ack.to_json().expect("serialize ack"))
+    };
+    assert_eq!(destination.imports(), [imported.mem_id()]);
+    assert_eq!(destination.import_source(imported.mem_id()), Some(mem_id));
+    assert_eq!(fabric.importers(mem_id), Some(1));
+
+    // Source: revoke its own access, then confirm
+    let _node = source.enter();
+    let ack = TransferAck::from_json(&ack_json).expect("parse ack");
+    let mut device = ObmmDevice::open(mem_id).expect("open device");
+    // Ranges outside the export are refused and the transfer handed back
+    let (granted, err) = granted
+        .revoke(&ack, &mut device, end, end + 4096)
+        .expect_err("revoke outside the export");
+    assert!(matches!(err, ObmmError::InvalidInput(_)));
+    let revoked = granted
+        .revoke(&ack, &mut device, start, end)
+        .map_err(|(_, e)| e)
+        .expect("revoke");
+    assert_eq!(source.ownership(start), Some(prot::NONE));
+    assert_eq!(destination.ownership(start), None);
+    let (mut memory, receipt) = revoked.confirm();
+    assert_eq!(receipt.remote_mem_id, imported.mem_id());
+
+    // The export outlives the transfer until the destination lets go
+    assert!(matches!(memory.unexport(), Err(ObmmError::Busy(_))));
+    assert_eq!(memory.importers(), Ok(1));
+    {
+        let _node = destination.enter();
+        imported.unimport().expect("unimport");
+        destination_registry
+            .remove(EntryKind::Import, receipt.remote_mem_id)
+            .expect("remove import");
+    }
+    assert_eq!(
+        unexport_graceful(memory.release().0, Duration::from_secs(1)),
+        Ok(UnexportOutcome::Clean)
+    );
+    source_registry
+        .remove(EntryKind::Export, mem_id)
+        .expect("remove export");
+    assert!(source.exports().is_empty());
+    assert!(destination_registry.imports().expect("imports").is_empty());
+
+    let _ = std::fs::remove_dir_all(&source_dir);
+    let _ = std::fs::remove_dir_all(&destination_dir);
+}
+
+#[test]
+fn test_grant_over_peer_quota() {
+    let fabric = MockFabric::new();
+    let (source, destination) = (fabric.node(1), fabric.node(2));
+    source.set_destination(Some(destination.eid()));
+    let (registry, dir) = registry("quota", &source);
+    registry
+        .set_peer_quota(destination.eid(), Some(LEN as u64))
+        .expect("set quota");
+
+    // Recording an export charges it to the peer named in its descriptor
+    let first = export(&source, &registry, LEN);
+    assert_eq!(registry.peer_usage(&destination.eid()), Ok(LEN as u64));
+    let _node = source.enter();
+    let second =
+        ExportedMemory::<UbPrivData>::export(&[LEN], ObmmExportFlags::ALLOWMMAP).expect("export");
+    let (_granted, _offer) = SourceTransfer::new(first)
+        .grant_checked(&registry)
+        .map_err(|(_, e)| e)
+        .expect("grant");
+    let (transfer, err) = SourceTransfer::new(second)
+        .grant_checked(&registry)
+        .expect_err("grant over quota");
+    assert_eq!(
+        err,
+        ObmmError::PeerQuotaExceeded {
+            requested: 2 * LEN as u64,
+            limit: LEN as u64,
+        }
+    );
+
+    // The refused export stays with the source, ungranted
+    assert_eq!(fabric.importers(transfer.memory().mem_id()), Some(0));
+    drop(transfer);
+    assert_eq!(source.exports().len(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_reclaim_expired_lease() {
+    let fabric = MockFabric::new();
+    let (source, destination) = (fabric.node(1), fabric.node(2));
+    let (registry, dir) = registry("lease", &source);
+
+    let (mem_id, desc) = export(&source, &registry, LEN).release();
+    let imported = {
+        let _node = destination.enter();
+        ImportOptions::new()
+            .import(&desc)
+            .expect("import")
+            .result
+            .mem_id
+    };
+    // Zero TTL and grace: reclaimable right away
+    registry
+        .set_lease(mem_id, Some(Lease::new(Duration::ZERO, Duration::ZERO)))
+        .expect("set lease");
+
+    let _node = source.enter();
+    let expired = registry.expired_leases().expect("expired leases");
+    assert_eq!(
+        expired.iter().map(|e| e.mem_id).collect::<Vec<_>>(),
+        [mem_id]
+    );
+    assert!(matches!(
+        mem_unexport(mem_id, ObmmUnexportFlags::empty()),
+        Err(ObmmError::Busy(_))
+    ));
+    // The importer ignored its lease: pull the memory from under it
+    assert_eq!(
+        unexport_graceful(mem_id, Duration::ZERO),
+        Ok(UnexportOutcome::Forced { importers: Some(1) })
+    );
+    registry
+        .remove(EntryKind::Export, mem_id)
+        .expect("remove export");
+
+    assert_eq!(fabric.importers(mem_id), None);
+    assert_eq!(destination.imports(), [imported]);
+    assert_eq!(destination.import_source(imported), None);
+    assert!(registry.exports().expect("exports").is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_import_requires_live_export() {
+    let fabric = MockFabric::new();
+    let (source, destination) = (fabric.node(1), fabric.node(2));
+
+    let (mem_id, desc) = {
+        let _node = source.enter();
+        ExportedMemory::<UbPrivData>::export(&[LEN], ObmmExportFlags::ALLOWMMAP)
+            .expect("export")
+            .release()
+    };
+    {
+        let _node = source.enter();
+        mem_unexport(mem_id, ObmmUnexportFlags::empty()).expect("unexport");
+    }
+
+    let _node = destination.enter();
+    assert!(ImportOptions::new().import(&desc).is_err());
+    assert!(destination.imports().is_empty());
+}
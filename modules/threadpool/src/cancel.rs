@@ -0,0 +1,122 @@
+use std::sync::{
+    Arc, Mutex, Weak,
+    atomic::{AtomicBool, Ordering},
+};
+
+/// 任务取消令牌
+///
+/// 克隆出的令牌共享同一个取消状态；子令牌在自身或任一祖先被取消时
+/// 视为已取消，取消子令牌不影响父令牌。长时间运行的任务应定期调用
+/// `is_cancelled()` 并尽快返回。
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    parent: Option<Arc<Inner>>,
+}
+
+impl CancellationToken {
+    /// 创建未取消的令牌
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 创建子令牌，父令牌取消时子令牌随之取消
+    pub fn child_token(&self) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                cancelled: AtomicBool::new(false),
+                parent: Some(Arc::clone(&self.inner)),
+            }),
+        }
+    }
+
+    /// 取消令牌及其所有子令牌
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+    }
+
+    /// 检查令牌是否已取消
+    pub fn is_cancelled(&self) -> bool {
+        let mut node = Some(&self.inner);
+        while let Some(inner) = node {
+            if inner.cancelled.load(Ordering::Acquire) {
+                return true;
+            }
+            node = inner.parent.as_ref();
+        }
+        false
+    }
+}
+
+/// 线程池登记的任务令牌，供 `cancel_all()` 统一取消
+#[derive(Debug, Default)]
+pub(crate) struct TokenSet {
+    tokens: Mutex<Vec<Weak<Inner>>>,
+}
+
+impl TokenSet {
+    /// 登记令牌，同时清理已结束任务的令牌
+    pub(crate) fn register(&self, token: &CancellationToken) {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        tokens.retain(|t| t.strong_count() > 0);
+        tokens.push(Arc::downgrade(&token.inner));
+    }
+
+    /// 取消所有仍存活的令牌
+    pub(crate) fn cancel_all(&self) {
+        let tokens = std::mem::take(&mut *self.tokens.lock().unwrap_or_else(|e| e.into_inner()));
+        for inner in tokens.iter().filter_map(Weak::upgrade) {
+            inner.cancelled.store(true, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_shared_by_clones() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+
+        token.cancel();
+        assert!(clone.is_cancelled());
+    }
+
+    #[test]
+    fn test_child_token() {
+        let parent = CancellationToken::new();
+        let child = parent.child_token();
+        let grandchild = child.child_token();
+
+        // 取消子令牌不影响父令牌
+        child.cancel();
+        assert!(grandchild.is_cancelled());
+        assert!(!parent.is_cancelled());
+
+        let other = parent.child_token();
+        parent.cancel();
+        assert!(other.is_cancelled());
+    }
+
+    #[test]
+    fn test_token_set() {
+        let set = TokenSet::default();
+        let parent = CancellationToken::new();
+        let task = parent.child_token();
+        set.register(&task);
+        set.register(&CancellationToken::new());
+
+        set.cancel_all();
+        assert!(task.is_cancelled());
+        assert!(!parent.is_cancelled());
+        assert!(set.tokens.lock().unwrap().is_empty());
+    }
+}
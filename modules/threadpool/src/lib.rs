@@ -4,9 +4,14 @@ use std::{
     time::Duration,
 };
 
-use anyhow::{Context, Result, anyhow, bail};
+use anyhow::{Result, anyhow, bail};
 use thiserror::Error;
 
+mod cancel;
+
+pub use cancel::CancellationToken;
+use cancel::TokenSet;
+
 #[derive(Error, Debug, Clone)]
 pub enum ThreadPoolError {
     #[error("Thread pool size must be greater than 0")]
@@ -29,11 +34,69 @@ pub enum ThreadPoolError {
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// 线程池
+///
+/// 默认所有任务共用一组工作线程。用 [`ThreadPool::with_lanes`] 创建时，
+/// 池内分为 I/O 和计算两条通道，各有独立的工作线程和任务队列：
+/// `execute_io` 提交的阻塞任务（如 procfs 读取）不会占用计算通道的线程，
+/// 长时间的 pread 不会拖慢解码和统计。
 #[derive(Debug)]
 pub struct ThreadPool {
+    /// 计算通道，未分通道时为唯一通道
+    cpu: Lane,
+    /// I/O 通道，未分通道时为 `None`
+    io: Option<Lane>,
+    is_shutdown: bool,
+    tokens: TokenSet,
+}
+
+/// 一组共用任务队列的工作线程
+#[derive(Debug)]
+struct Lane {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
-    is_shutdown: bool,
+}
+
+impl Lane {
+    /// 创建通道，线程名为 `{prefix}-{id}`
+    fn new(size: usize, prefix: &str) -> Result<Lane> {
+        if size == 0 {
+            bail!(ThreadPoolError::InvalidSize);
+        }
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut workers = Vec::with_capacity(size);
+        for id in 0..size {
+            match Worker::new(id, prefix, Arc::clone(&receiver)) {
+                Ok(worker) => workers.push(worker),
+                Err(e) => {
+                    // 如果第一个worker就失败，直接返回错误
+                    if workers.is_empty() {
+                        return Err(anyhow!("Failed to create first worker: {}", e));
+                    }
+                    // 否则记录警告并继续
+                    eprintln!("Warning: Failed to create worker {}: {}", id, e);
+                }
+            }
+        }
+        // 确保至少创建了一个worker
+        if workers.is_empty() {
+            bail!("Failed to create any workers");
+        }
+        Ok(Lane {
+            workers,
+            sender: Some(sender),
+        })
+    }
+
+    /// 提交任务
+    fn send(&self, job: Job) -> Result<()> {
+        self.sender
+            .as_ref()
+            .ok_or_else(|| anyhow!(ThreadPoolError::PoolShutdown))?
+            .send(job)
+            .map_err(|e| anyhow!(ThreadPoolError::TaskSubmissionFailed(e.to_string())))
+    }
 }
 
 #[derive(Debug)]
@@ -45,11 +108,12 @@ struct Worker {
 impl Worker {
     fn new(
         id: usize,
+        prefix: &str,
         receiver: Arc<Mutex<mpsc::Receiver<Job>>>,
     ) -> Result<Worker, ThreadPoolError> {
         let thread_builder = thread::Builder::new();
         let thread = thread_builder
-            .name(format!("worker-{}", id))
+            .name(format!("{}-{}", prefix, id))
             .spawn(move || Self::run_worker(id, receiver))
             .map_err(|e| ThreadPoolError::ThreadCreationFailed(e.to_string()))?;
 
@@ -62,9 +126,12 @@ impl Worker {
                 let lock_result = receiver
                     .lock()
                     .map_err(|e| anyhow!("Failed to lock mutex: {}", e))?;
-                let job = lock_result
-                    .recv_timeout(Duration::from_millis(100))
-                    .context("Failed to receive job")?;
+                let job = match lock_result.recv_timeout(Duration::from_millis(100)) {
+                    Ok(job) => job,
+                    // 空闲时继续等待，只有发送者被丢弃才退出
+                    Err(mpsc::RecvTimeoutError::Timeout) => return Ok(()),
+                    Err(e) => return Err(anyhow!(e).context("Failed to receive job")),
+                };
                 let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                     job();
                 }));
@@ -86,7 +153,7 @@ impl Worker {
                     if e.to_string().contains("disconnected")
                         || e.to_string().contains("Failed to receive job")
                     {
-                        println!("Worker {} disconnected; shutting down.", id);
+                        // 线程池关闭，正常退出
                         break;
                     } else if e.to_string().contains("poisoned") {
                         eprintln!(
@@ -108,38 +175,44 @@ impl Worker {
 
 impl ThreadPool {
     pub fn new(size: usize) -> Result<ThreadPool> {
-        if size == 0 {
+        Ok(ThreadPool {
+            cpu: Lane::new(size, "worker")?,
+            io: None,
+            is_shutdown: false,
+            tokens: TokenSet::default(),
+        })
+    }
+
+    /// 创建分为 I/O 和计算两条通道的线程池
+    ///
+    /// 两条通道的线程数分别为 `io` 和 `cpu`，线程名分别为 `io-worker-N`
+    /// 和 `cpu-worker-N`。`execute` 与 `execute_cpu` 相同。
+    pub fn with_lanes(io: usize, cpu: usize) -> Result<ThreadPool> {
+        if io == 0 || cpu == 0 {
             bail!(ThreadPoolError::InvalidSize);
         }
-        let (sender, receiver) = mpsc::channel();
-        let receiver = Arc::new(Mutex::new(receiver));
-        let mut workers = Vec::with_capacity(size);
-        for id in 0..size {
-            match Worker::new(id, Arc::clone(&receiver)) {
-                Ok(worker) => workers.push(worker),
-                Err(e) => {
-                    // 如果第一个worker就失败，直接返回错误
-                    if workers.is_empty() {
-                        return Err(anyhow!("Failed to create first worker: {}", e));
-                    }
-                    // 否则记录警告并继续
-                    eprintln!("Warning: Failed to create worker {}: {}", id, e);
-                }
-            }
-        }
-        // 确保至少创建了一个worker
-        if workers.is_empty() {
-            bail!("Failed to create any workers");
-        }
         Ok(ThreadPool {
-            workers,
-            sender: Some(sender),
+            cpu: Lane::new(cpu, "cpu-worker")?,
+            io: Some(Lane::new(io, "io-worker")?),
             is_shutdown: false,
+            tokens: TokenSet::default(),
         })
     }
-    /// 获取线程池大小
+    /// 获取线程池大小（所有通道的线程总数）
     pub fn size(&self) -> usize {
-        self.workers.len()
+        self.cpu.workers.len() + self.io.as_ref().map_or(0, |io| io.workers.len())
+    }
+    /// 检查线程池是否分为 I/O 和计算两条通道
+    pub fn has_lanes(&self) -> bool {
+        self.io.is_some()
+    }
+    /// 获取执行 I/O 任务的线程数
+    pub fn io_size(&self) -> usize {
+        self.io.as_ref().unwrap_or(&self.cpu).workers.len()
+    }
+    /// 获取执行计算任务的线程数
+    pub fn cpu_size(&self) -> usize {
+        self.cpu.workers.len()
     }
     /// 检查线程池是否已关闭
     pub fn is_shutdown(&self) -> bool {
@@ -147,22 +220,61 @@ impl ThreadPool {
     }
     /// 执行任务
     pub fn execute<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.execute_cpu(f)
+    }
+
+    /// 执行阻塞 I/O 任务
+    ///
+    /// 分通道时在 I/O 通道执行，否则与 `execute` 相同。
+    pub fn execute_io<F>(&self, f: F) -> Result<()>
     where
         F: FnOnce() + Send + 'static,
     {
         if self.is_shutdown {
             bail!(ThreadPoolError::PoolShutdown);
         }
+        self.io.as_ref().unwrap_or(&self.cpu).send(Box::new(f))
+    }
 
-        let job = Box::new(f);
+    /// 执行计算任务
+    ///
+    /// 分通道时在计算通道执行，否则与 `execute` 相同。
+    pub fn execute_cpu<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if self.is_shutdown {
+            bail!(ThreadPoolError::PoolShutdown);
+        }
+        self.cpu.send(Box::new(f))
+    }
 
-        self.sender
-            .as_ref()
-            .ok_or_else(|| anyhow!(ThreadPoolError::PoolShutdown))?
-            .send(job)
-            .map_err(|e| anyhow!(ThreadPoolError::TaskSubmissionFailed(e.to_string())))?;
+    /// 执行可取消的任务
+    ///
+    /// 任务收到 `token` 的子令牌：`token` 被取消或调用 `cancel_all()` 后，
+    /// 子令牌即变为已取消。尚未开始的任务会被直接跳过，正在运行的任务
+    /// 需自行检查 `is_cancelled()` 并尽快返回。
+    pub fn execute_cancellable<F>(&self, token: &CancellationToken, f: F) -> Result<()>
+    where
+        F: FnOnce(&CancellationToken) + Send + 'static,
+    {
+        let task_token = token.child_token();
+        self.tokens.register(&task_token);
+        self.execute(move || {
+            if !task_token.is_cancelled() {
+                f(&task_token);
+            }
+        })
+    }
 
-        Ok(())
+    /// 取消所有已提交的可取消任务
+    ///
+    /// 只影响调用前提交的任务，之后提交的任务不受影响。
+    pub fn cancel_all(&self) {
+        self.tokens.cancel_all();
     }
 
     pub fn shutdown(&mut self) -> Result<()> {
@@ -173,12 +285,16 @@ impl ThreadPool {
         self.is_shutdown = true;
 
         // 丢弃发送者，这样workers会在处理完所有任务后退出
-        drop(self.sender.take());
+        let mut workers = Vec::new();
+        for lane in std::iter::once(&mut self.cpu).chain(self.io.as_mut()) {
+            drop(lane.sender.take());
+            workers.append(&mut lane.workers);
+        }
 
         // 收集所有join错误
         let mut errors = Vec::new();
 
-        for worker in self.workers.drain(..) {
+        for worker in workers {
             match worker.thread.join() {
                 Ok(()) => (),
                 Err(e) => {
@@ -283,6 +399,18 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_idle_workers() -> Result<()> {
+        let pool = ThreadPool::new(2)?;
+
+        // 空闲超过接收超时后，工作线程仍在等待任务
+        thread::sleep(Duration::from_millis(300));
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(()).unwrap())?;
+        rx.recv_timeout(Duration::from_secs(5))?;
+        Ok(())
+    }
+
     #[test]
     fn test_execute_after_shutdown() -> Result<()> {
         let mut pool = ThreadPool::new(2)?;
@@ -437,6 +565,112 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_execute_cancellable() -> Result<()> {
+        let pool = ThreadPool::new(1)?;
+        let token = CancellationToken::new();
+        let (started_tx, started_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        // 第一个任务一直运行直到被取消
+        pool.execute_cancellable(&token, move |token| {
+            started_tx.send(()).unwrap();
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            done_tx.send(()).unwrap();
+        })?;
+        // 第二个任务排队时被取消，不会执行
+        let ran_clone = Arc::clone(&ran);
+        pool.execute_cancellable(&token, move |_| {
+            ran_clone.fetch_add(1, Ordering::SeqCst);
+        })?;
+
+        started_rx.recv_timeout(Duration::from_secs(5))?;
+        token.cancel();
+        done_rx.recv_timeout(Duration::from_secs(5))?;
+
+        drop(pool);
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancel_all() -> Result<()> {
+        let pool = ThreadPool::new(1)?;
+        let token = CancellationToken::new();
+        let (started_tx, started_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        pool.execute_cancellable(&token, move |token| {
+            started_tx.send(()).unwrap();
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+        })?;
+        let ran_clone = Arc::clone(&ran);
+        pool.execute_cancellable(&token, move |_| {
+            ran_clone.fetch_add(1, Ordering::SeqCst);
+        })?;
+
+        started_rx.recv_timeout(Duration::from_secs(5))?;
+        pool.cancel_all();
+
+        // 调用方的令牌不受影响，之后提交的任务正常执行
+        assert!(!token.is_cancelled());
+        pool.execute_cancellable(&token, move |token| {
+            done_tx.send(token.is_cancelled()).unwrap();
+        })?;
+        assert!(!done_rx.recv_timeout(Duration::from_secs(5))?);
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lanes() -> Result<()> {
+        let pool = ThreadPool::with_lanes(1, 2)?;
+        assert!(pool.has_lanes());
+        assert_eq!((pool.io_size(), pool.cpu_size(), pool.size()), (1, 2, 3));
+        assert!(ThreadPool::with_lanes(0, 2).is_err());
+
+        // 阻塞 I/O 通道的唯一线程，计算任务仍能执行
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        let (io_tx, io_rx) = mpsc::channel();
+        pool.execute_io(move || {
+            let name = thread::current().name().map(str::to_string);
+            release_rx.recv().unwrap();
+            io_tx.send(name).unwrap();
+        })?;
+        let (cpu_tx, cpu_rx) = mpsc::channel();
+        for _ in 0..4 {
+            let cpu_tx = cpu_tx.clone();
+            pool.execute_cpu(move || {
+                let name = thread::current().name().map(str::to_string);
+                cpu_tx.send(name).unwrap();
+            })?;
+        }
+        for _ in 0..4 {
+            let name = cpu_rx.recv_timeout(Duration::from_secs(5))?.unwrap();
+            assert!(name.starts_with("cpu-worker-"));
+        }
+
+        release_tx.send(())?;
+        let name = io_rx.recv_timeout(Duration::from_secs(5))?.unwrap();
+        assert_eq!(name, "io-worker-0");
+
+        // 未分通道时两类任务共用同一组线程
+        let pool = ThreadPool::new(2)?;
+        assert!(!pool.has_lanes());
+        assert_eq!((pool.io_size(), pool.cpu_size(), pool.size()), (2, 2, 2));
+        let (tx, rx) = mpsc::channel();
+        pool.execute_io(move || tx.send(()).unwrap())?;
+        rx.recv_timeout(Duration::from_secs(5))?;
+        Ok(())
+    }
+
     #[test]
     fn test_thread_names() -> Result<()> {
         let pool = ThreadPool::new(2)?;
@@ -0,0 +1,320 @@
+//! Async `mar_perf` measurement
+//!
+//! [`FwctlDevice::mar_perf_config`] blocks the calling thread for the whole
+//! measurement window. [`AsyncFwctlDevice`] starts the measurement and
+//! returns immediately; the query is made after an awaited [`sleep`]. All
+//! sleeps are served by one shared timer thread, so a monitoring service
+//! can measure many ports concurrently from a single task or executor
+//! thread instead of using a thread per port.
+//!
+//! The futures do not depend on any particular runtime and can be awaited
+//! from tokio, async-std or a hand-written executor. The ioctl calls
+//! themselves are short and still made synchronously.
+//!
+//! Only available with the `aio` feature.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use ubfwctl::aio::AsyncFwctlDevice;
+//!
+//! async fn measure() -> Result<(), ubfwctl::UbfwctlError> {
+//!     let device = AsyncFwctlDevice::open(0, 0)?;
+//!     // Both measurements run at the same time
+//!     let first = device.mar_perf_measure(0, 1000);
+//!     let second = device.mar_perf_measure(2, 1000);
+//!     println!("{}", first.await?);
+//!     println!("{}", second.await?);
+//!     Ok(())
+//! }
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Condvar, LazyLock, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::commands::mar_perf::{MarPerfCommand, check_port};
+use crate::error::UbfwctlError;
+use crate::ioctl::FwctlDevice;
+use crate::types::{IoDieInfo, MarPerfResult};
+
+/// Pending wake-up of a sleeping future
+#[derive(Debug)]
+struct TimerEntry {
+    /// When to wake the future
+    deadline: Instant,
+    /// Waker of the future
+    waker: Waker,
+}
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.deadline == other.deadline
+    }
+}
+
+impl Eq for TimerEntry {}
+
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.deadline.cmp(&other.deadline)
+    }
+}
+
+/// Timer thread shared by all [`Sleep`] futures
+#[derive(Debug)]
+struct Timer {
+    /// Pending wake-ups, earliest first
+    entries: Mutex<BinaryHeap<Reverse<TimerEntry>>>,
+    /// Signalled when an entry is added
+    changed: Condvar,
+}
+
+impl Timer {
+    /// Create the timer and start its thread
+    ///
+    /// The thread waits for [`TIMER`] to finish initializing before running.
+    ///
+    /// # Panics
+    /// Panics if the thread cannot be spawned
+    fn start() -> Self {
+        let _handle = thread::Builder::new()
+            .name("ubfwctl-timer".to_string())
+            .spawn(|| TIMER.run())
+            .expect("failed to spawn ubfwctl timer thread");
+        Self {
+            entries: Mutex::new(BinaryHeap::new()),
+            changed: Condvar::new(),
+        }
+    }
+
+    /// Wake futures as their deadlines pass
+    fn run(&self) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            let now = Instant::now();
+            while entries
+                .peek()
+                .is_some_and(|Reverse(entry)| entry.deadline <= now)
+            {
+                if let Some(Reverse(entry)) = entries.pop() {
+                    entry.waker.wake();
+                }
+            }
+            entries = match entries.peek() {
+                Some(Reverse(entry)) => {
+                    let timeout = entry.deadline.saturating_duration_since(now);
+                    self.changed
+                        .wait_timeout(entries, timeout)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+                None => self
+                    .changed
+                    .wait(entries)
+                    .unwrap_or_else(PoisonError::into_inner),
+            };
+        }
+    }
+
+    /// Wake `waker` at `deadline`
+    fn register(&self, deadline: Instant, waker: Waker) {
+        let mut entries = self.entries.lock().unwrap_or_else(PoisonError::into_inner);
+        entries.push(Reverse(TimerEntry { deadline, waker }));
+        drop(entries);
+        self.changed.notify_one();
+    }
+}
+
+/// The shared timer, started on first use
+static TIMER: LazyLock<Timer> = LazyLock::new(Timer::start);
+
+/// Future completing after a duration
+///
+/// Created by [`sleep`].
+#[derive(Debug, Clone, Copy)]
+#[must_use = "futures do nothing unless awaited"]
+pub struct Sleep {
+    /// When the future completes
+    deadline: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            Poll::Ready(())
+        } else {
+            TIMER.register(self.deadline, cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Wait for `duration` without blocking the calling thread
+///
+/// # Panics
+/// The first call panics if the timer thread cannot be spawned
+pub fn sleep(duration: Duration) -> Sleep {
+    // Far-future deadlines are clamped; nobody waits that long
+    let deadline = Instant::now()
+        .checked_add(duration)
+        .unwrap_or_else(|| Instant::now() + Duration::from_secs(u64::from(u32::MAX)));
+    Sleep { deadline }
+}
+
+/// fwctl device with non-blocking `mar_perf` measurement
+#[derive(Debug)]
+pub struct AsyncFwctlDevice {
+    /// Underlying blocking device
+    device: FwctlDevice,
+}
+
+impl AsyncFwctlDevice {
+    /// Open the fwctl device for a chip and die
+    ///
+    /// # Arguments
+    /// * `chip_id` - Chip ID
+    /// * `die_id` - Die ID
+    ///
+    /// # Returns
+    /// `Ok(AsyncFwctlDevice)` on success, `Err(UbfwctlError)` on failure
+    ///
+    /// # Errors
+    /// Returns an error if the device cannot be found or opened
+    pub fn open(chip_id: u32, die_id: u32) -> Result<Self, UbfwctlError> {
+        Ok(Self {
+            device: FwctlDevice::open(chip_id, die_id)?,
+        })
+    }
+
+    /// Get the underlying blocking device
+    #[must_use]
+    pub const fn device(&self) -> &FwctlDevice {
+        &self.device
+    }
+
+    /// Configure a `mar_perf` measurement and return immediately
+    ///
+    /// Query the results with [`Self::mar_perf_query`] once `time_ms` has
+    /// elapsed, or use [`Self::mar_perf_measure`] to do both.
+    ///
+    /// # Errors
+    /// Returns an error if the RPC call fails
+    pub fn mar_perf_config(&self, port: u32, time_ms: u32) -> Result<(), UbfwctlError> {
+        self.device.mar_perf_start(port, time_ms)
+    }
+
+    /// Query `mar_perf` results
+    ///
+    /// # Errors
+    /// Returns an error if the RPC call fails
+    pub fn mar_perf_query(&self, port: u32) -> Result<Vec<u32>, UbfwctlError> {
+        self.device.mar_perf_query(port)
+    }
+
+    /// Query IO die port information
+    ///
+    /// # Errors
+    /// Returns an error if the RPC call fails or the response is invalid
+    pub fn query_io_die_info(&self) -> Result<IoDieInfo, UbfwctlError> {
+        self.device.query_io_die_info()
+    }
+
+    /// Measure a port without blocking the calling thread
+    ///
+    /// Async version of [`MarPerfCommand::execute`] on this device. The
+    /// port pair stays locked until the measurement finishes or the future
+    /// is dropped.
+    ///
+    /// # Arguments
+    /// * `port` - Port ID
+    /// * `time_ms` - Measurement time in milliseconds
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The time parameter is invalid
+    /// - The port cannot be measured (see [`MarPerfCommand::execute`])
+    /// - Shared memory locking fails
+    /// - The ioctl call fails
+    pub async fn mar_perf_measure(
+        &self,
+        port: u32,
+        time_ms: u32,
+    ) -> Result<MarPerfResult, UbfwctlError> {
+        UbfwctlError::validate_time(time_ms)?;
+        check_port(&self.query_io_die_info()?.ports, port, false)?;
+        let info = &self.device.info;
+        let _lock = MarPerfCommand::acquire_shm_lock(info.chip_id, info.die_id, port)?;
+
+        self.mar_perf_config(port, time_ms)?;
+        sleep(Duration::from_millis(u64::from(time_ms))).await;
+        let raw_data = self.mar_perf_query(port)?;
+
+        Ok(MarPerfCommand::calculate(&raw_data, time_ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    /// Waker that unparks the polling thread
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    /// Poll two futures on the current thread until both complete
+    fn block_on_both<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+        let (mut a, mut b) = (std::pin::pin!(a), std::pin::pin!(b));
+        let (mut out_a, mut out_b) = (None, None);
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if out_a.is_none()
+                && let Poll::Ready(value) = a.as_mut().poll(&mut cx)
+            {
+                out_a = Some(value);
+            }
+            if out_b.is_none()
+                && let Poll::Ready(value) = b.as_mut().poll(&mut cx)
+            {
+                out_b = Some(value);
+            }
+            if let (Some(_), Some(_)) = (&out_a, &out_b) {
+                return (out_a.take().unwrap(), out_b.take().unwrap());
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn test_sleeps_run_concurrently() {
+        let start = Instant::now();
+        let ((), ()) = block_on_both(
+            sleep(Duration::from_millis(100)),
+            sleep(Duration::from_millis(50)),
+        );
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100));
+        assert!(elapsed < Duration::from_millis(150 + 500));
+    }
+}
@@ -0,0 +1,252 @@
+//! On-disk cache of device query results
+//!
+//! Listing devices costs an open and several firmware RPCs per IO die, which
+//! adds up on systems with many dies. A scan given a cache with
+//! [`ScanOptions::cache`](crate::device::ScanOptions::cache) therefore keeps
+//! what it learned about each device, its IO die information and backend,
+//! in a small JSON file, typically [`DEVICE_CACHE_PATH`] under `/run`, and
+//! reuses it for the TTL, so that listings repeated in quick succession,
+//! such as successive `memlink ls` calls, skip the RPCs. Scans do not use
+//! the cache unless asked to.
+//!
+//! An entry is only reused while the device still exists with the same
+//! entity name. Port link state read from the cache may be up to the TTL
+//! old; scan with [`ScanOptions::refresh`](crate::device::ScanOptions::refresh)
+//! to force a fresh query. The cache lives on tmpfs, so it does not survive a
+//! reboot, and failing to read or write it never fails a scan.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::device::DiscoveredDevice;
+use crate::error::UbfwctlError;
+use crate::sysfs::Backend;
+use crate::types::IoDieInfo;
+
+/// Default path of the device cache
+pub const DEVICE_CACHE_PATH: &str = "/run/ubfwctl/devices.json";
+
+/// Default time a cached device stays valid
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(10);
+
+/// What is remembered of one device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedDevice {
+    /// Entity name the device had when it was queried
+    pub entity_name: String,
+    /// IO die information including port details
+    pub io_die_info: IoDieInfo,
+    /// Backend that served the port information
+    pub backend: Backend,
+    /// When the device was queried, in seconds since the Unix epoch
+    pub cached_at: u64,
+}
+
+/// Device query results keyed by device name
+#[derive(Debug, Clone)]
+pub struct DeviceCache {
+    /// File the cache is loaded from and saved to
+    path: PathBuf,
+    /// Time an entry stays valid
+    ttl: Duration,
+    /// Entries that have not expired, by device name
+    entries: BTreeMap<String, CachedDevice>,
+}
+
+impl DeviceCache {
+    /// Load the cache at `path`, dropping expired entries
+    ///
+    /// A missing or unreadable file yields an empty cache.
+    ///
+    /// # Arguments
+    /// * `path` - Cache file
+    /// * `ttl` - Time an entry stays valid
+    #[must_use]
+    pub fn load(path: impl Into<PathBuf>, ttl: Duration) -> Self {
+        let path = path.into();
+        let mut entries: BTreeMap<String, CachedDevice> = fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let now = now_secs();
+        entries.retain(|_, entry| is_fresh(entry.cached_at, now, ttl));
+        Self { path, ttl, entries }
+    }
+
+    /// Get the path of the cache file
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the time an entry stays valid
+    #[must_use]
+    pub const fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Get the number of valid entries
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether the cache has no valid entries
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Look up a device
+    ///
+    /// # Arguments
+    /// * `name` - Device name (e.g., "fwctl00")
+    /// * `entity_name` - Current entity name of the device
+    ///
+    /// # Returns
+    /// `None` if the device is not cached, its entry expired or it was
+    /// cached under another entity name
+    #[must_use]
+    pub fn get(&self, name: &str, entity_name: &str) -> Option<&CachedDevice> {
+        self.entries
+            .get(name)
+            .filter(|entry| entry.entity_name == entity_name)
+            .filter(|entry| is_fresh(entry.cached_at, now_secs(), self.ttl))
+    }
+
+    /// Remember a device that was just queried
+    ///
+    /// # Arguments
+    /// * `name` - Device name (e.g., "fwctl00")
+    /// * `device` - Query result
+    pub fn insert(&mut self, name: &str, device: &DiscoveredDevice) {
+        let entry = CachedDevice {
+            entity_name: device.entity_name.clone(),
+            io_die_info: device.io_die_info.clone(),
+            backend: device.backend,
+            cached_at: now_secs(),
+        };
+        let _previous = self.entries.insert(name.to_string(), entry);
+    }
+
+    /// Keep only the devices in `names`
+    pub fn retain_devices(&mut self, names: &[String]) {
+        self.entries.retain(|name, _| names.contains(name));
+    }
+
+    /// Write the cache to its file
+    ///
+    /// The file is replaced atomically, so concurrent readers see either
+    /// the old or the new cache.
+    ///
+    /// # Errors
+    /// `UbfwctlError::IoError` if the file cannot be written, and
+    /// `UbfwctlError::Json` if encoding fails
+    pub fn save(&self) -> Result<(), UbfwctlError> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let data = serde_json::to_vec(&self.entries)?;
+        let tmp = self
+            .path
+            .with_extension(format!("tmp.{}", std::process::id()));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, &self.path).inspect_err(|_| {
+            let _result = fs::remove_file(&tmp);
+        })?;
+        Ok(())
+    }
+}
+
+/// Remove the device cache at [`DEVICE_CACHE_PATH`]
+///
+/// # Errors
+/// `UbfwctlError::IoError` if the file exists but cannot be removed
+pub fn clear() -> Result<(), UbfwctlError> {
+    match fs::remove_file(DEVICE_CACHE_PATH) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Get the current time in seconds since the Unix epoch
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Check whether an entry cached at `cached_at` is still valid at `now`
+///
+/// Entries from the future, left by a clock that was set back, are stale.
+const fn is_fresh(cached_at: u64, now: u64, ttl: Duration) -> bool {
+    cached_at <= now && now - cached_at < ttl.as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::FwctlDeviceInfo;
+
+    fn device(entity_name: &str) -> DiscoveredDevice {
+        let io_die_info = IoDieInfo {
+            port_count: 2,
+            chip_id: 0,
+            die_id: 1,
+            reserved: [0; 3],
+            ports: Vec::new(),
+        };
+        DiscoveredDevice::new(
+            FwctlDeviceInfo::new(0, 1, "/dev/fwctl/fwctl0001"),
+            io_die_info,
+            entity_name.to_string(),
+        )
+        .with_backend(Backend::Sysfs)
+    }
+
+    #[test]
+    fn test_is_fresh() {
+        let ttl = Duration::from_secs(10);
+        assert!(is_fresh(100, 100, ttl));
+        assert!(is_fresh(100, 109, ttl));
+        assert!(!is_fresh(100, 110, ttl));
+        assert!(!is_fresh(101, 100, ttl));
+        assert!(!is_fresh(100, 100, Duration::ZERO));
+    }
+
+    #[test]
+    fn test_device_cache_roundtrip() {
+        let path = std::env::temp_dir()
+            .join(format!("ubfwctl-cache-{}", std::process::id()))
+            .join("devices.json");
+        let ttl = Duration::from_mins(1);
+
+        let mut cache = DeviceCache::load(&path, ttl);
+        assert!(cache.is_empty());
+        cache.insert("fwctl0001", &device("ub_entity1"));
+        cache.insert("fwctl0002", &device("ub_entity2"));
+        cache.retain_devices(&["fwctl0001".to_string()]);
+        cache.save().unwrap();
+
+        let cache = DeviceCache::load(&path, ttl);
+        assert_eq!(cache.len(), 1);
+        let entry = cache.get("fwctl0001", "ub_entity1").unwrap();
+        assert_eq!(entry.io_die_info.port_count, 2);
+        assert_eq!(entry.backend, Backend::Sysfs);
+        // A different device now answers under the name
+        assert!(cache.get("fwctl0001", "ub_entity9").is_none());
+        assert!(cache.get("fwctl0002", "ub_entity2").is_none());
+
+        // Everything expires with a zero TTL
+        assert!(DeviceCache::load(&path, Duration::ZERO).is_empty());
+
+        fs::write(&path, "not json").unwrap();
+        assert!(DeviceCache::load(&path, ttl).is_empty());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -6,8 +6,11 @@
 
 use std::fmt::Write;
 
+use serde::{Deserialize, Serialize};
+
 use crate::device::{DiscoveredDevice, scan_devices};
 use crate::error::UbfwctlError;
+use crate::sysfs::Backend;
 
 /// List command for displaying device information
 ///
@@ -61,6 +64,18 @@ impl ListCommand {
     pub fn execute_raw(&self) -> Result<Vec<DiscoveredDevice>, UbfwctlError> {
         scan_devices()
     }
+
+    /// Execute the list command and return the device list as JSON
+    ///
+    /// # Returns
+    /// `Ok(String)` with the JSON device list on success
+    ///
+    /// # Errors
+    /// `UbfwctlError` if device scanning or encoding fails
+    pub fn execute_json(&self) -> Result<String, UbfwctlError> {
+        let devices = scan_devices()?;
+        format_device_list_json(&devices)
+    }
 }
 
 /// High-level function to list all devices
@@ -116,6 +131,31 @@ pub fn list_devices_raw() -> Result<Vec<DiscoveredDevice>, UbfwctlError> {
     scan_devices()
 }
 
+/// List devices and return them as JSON
+///
+/// Machine-readable counterpart of `list_devices()`; see
+/// `format_device_list_json()` for the layout.
+///
+/// # Returns
+/// `Ok(String)` with the JSON device list on success
+///
+/// # Errors
+/// `UbfwctlError` if device scanning or encoding fails
+///
+/// # Example
+/// ```no_run
+/// use ubfwctl::list_devices_json;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     println!("{}", list_devices_json()?);
+///     Ok(())
+/// }
+/// ```
+pub fn list_devices_json() -> Result<String, UbfwctlError> {
+    let cmd = ListCommand::new();
+    cmd.execute_json()
+}
+
 /// Format a list of discovered devices into a string representation
 ///
 /// The output format matches the C `ubctl ls` command:
@@ -130,6 +170,8 @@ pub fn list_devices_raw() -> Result<Vec<DiscoveredDevice>, UbfwctlError> {
 ///         ...
 /// total ubctl count: 1
 /// ```
+/// Devices listed from sysfs have no ports and carry an extra
+/// `backend: sysfs` line after `port_count`.
 ///
 /// # Arguments
 /// * `devices` - Slice of discovered devices to format
@@ -155,6 +197,42 @@ pub fn format_device_list(devices: &[DiscoveredDevice]) -> String {
     output
 }
 
+/// Format a list of discovered devices as a JSON array
+///
+/// Each element is a [`DeviceInfo`] object:
+/// ```text
+/// [
+///   {
+///     "ubctl_id": 0,
+///     "chip_id": 0,
+///     "die_id": 0,
+///     "path": "/dev/fwctl/fwctl00",
+///     "entity_name": "...",
+///     "port_count": 4,
+///     "backend": "ioctl",
+///     "ports": [
+///       { "port_id": 0, "port_type": "eth", "link_status": "up" },
+///       ...
+///     ]
+///   }
+/// ]
+/// ```
+/// The field names are part of the output format and stay stable for
+/// scripts. `backend` is `"sysfs"` for devices whose RPC is not
+/// permitted. An empty system yields `[]`.
+///
+/// # Arguments
+/// * `devices` - Slice of discovered devices to format
+///
+/// # Returns
+/// `Ok(String)` with pretty-printed JSON on success
+///
+/// # Errors
+/// Returns `UbfwctlError::Json` if encoding fails
+pub fn format_device_list_json(devices: &[DiscoveredDevice]) -> Result<String, UbfwctlError> {
+    Ok(serde_json::to_string_pretty(&to_device_info(devices))?)
+}
+
 /// Format a single device into a string representation
 ///
 /// # Arguments
@@ -172,6 +250,9 @@ pub fn format_device(device: &DiscoveredDevice, ubctl_id: u32) -> String {
     writeln!(output, "\tchip_id: {}", device.chip_id()).unwrap();
     writeln!(output, "\tdie_id: {}", device.die_id()).unwrap();
     writeln!(output, "\tport_count: {}", device.port_count()).unwrap();
+    if device.backend() != Backend::Ioctl {
+        writeln!(output, "\tbackend: {}", device.backend()).unwrap();
+    }
 
     // Port information
     for port in device.ports() {
@@ -186,7 +267,9 @@ pub fn format_device(device: &DiscoveredDevice, ubctl_id: u32) -> String {
 /// Device information structure for display
 ///
 /// This is a simplified version of `DiscoveredDevice` for public API use.
-#[derive(Debug, Clone)]
+/// It is also the element of the JSON device list, so its field names are
+/// kept stable.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DeviceInfo {
     /// ubctl ID (sequential index)
     pub ubctl_id: u32,
@@ -194,14 +277,21 @@ pub struct DeviceInfo {
     pub chip_id: u32,
     /// Die ID
     pub die_id: u32,
+    /// Device path
+    pub path: String,
+    /// Entity name from sysfs
+    pub entity_name: String,
     /// Port count
     pub port_count: u32,
+    /// Backend that served the port information
+    #[serde(default)]
+    pub backend: Backend,
     /// Port information
     pub ports: Vec<PortDisplayInfo>,
 }
 
 /// Port information for display
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PortDisplayInfo {
     /// Port ID
     pub port_id: u32,
@@ -227,7 +317,10 @@ impl From<&DiscoveredDevice> for DeviceInfo {
             ubctl_id: 0, // Will be set by caller
             chip_id: device.chip_id(),
             die_id: device.die_id(),
+            path: device.path().to_string(),
+            entity_name: device.entity_name().to_string(),
             port_count: device.port_count(),
+            backend: device.backend(),
             ports,
         }
     }
@@ -304,6 +397,10 @@ mod tests {
         assert!(output.contains("port_id: 0x1"));
         assert!(output.contains("port_type: ub"));
         assert!(output.contains("link_status: down"));
+        assert!(!output.contains("backend"));
+
+        let output = format_device(&create_test_device().with_backend(Backend::Sysfs), 0);
+        assert!(output.contains("\tport_count: 2\n\tbackend: sysfs\n"));
     }
 
     #[test]
@@ -338,6 +435,27 @@ mod tests {
         assert_eq!(info_list[0].ports.len(), 2);
     }
 
+    #[test]
+    fn test_format_device_list_json() {
+        let devices = vec![create_test_device()];
+        let json = format_device_list_json(&devices).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let device = &value[0];
+        assert_eq!(device["ubctl_id"], 0);
+        assert_eq!(device["chip_id"], 0);
+        assert_eq!(device["die_id"], 0);
+        assert_eq!(device["path"], "/dev/fwctl/fwctl00");
+        assert_eq!(device["entity_name"], "test_entity");
+        assert_eq!(device["port_count"], 2);
+        assert_eq!(device["backend"], "ioctl");
+        assert_eq!(device["ports"][1]["port_id"], 1);
+        assert_eq!(device["ports"][1]["port_type"], "ub");
+        assert_eq!(device["ports"][1]["link_status"], "down");
+
+        assert_eq!(format_device_list_json(&[]).unwrap(), "[]");
+    }
+
     #[test]
     fn test_list_command_new() {
         let _cmd = ListCommand::new();
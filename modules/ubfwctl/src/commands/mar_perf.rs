@@ -7,17 +7,33 @@ use libc::{LOCK_EX, LOCK_NB, c_int, flock};
 
 use crate::error::UbfwctlError;
 use crate::ioctl::FwctlDevice;
-use crate::types::{MarPerfQuery, MarPerfResult};
+use crate::types::{BA_MAR_PERF_NUM_TWO, MarPerfQuery, MarPerfResult, PortInfo};
+
+/// Port type of UB ports in [`PortInfo::port_type`]
+const PORT_TYPE_UB: u32 = 1;
 
 /// `mar_perf` command implementation
 #[derive(Debug, Clone, Copy)]
-pub struct MarPerfCommand;
+pub struct MarPerfCommand {
+    /// Measure ports whose link is down
+    force: bool,
+}
 
 impl MarPerfCommand {
     /// Create a new `mar_perf` command
     #[must_use]
     pub const fn new() -> Self {
-        Self
+        Self { force: false }
+    }
+
+    /// Measure ports whose link is down
+    ///
+    /// Such measurements read zero traffic; the port must still exist and
+    /// be a UB port.
+    #[must_use]
+    pub const fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
     }
 
     /// Execute the `mar_perf` measurement
@@ -35,6 +51,8 @@ impl MarPerfCommand {
     /// Returns an error if:
     /// - The time parameter is invalid
     /// - The device cannot be opened
+    /// - The port is not on the die (`InvalidPort`), is not a UB port or
+    ///   its link is down without [`Self::force`] (`PortNotMeasurable`)
     /// - The ioctl call fails
     /// - Shared memory locking fails
     pub fn execute(
@@ -47,28 +65,87 @@ impl MarPerfCommand {
         // Validate time parameter
         UbfwctlError::validate_time(time_ms)?;
 
+        // Open device and refuse ports the firmware cannot measure
+        let device = FwctlDevice::open(chip_id, die_id)?;
+        check_port(&device.query_io_die_info()?.ports, port, self.force)?;
+
         // Acquire shared memory lock for concurrent access safety
         let _lock = Self::acquire_shm_lock(chip_id, die_id, port)?;
 
-        // Open device
-        let device = FwctlDevice::open(chip_id, die_id)?;
-
         // Configuration phase - starts the measurement
         device.mar_perf_config(port, time_ms)?;
 
         // Query phase - get the results
         let raw_data = device.mar_perf_query(port)?;
 
+        Ok(Self::calculate(&raw_data, time_ms))
+    }
+
+    /// Execute the `mar_perf` measurement on every port pair of a die
+    ///
+    /// The measurements of all port pairs are started back to back and run
+    /// concurrently, so the whole die takes about `time_ms` instead of
+    /// `time_ms` per pair. Only pairs with a measurable port are measured;
+    /// see [`measurable_ports`].
+    ///
+    /// # Arguments
+    /// * `chip_id` - Chip ID
+    /// * `die_id` - Die ID
+    /// * `time_ms` - Measurement time in milliseconds
+    ///
+    /// # Returns
+    /// `Ok(Vec<MarPerfResult>)` with one result per port pair, ordered by
+    /// `first_port_id`, on success, `Err(UbfwctlError)` on failure
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The time parameter is invalid
+    /// - The device cannot be opened
+    /// - The port information cannot be queried
+    /// - An ioctl call fails
+    /// - Shared memory locking fails for any port pair
+    pub fn execute_all(
+        &self,
+        chip_id: u32,
+        die_id: u32,
+        time_ms: u32,
+    ) -> Result<Vec<MarPerfResult>, UbfwctlError> {
+        UbfwctlError::validate_time(time_ms)?;
+
+        let device = FwctlDevice::open(chip_id, die_id)?;
+        let ports = pair_ports(&measurable(&device.query_io_die_info()?.ports, self.force));
+
+        // Hold the locks of all pairs for the whole measurement
+        let _locks = ports
+            .iter()
+            .map(|&port| Self::acquire_shm_lock(chip_id, die_id, port))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Start every pair, then wait once: the last pair started finishes last
+        for &port in &ports {
+            device.mar_perf_start(port, time_ms)?;
+        }
+        let sleep_us = time_ms * crate::error::MS_TO_US;
+        std::thread::sleep(std::time::Duration::from_micros(u64::from(sleep_us)));
+
+        ports
+            .iter()
+            .map(|&port| {
+                let raw_data = device.mar_perf_query(port)?;
+                Ok(Self::calculate(&raw_data, time_ms))
+            })
+            .collect()
+    }
+
+    /// Calculate results from raw query data
+    pub(crate) fn calculate(raw_data: &[u32], time_ms: u32) -> MarPerfResult {
         // Parse query data
-        let query = MarPerfQuery::from_raw_data(&raw_data);
+        let query = MarPerfQuery::from_raw_data(raw_data);
 
         // Get clock frequency from raw data
         let clock_freq_hz = raw_data.get(1).copied().unwrap_or(0);
 
-        // Calculate results
-        let result = MarPerfResult::calculate(&query, time_ms, clock_freq_hz);
-
-        Ok(result)
+        MarPerfResult::calculate(&query, time_ms, clock_freq_hz)
     }
 
     /// Acquire shared memory lock for concurrent access safety
@@ -80,7 +157,7 @@ impl MarPerfCommand {
     ///
     /// # Returns
     /// `Ok(LockGuard)` on success, `Err(UbfwctlError)` on failure
-    fn acquire_shm_lock(
+    pub(crate) fn acquire_shm_lock(
         chip_id: u32,
         die_id: u32,
         port: u32,
@@ -93,6 +170,60 @@ impl MarPerfCommand {
     }
 }
 
+/// Check that `mar_perf` can measure a port of a die
+///
+/// # Arguments
+/// * `ports` - Ports of the die, as discovered
+/// * `port` - Port ID to measure
+/// * `force` - Accept a port whose link is down
+///
+/// # Errors
+/// Returns `UbfwctlError::InvalidPort` if the die has no such port and
+/// `UbfwctlError::PortNotMeasurable` if it is not a UB port or, unless
+/// `force` is set, its link is down
+pub(crate) fn check_port(ports: &[PortInfo], port: u32, force: bool) -> Result<(), UbfwctlError> {
+    let info = ports
+        .iter()
+        .find(|info| info.port_id == port)
+        .ok_or(UbfwctlError::InvalidPort(port))?;
+    if info.port_type != PORT_TYPE_UB {
+        return Err(UbfwctlError::PortNotMeasurable {
+            port,
+            reason: "not a ub port",
+        });
+    }
+    if info.link_status == 0 && !force {
+        return Err(UbfwctlError::PortNotMeasurable {
+            port,
+            reason: "link is down",
+        });
+    }
+    Ok(())
+}
+
+/// Get the IDs of the ports `mar_perf` can measure, in discovery order
+fn measurable(ports: &[PortInfo], force: bool) -> Vec<u32> {
+    ports
+        .iter()
+        .filter(|info| check_port(ports, info.port_id, force).is_ok())
+        .map(|info| info.port_id)
+        .collect()
+}
+
+/// Get the first port of each port pair, in ascending order
+///
+/// `mar_perf` measures ports in pairs (`2n`, `2n + 1`), so one measurement
+/// per pair covers every port.
+fn pair_ports(ports: &[u32]) -> Vec<u32> {
+    let mut firsts: Vec<u32> = ports
+        .iter()
+        .map(|port| port - port % BA_MAR_PERF_NUM_TWO)
+        .collect();
+    firsts.sort_unstable();
+    firsts.dedup();
+    firsts
+}
+
 impl Default for MarPerfCommand {
     fn default() -> Self {
         Self::new()
@@ -160,6 +291,36 @@ impl Drop for ShmLockGuard {
 unsafe impl Send for ShmLockGuard {}
 unsafe impl Sync for ShmLockGuard {}
 
+/// List the ports of a die that `mar_perf` can measure
+///
+/// A port is measurable if it is a UB port and its link is up.
+///
+/// # Arguments
+/// * `chip_id` - Chip ID
+/// * `die_id` - Die ID
+///
+/// # Returns
+/// `Ok(Vec<u32>)` with the measurable port IDs on success,
+/// `Err(UbfwctlError)` on failure
+///
+/// # Errors
+/// Returns an error if the device cannot be opened or the port
+/// information cannot be queried
+///
+/// # Example
+/// ```no_run
+/// use ubfwctl::commands::mar_perf::measurable_ports;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     println!("Measurable ports: {:?}", measurable_ports(0, 0)?);
+///     Ok(())
+/// }
+/// ```
+pub fn measurable_ports(chip_id: u32, die_id: u32) -> Result<Vec<u32>, UbfwctlError> {
+    let device = FwctlDevice::open(chip_id, die_id)?;
+    Ok(measurable(&device.query_io_die_info()?.ports, false))
+}
+
 /// High-level convenience function for `mar_perf` measurement
 ///
 /// # Arguments
@@ -175,6 +336,7 @@ unsafe impl Sync for ShmLockGuard {}
 /// Returns an error if:
 /// - The time parameter is invalid
 /// - The device cannot be opened
+/// - The port is not a UB port of the die with its link up
 /// - The ioctl call fails
 /// - Shared memory locking fails
 ///
@@ -199,6 +361,65 @@ pub fn mar_perf_measure(
     cmd.execute(chip_id, die_id, port, time_ms)
 }
 
+/// High-level convenience function for `mar_perf` measurement of a whole die
+///
+/// # Arguments
+/// * `chip_id` - Chip ID
+/// * `die_id` - Die ID
+/// * `time_ms` - Measurement time in milliseconds
+///
+/// # Returns
+/// `Ok(Vec<MarPerfResult>)` with one result per port pair on success,
+/// `Err(UbfwctlError)` on failure
+///
+/// # Errors
+/// Returns an error if:
+/// - The time parameter is invalid
+/// - The device cannot be opened
+/// - The ioctl call fails
+/// - Shared memory locking fails
+///
+/// # Example
+/// ```no_run
+/// use ubfwctl::commands::mar_perf::mar_perf_measure_all;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     for result in mar_perf_measure_all(0, 0, 1000)? {
+///         println!(
+///             "Ports {}-{}: {} bytes/s",
+///             result.first_port_id, result.second_port_id, result.sum_traffic
+///         );
+///     }
+///     Ok(())
+/// }
+/// ```
+pub fn mar_perf_measure_all(
+    chip_id: u32,
+    die_id: u32,
+    time_ms: u32,
+) -> Result<Vec<MarPerfResult>, UbfwctlError> {
+    let cmd = MarPerfCommand::new();
+    cmd.execute_all(chip_id, die_id, time_ms)
+}
+
+/// Format `mar_perf` results as a JSON array
+///
+/// Each element is an object keyed by the `MarPerfResult` field names
+/// (`first_port_id`, `wr_traffic`, ...). The names are part of the
+/// output format and stay stable for scripts.
+///
+/// # Arguments
+/// * `results` - Results to format
+///
+/// # Returns
+/// `Ok(String)` with pretty-printed JSON on success
+///
+/// # Errors
+/// Returns `UbfwctlError::Json` if encoding fails
+pub fn format_mar_perf_json(results: &[MarPerfResult]) -> Result<String, UbfwctlError> {
+    Ok(serde_json::to_string_pretty(results)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -208,4 +429,56 @@ mod tests {
         let _cmd = MarPerfCommand::new();
         // Just verify it can be created
     }
+
+    #[test]
+    fn test_format_mar_perf_json() {
+        let result = MarPerfResult {
+            first_port_id: 2,
+            second_port_id: 3,
+            wr_traffic: 100,
+            ..MarPerfResult::default()
+        };
+        let json = format_mar_perf_json(&[result]).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(value[0]["first_port_id"], 2);
+        assert_eq!(value[0]["second_port_id"], 3);
+        assert_eq!(value[0]["wr_traffic"], 100);
+        assert_eq!(value[0]["rd_delayed"], 0);
+    }
+
+    #[test]
+    fn test_pair_ports() {
+        assert_eq!(pair_ports(&[5, 0, 1, 4, 8]), vec![0, 4, 8]);
+        assert!(pair_ports(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_check_port() {
+        let port = |port_id, port_type, link_status| PortInfo {
+            port_id,
+            link_status,
+            port_type,
+            ..PortInfo::default()
+        };
+        let ports = [port(0, 1, 1), port(1, 1, 0), port(2, 0, 1)];
+
+        assert!(check_port(&ports, 0, false).is_ok());
+        assert!(matches!(
+            check_port(&ports, 7, true),
+            Err(UbfwctlError::InvalidPort(7))
+        ));
+        assert!(matches!(
+            check_port(&ports, 1, false),
+            Err(UbfwctlError::PortNotMeasurable { port: 1, .. })
+        ));
+        assert!(check_port(&ports, 1, true).is_ok());
+        assert!(matches!(
+            check_port(&ports, 2, true),
+            Err(UbfwctlError::PortNotMeasurable { port: 2, .. })
+        ));
+
+        assert_eq!(measurable(&ports, false), vec![0]);
+        assert_eq!(measurable(&ports, true), vec![0, 1]);
+    }
 }
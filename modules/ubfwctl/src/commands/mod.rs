@@ -2,6 +2,7 @@
 
 pub mod list;
 pub mod mar_perf;
+pub mod monitor;
 
 /// Trait for fwctl commands
 ///
@@ -0,0 +1,278 @@
+//! Continuous `mar_perf` monitoring of one port
+//!
+//! [`MarPerfMonitor`] measures a port back to back, one measurement window
+//! per sample, and yields timestamped [`MarPerfSample`]s. Samples can be
+//! written as CSV with [`MarPerfCsvWriter`].
+
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::commands::mar_perf::{MarPerfCommand, ShmLockGuard, check_port};
+use crate::error::UbfwctlError;
+use crate::ioctl::FwctlDevice;
+use crate::types::MarPerfResult;
+
+/// Default sampling interval in milliseconds
+pub const DEFAULT_INTERVAL_MS: u32 = 1000;
+
+/// One timestamped `mar_perf` measurement
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MarPerfSample {
+    /// End of the measurement window
+    pub timestamp: SystemTime,
+    /// Results over the measurement window
+    pub result: MarPerfResult,
+}
+
+/// Builder for continuous `mar_perf` monitoring of a port
+///
+/// # Example
+/// ```no_run
+/// use ubfwctl::commands::monitor::MarPerfMonitor;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let samples = MarPerfMonitor::new(0, 0, 0).interval_ms(500).count(20).start()?;
+///     for sample in samples {
+///         let sample = sample?;
+///         println!("{:?}: {} bytes/s", sample.timestamp, sample.result.sum_traffic);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct MarPerfMonitor {
+    /// Chip ID
+    chip_id: u32,
+    /// Die ID
+    die_id: u32,
+    /// Port ID
+    port: u32,
+    /// Sampling interval in milliseconds
+    interval_ms: u32,
+    /// Number of samples to take, `None` for no limit
+    count: Option<usize>,
+    /// Monitor the port even if its link is down
+    force: bool,
+}
+
+impl MarPerfMonitor {
+    /// Create a monitor for a port sampling every [`DEFAULT_INTERVAL_MS`]
+    /// with no sample limit
+    ///
+    /// # Arguments
+    /// * `chip_id` - Chip ID
+    /// * `die_id` - Die ID
+    /// * `port` - Port ID
+    #[must_use]
+    pub const fn new(chip_id: u32, die_id: u32, port: u32) -> Self {
+        Self {
+            chip_id,
+            die_id,
+            port,
+            interval_ms: DEFAULT_INTERVAL_MS,
+            count: None,
+            force: false,
+        }
+    }
+
+    /// Set the sampling interval, which is also the measurement window of
+    /// each sample
+    #[must_use]
+    pub const fn interval_ms(mut self, interval_ms: u32) -> Self {
+        self.interval_ms = interval_ms;
+        self
+    }
+
+    /// Stop after `count` samples
+    #[must_use]
+    pub const fn count(mut self, count: usize) -> Self {
+        self.count = Some(count);
+        self
+    }
+
+    /// Monitor the port even if its link is down
+    #[must_use]
+    pub const fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Start monitoring
+    ///
+    /// The port pair stays locked until the returned iterator is dropped.
+    ///
+    /// # Returns
+    /// `Ok(MarPerfSamples)` iterating over the samples on success,
+    /// `Err(UbfwctlError)` on failure
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The interval is not a valid measurement time
+    /// - The device cannot be opened
+    /// - The port cannot be measured (see [`MarPerfCommand::execute`])
+    /// - Shared memory locking fails
+    pub fn start(self) -> Result<MarPerfSamples, UbfwctlError> {
+        UbfwctlError::validate_time(self.interval_ms)?;
+        let device = FwctlDevice::open(self.chip_id, self.die_id)?;
+        check_port(&device.query_io_die_info()?.ports, self.port, self.force)?;
+        let lock = MarPerfCommand::acquire_shm_lock(self.chip_id, self.die_id, self.port)?;
+        Ok(MarPerfSamples {
+            device,
+            _lock: lock,
+            port: self.port,
+            interval_ms: self.interval_ms,
+            remaining: self.count,
+            failed: false,
+        })
+    }
+}
+
+/// Iterator over the samples of a running [`MarPerfMonitor`]
+///
+/// Each call to `next` blocks for one sampling interval. Iteration ends
+/// after the configured number of samples or after the first error.
+#[derive(Debug)]
+pub struct MarPerfSamples {
+    /// Open fwctl device
+    device: FwctlDevice,
+    /// Lock on the port pair
+    _lock: ShmLockGuard,
+    /// Port ID
+    port: u32,
+    /// Sampling interval in milliseconds
+    interval_ms: u32,
+    /// Samples left to take, `None` for no limit
+    remaining: Option<usize>,
+    /// Whether a sample failed
+    failed: bool,
+}
+
+impl MarPerfSamples {
+    /// Take one sample
+    fn sample(&self) -> Result<MarPerfSample, UbfwctlError> {
+        self.device.mar_perf_config(self.port, self.interval_ms)?;
+        let raw_data = self.device.mar_perf_query(self.port)?;
+        Ok(MarPerfSample {
+            timestamp: SystemTime::now(),
+            result: MarPerfCommand::calculate(&raw_data, self.interval_ms),
+        })
+    }
+}
+
+impl Iterator for MarPerfSamples {
+    type Item = Result<MarPerfSample, UbfwctlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.remaining == Some(0) {
+            return None;
+        }
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining -= 1;
+        }
+        let sample = self.sample();
+        self.failed = sample.is_err();
+        Some(sample)
+    }
+}
+
+/// CSV writer for `mar_perf` samples
+///
+/// Writes a header row on creation and one row per sample. Timestamps are
+/// milliseconds since the Unix epoch.
+///
+/// # Example
+/// ```
+/// use ubfwctl::commands::monitor::MarPerfCsvWriter;
+///
+/// let writer = MarPerfCsvWriter::new(Vec::new()).unwrap();
+/// let csv = String::from_utf8(writer.into_inner()).unwrap();
+/// assert!(csv.starts_with("timestamp_ms,first_port_id,"));
+/// ```
+#[derive(Debug)]
+pub struct MarPerfCsvWriter<W: Write> {
+    /// Destination of the CSV rows
+    writer: W,
+}
+
+impl<W: Write> MarPerfCsvWriter<W> {
+    /// Create a writer and write the header row
+    ///
+    /// # Errors
+    /// Returns an error if writing the header fails
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writeln!(
+            writer,
+            "timestamp_ms,first_port_id,second_port_id,wr_traffic,rd_traffic,sum_traffic,\
+             wr_pld_avg_len,rd_pld_avg_len,pld_avg_len,wr_delayed,rd_delayed"
+        )?;
+        Ok(Self { writer })
+    }
+
+    /// Write one sample as a row
+    ///
+    /// # Errors
+    /// Returns an error if writing the row fails
+    pub fn write_sample(&mut self, sample: &MarPerfSample) -> io::Result<()> {
+        let timestamp_ms = sample
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis());
+        let r = &sample.result;
+        writeln!(
+            self.writer,
+            "{timestamp_ms},{},{},{},{},{},{},{},{},{},{}",
+            r.first_port_id,
+            r.second_port_id,
+            r.wr_traffic,
+            r.rd_traffic,
+            r.sum_traffic,
+            r.wr_pld_avg_len,
+            r.rd_pld_avg_len,
+            r.pld_avg_len,
+            r.wr_delayed,
+            r.rd_delayed
+        )?;
+        self.writer.flush()
+    }
+
+    /// Get the underlying writer back
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_csv_writer() {
+        let mut writer = MarPerfCsvWriter::new(Vec::new()).unwrap();
+        let sample = MarPerfSample {
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_500),
+            result: MarPerfResult {
+                first_port_id: 2,
+                second_port_id: 3,
+                wr_traffic: 100,
+                rd_delayed: 7,
+                ..MarPerfResult::default()
+            },
+        };
+        writer.write_sample(&sample).unwrap();
+
+        let csv = String::from_utf8(writer.into_inner()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].split(',').count(), 11);
+        assert_eq!(lines[1], "1500,2,3,100,0,0,0,0,0,0,7");
+    }
+
+    #[test]
+    fn test_monitor_rejects_invalid_interval() {
+        let result = MarPerfMonitor::new(0, 0, 0).interval_ms(0).start();
+        assert!(matches!(result, Err(UbfwctlError::InvalidTime(0))));
+    }
+}
@@ -5,23 +5,36 @@
 //!
 //! # Device Discovery Process
 //!
-//! 1. Scan `/dev/fwctl/` directory for device nodes matching `fwctl*`
+//! 1. Scan `/dev/fwctl/` directory for device nodes matching `fwctl*` (or
+//!    `/sys/class/fwctl/` if the device nodes are not visible)
 //! 2. Verify each device is a ubase device by checking `/sys/class/fwctl/{device}/device/uevent`
-//! 3. Query IO die information from each device to get `chip_id`, `die_id`, and port details
+//! 3. Query IO die information from each device to get `chip_id`, `die_id`, and port details,
+//!    or listing the device without ports from [sysfs](crate::sysfs) if the RPC is
+//!    not permitted
 //! 4. Return a list of discovered devices with their metadata
+//!
+//! Step 3 runs in parallel across devices on a bounded thread pool. Scans
+//! that opt into the [device cache](crate::cache) skip it for devices
+//! queried within the last few seconds and read their results from there.
 
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use threadpool::ThreadPool;
 
+use crate::cache::{CachedDevice, DeviceCache};
 use crate::error::UbfwctlError;
 use crate::ioctl::{FWCTL_DEV_DIR, FWCTL_DEV_PREFIX, FwctlDevice};
+use crate::sysfs::{Backend, SysfsDevice, device_names, should_fall_back};
 use crate::types::{FwctlDeviceInfo, IoDieInfo};
 
 /// Sysfs path for fwctl class devices
-const SYS_CLASS_FWCTL_PATH: &str = "/sys/class/fwctl";
+pub(crate) const SYS_CLASS_FWCTL_PATH: &str = "/sys/class/fwctl";
 
 /// Uevent file name within device sysfs
-const UEVENT_FILE: &str = "device/uevent";
+pub(crate) const UEVENT_FILE: &str = "device/uevent";
 
 /// Driver key in uevent file
 const DRIVER_KEY: &str = "DRIVER";
@@ -41,6 +54,8 @@ pub struct DiscoveredDevice {
     pub io_die_info: IoDieInfo,
     /// Entity name from sysfs
     pub entity_name: String,
+    /// Backend that served the port information
+    pub backend: Backend,
 }
 
 impl DiscoveredDevice {
@@ -56,9 +71,17 @@ impl DiscoveredDevice {
             info,
             io_die_info,
             entity_name,
+            backend: Backend::Ioctl,
         }
     }
 
+    /// Record the backend that served the port information
+    #[must_use]
+    pub const fn with_backend(mut self, backend: Backend) -> Self {
+        self.backend = backend;
+        self
+    }
+
     /// Get the device path
     #[must_use]
     pub fn path(&self) -> &str {
@@ -83,6 +106,12 @@ impl DiscoveredDevice {
         &self.entity_name
     }
 
+    /// Get the backend that served the port information
+    #[must_use]
+    pub const fn backend(&self) -> Backend {
+        self.backend
+    }
+
     /// Get the number of ports
     #[must_use]
     pub const fn port_count(&self) -> u32 {
@@ -96,10 +125,96 @@ impl DiscoveredDevice {
     }
 }
 
+/// Default number of devices queried at once
+pub const DEFAULT_SCAN_CONCURRENCY: usize = 8;
+
+/// Options of a device scan
+///
+/// # Example
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use ubfwctl::device::{ScanOptions, scan_devices_with};
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     // Query four devices at a time, reusing results of the last ten seconds
+///     let options = ScanOptions::new()
+///         .concurrency(4)
+///         .cache("/run/ubfwctl/devices.json", Duration::from_secs(10));
+///     let devices = scan_devices_with(&options)?;
+///     println!("Found {} devices", devices.len());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Maximum number of devices queried at once
+    concurrency: usize,
+    /// Cache file and TTL, `None` to always query the devices
+    cache: Option<(PathBuf, Duration)>,
+    /// Whether to query every device but still update the cache
+    refresh: bool,
+}
+
+impl ScanOptions {
+    /// Create options querying [`DEFAULT_SCAN_CONCURRENCY`] devices at once
+    /// without a cache
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            concurrency: DEFAULT_SCAN_CONCURRENCY,
+            cache: None,
+            refresh: false,
+        }
+    }
+
+    /// Set the maximum number of devices queried at once (at least 1)
+    #[must_use]
+    pub const fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = if concurrency == 0 { 1 } else { concurrency };
+        self
+    }
+
+    /// Use the cache file at `path`, whose entries stay valid for `ttl`
+    ///
+    /// [`DEVICE_CACHE_PATH`](crate::cache::DEVICE_CACHE_PATH) and
+    /// [`DEFAULT_CACHE_TTL`](crate::cache::DEFAULT_CACHE_TTL) suit listings
+    /// repeated from the command line.
+    #[must_use]
+    pub fn cache(mut self, path: impl Into<PathBuf>, ttl: Duration) -> Self {
+        self.cache = Some((path.into(), ttl));
+        self
+    }
+
+    /// Query every device, replacing its cached results
+    #[must_use]
+    pub const fn refresh(mut self) -> Self {
+        self.refresh = true;
+        self
+    }
+
+    /// Query every device, neither reading nor writing the cache
+    #[must_use]
+    pub fn no_cache(mut self) -> Self {
+        self.cache = None;
+        self
+    }
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Scan for all fwctl devices in the system
 ///
 /// This function scans `/dev/fwctl/` directory, verifies each device is a ubase
 /// device by checking sysfs, and queries IO die information from each device.
+/// Devices whose RPC is not permitted are listed from sysfs instead; see
+/// [`DiscoveredDevice::backend`]. Devices are queried in parallel and
+/// every device is queried afresh; see [`scan_devices_with`] to reuse
+/// recent results from the [cache](crate::cache).
 ///
 /// # Returns
 /// `Ok(Vec<DiscoveredDevice>)` containing all discovered devices, or `Err(UbfwctlError)`
@@ -122,60 +237,79 @@ impl DiscoveredDevice {
 /// }
 /// ```
 pub fn scan_devices() -> Result<Vec<DiscoveredDevice>, UbfwctlError> {
-    let dev_path = Path::new(FWCTL_DEV_DIR);
-
-    if !dev_path.exists() {
-        return Err(UbfwctlError::DeviceNotFound {
-            chip_id: 0,
-            die_id: 0,
-        });
-    }
-
-    let mut devices = Vec::new();
-    let entries = fs::read_dir(dev_path).map_err(UbfwctlError::IoError)?;
-
-    for entry in entries {
-        let entry = entry.map_err(UbfwctlError::IoError)?;
-        let name = entry.file_name();
-        let name_str = name.to_string_lossy();
+    scan_devices_with(&ScanOptions::new())
+}
 
-        // Skip non-fwctl entries
-        if !name_str.starts_with(FWCTL_DEV_PREFIX) {
-            continue;
-        }
+/// Scan for all fwctl devices with explicit options
+///
+/// Devices not found in the cache are queried on a thread pool of up to
+/// `concurrency` threads, one device per task. Results are reported in
+/// device order whatever order the queries finish in.
+///
+/// # Arguments
+/// * `options` - Concurrency and cache settings
+///
+/// # Errors
+/// Same as [`scan_devices`]
+pub fn scan_devices_with(options: &ScanOptions) -> Result<Vec<DiscoveredDevice>, UbfwctlError> {
+    let names = device_names()?;
 
+    let mut targets = Vec::new();
+    for name in names {
         // Check if this is a ubase device
-        let Some(entity_name) = check_ubase_device(&name_str) else {
+        let Some(entity_name) = check_ubase_device(&name) else {
             continue;
         };
 
         // Parse chip_id and die_id from device name
         // Format: fwctl{chip_id}{die_id} where combined = (chip_id << 16) | die_id
-        let (chip_id, die_id) = parse_device_id(&name_str)?;
-
-        // Open device and query IO die info
-        let device = match FwctlDevice::open(chip_id, die_id) {
-            Ok(dev) => dev,
-            Err(e) => {
-                // Log warning but continue scanning other devices
-                eprintln!("Warning: Failed to open device {name_str}: {e}");
-                continue;
-            }
-        };
-
-        // Query IO die information
-        let io_die_info = match device.query_io_die_info() {
-            Ok(info) => info,
-            Err(e) => {
-                eprintln!("Warning: Failed to query IO die info for {name_str}: {e}");
-                continue;
-            }
-        };
+        let (chip_id, die_id) = parse_device_id(&name)?;
+        targets.push(ScanTarget {
+            name,
+            entity_name,
+            chip_id,
+            die_id,
+        });
+    }
 
-        let device_info =
-            FwctlDeviceInfo::new(chip_id, die_id, format!("{FWCTL_DEV_DIR}/{name_str}"));
+    let mut cache = options
+        .cache
+        .as_ref()
+        .map(|(path, ttl)| DeviceCache::load(path, *ttl));
+    let mut results: Vec<Option<Result<DiscoveredDevice, String>>> = targets
+        .iter()
+        .map(|target| {
+            cache
+                .as_ref()
+                .filter(|_| !options.refresh)
+                .and_then(|cache| cache.get(&target.name, &target.entity_name))
+                .map(|entry| Ok(target.cached(entry)))
+        })
+        .collect();
+    let pending: Vec<usize> = (0..targets.len())
+        .filter(|&index| results[index].is_none())
+        .collect();
+
+    for (index, result) in query_parallel(&targets, &pending, options.concurrency) {
+        if let (Some(cache), Ok(device)) = (cache.as_mut(), &result) {
+            cache.insert(&targets[index].name, device);
+        }
+        results[index] = Some(result);
+    }
+    if let Some(cache) = cache.as_mut().filter(|_| !pending.is_empty()) {
+        let names: Vec<String> = targets.iter().map(|target| target.name.clone()).collect();
+        cache.retain_devices(&names);
+        // Best effort: a scan without a writable cache is just slower
+        let _result = cache.save();
+    }
 
-        devices.push(DiscoveredDevice::new(device_info, io_die_info, entity_name));
+    let mut devices = Vec::new();
+    for result in results.into_iter().flatten() {
+        match result {
+            Ok(device) => devices.push(device),
+            // Log warning but keep the other devices
+            Err(warning) => eprintln!("Warning: {warning}"),
+        }
     }
 
     if devices.is_empty() {
@@ -195,6 +329,117 @@ pub fn scan_devices() -> Result<Vec<DiscoveredDevice>, UbfwctlError> {
     Ok(devices)
 }
 
+/// A ubase device to be queried
+#[derive(Debug, Clone)]
+struct ScanTarget {
+    /// Device name (e.g., "fwctl00")
+    name: String,
+    /// Entity name from sysfs
+    entity_name: String,
+    /// Chip ID
+    chip_id: u32,
+    /// Die ID
+    die_id: u32,
+}
+
+impl ScanTarget {
+    /// Get the identification information of the device
+    fn info(&self) -> FwctlDeviceInfo {
+        FwctlDeviceInfo::new(
+            self.chip_id,
+            self.die_id,
+            format!("{FWCTL_DEV_DIR}/{}", self.name),
+        )
+    }
+
+    /// Rebuild the device from a cache entry
+    fn cached(&self, entry: &CachedDevice) -> DiscoveredDevice {
+        DiscoveredDevice::new(
+            self.info(),
+            entry.io_die_info.clone(),
+            self.entity_name.clone(),
+        )
+        .with_backend(entry.backend)
+    }
+
+    /// Query the device, falling back to sysfs if the RPC is not permitted
+    ///
+    /// # Returns
+    /// The device, or a warning explaining why it is left out
+    fn discover(&self) -> Result<DiscoveredDevice, String> {
+        let name = &self.name;
+        match query_device(self.chip_id, self.die_id) {
+            Ok(io_die_info) => Ok(DiscoveredDevice::new(
+                self.info(),
+                io_die_info,
+                self.entity_name.clone(),
+            )),
+            Err(e) if should_fall_back(&e) => SysfsDevice::open(name)
+                .map(|sysfs| sysfs.io_die_info(self.chip_id, self.die_id))
+                .map(|io_die_info| {
+                    DiscoveredDevice::new(self.info(), io_die_info, self.entity_name.clone())
+                        .with_backend(Backend::Sysfs)
+                })
+                .map_err(|sysfs_err| {
+                    format!("Failed to query {name}: {e}; sysfs fallback: {sysfs_err}")
+                }),
+            Err(e) => Err(format!("Failed to query IO die info for {name}: {e}")),
+        }
+    }
+}
+
+/// Query the devices at `pending` of `targets`, up to `concurrency` at once
+///
+/// Falls back to querying one device at a time if no thread pool can be
+/// started.
+///
+/// # Returns
+/// The index and outcome of each queried device, in no particular order
+fn query_parallel(
+    targets: &[ScanTarget],
+    pending: &[usize],
+    concurrency: usize,
+) -> Vec<(usize, Result<DiscoveredDevice, String>)> {
+    let serial = |indices: &[usize]| -> Vec<_> {
+        indices
+            .iter()
+            .map(|&index| (index, targets[index].discover()))
+            .collect()
+    };
+    if pending.len() <= 1 {
+        return serial(pending);
+    }
+    let Ok(pool) = ThreadPool::new(concurrency.clamp(1, pending.len())) else {
+        return serial(pending);
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut unsent = Vec::new();
+    for &index in pending {
+        let target = targets[index].clone();
+        let tx = tx.clone();
+        if pool
+            .execute_io(move || {
+                let _result = tx.send((index, target.discover()));
+            })
+            .is_err()
+        {
+            unsent.push(index);
+        }
+    }
+    drop(tx);
+
+    // A query that panicked drops its sender and is left out
+    let mut results: Vec<_> = rx.iter().collect();
+    results.extend(serial(&unsent));
+    results
+}
+
+/// Query the IO die information of a device through RPCs
+fn query_device(chip_id: u32, die_id: u32) -> Result<IoDieInfo, UbfwctlError> {
+    FwctlDevice::open(chip_id, die_id)?.query_io_die_info()
+}
+
 /// Check if a device is a ubase device by reading its uevent file
 ///
 /// # Arguments
@@ -210,7 +455,14 @@ fn check_ubase_device(device_name: &str) -> Option<String> {
     let Ok(contents) = fs::read_to_string(&uevent_path) else {
         return None;
     };
+    parse_uevent(&contents)
+}
 
+/// Parse the entity name out of a device uevent file
+///
+/// # Returns
+/// `Some(String)` with the entity name if the driver is ubase, `None` otherwise
+pub(crate) fn parse_uevent(contents: &str) -> Option<String> {
     let mut is_ubase = false;
     let mut entity_name = String::new();
 
@@ -250,7 +502,7 @@ fn check_ubase_device(device_name: &str) -> Option<String> {
 ///
 /// # Errors
 /// `UbfwctlError::InvalidResponse` if the device name format is invalid
-fn parse_device_id(device_name: &str) -> Result<(u32, u32), UbfwctlError> {
+pub(crate) fn parse_device_id(device_name: &str) -> Result<(u32, u32), UbfwctlError> {
     let num_str = device_name.strip_prefix(FWCTL_DEV_PREFIX).ok_or_else(|| {
         UbfwctlError::InvalidResponse(format!("Invalid device name: {device_name}"))
     })?;
@@ -320,6 +572,7 @@ pub fn device_count() -> Result<usize, UbfwctlError> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::cache::{DEFAULT_CACHE_TTL, DEVICE_CACHE_PATH};
 
     #[test]
     fn test_parse_device_id() {
@@ -351,6 +604,45 @@ mod tests {
         assert!(parse_device_id("fwctlxyz").is_err());
     }
 
+    #[test]
+    fn test_scan_options() {
+        let options = ScanOptions::new();
+        assert_eq!(options.concurrency, DEFAULT_SCAN_CONCURRENCY);
+        assert!(options.cache.is_none());
+        assert!(!options.refresh);
+
+        let options = ScanOptions::new().cache(DEVICE_CACHE_PATH, DEFAULT_CACHE_TTL);
+        assert_eq!(
+            options.cache,
+            Some((PathBuf::from(DEVICE_CACHE_PATH), DEFAULT_CACHE_TTL))
+        );
+
+        let options = options.concurrency(0).refresh().no_cache();
+        assert_eq!(options.concurrency, 1);
+        assert!(options.refresh);
+        assert!(options.cache.is_none());
+    }
+
+    #[test]
+    fn test_query_parallel_keeps_indices() {
+        // Devices that do not exist fail the same way in any order
+        let targets: Vec<ScanTarget> = (0..4)
+            .map(|die_id| ScanTarget {
+                name: format!("fwctl{:08x}", 0xfff0_0000 | die_id),
+                entity_name: format!("ub_entity{die_id}"),
+                chip_id: 0xfff0,
+                die_id,
+            })
+            .collect();
+        let mut results = query_parallel(&targets, &[0, 2, 3], 2);
+        results.sort_by_key(|(index, _)| *index);
+        let indices: Vec<usize> = results.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, [0, 2, 3]);
+        for (index, result) in results {
+            assert!(result.unwrap_err().contains(&targets[index].name));
+        }
+    }
+
     #[test]
     fn test_discovered_device_accessors() {
         let device_info = FwctlDeviceInfo::new(1, 2, "/dev/fwctl/test");
@@ -13,6 +13,15 @@ pub enum UbfwctlError {
     #[error("Invalid port number: {0}")]
     InvalidPort(u32),
 
+    /// Port exists but `mar_perf` cannot measure it
+    #[error("Port {port} cannot be measured: {reason}")]
+    PortNotMeasurable {
+        /// Port ID
+        port: u32,
+        /// Why the port was refused
+        reason: &'static str,
+    },
+
     /// Ioctl operation failed
     #[error("Ioctl failed: {0}")]
     IoctlFailed(String),
@@ -30,10 +39,18 @@ pub enum UbfwctlError {
     #[error("Invalid response from kernel: {0}")]
     InvalidResponse(String),
 
+    /// Firmware RPC refused for lack of privileges
+    #[error("Not permitted: {0}")]
+    NotPermitted(String),
+
     /// Shared memory lock failed
     #[error("Shared memory lock failed: {0}")]
     ShmLockFailed(String),
 
+    /// RPC request rejected before sending
+    #[error("Invalid RPC: {0}")]
+    InvalidRpc(String),
+
     /// Command not supported
     #[error("Command not supported: {0}")]
     CommandNotSupported(String),
@@ -42,6 +59,10 @@ pub enum UbfwctlError {
     #[error("I/O error: {0}")]
     IoError(#[from] std::io::Error),
 
+    /// JSON encoding failed
+    #[error("JSON encoding failed: {0}")]
+    Json(#[from] serde_json::Error),
+
     /// Null pointer encountered
     #[error("Null pointer: {0}")]
     NullPointer(String),
@@ -76,4 +97,17 @@ impl UbfwctlError {
             Err(Self::InvalidTime(time_ms))
         }
     }
+
+    /// Check if the error means the caller may not access the device
+    ///
+    /// True for a refused RPC and for a device node that cannot be opened
+    /// for lack of permissions.
+    #[must_use]
+    pub fn is_not_permitted(&self) -> bool {
+        match self {
+            Self::NotPermitted(_) => true,
+            Self::IoError(e) => e.kind() == std::io::ErrorKind::PermissionDenied,
+            _ => false,
+        }
+    }
 }
@@ -1,10 +1,11 @@
 //! Kernel communication via ioctl
 
 use std::fs::{self, OpenOptions};
-use std::io;
 use std::os::unix::io::{AsRawFd, RawFd};
 
 use crate::error::UbfwctlError;
+use crate::ratelimit::{RateLimit, RateLimitStats, RateLimiter};
+use crate::rpc::RawRpc;
 use crate::types::{FwctlDeviceInfo, IoDieInfo, MarPerfConfig, UbFwctlCmd};
 
 /// fwctl device directory
@@ -18,6 +19,9 @@ pub const FWCTL_RPC: u64 = 0xC0_9A_00_01; // _IO(FWCTL_TYPE(0x9A), FWCTL_CMD_RPC
 /// RPC scope for configuration access
 pub const FWCTL_RPC_CONFIGURATION: u32 = 0;
 
+/// Output words reserved for `mar_perf` responses
+const MAR_PERF_OUTPUT_WORDS: usize = 64;
+
 /// fwctl RPC structure
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -87,12 +91,18 @@ pub struct FwctlRpcUbOut {
 }
 
 /// Wrapper for fwctl device operations
+///
+/// RPCs are rate limited per device, by default to
+/// [`DEFAULT_RPC_QPS`](crate::ratelimit::DEFAULT_RPC_QPS); see
+/// [`crate::ratelimit`].
 #[derive(Debug)]
 pub struct FwctlDevice {
     /// File descriptor for the device
     fd: RawFd,
     /// Device information
     pub info: FwctlDeviceInfo,
+    /// RPC rate limiter
+    limiter: RateLimiter,
 }
 
 impl FwctlDevice {
@@ -122,6 +132,7 @@ impl FwctlDevice {
         Ok(Self {
             fd,
             info: FwctlDeviceInfo::new(chip_id, die_id, path),
+            limiter: RateLimiter::new(RateLimit::default()),
         })
     }
 
@@ -169,8 +180,51 @@ impl FwctlDevice {
         Err(UbfwctlError::DeviceNotFound { chip_id, die_id })
     }
 
+    /// Get the raw file descriptor of the device
+    pub(crate) const fn raw_fd(&self) -> RawFd {
+        self.fd
+    }
+
+    /// Get the RPC rate limiter
+    pub(crate) const fn limiter(&self) -> &RateLimiter {
+        &self.limiter
+    }
+
+    /// Replace the RPC rate limit of the device
+    ///
+    /// # Arguments
+    /// * `limit` - New limit; the token bucket starts full
+    pub fn set_rate_limit(&self, limit: RateLimit) {
+        self.limiter.set_limit(limit);
+    }
+
+    /// Get the RPC rate limit of the device
+    #[must_use]
+    pub fn rate_limit(&self) -> RateLimit {
+        self.limiter.limit()
+    }
+
+    /// Get the throttling counters of the device
+    #[must_use]
+    pub fn rate_limit_stats(&self) -> RateLimitStats {
+        self.limiter.stats()
+    }
+
+    /// Start building an RPC with any firmware opcode
+    ///
+    /// See [`RawRpc`] for the builder and [`crate::rpc`] for an example.
+    ///
+    /// # Arguments
+    /// * `opcode` - Firmware command opcode
+    pub const fn rpc(&self, opcode: u32) -> RawRpc<'_> {
+        RawRpc::new(self, opcode)
+    }
+
     /// Send an RPC command to the kernel
     ///
+    /// Output beyond `output.len()` words is dropped; unused output words
+    /// are left untouched.
+    ///
     /// # Arguments
     /// * `cmd` - RPC command type
     /// * `input` - Input data buffer
@@ -181,104 +235,23 @@ impl FwctlDevice {
     ///
     /// # Errors
     /// Returns an error if:
+    /// - The input or output exceeds [`crate::rpc::MAX_RPC_WORDS`]
     /// - The ioctl call fails
     /// - The kernel returns an error
     /// - The response data is invalid
-    ///
-    /// # Safety
-    /// This function uses unsafe ioctl calls
-    #[allow(
-        clippy::as_conversions,
-        clippy::cast_ptr_alignment,
-        clippy::manual_slice_size_calculation
-    )]
     pub fn send_rpc(
         &self,
         cmd: UbFwctlCmd,
         input: &[u32],
         output: &mut [u32],
     ) -> Result<(), UbfwctlError> {
-        // Prepare input structure
-        let in_size = size_of::<FwctlRpcUbIn>() + input.len() * size_of::<u32>();
-        let mut in_buf = vec![0u8; in_size];
-
-        // Fill input header
-        // SAFETY: in_buf is large enough to hold FwctlRpcUbIn and the pointer is valid
-        let header = unsafe { &mut *(in_buf.as_mut_ptr().cast::<FwctlRpcUbIn>()) };
-        header.rpc_cmd = cmd.as_u32();
-        header.data_size = u32::try_from(input.len() * size_of::<u32>())
-            .map_err(|_| UbfwctlError::InvalidResponse("Input too large".to_string()))?;
-        header.version = 0;
-        header.rsvd = 0;
-
-        // Copy input data
-        if !input.is_empty() {
-            let data_offset = size_of::<FwctlRpcUbIn>();
-            // SAFETY: in_buf is large enough (size_of::<FwctlRpcUbIn>() + input.len() * 4 bytes)
-            // and the pointer is properly aligned for u32
-            let data_slice = unsafe {
-                std::slice::from_raw_parts_mut(
-                    in_buf.as_mut_ptr().add(data_offset).cast::<u32>(),
-                    input.len(),
-                )
-            };
-            data_slice.copy_from_slice(input);
-        }
-
-        // Prepare output buffer
-        let out_size = size_of::<FwctlRpcUbOut>() + output.len() * size_of::<u32>();
-        let mut out_buf = vec![0u8; out_size];
-
-        // Prepare RPC structure
-        let rpc = FwctlRpc::new(
-            FWCTL_RPC_CONFIGURATION,
-            u32::try_from(in_buf.len())
-                .map_err(|_| UbfwctlError::InvalidResponse("Input too large".to_string()))?,
-            u32::try_from(out_buf.len())
-                .map_err(|_| UbfwctlError::InvalidResponse("Output too large".to_string()))?,
-            in_buf.as_ptr() as u64,
-            out_buf.as_mut_ptr() as u64,
-        );
-
-        // Execute ioctl
-        // SAFETY: ioctl is called with a valid file descriptor and properly initialized rpc struct
-        let ret = unsafe { libc::ioctl(self.fd, FWCTL_RPC, &rpc) };
-
-        if ret < 0 {
-            return Err(UbfwctlError::IoctlFailed(format!(
-                "ioctl failed with errno: {}",
-                io::Error::last_os_error()
-            )));
-        }
-
-        // Parse output
-        // SAFETY: out_buf is large enough to hold FwctlRpcUbOut and the pointer is valid
-        let out_header = unsafe { &*(out_buf.as_ptr().cast::<FwctlRpcUbOut>()) };
-
-        if out_header.retval != 0 {
-            return Err(UbfwctlError::IoctlFailed(format!(
-                "Kernel returned error: {}",
-                out_header.retval
-            )));
-        }
-
-        // Copy output data
-        let data_offset = size_of::<FwctlRpcUbOut>();
-        let data_len = usize::try_from(out_header.data_size / 4)
-            .map_err(|_| UbfwctlError::InvalidResponse("Invalid data size".to_string()))?;
-        let copy_len = data_len.min(output.len());
-
-        if copy_len > 0 {
-            // SAFETY: out_buf has enough data (data_offset + copy_len * 4 bytes)
-            // and the pointer is properly aligned for u32
-            let data_slice = unsafe {
-                std::slice::from_raw_parts(
-                    out_buf.as_ptr().add(data_offset).cast::<u32>(),
-                    copy_len,
-                )
-            };
-            output[..copy_len].copy_from_slice(data_slice);
-        }
+        let response = self
+            .rpc(cmd.as_u32())
+            .input_u32s(input)
+            .output_capacity(output.len())
+            .call()?;
+        let data = response.data();
+        output[..data.len()].copy_from_slice(data);
 
         Ok(())
     }
@@ -295,11 +268,7 @@ impl FwctlDevice {
     /// # Errors
     /// Returns an error if the RPC call fails
     pub fn mar_perf_config(&self, port: u32, time_ms: u32) -> Result<(), UbfwctlError> {
-        let config = MarPerfConfig::new(port, time_ms);
-        let input = [config.port_id, config.time_ms];
-        let mut output = [0u32; 64];
-
-        self.send_rpc(UbFwctlCmd::ConfigBaMarPerfStats, &input, &mut output)?;
+        self.mar_perf_start(port, time_ms)?;
 
         // Sleep for the configured time (convert ms to us)
         let sleep_us = time_ms * crate::error::MS_TO_US;
@@ -308,6 +277,31 @@ impl FwctlDevice {
         Ok(())
     }
 
+    /// Start a `mar_perf` measurement without waiting for it to finish
+    ///
+    /// The results can be queried with [`Self::mar_perf_query`] once
+    /// `time_ms` has elapsed.
+    ///
+    /// # Arguments
+    /// * `port` - Port ID
+    /// * `time_ms` - Measurement time in milliseconds
+    ///
+    /// # Returns
+    /// `Ok(())` on success, `Err(UbfwctlError)` on failure
+    ///
+    /// # Errors
+    /// Returns an error if the RPC call fails
+    pub fn mar_perf_start(&self, port: u32, time_ms: u32) -> Result<(), UbfwctlError> {
+        let config = MarPerfConfig::new(port, time_ms);
+        let _response = self
+            .rpc(UbFwctlCmd::ConfigBaMarPerfStats.as_u32())
+            .input_u32s(&[config.port_id, config.time_ms])
+            .output_capacity(MAR_PERF_OUTPUT_WORDS)
+            .call()?;
+
+        Ok(())
+    }
+
     /// Query `mar_perf` results
     ///
     /// # Arguments
@@ -319,12 +313,16 @@ impl FwctlDevice {
     /// # Errors
     /// Returns an error if the RPC call fails
     pub fn mar_perf_query(&self, port: u32) -> Result<Vec<u32>, UbfwctlError> {
-        let input = [port];
-        let mut output = [0u32; 64];
-
-        self.send_rpc(UbFwctlCmd::QueryBaMarPerfStats, &input, &mut output)?;
-
-        Ok(output.to_vec())
+        let mut data = self
+            .rpc(UbFwctlCmd::QueryBaMarPerfStats.as_u32())
+            .input_u32(port)
+            .output_capacity(MAR_PERF_OUTPUT_WORDS)
+            .call()?
+            .into_data();
+        // Callers index the fixed-size layout; pad a short response with zeros
+        data.resize(MAR_PERF_OUTPUT_WORDS, 0);
+
+        Ok(data)
     }
 
     /// Query IO die port information
@@ -339,16 +337,9 @@ impl FwctlDevice {
         // Using u32 array: 508 / 4 = 127 u32s, round up to 128
         const MAX_OUTPUT_SIZE: usize = 128;
 
-        let input: [u32; 0] = [];
-        let mut output = [0u32; MAX_OUTPUT_SIZE];
-
-        self.send_rpc(UbFwctlCmd::QueryIoDiePortInfo, &input, &mut output)?;
-
-        // Convert output to bytes for parsing
-        let output_bytes: Vec<u8> = output.iter().flat_map(|v| v.to_ne_bytes()).collect();
-
-        IoDieInfo::from_raw_data(&output_bytes)
-            .map_err(|e| UbfwctlError::InvalidResponse(format!("Failed to parse IO die info: {e}")))
+        self.rpc(UbFwctlCmd::QueryIoDiePortInfo.as_u32())
+            .output_capacity(MAX_OUTPUT_SIZE)
+            .call_as()
     }
 }
 
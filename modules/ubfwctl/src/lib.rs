@@ -7,6 +7,14 @@
 //!
 //! - **`mar_perf`**: Bandwidth and latency measurement for UB ports
 //! - **`list`**: List all fwctl devices with their port information
+//! - **`cache`**: Short-lived on-disk cache of device query results, so
+//!   that repeated listings skip the firmware RPCs
+//! - **`rpc`**: Call any firmware command through a generic RPC builder
+//! - **`ratelimit`**: Per-device limit on the rate of firmware RPCs
+//! - **`sysfs`**: Read-only device listing when the firmware RPC is not
+//!   permitted
+//! - **`aio`** (feature): Non-blocking `mar_perf` measurement usable from
+//!   any async executor
 //!
 //! # Examples
 //!
@@ -68,17 +76,33 @@
     clippy::cargo
 )]
 
+#[cfg(feature = "aio")]
+pub mod aio;
+pub mod cache;
 pub mod commands;
 pub mod device;
 pub mod error;
 pub mod ioctl;
+pub mod ratelimit;
+pub mod rpc;
+pub mod sysfs;
 pub mod types;
 
-pub use commands::list::{format_device_list, list_devices, list_devices_raw};
-pub use commands::mar_perf::{MarPerfCommand, mar_perf_measure};
-pub use device::{DiscoveredDevice, device_count, list_device_paths, scan_devices};
+pub use commands::list::{
+    format_device_list, format_device_list_json, list_devices, list_devices_json, list_devices_raw,
+};
+pub use commands::mar_perf::{
+    MarPerfCommand, format_mar_perf_json, mar_perf_measure, mar_perf_measure_all, measurable_ports,
+};
+pub use commands::monitor::{MarPerfCsvWriter, MarPerfMonitor, MarPerfSample, MarPerfSamples};
+pub use device::{
+    DiscoveredDevice, ScanOptions, device_count, list_device_paths, scan_devices, scan_devices_with,
+};
 pub use error::UbfwctlError;
 pub use ioctl::FwctlDevice;
+pub use ratelimit::{RateLimit, RateLimitStats};
+pub use rpc::{FromRpcResponse, RawRpc, RpcResponse};
+pub use sysfs::{Backend, CapabilityReport, probe_capabilities};
 pub use types::{FwctlDeviceInfo, IoDieInfo, MarPerfConfig, MarPerfQuery, MarPerfResult, PortInfo};
 
 /// Convenience re-export for error handling
@@ -108,6 +132,8 @@ pub const NAME: &str = env!("CARGO_PKG_NAME");
 /// # Errors
 /// - `InvalidTime` if `time_ms` is not between 1 and 3600
 /// - `DeviceNotFound` if the fwctl device doesn't exist
+/// - `InvalidPort` or `PortNotMeasurable` if `port` is not a UB port of the
+///   die with its link up
 /// - `IoctlFailed` if communication with the kernel fails
 /// - `ShmLockFailed` if shared memory locking fails
 ///
@@ -0,0 +1,236 @@
+//! Per-device rate limiting of firmware RPCs
+//!
+//! Every [`FwctlDevice`](crate::FwctlDevice) owns a token bucket that each
+//! RPC draws from before it reaches the firmware mailbox. A call that finds
+//! the bucket empty sleeps until the next token is due, so a monitoring loop
+//! polling too fast is slowed down instead of failing. Administrative
+//! commands sent with [`RawRpc::admin`](crate::RawRpc::admin) skip the
+//! bucket.
+//!
+//! ```no_run
+//! use ubfwctl::FwctlDevice;
+//! use ubfwctl::ratelimit::RateLimit;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let device = FwctlDevice::open(0, 0)?;
+//!     device.set_rate_limit(RateLimit::per_second(10));
+//!     for _ in 0..4 {
+//!         let _info = device.query_io_die_info()?;
+//!     }
+//!     println!("{:?}", device.rate_limit_stats());
+//!     Ok(())
+//! }
+//! ```
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Default RPCs per second of a device
+pub const DEFAULT_RPC_QPS: u32 = 50;
+
+/// RPC rate limit of a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Sustained RPCs per second, 0 for no limit
+    qps: u32,
+    /// RPCs that may be sent back to back after an idle period
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Allow `qps` RPCs per second with a burst of the same size
+    #[must_use]
+    pub const fn per_second(qps: u32) -> Self {
+        Self { qps, burst: qps }
+    }
+
+    /// Do not limit RPCs
+    #[must_use]
+    pub const fn unlimited() -> Self {
+        Self { qps: 0, burst: 0 }
+    }
+
+    /// Set the burst size (at least 1)
+    #[must_use]
+    pub const fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = if burst == 0 { 1 } else { burst };
+        self
+    }
+
+    /// Get the sustained RPCs per second, `None` if unlimited
+    #[must_use]
+    pub const fn qps(&self) -> Option<u32> {
+        if self.qps == 0 { None } else { Some(self.qps) }
+    }
+
+    /// Get the burst size
+    #[must_use]
+    pub const fn burst(&self) -> u32 {
+        self.burst
+    }
+
+    /// Time for one token to accrue
+    fn interval(self) -> Duration {
+        Duration::from_secs(1) / self.qps.max(1)
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self::per_second(DEFAULT_RPC_QPS)
+    }
+}
+
+/// Counters of a device rate limiter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RateLimitStats {
+    /// RPCs that went through the limiter
+    pub calls: u64,
+    /// RPCs that had to wait for a token
+    pub throttled: u64,
+    /// Total time RPCs waited
+    pub throttled_time: Duration,
+    /// Administrative RPCs that bypassed the limiter
+    pub bypassed: u64,
+}
+
+/// Limiter state behind the lock
+#[derive(Debug)]
+struct LimiterState {
+    /// Configured limit
+    limit: RateLimit,
+    /// Instant at which the bucket would be full again; tokens are spent by
+    /// moving it forward one interval at a time
+    full_at: Instant,
+    /// Counters
+    stats: RateLimitStats,
+}
+
+/// Token bucket shared by the RPCs of one device
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    /// Locked state
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    /// Create a limiter with a full bucket
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            state: Mutex::new(LimiterState {
+                limit,
+                full_at: Instant::now(),
+                stats: RateLimitStats::default(),
+            }),
+        }
+    }
+
+    /// Lock the state, ignoring poisoning (the counters stay consistent)
+    fn lock(&self) -> MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Take a token at `now`
+    ///
+    /// # Returns
+    /// How long the caller must wait before sending
+    fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.lock();
+        state.stats.calls += 1;
+        let limit = state.limit;
+        if limit.qps().is_none() {
+            return Duration::ZERO;
+        }
+
+        // The bucket holds `burst` tokens: it can be drawn until `full_at`
+        // is `burst` intervals ahead of now
+        let interval = limit.interval();
+        let start = state.full_at.max(now);
+        state.full_at = start + interval;
+        let capacity = interval * limit.burst.max(1);
+        let wait = state.full_at.saturating_duration_since(now + capacity);
+        if !wait.is_zero() {
+            state.stats.throttled += 1;
+            state.stats.throttled_time += wait;
+        }
+        wait
+    }
+
+    /// Wait for a token
+    pub(crate) fn acquire(&self) {
+        let wait = self.reserve(Instant::now());
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+
+    /// Count an RPC that skipped the limiter
+    pub(crate) fn bypass(&self) {
+        self.lock().stats.bypassed += 1;
+    }
+
+    /// Replace the limit, refilling the bucket
+    pub(crate) fn set_limit(&self, limit: RateLimit) {
+        let mut state = self.lock();
+        state.limit = limit;
+        state.full_at = Instant::now();
+    }
+
+    /// Get the configured limit
+    pub(crate) fn limit(&self) -> RateLimit {
+        self.lock().limit
+    }
+
+    /// Get the counters
+    pub(crate) fn stats(&self) -> RateLimitStats {
+        self.lock().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        assert_eq!(RateLimit::default().qps(), Some(DEFAULT_RPC_QPS));
+        assert_eq!(RateLimit::unlimited().qps(), None);
+        assert_eq!(RateLimit::per_second(10).with_burst(0).burst(), 1);
+        assert_eq!(
+            RateLimit::per_second(4).interval(),
+            Duration::from_millis(250)
+        );
+    }
+
+    #[test]
+    fn test_burst_then_throttle() {
+        let limiter = RateLimiter::new(RateLimit::per_second(10).with_burst(3));
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve(now), Duration::ZERO);
+        }
+        assert_eq!(limiter.reserve(now), Duration::from_millis(100));
+        assert_eq!(limiter.reserve(now), Duration::from_millis(200));
+
+        // Idle time refills the bucket
+        let later = now + Duration::from_secs(1);
+        assert_eq!(limiter.reserve(later), Duration::ZERO);
+
+        limiter.bypass();
+        let stats = limiter.stats();
+        assert_eq!(stats.calls, 6);
+        assert_eq!(stats.throttled, 2);
+        assert_eq!(stats.throttled_time, Duration::from_millis(300));
+        assert_eq!(stats.bypassed, 1);
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = RateLimiter::new(RateLimit::unlimited());
+        let now = Instant::now();
+        for _ in 0..1000 {
+            assert_eq!(limiter.reserve(now), Duration::ZERO);
+        }
+        assert_eq!(limiter.stats().throttled, 0);
+    }
+}
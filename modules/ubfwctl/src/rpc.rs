@@ -0,0 +1,334 @@
+//! Generic fwctl RPC builder
+//!
+//! [`UbFwctlCmd`](crate::types::UbFwctlCmd) only names the opcodes this
+//! crate uses. [`RawRpc`] sends any opcode with any `u32` payload, so new
+//! firmware commands can be called without changing the crate:
+//!
+//! ```no_run
+//! use ubfwctl::FwctlDevice;
+//!
+//! fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let device = FwctlDevice::open(0, 0)?;
+//!     let response = device.rpc(0x0090).input_u32s(&[3]).output_capacity(16).call()?;
+//!     println!("status word: {:#x}", response.word(0)?);
+//!     Ok(())
+//! }
+//! ```
+
+use std::io;
+
+use crate::error::UbfwctlError;
+use crate::ioctl::{
+    FWCTL_RPC, FWCTL_RPC_CONFIGURATION, FwctlDevice, FwctlRpc, FwctlRpcUbIn, FwctlRpcUbOut,
+};
+use crate::types::IoDieInfo;
+
+/// Largest input or output payload of one RPC, in `u32` words (4 KiB)
+pub const MAX_RPC_WORDS: usize = 1024;
+
+/// Words taken by the input header in front of the payload
+const IN_HEADER_WORDS: usize = size_of::<FwctlRpcUbIn>() / size_of::<u32>();
+
+/// Words taken by the output header in front of the payload
+const OUT_HEADER_WORDS: usize = size_of::<FwctlRpcUbOut>() / size_of::<u32>();
+
+/// Builder for one fwctl RPC
+///
+/// Created by [`FwctlDevice::rpc`].
+#[derive(Debug, Clone)]
+#[must_use = "the RPC is only sent by `call`"]
+pub struct RawRpc<'a> {
+    /// Device to send the RPC to
+    device: &'a FwctlDevice,
+    /// Firmware opcode
+    opcode: u32,
+    /// Input payload
+    input: Vec<u32>,
+    /// Output payload capacity in words
+    output_capacity: usize,
+    /// Whether the RPC bypasses the device rate limiter
+    admin: bool,
+}
+
+impl<'a> RawRpc<'a> {
+    /// Create an RPC with no input and no output
+    pub(crate) const fn new(device: &'a FwctlDevice, opcode: u32) -> Self {
+        Self {
+            device,
+            opcode,
+            input: Vec::new(),
+            output_capacity: 0,
+            admin: false,
+        }
+    }
+
+    /// Append words to the input payload
+    pub fn input_u32s(mut self, words: &[u32]) -> Self {
+        self.input.extend_from_slice(words);
+        self
+    }
+
+    /// Append one word to the input payload
+    pub fn input_u32(mut self, word: u32) -> Self {
+        self.input.push(word);
+        self
+    }
+
+    /// Set how many words of output to accept
+    pub const fn output_capacity(mut self, words: usize) -> Self {
+        self.output_capacity = words;
+        self
+    }
+
+    /// Mark the RPC as administrative, bypassing the device rate limiter
+    ///
+    /// Meant for rare operator actions (resets, configuration) that must
+    /// not queue behind a monitoring loop.
+    pub const fn admin(mut self) -> Self {
+        self.admin = true;
+        self
+    }
+
+    /// Send the RPC
+    ///
+    /// Unless the RPC is [administrative](Self::admin), it first waits for
+    /// the device rate limiter.
+    ///
+    /// # Returns
+    /// `Ok(RpcResponse)` with up to `output_capacity` words on success,
+    /// `Err(UbfwctlError)` on failure
+    ///
+    /// # Errors
+    /// Returns an error if:
+    /// - The input or output payload exceeds [`MAX_RPC_WORDS`]
+    /// - The ioctl call fails
+    /// - The kernel returns an error
+    #[allow(clippy::as_conversions)]
+    pub fn call(self) -> Result<RpcResponse, UbfwctlError> {
+        check_len("input", self.input.len())?;
+        check_len("output", self.output_capacity)?;
+
+        // Input: rpc_cmd, data_size, version, rsvd, then the payload
+        let data_size = u32::try_from(self.input.len() * size_of::<u32>())
+            .map_err(|_| UbfwctlError::InvalidRpc("input too large".to_string()))?;
+        let mut in_buf = Vec::with_capacity(IN_HEADER_WORDS + self.input.len());
+        in_buf.extend_from_slice(&[self.opcode, data_size, 0, 0]);
+        in_buf.extend_from_slice(&self.input);
+
+        // Output: retval, data_size, then the payload
+        let mut out_buf = vec![0u32; OUT_HEADER_WORDS + self.output_capacity];
+
+        let rpc = FwctlRpc::new(
+            FWCTL_RPC_CONFIGURATION,
+            byte_len(&in_buf)?,
+            byte_len(&out_buf)?,
+            in_buf.as_ptr() as u64,
+            out_buf.as_mut_ptr() as u64,
+        );
+
+        if self.admin {
+            self.device.limiter().bypass();
+        } else {
+            self.device.limiter().acquire();
+        }
+
+        // SAFETY: ioctl is called with a valid file descriptor and an rpc
+        // struct pointing at buffers that outlive the call
+        let ret = unsafe { libc::ioctl(self.device.raw_fd(), FWCTL_RPC, &rpc) };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            let msg = format!("ioctl failed with errno: {err}");
+            if err.kind() == io::ErrorKind::PermissionDenied {
+                return Err(UbfwctlError::NotPermitted(msg));
+            }
+            return Err(UbfwctlError::IoctlFailed(msg));
+        }
+
+        let retval = i32::from_ne_bytes(out_buf[0].to_ne_bytes());
+        if retval != 0 {
+            return Err(UbfwctlError::IoctlFailed(format!(
+                "Kernel returned error: {retval}"
+            )));
+        }
+
+        let returned = usize::try_from(out_buf[1] / 4)
+            .map_err(|_| UbfwctlError::InvalidResponse("Invalid data size".to_string()))?;
+        let mut data = out_buf.split_off(OUT_HEADER_WORDS);
+        data.truncate(returned);
+        Ok(RpcResponse {
+            opcode: self.opcode,
+            data,
+            truncated: returned > self.output_capacity,
+        })
+    }
+
+    /// Send the RPC and parse the response as `T`
+    ///
+    /// # Errors
+    /// Returns the error of [`Self::call`], or the error of
+    /// [`FromRpcResponse::from_response`] if the response cannot be parsed
+    pub fn call_as<T: FromRpcResponse>(self) -> Result<T, UbfwctlError> {
+        T::from_response(&self.call()?)
+    }
+}
+
+/// Check a payload length against [`MAX_RPC_WORDS`]
+fn check_len(what: &str, words: usize) -> Result<(), UbfwctlError> {
+    if words > MAX_RPC_WORDS {
+        Err(UbfwctlError::InvalidRpc(format!(
+            "{what} of {words} words exceeds the maximum of {MAX_RPC_WORDS}"
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+/// Size of a buffer in bytes as the kernel expects it
+fn byte_len(buf: &[u32]) -> Result<u32, UbfwctlError> {
+    u32::try_from(size_of_val(buf))
+        .map_err(|_| UbfwctlError::InvalidRpc("buffer too large".to_string()))
+}
+
+/// Response of an RPC
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcResponse {
+    /// Opcode the response answers
+    opcode: u32,
+    /// Output payload
+    data: Vec<u32>,
+    /// Whether the firmware returned more words than requested
+    truncated: bool,
+}
+
+impl RpcResponse {
+    /// Get the opcode the response answers
+    #[must_use]
+    pub const fn opcode(&self) -> u32 {
+        self.opcode
+    }
+
+    /// Get the output payload
+    #[must_use]
+    pub fn data(&self) -> &[u32] {
+        &self.data
+    }
+
+    /// Get the number of output words
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Check if the response has no output
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Check if the firmware returned more words than the output capacity
+    #[must_use]
+    pub const fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Get one output word
+    ///
+    /// # Errors
+    /// Returns `UbfwctlError::InvalidResponse` if the response is shorter
+    /// than `index + 1` words
+    pub fn word(&self, index: usize) -> Result<u32, UbfwctlError> {
+        self.data.get(index).copied().ok_or_else(|| {
+            UbfwctlError::InvalidResponse(format!(
+                "opcode {:#06x} returned {} words, word {index} requested",
+                self.opcode,
+                self.data.len()
+            ))
+        })
+    }
+
+    /// Get the output as bytes in native endianness
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.data
+            .iter()
+            .flat_map(|word| word.to_ne_bytes())
+            .collect()
+    }
+
+    /// Take the output payload
+    #[must_use]
+    pub fn into_data(self) -> Vec<u32> {
+        self.data
+    }
+}
+
+/// Types that can be parsed from an RPC response
+///
+/// Implement this for the response of a new firmware command to use
+/// [`RawRpc::call_as`].
+pub trait FromRpcResponse: Sized {
+    /// Parse the response
+    ///
+    /// # Errors
+    /// Returns `UbfwctlError::InvalidResponse` if the response is malformed
+    fn from_response(response: &RpcResponse) -> Result<Self, UbfwctlError>;
+}
+
+impl FromRpcResponse for RpcResponse {
+    fn from_response(response: &RpcResponse) -> Result<Self, UbfwctlError> {
+        Ok(response.clone())
+    }
+}
+
+impl FromRpcResponse for Vec<u32> {
+    fn from_response(response: &RpcResponse) -> Result<Self, UbfwctlError> {
+        Ok(response.data.clone())
+    }
+}
+
+impl FromRpcResponse for IoDieInfo {
+    fn from_response(response: &RpcResponse) -> Result<Self, UbfwctlError> {
+        Self::from_raw_data(&response.to_bytes())
+            .map_err(|e| UbfwctlError::InvalidResponse(format!("Failed to parse IO die info: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_words() {
+        assert_eq!(IN_HEADER_WORDS, 4);
+        assert_eq!(OUT_HEADER_WORDS, 2);
+    }
+
+    #[test]
+    fn test_check_len() {
+        assert!(check_len("input", MAX_RPC_WORDS).is_ok());
+        assert!(matches!(
+            check_len("input", MAX_RPC_WORDS + 1),
+            Err(UbfwctlError::InvalidRpc(_))
+        ));
+    }
+
+    #[test]
+    fn test_rpc_response() {
+        let response = RpcResponse {
+            opcode: 0x48,
+            data: vec![1, 0x0403_0201],
+            truncated: false,
+        };
+        assert_eq!(response.len(), 2);
+        assert_eq!(response.word(1).unwrap(), 0x0403_0201);
+        assert!(matches!(
+            response.word(2),
+            Err(UbfwctlError::InvalidResponse(_))
+        ));
+        assert_eq!(response.to_bytes().len(), 8);
+        assert_eq!(
+            Vec::<u32>::from_response(&response).unwrap(),
+            vec![1, 0x0403_0201]
+        );
+    }
+}
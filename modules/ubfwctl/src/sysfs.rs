@@ -0,0 +1,306 @@
+//! Read-only sysfs backend for device listing
+//!
+//! In locked-down environments (containers without the char device, no
+//! `CAP_SYS_RAWIO`) the fwctl RPC may be refused while the ubase driver still
+//! publishes the entity name in sysfs:
+//!
+//! ```text
+//! /sys/class/fwctl/fwctl00/device/uevent    DRIVER=ubase, UB_ENTITY_NAME=...
+//! ```
+//!
+//! [`scan_devices`](crate::scan_devices) falls back to this backend when the
+//! RPC is not permitted and says which backend served the data. Sysfs has no
+//! port attributes, so such devices are listed without ports. Port link
+//! state and `mar_perf` need the RPC and have no fallback;
+//! [`CapabilityReport`] tells which operations a device supports.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::device::{SYS_CLASS_FWCTL_PATH, UEVENT_FILE, parse_device_id, parse_uevent};
+use crate::error::UbfwctlError;
+use crate::ioctl::{FWCTL_DEV_DIR, FWCTL_DEV_PREFIX, FwctlDevice};
+use crate::types::IoDieInfo;
+
+/// Source of device and port information
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Firmware RPC through the fwctl char device
+    #[default]
+    Ioctl,
+    /// Read-only sysfs attributes of the ubase driver
+    Sysfs,
+}
+
+impl Backend {
+    /// Get the backend name
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Ioctl => "ioctl",
+            Self::Sysfs => "sysfs",
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Sysfs view of one fwctl device
+#[derive(Debug, Clone)]
+pub struct SysfsDevice {
+    /// Device directory (`/sys/class/fwctl/fwctl00`)
+    dir: PathBuf,
+}
+
+impl SysfsDevice {
+    /// Open the sysfs directory of a device
+    ///
+    /// # Arguments
+    /// * `device_name` - Device name (e.g., "fwctl00")
+    ///
+    /// # Errors
+    /// `UbfwctlError::InvalidResponse` if the device has no sysfs directory
+    pub fn open(device_name: &str) -> Result<Self, UbfwctlError> {
+        Self::open_in(Path::new(SYS_CLASS_FWCTL_PATH), device_name)
+    }
+
+    /// Open the sysfs directory of a device below `class_dir`
+    ///
+    /// # Arguments
+    /// * `class_dir` - Directory holding the fwctl class devices
+    /// * `device_name` - Device name (e.g., "fwctl00")
+    ///
+    /// # Errors
+    /// `UbfwctlError::InvalidResponse` if the device has no sysfs directory
+    pub fn open_in(class_dir: &Path, device_name: &str) -> Result<Self, UbfwctlError> {
+        let dir = class_dir.join(device_name);
+        if !dir.is_dir() {
+            return Err(UbfwctlError::InvalidResponse(format!(
+                "No sysfs directory for {device_name}"
+            )));
+        }
+        Ok(Self { dir })
+    }
+
+    /// Get the entity name, `None` if this is not a ubase device
+    #[must_use]
+    pub fn entity_name(&self) -> Option<String> {
+        let contents = fs::read_to_string(self.dir.join(UEVENT_FILE)).ok()?;
+        parse_uevent(&contents)
+    }
+
+    /// Build the IO die information of a device listed from sysfs
+    ///
+    /// Sysfs publishes no port attributes, so the die has no ports.
+    #[must_use]
+    pub fn io_die_info(&self, chip_id: u32, die_id: u32) -> IoDieInfo {
+        IoDieInfo {
+            port_count: 0,
+            chip_id,
+            die_id,
+            reserved: [0; 3],
+            ports: Vec::new(),
+        }
+    }
+}
+
+/// Check whether an RPC failure should be retried through sysfs
+///
+/// Only access problems fall back: a device that answers with a firmware
+/// error is reported as is.
+pub(crate) fn should_fall_back(err: &UbfwctlError) -> bool {
+    err.is_not_permitted() || matches!(err, UbfwctlError::DeviceNotFound { .. })
+}
+
+/// Operations a device supports and the backend serving them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapabilityReport {
+    /// Device name (e.g., "fwctl00")
+    pub device: String,
+    /// Whether firmware RPCs are permitted
+    pub rpc: bool,
+    /// Whether sysfs publishes the entity name
+    pub sysfs: bool,
+}
+
+impl CapabilityReport {
+    /// Probe a device
+    ///
+    /// Sends one IO die query to check that RPCs are permitted.
+    ///
+    /// # Arguments
+    /// * `device_name` - Device name (e.g., "fwctl00")
+    #[must_use]
+    pub fn probe(device_name: &str) -> Self {
+        let rpc = parse_device_id(device_name)
+            .and_then(|(chip_id, die_id)| FwctlDevice::open(chip_id, die_id))
+            .is_ok_and(|device| device.query_io_die_info().is_ok());
+        let sysfs =
+            SysfsDevice::open(device_name).is_ok_and(|device| device.entity_name().is_some());
+        Self {
+            device: device_name.to_string(),
+            rpc,
+            sysfs,
+        }
+    }
+
+    /// Get the backend serving the device list
+    ///
+    /// # Returns
+    /// `None` if neither backend is available
+    #[must_use]
+    pub const fn backend(&self) -> Option<Backend> {
+        if self.rpc {
+            Some(Backend::Ioctl)
+        } else if self.sysfs {
+            Some(Backend::Sysfs)
+        } else {
+            None
+        }
+    }
+
+    /// Check whether port link state and `mar_perf` work
+    #[must_use]
+    pub const fn supports_rpc_commands(&self) -> bool {
+        self.rpc
+    }
+}
+
+impl fmt::Display for CapabilityReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.backend() {
+            Some(backend) => write!(f, "{}: list via {backend}", self.device)?,
+            None => write!(f, "{}: list unavailable", self.device)?,
+        }
+        if self.supports_rpc_commands() {
+            write!(f, ", ports/mar_perf available")
+        } else {
+            write!(f, ", ports/mar_perf unavailable")
+        }
+    }
+}
+
+/// Probe every ubase fwctl device
+///
+/// Devices are enumerated from `/dev/fwctl`, or from sysfs if the char
+/// devices are not visible.
+///
+/// # Errors
+/// `UbfwctlError::IoError` if neither directory can be read
+pub fn probe_capabilities() -> Result<Vec<CapabilityReport>, UbfwctlError> {
+    Ok(device_names()?
+        .iter()
+        .filter(|name| SysfsDevice::open(name).is_ok_and(|d| d.entity_name().is_some()))
+        .map(|name| CapabilityReport::probe(name))
+        .collect())
+}
+
+/// Names of the fwctl devices, from `/dev/fwctl` or else sysfs
+///
+/// Empty if neither directory exists.
+///
+/// # Errors
+/// `UbfwctlError::IoError` if the directory cannot be read
+pub(crate) fn device_names() -> Result<Vec<String>, UbfwctlError> {
+    let dev_dir = Path::new(FWCTL_DEV_DIR);
+    let dir = if dev_dir.exists() {
+        dev_dir
+    } else {
+        Path::new(SYS_CLASS_FWCTL_PATH)
+    };
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut names = Vec::new();
+    for entry in entries {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        if name.starts_with(FWCTL_DEV_PREFIX) {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, contents: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn test_sysfs_device() {
+        let root = std::env::temp_dir().join(format!("ubfwctl-sysfs-{}", std::process::id()));
+        let dir = root.join("fwctl00/device");
+        write(
+            &dir.join("uevent"),
+            "DRIVER=ubase\nUB_ENTITY_NAME=ub_entity0\n",
+        );
+
+        let device = SysfsDevice::open_in(&root, "fwctl00").unwrap();
+        assert_eq!(device.entity_name().as_deref(), Some("ub_entity0"));
+
+        let info = device.io_die_info(1, 2);
+        assert_eq!((info.chip_id, info.die_id), (1, 2));
+        assert_eq!(info.port_count, 0);
+        assert!(info.ports.is_empty());
+        assert!(SysfsDevice::open_in(&root, "fwctl01").is_err());
+
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_capability_report() {
+        let report = CapabilityReport {
+            device: "fwctl00".to_string(),
+            rpc: false,
+            sysfs: true,
+        };
+        assert_eq!(report.backend(), Some(Backend::Sysfs));
+        assert_eq!(
+            report.to_string(),
+            "fwctl00: list via sysfs, ports/mar_perf unavailable"
+        );
+        let report = CapabilityReport {
+            rpc: true,
+            ..report
+        };
+        assert_eq!(report.backend(), Some(Backend::Ioctl));
+        assert!(report.supports_rpc_commands());
+        let report = CapabilityReport {
+            rpc: false,
+            sysfs: false,
+            ..report
+        };
+        assert_eq!(report.backend(), None);
+    }
+
+    #[test]
+    fn test_fall_back() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(should_fall_back(&UbfwctlError::IoError(denied)));
+        assert!(should_fall_back(&UbfwctlError::NotPermitted(
+            "EPERM".to_string()
+        )));
+        assert!(should_fall_back(&UbfwctlError::DeviceNotFound {
+            chip_id: 0,
+            die_id: 0
+        }));
+        assert!(!should_fall_back(&UbfwctlError::IoctlFailed(
+            "Kernel returned error: -5".to_string()
+        )));
+    }
+}
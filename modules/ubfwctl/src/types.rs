@@ -184,23 +184,11 @@ impl MarPerfResult {
         };
 
         // Calculate average payload lengths
-        let wr_pld_avg_len = if query.wr_cmd_cnt > 0 {
-            query.flux_wr / query.wr_cmd_cnt
-        } else {
-            0
-        };
+        let wr_pld_avg_len = query.flux_wr.checked_div(query.wr_cmd_cnt).unwrap_or(0);
 
-        let rd_pld_avg_len = if query.rd_cmd_cnt > 0 {
-            query.flux_rd / query.rd_cmd_cnt
-        } else {
-            0
-        };
+        let rd_pld_avg_len = query.flux_rd.checked_div(query.rd_cmd_cnt).unwrap_or(0);
 
-        let pld_avg_len = if query.sum_cmd_cnt > 0 {
-            query.flux_sum / query.sum_cmd_cnt
-        } else {
-            0
-        };
+        let pld_avg_len = query.flux_sum.checked_div(query.sum_cmd_cnt).unwrap_or(0);
 
         // Calculate latency in nanoseconds
         #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]